@@ -0,0 +1,176 @@
+//! Benchmarks of the slow parts of the generation: downloading the template,
+//! extracting a large archive and rewriting the CMake files
+//!
+//! Each group also measures the previous naive implementation so the output
+//! shows the improvement. Run with `cargo bench`.
+
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::{self, Cursor, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use esp_create_project::archive::{detect_prefix, extract_zip, ExtractOptions};
+use esp_create_project::cmake::rewrite_project_cmake;
+use esp_create_project::download::{download_template, DEFAULT_MAX_TEMPLATE_SIZE};
+use esp_create_project::CancellationToken;
+
+const TEMPLATE_CMAKE: &str = "# The following lines of boilerplate have to be in your project's
+# CMakeLists in this exact order for cmake to work correctly
+cmake_minimum_required(VERSION 3.5)
+
+include($ENV{IDF_PATH}/tools/cmake/project.cmake)
+project(app-template)
+";
+
+/// Builds a template-like archive with the given number of 1 KiB files
+fn archive_with_files(count: usize) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let content = vec![b'x'; 1024];
+    writer.add_directory("template/", options).unwrap();
+    for i in 0..count {
+        writer
+            .start_file(format!("template/dir{}/file{}.c", i % 50, i), options)
+            .unwrap();
+        writer.write_all(&content).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Serves the body over HTTP on a local port for the whole benchmark run
+fn serve(body: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/template.zip", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&buffer[..read]),
+                }
+            }
+            let headers = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream
+                .write_all(headers.as_bytes())
+                .and_then(|_| stream.write_all(&body));
+        }
+    });
+    url
+}
+
+/// Extraction as it was done before: unbuffered writes through `io::copy`
+fn naive_extract(directory: &Path, zip: &mut ZipArchive<Cursor<Vec<u8>>>) {
+    for i in 1..zip.len() {
+        let mut file = zip.by_index(i).unwrap();
+        let outpath = directory.join(
+            file.enclosed_name()
+                .unwrap()
+                .strip_prefix("template")
+                .unwrap(),
+        );
+        if let Some(p) = outpath.parent() {
+            if !p.exists() {
+                fs::create_dir_all(p).unwrap();
+            }
+        }
+        let mut outfile = File::create(&outpath).unwrap();
+        io::copy(&mut file, &mut outfile).unwrap();
+    }
+}
+
+/// CMake rewrite as it was done before: one owned `String` per line
+fn naive_rewrite(content: &str, project_language: &str, project_name: &str) -> String {
+    let mut lines = content
+        .split('\n')
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+    lines[4] = project_language.into();
+    lines[5] = "set(EXTRA_COMPONENT_DIRS components)".into();
+    lines[6] = "include($ENV{IDF_PATH}/tools/cmake/project.cmake)".into();
+    lines.push(format!("project({})", project_name));
+    lines.join("\n")
+}
+
+fn bench_download(c: &mut Criterion) {
+    let url = serve(vec![0; 4 * 1024 * 1024]);
+    let token = CancellationToken::new();
+
+    c.bench_function("download 4 MiB to temp file", |b| {
+        b.iter(|| {
+            let mut tmp_file = tempfile::tempfile().unwrap();
+            download_template(&url, &mut tmp_file, DEFAULT_MAX_TEMPLATE_SIZE, &token).unwrap();
+        })
+    });
+}
+
+fn bench_extract(c: &mut Criterion) {
+    let archive = archive_with_files(1000);
+    let token = CancellationToken::new();
+    let mut group = c.benchmark_group("extract 1000 files");
+    group.sample_size(20);
+
+    group.bench_function("naive", |b| {
+        b.iter_batched(
+            || {
+                let zip = ZipArchive::new(Cursor::new(archive.clone())).unwrap();
+                (tempfile::tempdir().unwrap(), zip)
+            },
+            |(dir, mut zip)| {
+                naive_extract(dir.path(), &mut zip);
+                // Returned so the removal isn't measured
+                dir
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("buffered", |b| {
+        b.iter_batched(
+            || {
+                let zip = ZipArchive::new(Cursor::new(archive.clone())).unwrap();
+                (tempfile::tempdir().unwrap(), zip)
+            },
+            |(dir, mut zip)| {
+                let prefix: PathBuf = detect_prefix(&zip);
+                let directory = dir.path().to_str().unwrap();
+                extract_zip(
+                    directory,
+                    &mut zip,
+                    &prefix,
+                    ExtractOptions::default(),
+                    &token,
+                )
+                .unwrap();
+                dir
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn bench_cmake_rewrite(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rewrite CMakeLists.txt");
+    let language = "set(CMAKE_CXX_STANDARD 17)";
+
+    group.bench_function("naive", |b| {
+        b.iter(|| naive_rewrite(black_box(TEMPLATE_CMAKE), language, "blink"))
+    });
+    group.bench_function("in place", |b| {
+        b.iter(|| rewrite_project_cmake(black_box(TEMPLATE_CMAKE), language, "blink").unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_download, bench_extract, bench_cmake_rewrite);
+criterion_main!(benches);
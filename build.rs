@@ -0,0 +1,53 @@
+//! Passes build information to `--print-version-info`: the target triple,
+//! the build profile and the versions of the main dependencies, read from
+//! `Cargo.lock`
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Dependencies whose version is printed, they handle the downloads and the
+/// archives and matter the most in bug reports
+const REPORTED_DEPENDENCIES: &[&str] = &["zip", "ureq"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let unknown = || "unknown".to_string();
+    println!(
+        "cargo:rustc-env=ESP_CREATE_BUILD_TARGET={}",
+        env::var("TARGET").unwrap_or_else(|_| unknown())
+    );
+    println!(
+        "cargo:rustc-env=ESP_CREATE_BUILD_PROFILE={}",
+        env::var("PROFILE").unwrap_or_else(|_| unknown())
+    );
+
+    // Missing when built from a crate published without its lock file
+    let lock = env::var("CARGO_MANIFEST_DIR")
+        .ok()
+        .and_then(|dir| fs::read_to_string(Path::new(&dir).join("Cargo.lock")).ok())
+        .unwrap_or_default();
+    for name in REPORTED_DEPENDENCIES {
+        println!(
+            "cargo:rustc-env=ESP_CREATE_DEP_{}_VERSION={}",
+            name.to_uppercase(),
+            locked_version(&lock, name).unwrap_or_else(unknown)
+        );
+    }
+}
+
+/// Finds the version of a package in `Cargo.lock`, the first one if several
+/// versions are locked
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let name_line = format!("name = \"{}\"", name);
+    let mut lines = lock.lines();
+    lines.find(|line| line.trim() == name_line)?;
+    lines
+        .next()?
+        .trim()
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+        .map(str::to_string)
+}
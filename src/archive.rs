@@ -0,0 +1,1656 @@
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use zip::read::ZipFile;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::download::TemplateTooLarge;
+use crate::error::{CancellationToken, Result};
+use crate::platform;
+use crate::status;
+use crate::symbols::Symbol;
+
+/// Size of the read buffer shared by every extracted entry
+const EXTRACT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Size of the write buffer of each extracted file, kept small because most
+/// template files are a few KiB and a buffer is allocated per file
+const WRITE_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Whether the file systems of the platform ignore the letter case by
+/// default, two entries that only differ in it are then the same file
+const CASE_INSENSITIVE_PATHS: bool = cfg!(any(windows, target_os = "macos"));
+
+/// Entries added by macOS when it zips a directory: resource forks under
+/// `__MACOSX/` and the Finder metadata of each directory
+pub const MACOS_METADATA_ENTRIES: &[&str] = &["__MACOSX", ".DS_Store"];
+
+/// Names of the build-system files of an ESP-IDF project, the files
+/// extracted with [`ExtractOptions::build_files_only`] along with the
+/// `sdkconfig*`, `*.cmake` and `*.csv` (partition table) files
+pub const BUILD_FILES: &[&str] = &[
+    "CMakeLists.txt",
+    "Kconfig",
+    "Kconfig.projbuild",
+    "idf_component.yml",
+];
+
+/// Whether the file is part of the build system of the project, see
+/// [`BUILD_FILES`]
+///
+/// # Arguments
+/// * `path` - The path of the file, relative to the project
+pub fn is_build_file(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+    BUILD_FILES.contains(&name)
+        || name.starts_with("sdkconfig")
+        || matches!(
+            path.extension().and_then(|extension| extension.to_str()),
+            Some("cmake") | Some("csv")
+        )
+}
+
+/// Finds the directory that contains every entry of the archive
+///
+/// GitHub archives have a single root directory (e.g. `esp-idf-template-master/`)
+/// that must be stripped, while flat archives store the files at the root.
+/// The macOS metadata entries (see [`MACOS_METADATA_ENTRIES`]) don't count,
+/// `__MACOSX/` sits next to the root directory of archives zipped on macOS.
+///
+/// # Arguments
+/// * `zip` - The zip archive to inspect
+///
+/// # Returns
+/// The common root directory, or an empty path if the entries don't share one
+pub fn detect_prefix<R: Read + Seek>(zip: &ZipArchive<R>) -> PathBuf {
+    let mut root: Option<PathBuf> = None;
+    for name in zip.file_names() {
+        let path = match platform::archive_entry_path(name) {
+            Some(path) if !is_skipped(&path, MACOS_METADATA_ENTRIES) => path,
+            _ => continue,
+        };
+
+        let mut components = path.components();
+        let first = match components.next() {
+            Some(first) => PathBuf::from(first.as_os_str()),
+            None => continue,
+        };
+        // A file at the top level means there is no root directory
+        if components.next().is_none() && !name.ends_with('/') {
+            return PathBuf::new();
+        }
+        match &root {
+            Some(root) if *root != first => return PathBuf::new(),
+            Some(_) => {}
+            None => root = Some(first),
+        }
+    }
+    root.unwrap_or_default()
+}
+
+/// Checks that the files of the archive add up to at most `max_size`
+/// before anything is extracted, so a zip bomb is refused
+///
+/// The sizes are the ones stored in the central directory of the archive.
+///
+/// # Arguments
+/// * `zip` - The zip archive to inspect
+/// * `max_size` - The largest total accepted, in bytes
+///
+/// # Returns
+/// The total size of the files
+///
+/// # Errors
+/// If an entry cannot be read, or the total is larger than `max_size` (see
+/// [`TemplateTooLarge`])
+pub fn check_extracted_size<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    max_size: u64,
+) -> anyhow::Result<u64> {
+    let mut total: u64 = 0;
+    for i in 0..zip.len() {
+        let file = zip
+            .by_index_raw(i)
+            .context(format!("Cannot read entry #{}", i))?;
+        total = total.saturating_add(file.size());
+    }
+    if total > max_size {
+        return Err(anyhow::Error::new(TemplateTooLarge {
+            size: Some(total),
+            extracted: true,
+            limit: max_size,
+        }));
+    }
+    Ok(total)
+}
+
+/// An archive entry that could not be extracted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailedEntry {
+    /// The entry name as stored in the archive
+    pub name: String,
+    /// The error, including its causes
+    pub error: String,
+}
+
+/// How [`extract_zip`] handles errors, creates directories and which entries
+/// it leaves out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Whether an entry that cannot be extracted is skipped instead of
+    /// aborting the extraction
+    pub keep_going: bool,
+    /// Permissions of the created directories (Unix only), the system default if `None`
+    pub dir_mode: Option<u32>,
+    /// Names of the files and directories that are not extracted, wherever
+    /// they are in the archive; [`MACOS_METADATA_ENTRIES`] by default
+    pub skipped_entries: &'static [&'static str],
+    /// Whether only the build-system files are extracted, see
+    /// [`is_build_file`]; the directories are created for them only
+    pub build_files_only: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            keep_going: false,
+            dir_mode: None,
+            skipped_entries: MACOS_METADATA_ENTRIES,
+            build_files_only: false,
+        }
+    }
+}
+
+/// Checks if a component of the path is one of the skipped names
+///
+/// # Arguments
+/// * `path` - The path of the entry
+/// * `skipped` - The names, see [`ExtractOptions::skipped_entries`]
+pub(crate) fn is_skipped(path: &Path, skipped: &[&str]) -> bool {
+    path.components()
+        .any(|component| skipped.iter().any(|name| component.as_os_str() == *name))
+}
+
+/// Extracts the zip template file to the directory
+///
+/// An archive can store the same path twice; the first entry is extracted
+/// and the duplicates are errors, so the result doesn't depend on which one
+/// is written last.
+///
+/// The directory is resolved to its real path first (it may be a symlink),
+/// and every directory and file is checked to be inside it before it's
+/// written, so a symlink in the project can't redirect an entry elsewhere.
+///
+/// # Arguments
+/// * `directory` - The directory to extract the template to
+/// * `zip` - The zip archive to extract
+/// * `prefix` - The zip directory prefix, stripped from every entry (see [`detect_prefix`])
+/// * `options` - How errors are handled and directories created
+/// * `token` - Token checked between entries
+///
+/// # Returns
+/// The entries skipped because of an error, always empty unless
+/// `options.keep_going` is set
+///
+/// # Errors
+/// If the extraction is cancelled, or an entry cannot be extracted (or is a
+/// duplicate) and `options.keep_going` is not set
+pub fn extract_zip<R: Read + Seek>(
+    directory: &str,
+    zip: &mut ZipArchive<R>,
+    prefix: &Path,
+    options: ExtractOptions,
+    token: &CancellationToken,
+) -> Result<Vec<FailedEntry>> {
+    // A single read buffer is reused for every entry and the directories
+    // already created are remembered, which matters for large templates
+    let mut buffer = vec![0; EXTRACT_BUFFER_SIZE];
+    platform::create_dir_all_with_mode(Path::new(directory), options.dir_mode)
+        .context(format!("Failed to create directory \"{}\"", directory))?;
+    let root = fs::canonicalize(directory)
+        .context(format!("Cannot resolve the directory \"{}\"", directory))?;
+    let mut created_dirs = HashSet::new();
+    let mut extracted_files = HashSet::new();
+    let mut failed = Vec::new();
+
+    for i in 0..zip.len() {
+        token.check()?;
+        let mut file = match zip.by_index(i) {
+            Ok(file) => file,
+            Err(e) => {
+                let error = anyhow::Error::new(e).context(format!("Cannot read entry #{}", i));
+                if !options.keep_going {
+                    return Err(error.into());
+                }
+                status::warn(Symbol::Warning, &format!("{:#}, skipping it", error));
+                failed.push(FailedEntry {
+                    name: format!("#{}", i),
+                    error: format!("{:#}", error),
+                });
+                continue;
+            }
+        };
+
+        let mut dirs = DirCache {
+            root: &root,
+            created: &mut created_dirs,
+            mode: options.dir_mode,
+        };
+        if let Err(error) = extract_entry(
+            directory,
+            &mut file,
+            prefix,
+            options,
+            &mut buffer,
+            &mut dirs,
+            &mut extracted_files,
+        ) {
+            if !options.keep_going {
+                return Err(error.into());
+            }
+            status::warn(Symbol::Warning, &format!("{:#}, skipping it", error));
+            failed.push(FailedEntry {
+                name: file.name().to_string(),
+                error: format!("{:#}", error),
+            });
+        }
+    }
+    Ok(failed)
+}
+
+/// The path of an extracted file in the duplicate check of [`extract_zip`],
+/// in lower case if the letter case is ignored
+fn duplicate_key(path: &Path, case_insensitive: bool) -> PathBuf {
+    if case_insensitive {
+        PathBuf::from(path.to_string_lossy().to_lowercase())
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Extracts a single entry, see [`extract_zip`]
+fn extract_entry(
+    directory: &str,
+    file: &mut ZipFile,
+    prefix: &Path,
+    options: ExtractOptions,
+    buffer: &mut [u8],
+    dirs: &mut DirCache,
+    extracted_files: &mut HashSet<PathBuf>,
+) -> anyhow::Result<()> {
+    let outpath = match platform::archive_entry_path(file.name()) {
+        Some(path) if !is_skipped(&path, options.skipped_entries) => path,
+        _ => return Ok(()),
+    };
+    let relative = match outpath.strip_prefix(prefix) {
+        // The root directory itself
+        Ok(relative) if relative.as_os_str().is_empty() => return Ok(()),
+        Ok(relative) => relative,
+        Err(_) => return Ok(()),
+    };
+
+    let directory_entry = file.name().ends_with('/');
+    if options.build_files_only && (directory_entry || !is_build_file(relative)) {
+        return Ok(());
+    }
+    let outpath = PathBuf::new().join(directory).join(relative);
+    if directory_entry {
+        return dirs.create(&outpath);
+    }
+    if !extracted_files.insert(duplicate_key(&outpath, CASE_INSENSITIVE_PATHS)) {
+        bail!(
+            "Duplicate entry \"{}\", the first one with this path{} was extracted",
+            file.name(),
+            if CASE_INSENSITIVE_PATHS {
+                " (the letter case aside)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    if let Some(p) = outpath.parent() {
+        dirs.create(p)?;
+        dirs.check_inside_root(p)?;
+    }
+    // File::create would write to the target of the link
+    if fs::symlink_metadata(&outpath).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        bail!(
+            "\"{}\" is a symbolic link, it cannot be overwritten",
+            outpath.display()
+        );
+    }
+
+    let outfile = File::create(&outpath)
+        .context(format!("Failed to create file \"{}\"", outpath.display()))?;
+    platform::set_file_mode(&outfile, file.unix_mode()).context(format!(
+        "Failed to set permissions of \"{}\"",
+        outpath.display()
+    ))?;
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, outfile);
+    copy_with_buffer(file, &mut writer, buffer)
+        .and_then(|_| writer.flush())
+        .context(format!("Failed to unzip file \"{}\"", file.name()))
+}
+
+/// The directories created so far by [`extract_zip`]
+struct DirCache<'a> {
+    /// The real path of the extraction directory
+    root: &'a Path,
+    created: &'a mut HashSet<PathBuf>,
+    mode: Option<u32>,
+}
+
+impl DirCache<'_> {
+    /// Creates a directory and its parents unless it was already created
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to create
+    ///
+    /// # Errors
+    /// If it cannot be created or its existing part resolves outside the root
+    fn create(&mut self, dir: &Path) -> anyhow::Result<()> {
+        if !self.created.contains(dir) {
+            // Checked before creating, the missing parents would be created
+            // wherever a symlink in the existing part points to
+            if let Some(existing) = dir.ancestors().find(|dir| dir.exists()) {
+                self.check_inside_root(existing)?;
+            }
+            platform::create_dir_all_with_mode(dir, self.mode)
+                .context(format!("Failed to create directory \"{}\"", dir.display()))?;
+            self.created.insert(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Checks that an existing directory resolves inside the root
+    ///
+    /// # Errors
+    /// If it cannot be resolved or it's outside the root
+    fn check_inside_root(&self, dir: &Path) -> anyhow::Result<()> {
+        let real = fs::canonicalize(dir).context(format!(
+            "Cannot resolve the directory \"{}\"",
+            dir.display()
+        ))?;
+        if !real.starts_with(self.root) {
+            bail!(
+                "\"{}\" resolves to \"{}\", outside the project \"{}\"",
+                dir.display(),
+                real.display(),
+                self.root.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Copies the reader to the writer through the given buffer
+///
+/// Unlike `io::copy`, the buffer is provided by the caller so it can be reused.
+fn copy_with_buffer<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    buffer: &mut [u8],
+) -> io::Result<u64> {
+    let mut copied = 0;
+    loop {
+        let read = match reader.read(buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+    }
+}
+
+/// Lists the files under a directory, sorted so an archive of the same
+/// files is always the same
+///
+/// The links are listed as files, not followed.
+///
+/// # Arguments
+/// * `root` - The directory
+/// * `excluded` - Top-level entries left out, e.g. `.git`
+///
+/// # Returns
+/// The paths of the files relative to `root`
+///
+/// # Errors
+/// If a directory cannot be read
+pub fn list_files(root: &Path, excluded: &[&str]) -> anyhow::Result<Vec<PathBuf>> {
+    Ok(list_tree(root, excluded)?.0)
+}
+
+/// [`list_files`], and the empty directories, which an archive has to list
+/// on their own (e.g. `.git/refs/tags`)
+///
+/// # Returns
+/// The files and the empty directories, relative to `root`
+///
+/// # Errors
+/// If a directory cannot be read
+pub fn list_tree(root: &Path, excluded: &[&str]) -> anyhow::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    let mut empty_dirs = Vec::new();
+    list_files_in(root, Path::new(""), excluded, &mut files, &mut empty_dirs)?;
+    Ok((files, empty_dirs))
+}
+
+fn list_files_in(
+    root: &Path,
+    relative: &Path,
+    excluded: &[&str],
+    files: &mut Vec<PathBuf>,
+    empty_dirs: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let dir = root.join(relative);
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .context(format!("Cannot read \"{}\"", dir.display()))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    if entries.is_empty() && !relative.as_os_str().is_empty() {
+        empty_dirs.push(relative.to_path_buf());
+    }
+    for entry in entries {
+        let name = entry.file_name();
+        if relative.as_os_str().is_empty() && excluded.iter().any(|excluded| name == *excluded) {
+            continue;
+        }
+        let path = relative.join(&name);
+        let metadata = fs::symlink_metadata(entry.path())
+            .context(format!("Cannot read \"{}\"", entry.path().display()))?;
+        if metadata.is_dir() {
+            list_files_in(root, &path, excluded, files, empty_dirs)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// A file of a template and its size, whatever the template is stored in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySize {
+    /// The path of the file, relative to the root directory of the template
+    pub path: PathBuf,
+    /// The size of its content, uncompressed
+    pub size: u64,
+}
+
+/// What an entry of a template is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// In a zip, an entry with the link file type in its Unix mode
+    Symlink,
+}
+
+/// An entry of a zip archive or a directory, as stored: the root directory
+/// of a zip is not stripped, nothing is skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StoredEntry {
+    pub path: PathBuf,
+    /// The size of its content, uncompressed; 0 for a directory
+    pub size: u64,
+    pub kind: EntryKind,
+}
+
+/// The entries of a zip archive with a valid path, in the order of the
+/// archive
+///
+/// # Errors
+/// If an entry cannot be read
+pub(crate) fn zip_entries<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+) -> anyhow::Result<Vec<StoredEntry>> {
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let file = zip
+            .by_index_raw(i)
+            .context(format!("Cannot read entry #{}", i))?;
+        let kind = if file.is_dir() {
+            EntryKind::Directory
+        } else if file
+            .unix_mode()
+            .is_some_and(|mode| mode & 0o170_000 == 0o120_000)
+        {
+            EntryKind::Symlink
+        } else {
+            EntryKind::File
+        };
+        if let Some(path) = platform::archive_entry_path(file.name()) {
+            entries.push(StoredEntry {
+                path,
+                size: file.size(),
+                kind,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// The files of a zip template and their sizes, without its root directory
+/// (see [`detect_prefix`]) and the macOS metadata
+///
+/// # Errors
+/// If an entry cannot be read
+pub fn zip_entry_sizes<R: Read + Seek>(zip: &mut ZipArchive<R>) -> anyhow::Result<Vec<EntrySize>> {
+    let prefix = detect_prefix(zip);
+    Ok(zip_entries(zip)?
+        .into_iter()
+        .filter(|entry| {
+            entry.kind != EntryKind::Directory && !is_skipped(&entry.path, MACOS_METADATA_ENTRIES)
+        })
+        .map(|entry| EntrySize {
+            path: entry
+                .path
+                .strip_prefix(&prefix)
+                .unwrap_or(&entry.path)
+                .to_path_buf(),
+            size: entry.size,
+        })
+        .collect())
+}
+
+/// The files of a gzip-compressed tar template and their sizes, without
+/// their common root directory and the macOS metadata
+///
+/// # Errors
+/// If the archive is not gzip-compressed or a header is invalid
+pub fn tar_gz_entry_sizes(reader: impl Read) -> anyhow::Result<Vec<EntrySize>> {
+    let entries = tar_gz_entries(reader)?
+        .into_iter()
+        // Regular files only, not directories, links or pax headers
+        .filter(|entry| entry.is_file() && !is_skipped(&entry.path, MACOS_METADATA_ENTRIES))
+        .map(|entry| EntrySize {
+            path: entry.path,
+            size: entry.size,
+        })
+        .collect();
+    Ok(strip_common_root(entries))
+}
+
+/// An entry of a tar archive, as stored: its root directory is not stripped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TarEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub kind: tar::EntryType,
+}
+
+impl TarEntry {
+    /// Whether the entry is a regular file
+    pub fn is_file(&self) -> bool {
+        self.kind.is_file()
+    }
+}
+
+/// The entries of a gzip-compressed tar archive with a valid path, the GNU
+/// long names and the pax headers applied to the entry they describe
+///
+/// The contents are skipped, not read in memory.
+///
+/// # Errors
+/// If the archive is not gzip-compressed or a header is invalid
+pub(crate) fn tar_gz_entries(reader: impl Read) -> anyhow::Result<Vec<TarEntry>> {
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(reader));
+    let mut entries = Vec::new();
+    for entry in tar
+        .entries()
+        .context("The template is not a valid tar.gz")?
+    {
+        let entry = entry.context("The template is not a valid tar.gz")?;
+        let name = entry
+            .path()
+            .context("The template is not a valid tar.gz")?
+            .to_string_lossy()
+            .into_owned();
+        if let Some(path) = platform::archive_entry_path(&name) {
+            entries.push(TarEntry {
+                path,
+                size: entry.size(),
+                kind: entry.header().entry_type(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Strips the directory every entry is in, if they share one, as
+/// [`detect_prefix`] does for a zip
+fn strip_common_root(entries: Vec<EntrySize>) -> Vec<EntrySize> {
+    let first = |path: &Path| -> Option<PathBuf> {
+        let mut components = path.components();
+        let first = components.next()?;
+        components.next().map(|_| PathBuf::from(first.as_os_str()))
+    };
+    let root = match entries.first().and_then(|entry| first(&entry.path)) {
+        Some(root)
+            if entries
+                .iter()
+                .all(|entry| first(&entry.path).as_ref() == Some(&root)) =>
+        {
+            root
+        }
+        _ => return entries,
+    };
+    entries
+        .into_iter()
+        .map(|entry| EntrySize {
+            path: entry
+                .path
+                .strip_prefix(&root)
+                .unwrap_or(&entry.path)
+                .to_path_buf(),
+            size: entry.size,
+        })
+        .collect()
+}
+
+/// The files of a directory and their sizes, `.git` excluded
+///
+/// # Errors
+/// If a directory or a file cannot be read
+pub fn directory_entry_sizes(root: &Path) -> anyhow::Result<Vec<EntrySize>> {
+    Ok(directory_entries(root)?
+        .into_iter()
+        .filter(|entry| entry.kind != EntryKind::Directory)
+        .map(|entry| EntrySize {
+            path: entry.path,
+            size: entry.size,
+        })
+        .collect())
+}
+
+/// The entries of a directory sorted by path, `.git` excluded; the links
+/// are listed, not followed
+///
+/// # Errors
+/// If a directory or an entry cannot be read
+pub(crate) fn directory_entries(root: &Path) -> anyhow::Result<Vec<StoredEntry>> {
+    let mut entries = Vec::new();
+    directory_entries_in(root, Path::new(""), &mut entries)?;
+    Ok(entries)
+}
+
+fn directory_entries_in(
+    root: &Path,
+    relative: &Path,
+    entries: &mut Vec<StoredEntry>,
+) -> anyhow::Result<()> {
+    let dir = root.join(relative);
+    let mut children: Vec<_> = fs::read_dir(&dir)
+        .and_then(|children| children.collect::<io::Result<Vec<_>>>())
+        .context(format!("Cannot read \"{}\"", dir.display()))?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let path = relative.join(child.file_name());
+        if path == Path::new(".git") {
+            continue;
+        }
+        let metadata = fs::symlink_metadata(child.path())
+            .context(format!("Cannot read \"{}\"", child.path().display()))?;
+        if metadata.file_type().is_symlink() {
+            entries.push(StoredEntry {
+                path,
+                size: metadata.len(),
+                kind: EntryKind::Symlink,
+            });
+        } else if metadata.is_dir() {
+            entries.push(StoredEntry {
+                path: path.clone(),
+                size: 0,
+                kind: EntryKind::Directory,
+            });
+            directory_entries_in(root, &path, entries)?;
+        } else {
+            entries.push(StoredEntry {
+                path,
+                size: metadata.len(),
+                kind: EntryKind::File,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Name of a file in an archive, always with `/` separators
+///
+/// # Arguments
+/// * `relative` - The path of the file relative to the archived directory
+pub fn entry_name(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Timestamp of the entries of a `--reproducible` archive: 1980-01-01, the
+/// oldest a zip archive can store
+pub const REPRODUCIBLE_MTIME: u64 = 315_532_800;
+
+/// Formats a project can be archived to, see [`archive_directory`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// The format of an archive from its extension: `.zip`, `.tar.gz` or `.tgz`
+    ///
+    /// # Errors
+    /// If the extension is none of them
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".zip") {
+            Ok(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(ArchiveFormat::TarGz)
+        } else {
+            bail!(
+                "Unknown archive format \"{}\", use .zip, .tar.gz or .tgz",
+                path.display()
+            )
+        }
+    }
+}
+
+/// Permissions of a file in an archive
+///
+/// # Arguments
+/// * `path` - The file
+/// * `reproducible` - Only keep whether the file is executable, so the
+///   umask doesn't change the archive
+#[cfg(unix)]
+pub fn file_mode(path: &Path, reproducible: bool) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path).map_or(0o644, |metadata| metadata.permissions().mode());
+    match (reproducible, mode & 0o111 != 0) {
+        (false, _) => mode & 0o777,
+        (true, true) => 0o755,
+        (true, false) => 0o644,
+    }
+}
+
+/// Permissions of a file in an archive
+#[cfg(not(unix))]
+pub fn file_mode(_path: &Path, _reproducible: bool) -> u32 {
+    0o644
+}
+
+/// Options of the archive entry of a file, keeping the executable bit of scripts
+#[cfg(unix)]
+pub fn entry_options(path: &Path) -> FileOptions {
+    FileOptions::default().unix_permissions(file_mode(path, false))
+}
+
+/// Options of the archive entry of a file
+#[cfg(not(unix))]
+pub fn entry_options(_path: &Path) -> FileOptions {
+    FileOptions::default()
+}
+
+/// Writes a directory to an archive, under a top-level directory so it
+/// unpacks to a single directory
+///
+/// The entries are sorted; with `reproducible` their timestamps are
+/// [`REPRODUCIBLE_MTIME`] and their permissions only keep the executable
+/// bit, so the same files always give the same archive.
+///
+/// # Arguments
+/// * `root` - The directory to archive
+/// * `prefix` - Name of the top-level directory in the archive
+/// * `out` - The archive to write, its format comes from its extension, see
+///   [`ArchiveFormat::from_path`]
+/// * `reproducible` - Whether the archive only depends on the files
+///
+/// # Returns
+/// The number of files in the archive
+///
+/// # Errors
+/// If the format is unknown, a file cannot be read or the archive cannot be
+/// written
+pub fn archive_directory(
+    root: &Path,
+    prefix: &str,
+    out: &Path,
+    reproducible: bool,
+) -> anyhow::Result<usize> {
+    let (files, empty_dirs) = list_tree(root, &[])?;
+    let file = File::create(out).context(format!("Cannot create \"{}\"", out.display()))?;
+    match ArchiveFormat::from_path(out)? {
+        ArchiveFormat::Zip => write_zip(root, prefix, &files, &empty_dirs, file, reproducible),
+        ArchiveFormat::TarGz => write_tar_gz(root, prefix, &files, &empty_dirs, file, reproducible),
+    }
+    .context(format!("Cannot write \"{}\"", out.display()))?;
+    Ok(files.len())
+}
+
+/// The target of a link, with `/` separators if it is relative
+fn link_target(path: &Path) -> anyhow::Result<String> {
+    let target = fs::read_link(path).context(format!("Cannot read \"{}\"", path.display()))?;
+    Ok(if target.is_relative() {
+        entry_name(&target)
+    } else {
+        target.to_string_lossy().into_owned()
+    })
+}
+
+/// Checks if a file of [`list_tree`] is a link, which is stored as one
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Writes the files and the empty directories of [`list_tree`] to a zip archive
+fn write_zip<W: Write + Seek>(
+    root: &Path,
+    prefix: &str,
+    files: &[PathBuf],
+    empty_dirs: &[PathBuf],
+    out: W,
+    reproducible: bool,
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(out);
+    let options = |mode: u32| {
+        let options = FileOptions::default().unix_permissions(mode);
+        if reproducible {
+            options.last_modified_time(zip::DateTime::default())
+        } else {
+            options
+        }
+    };
+    for relative in files {
+        let path = root.join(relative);
+        let name = format!("{}/{}", prefix, entry_name(relative));
+        let added = if is_symlink(&path) {
+            zip.add_symlink(name, link_target(&path)?, options(0o777))
+                .map_err(Into::into)
+        } else {
+            let content = fs::read(&path).context(format!("Cannot read \"{}\"", path.display()))?;
+            zip.start_file(name, options(file_mode(&path, reproducible)))
+                .and_then(|_| zip.write_all(&content).map_err(Into::into))
+                .map_err(anyhow::Error::from)
+        };
+        added.context(format!(
+            "Cannot add \"{}\" to the archive",
+            relative.display()
+        ))?;
+    }
+    for relative in empty_dirs {
+        zip.add_directory(
+            format!("{}/{}", prefix, entry_name(relative)),
+            options(0o755),
+        )
+        .context(format!(
+            "Cannot add \"{}\" to the archive",
+            relative.display()
+        ))?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Writes the files and the empty directories of [`list_tree`] to a
+/// gzip-compressed tar archive, the names longer than the ustar header
+/// stored as GNU long names
+fn write_tar_gz<W: Write>(
+    root: &Path,
+    prefix: &str,
+    files: &[PathBuf],
+    empty_dirs: &[PathBuf],
+    out: W,
+    reproducible: bool,
+) -> anyhow::Result<()> {
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        BufWriter::new(out),
+        flate2::Compression::default(),
+    ));
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(REPRODUCIBLE_MTIME, |now| now.as_secs());
+    let mtime = if reproducible {
+        REPRODUCIBLE_MTIME
+    } else {
+        now
+    };
+    // Owned by root
+    let header = |kind: tar::EntryType, mode: u32, size: u64| {
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(kind);
+        header.set_mode(mode);
+        header.set_size(size);
+        header.set_mtime(mtime);
+        header.set_uid(0);
+        header.set_gid(0);
+        header
+    };
+    for relative in files {
+        let path = root.join(relative);
+        let name = format!("{}/{}", prefix, entry_name(relative));
+        if is_symlink(&path) {
+            let mut header = header(tar::EntryType::Symlink, 0o777, 0);
+            tar.append_link(&mut header, &name, link_target(&path)?)
+        } else {
+            let content = fs::read(&path).context(format!("Cannot read \"{}\"", path.display()))?;
+            let mut header = header(
+                tar::EntryType::Regular,
+                file_mode(&path, reproducible),
+                content.len() as u64,
+            );
+            tar.append_data(&mut header, &name, content.as_slice())
+        }
+        .context(format!(
+            "Cannot add \"{}\" to the archive",
+            relative.display()
+        ))?;
+    }
+    for relative in empty_dirs {
+        let name = format!("{}/{}/", prefix, entry_name(relative));
+        let mut header = header(tar::EntryType::Directory, 0o755, 0);
+        tar.append_data(&mut header, &name, io::empty())
+            .context(format!(
+                "Cannot add \"{}\" to the archive",
+                relative.display()
+            ))?;
+    }
+    tar.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EspCreateError;
+    use crate::test_fixtures;
+
+    /// Cancels the token when the archive is read from `cancel_at` on,
+    /// the central directory excepted
+    struct CancellingReader {
+        inner: io::Cursor<Vec<u8>>,
+        token: CancellationToken,
+        cancel_at: u64,
+        central_directory: u64,
+    }
+
+    impl Read for CancellingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let position = self.inner.position();
+            if position >= self.cancel_at && position < self.central_directory {
+                self.token.cancel();
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for CancellingReader {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_check_extracted_size() {
+        let padding = vec![0; 100 * 1024];
+        let mut zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("root/", b""),
+            ("root/a.bin", &padding),
+            ("root/b.bin", &padding),
+        ])))
+        .unwrap();
+
+        assert_eq!(
+            check_extracted_size(&mut zip, 200 * 1024).unwrap(),
+            200 * 1024
+        );
+        let error = check_extracted_size(&mut zip, 150 * 1024).unwrap_err();
+        assert!(error.is::<TemplateTooLarge>());
+        assert!(error.to_string().contains("extracts to 200KB"));
+    }
+
+    #[test]
+    fn test_detect_prefix() {
+        let zip = ZipArchive::new(io::Cursor::new(test_fixtures::template_zip())).unwrap();
+        assert_eq!(
+            detect_prefix(&zip),
+            PathBuf::from("esp-idf-template-master")
+        );
+
+        // Single root without an entry for the directory itself
+        let zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("root/CMakeLists.txt", b""),
+            ("root/main/main.c", b""),
+        ])))
+        .unwrap();
+        assert_eq!(detect_prefix(&zip), PathBuf::from("root"));
+    }
+
+    #[test]
+    fn test_extract_flat_zip() {
+        let root = tempfile::tempdir().unwrap();
+        let mut zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("CMakeLists.txt", test_fixtures::TEMPLATE_CMAKE.as_bytes()),
+            ("main/", b""),
+            ("main/main.c", test_fixtures::TEMPLATE_MAIN_C.as_bytes()),
+        ])))
+        .unwrap();
+
+        let prefix = detect_prefix(&zip);
+        assert_eq!(prefix, PathBuf::new());
+
+        let directory = root.path().to_str().unwrap();
+        extract_zip(
+            directory,
+            &mut zip,
+            &prefix,
+            ExtractOptions::default(),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert!(root.path().join("CMakeLists.txt").exists());
+        assert!(root.path().join("main").join("main.c").exists());
+    }
+
+    #[test]
+    fn test_extract_skips_macos_metadata() {
+        let macos_zip = || {
+            ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+                ("blink/", b""),
+                ("blink/CMakeLists.txt", b""),
+                ("blink/.DS_Store", b"finder"),
+                ("blink/main/main.c", b""),
+                ("blink/main/.DS_Store", b"finder"),
+                ("__MACOSX/", b""),
+                ("__MACOSX/blink/._CMakeLists.txt", b"fork"),
+                ("__MACOSX/blink/main/._main.c", b"fork"),
+            ])))
+            .unwrap()
+        };
+        let mut zip = macos_zip();
+        let prefix = detect_prefix(&zip);
+        assert_eq!(prefix, PathBuf::from("blink"));
+
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().to_str().unwrap();
+        extract_zip(
+            directory,
+            &mut zip,
+            &prefix,
+            ExtractOptions::default(),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            list_files(root.path(), &[]).unwrap(),
+            [
+                PathBuf::from("CMakeLists.txt"),
+                Path::new("main").join("main.c")
+            ]
+        );
+
+        // Only __MACOSX skipped, the Finder files are kept
+        let root = tempfile::tempdir().unwrap();
+        let options = ExtractOptions {
+            skipped_entries: &["__MACOSX"],
+            ..ExtractOptions::default()
+        };
+        extract_zip(
+            root.path().to_str().unwrap(),
+            &mut macos_zip(),
+            &prefix,
+            options,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert!(root.path().join(".DS_Store").exists());
+        assert!(root.path().join("main").join(".DS_Store").exists());
+    }
+
+    #[test]
+    fn test_extract_build_files_only() {
+        let root = tempfile::tempdir().unwrap();
+        let mut zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("CMakeLists.txt", b""),
+            ("README.md", b""),
+            ("sdkconfig.defaults.esp32c3", b""),
+            ("partitions.csv", b""),
+            ("docs/", b""),
+            ("main/", b""),
+            ("main/CMakeLists.txt", b""),
+            ("main/Kconfig.projbuild", b""),
+            ("main/main.c", b""),
+            ("main/include/main.h", b""),
+            ("cmake/tools.cmake", b""),
+        ])))
+        .unwrap();
+        let options = ExtractOptions {
+            build_files_only: true,
+            ..ExtractOptions::default()
+        };
+
+        extract_zip(
+            root.path().to_str().unwrap(),
+            &mut zip,
+            Path::new(""),
+            options,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        let mut files = list_files(root.path(), &[]).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "CMakeLists.txt",
+                "cmake/tools.cmake",
+                "main/CMakeLists.txt",
+                "main/Kconfig.projbuild",
+                "partitions.csv",
+                "sdkconfig.defaults.esp32c3",
+            ]
+            .map(PathBuf::from)
+        );
+        assert!(!root.path().join("docs").exists());
+    }
+
+    #[test]
+    fn test_extract_flat_zip_single_directory() {
+        // Every entry shares "main/" but there are top-level files too
+        let zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("main/", b""),
+            ("main/main.c", b""),
+            ("README.md", b""),
+        ])))
+        .unwrap();
+        assert_eq!(detect_prefix(&zip), PathBuf::new());
+    }
+    #[test]
+    fn test_extract_zip_cancelled() {
+        let root = tempfile::tempdir().unwrap();
+        let mut zip = ZipArchive::new(io::Cursor::new(test_fixtures::template_zip())).unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = extract_zip(
+            root.path().to_str().unwrap(),
+            &mut zip,
+            Path::new("esp-idf-template-master/"),
+            ExtractOptions::default(),
+            &token,
+        );
+
+        assert!(matches!(result, Err(EspCreateError::Cancelled)));
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_extract_zip_cancelled_midway() {
+        let root = tempfile::tempdir().unwrap();
+        let content = vec![b'#'; 1024];
+        let names: Vec<String> = (0..6).map(|i| format!("root/{}.txt", i)).collect();
+        let entries: Vec<(&str, &[u8])> = names
+            .iter()
+            .map(|name| (name.as_str(), content.as_slice()))
+            .collect();
+        let archive = test_fixtures::zip_archive(&entries);
+
+        // Cancelled while the fourth entry is read
+        let mut zip = ZipArchive::new(io::Cursor::new(archive.clone())).unwrap();
+        let cancel_at = zip.by_index(3).unwrap().header_start();
+        let central_directory = zip.by_index(0).unwrap().central_header_start();
+        let token = CancellationToken::new();
+        let mut zip = ZipArchive::new(CancellingReader {
+            inner: io::Cursor::new(archive),
+            token: token.clone(),
+            cancel_at,
+            central_directory,
+        })
+        .unwrap();
+        let result = extract_zip(
+            root.path().to_str().unwrap(),
+            &mut zip,
+            Path::new("root/"),
+            ExtractOptions::default(),
+            &token,
+        );
+
+        assert!(matches!(result, Err(EspCreateError::Cancelled)));
+        for i in 0..4 {
+            assert!(root.path().join(format!("{}.txt", i)).is_file());
+        }
+        assert!(!root.path().join("4.txt").exists());
+        assert!(!root.path().join("5.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_zip_duplicate_entries() {
+        let duplicate_zip = || {
+            ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+                ("main/main.c", b"first"),
+                ("main/./main.c", b"second"),
+                ("CMakeLists.txt", b"ok"),
+            ])))
+            .unwrap()
+        };
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().to_str().unwrap();
+
+        let error = extract_zip(
+            directory,
+            &mut duplicate_zip(),
+            Path::new(""),
+            ExtractOptions::default(),
+            &CancellationToken::new(),
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", anyhow::Error::from(error)).contains("Duplicate entry"));
+
+        let failed = extract_zip(
+            directory,
+            &mut duplicate_zip(),
+            Path::new(""),
+            ExtractOptions {
+                keep_going: true,
+                ..ExtractOptions::default()
+            },
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].name, "main/./main.c");
+        let main = root.path().join("main").join("main.c");
+        assert_eq!(fs::read_to_string(main).unwrap(), "first");
+        assert!(root.path().join("CMakeLists.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_zip_case_duplicates() {
+        let root = tempfile::tempdir().unwrap();
+        let mut zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("main/Main.c", b"first"),
+            ("main/main.c", b"second"),
+        ])))
+        .unwrap();
+
+        let result = extract_zip(
+            root.path().to_str().unwrap(),
+            &mut zip,
+            Path::new(""),
+            ExtractOptions::default(),
+            &CancellationToken::new(),
+        );
+
+        let main = root.path().join("main");
+        if CASE_INSENSITIVE_PATHS {
+            let error = format!("{:#}", anyhow::Error::from(result.unwrap_err()));
+            assert!(
+                error.contains("Duplicate entry \"main/main.c\""),
+                "{}",
+                error
+            );
+            assert_eq!(fs::read_to_string(main.join("Main.c")).unwrap(), "first");
+        } else {
+            result.unwrap();
+            assert_eq!(fs::read_to_string(main.join("Main.c")).unwrap(), "first");
+            assert_eq!(fs::read_to_string(main.join("main.c")).unwrap(), "second");
+        }
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        let upper = Path::new("blink").join("main").join("Main.c");
+        let lower = Path::new("blink").join("main").join("main.c");
+        assert_eq!(duplicate_key(&upper, true), duplicate_key(&lower, true));
+        assert_ne!(duplicate_key(&upper, false), duplicate_key(&lower, false));
+        assert_eq!(duplicate_key(&upper, false), upper);
+    }
+
+    /// Archive whose `main/main.c` cannot be written because a directory with
+    /// the same name is extracted first
+    fn conflicting_zip() -> ZipArchive<io::Cursor<Vec<u8>>> {
+        ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("main/main.c/", b""),
+            ("main/main.c", b"conflict"),
+            ("CMakeLists.txt", b"ok"),
+        ])))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_extract_zip_stops_on_error() {
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().to_str().unwrap();
+        let result = extract_zip(
+            directory,
+            &mut conflicting_zip(),
+            Path::new(""),
+            ExtractOptions::default(),
+            &CancellationToken::new(),
+        );
+
+        assert!(result.is_err());
+        assert!(!root.path().join("CMakeLists.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_zip_keep_going() {
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().to_str().unwrap();
+        let failed = extract_zip(
+            directory,
+            &mut conflicting_zip(),
+            Path::new(""),
+            ExtractOptions {
+                keep_going: true,
+                ..ExtractOptions::default()
+            },
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].name, "main/main.c");
+        assert_eq!(
+            fs::read_to_string(root.path().join("CMakeLists.txt")).unwrap(),
+            "ok"
+        );
+    }
+
+    #[test]
+    fn test_zip_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("blink");
+        crate::test_fixtures::write_template(&dir);
+        let out = root.path().join("blink.zip");
+
+        let files = archive_directory(&dir, "blink", &out, false).unwrap();
+
+        let zip = ZipArchive::new(File::open(&out).unwrap()).unwrap();
+        let names: Vec<&str> = zip.file_names().collect();
+        assert_eq!(files, names.len());
+        assert!(names.contains(&"blink/main/main.c"));
+        assert_eq!(detect_prefix(&zip), PathBuf::from("blink"));
+    }
+
+    #[test]
+    fn test_archive_format_from_path() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("blink.ZIP")).unwrap(),
+            ArchiveFormat::Zip
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("out/blink.tar.gz")).unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("blink.tgz")).unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert!(ArchiveFormat::from_path(Path::new("blink.tar")).is_err());
+    }
+
+    #[test]
+    fn test_archive_directory_reproducible() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("blink");
+        crate::test_fixtures::write_template(&dir);
+        fs::create_dir_all(dir.join(".git").join("refs").join("tags")).unwrap();
+
+        let first = root.path().join("first.tar.gz");
+        let second = root.path().join("second.tar.gz");
+        assert_eq!(archive_directory(&dir, "blink", &first, true).unwrap(), 4);
+        fs::write(dir.join("README.md"), "# Hello world template\n").unwrap();
+        archive_directory(&dir, "blink", &second, true).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), fs::read(&second).unwrap());
+
+        let mut tar = Vec::new();
+        flate2::read::GzDecoder::new(File::open(&first).unwrap())
+            .read_to_end(&mut tar)
+            .unwrap();
+        const TAR_BLOCK_SIZE: usize = 512;
+        let header = &tar[..TAR_BLOCK_SIZE];
+        assert!(header.starts_with(b"blink/CMakeLists.txt\0"));
+        assert_eq!(&header[257..263], b"ustar\0");
+        assert_eq!(
+            &header[136..148],
+            format!("{:011o}\0", REPRODUCIBLE_MTIME).as_bytes()
+        );
+        let checksum: u32 = header[..148]
+            .iter()
+            .chain(&[b' '; 8])
+            .chain(&header[156..])
+            .map(|byte| u32::from(*byte))
+            .sum();
+        let stored = String::from_utf8_lossy(&header[148..156]);
+        assert_eq!(
+            u32::from_str_radix(stored.trim_matches(|c| c == '\0' || c == ' '), 8).unwrap(),
+            checksum
+        );
+        let tags = tar
+            .chunks(TAR_BLOCK_SIZE)
+            .find(|block| block.starts_with(b"blink/.git/refs/tags/\0"))
+            .unwrap();
+        assert_eq!(tags[156], b'5');
+
+        let out = root.path().join("blink.zip");
+        archive_directory(&dir, "blink", &out, true).unwrap();
+        let mut zip = ZipArchive::new(File::open(&out).unwrap()).unwrap();
+        assert!(zip.by_name("blink/.git/refs/tags/").unwrap().is_dir());
+        let readme = zip.by_name("blink/README.md").unwrap();
+        assert_eq!(
+            readme.last_modified().datepart(),
+            zip::DateTime::default().datepart()
+        );
+    }
+
+    #[test]
+    fn test_archive_directory_long_names() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("blink");
+        // Longer than the name field, and than the name and prefix fields
+        let long = Path::new("components")
+            .join("component".repeat(12))
+            .join("main.c");
+        let longer = Path::new("components")
+            .join("a".repeat(200))
+            .join("b".repeat(100));
+        for relative in [&long, &longer] {
+            fs::create_dir_all(dir.join(relative).parent().unwrap()).unwrap();
+            fs::write(dir.join(relative), "ok").unwrap();
+        }
+
+        let out = root.path().join("blink.tar.gz");
+        assert_eq!(archive_directory(&dir, "blink", &out, true).unwrap(), 2);
+        let entries = tar_gz_entries(File::open(&out).unwrap()).unwrap();
+        let paths: Vec<_> = entries.iter().map(|entry| entry.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("blink").join(&longer),
+                Path::new("blink").join(&long)
+            ]
+        );
+        assert!(entries
+            .iter()
+            .all(|entry| entry.is_file() && entry.size == 2));
+    }
+
+    #[test]
+    fn test_tar_gz_entries_pax_path() {
+        let name = format!("blink/{}/main.c", "a".repeat(300));
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        tar.append_pax_extensions([("path", name.as_bytes())])
+            .unwrap();
+        let mut header = tar::Header::new_ustar();
+        header.set_path("blink/main.c").unwrap();
+        header.set_size(2);
+        header.set_cksum();
+        tar.append(&header, &b"ok"[..]).unwrap();
+        let archive = tar.into_inner().unwrap().finish().unwrap();
+
+        let entries = tar_gz_entries(archive.as_slice()).unwrap();
+        assert_eq!(
+            entries,
+            vec![TarEntry {
+                path: PathBuf::from(name),
+                size: 2,
+                kind: tar::EntryType::Regular,
+            }]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_archive_directory_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("blink");
+        fs::create_dir_all(dir.join("shared").join("led")).unwrap();
+        fs::write(dir.join("shared").join("led").join("led.c"), "ok").unwrap();
+        fs::create_dir_all(dir.join("app").join("components")).unwrap();
+        let led = dir.join("app").join("components").join("led");
+        std::os::unix::fs::symlink("../../shared/led", &led).unwrap();
+        // Followed, it would never end
+        std::os::unix::fs::symlink("..", dir.join("app").join("parent")).unwrap();
+
+        assert_eq!(
+            list_files(&dir, &[]).unwrap(),
+            vec![
+                Path::new("app").join("components").join("led"),
+                Path::new("app").join("parent"),
+                Path::new("shared").join("led").join("led.c"),
+            ]
+        );
+
+        let out = root.path().join("blink.tar.gz");
+        assert_eq!(archive_directory(&dir, "blink", &out, true).unwrap(), 3);
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(File::open(&out).unwrap()));
+        let links: Vec<_> = tar
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.header().entry_type() == tar::EntryType::Symlink)
+            .map(|entry| {
+                (
+                    entry.path().unwrap().into_owned(),
+                    entry.link_name().unwrap().unwrap().into_owned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                (
+                    PathBuf::from("blink/app/components/led"),
+                    PathBuf::from("../../shared/led")
+                ),
+                (PathBuf::from("blink/app/parent"), PathBuf::from("..")),
+            ]
+        );
+
+        let out = root.path().join("blink.zip");
+        archive_directory(&dir, "blink", &out, true).unwrap();
+        let mut zip = ZipArchive::new(File::open(&out).unwrap()).unwrap();
+        let entries = zip_entries(&mut zip).unwrap();
+        let led = entries
+            .iter()
+            .find(|entry| entry.path == Path::new("blink/app/components/led"))
+            .unwrap();
+        assert_eq!(led.kind, EntryKind::Symlink);
+        let mut target = String::new();
+        zip.by_name("blink/app/components/led")
+            .unwrap()
+            .read_to_string(&mut target)
+            .unwrap();
+        assert_eq!(target, "../../shared/led");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_zip_symlinked_destination() {
+        let root = tempfile::tempdir().unwrap();
+        let real = root.path().join("real");
+        let link = root.path().join("link");
+        fs::create_dir_all(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let mut zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[(
+            "main/main.c",
+            b"ok",
+        )])))
+        .unwrap();
+
+        extract_zip(
+            &link.to_string_lossy(),
+            &mut zip,
+            Path::new(""),
+            ExtractOptions::default(),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(real.join("main").join("main.c")).unwrap(),
+            "ok"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_zip_symlink_in_project() {
+        let root = tempfile::tempdir().unwrap();
+        let project = root.path().join("project");
+        let outside = root.path().join("outside");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        // As if replaced by a link while the previous entries were written
+        std::os::unix::fs::symlink(&outside, project.join("main")).unwrap();
+        std::os::unix::fs::symlink(outside.join("file"), project.join("README.md")).unwrap();
+        let mut zip = ZipArchive::new(io::Cursor::new(test_fixtures::zip_archive(&[
+            ("CMakeLists.txt", b"ok"),
+            ("main/main.c", b"escaped"),
+            ("main/sub/sub.c", b"escaped"),
+            ("README.md", b"escaped"),
+        ])))
+        .unwrap();
+        let options = ExtractOptions {
+            keep_going: true,
+            ..ExtractOptions::default()
+        };
+
+        let failed = extract_zip(
+            &project.to_string_lossy(),
+            &mut zip,
+            Path::new(""),
+            options,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        let names: Vec<&str> = failed.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["main/main.c", "main/sub/sub.c", "README.md"]);
+        assert!(failed[0].error.contains("outside the project"));
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+        assert!(project.join("CMakeLists.txt").is_file());
+    }
+}
@@ -0,0 +1,552 @@
+//! `batch`: generates many projects from one manifest, e.g. the identical
+//! projects of every student of a course, with a table of what was
+//! generated at the end
+
+use anyhow::{bail, Context};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::btree_map::{self, BTreeMap};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tempfile::NamedTempFile;
+
+use crate::config::{Profile, UserConfig};
+use crate::download;
+use crate::error::{CancellationToken, EspCreateError};
+use crate::generator::generate;
+use crate::naming::ProjectNames;
+use crate::options::ScaffoldOptions;
+use crate::platform;
+use crate::project;
+
+/// A project of the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchProject {
+    /// The project name or path, as given to the tool
+    pub name: String,
+    /// The answers of this project: its overrides in
+    /// [`BatchManifest::projects`], every answer once resolved
+    pub profile: Profile,
+}
+
+/// The manifest of a batch, a TOML file:
+///
+/// ```toml
+/// [base]
+/// extends = "course"
+/// starters = ["wifi"]
+///
+/// [[project]]
+/// name = "student-01"
+///
+/// [[project]]
+/// name = "student-02"
+/// target = "esp32c3"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchManifest {
+    /// The answers shared by every project, its `extends` names a profile
+    /// of the config file
+    pub base: Profile,
+    /// The projects, in the order they are generated
+    pub projects: Vec<BatchProject>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    #[serde(default)]
+    base: Profile,
+    #[serde(default)]
+    project: Vec<toml::Table>,
+}
+
+impl BatchManifest {
+    /// Parses a manifest from a TOML string
+    ///
+    /// # Arguments
+    /// * `content` - The TOML document to parse
+    ///
+    /// # Errors
+    /// If the TOML is malformed, has unknown fields, no project, or a
+    /// project without a valid name or with a name used twice
+    pub fn from_toml(content: &str) -> anyhow::Result<Self> {
+        let raw: RawManifest = toml::from_str(content).context("Invalid batch manifest")?;
+        if raw.project.is_empty() {
+            bail!("The batch manifest has no [[project]]");
+        }
+        let mut projects: Vec<BatchProject> = Vec::new();
+        let mut directories = Vec::new();
+        for (i, mut table) in raw.project.into_iter().enumerate() {
+            let name = match table.remove("name") {
+                Some(toml::Value::String(name)) => name,
+                _ => bail!("Project {} of the batch manifest has no name", i + 1),
+            };
+            // `a` and `./a` are the same project
+            let directory = ProjectNames::new(&name)?.directory;
+            let directory = platform::absolute_path(&directory).unwrap_or(directory);
+            if directories.contains(&directory) {
+                bail!("The project \"{}\" is listed twice", name);
+            }
+            directories.push(directory);
+            let profile: Profile = toml::Value::Table(table)
+                .try_into()
+                .context(format!("Invalid answers for the project \"{}\"", name))?;
+            if profile.extends.is_some() {
+                bail!(
+                    "The project \"{}\" cannot extend a profile, set \"extends\" in [base]",
+                    name
+                );
+            }
+            projects.push(BatchProject { name, profile });
+        }
+        Ok(BatchManifest {
+            base: raw.base,
+            projects,
+        })
+    }
+
+    /// Reads a manifest from a file
+    ///
+    /// # Errors
+    /// If the file cannot be read or is not a valid manifest
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path).context(format!(
+            "Cannot read the batch manifest \"{}\"",
+            path.display()
+        ))?;
+        Self::from_toml(&content).context(format!("In \"{}\"", path.display()))
+    }
+
+    /// The answers of every project: its overrides, then the base, then the
+    /// profile the base extends
+    ///
+    /// # Arguments
+    /// * `config` - The configuration with the profile extended by the base
+    ///
+    /// # Errors
+    /// If the profile extended by the base doesn't exist or cannot be resolved
+    pub fn resolve(&self, config: &UserConfig) -> anyhow::Result<Vec<BatchProject>> {
+        let base = match &self.base.extends {
+            Some(name) => self.base.clone().inherit(config.resolve_profile(name)?),
+            None => self.base.clone(),
+        };
+        Ok(self
+            .projects
+            .iter()
+            .map(|project| BatchProject {
+                name: project.name.clone(),
+                profile: project.profile.clone().inherit(base.clone()),
+            })
+            .collect())
+    }
+}
+
+impl BatchProject {
+    /// The options of the project, the answers the manifest doesn't set get
+    /// their defaults; the last choices are not used
+    ///
+    /// # Arguments
+    /// * `idf_path` - The ESP-IDF installation, usually `IDF_PATH`, a project
+    ///   cannot be created inside it
+    ///
+    /// # Errors
+    /// If the project directory cannot be used (not empty, a system
+    /// directory, inside another project) or the answers are invalid
+    pub fn options(self, idf_path: Option<&Path>) -> anyhow::Result<ScaffoldOptions> {
+        let dir = ProjectNames::new(&self.name)?.directory;
+        project::check_not_system_dir(&dir)?;
+        if project::directory_not_empty(&dir, &[]) {
+            bail!("Directory \"{}\" is not empty", self.name);
+        }
+        let absolute =
+            platform::absolute_path(&dir).context("Cannot read the current directory")?;
+        if let Some(location) = project::nested_location(&absolute, idf_path) {
+            bail!("\"{}\" is {}", absolute.display(), location);
+        }
+        let options = ScaffoldOptions::from_profile(self.name, self.profile, false);
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+/// What [`generate_batch`] reports while it runs
+#[derive(Debug)]
+pub enum BatchEvent<'a> {
+    /// A template is being downloaded, by URL
+    Downloading(&'a str),
+    /// A project is done, generated or not
+    Done {
+        result: &'a BatchResult,
+        /// Projects done so far, this one included
+        done: usize,
+        total: usize,
+    },
+}
+
+/// Generates the projects of a batch
+///
+/// Each template is downloaded once, then the projects are generated from
+/// it, `jobs` at the same time; a project that fails doesn't stop the others.
+///
+/// # Arguments
+/// * `planned` - The projects in the order of the manifest, by name, with
+///   their options or why they cannot be generated, see
+///   [`BatchProject::options`]
+/// * `jobs` - Projects generated at the same time, one if `None`
+/// * `token` - Token checked before each step, the projects not generated
+///   yet are then [`BatchStatus::Cancelled`]
+/// * `on_event` - Called as the batch runs, e.g. to print its progress
+///
+/// # Returns
+/// The results, in the order of the manifest
+///
+/// # Errors
+/// If the threads that generate the projects cannot be started
+pub fn generate_batch(
+    planned: Vec<(String, anyhow::Result<ScaffoldOptions>)>,
+    jobs: Option<NonZeroUsize>,
+    token: &CancellationToken,
+    on_event: impl Fn(BatchEvent) + Sync,
+) -> anyhow::Result<BatchReport> {
+    let mut templates: BTreeMap<String, anyhow::Result<NamedTempFile>> = BTreeMap::new();
+    for options in planned
+        .iter()
+        .filter_map(|(_, options)| options.as_ref().ok())
+    {
+        let url = options.template_url().into_owned();
+        if let btree_map::Entry::Vacant(entry) = templates.entry(url) {
+            on_event(BatchEvent::Downloading(entry.key()));
+            let file =
+                download::download_template_file(entry.key(), options.max_template_size(), token)
+                    .context(format!("Cannot download the template {}", entry.key()));
+            entry.insert(file);
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.map_or(1, NonZeroUsize::get))
+        .build()
+        .context("Cannot start the threads that generate the projects")?;
+    let total = planned.len();
+    let done = Mutex::new(0);
+    let results: Vec<BatchResult> = pool.install(|| {
+        planned
+            .into_par_iter()
+            .map(|(name, options)| {
+                let result = generate_project(name, options, &templates, token);
+                let mut done = done.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                *done += 1;
+                on_event(BatchEvent::Done {
+                    result: &result,
+                    done: *done,
+                    total,
+                });
+                result
+            })
+            .collect()
+    });
+    Ok(BatchReport::new(results))
+}
+
+/// Generates a project of a batch from its downloaded template
+///
+/// # Arguments
+/// * `name` - The project name, as in the manifest
+/// * `options` - The options of the project, see [`BatchProject::options`]
+/// * `templates` - The downloaded templates, by URL
+/// * `token` - Token checked before each step
+fn generate_project(
+    name: String,
+    options: anyhow::Result<ScaffoldOptions>,
+    templates: &BTreeMap<String, anyhow::Result<NamedTempFile>>,
+    token: &CancellationToken,
+) -> BatchResult {
+    let generated = options
+        .and_then(|mut options| {
+            match templates.get(options.template_url().as_ref()) {
+                Some(Ok(file)) => options.template_archive = Some(file.path().to_path_buf()),
+                Some(Err(error)) => bail!("{:#}", error),
+                None => bail!("The template {} was not downloaded", options.template_url()),
+            }
+            Ok(options)
+        })
+        .map_err(EspCreateError::Other)
+        .and_then(|options| generate(&options, token).map(|_| ()));
+    let (status, error) = match generated {
+        Ok(()) => (BatchStatus::Generated, None),
+        Err(EspCreateError::Cancelled) => (BatchStatus::Cancelled, None),
+        Err(EspCreateError::Other(error)) => (BatchStatus::Failed, Some(format!("{:#}", error))),
+    };
+    BatchResult {
+        directory: PathBuf::from(&name),
+        name,
+        status,
+        error,
+    }
+}
+
+/// What happened to a project of the batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Generated,
+    Failed,
+    /// Not generated because the batch was cancelled
+    Cancelled,
+}
+
+/// The result of a project of the batch, a row of the final table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchResult {
+    pub name: String,
+    pub directory: PathBuf,
+    pub status: BatchStatus,
+    /// Why the project failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The results of a batch, as printed by `batch --json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchReport {
+    pub generated: usize,
+    pub failed: usize,
+    /// In the order of the manifest
+    pub projects: Vec<BatchResult>,
+}
+
+impl BatchReport {
+    /// Counts the results
+    ///
+    /// # Arguments
+    /// * `projects` - The results, in the order of the manifest
+    pub fn new(projects: Vec<BatchResult>) -> Self {
+        let count = |status| {
+            projects
+                .iter()
+                .filter(|project| project.status == status)
+                .count()
+        };
+        BatchReport {
+            generated: count(BatchStatus::Generated),
+            failed: count(BatchStatus::Failed),
+            projects,
+        }
+    }
+
+    /// The table of the results, one project per line after the header
+    pub fn table(&self) -> Vec<String> {
+        let width = self
+            .projects
+            .iter()
+            .map(|project| project.name.chars().count())
+            .chain([4])
+            .max()
+            .unwrap_or(4);
+        let mut lines = vec![format!("{:<width$}  {:<9}  ERROR", "NAME", "STATUS")];
+        for project in &self.projects {
+            let status = match project.status {
+                BatchStatus::Generated => "generated",
+                BatchStatus::Failed => "failed",
+                BatchStatus::Cancelled => "cancelled",
+            };
+            let line = format!(
+                "{:<width$}  {:<9}  {}",
+                project.name,
+                status,
+                project.error.as_deref().unwrap_or("")
+            );
+            lines.push(line.trim_end().to_string());
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::ProgrammingLanguage;
+    use crate::test_fixtures;
+    use std::sync::atomic::Ordering;
+
+    /// A project of the batch in `dir`, generated from `url`
+    fn project(dir: &Path, name: &str, url: &str) -> BatchProject {
+        BatchProject {
+            name: dir.join(name).to_string_lossy().into_owned(),
+            profile: Profile {
+                template_url: Some(url.to_string()),
+                ..Profile::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = BatchManifest::from_toml(
+            r#"
+[base]
+extends = "course"
+starters = ["wifi"]
+
+[[project]]
+name = "student-01"
+
+[[project]]
+name = "student-02"
+language = "cpp17"
+"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.projects.len(), 2);
+        assert_eq!(manifest.base.extends.as_deref(), Some("course"));
+
+        let config = UserConfig::from_toml(
+            "[profile.course]\nlanguage = \"c\"\nuse_git = false\nstarters = [\"nvs\"]\n",
+        )
+        .unwrap();
+        let projects = manifest.resolve(&config).unwrap();
+        assert_eq!(projects[0].name, "student-01");
+        assert_eq!(projects[0].profile.language, Some(ProgrammingLanguage::C));
+        assert_eq!(projects[0].profile.use_git, Some(false));
+        assert_eq!(projects[0].profile.starters, Some(vec!["wifi".to_string()]));
+        assert_eq!(
+            projects[1].profile.language,
+            Some(ProgrammingLanguage::Cpp17)
+        );
+        assert!(manifest.resolve(&UserConfig::default()).is_err());
+
+        for invalid in [
+            "[base]\nlanguage = \"c\"\n",
+            "[[project]]\nlanguage = \"c\"\n",
+            "[[project]]\nname = \"a\"\n[[project]]\nname = \"a\"\n",
+            "[[project]]\nname = \"a\"\nunknown = 1\n",
+            "[[project]]\nname = \"a\"\nextends = \"course\"\n",
+            "[[project]]\nname = \"a/..\"\n",
+            "jobs = 2\n[[project]]\nname = \"a\"\n",
+        ] {
+            assert!(BatchManifest::from_toml(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_manifest_duplicate_directories() {
+        let duplicate = "[[project]]\nname = \"a\"\n[[project]]\nname = \"./a\"\n";
+        assert!(BatchManifest::from_toml(duplicate).is_err());
+        // Only the last component is the same
+        let distinct = "[[project]]\nname = \"a\"\n[[project]]\nname = \"b/a\"\n";
+        assert_eq!(
+            BatchManifest::from_toml(distinct).unwrap().projects.len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_generate_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, requests) = test_fixtures::serve_counted(test_fixtures::template_zip());
+        // Not a zip archive
+        let (broken, _) = test_fixtures::serve_counted(b"not a zip".to_vec());
+        // Not empty, the project cannot be generated there
+        fs::create_dir_all(dir.path().join("taken")).unwrap();
+        fs::write(dir.path().join("taken").join("notes.txt"), "").unwrap();
+
+        let planned = vec![
+            project(dir.path(), "student-01", &url),
+            project(dir.path(), "taken", &url),
+            project(dir.path(), "student-02", &broken),
+            project(dir.path(), "student-03", &url),
+        ]
+        .into_iter()
+        .map(|project| (project.name.clone(), project.options(None)))
+        .collect();
+        let events = Mutex::new(Vec::new());
+        let report = generate_batch(
+            planned,
+            NonZeroUsize::new(2),
+            &CancellationToken::new(),
+            |event| {
+                let event = match event {
+                    BatchEvent::Downloading(url) => url.to_string(),
+                    BatchEvent::Done { done, total, .. } => format!("{}/{}", done, total),
+                };
+                events.lock().unwrap().push(event);
+            },
+        )
+        .unwrap();
+
+        let statuses: Vec<BatchStatus> = report
+            .projects
+            .iter()
+            .map(|project| project.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                BatchStatus::Generated,
+                BatchStatus::Failed,
+                BatchStatus::Failed,
+                BatchStatus::Generated
+            ]
+        );
+        assert_eq!((report.generated, report.failed), (2, 2));
+        assert!(dir
+            .path()
+            .join("student-01")
+            .join("CMakeLists.txt")
+            .is_file());
+        assert!(dir
+            .path()
+            .join("student-03")
+            .join("CMakeLists.txt")
+            .is_file());
+        assert!(report.projects[1]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("not empty"));
+        // Downloaded once for both projects
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let events = events.into_inner().unwrap();
+        assert_eq!(events[..2], [url.clone(), broken.clone()]);
+        let mut done = events[2..].to_vec();
+        done.sort();
+        assert_eq!(done, ["1/4", "2/4", "3/4", "4/4"]);
+    }
+
+    #[test]
+    fn test_report() {
+        let result = |name: &str, status, error: Option<&str>| BatchResult {
+            name: name.to_string(),
+            directory: PathBuf::from(name),
+            status,
+            error: error.map(String::from),
+        };
+        let report = BatchReport::new(vec![
+            result("student-01", BatchStatus::Generated, None),
+            result(
+                "student-02",
+                BatchStatus::Failed,
+                Some("Directory not empty"),
+            ),
+            result("s3", BatchStatus::Cancelled, None),
+        ]);
+        assert_eq!((report.generated, report.failed), (1, 1));
+        assert_eq!(
+            report.table(),
+            [
+                "NAME        STATUS     ERROR",
+                "student-01  generated",
+                "student-02  failed     Directory not empty",
+                "s3          cancelled",
+            ]
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["projects"][1]["status"], "failed");
+        assert!(json["projects"][0].get("error").is_none());
+    }
+}
@@ -0,0 +1,256 @@
+//! Development boards: the chip, flash, PSRAM, pins and console of common
+//! boards, so a project starts configured for the board it runs on
+
+use anyhow::anyhow;
+
+use crate::idf_version::IdfVersion;
+
+/// Chips that can be selected as target when no board is picked
+pub const TARGETS: &[&str] = &[
+    "esp32", "esp32s2", "esp32s3", "esp32c3", "esp32c6", "esp32h2",
+];
+
+/// Chips with a RISC-V core, the others are Xtensa, see [`target_compiler`]
+const RISCV_TARGETS: &[&str] = &["esp32c2", "esp32c3", "esp32c6", "esp32h2", "esp32p4"];
+
+/// The GCC of the ESP-IDF tools that builds the apps of a chip: one per
+/// Xtensa chip, one for every RISC-V chip
+pub fn target_compiler(target: &str) -> &'static str {
+    match target {
+        "esp32s2" => "xtensa-esp32s2-elf-gcc",
+        "esp32s3" => "xtensa-esp32s3-elf-gcc",
+        _ if RISCV_TARGETS.contains(&target) => "riscv32-esp-elf-gcc",
+        _ => "xtensa-esp32-elf-gcc",
+    }
+}
+
+/// Oldest ESP-IDF that supports each chip, see [`target_min_idf_version`]
+const TARGET_MIN_IDF_VERSIONS: &[(&str, u32, u32)] = &[
+    ("esp32s2", 4, 2),
+    ("esp32c3", 4, 3),
+    ("esp32s3", 4, 4),
+    ("esp32c6", 5, 1),
+    ("esp32h2", 5, 1),
+];
+
+/// The oldest ESP-IDF that supports a chip
+///
+/// # Returns
+/// The version, `None` if every version supports it (esp32) or the chip is unknown
+pub fn target_min_idf_version(target: &str) -> Option<IdfVersion> {
+    TARGET_MIN_IDF_VERSIONS
+        .iter()
+        .find(|(name, _, _)| *name == target)
+        .map(|&(_, major, minor)| IdfVersion::new(major, minor))
+}
+
+/// Where the console (logs, `printf`) is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    /// UART0, through the USB-serial chip of the board
+    Uart,
+    /// The USB-Serial/JTAG peripheral of the chip
+    UsbSerialJtag,
+}
+
+/// A development board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    /// Identifier used on the command line
+    pub id: &'static str,
+    /// Name shown in the prompts
+    pub name: &'static str,
+    /// The chip, as in `idf.py set-target`
+    pub target: &'static str,
+    /// Flash size in MB
+    pub flash_size_mb: u32,
+    /// Whether the board has PSRAM
+    pub psram: bool,
+    /// GPIO of the addressable RGB LED, `None` if the board has none
+    pub led_gpio: Option<u8>,
+    /// GPIO of the user (or BOOT) button, `None` if the board has none
+    pub button_gpio: Option<u8>,
+    pub console: Console,
+}
+
+/// Every known board
+pub const BOARDS: &[Board] = &[
+    Board {
+        id: "esp32-devkitc",
+        name: "ESP32-DevKitC",
+        target: "esp32",
+        flash_size_mb: 4,
+        psram: false,
+        led_gpio: None,
+        button_gpio: Some(0),
+        console: Console::Uart,
+    },
+    Board {
+        id: "esp32-s3-devkitc-1",
+        name: "ESP32-S3-DevKitC-1 (v1.1, N8)",
+        target: "esp32s3",
+        flash_size_mb: 8,
+        psram: false,
+        led_gpio: Some(38),
+        button_gpio: Some(0),
+        console: Console::Uart,
+    },
+    Board {
+        id: "esp32-c3-devkitm-1",
+        name: "ESP32-C3-DevKitM-1",
+        target: "esp32c3",
+        flash_size_mb: 4,
+        psram: false,
+        led_gpio: Some(8),
+        button_gpio: Some(9),
+        console: Console::Uart,
+    },
+    Board {
+        id: "esp32-cam",
+        name: "ESP32-CAM (AI-Thinker)",
+        target: "esp32",
+        flash_size_mb: 4,
+        psram: true,
+        led_gpio: None,
+        button_gpio: None,
+        console: Console::Uart,
+    },
+    Board {
+        id: "wt32-eth01",
+        name: "WT32-ETH01",
+        target: "esp32",
+        flash_size_mb: 4,
+        psram: false,
+        led_gpio: None,
+        button_gpio: None,
+        console: Console::Uart,
+    },
+    Board {
+        id: "m5stack-core",
+        name: "M5Stack Core (Basic)",
+        target: "esp32",
+        flash_size_mb: 16,
+        psram: false,
+        led_gpio: None,
+        button_gpio: Some(39),
+        console: Console::Uart,
+    },
+];
+
+/// Finds a board by id
+///
+/// # Errors
+/// If no board has this id, the message lists the available ones
+pub fn find(id: &str) -> anyhow::Result<&'static Board> {
+    BOARDS.iter().find(|board| board.id == id).ok_or_else(|| {
+        let ids: Vec<&str> = BOARDS.iter().map(|board| board.id).collect();
+        anyhow!(
+            "Unknown board \"{}\", available boards: {}",
+            id,
+            ids.join(", ")
+        )
+    })
+}
+
+impl Board {
+    /// The `sdkconfig.defaults` entries for the board: target, flash size,
+    /// PSRAM, console, and the pins of the selected starters
+    ///
+    /// # Arguments
+    /// * `starters` - The ids of the starters added to the project
+    pub fn sdkconfig(&self, starters: &[String]) -> Vec<(String, String)> {
+        let mut entries = vec![
+            (
+                "CONFIG_IDF_TARGET".to_string(),
+                format!("\"{}\"", self.target),
+            ),
+            (
+                format!("CONFIG_ESPTOOLPY_FLASHSIZE_{}MB", self.flash_size_mb),
+                "y".into(),
+            ),
+        ];
+        if self.psram {
+            entries.push(("CONFIG_SPIRAM".into(), "y".into()));
+        }
+        if self.console == Console::UsbSerialJtag {
+            entries.push(("CONFIG_ESP_CONSOLE_USB_SERIAL_JTAG".into(), "y".into()));
+        }
+        if let Some(gpio) = self.led_gpio.filter(|_| uses_led(starters)) {
+            entries.push(("CONFIG_STARTER_LED_GPIO".into(), gpio.to_string()));
+        }
+        entries
+    }
+
+    /// Pins the selected starters need but the board doesn't have
+    ///
+    /// # Arguments
+    /// * `starters` - The ids of the starters added to the project
+    ///
+    /// # Returns
+    /// What to set by hand, empty if the board has every pin
+    pub fn manual_steps(&self, starters: &[String]) -> Vec<String> {
+        if self.led_gpio.is_none() && uses_led(starters) {
+            vec![format!(
+                "The {} has no addressable LED, connect one and set its GPIO in `idf.py menuconfig` (LED starter)",
+                self.name
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn uses_led(starters: &[String]) -> bool {
+    starters.iter().any(|id| id == "led")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("esp32-c3-devkitm-1").unwrap().target, "esp32c3");
+        assert!(find("arduino-uno")
+            .unwrap_err()
+            .to_string()
+            .contains("m5stack-core"));
+        for board in BOARDS {
+            assert!(TARGETS.contains(&board.target), "{}", board.id);
+        }
+    }
+
+    #[test]
+    fn test_target_compiler() {
+        assert_eq!(target_compiler("esp32"), "xtensa-esp32-elf-gcc");
+        assert_eq!(target_compiler("esp32s3"), "xtensa-esp32s3-elf-gcc");
+        for target in ["esp32c3", "esp32c6", "esp32h2"] {
+            assert_eq!(target_compiler(target), "riscv32-esp-elf-gcc");
+        }
+    }
+
+    #[test]
+    fn test_sdkconfig() {
+        let led = vec!["led".to_string()];
+        let c3 = find("esp32-c3-devkitm-1").unwrap();
+        assert_eq!(
+            c3.sdkconfig(&led),
+            vec![
+                ("CONFIG_IDF_TARGET".to_string(), "\"esp32c3\"".to_string()),
+                (
+                    "CONFIG_ESPTOOLPY_FLASHSIZE_4MB".to_string(),
+                    "y".to_string()
+                ),
+                ("CONFIG_STARTER_LED_GPIO".to_string(), "8".to_string()),
+            ]
+        );
+        assert!(c3.manual_steps(&led).is_empty());
+        assert_eq!(c3.sdkconfig(&[]).len(), 2);
+
+        let cam = find("esp32-cam").unwrap();
+        assert!(cam
+            .sdkconfig(&led)
+            .contains(&("CONFIG_SPIRAM".to_string(), "y".to_string())));
+        assert_eq!(cam.manual_steps(&led).len(), 1);
+    }
+}
@@ -0,0 +1,639 @@
+use anyhow::{bail, Context};
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::boards;
+use crate::export_env;
+use crate::platform;
+
+/// Result of probing one of the tools needed to build an ESP-IDF project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildDepCheck {
+    /// Name of the tool shown to the user
+    pub name: &'static str,
+    /// First line printed by the version probe, `None` if the tool is missing
+    pub version: Option<String>,
+    /// How to install the tool when it's missing
+    pub hint: &'static str,
+}
+
+impl BuildDepCheck {
+    /// Checks if the tool was found
+    pub fn found(&self) -> bool {
+        self.version.is_some()
+    }
+}
+
+/// Hint shown when the IDF itself is missing
+pub const IDF_HINT: &str = "install ESP-IDF and run its export script \
+    (https://docs.espressif.com/projects/esp-idf/en/latest/esp32/get-started/)";
+
+/// Probes every tool needed to build the project
+///
+/// # Arguments
+/// * `target` - The chip the project is built for, the compiler depends on
+///   it; [`crate::export_env::DEFAULT_TARGET`] when `None`
+///
+/// # Returns
+/// The status of `idf.py`, `cmake`, `ninja` and the compiler of the chip, in
+/// that order
+pub fn check_build_deps(target: Option<&str>) -> Vec<BuildDepCheck> {
+    let compiler = boards::target_compiler(target.unwrap_or(export_env::DEFAULT_TARGET));
+    vec![
+        BuildDepCheck {
+            name: "idf.py",
+            version: probe_idf(),
+            hint: IDF_HINT,
+        },
+        BuildDepCheck {
+            name: "cmake",
+            version: probe(platform::command("cmake").arg("--version")),
+            hint:
+                "run the ESP-IDF install script, or install CMake from https://cmake.org/download/",
+        },
+        BuildDepCheck {
+            name: "ninja",
+            version: probe(platform::command("ninja").arg("--version")),
+            hint: "run the ESP-IDF install script, or install Ninja from https://ninja-build.org/",
+        },
+        BuildDepCheck {
+            name: compiler,
+            version: probe(platform::command(compiler).arg("--version")),
+            hint: "run the ESP-IDF install script and source its export script",
+        },
+    ]
+}
+
+/// Number of output lines shown when the configure step fails, the CMake
+/// error is at the end
+const CONFIGURE_ERROR_LINES: usize = 30;
+
+/// How `idf.py` can be run
+pub enum IdfPy {
+    /// `idf.py` is on the `PATH`
+    OnPath,
+    /// `idf.py` inside `IDF_PATH`, run through python
+    Script {
+        python: &'static str,
+        script: PathBuf,
+    },
+    /// `idf.py` of an IDF whose export script was sourced for the child
+    /// process only, with the resulting environment
+    Exported {
+        script: PathBuf,
+        variables: Vec<(String, String)>,
+    },
+}
+
+impl IdfPy {
+    /// A command that runs `idf.py`, arguments are added by the caller
+    pub fn command(&self) -> Command {
+        match self {
+            IdfPy::OnPath => platform::command("idf.py"),
+            IdfPy::Script { python, script } => {
+                let mut command = platform::command(python);
+                command.arg(script);
+                command
+            }
+            IdfPy::Exported { script, variables } => {
+                // Resolved with the exported PATH, so the python environment
+                // of the IDF runs it
+                #[cfg(windows)]
+                let mut command = {
+                    let mut command = Command::new("python");
+                    command.arg(script);
+                    command
+                };
+                #[cfg(not(windows))]
+                let mut command = Command::new(script);
+                command.env_clear().envs(variables.iter().cloned());
+                command
+            }
+        }
+    }
+}
+
+/// Whether the ESP-IDF environment is already exported in the current
+/// environment, see [`IdfEnv::detect`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdfEnv {
+    /// `IDF_PATH`, `IDF_PYTHON_ENV_PATH` and `idf.py` on the `PATH` are all
+    /// set, `idf.py` runs as is
+    Ready {
+        idf_path: PathBuf,
+        python_env: PathBuf,
+        idf_py: PathBuf,
+    },
+    /// An IDF is installed but the environment lacks some of its variables,
+    /// its export script is sourced for the commands
+    NeedsExport {
+        idf_path: PathBuf,
+        /// What the environment lacks, e.g. `IDF_PYTHON_ENV_PATH`
+        missing: Vec<&'static str>,
+    },
+    /// No IDF in `IDF_PATH`, or in `~/esp/esp-idf` when it isn't set
+    NotFound,
+}
+
+impl IdfEnv {
+    /// Detects the ESP-IDF environment of the current process
+    ///
+    /// Sourcing the export script again is slow and can reorder the `PATH`,
+    /// so it's only done when the environment isn't already exported.
+    pub fn detect() -> Self {
+        Self::detect_in(|name| env::var_os(name), dirs::home_dir().as_deref())
+    }
+
+    /// Detects the ESP-IDF environment from the given variables
+    ///
+    /// # Arguments
+    /// * `var` - Reads a variable of the environment
+    /// * `home` - The home directory, for the default location of the IDF
+    fn detect_in(var: impl Fn(&str) -> Option<OsString>, home: Option<&Path>) -> Self {
+        let is_idf = |path: &PathBuf| path.join("tools").join("idf.py").is_file();
+        let set = |name: &str| {
+            var(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+
+        let idf_path = set("IDF_PATH");
+        let valid_idf_path = idf_path.clone().filter(is_idf);
+        let python_env = set("IDF_PYTHON_ENV_PATH").filter(|path| path.is_dir());
+        let idf_py = var("PATH").and_then(|path| platform::find_executable_in("idf.py", &path));
+        if let (Some(idf_path), Some(python_env), Some(idf_py)) =
+            (valid_idf_path.clone(), python_env.clone(), idf_py.clone())
+        {
+            return IdfEnv::Ready {
+                idf_path,
+                python_env,
+                idf_py,
+            };
+        }
+
+        let mut missing = Vec::new();
+        if valid_idf_path.is_none() {
+            missing.push("IDF_PATH");
+        }
+        if python_env.is_none() {
+            missing.push("IDF_PYTHON_ENV_PATH");
+        }
+        if idf_py.is_none() {
+            missing.push("idf.py on the PATH");
+        }
+        // A wrong IDF_PATH isn't replaced by the default location
+        let idf_path = match idf_path {
+            Some(idf_path) => Some(idf_path),
+            None => home.map(|home| home.join("esp").join("esp-idf")),
+        };
+        match idf_path.filter(is_idf) {
+            Some(idf_path) => IdfEnv::NeedsExport { idf_path, missing },
+            None => IdfEnv::NotFound,
+        }
+    }
+}
+
+impl fmt::Display for IdfEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdfEnv::Ready {
+                idf_path,
+                python_env,
+                ..
+            } => write!(
+                f,
+                "ESP-IDF environment exported: {} (python environment {})",
+                idf_path.display(),
+                python_env.display()
+            ),
+            IdfEnv::NeedsExport { idf_path, missing } => write!(
+                f,
+                "ESP-IDF found in {}, its export script is sourced for idf.py (not set: {})",
+                idf_path.display(),
+                missing.join(", ")
+            ),
+            IdfEnv::NotFound => write!(f, "ESP-IDF not found, {}", IDF_HINT),
+        }
+    }
+}
+
+/// Finds `idf.py`, either on the `PATH` or inside `IDF_PATH`
+///
+/// On Windows `idf.py` isn't directly executable, so it's run through python.
+///
+/// # Returns
+/// How to run `idf.py` and its version, or `None` if it's missing
+fn find_idf_py() -> Option<(IdfPy, String)> {
+    if let Some(version) = probe(IdfPy::OnPath.command().arg("--version")) {
+        return Some((IdfPy::OnPath, version));
+    }
+
+    let script = PathBuf::from(env::var_os("IDF_PATH")?)
+        .join("tools")
+        .join("idf.py");
+    if !script.is_file() {
+        return None;
+    }
+    ["python3", "python"].iter().find_map(|python| {
+        let idf = IdfPy::Script {
+            python,
+            script: script.clone(),
+        };
+        let version = probe(idf.command().arg("--version"))?;
+        Some((idf, version))
+    })
+}
+
+/// Probes `idf.py`, see [`find_idf_py`]
+///
+/// # Returns
+/// What `idf.py --version` printed, `None` if no ESP-IDF is found
+pub fn probe_idf() -> Option<String> {
+    find_idf_py().map(|(_, version)| version)
+}
+
+/// Finds how to run `idf.py` with a working ESP-IDF environment, shared by
+/// everything that spawns it
+///
+/// When the environment isn't already exported (see [`IdfEnv::detect`]),
+/// the export script of the IDF (`IDF_PATH`, or the default `~/esp/esp-idf`)
+/// is sourced and its environment is given to the commands, the current shell
+/// is left untouched.
+///
+/// # Errors
+/// If no ESP-IDF installation is found or its export script fails
+pub fn idf_py() -> anyhow::Result<IdfPy> {
+    match IdfEnv::detect() {
+        IdfEnv::Ready { .. } => Ok(IdfPy::OnPath),
+        IdfEnv::NeedsExport { idf_path, .. } => {
+            let variables = exported_env(&idf_path)?;
+            Ok(IdfPy::Exported {
+                script: idf_path.join("tools").join("idf.py"),
+                variables,
+            })
+        }
+        IdfEnv::NotFound => bail!("idf.py not found, {}", IDF_HINT),
+    }
+}
+
+/// Sources the export script of the IDF in a shell and reads the resulting
+/// environment
+///
+/// # Arguments
+/// * `idf_path` - The root of the IDF
+///
+/// # Errors
+/// If the shell cannot run or the export script fails
+fn exported_env(idf_path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    #[cfg(windows)]
+    let output = Command::new("cmd")
+        .arg("/C")
+        .arg(format!(
+            "call \"{}\" >nul 2>&1 && set",
+            idf_path.join("export.bat").display()
+        ))
+        .output();
+    #[cfg(not(windows))]
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(". \"$1/export.sh\" >/dev/null 2>&1 && env")
+        .arg("sh")
+        .arg(idf_path)
+        .output();
+
+    let output = output.context("Cannot run a shell to source the ESP-IDF export script")?;
+    if !output.status.success() {
+        bail!(
+            "the ESP-IDF export script in \"{}\" failed, run the install script of the IDF first",
+            idf_path.display()
+        );
+    }
+    Ok(parse_env(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the `NAME=value` lines printed by `env` or `set`
+///
+/// Lines that don't start with a variable name are skipped, e.g. the
+/// continuation lines of multi-line values.
+fn parse_env(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            let valid = !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '(' || c == ')');
+            if !valid {
+                return None;
+            }
+            Some((name.to_string(), value.trim_end_matches('\r').to_string()))
+        })
+        .collect()
+}
+
+/// Runs the CMake configure step of the project (`idf.py reconfigure`),
+/// which catches most errors in the CMake files without a full build
+///
+/// # Arguments
+/// * `project_dir` - The root of the project
+///
+/// # Errors
+/// If `idf.py` is missing or the configure step fails, with the end of its output
+pub fn verify_configure(project_dir: &Path) -> anyhow::Result<()> {
+    let idf = idf_py()?;
+    run_configure(idf.command().arg("-C").arg(project_dir).arg("reconfigure"))
+}
+
+/// Lock file of the managed components, written by the component manager
+pub const DEPENDENCIES_LOCK_FILE: &str = "dependencies.lock";
+
+/// Directory the component manager downloads the managed components to
+pub const MANAGED_COMPONENTS_DIR: &str = "managed_components";
+
+/// What [`fetch_components`] did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedComponents {
+    /// The components in `managed_components/`, sorted
+    pub components: Vec<String>,
+    /// Whether `dependencies.lock` was written
+    pub lock_file: bool,
+}
+
+/// Resolves the dependencies of the `idf_component.yml` files of the project
+/// and downloads them to `managed_components/`, writing `dependencies.lock`
+///
+/// The component manager runs as part of `idf.py reconfigure`, which needs
+/// the target and the IDF version to resolve the dependencies; it inherits
+/// the proxy variables of the environment.
+///
+/// # Arguments
+/// * `idf` - The `idf.py` to run, see [`idf_py`]
+/// * `project_dir` - The root of the project
+///
+/// # Errors
+/// If the configure step fails, with the end of its output
+pub fn fetch_components(idf: &IdfPy, project_dir: &Path) -> anyhow::Result<FetchedComponents> {
+    run_fetch(
+        idf.command().arg("-C").arg(project_dir).arg("reconfigure"),
+        project_dir,
+    )
+}
+
+/// Runs the configure command and lists what it fetched, see [`fetch_components`]
+fn run_fetch(command: &mut Command, project_dir: &Path) -> anyhow::Result<FetchedComponents> {
+    run_configure(command)?;
+    let mut components: Vec<String> = fs::read_dir(project_dir.join(MANAGED_COMPONENTS_DIR))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    components.sort_unstable();
+    Ok(FetchedComponents {
+        components,
+        lock_file: project_dir.join(DEPENDENCIES_LOCK_FILE).is_file(),
+    })
+}
+
+/// Runs the configure command, see [`verify_configure`]
+fn run_configure(command: &mut Command) -> anyhow::Result<()> {
+    let output = command.output().context("Cannot run idf.py")?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stdout.lines().chain(stderr.lines()).collect();
+    let tail = &lines[lines.len().saturating_sub(CONFIGURE_ERROR_LINES)..];
+    bail!("idf.py reconfigure failed:\n{}", tail.join("\n"))
+}
+
+/// Runs a version probe
+///
+/// # Arguments
+/// * `command` - The command that prints the version of the tool
+///
+/// # Returns
+/// The first non-empty line of the output, or `None` if the command cannot be
+/// run or fails
+pub fn probe(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("unknown version");
+    Some(line.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env() {
+        assert_eq!(
+            parse_env("IDF_PATH=/opt/esp-idf\nPATH=/a:/b\n  continued line\nCommonProgramFiles(x86)=C:\\x\r\n"),
+            vec![
+                ("IDF_PATH".to_string(), "/opt/esp-idf".to_string()),
+                ("PATH".to_string(), "/a:/b".to_string()),
+                ("CommonProgramFiles(x86)".to_string(), "C:\\x".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exported_env() {
+        use std::fs;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("export.sh"),
+            "echo exporting\nexport ESP_TEST_EXPORTED=ok\n",
+        )
+        .unwrap();
+        let variables = exported_env(dir.path()).unwrap();
+        assert!(variables.contains(&("ESP_TEST_EXPORTED".to_string(), "ok".to_string())));
+
+        fs::write(dir.path().join("export.sh"), "false\n").unwrap();
+        assert!(exported_env(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_idf_env_detect() {
+        use std::collections::HashMap;
+        use std::fs;
+
+        let dir = tempfile::tempdir().unwrap();
+        let idf_path = dir.path().join("esp").join("esp-idf");
+        fs::create_dir_all(idf_path.join("tools")).unwrap();
+        fs::write(idf_path.join("tools").join("idf.py"), "").unwrap();
+        let python_env = dir.path().join("python_env");
+        fs::create_dir_all(&python_env).unwrap();
+        #[cfg(windows)]
+        let idf_py = idf_path.join("tools").join("idf.py.exe");
+        #[cfg(not(windows))]
+        let idf_py = idf_path.join("tools").join("idf.py");
+        fs::write(&idf_py, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&idf_py, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let empty_home = dir.path().join("home");
+
+        let detect = |variables: &[(&str, &Path)], home: &Path| {
+            let variables: HashMap<String, OsString> = variables
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.as_os_str().to_owned()))
+                .collect();
+            IdfEnv::detect_in(|name| variables.get(name).cloned(), Some(home))
+        };
+        let tools = idf_path.join("tools");
+
+        assert_eq!(
+            detect(
+                &[
+                    ("IDF_PATH", &idf_path),
+                    ("IDF_PYTHON_ENV_PATH", &python_env),
+                    ("PATH", &tools),
+                ],
+                &empty_home
+            ),
+            IdfEnv::Ready {
+                idf_path: idf_path.clone(),
+                python_env: python_env.clone(),
+                idf_py,
+            }
+        );
+        // Only IDF_PATH, e.g. set in a shell profile without sourcing export.sh
+        assert_eq!(
+            detect(&[("IDF_PATH", &idf_path)], &empty_home),
+            IdfEnv::NeedsExport {
+                idf_path: idf_path.clone(),
+                missing: vec!["IDF_PYTHON_ENV_PATH", "idf.py on the PATH"],
+            }
+        );
+        // Nothing set, the IDF is in the default location
+        assert_eq!(
+            detect(&[("PATH", &tools)], dir.path()),
+            IdfEnv::NeedsExport {
+                idf_path: idf_path.clone(),
+                missing: vec!["IDF_PATH", "IDF_PYTHON_ENV_PATH"],
+            }
+        );
+        assert_eq!(detect(&[], &empty_home), IdfEnv::NotFound);
+        // A wrong IDF_PATH isn't replaced by the default location
+        assert_eq!(
+            detect(&[("IDF_PATH", &empty_home)], dir.path()),
+            IdfEnv::NotFound
+        );
+    }
+
+    #[test]
+    fn test_probe_missing_tool() {
+        assert_eq!(
+            probe(&mut Command::new("esp-create-project-missing-tool")),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_probe_version_and_failure() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("tool");
+        fs::write(
+            &tool,
+            "#!/bin/sh\nif [ \"$1\" = --version ]; then echo; echo 'tool 1.2.3'; else exit 1; fi\n",
+        )
+        .unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(
+            probe(Command::new(&tool).arg("--version")),
+            Some("tool 1.2.3".into())
+        );
+        assert_eq!(probe(Command::new(&tool).arg("--bad")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_configure() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("idf.py");
+        fs::write(
+            &tool,
+            "#!/bin/sh\necho '-- Configuring'\nif [ \"$1\" = ok ]; then exit 0; fi\necho 'CMake Error at CMakeLists.txt:8' >&2\nexit 2\n",
+        )
+        .unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(run_configure(Command::new(&tool).arg("ok")).is_ok());
+        let error = run_configure(&mut Command::new(&tool)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "idf.py reconfigure failed:\n-- Configuring\nCMake Error at CMakeLists.txt:8"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_fetch() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("idf.py");
+        // Writes what the component manager writes, and the proxy it got
+        fs::write(
+            &tool,
+            "#!/bin/sh\nmkdir -p managed_components/espressif__led_strip managed_components/espressif__cjson\necho \"$HTTPS_PROXY\" > dependencies.lock\n",
+        )
+        .unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(run_fetch(&mut Command::new("/nonexistent/idf.py"), dir.path()).is_err());
+        let fetched = run_fetch(
+            Command::new(&tool)
+                .current_dir(dir.path())
+                .env("HTTPS_PROXY", "http://proxy:3128"),
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(
+            fetched,
+            FetchedComponents {
+                components: vec!["espressif__cjson".into(), "espressif__led_strip".into()],
+                lock_file: true,
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join(DEPENDENCIES_LOCK_FILE)).unwrap(),
+            "http://proxy:3128\n"
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! `--template-manifest`: a catalog of named templates curated by an
+//! organization, picked with `--template-name` or from a list
+//!
+//! ```toml
+//! [[template]]
+//! name = "blink-starter"
+//! description = "LED blink with the team's logging setup"
+//! url = "https://github.com/acme/blink-starter/archive/{ref}.zip"
+//! ref = "v1.2.0"
+//! strip_prefix = "blink-starter-1.2.0"
+//! ```
+//!
+//! Not to be confused with [`crate::templatize::TemplateManifest`], the
+//! manifest stored inside a single template.
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::http;
+use crate::platform;
+use crate::template_ref;
+
+/// Placeholder of the URL of a template replaced by its ref
+pub const REF_PLACEHOLDER: &str = "{ref}";
+
+/// A template of a [`TemplateManifest`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestTemplate {
+    /// Name given to `--template-name`
+    pub name: String,
+    /// Shown next to the name in the list
+    #[serde(default)]
+    pub description: Option<String>,
+    /// URL or path of the template zip; a relative path is relative to the
+    /// manifest
+    pub url: String,
+    /// Branch, tag or commit replacing [`REF_PLACEHOLDER`] in the URL
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Directory of the archive the template files are in, detected when not
+    /// set, see [`crate::archive::detect_prefix`]
+    #[serde(default)]
+    pub strip_prefix: Option<String>,
+}
+
+impl ManifestTemplate {
+    /// The URL of the template zip, with its ref
+    pub fn url(&self) -> String {
+        match &self.git_ref {
+            Some(git_ref) => self.url.replace(REF_PLACEHOLDER, git_ref),
+            None => self.url.clone(),
+        }
+    }
+
+    /// Checks that the template can be offered: a usable name, a URL with
+    /// its ref and a prefix inside the archive
+    ///
+    /// # Errors
+    /// If a field is invalid, or a local template doesn't exist
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            bail!(
+                "Invalid template name \"{}\", expected letters, digits, '-', '_' or '.'",
+                self.name
+            );
+        }
+        match (self.url.contains(REF_PLACEHOLDER), &self.git_ref) {
+            (true, Some(git_ref)) => template_ref::validate_ref(git_ref)?,
+            (true, None) => bail!(
+                "The URL has a {} but the template has no ref",
+                REF_PLACEHOLDER
+            ),
+            (false, Some(_)) => bail!(
+                "The template has a ref but its URL has no {} to put it in",
+                REF_PLACEHOLDER
+            ),
+            (false, None) => {}
+        }
+        let url = self.url();
+        let path = match url.split_once("://") {
+            Some(("http", _)) | Some(("https", _)) => None,
+            Some(("file", path)) => Some(path),
+            Some((scheme, _)) => bail!("Unsupported URL scheme \"{}\" in \"{}\"", scheme, url),
+            None => Some(url.as_str()),
+        };
+        if let Some(path) = path.filter(|path| !Path::new(path).is_file()) {
+            bail!("The template \"{}\" doesn't exist", path);
+        }
+        if let Some(prefix) = &self.strip_prefix {
+            if platform::archive_entry_path(prefix).is_none() {
+                bail!(
+                    "Invalid strip_prefix \"{}\", expected a relative path inside the archive",
+                    prefix
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A catalog of named templates, read from TOML or JSON
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateManifest {
+    /// The templates, in the order they are listed
+    #[serde(default, rename = "template")]
+    pub templates: Vec<ManifestTemplate>,
+}
+
+/// A template left out of the manifest, and why
+#[derive(Debug)]
+pub struct InvalidTemplate {
+    pub name: String,
+    pub error: anyhow::Error,
+}
+
+impl TemplateManifest {
+    /// Parses a manifest, as JSON if `source` ends with `.json` and as TOML
+    /// otherwise
+    ///
+    /// # Arguments
+    /// * `content` - The manifest
+    /// * `source` - Path or URL of the manifest
+    ///
+    /// # Errors
+    /// If the manifest is invalid
+    pub fn parse(content: &str, source: &str) -> anyhow::Result<Self> {
+        if source.to_lowercase().ends_with(".json") {
+            serde_json::from_str(content).context("Invalid template manifest")
+        } else {
+            toml::from_str(content).context("Invalid template manifest")
+        }
+    }
+
+    /// Reads a manifest from a file or downloads it; the relative paths of
+    /// the templates of a local manifest are made relative to its directory
+    ///
+    /// # Arguments
+    /// * `source` - Path or HTTP(S) URL of the manifest
+    ///
+    /// # Errors
+    /// If the manifest cannot be read or downloaded, or is invalid
+    pub fn load(source: &str) -> anyhow::Result<Self> {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let error = format!("Cannot download the template manifest \"{}\"", source);
+            let content = http::agent(source)?
+                .get(source)
+                .call()
+                .context(error.clone())?
+                .into_string()
+                .context(error)?;
+            return Self::parse(&content, source);
+        }
+        let path = Path::new(source);
+        let content = fs::read_to_string(path)
+            .context(format!("Cannot read the template manifest \"{}\"", source))?;
+        let mut manifest = Self::parse(&content, source)?;
+        if let Some(dir) = path.parent() {
+            for template in &mut manifest.templates {
+                if !template.url.contains("://") && Path::new(&template.url).is_relative() {
+                    template.url = dir.join(&template.url).to_string_lossy().into_owned();
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Splits the templates into the ones that can be offered and the
+    /// invalid ones, see [`ManifestTemplate::validate`]; a name already
+    /// taken by an earlier template is invalid
+    pub fn validated(self) -> (Vec<ManifestTemplate>, Vec<InvalidTemplate>) {
+        let mut names = HashSet::new();
+        let mut valid = Vec::new();
+        let mut invalid = Vec::new();
+        for template in self.templates {
+            let result = template.validate().and_then(|_| {
+                if !names.insert(template.name.clone()) {
+                    bail!("Another template is already named \"{}\"", template.name);
+                }
+                Ok(())
+            });
+            match result {
+                Ok(()) => valid.push(template),
+                Err(error) => invalid.push(InvalidTemplate {
+                    name: template.name,
+                    error,
+                }),
+            }
+        }
+        (valid, invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(name: &str, url: &str, git_ref: Option<&str>) -> ManifestTemplate {
+        ManifestTemplate {
+            name: name.to_string(),
+            description: None,
+            url: url.to_string(),
+            git_ref: git_ref.map(String::from),
+            strip_prefix: None,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let toml = r#"
+[[template]]
+name = "blink-starter"
+description = "LED blink"
+url = "https://github.com/acme/blink-starter/archive/{ref}.zip"
+ref = "v1.2.0"
+strip_prefix = "blink-starter-1.2.0"
+
+[[template]]
+name = "sensor"
+url = "https://example.com/sensor.zip"
+"#;
+        let manifest = TemplateManifest::parse(toml, "catalog.toml").unwrap();
+        assert_eq!(manifest.templates.len(), 2);
+        assert_eq!(
+            manifest.templates[0].url(),
+            "https://github.com/acme/blink-starter/archive/v1.2.0.zip"
+        );
+        assert_eq!(
+            manifest.templates[0].strip_prefix.as_deref(),
+            Some("blink-starter-1.2.0")
+        );
+
+        let json = r#"{"template": [{"name": "sensor", "url": "https://example.com/sensor.zip"}]}"#;
+        let manifest = TemplateManifest::parse(json, "https://example.com/catalog.JSON").unwrap();
+        assert_eq!(manifest.templates[0].name, "sensor");
+
+        assert!(TemplateManifest::parse("[[template]]\nname = \"x\"\n", "catalog.toml").is_err());
+    }
+
+    #[test]
+    fn test_validated() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("local.zip"), b"").unwrap();
+        let manifest_file = dir.path().join("catalog.toml");
+        fs::write(
+            &manifest_file,
+            r#"
+[[template]]
+name = "local"
+url = "local.zip"
+
+[[template]]
+name = "missing"
+url = "missing.zip"
+
+[[template]]
+name = "no-ref"
+url = "https://example.com/{ref}.zip"
+
+[[template]]
+name = "bad name"
+url = "https://example.com/a.zip"
+
+[[template]]
+name = "local"
+url = "https://example.com/b.zip"
+
+[[template]]
+name = "escape"
+url = "https://example.com/c.zip"
+strip_prefix = "../c"
+"#,
+        )
+        .unwrap();
+
+        let manifest = TemplateManifest::load(&manifest_file.to_string_lossy()).unwrap();
+        let (valid, invalid) = manifest.validated();
+
+        assert_eq!(valid.len(), 1);
+        assert_eq!(
+            Path::new(&valid[0].url()),
+            dir.path().join("local.zip").as_path()
+        );
+        let invalid: Vec<&str> = invalid
+            .iter()
+            .map(|template| template.name.as_str())
+            .collect();
+        assert_eq!(
+            invalid,
+            ["missing", "no-ref", "bad name", "local", "escape"]
+        );
+    }
+
+    #[test]
+    fn test_validate_ref() {
+        assert!(template("a", "https://example.com/a.zip", Some("v1"))
+            .validate()
+            .is_err());
+        assert!(template("a", "https://example.com/{ref}.zip", Some("v1"))
+            .validate()
+            .is_ok());
+        assert!(template("a", "ftp://example.com/a.zip", None)
+            .validate()
+            .is_err());
+    }
+}
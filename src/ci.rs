@@ -0,0 +1,185 @@
+//! `--ci`: a pipeline that builds the project in the `espressif/idf` image,
+//! for GitHub Actions or GitLab CI
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::factory_test::FACTORY_TEST_APP;
+use crate::idf_version::IdfVersion;
+use crate::options::ScaffoldOptions;
+use crate::templates;
+
+/// The CI service the pipeline is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CiProvider {
+    /// A GitHub Actions workflow, see [`templates::GITHUB_CI_FILE`]
+    GitHub,
+    /// A GitLab CI pipeline, see [`templates::GITLAB_CI_FILE`]
+    GitLab,
+}
+
+impl CiProvider {
+    /// Every provider, in the order they are listed
+    pub const ALL: &'static [CiProvider] = &[CiProvider::GitHub, CiProvider::GitLab];
+
+    /// Path of the pipeline, relative to the project
+    pub fn file_name(self) -> &'static str {
+        match self {
+            CiProvider::GitHub => templates::GITHUB_CI_FILE,
+            CiProvider::GitLab => templates::GITLAB_CI_FILE,
+        }
+    }
+
+    /// Renders the pipeline, nothing is written
+    ///
+    /// # Arguments
+    /// * `target` - The chip the project is built for
+    /// * `apps` - The directories built, relative to the project
+    /// * `idf_version` - The ESP-IDF version the project assumes, the image
+    ///   is pinned to it, see [`image_tag`]
+    pub fn render(self, target: &str, apps: &[&str], idf_version: Option<&IdfVersion>) -> String {
+        let template = match self {
+            CiProvider::GitHub => templates::GITHUB_CI_TEMPLATE,
+            CiProvider::GitLab => templates::GITLAB_CI_TEMPLATE,
+        };
+        template
+            .replace("{{target}}", target)
+            .replace("{{apps}}", &apps.join(" "))
+            .replace("{{idf_tag}}", &image_tag(idf_version))
+    }
+
+    /// The status badge of the pipeline, for the README
+    ///
+    /// The links are relative to the README so they work wherever the
+    /// repository is hosted: on GitHub the status of the default branch, on
+    /// GitLab the one of `main`.
+    pub fn badge(self) -> String {
+        match self {
+            CiProvider::GitHub => format!(
+                "[![Build](../../actions/workflows/{0}/badge.svg)](../../actions/workflows/{0})",
+                Path::new(templates::GITHUB_CI_FILE)
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or_default()
+            ),
+            CiProvider::GitLab => {
+                "[![Pipeline](../../../badges/main/pipeline.svg)](../../../-/pipelines)".to_string()
+            }
+        }
+    }
+}
+
+/// The tag of the `espressif/idf` image: the release of the version, e.g.
+/// `v5.1` or `v5.1.2`, the release branch of a pre-release (`release-v5.2`),
+/// `latest` without a version
+///
+/// # Arguments
+/// * `idf_version` - The ESP-IDF version the project assumes
+pub fn image_tag(idf_version: Option<&IdfVersion>) -> String {
+    match idf_version {
+        Some(version) if version.pre_release.is_some() => {
+            format!("release-v{}.{}", version.major, version.minor)
+        }
+        Some(version) => format!("v{}", version),
+        None => "latest".to_string(),
+    }
+}
+
+impl FromStr for CiProvider {
+    type Err = String;
+
+    fn from_str(provider: &str) -> Result<Self, Self::Err> {
+        CiProvider::ALL
+            .iter()
+            .copied()
+            .find(|candidate| candidate.to_string() == provider)
+            .ok_or_else(|| {
+                format!(
+                    "unknown CI provider \"{}\", expected github or gitlab",
+                    provider
+                )
+            })
+    }
+}
+
+impl fmt::Display for CiProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CiProvider::GitHub => "github",
+            CiProvider::GitLab => "gitlab",
+        })
+    }
+}
+
+/// The directories the pipeline builds: the project, or each app of a
+/// workspace and its factory test app
+///
+/// # Arguments
+/// * `options` - The options of the generation
+pub fn apps(options: &ScaffoldOptions) -> Vec<&str> {
+    if options.workspace_apps.is_empty() {
+        return vec!["."];
+    }
+    let mut apps: Vec<&str> = options.workspace_apps.iter().map(String::as_str).collect();
+    if options.factory_test {
+        apps.push(FACTORY_TEST_APP);
+    }
+    apps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let gitlab = CiProvider::GitLab.render("esp32c3", &["."], None);
+        assert!(gitlab.contains("  image: espressif/idf:latest\n"));
+        assert!(gitlab.contains("    IDF_TARGET: esp32c3\n    APPS: \".\"\n"));
+        assert!(!gitlab.contains("{{"));
+        let github = CiProvider::GitHub.render("esp32", &["sensor", "gateway"], None);
+        assert!(github.contains("    container: espressif/idf:latest\n"));
+        let pinned = CiProvider::GitHub.render("esp32", &["."], Some(&IdfVersion::new(5, 1)));
+        assert!(pinned.contains("    container: espressif/idf:v5.1\n"));
+        assert!(github.contains("      APPS: \"sensor gateway\"\n"));
+        assert_eq!(CiProvider::GitLab.file_name(), ".gitlab-ci.yml");
+
+        assert_eq!("gitlab".parse(), Ok(CiProvider::GitLab));
+        assert_eq!("github".parse(), Ok(CiProvider::GitHub));
+        assert!("jenkins".parse::<CiProvider>().is_err());
+        assert!("GitLab".parse::<CiProvider>().is_err());
+    }
+
+    #[test]
+    fn test_image_tag() {
+        assert_eq!(image_tag(None), "latest");
+        assert_eq!(image_tag(Some(&IdfVersion::new(5, 1))), "v5.1");
+        assert_eq!(image_tag(Some(&"5.1.2".parse().unwrap())), "v5.1.2");
+        assert_eq!(image_tag(Some(&"5.2-rc1".parse().unwrap())), "release-v5.2");
+    }
+
+    #[test]
+    fn test_badge() {
+        assert_eq!(
+            CiProvider::GitHub.badge(),
+            "[![Build](../../actions/workflows/build.yml/badge.svg)](../../actions/workflows/build.yml)"
+        );
+        assert!(CiProvider::GitLab
+            .badge()
+            .contains("/badges/main/pipeline.svg"));
+    }
+
+    #[test]
+    fn test_apps() {
+        let mut options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        assert_eq!(apps(&options), ["."]);
+        options.workspace_apps = vec!["sensor".into(), "gateway".into()];
+        assert_eq!(apps(&options), ["sensor", "gateway"]);
+        options.factory_test = true;
+        assert_eq!(apps(&options), ["sensor", "gateway", "factory_test"]);
+    }
+}
@@ -0,0 +1,1008 @@
+use anyhow::{bail, Context};
+
+use crate::naming;
+
+/// Replaces whole lines of a file without splitting it into owned lines
+///
+/// # Arguments
+/// * `content` - The content of the file
+/// * `replacements` - The zero-based line indices and their new content
+///
+/// # Errors
+/// If the file has fewer lines than the highest replaced index
+fn replace_lines(content: &str, replacements: &[(usize, &str)]) -> anyhow::Result<String> {
+    let line_count = content.split('\n').count();
+    if let Some((index, _)) = replacements.iter().find(|(index, _)| *index >= line_count) {
+        bail!(
+            "Unexpected CMakeLists.txt layout, line {} not found (the file has {} lines)",
+            index + 1,
+            line_count
+        );
+    }
+
+    let extra: usize = replacements.iter().map(|(_, line)| line.len()).sum();
+    let mut result = String::with_capacity(content.len() + extra);
+    for (i, line) in content.split('\n').enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+        match replacements.iter().find(|(index, _)| *index == i) {
+            Some((_, replacement)) => result.push_str(replacement),
+            None => result.push_str(line),
+        }
+    }
+    Ok(result)
+}
+
+/// Makes CMake fail when the compiler doesn't support the selected C++
+/// standard, instead of falling back to an older one
+pub const CXX_STANDARD_REQUIRED: &str = "set(CMAKE_CXX_STANDARD_REQUIRED ON)";
+
+/// Rewrites the top-level CMakeLists.txt of the template
+///
+/// # Arguments
+/// * `content` - The content of the template CMakeLists.txt
+/// * `project_language` - The CMake line that sets the language standard, may be empty
+/// * `project_name` - The name used in `project(...)`
+///
+/// # Errors
+/// If the file doesn't have the layout of the template
+pub fn rewrite_project_cmake(
+    content: &str,
+    project_language: &str,
+    project_name: &str,
+) -> anyhow::Result<String> {
+    let mut result = replace_lines(
+        content,
+        &[
+            (4, project_language),
+            (5, "set(EXTRA_COMPONENT_DIRS components)"),
+            (6, "include($ENV{IDF_PATH}/tools/cmake/project.cmake)"),
+        ],
+    )?;
+    result.push('\n');
+    result.push_str("project(");
+    result.push_str(project_name);
+    result.push(')');
+    Ok(result)
+}
+
+/// Adds [`CXX_STANDARD_REQUIRED`] after `set(CMAKE_CXX_STANDARD ...)`
+///
+/// A file without a C++ standard, or that already sets
+/// `CMAKE_CXX_STANDARD_REQUIRED`, is returned unchanged.
+///
+/// # Arguments
+/// * `content` - The content of the top-level CMakeLists.txt
+pub fn require_cxx_standard(content: &str) -> String {
+    if variable(content, "CMAKE_CXX_STANDARD_REQUIRED").is_some() {
+        return content.into();
+    }
+    let mut lines: Vec<&str> = content.split('\n').collect();
+    // After the last one, the one CMake uses
+    match lines
+        .iter()
+        .rposition(|line| variable(line, "CMAKE_CXX_STANDARD").is_some())
+    {
+        Some(index) => {
+            lines.insert(index + 1, CXX_STANDARD_REQUIRED);
+            lines.join("\n")
+        }
+        None => content.into(),
+    }
+}
+
+/// Whether a line includes `project.cmake` of ESP-IDF
+fn is_project_include(line: &str) -> bool {
+    line.trim_start().starts_with("include(") && line.contains("project.cmake")
+}
+
+/// Whether a line is a `project(...)` command
+fn is_project_command(line: &str) -> bool {
+    line.trim_start()
+        .strip_prefix("project")
+        .is_some_and(|rest| rest.trim_start().starts_with('('))
+}
+
+/// Removes the commands that old versions of the generator left twice when
+/// they rewrote the lines of a template without the expected layout
+///
+/// The `include` of `project.cmake` is kept once, and only the last
+/// `project(...)`, the one the generator appended, is kept.
+///
+/// # Arguments
+/// * `content` - The content of the top-level CMakeLists.txt
+pub fn remove_duplicate_commands(content: &str) -> String {
+    let lines: Vec<&str> = content.split('\n').collect();
+    let last_project = lines.iter().rposition(|line| is_project_command(line));
+    let mut included = false;
+    let mut result = Vec::with_capacity(lines.len());
+    for (i, line) in lines.into_iter().enumerate() {
+        if is_project_include(line) {
+            if included {
+                continue;
+            }
+            included = true;
+        } else if is_project_command(line) && Some(i) != last_project {
+            continue;
+        }
+        result.push(line);
+    }
+    result.join("\n")
+}
+
+/// Points a sub-project of a workspace to the shared components and
+/// `sdkconfig.defaults` at the workspace root
+///
+/// `EXTRA_COMPONENT_DIRS` is replaced by the shared directory, and
+/// `SDKCONFIG_DEFAULTS` lists the shared defaults before the ones of the
+/// sub-project, so the sub-project overrides them.
+///
+/// # Arguments
+/// * `content` - The top-level CMakeLists.txt, as written by [`rewrite_project_cmake`]
+/// * `components_dir` - The shared components, relative to the sub-project
+/// * `shared_defaults` - The shared `sdkconfig.defaults`, relative to the
+///   sub-project; `None` when the workspace has none, `SDKCONFIG_DEFAULTS`
+///   is then not set
+///
+/// # Errors
+/// If the file doesn't set `EXTRA_COMPONENT_DIRS`
+pub fn use_workspace_dirs(
+    content: &str,
+    components_dir: &str,
+    shared_defaults: Option<&str>,
+) -> anyhow::Result<String> {
+    let index = content
+        .split('\n')
+        .position(|line| line.trim_start().starts_with("set(EXTRA_COMPONENT_DIRS"))
+        .context("No set(EXTRA_COMPONENT_DIRS ...) found")?;
+    let mut dirs = format!("set(EXTRA_COMPONENT_DIRS {})", components_dir);
+    if let Some(shared_defaults) = shared_defaults {
+        dirs.push_str(&format!(
+            "\nset(SDKCONFIG_DEFAULTS \"{};sdkconfig.defaults\")",
+            shared_defaults
+        ));
+    }
+    replace_lines(content, &[(index, &dirs)])
+}
+
+/// Rewrites the main component CMakeLists.txt of the template to build another source
+///
+/// # Arguments
+/// * `content` - The content of the template main/CMakeLists.txt
+/// * `source` - The source file of the main component
+///
+/// # Errors
+/// If the file doesn't have the layout of the template
+pub fn rewrite_main_cmake_sources(content: &str, source: &str) -> anyhow::Result<String> {
+    let srcs = format!("set(COMPONENT_SRCS \"{}\")", source);
+    replace_lines(content, &[(4, &srcs)])
+}
+
+/// Keywords of `idf_component_register(...)`, one of them ends the `SRCS` list
+const REGISTER_KEYWORDS: &[&str] = &[
+    "SRCS",
+    "SRC_DIRS",
+    "EXCLUDE_SRCS",
+    "INCLUDE_DIRS",
+    "PRIV_INCLUDE_DIRS",
+    "LDFRAGMENTS",
+    "REQUIRES",
+    "PRIV_REQUIRES",
+    "REQUIRED_IDF_TARGETS",
+    "EMBED_FILES",
+    "EMBED_TXTFILES",
+    "KCONFIG",
+    "KCONFIG_PROJBUILD",
+    "WHOLE_ARCHIVE",
+];
+
+/// Finds the values of the source list of a component CMakeLists.txt,
+/// `SRCS` of `idf_component_register(...)` or the legacy `set(COMPONENT_SRCS ...)`
+fn sources_range(content: &str) -> anyhow::Result<std::ops::Range<usize>> {
+    if let Some(start) = find_keyword(content, "set(COMPONENT_SRCS") {
+        let start = start + "set(COMPONENT_SRCS".len();
+        let end = start
+            + content[start..]
+                .find(')')
+                .context("Unclosed set(...) call")?;
+        return Ok(start..end);
+    }
+    let args_start = content
+        .find(COMPONENT_REGISTER)
+        .map(|call| call + COMPONENT_REGISTER.len())
+        .context("No idf_component_register(...) found in the component CMakeLists.txt")?;
+    let args_end = args_start
+        + content[args_start..]
+            .find(')')
+            .context("Unclosed idf_component_register(...) call")?;
+    let args = &content[args_start..args_end];
+    let start =
+        find_keyword(args, "SRCS").context("The component has no SRCS list")? + "SRCS".len();
+    let end = REGISTER_KEYWORDS
+        .iter()
+        .filter_map(|keyword| find_keyword(&args[start..], keyword))
+        .min()
+        .map_or(args.len(), |end| start + end);
+    Ok(args_start + start..args_start + end)
+}
+
+/// Reads the sources listed in a component CMakeLists.txt, in both the
+/// `idf_component_register(SRCS ...)` and the legacy form
+///
+/// # Arguments
+/// * `content` - The content of the component CMakeLists.txt
+///
+/// # Errors
+/// If the file has no source list
+pub fn component_sources(content: &str) -> anyhow::Result<Vec<String>> {
+    let range = sources_range(content)?;
+    Ok(content[range]
+        .split_whitespace()
+        .map(|source| source.trim_matches('"'))
+        .filter(|source| !source.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Replaces the sources listed in a component CMakeLists.txt, the line
+/// breaks around the list are kept
+///
+/// # Arguments
+/// * `content` - The content of the component CMakeLists.txt
+/// * `sources` - The sources, relative to the component, in order
+///
+/// # Errors
+/// If the file has no source list
+pub fn set_component_sources(content: &str, sources: &[String]) -> anyhow::Result<String> {
+    let range = sources_range(content)?;
+    let values = &content[range.clone()];
+    // The separator before the next keyword, none before the `)`
+    let trailing = if content[range.end..].starts_with(')') {
+        ""
+    } else if values.trim().is_empty() {
+        values
+    } else {
+        &values[values.trim_end().len()..]
+    };
+    let quoted: Vec<String> = sources
+        .iter()
+        .map(|source| format!("\"{}\"", source))
+        .collect();
+    Ok(format!(
+        "{} {}{}{}",
+        &content[..range.start],
+        quoted.join(" "),
+        trailing,
+        &content[range.end..]
+    ))
+}
+
+/// Replaces a source file listed in a component CMakeLists.txt, quoted or
+/// not, in both the `idf_component_register(SRCS ...)` and the legacy form
+///
+/// # Arguments
+/// * `content` - The content of the component CMakeLists.txt
+/// * `old` - The listed source, relative to the component
+/// * `new` - The source that replaces it
+///
+/// # Returns
+/// The new content, `None` if `old` is not listed
+pub fn replace_source(content: &str, old: &str, new: &str) -> Option<String> {
+    let is_separator = |c: char| c.is_whitespace() || matches!(c, '(' | ')' | '"');
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (i, _) in content.match_indices(old) {
+        let before = content[..i].chars().next_back();
+        let after = content[i + old.len()..].chars().next();
+        if before.is_none_or(is_separator) && after.is_none_or(is_separator) {
+            result.push_str(&content[last..i]);
+            result.push_str(new);
+            last = i + old.len();
+        }
+    }
+    if last == 0 {
+        return None;
+    }
+    result.push_str(&content[last..]);
+    Some(result)
+}
+
+/// Call that registers a component with the current build system
+const COMPONENT_REGISTER: &str = "idf_component_register(";
+
+/// Adds source files to a component CMakeLists.txt
+///
+/// Both the current `idf_component_register(SRCS ...)` form and the legacy
+/// `set(COMPONENT_SRCS ...)` form are supported. Sources already listed are
+/// skipped, and nothing is added when the component uses `SRC_DIRS`, which
+/// already picks up every source of the directory.
+///
+/// # Arguments
+/// * `content` - The content of the component CMakeLists.txt
+/// * `sources` - The source files, relative to the component
+///
+/// # Errors
+/// If the file doesn't register the component in any of the supported forms
+pub fn add_component_sources(content: &str, sources: &[&str]) -> anyhow::Result<String> {
+    let quoted: Vec<String> = sources
+        .iter()
+        .map(|source| format!("\"{}\"", source))
+        .collect();
+    add_component_values(content, "COMPONENT_SRCS", "SRCS", Some("SRC_DIRS"), &quoted)
+}
+
+/// Adds component requirements to a component CMakeLists.txt, see [`add_component_sources`]
+///
+/// # Arguments
+/// * `content` - The content of the component CMakeLists.txt
+/// * `requires` - The names of the required components
+///
+/// # Errors
+/// If the file doesn't register the component in any of the supported forms
+pub fn add_component_requires(content: &str, requires: &[&str]) -> anyhow::Result<String> {
+    let requires: Vec<String> = requires.iter().map(|r| r.to_string()).collect();
+    add_component_values(content, "COMPONENT_REQUIRES", "REQUIRES", None, &requires)
+}
+
+/// Legacy component variables and the `idf_component_register` keyword that replaces them
+const LEGACY_COMPONENT_VARIABLES: &[(&str, &str)] = &[
+    ("COMPONENT_SRCS", "SRCS"),
+    ("COMPONENT_SRCDIRS", "SRC_DIRS"),
+    ("COMPONENT_ADD_INCLUDEDIRS", "INCLUDE_DIRS"),
+    ("COMPONENT_PRIV_INCLUDEDIRS", "PRIV_INCLUDE_DIRS"),
+    ("COMPONENT_REQUIRES", "REQUIRES"),
+    ("COMPONENT_PRIV_REQUIRES", "PRIV_REQUIRES"),
+    ("COMPONENT_EMBED_FILES", "EMBED_FILES"),
+    ("COMPONENT_EMBED_TXTFILES", "EMBED_TXTFILES"),
+];
+
+/// Converts a component CMakeLists.txt from the legacy `set(COMPONENT_*)` +
+/// `register_component()` form to `idf_component_register(...)`
+///
+/// Empty values are dropped and the other lines are kept. A file that
+/// already uses `idf_component_register` is returned unchanged.
+///
+/// # Arguments
+/// * `content` - The content of the component CMakeLists.txt
+///
+/// # Errors
+/// If the file uses neither form, or sets a legacy variable over several lines
+pub fn modernize_component_cmake(content: &str) -> anyhow::Result<String> {
+    if content.contains(COMPONENT_REGISTER) {
+        return Ok(content.into());
+    }
+    if !content
+        .lines()
+        .any(|line| line.trim() == "register_component()")
+    {
+        bail!("No register_component() or idf_component_register(...) found in the component CMakeLists.txt");
+    }
+
+    let mut arguments = Vec::new();
+    let mut lines = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        let legacy = LEGACY_COMPONENT_VARIABLES.iter().find(|(variable, _)| {
+            trimmed
+                .strip_prefix("set(")
+                .and_then(|rest| rest.trim_start().strip_prefix(variable))
+                .is_some_and(|rest| rest.starts_with(|c: char| c.is_whitespace() || c == ')'))
+        });
+        match legacy {
+            Some((variable, keyword)) => {
+                let values = trimmed.strip_suffix(')').context(format!(
+                    "set({} ...) over several lines is not supported",
+                    variable
+                ))?;
+                let values: Vec<&str> = values["set(".len()..].trim_start()[variable.len()..]
+                    .split_whitespace()
+                    .filter(|value| *value != "\"\"")
+                    .collect();
+                if !values.is_empty() {
+                    arguments.push(format!("{} {}", keyword, values.join(" ")));
+                }
+            }
+            None if trimmed == "register_component()" => lines.push(None),
+            None => lines.push(Some(line)),
+        }
+    }
+
+    let register = format!(
+        "{}{})",
+        COMPONENT_REGISTER,
+        arguments.join(&format!("\n{}", " ".repeat(COMPONENT_REGISTER.len())))
+    );
+    let mut result = String::with_capacity(content.len());
+    let mut previous_blank = true;
+    for line in lines {
+        let line = line.unwrap_or(&register);
+        // The removed set() lines leave runs of blank lines behind
+        let blank = line.trim().is_empty();
+        if blank && previous_blank {
+            continue;
+        }
+        previous_blank = blank;
+        result.push_str(line);
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+/// Updates a top-level CMakeLists.txt to what the generator writes now
+///
+/// Removes the duplicated commands of [`remove_duplicate_commands`], adds
+/// `set(EXTRA_COMPONENT_DIRS components)` before the `project.cmake` include
+/// if missing, and replaces a `project(...)` name that is not a valid CMake
+/// identifier with its sanitized form. The rest of the file is kept.
+///
+/// # Arguments
+/// * `content` - The content of the top-level CMakeLists.txt
+///
+/// # Errors
+/// If the file has no `project(...)` command
+pub fn modernize_project_cmake(content: &str) -> anyhow::Result<String> {
+    let content = remove_duplicate_commands(content);
+    let name = project_name(&content).context("No project(...) command found")?;
+    let mut result = rename_project(&content, &naming::cmake_identifier(name))?;
+    if variable(&result, "EXTRA_COMPONENT_DIRS").is_none() {
+        if let Some(include) = result.lines().position(is_project_include) {
+            let mut lines: Vec<&str> = result.split('\n').collect();
+            lines.insert(include, "set(EXTRA_COMPONENT_DIRS components)");
+            result = lines.join("\n");
+        }
+    }
+    Ok(result)
+}
+
+fn add_component_values(
+    content: &str,
+    legacy_variable: &str,
+    keyword: &str,
+    skip_keyword: Option<&str>,
+    values: &[String],
+) -> anyhow::Result<String> {
+    let values: Vec<&str> = values
+        .iter()
+        .map(String::as_str)
+        .filter(|value| !contains_token(content, value))
+        .collect();
+    if values.is_empty() {
+        return Ok(content.into());
+    }
+    let joined = values.join(" ");
+
+    // Legacy: set(COMPONENT_SRCS "main.c")
+    if let Some(start) = find_keyword(content, &format!("set({}", legacy_variable)) {
+        let end = start
+            + content[start..]
+                .find(')')
+                .context("Unclosed set(...) call")?;
+        let separator = if content[..end].ends_with(char::is_whitespace) {
+            ""
+        } else {
+            " "
+        };
+        return Ok(format!(
+            "{}{}{}{}",
+            &content[..end],
+            separator,
+            joined,
+            &content[end..]
+        ));
+    }
+
+    let args_start = content
+        .find(COMPONENT_REGISTER)
+        .map(|call| call + COMPONENT_REGISTER.len())
+        .context("No idf_component_register(...) found in the component CMakeLists.txt")?;
+    let args_end = args_start
+        + content[args_start..]
+            .find(')')
+            .context("Unclosed idf_component_register(...) call")?;
+    let args = &content[args_start..args_end];
+    if skip_keyword.is_some_and(|skip| find_keyword(args, skip).is_some()) {
+        return Ok(content.into());
+    }
+    let (position, insertion) = match find_keyword(args, keyword) {
+        Some(position) => (
+            args_start + position + keyword.len(),
+            format!(" {}", joined),
+        ),
+        None => (
+            args_start + args.trim_end().len(),
+            format!(" {} {}", keyword, joined),
+        ),
+    };
+    Ok(format!(
+        "{}{}{}",
+        &content[..position],
+        insertion,
+        &content[position..]
+    ))
+}
+
+/// Finds the name in the `project(...)` command of a top-level CMakeLists.txt
+///
+/// # Arguments
+/// * `content` - The content of the CMakeLists.txt
+///
+/// # Returns
+/// The byte range of the name, or `None` if there is no `project(...)` command
+fn find_project_name(content: &str) -> Option<std::ops::Range<usize>> {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let command = line.trim_start();
+        let start = offset + line.len() - command.len();
+        offset += line.len();
+        let arguments = match command.strip_prefix("project") {
+            Some(rest) if rest.trim_start().starts_with('(') => rest.trim_start()[1..].trim_start(),
+            _ => continue,
+        };
+        let name_start = start + command.len() - arguments.len();
+        let name_len = arguments
+            .find(|c: char| c.is_whitespace() || c == ')')
+            .unwrap_or(arguments.len());
+        if name_len > 0 {
+            return Some(name_start..name_start + name_len);
+        }
+    }
+    None
+}
+
+/// Reads the name in the `project(...)` command of a top-level CMakeLists.txt
+///
+/// # Arguments
+/// * `content` - The content of the CMakeLists.txt
+pub fn project_name(content: &str) -> Option<&str> {
+    find_project_name(content).map(|range| &content[range])
+}
+
+/// Replaces the name in the `project(...)` command, keeping its other arguments
+///
+/// # Arguments
+/// * `content` - The content of the top-level CMakeLists.txt
+/// * `project_name` - The new name
+///
+/// # Errors
+/// If the file has no `project(...)` command
+pub fn rename_project(content: &str, project_name: &str) -> anyhow::Result<String> {
+    let range = find_project_name(content).context("No project(...) command found")?;
+    Ok(format!(
+        "{}{}{}",
+        &content[..range.start],
+        project_name,
+        &content[range.end..]
+    ))
+}
+
+/// Keywords that start the named arguments of the `project(...)` command
+const PROJECT_KEYWORDS: &[&str] = &["VERSION", "DESCRIPTION", "HOMEPAGE_URL", "LANGUAGES"];
+
+/// Splits CMake arguments on whitespace, keeping quoted arguments whole
+fn split_arguments(arguments: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in arguments.char_indices() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                start.get_or_insert(i);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some(start) = start.take() {
+                    result.push(&arguments[start..i]);
+                }
+            }
+            _ => {
+                start.get_or_insert(i);
+            }
+        }
+    }
+    if let Some(start) = start {
+        result.push(&arguments[start..]);
+    }
+    result
+}
+
+/// Sets the languages CMake enables in the `project(...)` command, replacing
+/// the ones it lists
+///
+/// `project(name)` and `project(name C)` get the languages after the name,
+/// a command with keywords (`VERSION`...) gets a `LANGUAGES` clause.
+///
+/// # Arguments
+/// * `content` - The content of the top-level CMakeLists.txt
+/// * `languages` - The languages, e.g. `["C", "CXX"]`
+///
+/// # Errors
+/// If the file has no `project(...)` command or it is not closed
+pub fn set_project_languages(content: &str, languages: &[&str]) -> anyhow::Result<String> {
+    let range = find_project_name(content).context("No project(...) command found")?;
+    let mut quoted = false;
+    let end = content[range.end..]
+        .char_indices()
+        .find(|&(_, c)| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ')' && !quoted
+        })
+        .map(|(i, _)| range.end + i)
+        .context("The project(...) command is not closed")?;
+    let arguments = split_arguments(&content[range.end..end]);
+
+    let mut kept: Vec<&str> = Vec::with_capacity(arguments.len() + languages.len() + 1);
+    if arguments
+        .iter()
+        .any(|argument| PROJECT_KEYWORDS.contains(argument))
+    {
+        let mut in_languages = false;
+        for argument in arguments {
+            if PROJECT_KEYWORDS.contains(&argument) {
+                in_languages = argument == "LANGUAGES";
+            }
+            if !in_languages {
+                kept.push(argument);
+            }
+        }
+        kept.push("LANGUAGES");
+    }
+    kept.extend(languages);
+    Ok(format!(
+        "{} {}{}",
+        &content[..range.end],
+        kept.join(" "),
+        &content[end..]
+    ))
+}
+
+/// Turns a top-level CMakeLists.txt written by [`rewrite_project_cmake`] back
+/// into the layout of the template, so it can be rewritten again
+///
+/// The language line is cleared and the `project(...)` command moves back
+/// after the `include` of `project.cmake`, with the name replaced.
+///
+/// # Arguments
+/// * `content` - The content of the top-level CMakeLists.txt of a generated project
+/// * `project_name` - The name used in `project(...)`, usually a placeholder
+///
+/// # Errors
+/// If the file doesn't have the layout written by [`rewrite_project_cmake`]
+pub fn to_template_layout(content: &str, project_name: &str) -> anyhow::Result<String> {
+    let range = find_project_name(content).context("No project(...) command found")?;
+    let project_index = content[..range.start].matches('\n').count();
+    let lines: Vec<&str> = content.split('\n').collect();
+    if project_index < 7 || !lines[6].trim_start().starts_with("include(") {
+        bail!("Unexpected CMakeLists.txt layout, the project was not generated from the template");
+    }
+
+    let project_line = rename_project(lines[project_index], project_name)?;
+    // Lines 4 to 6 of the template are replaced when generating, the last one is kept empty
+    let mut result = lines[..4].to_vec();
+    result.extend([lines[6], &project_line, ""]);
+    result.extend(
+        lines[7..]
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i + 7 != project_index)
+            .map(|(_, line)| *line),
+    );
+    Ok(result.join("\n"))
+}
+
+/// Reads the value of a variable set with `set(NAME value)`
+///
+/// # Arguments
+/// * `content` - The content of the CMakeLists.txt
+/// * `name` - The variable name, e.g. `CMAKE_CXX_STANDARD`
+///
+/// # Returns
+/// The value without quotes, the last one if the variable is set several times
+pub fn variable<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let arguments = line.trim_start().strip_prefix("set(")?.trim_start();
+            let value = arguments.strip_prefix(name)?;
+            if !value.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let value = value.trim().strip_suffix(')')?.trim();
+            Some(value.trim_matches('"'))
+        })
+        .next_back()
+}
+
+/// Checks if the value is one of the whitespace or parenthesis separated tokens
+fn contains_token(content: &str, value: &str) -> bool {
+    content
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .any(|token| token == value)
+}
+
+/// Finds a keyword that is not part of a longer word (e.g. `REQUIRES` in `PRIV_REQUIRES`)
+fn find_keyword(content: &str, keyword: &str) -> Option<usize> {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    content.match_indices(keyword).map(|(i, _)| i).find(|&i| {
+        let before = content[..i].chars().next_back();
+        let after = content[i + keyword.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{TEMPLATE_CMAKE, TEMPLATE_MAIN_CMAKE};
+
+    #[test]
+    fn test_rewrite_project_cmake() {
+        let cmake =
+            rewrite_project_cmake(TEMPLATE_CMAKE, "set(CMAKE_CXX_STANDARD 17)", "blink").unwrap();
+        let lines: Vec<&str> = cmake.split('\n').collect();
+
+        assert_eq!(lines[4], "set(CMAKE_CXX_STANDARD 17)");
+        assert_eq!(lines[5], "set(EXTRA_COMPONENT_DIRS components)");
+        assert_eq!(
+            lines[6],
+            "include($ENV{IDF_PATH}/tools/cmake/project.cmake)"
+        );
+        assert_eq!(lines.last(), Some(&"project(blink)"));
+    }
+
+    #[test]
+    fn test_use_workspace_dirs() {
+        let cmake = rewrite_project_cmake(TEMPLATE_CMAKE, "", "app").unwrap();
+        let cmake =
+            use_workspace_dirs(&cmake, "../components", Some("../sdkconfig.defaults")).unwrap();
+
+        assert_eq!(
+            variable(&cmake, "EXTRA_COMPONENT_DIRS"),
+            Some("../components")
+        );
+        assert_eq!(
+            variable(&cmake, "SDKCONFIG_DEFAULTS"),
+            Some("../sdkconfig.defaults;sdkconfig.defaults")
+        );
+        assert_eq!(project_name(&cmake), Some("app"));
+        assert!(use_workspace_dirs(
+            TEMPLATE_CMAKE,
+            "../components",
+            Some("../sdkconfig.defaults")
+        )
+        .is_err());
+
+        let cmake = rewrite_project_cmake(TEMPLATE_CMAKE, "", "app").unwrap();
+        let cmake = use_workspace_dirs(&cmake, "../components", None).unwrap();
+        assert_eq!(variable(&cmake, "SDKCONFIG_DEFAULTS"), None);
+    }
+
+    #[test]
+    fn test_rename_project() {
+        let cmake =
+            rewrite_project_cmake(TEMPLATE_CMAKE, "set(CMAKE_CXX_STANDARD 17)", "blink").unwrap();
+        assert_eq!(project_name(&cmake), Some("blink"));
+        let renamed = rename_project(&cmake, "sensor").unwrap();
+        assert_eq!(renamed, cmake.replace("project(blink)", "project(sensor)"));
+
+        // Other arguments are kept, project.cmake is not a project() command
+        let cmake =
+            "include($ENV{IDF_PATH}/tools/cmake/project.cmake)\n  project (blink LANGUAGES C)\n";
+        assert_eq!(
+            rename_project(cmake, "sensor").unwrap(),
+            "include($ENV{IDF_PATH}/tools/cmake/project.cmake)\n  project (sensor LANGUAGES C)\n"
+        );
+        assert!(rename_project("cmake_minimum_required(VERSION 3.5)\n", "sensor").is_err());
+    }
+
+    #[test]
+    fn test_set_project_languages() {
+        let cxx = ["C", "CXX"];
+        let cmake = rewrite_project_cmake(TEMPLATE_CMAKE, "", "blink").unwrap();
+        let cmake = set_project_languages(&cmake, &["C"]).unwrap();
+        assert!(cmake.ends_with("\nproject(blink C)"));
+        // Amended in place, not appended again
+        let cmake = set_project_languages(&cmake, &cxx).unwrap();
+        assert!(cmake.ends_with("\nproject(blink C CXX)"));
+        assert_eq!(cmake.matches("project(").count(), 1);
+        assert_eq!(project_name(&cmake), Some("blink"));
+
+        for (cmake, expected) in [
+            ("project(blink)\n", "project(blink C CXX)\n"),
+            ("  project (blink C)\n", "  project (blink C CXX)\n"),
+            (
+                "project(blink VERSION 1.0 LANGUAGES C)\n",
+                "project(blink VERSION 1.0 LANGUAGES C CXX)\n",
+            ),
+            (
+                "project(blink LANGUAGES C DESCRIPTION \"A (C) app\")",
+                "project(blink DESCRIPTION \"A (C) app\" LANGUAGES C CXX)",
+            ),
+        ] {
+            assert_eq!(set_project_languages(cmake, &cxx).unwrap(), expected);
+        }
+        assert!(set_project_languages("cmake_minimum_required(VERSION 3.5)\n", &cxx).is_err());
+        assert!(set_project_languages("project(blink C\n", &cxx).is_err());
+    }
+
+    #[test]
+    fn test_to_template_layout() {
+        let template = TEMPLATE_CMAKE.replace("app-template", "{{project_name}}");
+        for language in ["", "set(CMAKE_CXX_STANDARD 17)"] {
+            let cmake = rewrite_project_cmake(TEMPLATE_CMAKE, language, "blink").unwrap();
+            let layout = to_template_layout(&cmake, "{{project_name}}").unwrap();
+            assert_eq!(layout, template);
+            // Generating from it again gives the same file
+            assert_eq!(
+                rewrite_project_cmake(&layout, language, "blink").unwrap(),
+                cmake
+            );
+        }
+        assert!(to_template_layout(TEMPLATE_CMAKE, "{{project_name}}").is_err());
+    }
+
+    #[test]
+    fn test_component_sources() {
+        let sources = vec!["app.cpp".to_string(), "main.cpp".to_string()];
+        let legacy = "set(COMPONENT_SRCS \"main.cpp\" wifi.c)\nregister_component()\n";
+        assert_eq!(component_sources(legacy).unwrap(), ["main.cpp", "wifi.c"]);
+        assert_eq!(
+            set_component_sources(legacy, &sources).unwrap(),
+            "set(COMPONENT_SRCS \"app.cpp\" \"main.cpp\")\nregister_component()\n"
+        );
+
+        let cmake = "idf_component_register(SRCS \"main.cpp\" \"wifi.c\"\n                       INCLUDE_DIRS \".\"\n                       REQUIRES nvs_flash)\n";
+        assert_eq!(component_sources(cmake).unwrap(), ["main.cpp", "wifi.c"]);
+        let rewritten = set_component_sources(cmake, &sources).unwrap();
+        assert_eq!(
+            rewritten,
+            "idf_component_register(SRCS \"app.cpp\" \"main.cpp\"\n                       INCLUDE_DIRS \".\"\n                       REQUIRES nvs_flash)\n"
+        );
+        assert_eq!(component_sources(&rewritten).unwrap(), sources);
+
+        let cmake = "idf_component_register(SRCS \"main.cpp\")";
+        assert_eq!(
+            set_component_sources(cmake, &sources).unwrap(),
+            "idf_component_register(SRCS \"app.cpp\" \"main.cpp\")"
+        );
+        // EXCLUDE_SRCS is not SRCS
+        assert!(
+            component_sources("idf_component_register(SRC_DIRS \".\" EXCLUDE_SRCS a.c)").is_err()
+        );
+    }
+
+    #[test]
+    fn test_modernize_component_cmake() {
+        let cmake = modernize_component_cmake(TEMPLATE_MAIN_CMAKE).unwrap();
+        assert_eq!(
+            cmake,
+            "# Edit following two lines to set component requirements (see docs)\n\nidf_component_register(SRCS \"main.c\")\n"
+        );
+        assert_eq!(modernize_component_cmake(&cmake).unwrap(), cmake);
+
+        let legacy = "set(COMPONENT_SRCS \"main.c\" \"wifi.c\")\nset(COMPONENT_REQUIRES nvs_flash)\nregister_component()\n";
+        assert_eq!(
+            modernize_component_cmake(legacy).unwrap(),
+            "idf_component_register(SRCS \"main.c\" \"wifi.c\"\n                       REQUIRES nvs_flash)\n"
+        );
+        assert!(modernize_component_cmake("add_library(foo foo.c)\n").is_err());
+    }
+
+    #[test]
+    fn test_modernize_project_cmake() {
+        let old = "cmake_minimum_required(VERSION 3.5)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(café)";
+        let cmake = modernize_project_cmake(old).unwrap();
+        assert_eq!(
+            cmake,
+            "cmake_minimum_required(VERSION 3.5)\nset(EXTRA_COMPONENT_DIRS components)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(cafe)"
+        );
+        assert_eq!(modernize_project_cmake(&cmake).unwrap(), cmake);
+    }
+
+    #[test]
+    fn test_require_cxx_standard() {
+        let cpp = "set(CMAKE_CXX_STANDARD 17)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(blink)";
+        let cmake = require_cxx_standard(cpp);
+        assert!(
+            cmake.starts_with("set(CMAKE_CXX_STANDARD 17)\nset(CMAKE_CXX_STANDARD_REQUIRED ON)\n")
+        );
+        assert_eq!(require_cxx_standard(&cmake), cmake);
+        let c = "include($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(blink)";
+        assert_eq!(require_cxx_standard(c), c);
+    }
+
+    #[test]
+    fn test_remove_duplicate_commands() {
+        // The template's own include and project() survived the line edits
+        let broken = "cmake_minimum_required(VERSION 3.5)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(app-template)\nset(EXTRA_COMPONENT_DIRS components)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(blink)";
+        let cmake = remove_duplicate_commands(broken);
+        assert_eq!(
+            cmake,
+            "cmake_minimum_required(VERSION 3.5)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\nset(EXTRA_COMPONENT_DIRS components)\nproject(blink)"
+        );
+        assert_eq!(remove_duplicate_commands(&cmake), cmake);
+        assert_eq!(
+            project_name(&modernize_project_cmake(broken).unwrap()),
+            Some("blink")
+        );
+    }
+
+    #[test]
+    fn test_variable() {
+        let cmake =
+            "set(CMAKE_CXX_STANDARD 17)\nset(PROJECT_VER \"1.2.0\")\nset(PROJECT_VERSION 2)\n";
+        assert_eq!(variable(cmake, "CMAKE_CXX_STANDARD"), Some("17"));
+        assert_eq!(variable(cmake, "PROJECT_VER"), Some("1.2.0"));
+        assert_eq!(variable(cmake, "CMAKE_C_STANDARD"), None);
+    }
+
+    #[test]
+    fn test_replace_source() {
+        assert_eq!(
+            replace_source(TEMPLATE_MAIN_CMAKE, "main.c", "main.cpp").unwrap(),
+            TEMPLATE_MAIN_CMAKE.replace("\"main.c\"", "\"main.cpp\"")
+        );
+        assert_eq!(
+            replace_source(
+                "idf_component_register(SRCS app.c util.c\n  INCLUDE_DIRS .)",
+                "app.c",
+                "app.cpp"
+            )
+            .unwrap(),
+            "idf_component_register(SRCS app.cpp util.c\n  INCLUDE_DIRS .)"
+        );
+        // Not a listed source, only part of one
+        assert_eq!(
+            replace_source("idf_component_register(SRCS \"my_main.c\")", "main.c", "x"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rewrite_main_cmake_sources() {
+        let cmake = rewrite_main_cmake_sources(TEMPLATE_MAIN_CMAKE, "main.cpp").unwrap();
+        assert_eq!(
+            cmake.split('\n').nth(4),
+            Some(r#"set(COMPONENT_SRCS "main.cpp")"#)
+        );
+        assert_eq!(cmake.len(), TEMPLATE_MAIN_CMAKE.len() + 2);
+    }
+
+    #[test]
+    fn test_rewrite_unexpected_layout() {
+        assert!(rewrite_project_cmake("project(x)\n", "", "blink").is_err());
+        assert!(rewrite_main_cmake_sources("", "main.cpp").is_err());
+    }
+
+    #[test]
+    fn test_add_component_sources_legacy() {
+        let cmake = add_component_sources(TEMPLATE_MAIN_CMAKE, &["wifi.c", "main.c"]).unwrap();
+        assert!(cmake.contains(r#"set(COMPONENT_SRCS "main.c" "wifi.c")"#));
+        let cmake = add_component_requires(&cmake, &["nvs_flash", "esp_wifi"]).unwrap();
+        assert!(cmake.contains("set(COMPONENT_REQUIRES nvs_flash esp_wifi)"));
+        assert!(cmake.contains("set(COMPONENT_PRIV_REQUIRES )"));
+    }
+
+    #[test]
+    fn test_add_component_sources_register() {
+        let content = "idf_component_register(SRCS \"main.c\"\n                    PRIV_REQUIRES spi_flash\n                    INCLUDE_DIRS \".\")\n";
+        let cmake = add_component_sources(content, &["wifi.c"]).unwrap();
+        assert!(cmake.contains(r#"SRCS "wifi.c" "main.c""#));
+        let cmake = add_component_requires(&cmake, &["esp_wifi"]).unwrap();
+        assert!(cmake.ends_with("INCLUDE_DIRS \".\" REQUIRES esp_wifi)\n"));
+        // Already required
+        assert_eq!(
+            add_component_requires(&cmake, &["esp_wifi"]).unwrap(),
+            cmake
+        );
+
+        let src_dirs = "idf_component_register(SRC_DIRS \".\")\n";
+        assert_eq!(
+            add_component_sources(src_dirs, &["wifi.c"]).unwrap(),
+            src_dirs
+        );
+        assert!(add_component_sources("project(x)\n", &["wifi.c"]).is_err());
+    }
+}
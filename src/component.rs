@@ -0,0 +1,180 @@
+//! `component new`: scaffolds a component in an existing project
+
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::naming;
+use crate::project::{self, SourceLanguage};
+
+/// Directory of the project components, listed in `EXTRA_COMPONENT_DIRS`
+pub const COMPONENTS_DIR: &str = "components";
+
+/// Optional parts of a new component
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentOptions {
+    /// Whether to add a `Kconfig` with a sample option
+    pub kconfig: bool,
+    /// Whether to add a `test/` directory with a Unity test case
+    pub unit_test: bool,
+}
+
+/// Creates `components/<name>/` in the project that contains `start`
+///
+/// The component has a CMakeLists.txt with `idf_component_register`, a public
+/// header in `include/` and a source file in the language of the project.
+///
+/// # Arguments
+/// * `start` - A directory inside the project, usually the current directory
+/// * `name` - The component name, a C identifier
+/// * `options` - The optional parts of the component
+///
+/// # Returns
+/// The directory of the new component
+///
+/// # Errors
+/// If `start` is not inside a project, the name is not a C identifier, the
+/// component already exists or its files cannot be written
+pub fn new_component(
+    start: &Path,
+    name: &str,
+    options: ComponentOptions,
+) -> anyhow::Result<PathBuf> {
+    naming::validate_c_identifier(name)
+        .context(format!("\"{}\" cannot be used as a component name", name))?;
+    let root = project::find_project_root(start).context(format!(
+        "\"{}\" is not inside an ESP-IDF project (no CMakeLists.txt including project.cmake found)",
+        start.display()
+    ))?;
+
+    let dir = root.join(COMPONENTS_DIR).join(name);
+    if dir.exists() {
+        bail!("Component \"{}\" already exists", dir.display());
+    }
+
+    let language = project::detect_language(&root);
+    let source = format!("{}.{}", name, language.extension());
+    write(
+        &dir.join("CMakeLists.txt"),
+        &format!(
+            "idf_component_register(SRCS \"{}\"\n                       INCLUDE_DIRS \"include\")\n",
+            source
+        ),
+    )?;
+    write(
+        &dir.join("include").join(format!("{}.h", name)),
+        &header(name, language),
+    )?;
+    write(&dir.join(&source), &source_file(name))?;
+
+    if options.kconfig {
+        write(&dir.join("Kconfig"), &kconfig(name))?;
+    }
+    if options.unit_test {
+        write(
+            &dir.join("test").join("CMakeLists.txt"),
+            &format!(
+                "idf_component_register(SRC_DIRS \".\"\n                       INCLUDE_DIRS \".\"\n                       REQUIRES unity {})\n",
+                name
+            ),
+        )?;
+        write(
+            &dir.join("test")
+                .join(format!("test_{}.{}", name, language.extension())),
+            &unit_test(name),
+        )?;
+    }
+    Ok(dir)
+}
+
+/// Writes a file, creating its parent directories
+fn write(path: &Path, content: &str) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!(
+            "Failed to create directory \"{}\"",
+            parent.display()
+        ))?;
+    }
+    fs::write(path, content).context(format!("Cannot write \"{}\"", path.display()))
+}
+
+fn header(name: &str, language: SourceLanguage) -> String {
+    let declaration = format!(
+        "/// Initializes the {} component\nvoid {}_init(void);\n",
+        name, name
+    );
+    match language {
+        SourceLanguage::C => format!("#pragma once\n\n{}", declaration),
+        // Callable from C sources too
+        SourceLanguage::Cpp => format!(
+            "#pragma once\n\n#ifdef __cplusplus\nextern \"C\" {{\n#endif\n\n{}\n#ifdef __cplusplus\n}}\n#endif\n",
+            declaration
+        ),
+    }
+}
+
+fn source_file(name: &str) -> String {
+    format!(
+        "#include \"{name}.h\"\n\n#include \"esp_log.h\"\n\nstatic const char *TAG = \"{name}\";\n\nvoid {name}_init(void)\n{{\n    ESP_LOGI(TAG, \"Initialized\");\n}}\n",
+        name = name
+    )
+}
+
+fn kconfig(name: &str) -> String {
+    format!(
+        "menu \"{name}\"\n\n    config {upper}_ENABLED\n        bool \"Enable {name}\"\n        default y\n        help\n            Read in the code as CONFIG_{upper}_ENABLED.\n\nendmenu\n",
+        name = name,
+        upper = name.to_uppercase()
+    )
+}
+
+fn unit_test(name: &str) -> String {
+    format!(
+        "#include \"unity.h\"\n#include \"{name}.h\"\n\nTEST_CASE(\"{name} initializes\", \"[{name}]\")\n{{\n    {name}_init();\n}}\n",
+        name = name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_new_component() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        let options = ComponentOptions {
+            kconfig: true,
+            unit_test: true,
+        };
+
+        // Run from a subdirectory of the project
+        let component = new_component(&dir.path().join("main"), "sensor", options).unwrap();
+
+        assert_eq!(component, dir.path().join("components").join("sensor"));
+        let cmake = fs::read_to_string(component.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains("idf_component_register(SRCS \"sensor.c\""));
+        assert!(component.join("include").join("sensor.h").exists());
+        assert!(component.join("sensor.c").exists());
+        assert!(component.join("Kconfig").exists());
+        assert!(component.join("test").join("test_sensor.c").exists());
+    }
+
+    #[test]
+    fn test_new_component_refuses_existing() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        new_component(dir.path(), "sensor", ComponentOptions::default()).unwrap();
+
+        let error = new_component(dir.path(), "sensor", ComponentOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+    }
+
+    #[test]
+    fn test_new_component_outside_project() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(new_component(dir.path(), "sensor", ComponentOptions::default()).is_err());
+        assert!(!dir.path().join("components").exists());
+    }
+}
@@ -0,0 +1,758 @@
+//! User configuration, read from `config.toml` in the platform config
+//! directory (e.g. `~/.config/esp-create-project/config.toml` on Linux)
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::hygiene::HygieneFile;
+use crate::idf_component::ComponentMetadata;
+use crate::options::{
+    AssertionLevel, ClangFormatStyle, HeapDebugging, ProgrammingLanguage, RtosTuning,
+    ScaffoldOptions, SecurityFeatures,
+};
+use crate::partitions::PartitionLayout;
+use crate::template_cache::TemplateTtl;
+use crate::theme::Theme;
+
+/// Directory name used in the platform config and cache directories
+pub const APP_DIR: &str = "esp-create-project";
+
+/// Name of the configuration file
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Name of the file, in the state directory, with the last choices
+pub const LAST_CHOICES_FILE: &str = "last-choices.toml";
+
+/// The fields of a [`Profile`], other than `extends`, in the order of the prompts
+pub const PROFILE_FIELDS: &[&str] = &[
+    "language",
+    "board",
+    "target",
+    "use_git",
+    "starters",
+    "hygiene",
+    "clang_format",
+    "kconfig_example",
+    "assertions",
+    "heap_debugging",
+    "rtos",
+    "partitions",
+    "security",
+    "component_metadata",
+    "template_ref",
+    "template_url",
+];
+
+/// Files that don't make a directory non-empty with `--no-prompt-on-empty`,
+/// left there by file managers
+pub const DEFAULT_IGNORABLE_FILES: &[&str] = &[".DS_Store", "Thumbs.db", "desktop.ini"];
+
+/// Settings of the user configuration file, every field is optional
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// Whether to check once a day for a new release and suggest `self-update`
+    pub update_check: bool,
+    /// Profile used when `--profile` is not passed, e.g. the defaults set by
+    /// the first-run onboarding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// Theme of the prompts and status lines when `--theme` is not passed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+    /// How long a cached template is used without downloading it again when
+    /// `--template-ttl` is not passed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_ttl: Option<TemplateTtl>,
+    /// Answers the prompts propose when there are no last choices, e.g. set
+    /// by the first-run onboarding; unlike a profile, the prompts still ask
+    #[serde(skip_serializing_if = "LastChoices::is_empty")]
+    pub defaults: LastChoices,
+    /// Named presets selected with `--profile`, from the `[profile.<name>]` tables
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile: BTreeMap<String, Profile>,
+    /// Files that don't make the project directory non-empty with
+    /// `--no-prompt-on-empty`, [`DEFAULT_IGNORABLE_FILES`] if not set
+    #[serde(skip_serializing_if = "is_default_ignorable")]
+    pub ignorable_files: Vec<String>,
+    /// The file the configuration was read from, `None` for the defaults
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// The top-level settings written in the file, the others have their default
+    #[serde(skip)]
+    pub explicit: BTreeSet<String>,
+}
+
+impl Default for UserConfig {
+    fn default() -> Self {
+        Self {
+            update_check: true,
+            default_profile: None,
+            theme: None,
+            template_ttl: None,
+            defaults: LastChoices::default(),
+            profile: BTreeMap::new(),
+            ignorable_files: DEFAULT_IGNORABLE_FILES
+                .iter()
+                .map(|file| file.to_string())
+                .collect(),
+            source: None,
+            explicit: BTreeSet::new(),
+        }
+    }
+}
+
+/// Answers to the prompts shared by a team, every unset field is asked
+///
+/// Field names and values are the ones of an options file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Profile whose values are used for the fields not set in this one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<ProgrammingLanguage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_git: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub board: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assertions: Option<AssertionLevel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heap_debugging: Option<HeapDebugging>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kconfig_example: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clang_format: Option<ClangFormatStyle>,
+    /// Hygiene files pre-selected in the prompt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hygiene: Option<Vec<HygieneFile>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtos: Option<RtosTuning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partitions: Option<PartitionLayout>,
+    /// Secure boot and flash encryption stubs, only prompted for in advanced mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security: Option<SecurityFeatures>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component_metadata: Option<ComponentMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starters: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_url: Option<String>,
+}
+
+impl Profile {
+    /// The fields set in this profile and their values, as in the
+    /// configuration file
+    ///
+    /// # Returns
+    /// The values by field name, strings without quotes and the other values
+    /// as JSON
+    pub fn values(&self) -> BTreeMap<String, String> {
+        let fields = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            // Only plain values and structs, it cannot fail
+            _ => return BTreeMap::new(),
+        };
+        fields
+            .into_iter()
+            .filter(|(name, _)| name != "extends")
+            .map(|(name, value)| {
+                let value = match value {
+                    serde_json::Value::String(text) => text,
+                    value => value.to_string(),
+                };
+                (name, value)
+            })
+            .collect()
+    }
+
+    /// Fills the fields not set in this profile with the values of `parent`
+    pub(crate) fn inherit(self, parent: Profile) -> Profile {
+        Profile {
+            extends: parent.extends,
+            language: self.language.or(parent.language),
+            use_git: self.use_git.or(parent.use_git),
+            board: self.board.or(parent.board),
+            target: self.target.or(parent.target),
+            assertions: self.assertions.or(parent.assertions),
+            heap_debugging: self.heap_debugging.or(parent.heap_debugging),
+            kconfig_example: self.kconfig_example.or(parent.kconfig_example),
+            clang_format: self.clang_format.or(parent.clang_format),
+            hygiene: self.hygiene.or(parent.hygiene),
+            rtos: self.rtos.or(parent.rtos),
+            partitions: self.partitions.or(parent.partitions),
+            security: self.security.or(parent.security),
+            component_metadata: self.component_metadata.or(parent.component_metadata),
+            starters: self.starters.or(parent.starters),
+            template_ref: self.template_ref.or(parent.template_ref),
+            template_url: self.template_url.or(parent.template_url),
+        }
+    }
+}
+
+fn is_default_ignorable(files: &[String]) -> bool {
+    files
+        .iter()
+        .map(String::as_str)
+        .eq(DEFAULT_IGNORABLE_FILES.iter().copied())
+}
+
+impl UserConfig {
+    /// Parses the configuration from a TOML string
+    ///
+    /// # Arguments
+    /// * `content` - The TOML document to parse
+    ///
+    /// # Errors
+    /// If the TOML is malformed or has unknown or invalid settings
+    pub fn from_toml(content: &str) -> anyhow::Result<Self> {
+        let mut config: Self = toml::from_str(content).context("Invalid configuration")?;
+        let table: toml::Table = toml::from_str(content).context("Invalid configuration")?;
+        config.explicit = table
+            .into_iter()
+            .map(|(key, _)| key)
+            .filter(|key| key != "profile")
+            .collect();
+        Ok(config)
+    }
+
+    /// Reads the configuration from a file, the defaults are used if it doesn't exist
+    ///
+    /// # Arguments
+    /// * `path` - The path to the configuration file
+    ///
+    /// # Errors
+    /// If the file exists but cannot be read or is not a valid configuration
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .context(format!("Cannot read config file \"{}\"", path.display()))?;
+        let mut config = Self::from_toml(&content)
+            .context(format!("Cannot load config file \"{}\"", path.display()))?;
+        config.source = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Resolves a profile, following its `extends` chain
+    ///
+    /// # Arguments
+    /// * `name` - The name of the profile
+    ///
+    /// # Returns
+    /// The profile with the inherited values filled in
+    ///
+    /// # Errors
+    /// If the profile, or one it extends, doesn't exist, or the chain has a cycle
+    pub fn resolve_profile(&self, name: &str) -> anyhow::Result<Profile> {
+        self.resolve_profile_sources(name)
+            .map(|(profile, _)| profile)
+    }
+
+    /// Resolves a profile like [`UserConfig::resolve_profile`], and tells
+    /// which profile of the `extends` chain each field comes from
+    ///
+    /// # Arguments
+    /// * `name` - The name of the profile
+    ///
+    /// # Returns
+    /// The profile, and the name of the profile that set each of its fields
+    ///
+    /// # Errors
+    /// If the profile, or one it extends, doesn't exist, or the chain has a cycle
+    pub fn resolve_profile_sources(
+        &self,
+        name: &str,
+    ) -> anyhow::Result<(Profile, BTreeMap<String, String>)> {
+        let mut chain = vec![name];
+        let mut resolved = self.find_profile(name)?.clone();
+        let mut sources: BTreeMap<String, String> = resolved
+            .values()
+            .into_keys()
+            .map(|field| (field, name.to_string()))
+            .collect();
+        while let Some(parent) = resolved.extends.clone() {
+            if chain.contains(&parent.as_str()) {
+                bail!(
+                    "Profile \"{}\" extends itself: {} -> {}",
+                    name,
+                    chain.join(" -> "),
+                    parent
+                );
+            }
+            let (parent_name, parent) = self.profile.get_key_value(&parent).context(format!(
+                "Profile \"{}\" extends \"{}\", which doesn't exist",
+                chain.last().unwrap_or(&name),
+                parent
+            ))?;
+            chain.push(parent_name);
+            for field in parent.values().into_keys() {
+                sources.entry(field).or_insert_with(|| parent_name.clone());
+            }
+            resolved = resolved.inherit(parent.clone());
+        }
+        Ok((resolved, sources))
+    }
+
+    fn find_profile(&self, name: &str) -> anyhow::Result<&Profile> {
+        self.profile.get(name).with_context(|| {
+            let names: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+            if names.is_empty() {
+                format!("Unknown profile \"{}\", no profile is defined", name)
+            } else {
+                format!(
+                    "Unknown profile \"{}\", expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        })
+    }
+
+    /// The profile to apply
+    ///
+    /// # Arguments
+    /// * `flag` - The name passed to `--profile`, which wins over
+    ///   [`UserConfig::default_profile`]
+    pub fn profile_name<'a>(&'a self, flag: Option<&'a str>) -> Option<&'a str> {
+        flag.or(self.default_profile.as_deref())
+    }
+
+    /// Formats the configuration as TOML
+    ///
+    /// # Errors
+    /// If a value cannot be represented in TOML
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        toml::to_string(self).context("Cannot serialize the configuration")
+    }
+
+    /// Edits the configuration file in place, its comments and layout are
+    /// kept; the file is created if it doesn't exist
+    ///
+    /// # Arguments
+    /// * `path` - The configuration file, see [`config_path`]
+    /// * `edit` - Changes the document
+    ///
+    /// # Errors
+    /// If the file cannot be read or written, or is not a valid configuration
+    /// before or after the edit
+    pub fn edit_file(
+        path: &Path,
+        edit: impl FnOnce(&mut toml_edit::DocumentMut) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => {
+                return Err(anyhow::Error::new(error)
+                    .context(format!("Cannot read config file \"{}\"", path.display())))
+            }
+        };
+        let mut document: toml_edit::DocumentMut = content
+            .parse()
+            .context(format!("Cannot load config file \"{}\"", path.display()))?;
+        edit(&mut document)?;
+        let content = document.to_string();
+        Self::from_toml(&content).context("The edited configuration is invalid")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(format!(
+                "Cannot create the config directory \"{}\"",
+                parent.display()
+            ))?;
+        }
+        fs::write(path, content).context(format!("Cannot write config file \"{}\"", path.display()))
+    }
+
+    /// Reads the configuration file of the user, see [`config_path`]
+    ///
+    /// # Errors
+    /// If the file exists but cannot be read or is not a valid configuration
+    pub fn load() -> anyhow::Result<Self> {
+        match config_path() {
+            Some(path) => Self::from_file(&path),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Path of the user configuration file
+///
+/// # Returns
+/// The path, or `None` if the platform has no config directory
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR).join(CONFIG_FILE))
+}
+
+/// Directory where state between runs is kept (e.g. the last update check)
+///
+/// # Returns
+/// The directory, or `None` if the platform has no cache directory
+pub fn state_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join(APP_DIR))
+}
+
+/// Answers given for the last generated project, used as the defaults of
+/// the prompts of the next one
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LastChoices {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<ProgrammingLanguage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub board: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_git: Option<bool>,
+}
+
+impl LastChoices {
+    /// Whether no choice is remembered
+    pub fn is_empty(&self) -> bool {
+        *self == LastChoices::default()
+    }
+
+    /// Fills the choices not remembered with `defaults`, e.g. the
+    /// [`UserConfig::defaults`]; a board or a chip is one choice
+    pub fn or(self, defaults: &LastChoices) -> LastChoices {
+        let (board, target) = if self.board.is_some() || self.target.is_some() {
+            (self.board, self.target)
+        } else {
+            (defaults.board.clone(), defaults.target.clone())
+        };
+        LastChoices {
+            language: self.language.or(defaults.language),
+            board,
+            target,
+            use_git: self.use_git.or(defaults.use_git),
+        }
+    }
+
+    /// Replaces the choices answered at a prompt with their answers, the
+    /// others keep their last value: a setting from a flag, a profile or
+    /// `--yes` is not remembered; a board or a chip is one choice
+    ///
+    /// # Arguments
+    /// * `asked` - The fields asked at a prompt, see
+    ///   [`crate::explain::explain_profile`]
+    /// * `options` - The options the answers resolved to
+    pub fn answered(self, asked: &[&str], options: &ScaffoldOptions) -> LastChoices {
+        let was_asked = |field: &str| asked.contains(&field);
+        let (board, target) = if was_asked("board") || was_asked("target") {
+            (options.board.clone(), options.target.clone())
+        } else {
+            (self.board, self.target)
+        };
+        LastChoices {
+            language: if was_asked("language") {
+                Some(options.language)
+            } else {
+                self.language
+            },
+            board,
+            target,
+            use_git: if was_asked("use_git") {
+                Some(options.use_git)
+            } else {
+                self.use_git
+            },
+        }
+    }
+
+    /// Reads the last choices, see [`LAST_CHOICES_FILE`]
+    ///
+    /// # Returns
+    /// The choices, empty if there are none yet or the file is unreadable
+    pub fn load() -> Self {
+        state_dir()
+            .map(|dir| Self::from_file(&dir.join(LAST_CHOICES_FILE)))
+            .unwrap_or_default()
+    }
+
+    /// Reads the choices from a file, empty if it's missing or invalid
+    pub fn from_file(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| toml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the choices for the next run
+    ///
+    /// # Errors
+    /// If the file cannot be written
+    pub fn save(&self) -> anyhow::Result<()> {
+        match state_dir() {
+            Some(dir) => self.write(&dir.join(LAST_CHOICES_FILE)),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the choices to a file
+    ///
+    /// # Errors
+    /// If the file cannot be written
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Cannot create the state directory")?;
+        }
+        let content = toml::to_string(self).context("Cannot serialize the last choices")?;
+        fs::write(path, content).context(format!("Cannot write \"{}\"", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_toml() {
+        assert_eq!(UserConfig::from_toml("").unwrap(), UserConfig::default());
+        assert!(UserConfig::default().update_check);
+        assert!(
+            !UserConfig::from_toml("update_check = false")
+                .unwrap()
+                .update_check
+        );
+        assert!(UserConfig::from_toml("unknown = 1").is_err());
+        assert!(UserConfig::from_toml("update_check = \"no\"").is_err());
+        assert_eq!(
+            UserConfig::from_toml("theme = \"high-contrast\"")
+                .unwrap()
+                .theme,
+            Some(Theme::HighContrast)
+        );
+        assert_eq!(
+            UserConfig::from_toml("template_ttl = \"infinite\"")
+                .unwrap()
+                .template_ttl,
+            Some(TemplateTtl::Infinite)
+        );
+        assert!(UserConfig::from_toml("template_ttl = \"1y\"").is_err());
+        assert_eq!(
+            UserConfig::default().ignorable_files,
+            DEFAULT_IGNORABLE_FILES
+        );
+        assert_eq!(
+            UserConfig::from_toml("ignorable_files = [\".directory\"]")
+                .unwrap()
+                .ignorable_files,
+            [".directory"]
+        );
+        assert!(!UserConfig::default()
+            .to_toml()
+            .unwrap()
+            .contains("ignorable_files"));
+    }
+
+    #[test]
+    fn test_resolve_profile() {
+        let config = UserConfig::from_toml(
+            r#"
+[profile.base]
+language = "cpp17"
+use_git = true
+starters = ["nvs"]
+
+[profile.sensor-fw]
+extends = "base"
+starters = ["wifi", "mqtt"]
+clang_format = { indent_width = 2 }
+"#,
+        )
+        .unwrap();
+
+        let profile = config.resolve_profile("sensor-fw").unwrap();
+        assert_eq!(profile.language, Some(ProgrammingLanguage::Cpp17));
+        assert_eq!(profile.use_git, Some(true));
+        assert_eq!(
+            profile.starters,
+            Some(vec!["wifi".to_string(), "mqtt".to_string()])
+        );
+        assert_eq!(profile.clang_format.unwrap().indent_width, 2);
+        assert_eq!(profile.assertions, None);
+        assert!(config.resolve_profile("missing").is_err());
+
+        let (_, sources) = config.resolve_profile_sources("sensor-fw").unwrap();
+        let sources: Vec<(&str, &str)> = sources
+            .iter()
+            .map(|(field, profile)| (field.as_str(), profile.as_str()))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                ("clang_format", "sensor-fw"),
+                ("language", "base"),
+                ("starters", "sensor-fw"),
+                ("use_git", "base"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_profile_fields() {
+        let config = UserConfig::from_toml(
+            r#"
+update_check = false
+
+[profile.full]
+language = "c"
+board = "esp32-devkitc"
+target = "esp32"
+use_git = true
+starters = ["nvs"]
+hygiene = ["gitignore"]
+clang_format = {}
+kconfig_example = true
+assertions = "silent"
+heap_debugging = "disabled"
+rtos = {}
+partitions = { flash_size_mb = 4, scheme = "single_app" }
+security = {}
+component_metadata = {}
+template_ref = "v1.0"
+template_url = "https://example.com/template.zip"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.explicit,
+            BTreeSet::from(["update_check".to_string()])
+        );
+
+        let values = config.resolve_profile("full").unwrap().values();
+        let mut fields: Vec<&str> = values.keys().map(String::as_str).collect();
+        let mut expected = PROFILE_FIELDS.to_vec();
+        fields.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(fields, expected);
+        assert_eq!(values["language"], "c");
+        assert_eq!(values["starters"], r#"["nvs"]"#);
+    }
+
+    #[test]
+    fn test_resolve_profile_cycle() {
+        let config = UserConfig::from_toml(
+            "[profile.a]\nextends = \"b\"\n[profile.b]\nextends = \"a\"\n[profile.c]\nextends = \"d\"\n",
+        )
+        .unwrap();
+
+        let error = config.resolve_profile("a").unwrap_err().to_string();
+        assert!(error.contains("a -> b -> a"), "{}", error);
+        assert!(config.resolve_profile("c").is_err());
+    }
+
+    #[test]
+    fn test_config_missing_file_uses_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            UserConfig::from_file(&dir.path().join(CONFIG_FILE)).unwrap(),
+            UserConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_last_choices() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("state").join(LAST_CHOICES_FILE);
+        assert_eq!(LastChoices::from_file(&file), LastChoices::default());
+
+        let choices = LastChoices {
+            language: Some(ProgrammingLanguage::Cpp17),
+            board: None,
+            target: Some("esp32c3".into()),
+            use_git: Some(false),
+        };
+        choices.write(&file).unwrap();
+        assert_eq!(LastChoices::from_file(&file), choices);
+
+        fs::write(&file, "language = 3").unwrap();
+        assert_eq!(LastChoices::from_file(&file), LastChoices::default());
+
+        let defaults = LastChoices {
+            language: Some(ProgrammingLanguage::C),
+            board: Some("esp32-devkitc".into()),
+            target: None,
+            use_git: Some(true),
+        };
+        // The remembered chip wins over the default board
+        assert_eq!(
+            choices.clone().or(&defaults),
+            LastChoices {
+                use_git: Some(false),
+                ..choices.clone()
+            }
+        );
+        assert_eq!(LastChoices::default().or(&defaults), defaults);
+        assert!(LastChoices::default().is_empty());
+    }
+
+    #[test]
+    fn test_last_choices_answered() {
+        let last = LastChoices {
+            language: Some(ProgrammingLanguage::Cpp17),
+            board: Some("esp32-devkitc".into()),
+            target: None,
+            use_git: Some(false),
+        };
+        let mut options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        options.target = Some("esp32c3".into());
+        options.use_git = true;
+
+        // Nothing asked, e.g. with --yes: nothing changes
+        assert_eq!(last.clone().answered(&[], &options), last);
+        // Only the prompts answered are remembered
+        assert_eq!(
+            last.clone().answered(&["language", "target"], &options),
+            LastChoices {
+                language: Some(ProgrammingLanguage::C),
+                board: None,
+                target: Some("esp32c3".into()),
+                use_git: Some(false),
+            }
+        );
+    }
+
+    #[test]
+    fn test_edit_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config").join(CONFIG_FILE);
+        UserConfig::edit_file(&path, |document| {
+            document["update_check"] = toml_edit::value(false);
+            Ok(())
+        })
+        .unwrap();
+        assert!(!UserConfig::from_file(&path).unwrap().update_check);
+
+        fs::write(&path, "# Mine\ntheme = \"simple\" # no colors\n").unwrap();
+        UserConfig::edit_file(&path, |document| {
+            document["defaults"]["language"] = toml_edit::value("cpp17");
+            Ok(())
+        })
+        .unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# Mine\ntheme = \"simple\" # no colors\n"));
+        let config = UserConfig::from_toml(&content).unwrap();
+        assert_eq!(config.defaults.language, Some(ProgrammingLanguage::Cpp17));
+
+        // An invalid result is not written
+        let error = UserConfig::edit_file(&path, |document| {
+            document["unknown"] = toml_edit::value(1);
+            Ok(())
+        });
+        assert!(error.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+    }
+}
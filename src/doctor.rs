@@ -0,0 +1,709 @@
+//! `doctor`: checks the environment the tool itself needs (the template
+//! server, the cache directory, git and ESP-IDF), so a user can tell why
+//! scaffolding fails before filing an issue, and whether a board can be
+//! flashed from this machine (the serial port and what it needs to open it)
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::build_deps::{IdfEnv, IDF_HINT};
+use crate::download;
+use crate::http;
+use crate::platform;
+use crate::serial::{self, SerialDevice};
+use crate::template_cache::TemplateCache;
+
+/// Time given to the template server to answer
+pub const TEMPLATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// File written and removed to check that the cache directory is writable
+const PROBE_FILE: &str = ".doctor-probe";
+
+/// Groups that own the serial ports on Linux: `dialout` on Debian, Ubuntu and
+/// Fedora, `uucp` on Arch
+const SERIAL_GROUPS: &[&str] = &["dialout", "uucp"];
+
+/// Linux kernel modules of the USB-serial chips, see [`serial::identify`]
+const KERNEL_MODULES: &[(&str, &str)] = &[
+    ("Espressif USB-Serial/JTAG", "cdc_acm"),
+    ("Silicon Labs CP210x", "cp210x"),
+    ("WCH CH340", "ch341"),
+    ("WCH CH9102", "cdc_acm"),
+    ("FTDI", "ftdi_sio"),
+];
+
+/// Third-party USB-serial drivers of macOS and where they are installed,
+/// FTDI and recent CH34x chips work with the drivers of macOS
+const MACOS_DRIVERS: &[(&str, &[&str])] = &[
+    (
+        "Silicon Labs CP210x",
+        &[
+            "Applications/CP210xVCPDriver.app",
+            "Library/Extensions/SiLabsUSBDriver.kext",
+        ],
+    ),
+    (
+        "WCH CH34x",
+        &[
+            "Applications/CH34xVCPDriver.app",
+            "Library/Extensions/CH34xVCPDriver.kext",
+            "Library/Extensions/usbserial.kext",
+        ],
+    ),
+];
+
+/// Where to get the USB-serial drivers of macOS and Windows
+const DRIVER_HINT: &str = "plug in the board with a data cable; if no port shows up, install the driver of its USB-serial chip: \
+     CP210x from https://www.silabs.com/developers/usb-to-uart-bridge-vcp-drivers, \
+     CH34x from https://www.wch-ic.com/downloads/CH34XSER_MAC_ZIP.html (CH341SER_EXE_ZIP.html on Windows)";
+
+/// The outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// Not run, e.g. the network check with `--offline`
+    Skipped,
+}
+
+/// A line of the checklist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// What was found
+    pub detail: String,
+    /// How to fix a failed check
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: String) -> Self {
+        Check {
+            name,
+            status: CheckStatus::Pass,
+            detail,
+            hint: None,
+        }
+    }
+
+    fn fail(name: &'static str, detail: String, hint: &str) -> Self {
+        Check {
+            name,
+            status: CheckStatus::Fail,
+            detail,
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+/// Checks that the template can be reached: a HEAD request for a URL, or a
+/// GET of its first byte for a server that refuses HEAD, the file or
+/// directory for a local path
+///
+/// # Arguments
+/// * `url` - The template URL or path, see [`crate::options::ScaffoldOptions::template_url`]
+/// * `offline` - Skips the request of a URL
+/// * `timeout` - Time given to the server to answer
+pub fn check_template(url: &str, offline: bool, timeout: Duration) -> Check {
+    const NAME: &str = "template";
+    if let Some(path) = download::local_path(url) {
+        let path = Path::new(path);
+        return if path.is_file() || path.is_dir() {
+            Check::pass(NAME, format!("\"{}\" exists", url))
+        } else {
+            Check::fail(
+                NAME,
+                format!("\"{}\" not found", url),
+                "check the path passed to --template",
+            )
+        };
+    }
+    if offline {
+        return Check {
+            name: NAME,
+            status: CheckStatus::Skipped,
+            detail: format!("{} not checked with --offline", url),
+            hint: None,
+        };
+    }
+    let agent = match http::agent_with_timeout(url, timeout) {
+        Ok(agent) => agent,
+        Err(error) => {
+            return Check::fail(
+                NAME,
+                format!("{:#}", error),
+                "check the HTTPS_PROXY and NO_PROXY variables",
+            )
+        }
+    };
+    match probe_template(&agent, url).map_err(|error| *error) {
+        Ok(_) => Check::pass(NAME, format!("{} is reachable", url)),
+        Err(ureq::Error::Status(code, _)) => Check::fail(
+            NAME,
+            format!("{} answered with HTTP {}", url, code),
+            "check the template URL, or the ref passed to --template-ref",
+        ),
+        // The error starts with the URL
+        Err(error) => Check::fail(
+            NAME,
+            format!("cannot be reached: {}", error),
+            "check the network connection, and the HTTPS_PROXY variable behind a proxy",
+        ),
+    }
+}
+
+/// Requests a template URL without downloading it: a HEAD request, or a GET
+/// of its first byte for a server that refuses HEAD
+///
+/// # Arguments
+/// * `agent` - The agent the request is sent with, see [`http::agent_with_timeout`]
+/// * `url` - The URL of the template archive
+///
+/// # Errors
+/// If the server cannot be reached or answers with an error status
+pub fn probe_template(agent: &ureq::Agent, url: &str) -> Result<ureq::Response, Box<ureq::Error>> {
+    match agent.head(url).call() {
+        Err(ureq::Error::Status(405, _)) => agent.get(url).set("Range", "bytes=0-0").call(),
+        response => response,
+    }
+    .map_err(Box::new)
+}
+
+/// Checks that the template cache can be created and written
+///
+/// # Arguments
+/// * `dir` - The cache directory, `None` if the platform has none, see
+///   [`crate::template_cache::TemplateCache::default_dir`]
+pub fn check_cache_dir(dir: Option<&Path>) -> Check {
+    const NAME: &str = "cache directory";
+    let dir = match dir {
+        Some(dir) => dir,
+        None => {
+            return Check::fail(
+                NAME,
+                "this platform has no cache directory".to_string(),
+                "set HOME (or XDG_STATE_HOME) so templates can be cached",
+            )
+        }
+    };
+    let probe = dir.join(PROBE_FILE);
+    match fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, ""))
+        .and_then(|_| fs::remove_file(&probe))
+    {
+        Ok(()) => Check::pass(NAME, format!("\"{}\" is writable", dir.display())),
+        Err(error) => Check::fail(
+            NAME,
+            format!("\"{}\" is not writable: {}", dir.display(), error),
+            "fix the permissions of the directory, or remove it so it's created again",
+        ),
+    }
+}
+
+/// Checks that git is on the `PATH` and runs
+///
+/// # Arguments
+/// * `program` - The git executable, `git` outside tests
+pub fn check_git(program: &str) -> Check {
+    const NAME: &str = "git";
+    const HINT: &str = "install git (https://git-scm.com/downloads), or pass --no-git";
+    let path = match platform::find_executable(program) {
+        Some(path) => path,
+        None => return Check::fail(NAME, "not found on the PATH".to_string(), HINT),
+    };
+    match platform::command(program).arg("--version").output() {
+        Ok(output) if output.status.success() => Check::pass(
+            NAME,
+            format!(
+                "{} ({})",
+                String::from_utf8_lossy(&output.stdout).trim(),
+                path.display()
+            ),
+        ),
+        _ => Check::fail(NAME, format!("\"{}\" doesn't run", path.display()), HINT),
+    }
+}
+
+/// Checks that ESP-IDF is set up, see [`IdfEnv::detect`]
+///
+/// # Arguments
+/// * `idf_env` - The detected ESP-IDF environment
+pub fn check_idf(idf_env: &IdfEnv) -> Check {
+    const NAME: &str = "ESP-IDF";
+    match idf_env {
+        IdfEnv::Ready { idf_path, .. } => {
+            Check::pass(NAME, format!("IDF_PATH is \"{}\"", idf_path.display()))
+        }
+        // The export script is sourced for the commands that need it
+        IdfEnv::NeedsExport { idf_path, missing } => Check::pass(
+            NAME,
+            format!(
+                "found in \"{}\", {} not set, its export script is sourced when needed",
+                idf_path.display(),
+                missing.join(", ")
+            ),
+        ),
+        IdfEnv::NotFound => Check::fail(NAME, "IDF_PATH is not set".to_string(), IDF_HINT),
+    }
+}
+
+/// Checks that the user can open the serial ports on Linux: root, or a
+/// member of the group that owns them
+///
+/// # Arguments
+/// * `user` - The name of the user
+/// * `user_groups` - The groups of the user, from `id -Gn`
+/// * `system_groups` - The groups of the system, see [`group_names`]
+pub fn check_serial_group(user: &str, user_groups: &[&str], system_groups: &[&str]) -> Check {
+    const NAME: &str = "serial permissions";
+    if user == "root" {
+        return Check::pass(NAME, "root opens every serial port".to_string());
+    }
+    let groups: Vec<&str> = SERIAL_GROUPS
+        .iter()
+        .copied()
+        .filter(|group| system_groups.contains(group))
+        .collect();
+    if let Some(group) = groups.iter().find(|group| user_groups.contains(group)) {
+        return Check::pass(NAME, format!("{} is in the {} group", user, group));
+    }
+    match groups.first() {
+        Some(group) => Check::fail(
+            NAME,
+            format!(
+                "{} is not in the {} group, the serial ports cannot be opened",
+                user, group
+            ),
+            &format!(
+                "run `sudo usermod -aG {} {}`, then log out and back in",
+                group, user
+            ),
+        ),
+        None => Check {
+            name: NAME,
+            status: CheckStatus::Skipped,
+            detail: format!("no {} group on this system", SERIAL_GROUPS.join(" or ")),
+            hint: None,
+        },
+    }
+}
+
+/// The names of the groups of `/etc/group`
+///
+/// # Arguments
+/// * `etc_group` - The content of `/etc/group`
+pub fn group_names(etc_group: &str) -> Vec<&str> {
+    etc_group
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split(':').next())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Checks that an ESP board is on a serial port; a known USB-serial chip
+/// plugged in without a port means its driver isn't loaded
+///
+/// # Arguments
+/// * `ports` - The serial ports, see [`serial::list_ports`]
+/// * `usb_devices` - The USB devices plugged in, see [`serial::usb_devices`];
+///   empty outside Linux
+pub fn check_serial_port(
+    ports: anyhow::Result<Vec<SerialDevice>>,
+    usb_devices: &[(u16, u16)],
+) -> Check {
+    const NAME: &str = "serial port";
+    let ports = match ports {
+        Ok(ports) => ports,
+        Err(error) => {
+            return Check::fail(
+                NAME,
+                format!("{:#}", error),
+                "pass the port of the board to flash and monitor with --port",
+            )
+        }
+    };
+    let boards: Vec<String> = ports
+        .iter()
+        .filter(|port| port.is_esp_board())
+        .map(|port| format!("{} ({})", port.name, port.description()))
+        .collect();
+    if !boards.is_empty() {
+        return Check::pass(NAME, boards.join(", "));
+    }
+    let unbound = usb_devices
+        .iter()
+        .find_map(|&(vid, pid)| serial::identify(vid, pid).map(|chip| (chip, vid, pid)));
+    if let Some((chip, vid, pid)) = unbound {
+        let module = KERNEL_MODULES
+            .iter()
+            .find(|(known, _)| *known == chip)
+            .map_or("usbserial", |(_, module)| *module);
+        let mut hint = format!(
+            "load its driver with `sudo modprobe {}`, it's in the extra modules package of some distributions (e.g. linux-modules-extra)",
+            module
+        );
+        if module == "ch341" {
+            hint.push_str("; on Ubuntu, brltty takes CH340 ports: `sudo apt remove brltty`");
+        }
+        return Check::fail(
+            NAME,
+            format!(
+                "{} ({:04x}:{:04x}) plugged in, but it has no serial port",
+                chip, vid, pid
+            ),
+            &hint,
+        );
+    }
+    Check {
+        name: NAME,
+        status: CheckStatus::Skipped,
+        detail: format!(
+            "no ESP board plugged in, {} other serial port(s)",
+            ports.len()
+        ),
+        hint: None,
+    }
+}
+
+/// The USB-serial drivers of [`MACOS_DRIVERS`] installed on macOS
+///
+/// # Arguments
+/// * `root` - The root of the file system, `/` outside tests
+pub fn macos_drivers(root: &Path) -> Vec<&'static str> {
+    MACOS_DRIVERS
+        .iter()
+        .filter(|(_, paths)| paths.iter().any(|path| root.join(path).exists()))
+        .map(|(chip, _)| *chip)
+        .collect()
+}
+
+/// Checks that macOS has a driver for the USB-serial chip of the board: a
+/// third-party driver installed, or a `/dev/cu.*` USB device
+///
+/// # Arguments
+/// * `drivers` - The installed drivers, see [`macos_drivers`]
+/// * `ports` - The serial ports, see [`serial::list_ports`]
+pub fn check_macos_drivers(drivers: &[&str], ports: &[SerialDevice]) -> Check {
+    const NAME: &str = "USB-serial drivers";
+    let devices: Vec<&str> = ports
+        .iter()
+        .filter(|port| port.name.starts_with("/dev/cu.") && port.usb_id.is_some())
+        .map(|port| port.name.as_str())
+        .collect();
+    let mut found = Vec::new();
+    if !drivers.is_empty() {
+        found.push(format!("{} installed", drivers.join(", ")));
+    }
+    if !devices.is_empty() {
+        found.push(devices.join(", "));
+    }
+    if found.is_empty() {
+        return Check {
+            name: NAME,
+            status: CheckStatus::Skipped,
+            detail: "no USB-serial driver installed, no /dev/cu.* USB device".to_string(),
+            hint: Some(DRIVER_HINT.to_string()),
+        };
+    }
+    Check::pass(NAME, found.join("; "))
+}
+
+/// Checks the COM ports Windows lists in the registry
+///
+/// # Arguments
+/// * `ports` - The COM ports and their devices, see [`serial::registry_ports`]
+pub fn check_com_ports(ports: anyhow::Result<Vec<(String, String)>>) -> Check {
+    const NAME: &str = "COM ports";
+    match ports {
+        Ok(ports) if ports.is_empty() => Check {
+            name: NAME,
+            status: CheckStatus::Skipped,
+            detail: "no COM port in the registry".to_string(),
+            hint: Some(DRIVER_HINT.to_string()),
+        },
+        Ok(ports) => Check::pass(
+            NAME,
+            ports
+                .iter()
+                .map(|(port, device)| format!("{} ({})", port, device))
+                .collect::<Vec<String>>()
+                .join(", "),
+        ),
+        Err(error) => Check::fail(
+            NAME,
+            format!("{:#}", error),
+            "open the Device Manager and look for the board under \"Ports (COM & LPT)\"",
+        ),
+    }
+}
+
+/// The output of a command, trimmed; `None` if it cannot be run or fails
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = platform::command(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The checks that a board can be flashed from this machine: the
+/// permissions (Linux), drivers (macOS) or COM ports (Windows), then the
+/// serial port, found by [`serial::list_ports`] like `flash` and `monitor`
+pub fn check_serial() -> Vec<Check> {
+    let mut checks = Vec::new();
+    let ports = serial::list_ports();
+    let mut usb_devices = Vec::new();
+    if cfg!(target_os = "linux") {
+        let etc_group = fs::read_to_string("/etc/group").unwrap_or_default();
+        checks.push(
+            match (
+                command_output("id", &["-un"]),
+                command_output("id", &["-Gn"]),
+            ) {
+                (Some(user), Some(groups)) => check_serial_group(
+                    &user,
+                    &groups.split_whitespace().collect::<Vec<&str>>(),
+                    &group_names(&etc_group),
+                ),
+                _ => Check {
+                    name: "serial permissions",
+                    status: CheckStatus::Skipped,
+                    detail: "the groups of the user cannot be read with `id`".to_string(),
+                    hint: None,
+                },
+            },
+        );
+        usb_devices = serial::usb_devices(Path::new(serial::SYSFS_USB_DEVICES));
+    } else if cfg!(target_os = "macos") {
+        let listed = ports.as_deref().unwrap_or_default();
+        checks.push(check_macos_drivers(&macos_drivers(Path::new("/")), listed));
+    } else if cfg!(windows) {
+        checks.push(check_com_ports(serial::registry_ports()));
+    }
+    checks.push(check_serial_port(ports, &usb_devices));
+    checks
+}
+
+/// Runs every check, in the order they are printed
+///
+/// # Arguments
+/// * `template_url` - The template URL or path
+/// * `offline` - Skips the network check
+pub fn run(template_url: &str, offline: bool) -> Vec<Check> {
+    let mut checks = vec![
+        check_template(template_url, offline, TEMPLATE_TIMEOUT),
+        check_cache_dir(TemplateCache::default_dir().as_deref()),
+        check_git("git"),
+        check_idf(&IdfEnv::detect()),
+    ];
+    checks.extend(check_serial());
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_check_template() {
+        let url = crate::test_fixtures::serve(b"PK".to_vec(), None);
+        let check = check_template(&url, false, TEMPLATE_TIMEOUT);
+        assert_eq!(check.status, CheckStatus::Pass, "{:?}", check);
+
+        // Nothing listens on port 1
+        let check = check_template(
+            "http://127.0.0.1:1/template.zip",
+            false,
+            Duration::from_secs(2),
+        );
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.hint.is_some());
+
+        let check = check_template("http://127.0.0.1:1/template.zip", true, TEMPLATE_TIMEOUT);
+        assert_eq!(check.status, CheckStatus::Skipped);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template.zip");
+        let check = check_template(&path.to_string_lossy(), true, TEMPLATE_TIMEOUT);
+        assert_eq!(check.status, CheckStatus::Fail);
+        fs::write(&path, "PK").unwrap();
+        let check = check_template(&path.to_string_lossy(), true, TEMPLATE_TIMEOUT);
+        assert_eq!(check.status, CheckStatus::Pass);
+        let url = format!("file://{}", path.display());
+        let check = check_template(&url, true, TEMPLATE_TIMEOUT);
+        assert_eq!(check.status, CheckStatus::Pass, "{:?}", check);
+        // An extracted template
+        let check = check_template(&dir.path().to_string_lossy(), true, TEMPLATE_TIMEOUT);
+        assert_eq!(check.status, CheckStatus::Pass, "{:?}", check);
+    }
+
+    #[test]
+    fn test_check_template_without_head() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/template.zip", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap_or(0);
+                let response: &[u8] = if request[..read].starts_with(b"HEAD ") {
+                    b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    b"HTTP/1.1 206 Partial Content\r\nContent-Length: 1\r\nConnection: close\r\n\r\nP"
+                };
+                let _ = stream.write_all(response);
+            }
+        });
+        let check = check_template(&url, false, TEMPLATE_TIMEOUT);
+        assert_eq!(check.status, CheckStatus::Pass, "{:?}", check);
+    }
+
+    #[test]
+    fn test_check_cache_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache").join("templates");
+        assert_eq!(check_cache_dir(Some(&cache)).status, CheckStatus::Pass);
+        assert!(!cache.join(PROBE_FILE).exists());
+
+        // A file where the directory should be
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        assert_eq!(
+            check_cache_dir(Some(&file.join("templates"))).status,
+            CheckStatus::Fail
+        );
+        assert_eq!(check_cache_dir(None).status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_check_git_and_idf() {
+        let check = check_git("esp-create-project-missing-git");
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.hint.unwrap().contains("--no-git"));
+
+        let ready = IdfEnv::Ready {
+            idf_path: PathBuf::from("/opt/esp-idf"),
+            python_env: PathBuf::from("/opt/python_env"),
+            idf_py: PathBuf::from("/opt/esp-idf/tools/idf.py"),
+        };
+        assert_eq!(check_idf(&ready).status, CheckStatus::Pass);
+        let needs_export = IdfEnv::NeedsExport {
+            idf_path: PathBuf::from("/opt/esp-idf"),
+            missing: vec!["IDF_PYTHON_ENV_PATH"],
+        };
+        let check = check_idf(&needs_export);
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert!(check.detail.contains("IDF_PYTHON_ENV_PATH"));
+        assert_eq!(check_idf(&IdfEnv::NotFound).status, CheckStatus::Fail);
+    }
+
+    fn device(name: &str, usb_id: Option<(u16, u16)>) -> SerialDevice {
+        SerialDevice {
+            name: name.into(),
+            usb_id,
+            product: None,
+            chip: usb_id.and_then(|(vid, pid)| serial::identify(vid, pid)),
+        }
+    }
+
+    #[test]
+    fn test_check_serial_group() {
+        let system = group_names("# groups\nroot:x:0:\ndialout:x:20:bob\nplugdev:x:46:\n");
+        assert_eq!(system, ["root", "dialout", "plugdev"]);
+        assert_eq!(
+            check_serial_group("bob", &["bob", "dialout"], &system).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            check_serial_group("root", &["root"], &system).status,
+            CheckStatus::Pass
+        );
+        let check = check_serial_group("alice", &["alice", "plugdev"], &system);
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert_eq!(
+            check.hint.unwrap(),
+            "run `sudo usermod -aG dialout alice`, then log out and back in"
+        );
+        // Arch
+        let check = check_serial_group("alice", &["alice"], &["root", "uucp"]);
+        assert!(check.hint.unwrap().contains("-aG uucp alice"));
+        assert_eq!(
+            check_serial_group("alice", &["alice"], &["root"]).status,
+            CheckStatus::Skipped
+        );
+    }
+
+    #[test]
+    fn test_check_serial_port() {
+        let cp210x = device("/dev/ttyUSB0", Some((0x10c4, 0xea60)));
+        let serial = device("/dev/ttyS0", None);
+        let check = check_serial_port(Ok(vec![serial.clone(), cp210x]), &[(0x10c4, 0xea60)]);
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert_eq!(
+            check.detail,
+            "/dev/ttyUSB0 (Silicon Labs CP210x (10c4:ea60))"
+        );
+
+        // A CH340 without a driver
+        let check = check_serial_port(
+            Ok(vec![serial.clone()]),
+            &[(0x1d6b, 0x0002), (0x1a86, 0x7523)],
+        );
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.detail.starts_with("WCH CH340 (1a86:7523)"));
+        let hint = check.hint.unwrap();
+        assert!(hint.contains("`sudo modprobe ch341`"));
+        assert!(hint.contains("brltty"));
+
+        let check = check_serial_port(Ok(vec![serial]), &[(0x1d6b, 0x0002)]);
+        assert_eq!(check.status, CheckStatus::Skipped);
+        assert_eq!(
+            check.detail,
+            "no ESP board plugged in, 1 other serial port(s)"
+        );
+        let check = check_serial_port(Err(anyhow::anyhow!("Cannot list the serial ports")), &[]);
+        assert_eq!(check.status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_check_macos_drivers_and_com_ports() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(macos_drivers(root.path()).is_empty());
+        fs::create_dir_all(root.path().join("Applications/CP210xVCPDriver.app")).unwrap();
+        let drivers = macos_drivers(root.path());
+        assert_eq!(drivers, ["Silicon Labs CP210x"]);
+
+        let ports = [
+            device("/dev/cu.usbserial-0001", Some((0x10c4, 0xea60))),
+            device("/dev/tty.usbserial-0001", Some((0x10c4, 0xea60))),
+            device("/dev/cu.Bluetooth-Incoming-Port", None),
+        ];
+        let check = check_macos_drivers(&drivers, &ports);
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert_eq!(
+            check.detail,
+            "Silicon Labs CP210x installed; /dev/cu.usbserial-0001"
+        );
+        let check = check_macos_drivers(&[], &ports[2..]);
+        assert_eq!(check.status, CheckStatus::Skipped);
+        assert!(check.hint.unwrap().contains("silabs.com"));
+
+        let check = check_com_ports(Ok(vec![(
+            "COM3".to_string(),
+            "\\Device\\Silabser0".to_string(),
+        )]));
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert_eq!(check.detail, "COM3 (\\Device\\Silabser0)");
+        assert_eq!(check_com_ports(Ok(vec![])).status, CheckStatus::Skipped);
+        assert_eq!(
+            check_com_ports(Err(anyhow::anyhow!("Cannot run reg query"))).status,
+            CheckStatus::Fail
+        );
+    }
+}
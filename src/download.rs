@@ -0,0 +1,356 @@
+use anyhow::Context;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::{CancellationToken, EspCreateError, Result};
+use crate::http;
+use crate::partitions;
+use crate::self_update::HashingReader;
+use crate::status;
+use crate::symbols::Symbol;
+
+/// Number of attempts to download the template before giving up
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// Size of the chunks read from the network, the token is checked between them
+const DOWNLOAD_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Default limit of the template size, downloaded and extracted; templates
+/// are a few KB, anything near this is a wrong URL or a zip bomb
+pub const DEFAULT_MAX_TEMPLATE_SIZE: u64 = 200 * 1024 * 1024;
+
+/// The template, or what it extracts to, is larger than the limit
+///
+/// Downloading it again doesn't help, so it's not retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateTooLarge {
+    /// The size in bytes, `None` if the download was stopped at the limit
+    pub size: Option<u64>,
+    /// Whether the size is the total of the extracted files
+    pub extracted: bool,
+    /// The limit in bytes
+    pub limit: u64,
+}
+
+impl fmt::Display for TemplateTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.size, self.extracted) {
+            (Some(size), true) => write!(f, "The template extracts to {}", format_size(size))?,
+            (Some(size), false) => write!(f, "The template is {}", format_size(size))?,
+            (None, _) => write!(f, "The template is")?,
+        }
+        write!(
+            f,
+            ", larger than the {} limit (raise it with --max-template-size)",
+            format_size(self.limit)
+        )
+    }
+}
+
+impl std::error::Error for TemplateTooLarge {}
+
+/// Formats a size in bytes as KB, MB or GB with one decimal
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB"];
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    let size = format!("{:.1}", size);
+    format!("{}{}", size.trim_end_matches(".0"), UNITS[unit])
+}
+
+/// Parses the `--max-template-size` argument, e.g. `500M` or `1024K`
+///
+/// # Returns
+/// The size in bytes
+///
+/// # Errors
+/// If the size cannot be parsed or is zero
+pub fn parse_max_size(size: &str) -> std::result::Result<u64, String> {
+    match partitions::parse_size(size) {
+        Some(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(format!(
+            "invalid size \"{}\", expected e.g. 500M or 1024K",
+            size
+        )),
+    }
+}
+
+/// Downloads the template to the temp file, retrying on failures
+///
+/// A local path (or `file://` URL) is copied instead, e.g. an archive
+/// written by `templatize`.
+///
+/// A template larger than `max_size` is refused: from its `Content-Length`
+/// when the server sends one, otherwise once that much was downloaded.
+///
+/// # Arguments
+/// * `url` - The URL or path of the template
+/// * `tmp_file` - The file to write the template to
+/// * `max_size` - The largest template accepted, in bytes
+/// * `token` - Token checked before each attempt and while downloading
+///
+/// # Returns
+/// The SHA-256 of the template, computed while it's written
+///
+/// # Errors
+/// If the download was cancelled, the template is too large (see
+/// [`TemplateTooLarge`]) or every attempt failed
+pub fn download_template(
+    url: &str,
+    tmp_file: &mut File,
+    max_size: u64,
+    token: &CancellationToken,
+) -> Result<String> {
+    if let Some(path) = local_path(url) {
+        token.check()?;
+        if let Some(size) = fs_size(path).filter(|size| *size > max_size) {
+            return Err(too_large(Some(size), max_size).into());
+        }
+        let mut file =
+            File::open(path).context(format!("Cannot open the template \"{}\"", path))?;
+        // Still limited, a device (e.g. /dev/zero) has no size
+        let error = format!("Cannot read the template \"{}\"", path);
+        return copy_template(&mut file, tmp_file, max_size, &error, token);
+    }
+    let mut attempt = 1;
+    loop {
+        token.check()?;
+        tmp_file
+            .set_len(0)
+            .and_then(|_| tmp_file.seek(SeekFrom::Start(0)))
+            .context("Cannot reset the temp file")?;
+
+        match try_download_template(url, tmp_file, max_size, token) {
+            Ok(sha256) => return Ok(sha256),
+            Err(EspCreateError::Other(e))
+                if attempt < DOWNLOAD_ATTEMPTS && !e.is::<TemplateTooLarge>() =>
+            {
+                attempt += 1;
+                thread::sleep(Duration::from_millis(500));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The path of a template that is not downloaded, `None` for an HTTP(S) URL
+pub fn local_path(url: &str) -> Option<&str> {
+    match url.strip_prefix("file://") {
+        Some(path) => Some(path),
+        None if !url.contains("://") => Some(url),
+        None => None,
+    }
+}
+
+/// The error of a template that has to be downloaded with `--offline`
+///
+/// # Arguments
+/// * `url` - The URL of the template
+pub fn offline_error(url: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "The template {} is downloaded from the network and is not cached, it cannot be used with --offline",
+        url
+    )
+}
+
+/// The size of a local file, `None` if it has none (a device) or cannot be read
+fn fs_size(path: &str) -> Option<u64> {
+    std::fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+fn too_large(size: Option<u64>, limit: u64) -> anyhow::Error {
+    anyhow::Error::new(TemplateTooLarge {
+        size,
+        extracted: false,
+        limit,
+    })
+}
+
+/// Downloads the template to a temporary file, for `--record-full` or the
+/// projects of a `batch`; the generation reads it instead of downloading it
+/// again, see [`crate::options::ScaffoldOptions::template_archive`]
+///
+/// # Arguments
+/// * `url` - The URL or path of the template
+/// * `max_size` - The largest template accepted, in bytes
+/// * `token` - Token checked while downloading
+///
+/// # Errors
+/// If the template cannot be downloaded, or the download is cancelled
+pub fn download_template_file(
+    url: &str,
+    max_size: u64,
+    token: &CancellationToken,
+) -> anyhow::Result<tempfile::NamedTempFile> {
+    let mut file = tempfile::NamedTempFile::new().context("Cannot create a temp file")?;
+    status::start(Symbol::Download, "Downloading template");
+    if let Err(error) = download_template(url, file.as_file_mut(), max_size, token) {
+        status::clear();
+        return Err(error.into());
+    }
+    status::finish(Symbol::Done, "Template downloaded");
+    Ok(file)
+}
+
+/// Performs a single download attempt, see [`download_template`]
+fn try_download_template(
+    url: &str,
+    tmp_file: &mut File,
+    max_size: u64,
+    token: &CancellationToken,
+) -> Result<String> {
+    let response = http::agent(url)?
+        .get(url)
+        .call()
+        .context("Cannot download the template")?;
+    if let Some(length) = response
+        .header("Content-Length")
+        .and_then(|length| length.parse::<u64>().ok())
+        .filter(|length| *length > max_size)
+    {
+        return Err(too_large(Some(length), max_size).into());
+    }
+    copy_template(
+        &mut response.into_reader(),
+        tmp_file,
+        max_size,
+        "Cannot download the template",
+        token,
+    )
+}
+
+/// Copies the template to the temp file in chunks, hashing it and checking
+/// the token between them
+///
+/// # Returns
+/// The SHA-256 of the template
+///
+/// # Arguments
+/// * `read_error` - The context of an error reading the template
+///
+/// # Errors
+/// If the copy was cancelled, more than `max_size` bytes were read (the
+/// `Content-Length` may be missing or wrong) or a chunk cannot be read or
+/// written
+fn copy_template(
+    reader: &mut impl Read,
+    tmp_file: &mut File,
+    max_size: u64,
+    read_error: &str,
+    token: &CancellationToken,
+) -> Result<String> {
+    let mut reader = HashingReader::new(reader);
+    let mut buffer = vec![0; DOWNLOAD_BUFFER_SIZE];
+    let mut copied = 0;
+    loop {
+        token.check()?;
+        let read = reader.read(&mut buffer).context(read_error.to_string())?;
+        if read == 0 {
+            return Ok(reader.finish());
+        }
+        copied += read as u64;
+        if copied > max_size {
+            return Err(too_large(None, max_size).into());
+        }
+        tmp_file
+            .write_all(&buffer[..read])
+            .context("Cannot copy the template to temp file")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::{extract_zip, ExtractOptions};
+    use crate::templates;
+    use std::path::Path;
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_download_and_unzip_file() {
+        let mut tmp_file = tempfile::tempfile().unwrap();
+        let token = CancellationToken::new();
+        let download_res = download_template(
+            templates::TEMPLATE_FILE,
+            &mut tmp_file,
+            DEFAULT_MAX_TEMPLATE_SIZE,
+            &token,
+        );
+        assert!(download_res.is_ok());
+
+        let mut zip = ZipArchive::new(tmp_file).unwrap();
+        let extract_res = extract_zip(
+            "test",
+            &mut zip,
+            Path::new("esp-idf-template-master/"),
+            ExtractOptions::default(),
+            &token,
+        );
+        assert!(extract_res.is_ok());
+    }
+
+    #[test]
+    fn test_download_from_fixture_server() {
+        let body = vec![7; 200 * 1024];
+        let url = crate::test_fixtures::serve(body.clone(), None);
+        let mut tmp_file = tempfile::tempfile().unwrap();
+
+        let sha256 = download_template(
+            &url,
+            &mut tmp_file,
+            DEFAULT_MAX_TEMPLATE_SIZE,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        let mut downloaded = Vec::new();
+        tmp_file.seek(SeekFrom::Start(0)).unwrap();
+        tmp_file.read_to_end(&mut downloaded).unwrap();
+        assert_eq!(downloaded, body);
+        assert_eq!(sha256, crate::self_update::sha256_hex(&body));
+    }
+
+    #[test]
+    fn test_download_size_limit() {
+        let token = CancellationToken::new();
+        let url = crate::test_fixtures::serve(vec![7; 200 * 1024], None);
+        let error =
+            match download_template(&url, &mut tempfile::tempfile().unwrap(), 100 * 1024, &token) {
+                Err(EspCreateError::Other(error)) => error,
+                other => panic!("{:?}", other),
+            };
+        assert_eq!(
+            error.downcast_ref::<TemplateTooLarge>(),
+            Some(&TemplateTooLarge {
+                size: Some(200 * 1024),
+                extracted: false,
+                limit: 100 * 1024
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "The template is 200KB, larger than the 100KB limit (raise it with --max-template-size)"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template.zip");
+        std::fs::write(&path, vec![7; 2048]).unwrap();
+        let path = path.to_str().unwrap();
+        assert!(download_template(path, &mut tempfile::tempfile().unwrap(), 1024, &token).is_err());
+        assert!(download_template(path, &mut tempfile::tempfile().unwrap(), 2048, &token).is_ok());
+
+        assert_eq!(parse_max_size("500M"), Ok(500 * 1024 * 1024));
+        assert!(parse_max_size("0").is_err());
+        assert_eq!(format_size(1536 * 1024), "1.5MB");
+    }
+}
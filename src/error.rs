@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Errors returned by the library API
+#[derive(Debug, thiserror::Error)]
+pub enum EspCreateError {
+    /// The generation was cancelled through its [`CancellationToken`]
+    #[error("Generation cancelled")]
+    Cancelled,
+    /// Any other failure, with its context chain
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Result type of the library API
+pub type Result<T> = std::result::Result<T, EspCreateError>;
+
+/// Shared flag used to cancel a running generation from another thread
+/// (a Ctrl+C handler, a GUI button...)
+///
+/// Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests the cancellation, the generation stops at its next check
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Checks if the cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Returns [`EspCreateError::Cancelled`] if the cancellation was requested
+    ///
+    /// # Errors
+    /// If the cancellation was requested
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(EspCreateError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Formatting of the errors that end the program: the error, then each of
+//! its causes (the `.context()` chain) as an indented bullet, wrapped to the
+//! width of the terminal
+
+use crate::symbols::Symbol;
+
+/// Indentation of the causes, their bullet included
+const CAUSE_INDENT: usize = 4;
+
+/// Formats an error and the chain of its causes
+///
+/// ```text
+/// Error: Cannot load options file "blink.json"
+///   - Invalid project options
+///   - missing field `language` at line 1 column 26
+/// ```
+///
+/// # Arguments
+/// * `error` - The error
+/// * `width` - Columns the lines are wrapped to, no wrapping if `None`;
+///   words longer than a line (paths, URLs) are never split
+pub fn format_error(error: &anyhow::Error, width: Option<usize>) -> String {
+    format_chain(error, width, &Symbol::Cause.to_string())
+}
+
+/// [`format_error`] with the bullet of the causes
+fn format_chain(error: &anyhow::Error, width: Option<usize>, bullet: &str) -> String {
+    let mut text = String::new();
+    let mut chain = error.chain();
+    if let Some(error) = chain.next() {
+        text.push_str(&wrap(&format!("Error: {}", error), width, 0, 2));
+    }
+    let bullet = format!("  {} ", bullet);
+    for cause in chain {
+        let line = wrap(&cause.to_string(), width, CAUSE_INDENT, CAUSE_INDENT);
+        text.push_str(&bullet);
+        // An empty cause is only its new line, shorter than the indent
+        text.push_str(line.get(CAUSE_INDENT..).unwrap_or("\n"));
+    }
+    text
+}
+
+/// Wraps text at spaces, every line ends with a new line
+///
+/// # Arguments
+/// * `text` - The text, its own new lines are kept
+/// * `width` - Columns of a line, no wrapping if `None`
+/// * `first_indent` - Spaces before the first line
+/// * `indent` - Spaces before the next lines
+fn wrap(text: &str, width: Option<usize>, first_indent: usize, indent: usize) -> String {
+    let mut wrapped = String::new();
+    let mut line = " ".repeat(first_indent);
+    let mut line_width = first_indent;
+    let mut empty = true;
+    for (i, paragraph) in text.lines().enumerate() {
+        if i > 0 {
+            wrapped.push_str(line.trim_end());
+            wrapped.push('\n');
+            line = " ".repeat(indent);
+            line_width = indent;
+            empty = true;
+        }
+        for word in paragraph.split(' ').filter(|word| !word.is_empty()) {
+            let word_width = console::measure_text_width(word);
+            let fits = width.is_none_or(|width| line_width + 1 + word_width <= width);
+            if !empty && !fits {
+                wrapped.push_str(&line);
+                wrapped.push('\n');
+                line = " ".repeat(indent);
+                line_width = indent;
+                empty = true;
+            }
+            if !empty {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(word);
+            line_width += word_width;
+            empty = false;
+        }
+    }
+    wrapped.push_str(line.trim_end());
+    wrapped.push('\n');
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("Cannot read the file of the project", Some(16), 0, 2),
+            "Cannot read the\n  file of the\n  project\n"
+        );
+        assert_eq!(
+            wrap("see /a/very/long/path/to/a/file now", Some(10), 0, 0),
+            "see\n/a/very/long/path/to/a/file\nnow\n"
+        );
+        assert_eq!(wrap("two\nlines", None, 0, 2), "two\n  lines\n");
+        assert_eq!(
+            wrap("not wrapped at all", None, 2, 2),
+            "  not wrapped at all\n"
+        );
+    }
+
+    #[test]
+    fn test_format_error() {
+        let error = Err::<(), _>(anyhow::anyhow!("missing field `language`"))
+            .context("Invalid project options")
+            .context("Cannot load options file \"blink.json\"")
+            .unwrap_err();
+
+        assert_eq!(
+            format_chain(&error, None, "-"),
+            "Error: Cannot load options file \"blink.json\"\n  - Invalid project options\n  - missing field `language`\n"
+        );
+        assert_eq!(
+            format_chain(&error, Some(24), "-"),
+            "Error: Cannot load\n  options file\n  \"blink.json\"\n  - Invalid project\n    options\n  - missing field\n    `language`\n"
+        );
+
+        let error = Err::<(), _>(anyhow::anyhow!(""))
+            .context("Cannot run git")
+            .unwrap_err();
+        assert_eq!(
+            format_chain(&error, Some(24), "-"),
+            "Error: Cannot run git\n  - \n"
+        );
+    }
+}
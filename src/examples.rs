@@ -0,0 +1,312 @@
+//! The example projects of ESP-IDF, listed from the `examples` tree of the
+//! ESP-IDF repository and cached between runs
+
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::http;
+
+/// GitHub API endpoint with every file under `examples/` of ESP-IDF master
+const EXAMPLES_TREE_URL: &str =
+    "https://api.github.com/repos/espressif/esp-idf/git/trees/master:examples?recursive=1";
+
+/// Name of the file, in the state directory, with the cached listing
+pub const CACHE_FILE: &str = "examples.txt";
+
+/// Age after which the cached listing is fetched again
+pub const CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Examples listed when GitHub cannot be reached and nothing is cached
+pub const CURATED_EXAMPLES: &[&str] = &[
+    "bluetooth/bluedroid/ble/gatt_client",
+    "bluetooth/bluedroid/ble/gatt_server",
+    "bluetooth/nimble/blecent",
+    "bluetooth/nimble/bleprph",
+    "get-started/blink",
+    "get-started/hello_world",
+    "peripherals/adc/oneshot_read",
+    "peripherals/gpio/generic_gpio",
+    "peripherals/i2c/i2c_basic",
+    "peripherals/ledc/ledc_basic",
+    "peripherals/uart/uart_echo",
+    "protocols/http_server/simple",
+    "protocols/https_request",
+    "protocols/mqtt/tcp",
+    "protocols/sntp",
+    "storage/nvs_rw_value",
+    "storage/spiffs",
+    "system/deep_sleep",
+    "system/ota/simple_ota_example",
+    "wifi/getting_started/softAP",
+    "wifi/getting_started/station",
+    "wifi/wifi_provisioning",
+];
+
+/// Where the listing comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExampleSource {
+    /// Fetched from GitHub in this run
+    Fetched,
+    /// Read from the cache
+    Cache,
+    /// The built-in [`CURATED_EXAMPLES`]
+    Curated,
+}
+
+/// The example projects and where the listing comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExampleList {
+    /// Paths of the examples relative to `examples/`, sorted
+    pub examples: Vec<String>,
+    pub source: ExampleSource,
+}
+
+impl ExampleList {
+    /// Groups the examples by category, the first directory of their path
+    ///
+    /// # Returns
+    /// The examples of each category, by category name
+    pub fn by_category(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut groups: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for example in &self.examples {
+            let category = example.split('/').next().unwrap_or(example);
+            groups.entry(category).or_default().push(example);
+        }
+        groups
+    }
+}
+
+#[derive(Deserialize)]
+struct TreeResponse {
+    tree: Vec<TreeEntry>,
+}
+
+#[derive(Deserialize)]
+struct TreeEntry {
+    path: String,
+}
+
+/// Extracts the example projects from a GitHub tree response
+///
+/// An example is a directory with a `main/CMakeLists.txt`.
+///
+/// # Arguments
+/// * `json` - The response of the GitHub trees API for `examples/`
+///
+/// # Returns
+/// The paths of the examples, sorted
+///
+/// # Errors
+/// If the response is not a tree
+pub fn parse_tree(json: &str) -> anyhow::Result<Vec<String>> {
+    let response: TreeResponse = serde_json::from_str(json).context("Invalid examples listing")?;
+    let mut examples: Vec<String> = response
+        .tree
+        .into_iter()
+        .filter_map(|entry| {
+            entry
+                .path
+                .strip_suffix("/main/CMakeLists.txt")
+                .map(str::to_string)
+        })
+        .collect();
+    examples.sort();
+    Ok(examples)
+}
+
+/// Fetches the examples from GitHub
+///
+/// # Errors
+/// If GitHub cannot be reached or the response is not a tree
+pub fn fetch_examples() -> anyhow::Result<Vec<String>> {
+    let response = http::agent(EXAMPLES_TREE_URL)?
+        .get(EXAMPLES_TREE_URL)
+        .call()
+        .context("Cannot get the ESP-IDF examples from GitHub")?
+        .into_string()
+        .context("Cannot get the ESP-IDF examples from GitHub")?;
+    parse_tree(&response)
+}
+
+/// Reads the cached listing
+///
+/// # Arguments
+/// * `cache_file` - The cache file
+/// * `max_age` - Age after which the cache is ignored, `None` to accept any age
+/// * `now` - The current time
+///
+/// # Returns
+/// The examples, or `None` if there's no cache, it's unreadable or too old
+pub fn read_cache(
+    cache_file: &Path,
+    max_age: Option<Duration>,
+    now: SystemTime,
+) -> Option<Vec<String>> {
+    if let Some(max_age) = max_age {
+        let modified = fs::metadata(cache_file).and_then(|m| m.modified()).ok()?;
+        // A cache from the future (the clock was moved back) is still used
+        if now.duration_since(modified).is_ok_and(|age| age > max_age) {
+            return None;
+        }
+    }
+    let content = fs::read_to_string(cache_file).ok()?;
+    let examples: Vec<String> = content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    if examples.is_empty() {
+        None
+    } else {
+        Some(examples)
+    }
+}
+
+/// Stores the listing in the cache
+///
+/// # Errors
+/// If the cache file cannot be written
+pub fn write_cache(cache_file: &Path, examples: &[String]) -> anyhow::Result<()> {
+    if let Some(parent) = cache_file.parent() {
+        fs::create_dir_all(parent).context("Cannot create the cache directory")?;
+    }
+    fs::write(cache_file, examples.join("\n") + "\n").context("Cannot write the examples cache")
+}
+
+/// Lists the examples: from the cache if it's recent, otherwise from GitHub,
+/// falling back to an old cache and then to [`CURATED_EXAMPLES`]
+///
+/// # Arguments
+/// * `offline` - Never access the network, use the cache of any age
+///
+/// # Returns
+/// The listing, and a warning when it may be outdated
+pub fn list_examples(offline: bool) -> (ExampleList, Option<String>) {
+    list_examples_with(
+        cache_file().as_deref(),
+        offline,
+        SystemTime::now(),
+        fetch_examples,
+    )
+}
+
+fn cache_file() -> Option<PathBuf> {
+    crate::config::state_dir().map(|dir| dir.join(CACHE_FILE))
+}
+
+/// [`list_examples`] with the cache, the clock and the fetch replaced in tests
+fn list_examples_with(
+    cache_file: Option<&Path>,
+    offline: bool,
+    now: SystemTime,
+    fetch: impl FnOnce() -> anyhow::Result<Vec<String>>,
+) -> (ExampleList, Option<String>) {
+    let cached = |max_age| cache_file.and_then(|file| read_cache(file, max_age, now));
+    let list = |examples, source| ExampleList { examples, source };
+
+    if !offline {
+        if let Some(examples) = cached(Some(CACHE_MAX_AGE)) {
+            return (list(examples, ExampleSource::Cache), None);
+        }
+    }
+    let error = if offline {
+        "--offline".to_string()
+    } else {
+        match fetch() {
+            Ok(examples) => {
+                if let Some(file) = cache_file {
+                    // Not being able to cache only means fetching again next run
+                    let _ = write_cache(file, &examples);
+                }
+                return (list(examples, ExampleSource::Fetched), None);
+            }
+            Err(error) => format!("{:#}", error),
+        }
+    };
+    match cached(None) {
+        Some(examples) => (
+            list(examples, ExampleSource::Cache),
+            Some(format!(
+                "Showing the cached examples, they may be outdated ({})",
+                error
+            )),
+        ),
+        None => (
+            list(
+                CURATED_EXAMPLES.iter().map(|e| e.to_string()).collect(),
+                ExampleSource::Curated,
+            ),
+            Some(format!(
+                "Showing a short list of common examples, the full list cannot be fetched ({})",
+                error
+            )),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_parse_tree() {
+        let json = r#"{"sha": "abc", "tree": [
+            {"path": "README.md", "type": "blob"},
+            {"path": "wifi/getting_started/station", "type": "tree"},
+            {"path": "wifi/getting_started/station/main/CMakeLists.txt", "type": "blob"},
+            {"path": "get-started/blink/main/CMakeLists.txt", "type": "blob"},
+            {"path": "get-started/blink/CMakeLists.txt", "type": "blob"}
+        ], "truncated": false}"#;
+        let examples = parse_tree(json).unwrap();
+        assert_eq!(
+            examples,
+            vec!["get-started/blink", "wifi/getting_started/station"]
+        );
+        assert!(parse_tree("[]").is_err());
+
+        let list = ExampleList {
+            examples,
+            source: ExampleSource::Fetched,
+        };
+        let groups = list.by_category();
+        assert_eq!(
+            groups.keys().copied().collect::<Vec<_>>(),
+            ["get-started", "wifi"]
+        );
+        assert_eq!(groups["wifi"], ["wifi/getting_started/station"]);
+    }
+
+    #[test]
+    fn test_list_examples_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("state").join(CACHE_FILE);
+        let now = SystemTime::now();
+        let fetched = || Ok(vec!["get-started/blink".to_string()]);
+        let unreachable = || Err(anyhow!("unreachable"));
+
+        let (list, warning) = list_examples_with(Some(&file), true, now, fetched);
+        assert_eq!(list.source, ExampleSource::Curated);
+        assert!(warning.unwrap().contains("--offline"));
+
+        let (list, warning) = list_examples_with(Some(&file), false, now, fetched);
+        assert_eq!(list.source, ExampleSource::Fetched);
+        assert_eq!(warning, None);
+
+        // A recent cache is used without fetching
+        let (list, warning) = list_examples_with(Some(&file), false, now, unreachable);
+        assert_eq!(list.source, ExampleSource::Cache);
+        assert_eq!(list.examples, ["get-started/blink"]);
+        assert_eq!(warning, None);
+
+        // An old one only when the fetch fails
+        let later = now + CACHE_MAX_AGE + Duration::from_secs(60);
+        let (list, warning) = list_examples_with(Some(&file), false, later, unreachable);
+        assert_eq!(list.source, ExampleSource::Cache);
+        assert!(warning.unwrap().contains("unreachable"));
+    }
+}
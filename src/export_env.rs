@@ -0,0 +1,162 @@
+//! `--export-env`: the choices of a generation as shell variables, for the
+//! scripts that build around the scaffolded project
+
+use anyhow::Context;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::naming::ProjectNames;
+use crate::options::ScaffoldOptions;
+
+/// Chip ESP-IDF builds for when neither a board nor a target is picked
+pub const DEFAULT_TARGET: &str = "esp32";
+
+/// The shell the variables are written for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportShell {
+    /// `export NAME='value'`, for sh, bash, zsh...
+    #[default]
+    Sh,
+    /// `$env:NAME = 'value'`
+    PowerShell,
+}
+
+impl FromStr for ExportShell {
+    type Err = String;
+
+    fn from_str(shell: &str) -> Result<Self, Self::Err> {
+        match shell {
+            "sh" => Ok(ExportShell::Sh),
+            "powershell" => Ok(ExportShell::PowerShell),
+            _ => Err(format!(
+                "unknown shell \"{}\", expected sh or powershell",
+                shell
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ExportShell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportShell::Sh => "sh",
+            ExportShell::PowerShell => "powershell",
+        })
+    }
+}
+
+/// The variables describing a generated project, in the order they are
+/// written; the ones of unset choices (e.g. `ESP_BOARD`) are left out
+///
+/// # Arguments
+/// * `options` - The options the project was generated with
+/// * `directory` - The directory of the project, written as it is
+///
+/// # Errors
+/// If the project name or the board is invalid
+pub fn variables(
+    options: &ScaffoldOptions,
+    directory: &Path,
+) -> anyhow::Result<Vec<(&'static str, String)>> {
+    let names = ProjectNames::new(&options.project_name)?;
+    let board = options.board()?;
+    let target = options.build_target()?;
+    let language = serde_json::to_value(options.language)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .context("The language of the project is unknown")?;
+
+    let mut variables = vec![
+        ("ESP_PROJECT_NAME", names.cmake),
+        ("ESP_PROJECT_DIR", directory.display().to_string()),
+        ("ESP_TARGET", target),
+    ];
+    if let Some(board) = board {
+        variables.push(("ESP_BOARD", board.id.to_string()));
+    }
+    variables.push(("ESP_LANGUAGE", language));
+    variables.push(("ESP_STARTERS", options.starters.join(",")));
+    variables.push(("ESP_USE_GIT", options.use_git.to_string()));
+    Ok(variables)
+}
+
+/// Quotes a value for a single-quoted string of the shell
+fn quote(value: &str, shell: ExportShell) -> String {
+    match shell {
+        ExportShell::Sh => format!("'{}'", value.replace('\'', r"'\''")),
+        ExportShell::PowerShell => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+/// Renders the variables as a script to source (`. ./esp-env.sh`) or to
+/// `eval`, one assignment per line
+///
+/// # Arguments
+/// * `variables` - The variables, see [`variables`]
+/// * `shell` - The shell the script is written for
+pub fn render(variables: &[(&str, String)], shell: ExportShell) -> String {
+    variables
+        .iter()
+        .map(|(name, value)| match shell {
+            ExportShell::Sh => format!("export {}={}\n", name, quote(value, shell)),
+            ExportShell::PowerShell => format!("$env:{} = {}\n", name, quote(value, shell)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::ProgrammingLanguage;
+
+    #[test]
+    fn test_variables() {
+        let mut options = ScaffoldOptions::from_json(
+            r#"{"project_name": "my-app", "language": "cpp17", "starters": ["wifi", "nvs"]}"#,
+        )
+        .unwrap();
+        let variables = variables(&options, Path::new("/work/my-app")).unwrap();
+        assert_eq!(
+            variables,
+            [
+                ("ESP_PROJECT_NAME", "my-app".to_string()),
+                ("ESP_PROJECT_DIR", "/work/my-app".to_string()),
+                ("ESP_TARGET", "esp32".to_string()),
+                ("ESP_LANGUAGE", "cpp17".to_string()),
+                ("ESP_STARTERS", "wifi,nvs".to_string()),
+                ("ESP_USE_GIT", "false".to_string()),
+            ]
+        );
+
+        options.board = Some("esp32-c3-devkitm-1".into());
+        options.target = Some("esp32s3".into());
+        let variables = super::variables(&options, Path::new("my-app")).unwrap();
+        assert_eq!(variables[2], ("ESP_TARGET", "esp32c3".to_string()));
+        assert_eq!(
+            variables[3],
+            ("ESP_BOARD", "esp32-c3-devkitm-1".to_string())
+        );
+
+        options.language = ProgrammingLanguage::Unknown;
+        assert!(super::variables(&options, Path::new("my-app")).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let variables = [
+            ("ESP_TARGET", "esp32c3".to_string()),
+            ("ESP_PROJECT_DIR", "/home/o'neil/my app".to_string()),
+        ];
+        assert_eq!(
+            render(&variables, ExportShell::Sh),
+            "export ESP_TARGET='esp32c3'\nexport ESP_PROJECT_DIR='/home/o'\\''neil/my app'\n"
+        );
+        assert_eq!(
+            render(&variables, ExportShell::PowerShell),
+            "$env:ESP_TARGET = 'esp32c3'\n$env:ESP_PROJECT_DIR = '/home/o''neil/my app'\n"
+        );
+        assert_eq!("powershell".parse(), Ok(ExportShell::PowerShell));
+        assert!("fish".parse::<ExportShell>().is_err());
+    }
+}
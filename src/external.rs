@@ -0,0 +1,123 @@
+//! External subcommands: `esp-create-project foo` runs an `esp-create-project-foo`
+//! executable from the `PATH`, so the tool can be extended without patching it
+
+use anyhow::Context;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::platform;
+
+/// Prefix of the executables run as external subcommands
+pub const EXTERNAL_PREFIX: &str = "esp-create-project-";
+
+/// Variable set to the path of this binary when an external subcommand runs,
+/// so it can call back into the tool
+pub const EXE_VARIABLE: &str = "ESP_CREATE_PROJECT";
+
+/// Checks if the argument can name an external subcommand
+///
+/// Only plain names qualify (ASCII letters, digits, `-` and `_`, not starting
+/// with `-`), so paths and options are never looked up on the `PATH`.
+///
+/// # Arguments
+/// * `name` - The first argument given to the tool
+pub fn is_subcommand_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Finds the executable of an external subcommand on the `PATH`
+///
+/// # Arguments
+/// * `name` - The subcommand name, e.g. `foo` for `esp-create-project-foo`
+///
+/// # Returns
+/// The path of the executable, or `None` if the name is not a plain name or
+/// no executable is found
+pub fn find(name: &str) -> Option<PathBuf> {
+    find_in(name, &env::var_os("PATH")?)
+}
+
+/// Finds the executable of an external subcommand in a `PATH`-like list, see [`find`]
+///
+/// # Arguments
+/// * `name` - The subcommand name
+/// * `path` - The list of directories, separated by the platform separator
+pub fn find_in(name: &str, path: &OsString) -> Option<PathBuf> {
+    if !is_subcommand_name(name) {
+        return None;
+    }
+    platform::find_executable_in(&format!("{}{}", EXTERNAL_PREFIX, name), path)
+}
+
+/// Runs an external subcommand and waits for it
+///
+/// The arguments after the subcommand name are passed unchanged, stdin,
+/// stdout and stderr are inherited, and [`EXE_VARIABLE`] is set to the path
+/// of this binary.
+///
+/// # Arguments
+/// * `executable` - The executable found by [`find`]
+/// * `args` - The arguments after the subcommand name
+///
+/// # Returns
+/// The exit code of the subcommand, 1 if it was killed by a signal
+///
+/// # Errors
+/// If the executable cannot be started
+pub fn run<I, S>(executable: &PathBuf, args: I) -> anyhow::Result<i32>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new(executable);
+    command.args(args);
+    if let Ok(exe) = env::current_exe() {
+        command.env(EXE_VARIABLE, exe);
+    }
+    let status = command
+        .status()
+        .context(format!("Cannot run \"{}\"", executable.display()))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_subcommand_name() {
+        assert!(is_subcommand_name("lint"));
+        assert!(is_subcommand_name("board_gen-2"));
+        assert!(!is_subcommand_name(""));
+        assert!(!is_subcommand_name("--help"));
+        assert!(!is_subcommand_name("my project"));
+        assert!(!is_subcommand_name("../lint"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_and_run() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let executable = dir.path().join("esp-create-project-lint");
+        fs::write(
+            &executable,
+            "#!/bin/sh\n[ -n \"$ESP_CREATE_PROJECT\" ] && [ \"$1\" = --fix ] && exit 3\nexit 1\n",
+        )
+        .unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        let path = dir.path().as_os_str().to_os_string();
+
+        assert_eq!(find_in("lint", &path), Some(executable.clone()));
+        assert_eq!(find_in("format", &path), None);
+        assert_eq!(run(&executable, ["--fix"]).unwrap(), 3);
+    }
+}
@@ -0,0 +1,285 @@
+//! The factory test app of a workspace: the console starter with one command
+//! per starter of the project that checks its hardware (blinking the LED,
+//! scanning Wi-Fi...), flashed to the `test` partition next to the main app
+//! for the production line
+//!
+//! The commands come from [`Starter::factory_test`], so a starter with a
+//! factory test gets its command in every factory test app.
+
+use anyhow::Context;
+use std::fs;
+use std::path::Path;
+
+use crate::cmake;
+use crate::starters::{self, FactoryTest, Integration, Starter, CONSOLE};
+
+/// Directory of the factory test app in the workspace
+pub const FACTORY_TEST_APP: &str = "factory_test";
+
+/// Header of the factory test commands, in `main/`
+pub const HEADER_FILE: &str = "factory_test.h";
+
+/// Source of the factory test commands, in `main/`
+pub const SOURCE_FILE: &str = "factory_test.c";
+
+/// Function that registers the commands, called after the console starter
+pub const INIT_FUNCTION: &str = "factory_test_init";
+
+/// Command that runs every test and prints PASS or FAIL for each
+pub const ALL_COMMAND: &str = "test_all";
+
+/// Help of [`ALL_COMMAND`]
+const ALL_HELP: &str = "Run every test, print PASS or FAIL for each";
+
+const HEADER: &str = r#"#pragma once
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Adds the factory test commands to the console of the console starter
+void factory_test_init(void);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+/// The starters of the factory test app: the ones of the project and the
+/// console the commands run on
+///
+/// # Arguments
+/// * `ids` - Ids of the starters of the project
+pub fn starter_ids(ids: &[String]) -> Vec<String> {
+    let mut ids = ids.to_vec();
+    if !ids.iter().any(|id| id == CONSOLE.id) {
+        ids.push(CONSOLE.id.to_string());
+    }
+    ids
+}
+
+/// The tests of the starters, in the order of the starters
+///
+/// # Arguments
+/// * `starters` - The starters of the project
+pub fn tests<'a>(starters: &[&'a Starter]) -> Vec<(&'a Starter, &'a FactoryTest)> {
+    starters
+        .iter()
+        .filter_map(|starter| starter.factory_test.as_ref().map(|test| (*starter, test)))
+        .collect()
+}
+
+/// The commands of the factory test app and their help, [`ALL_COMMAND`]
+/// last; empty if no starter has a test
+///
+/// # Arguments
+/// * `starters` - The starters of the project
+pub fn commands(starters: &[&Starter]) -> Vec<(&'static str, &'static str)> {
+    let mut commands: Vec<(&'static str, &'static str)> = starters
+        .iter()
+        .filter_map(|starter| starter.factory_test.as_ref())
+        .map(|test| (test.command, test.help))
+        .collect();
+    if !commands.is_empty() {
+        commands.push((ALL_COMMAND, ALL_HELP));
+    }
+    commands
+}
+
+/// The content of [`SOURCE_FILE`]: a function per test, and [`ALL_COMMAND`]
+///
+/// # Arguments
+/// * `starters` - The starters of the project
+pub fn source(starters: &[&Starter]) -> String {
+    let tests = tests(starters);
+    let mut includes = vec!["esp_err.h", CONSOLE.header];
+    for (starter, test) in &tests {
+        for header in std::iter::once(&starter.header).chain(test.includes) {
+            if !includes.contains(header) {
+                includes.push(header);
+            }
+        }
+    }
+
+    let mut source = format!("#include \"{}\"\n\n#include <stdio.h>\n", HEADER_FILE);
+    for header in includes {
+        source.push_str(&format!("#include \"{}\"\n", header));
+    }
+    for (_, test) in &tests {
+        source.push_str(&format!(
+            "\n// {}\nstatic int test_{}(int argc, char **argv)\n{{\n",
+            test.help, test.command
+        ));
+        for line in test.body.lines() {
+            if !line.is_empty() {
+                source.push_str("    ");
+            }
+            source.push_str(line);
+            source.push('\n');
+        }
+        source.push_str("}\n");
+    }
+    if !tests.is_empty() {
+        source.push_str(
+            r#"
+// Runs a test, prints PASS or FAIL
+static int run(const char *command, esp_console_cmd_func_t test)
+{
+    int result = test(0, NULL);
+    printf("%s: %s\n", command, result == 0 ? "PASS" : "FAIL");
+    return result != 0;
+}
+
+static int test_all(int argc, char **argv)
+{
+    int failed = 0;
+"#,
+        );
+        for (_, test) in &tests {
+            source.push_str(&format!(
+                "    failed += run(\"{0}\", test_{0});\n",
+                test.command
+            ));
+        }
+        source.push_str("    printf(\"%d failed\\n\", failed);\n    return failed;\n}\n");
+    }
+
+    source.push_str(&format!("\nvoid {}(void)\n{{\n", INIT_FUNCTION));
+    if tests.is_empty() {
+        source.push_str("    // None of the starters of the project has a factory test\n");
+    }
+    for (_, test) in &tests {
+        source.push_str(&format!(
+            "    ESP_ERROR_CHECK(starter_console_register(\"{0}\", \"{1}\", test_{0}));\n",
+            test.command, test.help
+        ));
+    }
+    if !tests.is_empty() {
+        source.push_str(&format!(
+            "    ESP_ERROR_CHECK(starter_console_register(\"{}\", \"{}\", test_all));\n",
+            ALL_COMMAND, ALL_HELP
+        ));
+    }
+    source.push_str("}\n");
+    source
+}
+
+/// Adds the factory test commands to an app that has the console starter:
+/// writes [`HEADER_FILE`] and [`SOURCE_FILE`] to `main/`, lists the source
+/// and calls [`INIT_FUNCTION`] after the init functions of the starters
+///
+/// # Arguments
+/// * `root` - The root of the app
+/// * `starters` - The starters of the project
+///
+/// # Returns
+/// The manual steps, the init call if it could not be inserted
+///
+/// # Errors
+/// If a file cannot be written, or the main CMakeLists.txt cannot be edited
+pub fn apply(root: &Path, starters: &[&Starter]) -> anyhow::Result<Vec<String>> {
+    let main_dir = root.join("main");
+    for (name, content) in [
+        (HEADER_FILE, HEADER.to_string()),
+        (SOURCE_FILE, source(starters)),
+    ] {
+        let path = main_dir.join(name);
+        fs::write(&path, content).context(format!("Cannot write \"{}\"", path.display()))?;
+    }
+
+    let cmake_file = main_dir.join("CMakeLists.txt");
+    let cmake = fs::read_to_string(&cmake_file)
+        .context(format!("Cannot read \"{}\"", cmake_file.display()))?;
+    let cmake = cmake::add_component_sources(&cmake, &[SOURCE_FILE])
+        .context("Cannot add the factory test to the build")?;
+    fs::write(&cmake_file, cmake).context(format!("Cannot write \"{}\"", cmake_file.display()))?;
+
+    let integration = Integration::detect(&main_dir);
+    if starters::insert_init_call(&main_dir, integration, HEADER_FILE, INIT_FUNCTION)? {
+        return Ok(Vec::new());
+    }
+    Ok(vec![format!(
+        "Call {}() from {}, after {}(), after #include \"{}\"",
+        INIT_FUNCTION,
+        integration.caller(),
+        CONSOLE.init_function,
+        HEADER_FILE
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starters::{EVENTS, LED, NVS, WIFI};
+
+    #[test]
+    fn test_source() {
+        assert_eq!(
+            starter_ids(&["led".to_string()]),
+            ["led".to_string(), "console".to_string()]
+        );
+        assert_eq!(starter_ids(&["console".to_string()]), ["console"]);
+
+        let starters = [&NVS, &WIFI, &EVENTS, &LED, &CONSOLE];
+        let names: Vec<&str> = commands(&starters)
+            .into_iter()
+            .map(|(command, _)| command)
+            .collect();
+        assert_eq!(names, ["nvs_check", "wifi_scan", "led_blink", ALL_COMMAND]);
+        let source = source(&starters);
+        assert!(source.starts_with("#include \"factory_test.h\"\n\n#include <stdio.h>\n"));
+        // Each header once, only for the starters with a test
+        assert_eq!(source.matches("#include \"freertos/task.h\"").count(), 1);
+        assert!(source.contains("#include \"starter_led.h\"\n"));
+        assert!(!source.contains("starter_events.h"));
+        assert!(source.contains(
+            "static int test_led_blink(int argc, char **argv)\n{\n    const uint8_t colors"
+        ));
+        assert!(source.contains("    failed += run(\"wifi_scan\", test_wifi_scan);\n"));
+        assert!(source.contains(
+            "    ESP_ERROR_CHECK(starter_console_register(\"nvs_check\", \"Write a key to NVS and read it back\", test_nvs_check));\n"
+        ));
+        assert!(source.ends_with("test_all));\n}\n"));
+
+        let source = super::source(&[&EVENTS, &CONSOLE]);
+        assert!(commands(&[&EVENTS, &CONSOLE]).is_empty());
+        assert!(!source.contains("test_all"));
+        assert!(source.contains("void factory_test_init(void)\n{\n    // None"));
+    }
+
+    #[test]
+    fn test_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let main_dir = dir.path().join("main");
+        fs::create_dir(&main_dir).unwrap();
+        fs::write(
+            main_dir.join("CMakeLists.txt"),
+            "idf_component_register(SRCS \"main.c\" \"starter_console.c\"\n                       INCLUDE_DIRS \".\")\n",
+        )
+        .unwrap();
+        fs::write(
+            main_dir.join("main.c"),
+            "#include \"starter_console.h\"\n\nvoid app_main(void)\n{\n    starter_console_init();\n    // TODO Insert code\n}\n",
+        )
+        .unwrap();
+
+        assert!(apply(dir.path(), &[&LED, &CONSOLE]).unwrap().is_empty());
+        let main = fs::read_to_string(main_dir.join("main.c")).unwrap();
+        assert!(main.contains("#include \"factory_test.h\"\n"));
+        assert!(main.contains("    starter_console_init();\n    factory_test_init();\n"));
+        let cmake = fs::read_to_string(main_dir.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains("\"factory_test.c\""));
+        assert!(main_dir.join(HEADER_FILE).is_file());
+        assert!(fs::read_to_string(main_dir.join(SOURCE_FILE))
+            .unwrap()
+            .contains("test_led_blink"));
+
+        // Without the marker, the call is a manual step
+        fs::write(main_dir.join("main.c"), "void app_main(void) {}\n").unwrap();
+        let steps = apply(dir.path(), &[&LED, &CONSOLE]).unwrap();
+        assert_eq!(
+            steps,
+            ["Call factory_test_init() from app_main, after starter_console_init(), after #include \"factory_test.h\""]
+        );
+    }
+}
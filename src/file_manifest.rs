@@ -0,0 +1,201 @@
+//! `--emit-manifest` and `--verify-manifest`: the files of a generated
+//! project and their SHA-256, so CI can check that the scaffold of a
+//! template doesn't change across tool versions
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::report::{self, FileHash};
+use crate::self_update::CURRENT_VERSION;
+
+/// The files of a project and their hashes, `.git` excluded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileManifest {
+    /// The version of esp-create-project that generated the files
+    pub tool_version: String,
+    /// Sorted by path
+    pub files: Vec<FileHash>,
+}
+
+/// A difference between the expected files and the generated ones
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// Expected but not generated
+    Missing(String),
+    /// Generated but not expected
+    Unexpected(String),
+    /// Generated with another content
+    Changed {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Missing(path) => write!(f, "missing: {}", path),
+            Drift::Unexpected(path) => write!(f, "unexpected: {}", path),
+            Drift::Changed {
+                path,
+                expected,
+                actual,
+            } => write!(f, "changed: {} ({} instead of {})", path, actual, expected),
+        }
+    }
+}
+
+impl FileManifest {
+    /// Hashes the files of a project
+    ///
+    /// # Arguments
+    /// * `root` - The project directory
+    ///
+    /// # Errors
+    /// If a directory or a file cannot be read
+    pub fn of_project(root: &Path) -> anyhow::Result<Self> {
+        Ok(FileManifest {
+            tool_version: CURRENT_VERSION.to_string(),
+            files: report::hash_files(root)?,
+        })
+    }
+
+    /// Reads a manifest written by [`FileManifest::write`]
+    ///
+    /// # Errors
+    /// If the file cannot be read or is not a manifest
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read_to_string(path)
+            .context(format!("Cannot read the manifest \"{}\"", path.display()))?;
+        serde_json::from_str(&json).context(format!("Invalid manifest \"{}\"", path.display()))
+    }
+
+    /// Writes the manifest as JSON
+    ///
+    /// # Errors
+    /// If the file cannot be written
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        // Only strings and lists, it cannot fail
+        let json = serde_json::to_string_pretty(self).unwrap_or_default();
+        fs::write(path, json).context(format!("Cannot write the manifest \"{}\"", path.display()))
+    }
+
+    /// The differences between this manifest, the expected one, and the
+    /// files actually generated, sorted by path
+    ///
+    /// # Arguments
+    /// * `actual` - The manifest of the generated project
+    pub fn drift(&self, actual: &FileManifest) -> Vec<Drift> {
+        let expected: BTreeMap<&str, &str> = self
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.sha256.as_str()))
+            .collect();
+        let generated: BTreeMap<&str, &str> = actual
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.sha256.as_str()))
+            .collect();
+        let mut paths: Vec<&str> = expected.keys().chain(generated.keys()).copied().collect();
+        paths.sort_unstable();
+        paths.dedup();
+        paths
+            .into_iter()
+            .filter_map(|path| match (expected.get(path), generated.get(path)) {
+                (Some(_), None) => Some(Drift::Missing(path.to_string())),
+                (None, Some(_)) => Some(Drift::Unexpected(path.to_string())),
+                (Some(expected), Some(actual)) if expected != actual => Some(Drift::Changed {
+                    path: path.to_string(),
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Checks that the generated files match this manifest
+    ///
+    /// # Arguments
+    /// * `actual` - The manifest of the generated project
+    ///
+    /// # Errors
+    /// If a file is missing, unexpected or changed, every difference is listed
+    pub fn verify(&self, actual: &FileManifest) -> anyhow::Result<()> {
+        let drift = self.drift(actual);
+        if drift.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<String> = drift.iter().map(|drift| format!("  {}", drift)).collect();
+        bail!(
+            "The generated files don't match the manifest of esp-create-project {} ({} differences):\n{}",
+            self.tool_version,
+            drift.len(),
+            lines.join("\n")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_update::sha256_hex;
+
+    #[test]
+    fn test_emit_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        fs::create_dir_all(project.join("main")).unwrap();
+        fs::write(project.join("CMakeLists.txt"), "project(blink)\n").unwrap();
+        fs::write(
+            project.join("main").join("main.c"),
+            "void app_main(void) {}\n",
+        )
+        .unwrap();
+
+        let path = dir.path().join("manifest.json");
+        FileManifest::of_project(&project)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        let expected = FileManifest::read(&path).unwrap();
+        assert_eq!(expected.files.len(), 2);
+        expected
+            .verify(&FileManifest::of_project(&project).unwrap())
+            .unwrap();
+
+        fs::write(project.join("main").join("main.c"), "int main;\n").unwrap();
+        fs::remove_file(project.join("CMakeLists.txt")).unwrap();
+        fs::write(project.join("README.md"), "# blink\n").unwrap();
+        let actual = FileManifest::of_project(&project).unwrap();
+        assert_eq!(
+            expected.drift(&actual),
+            [
+                Drift::Missing("CMakeLists.txt".into()),
+                Drift::Unexpected("README.md".into()),
+                Drift::Changed {
+                    path: "main/main.c".into(),
+                    expected: sha256_hex(b"void app_main(void) {}\n"),
+                    actual: sha256_hex(b"int main;\n"),
+                },
+            ]
+        );
+        let error = expected.verify(&actual).unwrap_err().to_string();
+        assert!(error.contains("3 differences"), "{}", error);
+        assert!(error.contains("missing: CMakeLists.txt"), "{}", error);
+    }
+
+    #[test]
+    fn test_read_invalid_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest.json");
+        assert!(FileManifest::read(&path).is_err());
+        fs::write(&path, "{\"files\": 1}").unwrap();
+        assert!(FileManifest::read(&path).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! `flash` and `monitor`: run `idf.py` on a project without setting up the
+//! IDF environment or looking up the serial port first
+
+use anyhow::{bail, Context};
+use std::fmt;
+use std::path::Path;
+
+use crate::build_deps::{self, IdfPy};
+use crate::project;
+use crate::serial;
+
+/// What to do with the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardAction {
+    /// Build the project and flash it
+    Flash,
+    /// Open the serial monitor
+    Monitor,
+}
+
+impl fmt::Display for BoardAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BoardAction::Flash => "flash",
+            BoardAction::Monitor => "monitor",
+        })
+    }
+}
+
+/// Runs `idf.py flash` or `idf.py monitor` on the project that contains `start`
+///
+/// The checks run in order and each failure says which one failed: the IDF
+/// environment, then the serial port (the only ESP board connected unless
+/// `port` is given), then the build, and last the flashing or monitor itself.
+///
+/// # Arguments
+/// * `action` - Flash or monitor
+/// * `start` - A directory inside the project, usually the current directory
+/// * `port` - The serial port, detected if `None`
+///
+/// # Errors
+/// If one of the steps fails, see above
+pub fn run(action: BoardAction, start: &Path, port: Option<String>) -> anyhow::Result<()> {
+    let root = project::find_project_root(start).context(format!(
+        "\"{}\" is not inside an ESP-IDF project (no CMakeLists.txt including project.cmake found)",
+        start.display()
+    ))?;
+    let idf = build_deps::idf_py().context("ESP-IDF environment not ready")?;
+    let port = match port {
+        Some(port) => port,
+        None => serial::detect_port().context("Serial port not found")?,
+    };
+    run_idf(&idf, &root, &port, action)
+}
+
+/// Runs the `idf.py` steps of the action, see [`run`]
+fn run_idf(idf: &IdfPy, root: &Path, port: &str, action: BoardAction) -> anyhow::Result<()> {
+    if action == BoardAction::Flash {
+        // Built on its own first, so a compile error isn't blamed on the board
+        let status = idf_status(idf, root, None, "build")?;
+        if !status {
+            bail!("Build failed, fix the errors above and run flash again");
+        }
+    }
+    let status = idf_status(idf, root, Some(port), &action.to_string())?;
+    if !status {
+        match action {
+            BoardAction::Flash => bail!(
+                "Flashing to {} failed: check that no monitor is using the port, and hold \
+                 the BOOT button while flashing if the board doesn't enter download mode",
+                port
+            ),
+            BoardAction::Monitor => bail!("The monitor on {} exited with an error", port),
+        }
+    }
+    Ok(())
+}
+
+/// Runs an `idf.py` action with the output shown to the user
+///
+/// # Returns
+/// Whether the action succeeded
+///
+/// # Errors
+/// If `idf.py` cannot be started, an environment problem
+fn idf_status(idf: &IdfPy, root: &Path, port: Option<&str>, action: &str) -> anyhow::Result<bool> {
+    let mut command = idf.command();
+    command.arg("-C").arg(root);
+    if let Some(port) = port {
+        command.arg("-p").arg(port);
+    }
+    let status = command
+        .arg(action)
+        .status()
+        .context("ESP-IDF environment not ready, idf.py cannot be started")?;
+    Ok(status.success())
+}
+
+/// Flash script of Linux and macOS
+pub const SCRIPT_UNIX_FILE: &str = "flash.sh";
+
+/// Flash script of Windows
+pub const SCRIPT_WINDOWS_FILE: &str = "flash.ps1";
+
+const SCRIPT_UNIX_TEMPLATE: &str = r#"#!/bin/sh
+# Builds the project, flashes it and opens the serial monitor (Ctrl+] quits).
+# Run it where ESP-IDF is set up (. $IDF_PATH/export.sh):
+#   ./flash.sh [PORT]
+# {{default}}
+set -e
+cd "$(dirname "$0")"
+if ! command -v idf.py >/dev/null 2>&1; then
+    echo "idf.py not found, set up ESP-IDF first: . \$IDF_PATH/export.sh" >&2
+    exit 1
+fi
+DEFAULT_PORT='{{port}}'
+PORT="${1:-$DEFAULT_PORT}"
+if [ -n "$PORT" ]; then
+    exec idf.py -p "$PORT" flash monitor
+fi
+exec idf.py flash monitor
+"#;
+
+const SCRIPT_WINDOWS_TEMPLATE: &str = r#"# Builds the project, flashes it and opens the serial monitor (Ctrl+] quits).
+# Run it where ESP-IDF is set up (the ESP-IDF PowerShell, or export.ps1):
+#   .\flash.ps1 [PORT]
+# {{default}}
+param([string]$Port = '{{port}}')
+$ErrorActionPreference = 'Stop'
+Set-Location $PSScriptRoot
+if (-not (Get-Command idf.py -ErrorAction SilentlyContinue)) {
+    Write-Error 'idf.py not found, set up ESP-IDF first: $env:IDF_PATH\export.ps1'
+    exit 1
+}
+if ($Port) {
+    idf.py -p $Port flash monitor
+} else {
+    idf.py flash monitor
+}
+exit $LASTEXITCODE
+"#;
+
+/// Checks that a serial port name can be written in the flash script as it
+/// is: no quotes, whitespace or control characters
+///
+/// # Errors
+/// If the name is empty or has one of those characters
+pub fn validate_port(port: &str) -> anyhow::Result<()> {
+    let invalid = port
+        .chars()
+        .find(|c| c.is_whitespace() || c.is_control() || matches!(c, '\'' | '"' | '`' | '$'));
+    match invalid {
+        _ if port.is_empty() => bail!("The serial port cannot be empty"),
+        Some(c) => bail!(
+            "Invalid serial port \"{}\": it cannot contain {:?}",
+            port.escape_default(),
+            c
+        ),
+        None => Ok(()),
+    }
+}
+
+/// The script that builds, flashes and monitors the project, for the OS it's
+/// generated on: `flash.sh` (to be made executable) or `flash.ps1` on Windows
+///
+/// # Arguments
+/// * `port` - The port used when the script is run without one, `idf.py`
+///   finds the board if `None`; see [`validate_port`]
+/// * `windows` - Whether to write the PowerShell script
+///
+/// # Returns
+/// The file name of the script and its content
+pub fn script(port: Option<&str>, windows: bool) -> (&'static str, String) {
+    let (file, template) = if windows {
+        (SCRIPT_WINDOWS_FILE, SCRIPT_WINDOWS_TEMPLATE)
+    } else {
+        (SCRIPT_UNIX_FILE, SCRIPT_UNIX_TEMPLATE)
+    };
+    let default = match port {
+        Some(port) => format!("Without a port it uses {}", port),
+        None => "Without a port idf.py looks for the board".to_string(),
+    };
+    let content = template
+        .replace("{{default}}", &default)
+        .replace("{{port}}", port.unwrap_or_default());
+    (file, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_idf() {
+        use std::env;
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        let script = dir.path().join("idf.py");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {}\n[ \"$3\" = build ] && [ -n \"$FAIL_BUILD\" ] && exit 1\nexit 0\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let idf = |fail_build: bool| {
+            let mut variables = vec![("PATH".to_string(), env::var("PATH").unwrap())];
+            if fail_build {
+                variables.push(("FAIL_BUILD".into(), "1".into()));
+            }
+            IdfPy::Exported {
+                script: script.clone(),
+                variables,
+            }
+        };
+        let root = dir.path();
+
+        run_idf(&idf(false), root, "/dev/ttyUSB0", BoardAction::Flash).unwrap();
+        let expected = format!(
+            "-C {0} build\n-C {0} -p /dev/ttyUSB0 flash\n",
+            root.display()
+        );
+        assert_eq!(fs::read_to_string(&log).unwrap(), expected);
+
+        let error = run_idf(&idf(true), root, "/dev/ttyUSB0", BoardAction::Flash).unwrap_err();
+        assert!(error.to_string().starts_with("Build failed"));
+        // The board isn't touched when the build fails
+        assert!(fs::read_to_string(&log).unwrap().ends_with(" build\n"));
+    }
+    #[test]
+    fn test_script() {
+        let (file, content) = script(Some("/dev/ttyUSB0"), false);
+        assert_eq!(file, SCRIPT_UNIX_FILE);
+        assert!(content.starts_with("#!/bin/sh\n"));
+        assert!(content.contains("DEFAULT_PORT='/dev/ttyUSB0'\n"));
+        assert!(content.contains("# Without a port it uses /dev/ttyUSB0\n"));
+
+        let (file, content) = script(None, true);
+        assert_eq!(file, SCRIPT_WINDOWS_FILE);
+        assert!(content.contains("param([string]$Port = '')\n"));
+        assert!(content.contains("idf.py looks for the board"));
+
+        assert!(validate_port("COM3").is_ok());
+        assert!(validate_port("/dev/tty.usbserial-0001").is_ok());
+        for port in ["", "COM3'", "a b", "$(reboot)", "COM3\n"] {
+            assert!(validate_port(port).is_err(), "{:?}", port);
+        }
+    }
+}
@@ -0,0 +1,3420 @@
+use anyhow::{anyhow, bail, Context};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::archive::{
+    check_extracted_size, detect_prefix, extract_zip, ExtractOptions, FailedEntry,
+};
+use crate::ci::{self, CiProvider};
+use crate::cmake;
+use crate::download::{self, download_template, TemplateTooLarge};
+use crate::error::{CancellationToken, EspCreateError, Result};
+use crate::factory_test::{self, FACTORY_TEST_APP};
+use crate::flash;
+use crate::hygiene::HygieneFile;
+use crate::idf_component::{self, ComponentMetadata, IDF_COMPONENT_FILE};
+use crate::idf_version::{self, IdfVersion};
+use crate::metadata::{ProjectMetadata, TemplateProvenance};
+use crate::naming::ProjectNames;
+use crate::options::{
+    AssertionLevel, ClangFormatStyle, PostProcess, ProgrammingLanguage, ScaffoldOptions,
+    WORKSPACE_COMPONENTS_DIR,
+};
+use crate::partitions::{self, PartitionLayout};
+use crate::platform;
+use crate::project;
+use crate::replacements::FileReplacements;
+use crate::report::{self, FileHash};
+use crate::sdkconfig::{SdkConfigDefaults, SDKCONFIG_DEFAULTS_FILE};
+use crate::self_update::sha256_hex;
+use crate::starters::{self, MergeMode};
+use crate::status;
+use crate::steps::{StageRecord, StepRunner};
+use crate::symbols::Symbol;
+use crate::template_cache::TemplateCache;
+use crate::template_size::SizeReport;
+use crate::templates;
+use crate::templatize;
+use crate::workspace;
+
+/// What the generation did, filled in as it runs
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct GenerationReport {
+    /// Template entries skipped because they could not be extracted, only
+    /// possible when [`ScaffoldOptions::keep_going`] is set
+    pub failed_entries: Vec<FailedEntry>,
+    /// Apps of a workspace that could not be written, named after the app;
+    /// only possible when [`ScaffoldOptions::keep_going`] is set
+    pub failed_apps: Vec<FailedEntry>,
+    /// Changes left to the user, e.g. starter calls that could not be added to a custom main file
+    pub manual_steps: Vec<String>,
+    /// Post-processing steps skipped because the template was copied as it
+    /// is, each with why, see [`PostProcess`]
+    pub skipped_post_processing: Vec<String>,
+    /// The files changed by the replacements of the template manifest, see
+    /// [`crate::replacements`]
+    pub replacements: Vec<FileReplacements>,
+    /// The steps that completed, in order
+    pub steps: Vec<String>,
+    /// SHA-256 of the template archive, once downloaded
+    pub template_sha256: Option<String>,
+    /// The files of the project and their hashes (git metadata excluded),
+    /// the ones written before the failure if the generation failed
+    pub files: Vec<FileHash>,
+    /// Whether the files were removed because the generation failed
+    pub cleaned_up: bool,
+    /// Whether a git repo was initialized, a failed `git init` is only a
+    /// warning and a manual step unless it was aborted
+    pub git_initialized: bool,
+    /// The stages run and how each one ended, see [`crate::steps`]
+    pub stages: Vec<StageRecord>,
+    /// The sizes of the extracted template by top-level directory, `None`
+    /// for a workspace
+    pub template_size: Option<SizeReport>,
+    /// Files that already existed and were kept as they were, only with
+    /// [`ScaffoldOptions::scaffold_only_config`] around an existing codebase
+    pub kept_files: Vec<PathBuf>,
+}
+
+/// Generates the project described by the options
+///
+/// The token is checked between archive entries, before each download attempt
+/// and before each post-processing step. If the generation fails or is
+/// cancelled, everything written to the project directory is removed.
+///
+/// # Arguments
+/// * `options` - The options of the project to generate
+/// * `token` - Token used to cancel the generation from another thread
+///
+/// # Returns
+/// The report of the generation, see [`GenerationReport`]
+///
+/// # Errors
+/// [`crate::EspCreateError::Cancelled`] if the generation was cancelled, any other
+/// error if the template cannot be downloaded or the project files cannot be written
+pub fn generate(options: &ScaffoldOptions, token: &CancellationToken) -> Result<GenerationReport> {
+    let mut report = GenerationReport::default();
+    generate_with_report(options, token, &mut report)?;
+    Ok(report)
+}
+
+/// Same as [`generate`], filling in `report` as the generation runs so it
+/// tells how far the generation got if it fails
+///
+/// The files are hashed once the generation completes, or before they are
+/// removed if it fails.
+///
+/// # Arguments
+/// * `options` - The options of the project to generate
+/// * `token` - Token used to cancel the generation from another thread
+/// * `report` - The report to fill in, usually empty
+///
+/// # Errors
+/// See [`generate`]
+pub fn generate_with_report(
+    options: &ScaffoldOptions,
+    token: &CancellationToken,
+    report: &mut GenerationReport,
+) -> Result<()> {
+    generate_with_runner(options, token, report, &mut StepRunner::default())
+}
+
+/// Same as [`generate_with_report`], running the stages with `runner` so a
+/// failed optional stage (git init) can be retried, skipped or abort the
+/// generation, see [`crate::steps`]
+///
+/// # Arguments
+/// * `options` - The options of the project to generate
+/// * `token` - Token used to cancel the generation from another thread
+/// * `report` - The report to fill in, usually empty
+/// * `runner` - Runs the stages, its stages are copied to the report
+///
+/// # Errors
+/// See [`generate`], and an aborted optional stage
+pub fn generate_with_runner(
+    options: &ScaffoldOptions,
+    token: &CancellationToken,
+    report: &mut GenerationReport,
+    runner: &mut StepRunner,
+) -> Result<()> {
+    let names = ProjectNames::new(&options.project_name)?;
+    let dir = names.directory.as_path();
+    if options.scaffold_only_config && project::directory_not_empty(dir, &[]) {
+        return generate_around(options, &names, token, runner, report);
+    }
+
+    let created = !dir.exists();
+    if created {
+        platform::create_dir_all_with_mode(dir, options.dir_mode).context(format!(
+            "Failed to create directory \"{}\"",
+            &options.project_name
+        ))?;
+    }
+
+    let result = generate_into(options, &names, token, runner, report);
+    report.stages = runner.stages().to_vec();
+    // Not being able to hash only leaves the list of files empty
+    report.files = report::hash_files(dir).unwrap_or_default();
+    if result.is_err() {
+        // The error is printed on its own line
+        status::clear();
+        cleanup(dir, created);
+        report.cleaned_up = true;
+    }
+    result
+}
+
+/// [`ScaffoldOptions::scaffold_only_config`] around an existing codebase:
+/// the project is generated in a staging directory, then only the files
+/// missing from the project directory are copied to it. The existing files
+/// are never overwritten, they are listed in [`GenerationReport::kept_files`],
+/// and nothing is removed if the generation fails.
+///
+/// # Arguments
+/// See [`generate_with_runner`], `names` are the names of the project
+///
+/// # Errors
+/// See [`generate`]
+fn generate_around(
+    options: &ScaffoldOptions,
+    names: &ProjectNames,
+    token: &CancellationToken,
+    runner: &mut StepRunner,
+    report: &mut GenerationReport,
+) -> Result<()> {
+    let staging = tempfile::tempdir().context("Cannot create a staging directory")?;
+    // Same directory name, the names of the project come from it
+    let staged_dir = staging.path().join(&names.display);
+    let mut staged = options.clone();
+    staged.project_name = staged_dir.to_string_lossy().into_owned();
+    staged.use_git = false;
+
+    let result = generate_into(&staged, names, token, runner, report).and_then(|()| {
+        token.check()?;
+        copy_missing(
+            &staged_dir,
+            &names.directory,
+            Path::new(""),
+            options.dir_mode,
+            &mut report.kept_files,
+        )
+        .context("Cannot copy the build files to the project")?;
+        for kept in &report.kept_files {
+            status::warn(
+                Symbol::Warning,
+                &format!("{} already exists, kept as it is", kept.display()),
+            );
+        }
+        report
+            .steps
+            .push("Build files copied to the project".into());
+        if options.use_git {
+            init_git(
+                &options.project_name,
+                names,
+                options.no_gitignore,
+                runner,
+                report,
+            )?;
+        }
+        Ok(())
+    });
+    report.stages = runner.stages().to_vec();
+    report.files = report::hash_files(&names.directory).unwrap_or_default();
+    if result.is_err() {
+        status::clear();
+    }
+    result
+}
+
+/// Copies the files of `from` missing from `to`, recursively
+///
+/// # Arguments
+/// * `from` - The directory to copy
+/// * `to` - The destination, created if missing
+/// * `relative` - The path of `from` in the copied tree, empty at the top
+/// * `dir_mode` - Permissions of the created directories, see
+///   [`ScaffoldOptions::dir_mode`]
+/// * `kept` - Where the files that already exist in `to` are listed,
+///   relative to the top
+///
+/// # Errors
+/// If a directory cannot be read or a file cannot be copied
+fn copy_missing(
+    from: &Path,
+    to: &Path,
+    relative: &Path,
+    dir_mode: Option<u32>,
+    kept: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    platform::create_dir_all_with_mode(to, dir_mode)
+        .context(format!("Cannot create \"{}\"", to.display()))?;
+    let mut entries = fs::read_dir(from)
+        .context(format!("Cannot read \"{}\"", from.display()))?
+        .collect::<std::io::Result<Vec<_>>>()
+        .context(format!("Cannot read \"{}\"", from.display()))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let source = entry.path();
+        let destination = to.join(entry.file_name());
+        let relative = relative.join(entry.file_name());
+        if source.is_dir() {
+            copy_missing(&source, &destination, &relative, dir_mode, kept)?;
+        } else if destination.exists() {
+            kept.push(relative);
+        } else {
+            fs::copy(&source, &destination)
+                .context(format!("Cannot write \"{}\"", destination.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs every generation step, see [`generate`]
+fn generate_into(
+    options: &ScaffoldOptions,
+    names: &ProjectNames,
+    token: &CancellationToken,
+    runner: &mut StepRunner,
+    report: &mut GenerationReport,
+) -> Result<()> {
+    let project_name = &options.project_name;
+
+    // Read before downloading, a wrong path fails right away
+    let main_source = match &options.main_file {
+        Some(path) => {
+            fs::read(path).context(format!("Cannot read main file \"{}\"", path.display()))?
+        }
+        None => {
+            templates::main_template(options.language, options.kconfig_example, options.app_class)
+                .as_bytes()
+                .to_vec()
+        }
+    };
+
+    let tmp_file = runner.essential("template", || {
+        // Create a temp file to download the template
+        let mut tmp_file = tempfile::tempfile().context("Cannot create a temp file")?;
+
+        // Download the template, or read the local archive
+        let url = options.template_url();
+        let cache = options.template_cache.clone().map(TemplateCache::new);
+        if let Some(path) = &options.template_archive {
+            let content = fs::read(path)
+                .context(format!("Cannot read the template \"{}\"", path.display()))?;
+            tmp_file
+                .write_all(&content)
+                .context("Cannot copy the template")?;
+            status::finish(Symbol::Done, "Template read from a local archive");
+            report
+                .steps
+                .push("Template read from a local archive".into());
+            report.template_sha256 = Some(sha256_hex(&content));
+        } else if let Some((sha256, path)) = cache
+            .as_ref()
+            .and_then(|cache| cache.fresh(&url, options.template_ttl, SystemTime::now()))
+        {
+            tmp_file = fs::File::open(&path).context(format!(
+                "Cannot open the cached template \"{}\"",
+                path.display()
+            ))?;
+            let step = format!(
+                "Template read from the cache (template TTL {})",
+                options.template_ttl
+            );
+            status::finish(Symbol::Done, &step);
+            report.steps.push(step);
+            report.template_sha256 = Some(sha256);
+        } else if options.offline && download::local_path(&url).is_none() {
+            // Past its TTL, the cached template still beats no template
+            let (sha256, path) = cache
+                .as_ref()
+                .and_then(|cache| cache.lookup(&url))
+                .ok_or_else(|| download::offline_error(&url))?;
+            tmp_file = fs::File::open(&path).context(format!(
+                "Cannot open the cached template \"{}\"",
+                path.display()
+            ))?;
+            let step = "Template read from the cache (--offline)";
+            status::finish(Symbol::Done, step);
+            report.steps.push(step.into());
+            report.template_sha256 = Some(sha256);
+        } else {
+            status::start(Symbol::Download, "Downloading template");
+            match download_template(&url, &mut tmp_file, options.max_template_size(), token) {
+                Ok(sha256) => {
+                    status::finish(Symbol::Done, "Template downloaded");
+                    report.steps.push("Template downloaded".into());
+                    if let Some(cache) = &cache {
+                        // Not being able to cache only means no fallback next time
+                        if let Err(error) = cache.store(&url, &sha256, &mut tmp_file) {
+                            status::warn(
+                                Symbol::Warning,
+                                &format!("Cannot cache the template: {:#}", error),
+                            );
+                        }
+                    }
+                    report.template_sha256 = Some(sha256);
+                }
+                Err(EspCreateError::Other(error)) if !error.is::<TemplateTooLarge>() => {
+                    let (sha256, path) = match cache.as_ref().and_then(|cache| cache.lookup(&url)) {
+                        Some(cached) => cached,
+                        None => return Err(error.into()),
+                    };
+                    status::finish(
+                        Symbol::Warning,
+                        &format!(
+                            "Template read from the cache, the download failed: {:#}",
+                            error
+                        ),
+                    );
+                    tmp_file = fs::File::open(&path).context(format!(
+                        "Cannot open the cached template \"{}\"",
+                        path.display()
+                    ))?;
+                    report.steps.push("Template read from the cache".into());
+                    report.template_sha256 = Some(sha256);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        if let (Some(expected), Some(sha256)) = (&options.template_sha256, &report.template_sha256)
+        {
+            if expected != sha256 {
+                return Err(anyhow!(
+                    "The template changed since it was recorded: its SHA-256 is {} instead of {}",
+                    sha256,
+                    expected
+                )
+                .into());
+            }
+        }
+        tmp_file
+            .seek(SeekFrom::Start(0))
+            .context("Cannot read the downloaded template")?;
+        Ok(tmp_file)
+    })?;
+
+    let extract_options = ExtractOptions {
+        keep_going: options.keep_going,
+        dir_mode: options.dir_mode,
+        build_files_only: options.scaffold_only_config,
+        ..ExtractOptions::default()
+    };
+    let (mut zip, prefix) = runner.essential("extraction", || {
+        // Unzip the template
+        status::start(Symbol::Unzip, "Unzipping the template");
+        let mut zip = zip::ZipArchive::new(tmp_file).context("The template is not a valid zip")?;
+        check_extracted_size(&mut zip, options.max_template_size())?;
+        status::finish(Symbol::Done, "Template unzipped");
+
+        let prefix = match &options.template_prefix {
+            Some(prefix) => {
+                let prefix = PathBuf::from(prefix);
+                let found = zip.file_names().any(|name| {
+                    platform::archive_entry_path(name).is_some_and(|path| path.starts_with(&prefix))
+                });
+                if !found {
+                    return Err(anyhow!(
+                        "The template has no directory \"{}\" to take its files from",
+                        prefix.display()
+                    )
+                    .into());
+                }
+                prefix
+            }
+            None => detect_prefix(&zip),
+        };
+
+        // Write the zip contents to the directory, the apps of a workspace
+        // extract it on their own
+        status::start(Symbol::Write, "Writing files");
+        if options.workspace_apps.is_empty() {
+            report.failed_entries =
+                extract_zip(project_name, &mut zip, &prefix, extract_options, token)?;
+            report.steps.push("Template extracted".into());
+            // Not being able to measure it only leaves the sizes out
+            report.template_size = SizeReport::of_directory(Path::new(project_name)).ok();
+        }
+        Ok((zip, prefix))
+    })?;
+
+    // A template without the expected layout is copied as it is, unless the
+    // user wants it post-processed anyway; asked before the stage runs
+    let full = options.post_process == PostProcess::Auto && {
+        let issues = if options.workspace_apps.is_empty() {
+            template_layout_issues(Path::new(project_name))
+        } else {
+            archive_layout_issues(&mut zip, &prefix)
+        };
+        !issues.is_empty() && !runner.plain_copy(&issues)?
+    };
+    let post_processed;
+    let options = if full {
+        post_processed = ScaffoldOptions {
+            post_process: PostProcess::Full,
+            ..options.clone()
+        };
+        &post_processed
+    } else {
+        options
+    };
+
+    runner.essential("post-processing", || {
+        if options.workspace_apps.is_empty() {
+            let scaffold = scaffold_app(project_name, names, options, &main_source, token)?;
+            report.manual_steps = scaffold.manual_steps;
+            report.skipped_post_processing = scaffold.skipped;
+            report.replacements = scaffold.replacements;
+            report.steps.push("Project files written".into());
+        } else {
+            token.check()?;
+            write_workspace(project_name, names, options)?;
+            report.steps.push("Workspace files written".into());
+            // Every app reads the template on its own
+            let mut template = Vec::new();
+            let mut tmp_file = zip.into_inner();
+            tmp_file
+                .seek(SeekFrom::Start(0))
+                .and_then(|_| tmp_file.read_to_end(&mut template))
+                .context("Cannot read the downloaded template")?;
+            let app = AppTemplate {
+                template: &template,
+                prefix: &prefix,
+                extract_options,
+                main_source: &main_source,
+            };
+            let apps = write_apps(project_name, &app, options, token, report)?;
+            if options.relative_symlinks {
+                for shared in
+                    workspace::share_components(Path::new(project_name), &apps, options.dir_mode)?
+                {
+                    report.steps.push(format!("{} shared by the apps", shared));
+                }
+            }
+        }
+
+        if let Some(board) = options.board()? {
+            report
+                .manual_steps
+                .extend(board.manual_steps(&options.starters));
+        }
+        if options.no_sdkconfig {
+            report
+                .manual_steps
+                .extend(unwritten_sdkconfig_step(options)?);
+        }
+
+        // Found in the parent directories by clang-format, git and the editors,
+        // so a workspace shares one of each
+        let hygiene = options.hygiene_files();
+        let style = options.clang_format.unwrap_or_default();
+        for file in &hygiene {
+            write_hygiene_file(project_name, *file, style)?;
+            report.steps.push(format!("{} written", file.file_name()));
+        }
+        // Only build files around an existing codebase
+        if !options.scaffold_only_config || !hygiene.is_empty() {
+            let metadata = ProjectMetadata {
+                project: Some(names.display.clone()),
+                hygiene,
+                template: Some(TemplateProvenance {
+                    url: options.template_url().into_owned(),
+                    git_ref: options.template_ref.clone(),
+                    sha256: report.template_sha256.clone(),
+                }),
+            };
+            metadata.write(Path::new(project_name))?;
+        }
+        if let Some(provider) = options.ci {
+            write_ci(project_name, provider, options)?;
+            report
+                .steps
+                .push(format!("{} written", provider.file_name()));
+        }
+
+        status::finish(Symbol::Done, "Files written");
+        Ok(())
+    })?;
+
+    if options.use_git {
+        token.check()?;
+        init_git(project_name, names, options.no_gitignore, runner, report)?;
+    }
+
+    Ok(())
+}
+
+/// Initializes the git repo of the project, unless it's already in a checkout
+///
+/// # Arguments
+/// * `directory` - The directory of the project
+/// * `names` - The names of the project
+/// * `no_gitignore` - See [`ScaffoldOptions::no_gitignore`]
+/// * `runner` - Decides whether a failed `git init` is retried, skipped or aborts
+/// * `report` - Where the outcome is recorded
+///
+/// # Errors
+/// If `git init` failed and the runner aborted
+fn init_git(
+    directory: &str,
+    names: &ProjectNames,
+    no_gitignore: bool,
+    runner: &mut StepRunner,
+    report: &mut GenerationReport,
+) -> Result<()> {
+    // `git init` in a worktree would write to the metadata of its repository
+    match project::git_checkout(Path::new(directory)) {
+        Some(checkout) => {
+            status::finish(
+                Symbol::Done,
+                &format!("Already {}, git init skipped", checkout),
+            );
+            report
+                .steps
+                .push(format!("Git init skipped, already {}", checkout));
+        }
+        None => {
+            initialize_git_repo_or_warn("git", directory, &names.display, runner, report)?;
+            if no_gitignore && report.git_initialized {
+                report_global_gitignore("git", report);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What [`scaffold_app`] reports
+struct Scaffold {
+    /// The manual steps reported by the starters
+    manual_steps: Vec<String>,
+    /// The steps skipped by a plain copy, see [`PostProcess`]
+    skipped: Vec<String>,
+    /// The files changed by the replacements of the template
+    replacements: Vec<FileReplacements>,
+}
+
+/// Why the extracted template doesn't have the layout of an ESP-IDF project
+/// the post-processing expects, empty if it has it
+///
+/// The top-level `CMakeLists.txt` must have a `project(...)` command and the
+/// main component must exist; a missing main source is added by the
+/// post-processing.
+///
+/// # Arguments
+/// * `directory` - The directory of the extracted template
+pub fn template_layout_issues(directory: &Path) -> Vec<String> {
+    layout_issues(
+        fs::read_to_string(directory.join("CMakeLists.txt")).ok(),
+        directory.join("main").is_dir(),
+    )
+}
+
+/// Same as [`template_layout_issues`], on the archive of the template
+/// before the apps of a workspace extract it
+///
+/// # Arguments
+/// * `zip` - The template
+/// * `prefix` - The directory of the archive the files are taken from
+fn archive_layout_issues<R: Read + Seek>(
+    zip: &mut zip::ZipArchive<R>,
+    prefix: &Path,
+) -> Vec<String> {
+    let mut cmake_lists = None;
+    let mut has_main = false;
+    for index in 0..zip.len() {
+        let mut file = match zip.by_index(index) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        let path = match platform::archive_entry_path(file.name()) {
+            Some(path) => path,
+            None => continue,
+        };
+        let path = match path.strip_prefix(prefix) {
+            Ok(path) => path.to_path_buf(),
+            Err(_) => continue,
+        };
+        if path == Path::new("CMakeLists.txt") {
+            let mut content = String::new();
+            cmake_lists = file.read_to_string(&mut content).ok().map(|_| content);
+        } else if path.starts_with("main") && (file.is_dir() || path != Path::new("main")) {
+            has_main = true;
+        }
+    }
+    layout_issues(cmake_lists, has_main)
+}
+
+/// The issues of [`template_layout_issues`]
+///
+/// # Arguments
+/// * `cmake_lists` - The top-level `CMakeLists.txt`, `None` if missing
+/// * `has_main` - Whether the main component exists
+fn layout_issues(cmake_lists: Option<String>, has_main: bool) -> Vec<String> {
+    let mut issues = Vec::new();
+    match cmake_lists {
+        Some(content) if cmake::project_name(&content).is_none() => {
+            issues.push("CMakeLists.txt has no project(...) command".to_string())
+        }
+        Some(_) => {}
+        None => issues.push("no CMakeLists.txt".to_string()),
+    }
+    if !has_main {
+        issues.push("no main/ directory".to_string());
+    }
+    issues
+}
+
+/// The post-processing steps [`scaffold_app`] runs with these options,
+/// listed when they are skipped
+fn post_processing_steps(options: &ScaffoldOptions) -> Vec<String> {
+    let steps = [
+        (options.no_gitignore, "removal of the template .gitignore"),
+        (!options.scaffold_only_config, "main source"),
+        (options.app_class, "application class"),
+        (options.kconfig_example, "Kconfig example"),
+        (options.component_metadata.is_some(), "component metadata"),
+        (true, "CMake project name and language"),
+        (!options.scaffold_only_config, "README"),
+        (
+            options.assumed_idf_version.is_some(),
+            "ESP-IDF version constraint",
+        ),
+        (!options.no_sdkconfig, "sdkconfig.defaults"),
+        (options.partitions.is_some(), "partition table"),
+        (options.host_test_asan, "host test"),
+        (options.bootloader_hooks, "bootloader hooks"),
+        (options.flash_script, "flash script"),
+    ];
+    steps
+        .iter()
+        .filter(|(runs, _)| *runs)
+        .map(|(_, step)| step.to_string())
+        .chain(options.starters.iter().map(|id| format!("starter {}", id)))
+        .collect()
+}
+
+/// Runs the post-processing of a project extracted from the template:
+/// placeholders, main file, CMake options, README, configuration and starters
+///
+/// In a workspace it runs for each sub-project, with the sub-project name.
+///
+/// With [`PostProcess::None`], or [`PostProcess::Auto`] and a template
+/// without the expected layout (see [`template_layout_issues`]), only the
+/// placeholders are filled in and the other steps are reported as skipped.
+///
+/// # Arguments
+/// * `directory` - The directory of the project
+/// * `names` - The names of the project
+/// * `options` - The options of the generation
+/// * `main_source` - The content of the main file
+/// * `token` - Token checked before each step
+///
+/// # Returns
+/// The manual steps reported by the starters, and the skipped steps
+fn scaffold_app(
+    directory: &str,
+    names: &ProjectNames,
+    options: &ScaffoldOptions,
+    main_source: &[u8],
+    token: &CancellationToken,
+) -> Result<Scaffold> {
+    let language_selection = options.language;
+    let workspace = !options.workspace_apps.is_empty();
+
+    token.check()?;
+    let plain_copy = match options.post_process {
+        PostProcess::Full => None,
+        PostProcess::None => Some("--post-process none".to_string()),
+        PostProcess::Auto => {
+            let issues = template_layout_issues(Path::new(directory));
+            (!issues.is_empty()).then(|| issues.join(", "))
+        }
+    };
+    let replacements = templatize::fill_placeholders(Path::new(directory), names)?;
+    if let Some(reason) = plain_copy {
+        status::warn(
+            Symbol::Warning,
+            &format!(
+                "The template was copied as it is, without post-processing ({})",
+                reason
+            ),
+        );
+        return Ok(Scaffold {
+            manual_steps: Vec::new(),
+            skipped: post_processing_steps(options)
+                .into_iter()
+                .map(|step| format!("{} ({})", step, reason))
+                .collect(),
+            replacements,
+        });
+    }
+    if options.no_gitignore {
+        remove_template_gitignore(directory)?;
+    }
+    let mut manual_steps = if options.scaffold_only_config {
+        vec!["Add your sources to main/ and list them in main/CMakeLists.txt".to_string()]
+    } else {
+        replace_main_file(directory, language_selection, main_source)?
+    };
+    if options.app_class {
+        manual_steps.extend(write_app_class(directory, options.kconfig_example)?);
+    }
+    if options.kconfig_example {
+        write_kconfig_example(directory)?;
+    }
+    if let Some(metadata) = &options.component_metadata {
+        let target = match options.board()? {
+            Some(board) => Some(board.target),
+            None => options.target.as_deref(),
+        };
+        let metadata = metadata.clone().derive(&names.display, target);
+        write_component_metadata(directory, &metadata)?;
+    }
+
+    let project_language = match language_selection {
+        ProgrammingLanguage::C => "",
+        ProgrammingLanguage::Cpp11 => "set(CMAKE_CXX_STANDARD 11)",
+        ProgrammingLanguage::Cpp14 => "set(CMAKE_CXX_STANDARD 14)",
+        ProgrammingLanguage::Cpp17 => "set(CMAKE_CXX_STANDARD 17)",
+        _ => return Err(anyhow!("Invalid programming language").into()),
+    };
+    token.check()?;
+    set_cmake_options(
+        directory,
+        project_language,
+        cmake_languages(language_selection),
+        &names.cmake,
+        workspace,
+        !options.no_sdkconfig,
+    )?;
+    token.check()?;
+    if !options.scaffold_only_config {
+        // A workspace shows them once, at its root
+        let badges = if workspace {
+            String::new()
+        } else {
+            badges(options)?
+        };
+        write_readme(directory, &names.display, &badges)?;
+    }
+    if let Some(version) = &options.assumed_idf_version {
+        write_idf_constraint(directory, version)?;
+    }
+
+    token.check()?;
+    // Without it, the settings are a manual step of the generation
+    if !options.no_sdkconfig {
+        if workspace {
+            write_app_sdkconfig_defaults(directory)?;
+        } else {
+            sdkconfig_defaults(options)?.write(directory)?;
+        }
+    }
+    if let Some(layout) = &options.partitions {
+        write_partitions(directory, layout, options.factory_test)?;
+    }
+    if options.host_test_asan {
+        write_host_test(directory, language_selection)?;
+    }
+    if options.bootloader_hooks {
+        write_bootloader_hooks(directory)?;
+    }
+    if options.flash_script {
+        write_flash_script(directory, options.flash_port.as_deref())?;
+    }
+
+    for starter in options.resolved_starters()? {
+        token.check()?;
+        let report = starters::apply_starter(
+            Path::new(directory),
+            starter,
+            MergeMode::Fail,
+            !options.no_sdkconfig,
+        )?;
+        manual_steps.extend(report.manual_steps);
+    }
+    if let Some(sources) = &options.main_sources {
+        token.check()?;
+        manual_steps.extend(write_main_sources(
+            directory,
+            &options.planned_main_sources()?,
+            sources,
+        )?);
+    }
+    Ok(Scaffold {
+        manual_steps,
+        skipped: Vec::new(),
+        replacements,
+    })
+}
+
+/// What every app of a workspace is written from
+struct AppTemplate<'a> {
+    /// The template zip
+    template: &'a [u8],
+    /// The directory of the zip the files are taken from
+    prefix: &'a Path,
+    extract_options: ExtractOptions,
+    /// The content of the main file
+    main_source: &'a [u8],
+}
+
+/// Writes the apps of a workspace, [`ScaffoldOptions::jobs`] at the same
+/// time
+///
+/// The apps don't depend on each other, so one that fails doesn't stop the
+/// others; each app prints one line when it's done.
+///
+/// # Arguments
+/// * `project_name` - The workspace root
+/// * `app` - What the apps are written from
+/// * `options` - The options of the generation
+/// * `token` - Token checked before each step
+/// * `report` - Gets the steps, skipped entries and manual steps of each app
+///
+/// # Returns
+/// The apps written
+///
+/// # Errors
+/// If the generation is cancelled, or an app fails and
+/// [`ScaffoldOptions::keep_going`] is not set
+fn write_apps(
+    project_name: &str,
+    app: &AppTemplate,
+    options: &ScaffoldOptions,
+    token: &CancellationToken,
+    report: &mut GenerationReport,
+) -> Result<Vec<String>> {
+    let mut apps = options.workspace_apps.clone();
+    if options.factory_test {
+        apps.push(FACTORY_TEST_APP.to_string());
+    }
+    let total = apps.len();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs.map_or(0, NonZeroUsize::get))
+        .build()
+        .context("Cannot start the threads that write the apps")?;
+    // Held while printing, so the count and the lines of the workers follow
+    // each other
+    let done = Mutex::new(0);
+    let results: Vec<Result<AppReport>> = pool.install(|| {
+        apps.par_iter()
+            .map(|name| {
+                let result = write_app(project_name, name, app, options, token);
+                let mut done = done.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                *done += 1;
+                match &result {
+                    Ok(_) => status::finish(Symbol::Done, &format!("App {} written", name)),
+                    Err(error) => {
+                        status::finish(Symbol::Failed, &format!("App {} failed: {}", name, error))
+                    }
+                }
+                if *done < total {
+                    status::start(Symbol::Write, &format!("Writing apps ({}/{})", done, total));
+                }
+                result
+            })
+            .collect()
+    });
+
+    let mut written = Vec::new();
+    let mut failed = Vec::new();
+    for (name, result) in apps.iter().zip(results) {
+        match result {
+            Ok(app_report) => {
+                report.failed_entries.extend(app_report.failed_entries);
+                report.manual_steps.extend(app_report.manual_steps);
+                report
+                    .skipped_post_processing
+                    .extend(app_report.skipped_post_processing);
+                report.replacements.extend(app_report.replacements);
+                report.steps.push(format!("App {} written", name));
+                written.push(name.clone());
+            }
+            Err(EspCreateError::Cancelled) => return Err(EspCreateError::Cancelled),
+            Err(EspCreateError::Other(error)) => failed.push(FailedEntry {
+                name: name.clone(),
+                error: format!("{:#}", error),
+            }),
+        }
+    }
+    if failed.is_empty() {
+        return Ok(written);
+    }
+    let summary = format!("{} of {} apps failed", failed.len(), total);
+    if !options.keep_going {
+        let apps: Vec<String> = failed
+            .iter()
+            .map(|app| format!("{}: {}", app.name, app.error))
+            .collect();
+        return Err(anyhow!("{}:\n{}", summary, apps.join("\n")).into());
+    }
+    status::warn(Symbol::Warning, &summary);
+    report.failed_apps = failed;
+    Ok(written)
+}
+
+/// What [`write_app`] reports, prefixed with the app
+struct AppReport {
+    failed_entries: Vec<FailedEntry>,
+    manual_steps: Vec<String>,
+    skipped_post_processing: Vec<String>,
+    replacements: Vec<FileReplacements>,
+}
+
+/// Writes one app of a workspace, see [`write_apps`]; the factory test app
+/// also gets the console starter and the factory test commands
+///
+/// # Arguments
+/// * `project_name` - The workspace root
+/// * `name` - The name of the app, its directory in the workspace
+/// * `app` - What the app is written from
+/// * `options` - The options of the generation
+/// * `token` - Token checked before each step
+fn write_app(
+    project_name: &str,
+    name: &str,
+    app: &AppTemplate,
+    options: &ScaffoldOptions,
+    token: &CancellationToken,
+) -> Result<AppReport> {
+    let app_dir = Path::new(project_name).join(name);
+    platform::create_dir_all_with_mode(&app_dir, options.dir_mode).context(format!(
+        "Failed to create directory \"{}\"",
+        app_dir.display()
+    ))?;
+    let directory = app_dir.to_string_lossy();
+    let mut zip = zip::ZipArchive::new(Cursor::new(app.template))
+        .context("The template is not a valid zip")?;
+    let failed = extract_zip(&directory, &mut zip, app.prefix, app.extract_options, token)?;
+    let app_names = ProjectNames::new(name)?;
+    let factory_test = options.factory_test && name == FACTORY_TEST_APP;
+    let mut scaffold = if factory_test {
+        let factory_options = ScaffoldOptions {
+            starters: factory_test::starter_ids(&options.starters),
+            ..options.clone()
+        };
+        scaffold_app(
+            &directory,
+            &app_names,
+            &factory_options,
+            app.main_source,
+            token,
+        )?
+    } else {
+        scaffold_app(&directory, &app_names, options, app.main_source, token)?
+    };
+    if factory_test {
+        if scaffold.skipped.is_empty() {
+            token.check()?;
+            let steps = factory_test::apply(&app_dir, &options.factory_test_starters()?)?;
+            scaffold.manual_steps.extend(steps);
+        } else {
+            scaffold.skipped.push("factory test commands".to_string());
+        }
+    }
+    let prefixed = |steps: Vec<String>| -> Vec<String> {
+        steps
+            .into_iter()
+            .map(|step| format!("{}: {}", name, step))
+            .collect()
+    };
+    Ok(AppReport {
+        failed_entries: failed
+            .into_iter()
+            .map(|entry| FailedEntry {
+                name: format!("{}/{}", name, entry.name),
+                ..entry
+            })
+            .collect(),
+        manual_steps: prefixed(scaffold.manual_steps),
+        skipped_post_processing: prefixed(scaffold.skipped),
+        replacements: scaffold
+            .replacements
+            .into_iter()
+            .map(|file| FileReplacements {
+                path: Path::new(name).join(file.path),
+                ..file
+            })
+            .collect(),
+    })
+}
+
+/// Writes the files at the root of a workspace: the shared components
+/// directory, the shared `sdkconfig.defaults` and the README
+///
+/// # Arguments
+/// * `directory` - The workspace root
+/// * `names` - The names of the workspace
+/// * `options` - The options of the generation, with the sub-project names
+///
+/// # Errors
+/// If a file or directory cannot be written
+fn write_workspace(
+    directory: &str,
+    names: &ProjectNames,
+    options: &ScaffoldOptions,
+) -> anyhow::Result<()> {
+    let components = Path::new(directory).join(WORKSPACE_COMPONENTS_DIR);
+    platform::create_dir_all_with_mode(&components, options.dir_mode)
+        .context("Cannot create the shared components directory")?;
+    fs::write(
+        components.join("README.md"),
+        templates::WORKSPACE_COMPONENTS_README,
+    )
+    .context("Cannot write components/README.md")?;
+    if !options.no_sdkconfig {
+        sdkconfig_defaults(options)?.write(directory)?;
+    }
+    let factory_test = if options.factory_test {
+        Some(factory_test::commands(&options.factory_test_starters()?))
+    } else {
+        None
+    };
+    fs::write(
+        Path::new(directory).join("README.md"),
+        templates::workspace_readme(
+            &names.display,
+            &options.workspace_apps,
+            factory_test.as_deref(),
+            &badges(options)?,
+        ),
+    )
+    .context("Cannot write README.md")
+}
+
+/// Removes what the generation wrote to the project directory
+///
+/// # Arguments
+/// * `dir` - The project directory
+/// * `created` - Whether the directory was created by the generation, an
+///   existing directory is kept but emptied, except its `.git` (e.g. a worktree)
+fn cleanup(dir: &Path, created: bool) {
+    let result = if created {
+        fs::remove_dir_all(dir)
+    } else {
+        project::clear_directory(dir)
+    };
+    if let Err(e) = result {
+        status::warn(
+            Symbol::Warning,
+            &format!(
+                "Cannot clean up \"{}\", remove it manually, error: {}",
+                dir.display(),
+                e
+            ),
+        );
+    }
+}
+
+/// Initializes the git repository as an optional stage of the runner: the
+/// project itself is complete without it, so a skipped failure is only a
+/// warning and a manual step
+///
+/// # Arguments
+/// * `git` - The git program, `git` outside tests
+/// * `directory` - The directory to initialize the git repository in
+/// * `description` - The repository description, the display name of the project
+/// * `runner` - Decides whether a failure is retried, skipped or aborts
+/// * `report` - Where the outcome is recorded
+///
+/// # Errors
+/// If `git init` failed and the runner aborted
+fn initialize_git_repo_or_warn(
+    git: &str,
+    directory: &str,
+    description: &str,
+    runner: &mut StepRunner,
+    report: &mut GenerationReport,
+) -> anyhow::Result<()> {
+    let initialized = runner.optional("git init", || {
+        status::start(Symbol::Git, "Initializing git repo");
+        initialize_git_repo(git, directory, description).map_err(|e| {
+            status::finish(
+                Symbol::Warning,
+                &format!("Git repo not initialized: {:#}", e),
+            );
+            e
+        })
+    })?;
+    if initialized {
+        status::finish(Symbol::Done, "Git repo initialized");
+        report.git_initialized = true;
+        report.steps.push("Git repo initialized".into());
+    } else {
+        let error = runner
+            .stages()
+            .last()
+            .and_then(|stage| stage.error.clone())
+            .unwrap_or_default();
+        report.steps.push(format!("Git init failed: {}", error));
+        report
+            .manual_steps
+            .push("Initialize the git repo with `git init`, it failed above".into());
+    }
+    Ok(())
+}
+
+/// Removes the `.gitignore` of the template, see
+/// [`ScaffoldOptions::no_gitignore`]
+///
+/// # Arguments
+/// * `directory` - The directory of the project or of an app of a workspace
+///
+/// # Errors
+/// If the file cannot be removed
+fn remove_template_gitignore(directory: &str) -> anyhow::Result<()> {
+    let path = Path::new(directory).join(templates::GITIGNORE_FILE);
+    if path.is_file() {
+        fs::remove_file(&path).context(format!("Cannot remove \"{}\"", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Tells which global ignore file git applies to a project without a
+/// `.gitignore`, or that nothing keeps the build output out of git
+///
+/// # Arguments
+/// * `git` - The git program, `git` outside tests
+/// * `report` - Where the outcome is recorded
+fn report_global_gitignore(git: &str, report: &mut GenerationReport) {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")));
+    match config_home.and_then(|config_home| global_gitignore(git, &config_home)) {
+        Some(path) => {
+            let step = format!("No .gitignore, git applies \"{}\"", path.display());
+            status::finish(Symbol::Done, &step);
+            report.steps.push(step);
+        }
+        None => {
+            status::finish(
+                Symbol::Warning,
+                "No .gitignore and no global ignore file, build/ is not ignored by git",
+            );
+            report.manual_steps.push(
+                "Set core.excludesFile in your git config, or add a .gitignore, to keep build/ out of git"
+                    .into(),
+            );
+        }
+    }
+}
+
+/// The ignore file git applies to every repository of the user:
+/// `core.excludesFile`, or `git/ignore` in the config directory if it
+/// exists
+///
+/// # Arguments
+/// * `git` - The git program
+/// * `config_home` - `$XDG_CONFIG_HOME`, `~/.config` by default
+fn global_gitignore(git: &str, config_home: &Path) -> Option<PathBuf> {
+    let configured = platform::command(git)
+        .args(["config", "--path", "--get", "core.excludesFile"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|path| !path.is_empty());
+    match configured {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(config_home.join("git").join("ignore")).filter(|path| path.is_file()),
+    }
+}
+
+/// Intializes the git repository in the selected directory
+///
+/// # Arguments
+/// * `git` - The git program
+/// * `directory` - The directory to initialize the git repository in
+/// * `description` - The repository description, the display name of the project
+///
+/// # Errors
+/// If git cannot be started or `git init` fails, with what git printed
+fn initialize_git_repo(git: &str, directory: &str, description: &str) -> anyhow::Result<()> {
+    let output = platform::command(git)
+        .arg("init")
+        .arg(directory)
+        .output()
+        .context("git cannot be started, is it installed and in the PATH?")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("git init failed ({}): {}", output.status, stderr.trim());
+    }
+
+    let description_file = Path::new(directory).join(".git").join("description");
+    if description_file.exists() {
+        fs::write(description_file, format!("{}\n", description))
+            .context("Cannot write the git repo description")?;
+    }
+    Ok(())
+}
+
+/// The `sdkconfig.defaults` entries of the selected configuration
+///
+/// # Arguments
+/// * `options` - The options of the project
+///
+/// # Errors
+/// If the board is unknown
+fn sdkconfig_defaults(options: &ScaffoldOptions) -> anyhow::Result<SdkConfigDefaults> {
+    let mut defaults = SdkConfigDefaults::new();
+    match options.board()? {
+        Some(board) => {
+            for (key, value) in board.sdkconfig(&options.starters) {
+                defaults.set(&key, &value);
+            }
+        }
+        None => {
+            if let Some(target) = &options.target {
+                defaults.set("CONFIG_IDF_TARGET", &format!("\"{}\"", target));
+            }
+        }
+    }
+    defaults.enable(options.assertions.config_key());
+    for key in options.heap_debugging.config_keys() {
+        defaults.enable(key);
+    }
+    if let Some(rtos) = &options.rtos {
+        for (key, value) in rtos.sdkconfig() {
+            defaults.set(&key, &value);
+        }
+    }
+    if let Some(layout) = &options.partitions {
+        for (key, value) in layout.sdkconfig() {
+            defaults.set(&key, &value);
+        }
+    }
+    if options.factory_test {
+        defaults.enable("CONFIG_BOOTLOADER_APP_TEST");
+        defaults.comment(
+            "CONFIG_BOOTLOADER_APP_TEST",
+            "Hold the GPIO of CONFIG_BOOTLOADER_NUM_PIN_APP_TEST at reset to start the factory test app,\ncheck the pin and its level in menuconfig",
+        );
+    }
+    for (key, value, warning) in options.security.sdkconfig() {
+        defaults.set(&key, &value);
+        if !warning.is_empty() {
+            defaults.comment(&key, &warning);
+        }
+    }
+    Ok(defaults)
+}
+
+/// The manual step listing the settings `sdkconfig.defaults` would have had,
+/// see [`ScaffoldOptions::no_sdkconfig`]
+///
+/// # Returns
+/// `None` if only the ESP-IDF defaults were selected
+///
+/// # Errors
+/// If the board is unknown
+fn unwritten_sdkconfig_step(options: &ScaffoldOptions) -> anyhow::Result<Option<String>> {
+    let mut selected = SdkConfigDefaults::new();
+    for (key, value) in sdkconfig_defaults(options)?.entries() {
+        // Always written, but only the IDF default unless picked
+        if key != AssertionLevel::default().config_key() {
+            selected.set(key, value);
+        }
+    }
+    Ok((!selected.entries().is_empty()).then(|| {
+        format!(
+            "Set {} with idf.py menuconfig (idf.py set-target for the target), sdkconfig.defaults was not written",
+            selected.summary()
+        )
+    }))
+}
+
+/// Adds the registry metadata to `main/idf_component.yml`, creating it if needed
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `metadata` - The metadata, with the derived values filled in
+///
+/// # Errors
+/// If the metadata is invalid or the manifest cannot be read or written
+fn write_component_metadata(directory: &str, metadata: &ComponentMetadata) -> anyhow::Result<()> {
+    metadata.validate()?;
+    let path = Path::new(directory).join("main").join(IDF_COMPONENT_FILE);
+    let content = if path.is_file() {
+        fs::read_to_string(&path).context(format!("Cannot read main/{}", IDF_COMPONENT_FILE))?
+    } else {
+        String::new()
+    };
+    fs::write(&path, idf_component::add_metadata(&content, metadata))
+        .context(format!("Cannot write main/{}", IDF_COMPONENT_FILE))
+}
+
+/// Writes the `sdkconfig.defaults` of a workspace sub-project, applied over
+/// the shared one; it only has a comment so the starters can add to it
+///
+/// # Arguments
+/// * `directory` - The directory of the sub-project
+///
+/// # Errors
+/// If the file cannot be written
+fn write_app_sdkconfig_defaults(directory: &str) -> anyhow::Result<()> {
+    fs::write(
+        Path::new(directory).join(SDKCONFIG_DEFAULTS_FILE),
+        templates::WORKSPACE_APP_SDKCONFIG_DEFAULTS,
+    )
+    .context("Cannot write sdkconfig.defaults")
+}
+
+/// The languages CMake enables for a project: `C`, and `CXX` for C++
+fn cmake_languages(language: ProgrammingLanguage) -> &'static [&'static str] {
+    match language {
+        ProgrammingLanguage::C | ProgrammingLanguage::Unknown => &["C"],
+        ProgrammingLanguage::Cpp11 | ProgrammingLanguage::Cpp14 | ProgrammingLanguage::Cpp17 => {
+            &["C", "CXX"]
+        }
+    }
+}
+
+/// Sets the programming language in the CMakeLists.txt file
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `language` - The programming language CMake template to use
+/// * `languages` - The languages CMake enables in `project(...)`, see
+///   [`cmake_languages`]
+/// * `project_name` - The name used in `project(...)`
+/// * `workspace` - Whether the project is a sub-project of a workspace, which
+///   uses the shared components and `sdkconfig.defaults` of the parent directory
+/// * `sdkconfig` - Whether the project has `sdkconfig.defaults` files
+///
+/// # Errors
+/// If the file cannot be found or the file cannot be written
+fn set_cmake_options(
+    directory: &str,
+    project_language: &str,
+    languages: &[&str],
+    project_name: &str,
+    workspace: bool,
+    sdkconfig: bool,
+) -> anyhow::Result<()> {
+    let cmake_file = Path::new(&directory).join("CMakeLists.txt");
+    let cmake_list_file = fs::read_to_string(&cmake_file).context("Cannot find CMakeLists.txt")?;
+
+    let mut new_cmake_file =
+        cmake::rewrite_project_cmake(&cmake_list_file, project_language, project_name)?;
+    new_cmake_file = cmake::set_project_languages(&new_cmake_file, languages)?;
+    if workspace {
+        new_cmake_file = cmake::use_workspace_dirs(
+            &new_cmake_file,
+            &format!("../{}", WORKSPACE_COMPONENTS_DIR),
+            sdkconfig
+                .then(|| format!("../{}", SDKCONFIG_DEFAULTS_FILE))
+                .as_deref(),
+        )?;
+    }
+
+    fs::write(&cmake_file, new_cmake_file)
+        .context("Cannot write CMakeLists.txt to set programming language")?;
+
+    Ok(())
+}
+
+/// Writes the README of the project, replacing the one from the template
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `display_name` - The name of the project shown in the title
+/// * `badges` - The badges section, see [`templates::readme_badges`]
+///
+/// # Errors
+/// If the file cannot be written
+fn write_readme(directory: &str, display_name: &str, badges: &str) -> anyhow::Result<()> {
+    let readme = templates::README_TEMPLATE
+        .replace(templates::PROJECT_NAME_PLACEHOLDER, display_name)
+        .replace(templates::BADGES_PLACEHOLDER, badges);
+    fs::write(Path::new(directory).join("README.md"), readme).context("Cannot write README.md")
+}
+
+/// The badges section of the README: the status of the CI pipeline, the
+/// license of the component metadata and the minimum ESP-IDF version, see
+/// [`templates::readme_badges`]
+///
+/// # Returns
+/// The section, empty with [`ScaffoldOptions::no_badges`] or without badges
+///
+/// # Errors
+/// If the board or a starter of the options is unknown
+fn badges(options: &ScaffoldOptions) -> anyhow::Result<String> {
+    if options.no_badges {
+        return Ok(String::new());
+    }
+    let license = options
+        .component_metadata
+        .as_ref()
+        .and_then(|metadata| metadata.license.as_deref());
+    let requirements = options.idf_requirements()?;
+    let idf_version = options
+        .assumed_idf_version
+        .as_ref()
+        .or_else(|| idf_version::minimum(&requirements));
+    Ok(templates::readme_badges(options.ci, license, idf_version))
+}
+
+/// Records the ESP-IDF version the project assumes, in the README and as the
+/// `idf` dependency of `main/idf_component.yml`
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `version` - The minimum version, see [`ScaffoldOptions::idf_requirements`]
+///
+/// # Errors
+/// If the files cannot be read or written
+fn write_idf_constraint(directory: &str, version: &IdfVersion) -> anyhow::Result<()> {
+    let readme = Path::new(directory).join("README.md");
+    let mut content = fs::read_to_string(&readme).context("Cannot read README.md")?;
+    content.push_str(&format!("\nThis project assumes ESP-IDF >= {}.\n", version));
+    fs::write(&readme, content).context("Cannot write README.md")?;
+
+    let path = Path::new(directory).join("main").join(IDF_COMPONENT_FILE);
+    let content = if path.is_file() {
+        fs::read_to_string(&path).context(format!("Cannot read main/{}", IDF_COMPONENT_FILE))?
+    } else {
+        String::new()
+    };
+    let requirement = format!(">={}", version);
+    fs::write(
+        &path,
+        idf_component::add_dependencies(&content, &[("idf", &requirement)]),
+    )
+    .context(format!("Cannot write main/{}", IDF_COMPONENT_FILE))
+}
+
+/// Writes the `App` class of the application class layout, `main/app.hpp`
+/// and `main/app.cpp`, and adds `app.cpp` to the sources of the component
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `kconfig_example` - Whether `App::init` reads the sample Kconfig option
+///
+/// # Returns
+/// What's left to do by hand, when `main/CMakeLists.txt` cannot be updated
+///
+/// # Errors
+/// If the files cannot be read or written
+fn write_app_class(directory: &str, kconfig_example: bool) -> anyhow::Result<Vec<String>> {
+    let main_dir = Path::new(directory).join("main");
+    fs::write(
+        main_dir.join(templates::APP_CLASS_HEADER_FILE),
+        templates::APP_CLASS_HEADER_TEMPLATE,
+    )
+    .context(format!(
+        "Cannot write main/{}",
+        templates::APP_CLASS_HEADER_FILE
+    ))?;
+    fs::write(
+        main_dir.join(templates::APP_CLASS_SOURCE_FILE),
+        templates::app_class_source(kconfig_example),
+    )
+    .context(format!(
+        "Cannot write main/{}",
+        templates::APP_CLASS_SOURCE_FILE
+    ))?;
+
+    let cmake_file = main_dir.join("CMakeLists.txt");
+    let component_cmake =
+        fs::read_to_string(&cmake_file).context("Cannot read main/CMakeLists.txt")?;
+    match cmake::add_component_sources(&component_cmake, &[templates::APP_CLASS_SOURCE_FILE]) {
+        Ok(new_cmake) => {
+            fs::write(cmake_file, new_cmake).context("Cannot write main/CMakeLists.txt")?;
+            Ok(Vec::new())
+        }
+        Err(_) => Ok(vec![format!(
+            "Add main/{} to the sources of main/CMakeLists.txt, its layout is not supported",
+            templates::APP_CLASS_SOURCE_FILE
+        )]),
+    }
+}
+
+/// Writes the sample `main/Kconfig.projbuild`
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+///
+/// # Errors
+/// If the file cannot be written
+fn write_kconfig_example(directory: &str) -> anyhow::Result<()> {
+    fs::write(
+        Path::new(directory).join(templates::KCONFIG_FILE),
+        templates::KCONFIG_TEMPLATE,
+    )
+    .context("Cannot write main/Kconfig.projbuild")
+}
+
+/// Writes a hygiene file of the project
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `file` - The hygiene file
+/// * `style` - The clang-format style, see [`HygieneFile::render`]
+///
+/// # Errors
+/// If the file cannot be written
+fn write_hygiene_file(
+    directory: &str,
+    file: HygieneFile,
+    style: ClangFormatStyle,
+) -> anyhow::Result<()> {
+    fs::write(
+        Path::new(directory).join(file.file_name()),
+        file.render(style),
+    )
+    .context(format!("Cannot write {}", file.file_name()))
+}
+
+/// Writes the build pipeline of the CI service, see [`crate::ci`]
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `provider` - The CI service
+/// * `options` - The options of the generation, for the target and the apps
+///
+/// # Errors
+/// If the board is unknown, or the file cannot be written
+fn write_ci(
+    directory: &str,
+    provider: CiProvider,
+    options: &ScaffoldOptions,
+) -> anyhow::Result<()> {
+    let path = Path::new(directory).join(provider.file_name());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!("Cannot create {}", parent.display()))?;
+    }
+    let content = provider.render(
+        &options.build_target()?,
+        &ci::apps(options),
+        options.assumed_idf_version.as_ref(),
+    );
+    fs::write(&path, content).context(format!("Cannot write {}", path.display()))
+}
+
+/// Writes the `partitions.csv` of the selected scheme
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `layout` - The flash size, partition scheme and app size
+/// * `factory_test` - Whether the table gets the partition of the factory
+///   test app, every app of the workspace has the same table
+///
+/// # Errors
+/// If the file cannot be written
+fn write_partitions(
+    directory: &str,
+    layout: &PartitionLayout,
+    factory_test: bool,
+) -> anyhow::Result<()> {
+    let csv = if factory_test {
+        partitions::with_test_partition(&layout.csv())
+    } else {
+        layout.csv()
+    };
+    fs::write(Path::new(directory).join(partitions::PARTITIONS_FILE), csv)
+        .context("Cannot write partitions.csv")
+}
+
+/// Writes the AddressSanitizer host test to `host_test/`
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `language` - The programming language of the test
+///
+/// # Errors
+/// If the files cannot be written
+fn write_host_test(directory: &str, language: ProgrammingLanguage) -> anyhow::Result<()> {
+    let dir = Path::new(directory).join(templates::HOST_TEST_DIR);
+    fs::create_dir_all(&dir).context("Cannot create the host_test directory")?;
+    for (name, content) in templates::host_test_files(language) {
+        fs::write(dir.join(&name), content).context(format!("Cannot write host_test/{}", name))?;
+    }
+    Ok(())
+}
+
+/// Writes the bootloader hooks component to `bootloader_components/`
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+///
+/// # Errors
+/// If the files cannot be written
+fn write_bootloader_hooks(directory: &str) -> anyhow::Result<()> {
+    let dir = Path::new(directory).join(templates::BOOTLOADER_COMPONENTS_DIR);
+    fs::create_dir_all(dir.join(templates::BOOTLOADER_HOOKS_COMPONENT))
+        .context("Cannot create the bootloader_components directory")?;
+    for (name, content) in templates::bootloader_hooks_files() {
+        fs::write(dir.join(&name), content).context(format!(
+            "Cannot write {}/{}",
+            templates::BOOTLOADER_COMPONENTS_DIR,
+            name
+        ))?;
+    }
+    Ok(())
+}
+
+/// Writes the script that flashes the board and opens the monitor, for the
+/// OS the project is generated on, executable on Unix
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `port` - The default port of the script, see [`flash::script`]
+///
+/// # Errors
+/// If the script cannot be written
+fn write_flash_script(directory: &str, port: Option<&str>) -> anyhow::Result<()> {
+    let (name, content) = flash::script(port, cfg!(windows));
+    let path = Path::new(directory).join(name);
+    let mut file = fs::File::create(&path).context(format!("Cannot create {}", path.display()))?;
+    file.write_all(content.as_bytes())
+        .and_then(|_| platform::set_file_mode(&file, Some(0o755)))
+        .context(format!("Cannot write {}", path.display()))
+}
+
+/// Extensions of the sources that can be the main file of the template
+const MAIN_SOURCE_EXTENSIONS: &[&str] = &["c", "cpp", "cc", "cxx"];
+
+/// Lists the sources of [`ScaffoldOptions::main_sources`] in
+/// `main/CMakeLists.txt`
+///
+/// The planned main file stands for the main source of the template, and the
+/// sources the template lists that were not planned are kept after the
+/// chosen ones. The chosen sources that don't exist are written empty.
+///
+/// # Arguments
+/// * `directory` - The directory that contains the project
+/// * `planned` - See [`ScaffoldOptions::planned_main_sources`]
+/// * `sources` - The chosen sources, in order
+///
+/// # Returns
+/// What's left to do by hand, when `main/CMakeLists.txt` cannot be updated
+///
+/// # Errors
+/// If the files cannot be read or written
+fn write_main_sources(
+    directory: &str,
+    planned: &[String],
+    sources: &[String],
+) -> anyhow::Result<Vec<String>> {
+    let main_dir = Path::new(directory).join("main");
+    let main_source = find_main_source(&main_dir).and_then(|path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    });
+    let actual = |source: &String| match (planned.first(), &main_source) {
+        (Some(planned_main), Some(main_source)) if source == planned_main => main_source.clone(),
+        _ => source.clone(),
+    };
+    let planned: Vec<String> = planned.iter().map(actual).collect();
+    let mut chosen: Vec<String> = sources.iter().map(actual).collect();
+
+    let cmake_file = main_dir.join("CMakeLists.txt");
+    let component_cmake =
+        fs::read_to_string(&cmake_file).context("Cannot read main/CMakeLists.txt")?;
+    let listed = match cmake::component_sources(&component_cmake) {
+        Ok(listed) => listed,
+        Err(_) => {
+            return Ok(vec![format!(
+                "List {} in main/CMakeLists.txt, its layout is not supported",
+                chosen.join(", ")
+            )])
+        }
+    };
+    let kept: Vec<String> = listed
+        .into_iter()
+        .filter(|source| !planned.contains(source) && !chosen.contains(source))
+        .collect();
+    for source in &chosen {
+        let path = main_dir.join(source);
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .context(format!("Cannot create \"{}\"", parent.display()))?;
+            }
+            fs::write(&path, "").context(format!("Cannot write main/{}", source))?;
+        }
+    }
+    chosen.extend(kept);
+    let new_cmake = cmake::set_component_sources(&component_cmake, &chosen)?;
+    fs::write(cmake_file, new_cmake).context("Cannot write main/CMakeLists.txt")?;
+    Ok(Vec::new())
+}
+
+/// Finds the main source of the template: the source of `main/` that defines
+/// `app_main`, or its only source
+///
+/// # Arguments
+/// * `main_dir` - The main component of the extracted template
+///
+/// # Returns
+/// The path of the source, `None` if there is none or it's ambiguous
+fn find_main_source(main_dir: &Path) -> Option<PathBuf> {
+    let mut sources: Vec<PathBuf> = fs::read_dir(main_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| MAIN_SOURCE_EXTENSIONS.contains(&ext))
+        })
+        .collect();
+    sources.sort();
+    if let Some(main) = sources
+        .iter()
+        .find(|path| fs::read_to_string(path).is_ok_and(|content| content.contains("app_main")))
+    {
+        return Some(main.clone());
+    }
+    if sources.len() == 1 {
+        sources.pop()
+    } else {
+        None
+    }
+}
+
+/// Replaces the main file of the template with the selected programming language
+///
+/// The main source of the template is looked up rather than assumed to be
+/// `main/main.c`. When it's in the selected language, the file is kept and
+/// overwritten. Otherwise it's replaced by a file of the same name with the
+/// extension of the language (e.g. `app.c` by `app.cpp`) and
+/// `main/CMakeLists.txt` is updated; the mismatch is printed as a warning.
+///
+/// # Arguments
+/// * `directory` - The directory to write the file to
+/// * `language_selection` - The programming language to use
+/// * `source` - The content of the main file
+///
+/// # Returns
+/// What's left to do by hand, when `main/CMakeLists.txt` cannot be updated
+///
+/// # Errors
+/// If the files cannot be read or written
+fn replace_main_file(
+    directory: &str,
+    language_selection: ProgrammingLanguage,
+    source: &[u8],
+) -> anyhow::Result<Vec<String>> {
+    let main_dir = Path::new(directory).join("main");
+    let (extension, language_name) = match language_selection {
+        ProgrammingLanguage::C => ("c", "C"),
+        _ => ("cpp", "C++"),
+    };
+    let template_main = find_main_source(&main_dir);
+    let same_language = template_main.as_ref().is_some_and(|path| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| language_selection.source_extensions().contains(&ext))
+    });
+    if let (Some(path), true) = (&template_main, same_language) {
+        return fs::write(path, source)
+            .context(format!("Cannot write \"{}\"", path.display()))
+            .map(|_| Vec::new());
+    }
+
+    let old_name = template_main
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned());
+    let stem = template_main
+        .as_ref()
+        .and_then(|path| path.file_stem())
+        .map_or_else(|| "main".into(), |stem| stem.to_string_lossy());
+    let new_name = format!("{}.{}", stem, extension);
+    if let Some(path) = &template_main {
+        fs::remove_file(path).context(format!("Cannot remove \"{}\"", path.display()))?;
+    }
+    fs::write(main_dir.join(&new_name), source)
+        .context(format!("Cannot write main/{}", new_name))?;
+    // The official template is in C, switching it to C++ is expected
+    let expected = old_name.as_deref() == Some("main.c") && new_name == "main.cpp";
+    match &old_name {
+        Some(old_name) if !expected => status::warn(
+            Symbol::Warning,
+            &format!(
+                "The main source of the template, main/{}, is not {}, it was replaced by main/{}",
+                old_name, language_name, new_name
+            ),
+        ),
+        Some(_) => {}
+        None => status::warn(
+            Symbol::Warning,
+            &format!(
+                "The template has no main source (a file of main/ defining app_main), main/{} was added",
+                new_name
+            ),
+        ),
+    }
+
+    // Tell CMake to build the new file
+    let cmake_file = main_dir.join("CMakeLists.txt");
+    let component_cmake =
+        fs::read_to_string(&cmake_file).context("Cannot read main/CMakeLists.txt")?;
+    let new_cmake = old_name
+        .as_deref()
+        .and_then(|old_name| cmake::replace_source(&component_cmake, old_name, &new_name))
+        .map(Ok)
+        .unwrap_or_else(|| cmake::add_component_sources(&component_cmake, &[&new_name]));
+    match new_cmake {
+        Ok(new_cmake) => {
+            fs::write(cmake_file, new_cmake).context("Cannot write main/CMakeLists.txt")?;
+            Ok(Vec::new())
+        }
+        Err(_) => Ok(vec![format!(
+            "Add main/{} to the sources of main/CMakeLists.txt, its layout is not supported",
+            new_name
+        )]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::naming;
+    use crate::options::{AssertionLevel, HeapDebugging, RtosTuning, SecurityFeatures};
+    use crate::partitions::PartitionScheme;
+    use crate::project::LOCAL_CONFIG_FILE;
+    use crate::template_cache::TemplateTtl;
+    use crate::test_fixtures;
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_adversarial_names_pipeline() {
+        let root = tempfile::tempdir().unwrap();
+        for name in naming::ADVERSARIAL_NAMES {
+            let names = match ProjectNames::new(name) {
+                Ok(names) => names,
+                Err(_) => continue,
+            };
+            let dir = root.path().join(&names.directory);
+            let directory = dir.to_str().unwrap();
+            test_fixtures::write_template(&dir);
+
+            replace_main_file(directory, ProgrammingLanguage::Cpp17, b"").unwrap();
+            set_cmake_options(
+                directory,
+                "set(CMAKE_CXX_STANDARD 17)",
+                &["C", "CXX"],
+                &names.cmake,
+                false,
+                true,
+            )
+            .unwrap();
+            write_readme(directory, &names.display, "").unwrap();
+
+            assert!(dir.join("main").join("main.cpp").exists());
+            let cmake = fs::read_to_string(dir.join("CMakeLists.txt")).unwrap();
+            assert!(cmake.ends_with(&format!("project({} C CXX)", names.cmake)));
+            let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+            assert!(readme.starts_with(&format!("# {}\n", names.display)));
+        }
+    }
+
+    fn fixture_options(dir: &Path, template_url: String) -> ScaffoldOptions {
+        ScaffoldOptions {
+            project_name: dir.to_str().unwrap().into(),
+            language: ProgrammingLanguage::C,
+            use_git: false,
+            board: None,
+            target: None,
+            assertions: AssertionLevel::Abort,
+            heap_debugging: HeapDebugging::Disabled,
+            kconfig_example: false,
+            keep_going: false,
+            // A broken template fails, the plain copy is tested on its own
+            post_process: PostProcess::Full,
+            dir_mode: None,
+            max_template_size: None,
+            jobs: None,
+            template_cache: None,
+            template_ttl: TemplateTtl::default(),
+            offline: false,
+            assumed_idf_version: None,
+            template_archive: None,
+            template_sha256: None,
+            main_file: None,
+            app_class: false,
+            main_sources: None,
+            scaffold_only_config: false,
+            clang_format: None,
+            hygiene: vec![],
+            rtos: None,
+            security: SecurityFeatures::default(),
+            partitions: None,
+            component_metadata: None,
+            host_test_asan: false,
+            bootloader_hooks: false,
+            flash_script: false,
+            flash_port: None,
+            ci: None,
+            no_badges: false,
+            no_gitignore: false,
+            no_sdkconfig: false,
+            workspace_apps: vec![],
+            relative_symlinks: false,
+            factory_test: false,
+            starters: vec![],
+            template_ref: None,
+            template_url: Some(template_url),
+            template_prefix: None,
+        }
+    }
+
+    #[test]
+    fn test_generate_from_fixture_server() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+
+        generate(&fixture_options(&dir, url), &CancellationToken::new()).unwrap();
+
+        assert!(dir.join("main").join("main.c").exists());
+        assert!(dir.join("sdkconfig.defaults").exists());
+        assert!(!dir.join("main").join(IDF_COMPONENT_FILE).exists());
+        let cmake = fs::read_to_string(dir.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.ends_with("project(project C)"));
+    }
+
+    #[test]
+    fn test_set_cmake_options_languages() {
+        let root = tempfile::tempdir().unwrap();
+        for (language, project) in [
+            (ProgrammingLanguage::C, "project(blink C)"),
+            (ProgrammingLanguage::Cpp11, "project(blink C CXX)"),
+            (ProgrammingLanguage::Cpp14, "project(blink C CXX)"),
+            (ProgrammingLanguage::Cpp17, "project(blink C CXX)"),
+        ] {
+            let dir = root.path().join(format!("{:?}", language));
+            test_fixtures::write_template(&dir);
+            set_cmake_options(
+                dir.to_str().unwrap(),
+                "",
+                cmake_languages(language),
+                "blink",
+                false,
+                true,
+            )
+            .unwrap();
+            let cmake = fs::read_to_string(dir.join("CMakeLists.txt")).unwrap();
+            assert!(cmake.ends_with(project), "{:?}: {}", language, cmake);
+            assert_eq!(cmake.matches("project(").count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_generate_from_template_archive() {
+        let root = tempfile::tempdir().unwrap();
+        let template = test_fixtures::template_zip();
+        let archive = root.path().join("template.zip");
+        fs::write(&archive, &template).unwrap();
+        // Never downloaded
+        let url = "http://127.0.0.1:1/template.zip".to_string();
+        let mut options = fixture_options(&root.path().join("project"), url);
+        options.template_archive = Some(archive);
+        options.template_sha256 = Some(sha256_hex(&template));
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert_eq!(report.steps[0], "Template read from a local archive");
+        assert!(root.path().join("project").join("CMakeLists.txt").exists());
+
+        // Not the recorded template
+        options.project_name = root.path().join("other").to_string_lossy().into_owned();
+        options.template_sha256 = Some(sha256_hex(b"other"));
+        let error = generate(&options, &CancellationToken::new()).unwrap_err();
+        assert!(error.to_string().contains("changed since it was recorded"));
+        assert!(!root.path().join("other").exists());
+    }
+
+    #[test]
+    fn test_generate_template_prefix() {
+        let root = tempfile::tempdir().unwrap();
+        let archive = root.path().join("template.zip");
+        fs::write(&archive, test_fixtures::template_zip()).unwrap();
+        let url = "http://127.0.0.1:1/template.zip".to_string();
+        let mut options = fixture_options(&root.path().join("project"), url);
+        options.template_archive = Some(archive);
+        options.template_prefix = Some("esp-idf-template-master".into());
+
+        generate(&options, &CancellationToken::new()).unwrap();
+        assert!(root.path().join("project").join("CMakeLists.txt").exists());
+
+        options.project_name = root.path().join("other").to_string_lossy().into_owned();
+        options.template_prefix = Some("blink-starter-1.2.0".into());
+        let error = generate(&options, &CancellationToken::new()).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("no directory \"blink-starter-1.2.0\""));
+    }
+
+    #[test]
+    fn test_generate_with_report() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let template = test_fixtures::template_zip();
+        let template_sha256 = sha256_hex(&template);
+        let url = test_fixtures::serve(template, None);
+        let mut report = GenerationReport::default();
+
+        generate_with_report(
+            &fixture_options(&dir, url),
+            &CancellationToken::new(),
+            &mut report,
+        )
+        .unwrap();
+
+        assert_eq!(report.template_sha256, Some(template_sha256));
+        assert_eq!(
+            report.steps,
+            [
+                "Template downloaded",
+                "Template extracted",
+                "Project files written"
+            ]
+        );
+        let main = report
+            .files
+            .iter()
+            .find(|file| file.path == "main/main.c")
+            .unwrap();
+        let content = fs::read(dir.join("main").join("main.c")).unwrap();
+        assert_eq!(main.sha256, sha256_hex(&content));
+        assert!(!report.cleaned_up);
+
+        // A template without project() fails after the extraction
+        let dir = root.path().join("broken");
+        let url = test_fixtures::serve(
+            test_fixtures::zip_archive(&[("CMakeLists.txt", b"# nothing\n")]),
+            None,
+        );
+        let mut report = GenerationReport::default();
+        assert!(generate_with_report(
+            &fixture_options(&dir, url),
+            &CancellationToken::new(),
+            &mut report
+        )
+        .is_err());
+        assert_eq!(report.steps, ["Template downloaded", "Template extracted"]);
+        assert_eq!(report.files[0].path, "CMakeLists.txt");
+        assert!(report.cleaned_up);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_generate_refuses_zip_bomb() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        // 4 MB of zeros deflate to a few KB
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file("bomb.bin", zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(&vec![0; 4 * 1024 * 1024]).unwrap();
+        let bomb = writer.finish().unwrap().into_inner();
+        assert!(bomb.len() < 64 * 1024);
+        let mut options = fixture_options(&dir, test_fixtures::serve(bomb, None));
+        options.max_template_size = Some(1024 * 1024);
+
+        let error = match generate(&options, &CancellationToken::new()) {
+            Err(EspCreateError::Other(error)) => error,
+            other => panic!("{:?}", other),
+        };
+        assert!(error.is::<TemplateTooLarge>());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_generate_from_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let template = test_fixtures::template_zip();
+        let url = test_fixtures::serve(template.clone(), None);
+        let mut options = fixture_options(&root.path().join("first"), url.clone());
+        options.template_cache = Some(root.path().join("cache"));
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+        let cache = TemplateCache::new(root.path().join("cache"));
+        let (sha256, _) = cache.lookup(&url).unwrap();
+        assert_eq!(report.template_sha256, Some(sha256.clone()));
+        assert_eq!(sha256, sha256_hex(&template));
+
+        // Nothing listens on port 1, the cached archive of the URL is used
+        let unreachable = "http://127.0.0.1:1/template.zip";
+        cache
+            .store(
+                unreachable,
+                &sha256,
+                &mut fs::File::open(cache.archive_path(&sha256)).unwrap(),
+            )
+            .unwrap();
+        options.project_name = root.path().join("second").to_string_lossy().into_owned();
+        options.template_url = Some(unreachable.into());
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+        assert_eq!(report.steps[0], "Template read from the cache");
+        assert!(root.path().join("second").join("main").is_dir());
+
+        // Within the TTL the cached archive is used without downloading
+        options.template_ttl = "1h".parse().unwrap();
+        options.project_name = root.path().join("fresh").to_string_lossy().into_owned();
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+        assert_eq!(
+            report.steps[0],
+            "Template read from the cache (template TTL 1h)"
+        );
+        assert_eq!(report.template_sha256, Some(sha256));
+        options.template_ttl = TemplateTtl::default();
+
+        options.template_cache = None;
+        options.project_name = root.path().join("third").to_string_lossy().into_owned();
+        assert!(generate(&options, &CancellationToken::new()).is_err());
+    }
+
+    #[test]
+    fn test_generate_offline() {
+        let root = tempfile::tempdir().unwrap();
+        let (url, requests) = test_fixtures::serve_counted(test_fixtures::template_zip());
+        let mut options = fixture_options(&root.path().join("online"), url);
+        options.template_cache = Some(root.path().join("cache"));
+        generate(&options, &CancellationToken::new()).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // Past its TTL, the cached archive is used rather than downloaded
+        options.offline = true;
+        options.project_name = root.path().join("cached").to_string_lossy().into_owned();
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+        assert_eq!(report.steps[0], "Template read from the cache (--offline)");
+        assert!(root.path().join("cached").join("main").is_dir());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        options.template_cache = None;
+        options.project_name = root.path().join("uncached").to_string_lossy().into_owned();
+        let error = generate(&options, &CancellationToken::new()).unwrap_err();
+        assert!(format!("{:#}", error).contains("--offline"), "{:#}", error);
+        assert!(!root.path().join("uncached").exists());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // A local template needs no network
+        let template = root.path().join("template.zip");
+        fs::write(&template, test_fixtures::template_zip()).unwrap();
+        options.template_url = Some(template.to_string_lossy().into_owned());
+        options.project_name = root.path().join("local").to_string_lossy().into_owned();
+        generate(&options, &CancellationToken::new()).unwrap();
+        assert!(root.path().join("local").join("main").is_dir());
+    }
+
+    #[test]
+    fn test_generate_into_worktree() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        fs::create_dir_all(&dir).unwrap();
+        let git_file = "gitdir: ../repo/.git/worktrees/project\n";
+        fs::write(dir.join(".git"), git_file).unwrap();
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.use_git = true;
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        // Not turned into a repository of its own
+        assert_eq!(fs::read_to_string(dir.join(".git")).unwrap(), git_file);
+        assert!(report.steps.last().unwrap().starts_with("Git init skipped"));
+        assert!(dir.join("main").join("main.c").exists());
+
+        // Cleaned up without losing the link to the repository
+        let url = test_fixtures::serve(
+            test_fixtures::zip_archive(&[("CMakeLists.txt", b"# nothing\n")]),
+            None,
+        );
+        fs::remove_dir_all(dir.join("main")).unwrap();
+        assert!(generate(&fixture_options(&dir, url), &CancellationToken::new()).is_err());
+        let entries: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, [".git"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_initialize_git_repo_failure() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().join("project").to_string_lossy().into_owned();
+        let failing_git = root.path().join("git");
+        fs::write(
+            &failing_git,
+            "#!/bin/sh\necho \"fatal: invalid branch name\" >&2\nexit 128\n",
+        )
+        .unwrap();
+        fs::set_permissions(&failing_git, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let error = initialize_git_repo(&failing_git.to_string_lossy(), &directory, "project")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("fatal: invalid branch name"), "{}", error);
+        let missing_git = root.path().join("missing").join("git");
+        assert!(
+            initialize_git_repo(&missing_git.to_string_lossy(), &directory, "project").is_err()
+        );
+
+        let mut report = GenerationReport::default();
+        initialize_git_repo_or_warn(
+            &failing_git.to_string_lossy(),
+            &directory,
+            "project",
+            &mut StepRunner::default(),
+            &mut report,
+        )
+        .unwrap();
+        assert!(!report.git_initialized);
+        assert_eq!(report.manual_steps.len(), 1);
+        assert!(report.steps[0].starts_with("Git init failed"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_initialize_git_repo_retry_and_abort() {
+        use crate::steps::{Recovery, StageStatus};
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().join("project").to_string_lossy().into_owned();
+        // Fails once, as if git was being installed, then succeeds
+        let flaky_git = root.path().join("git");
+        let marker = root.path().join("failed-once");
+        fs::write(
+            &flaky_git,
+            format!(
+                "#!/bin/sh\nif [ -e \"{0}\" ]; then exit 0; fi\ntouch \"{0}\"\necho \"fatal: busy\" >&2\nexit 1\n",
+                marker.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&flaky_git, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut answers = vec![Recovery::Retry].into_iter();
+        let mut runner = StepRunner::with_prompt(Box::new(move |_, _| Ok(answers.next().unwrap())));
+        let mut report = GenerationReport::default();
+        initialize_git_repo_or_warn(
+            &flaky_git.to_string_lossy(),
+            &directory,
+            "project",
+            &mut runner,
+            &mut report,
+        )
+        .unwrap();
+        assert!(report.git_initialized);
+        assert!(report.manual_steps.is_empty());
+        assert_eq!(runner.stages()[0].status, StageStatus::Done);
+        assert_eq!(runner.stages()[0].attempts, 2);
+
+        let failing_git = root.path().join("missing").join("git");
+        let mut runner = StepRunner::with_prompt(Box::new(|_, _| Ok(Recovery::Abort)));
+        let mut report = GenerationReport::default();
+        assert!(initialize_git_repo_or_warn(
+            &failing_git.to_string_lossy(),
+            &directory,
+            "project",
+            &mut runner,
+            &mut report,
+        )
+        .is_err());
+        assert!(!report.git_initialized);
+        assert_eq!(runner.stages()[0].status, StageStatus::Failed);
+    }
+
+    #[test]
+    fn test_generate_kconfig_example() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.kconfig_example = true;
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let kconfig = fs::read_to_string(dir.join("main").join("Kconfig.projbuild")).unwrap();
+        assert!(kconfig.contains("config EXAMPLE_WIFI_SSID"));
+        let main = fs::read_to_string(dir.join("main").join("main.c")).unwrap();
+        assert!(main.contains("CONFIG_EXAMPLE_WIFI_SSID"));
+    }
+
+    #[test]
+    fn test_generate_clang_format() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.clang_format = Some(ClangFormatStyle {
+            indent_width: 2,
+            column_limit: 120,
+        });
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let clang_format = fs::read_to_string(dir.join(".clang-format")).unwrap();
+        assert!(clang_format.contains("IndentWidth: 2\n"));
+        assert!(clang_format.contains("ColumnLimit: 120\n"));
+    }
+
+    #[test]
+    fn test_generate_no_gitignore() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let archive = root.path().join("template.zip");
+        fs::write(
+            &archive,
+            test_fixtures::zip_archive(&[
+                ("CMakeLists.txt", test_fixtures::TEMPLATE_CMAKE.as_bytes()),
+                ("main/main.c", test_fixtures::TEMPLATE_MAIN_C.as_bytes()),
+                (".gitignore", b"build/\n"),
+            ]),
+        )
+        .unwrap();
+        let mut options = fixture_options(&dir, "http://127.0.0.1:1/template.zip".into());
+        options.template_archive = Some(archive);
+        options.hygiene = vec![HygieneFile::Gitignore, HygieneFile::EditorConfig];
+        options.no_gitignore = true;
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(!dir.join(".gitignore").exists());
+        assert!(dir.join(".editorconfig").exists());
+    }
+
+    #[test]
+    fn test_generate_no_sdkconfig() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url.clone());
+        options.target = Some("esp32c6".into());
+        options.heap_debugging = HeapDebugging::Light;
+        options.no_sdkconfig = true;
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(!dir.join("sdkconfig.defaults").exists());
+        assert_eq!(
+            report.manual_steps,
+            ["Set CONFIG_IDF_TARGET=\"esp32c6\", CONFIG_HEAP_POISONING_LIGHT=y with idf.py menuconfig \
+              (idf.py set-target for the target), sdkconfig.defaults was not written"]
+        );
+
+        // The apps of a workspace don't read the missing files
+        let dir = root.path().join("firmware");
+        let mut options = fixture_options(&dir, url);
+        options.workspace_apps = vec!["app".into()];
+        options.no_sdkconfig = true;
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(report.manual_steps.is_empty());
+        assert!(!dir.join("sdkconfig.defaults").exists());
+        assert!(!dir.join("app").join("sdkconfig.defaults").exists());
+        let cmake = fs::read_to_string(dir.join("app").join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains("set(EXTRA_COMPONENT_DIRS ../components)"));
+        assert!(!cmake.contains("SDKCONFIG_DEFAULTS"));
+    }
+
+    #[test]
+    fn test_global_gitignore() {
+        let config_home = tempfile::tempdir().unwrap();
+        assert_eq!(
+            global_gitignore("no-such-git-program", config_home.path()),
+            None
+        );
+        let ignore = config_home.path().join("git").join("ignore");
+        fs::create_dir_all(ignore.parent().unwrap()).unwrap();
+        fs::write(&ignore, "build/\n").unwrap();
+        assert_eq!(
+            global_gitignore("no-such-git-program", config_home.path()),
+            Some(ignore)
+        );
+    }
+
+    #[test]
+    fn test_generate_hygiene_files() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.hygiene = vec![HygieneFile::PreCommit, HygieneFile::EditorConfig];
+        options.clang_format = Some(ClangFormatStyle {
+            indent_width: 2,
+            column_limit: 120,
+        });
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        let editorconfig = fs::read_to_string(dir.join(".editorconfig")).unwrap();
+        assert!(editorconfig.contains("indent_size = 2\n"));
+        assert!(dir.join(".clang-format").exists());
+        assert!(dir.join(".pre-commit-config.yaml").exists());
+        assert!(!dir.join(".gitignore").exists());
+        assert!(report
+            .steps
+            .contains(&".pre-commit-config.yaml written".to_string()));
+        assert_eq!(
+            ProjectMetadata::load(&dir).unwrap().hygiene,
+            [
+                HygieneFile::EditorConfig,
+                HygieneFile::ClangFormat,
+                HygieneFile::PreCommit
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_partitions() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.partitions = Some(PartitionLayout {
+            flash_size_mb: 4,
+            scheme: PartitionScheme::TwoOta,
+            app_size_kb: None,
+        });
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let csv = fs::read_to_string(dir.join("partitions.csv")).unwrap();
+        assert_eq!(csv, PartitionScheme::TwoOta.csv());
+        let defaults = fs::read_to_string(dir.join("sdkconfig.defaults")).unwrap();
+        assert!(defaults.contains("CONFIG_ESPTOOLPY_FLASHSIZE_4MB=y\n"));
+        assert!(defaults.contains("CONFIG_PARTITION_TABLE_CUSTOM_FILENAME=\"partitions.csv\"\n"));
+    }
+
+    #[test]
+    fn test_generate_board() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.board = Some("esp32-s3-devkitc-1".into());
+        options.starters = vec!["led".into()];
+        options.rtos = Some(RtosTuning::default());
+        options.security.secure_boot = true;
+        options.component_metadata = Some(ComponentMetadata {
+            license: Some("MIT".into()),
+            ..ComponentMetadata::default()
+        });
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let manifest = fs::read_to_string(dir.join("main").join(IDF_COMPONENT_FILE)).unwrap();
+        assert!(manifest.starts_with(
+            "version: \"0.1.0\"\ndescription: \"Main component of project\"\nlicense: \"MIT\"\ntargets:\n  - \"esp32s3\"\n"
+        ));
+        assert_eq!(idf_component::dependencies(&manifest).len(), 1);
+
+        let defaults = fs::read_to_string(dir.join("sdkconfig.defaults")).unwrap();
+        assert!(defaults.starts_with("CONFIG_IDF_TARGET=\"esp32s3\"\n"));
+        assert!(defaults.contains("CONFIG_ESPTOOLPY_FLASHSIZE_8MB=y\n"));
+        assert!(defaults.contains("CONFIG_STARTER_LED_GPIO=38\n"));
+        assert!(defaults.contains("CONFIG_FREERTOS_HZ=1000\n"));
+        assert!(defaults.contains("CONFIG_ESP_MAIN_TASK_STACK_SIZE=4096\n"));
+        assert!(defaults.contains(&format!(
+            "# See {}\nCONFIG_SECURE_BOOT=y\n",
+            crate::options::SECURE_BOOT_DOCS_URL
+        )));
+        assert!(!defaults.contains("CONFIG_SECURE_FLASH_ENC_ENABLED"));
+    }
+
+    #[test]
+    fn test_templatize_round_trip() {
+        fn tree(dir: &Path, relative: &Path, files: &mut Vec<(std::path::PathBuf, String)>) {
+            let mut entries: Vec<_> = fs::read_dir(dir.join(relative))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            entries.sort();
+            for name in entries {
+                let path = relative.join(name);
+                if dir.join(&path).is_dir() {
+                    tree(dir, &path, files);
+                } else {
+                    files.push((path.clone(), fs::read_to_string(dir.join(&path)).unwrap()));
+                }
+            }
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        let alpha = root.path().join("alpha");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&alpha, url);
+        options.language = ProgrammingLanguage::Cpp17;
+        options.kconfig_example = true;
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let template = root.path().join("alpha-template.zip");
+        templatize::templatize(&alpha, &template).unwrap();
+        let beta = root.path().join("beta");
+        options.project_name = beta.to_str().unwrap().into();
+        options.template_url = Some(template.to_str().unwrap().into());
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let (mut expected, mut generated) = (Vec::new(), Vec::new());
+        tree(&alpha, Path::new(""), &mut expected);
+        tree(&beta, Path::new(""), &mut generated);
+        // Only the provenance of the template differs
+        let provenance = Path::new(LOCAL_CONFIG_FILE);
+        expected.retain(|(path, _)| path != provenance);
+        generated.retain(|(path, _)| path != provenance);
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(path, content)| (path, content.replace("alpha", "beta")))
+            .collect();
+        assert_eq!(generated, expected);
+        let metadata = ProjectMetadata::load(&beta).unwrap();
+        assert_eq!(metadata.project.as_deref(), Some("beta"));
+        assert_eq!(metadata.template.unwrap().url, template.to_str().unwrap());
+    }
+
+    #[test]
+    fn test_generate_host_test() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.language = ProgrammingLanguage::Cpp17;
+        options.host_test_asan = true;
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let host_test = dir.join("host_test");
+        let cmake = fs::read_to_string(host_test.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains("project(host_test CXX)\nset(CMAKE_CXX_STANDARD 17)"));
+        assert!(cmake.contains("add_executable(host_test test_main.cpp)"));
+        assert!(cmake.contains("-fsanitize=address"));
+        let source = fs::read_to_string(host_test.join("test_main.cpp")).unwrap();
+        // Nothing the test needs runs inside assert(), gone with NDEBUG
+        assert!(!source.contains("assert(copy_string"));
+        assert!(source.contains("assert(copied == 3);"));
+        assert!(host_test.join("README.md").exists());
+    }
+
+    #[test]
+    fn test_generate_template_size() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let options = fixture_options(&dir, url);
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        // Measured as extracted, before the post-processing
+        let size = report.template_size.unwrap();
+        assert_eq!(size.files, 5);
+        assert_eq!(
+            size.directories[0].name,
+            crate::template_size::TOP_LEVEL_FILES
+        );
+        assert!(size
+            .directories
+            .iter()
+            .any(|directory| directory.name == "main"));
+        assert!(size.trimming_lines().is_empty());
+    }
+
+    #[test]
+    fn test_generate_flash_script() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.flash_script = true;
+        options.flash_port = Some("/dev/ttyACM0".into());
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let (name, _) = flash::script(None, cfg!(windows));
+        let script = dir.join(name);
+        assert!(fs::read_to_string(&script)
+            .unwrap()
+            .contains("/dev/ttyACM0"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&script).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+    }
+
+    #[test]
+    fn test_generate_ci() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.ci = Some(CiProvider::GitHub);
+        options.target = Some("esp32s3".into());
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        let workflow = fs::read_to_string(dir.join(".github/workflows/build.yml")).unwrap();
+        assert!(workflow.contains("      IDF_TARGET: esp32s3\n"));
+        assert!(report
+            .steps
+            .contains(&".github/workflows/build.yml written".to_string()));
+    }
+
+    #[test]
+    fn test_generate_bootloader_hooks() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.language = ProgrammingLanguage::Cpp17;
+        options.bootloader_hooks = true;
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let components = dir.join("bootloader_components");
+        assert!(components.join("README.md").exists());
+        let cmake =
+            fs::read_to_string(components.join("bootloader_hooks").join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains("idf_component_register(SRCS \"hooks.c\")"));
+        assert!(cmake.contains("-u bootloader_hooks_include"));
+        let source =
+            fs::read_to_string(components.join("bootloader_hooks").join("hooks.c")).unwrap();
+        assert!(source.contains("void bootloader_hooks_include(void)"));
+        assert!(source.contains("void bootloader_after_init(void)"));
+        // Not part of the app build
+        assert!(!fs::read_to_string(dir.join("CMakeLists.txt"))
+            .unwrap()
+            .contains("bootloader_components"));
+    }
+
+    #[test]
+    fn test_generate_assumed_idf_version() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.assumed_idf_version = Some(IdfVersion::new(5, 1));
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+        assert!(readme.ends_with("\nThis project assumes ESP-IDF >= 5.1.\n"));
+        let manifest = fs::read_to_string(dir.join("main").join(IDF_COMPONENT_FILE)).unwrap();
+        assert!(manifest.contains("idf: \">=5.1\""));
+    }
+
+    #[test]
+    fn test_generate_readme_badges() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.target = Some("esp32c6".into());
+        options.component_metadata = Some(ComponentMetadata {
+            license: Some("MIT OR Apache-2.0".into()),
+            ..ComponentMetadata::default()
+        });
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+        assert!(readme.starts_with(
+            "# project\n\n\
+             [![License: MIT OR Apache-2.0](https://img.shields.io/badge/license-MIT%20OR%20Apache--2.0-blue.svg)](https://spdx.org/licenses/)\n\
+             [![ESP-IDF >= 5.1](https://img.shields.io/badge/ESP--IDF-%3E%3D5.1-red.svg)](https://github.com/espressif/esp-idf)\n\
+             \nESP-IDF project"
+        ));
+
+        // Without a license nor a chip that needs a recent ESP-IDF
+        let other = root.path().join("other");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        generate(&fixture_options(&other, url), &CancellationToken::new()).unwrap();
+        let readme = fs::read_to_string(other.join("README.md")).unwrap();
+        assert!(readme.starts_with("# other\n\nESP-IDF project"));
+    }
+
+    #[test]
+    fn test_generate_readme_ci_badge() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.ci = Some(CiProvider::GitHub);
+        options.assumed_idf_version = Some(IdfVersion::new(5, 1));
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+        assert!(readme.starts_with(&format!(
+            "# project\n\n{}\n[![ESP-IDF >= 5.1]",
+            CiProvider::GitHub.badge()
+        )));
+        let workflow = fs::read_to_string(dir.join(templates::GITHUB_CI_FILE)).unwrap();
+        assert!(workflow.contains("    container: espressif/idf:v5.1\n"));
+    }
+
+    #[test]
+    fn test_generate_workspace() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("firmware");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.workspace_apps = vec!["app".into(), "factory".into()];
+        options.starters = vec!["nvs".into()];
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(dir.join("components").join("README.md").exists());
+        assert!(fs::read_to_string(dir.join("sdkconfig.defaults"))
+            .unwrap()
+            .contains("CONFIG_COMPILER_OPTIMIZATION_ASSERTIONS_ENABLE=y"));
+        let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+        assert!(readme.starts_with("# firmware\n"));
+        assert!(readme.contains("factory/"));
+        assert!(!dir.join("main").exists());
+        for app in &options.workspace_apps {
+            let app_dir = dir.join(app);
+            let cmake = fs::read_to_string(app_dir.join("CMakeLists.txt")).unwrap();
+            assert!(cmake.contains("set(EXTRA_COMPONENT_DIRS ../components)"));
+            assert!(cmake
+                .contains("set(SDKCONFIG_DEFAULTS \"../sdkconfig.defaults;sdkconfig.defaults\")"));
+            assert!(cmake.ends_with(&format!("project({} C)", app)));
+            let main = fs::read_to_string(app_dir.join("main").join("main.c")).unwrap();
+            assert!(main.contains("starter_nvs_init();"));
+            assert!(app_dir.join("sdkconfig.defaults").exists());
+            assert!(fs::read_to_string(app_dir.join("README.md"))
+                .unwrap()
+                .starts_with(&format!("# {}\n", app)));
+        }
+    }
+
+    #[test]
+    fn test_generate_workspace_factory_test() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("firmware");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.workspace_apps = vec!["app".into(), "factory".into()];
+        options.starters = vec!["led".into()];
+        options.partitions = Some(PartitionLayout {
+            flash_size_mb: 4,
+            scheme: PartitionScheme::TwoOta,
+            app_size_kb: None,
+        });
+        options.factory_test = true;
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(report
+            .steps
+            .contains(&"App factory_test written".to_string()));
+        let main_dir = dir.join("factory_test").join("main");
+        let main = fs::read_to_string(main_dir.join("main.c")).unwrap();
+        assert!(main.contains(
+            "    starter_led_init();\n    starter_console_init();\n    factory_test_init();\n"
+        ));
+        assert!(fs::read_to_string(main_dir.join("factory_test.c"))
+            .unwrap()
+            .contains("test_led_blink"));
+        assert!(fs::read_to_string(main_dir.join("CMakeLists.txt"))
+            .unwrap()
+            .contains("\"factory_test.c\""));
+        // Only the factory test app has the console
+        assert!(!dir
+            .join("app")
+            .join("main")
+            .join("starter_console.c")
+            .exists());
+        for app in ["app", "factory", "factory_test"] {
+            let csv = fs::read_to_string(dir.join(app).join("partitions.csv")).unwrap();
+            assert!(
+                csv.ends_with("test,     app,  test,    ,        1M,\n"),
+                "{}",
+                csv
+            );
+        }
+        let defaults = fs::read_to_string(dir.join("sdkconfig.defaults")).unwrap();
+        assert!(defaults.contains("CONFIG_BOOTLOADER_APP_TEST=y\n"));
+        let readme = fs::read_to_string(dir.join("README.md")).unwrap();
+        assert!(readme
+            .contains("factory_test/       Factory test app, flashed to the test partition\n"));
+        assert!(readme.contains("- `led_blink`: Light the LED red, green then blue\n"));
+        assert!(readme.contains("--partition-name=test --input build/factory_test.bin"));
+    }
+
+    #[test]
+    fn test_generate_workspace_failed_app() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("firmware");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.workspace_apps = vec!["app".into(), "factory".into(), "test".into()];
+        options.jobs = NonZeroUsize::new(2);
+        // A file where the factory app goes
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("factory"), "").unwrap();
+
+        let error = generate(&options, &CancellationToken::new()).unwrap_err();
+        let error = error.to_string();
+        assert!(
+            error.starts_with("1 of 3 apps failed:\nfactory: "),
+            "{}",
+            error
+        );
+
+        // The other apps are written anyway
+        fs::write(dir.join("factory"), "").unwrap();
+        options.keep_going = true;
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+        assert_eq!(report.failed_apps.len(), 1);
+        assert_eq!(report.failed_apps[0].name, "factory");
+        assert!(report.steps.contains(&"App app written".to_string()));
+        assert!(report.steps.contains(&"App test written".to_string()));
+        assert!(dir.join("app").join("main").join("main.c").is_file());
+        assert!(dir.join("test").join("main").join("main.c").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generate_workspace_relative_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("firmware");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.workspace_apps = vec!["app".into(), "factory".into()];
+        options.bootloader_hooks = true;
+        options.relative_symlinks = true;
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(report
+            .steps
+            .contains(&"bootloader_components/bootloader_hooks shared by the apps".to_string()));
+        let shared = dir.join("bootloader_components").join("bootloader_hooks");
+        assert!(shared.join("hooks.c").is_file());
+        for app in &options.workspace_apps {
+            let link = dir
+                .join(app)
+                .join("bootloader_components")
+                .join("bootloader_hooks");
+            assert_eq!(
+                fs::read_link(&link).unwrap(),
+                Path::new("../../bootloader_components/bootloader_hooks")
+            );
+            assert!(link.join("CMakeLists.txt").is_file());
+        }
+    }
+
+    #[test]
+    fn test_generate_with_starters() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.starters = vec!["nvs".into(), "wifi".into()];
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(report.manual_steps.is_empty());
+        let main = fs::read_to_string(dir.join("main").join("main.c")).unwrap();
+        assert!(main.contains("    starter_nvs_init();\n    starter_wifi_init();\n"));
+        let cmake = fs::read_to_string(dir.join("main").join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains(r#""starter_nvs.c" "starter_wifi.c""#));
+    }
+
+    #[test]
+    fn test_generate_app_class() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.language = ProgrammingLanguage::Cpp17;
+        options.app_class = true;
+        options.starters = vec!["nvs".into()];
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(report.manual_steps.is_empty());
+        let main_dir = dir.join("main");
+        assert_eq!(
+            fs::read_to_string(main_dir.join("main.cpp")).unwrap(),
+            templates::APP_CLASS_MAIN_TEMPLATE
+        );
+        assert!(main_dir.join(templates::APP_CLASS_HEADER_FILE).exists());
+        let app = fs::read_to_string(main_dir.join(templates::APP_CLASS_SOURCE_FILE)).unwrap();
+        assert!(app.contains("void App::init()\n{\n    starter_nvs_init();\n"));
+        let cmake = fs::read_to_string(main_dir.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains(r#""main.cpp" "app.cpp" "starter_nvs.c""#));
+    }
+
+    #[test]
+    fn test_generate_main_sources() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        // The template lists a source that was not planned
+        let main_cmake =
+            test_fixtures::TEMPLATE_MAIN_CMAKE.replace("\"main.c\"", "\"main.c\" util.c");
+        let url = test_fixtures::serve(
+            test_fixtures::zip_archive(&[
+                ("CMakeLists.txt", test_fixtures::TEMPLATE_CMAKE.as_bytes()),
+                ("main/CMakeLists.txt", main_cmake.as_bytes()),
+                ("main/main.c", test_fixtures::TEMPLATE_MAIN_C.as_bytes()),
+                ("main/util.c", b"int util;\n"),
+            ]),
+            None,
+        );
+        let mut options = fixture_options(&dir, url);
+        options.language = ProgrammingLanguage::Cpp17;
+        options.app_class = true;
+        options.starters = vec!["nvs".into()];
+        assert_eq!(
+            options.planned_main_sources().unwrap(),
+            ["main.cpp", "app.cpp", "starter_nvs.c"]
+        );
+        // The generated sources cannot be left out
+        options.main_sources = Some(vec!["main.cpp".into(), "app.cpp".into()]);
+        assert!(options.validate().is_err());
+        options.main_sources = Some(vec![
+            "app.cpp".into(),
+            "main.cpp".into(),
+            "drivers/uart.cpp".into(),
+            "starter_nvs.c".into(),
+        ]);
+        options.validate().unwrap();
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(report.manual_steps.is_empty());
+        let main_dir = dir.join("main");
+        let cmake = fs::read_to_string(main_dir.join("CMakeLists.txt")).unwrap();
+        assert_eq!(
+            cmake::component_sources(&cmake).unwrap(),
+            [
+                "app.cpp",
+                "main.cpp",
+                "drivers/uart.cpp",
+                "starter_nvs.c",
+                "util.c"
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(main_dir.join("drivers").join("uart.cpp")).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_generate_template_replacements() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("sensor");
+        let manifest =
+            "[[replacements]]\nliteral = \"blink_example\"\nwith = \"{{project_name}}\"\n";
+        let url = test_fixtures::serve(
+            test_fixtures::zip_archive(&[
+                ("CMakeLists.txt", test_fixtures::TEMPLATE_CMAKE.as_bytes()),
+                (
+                    "main/CMakeLists.txt",
+                    test_fixtures::TEMPLATE_MAIN_CMAKE.as_bytes(),
+                ),
+                ("main/main.c", test_fixtures::TEMPLATE_MAIN_C.as_bytes()),
+                ("docs/blink.md", b"Run blink_example, blink_example!\n"),
+                ("template.toml", manifest.as_bytes()),
+            ]),
+            None,
+        );
+
+        let report = generate(&fixture_options(&dir, url), &CancellationToken::new()).unwrap();
+
+        assert!(!dir.join("template.toml").exists());
+        let docs = fs::read_to_string(dir.join("docs").join("blink.md")).unwrap();
+        let names = naming::ProjectNames::new(dir.to_str().unwrap()).unwrap();
+        assert_eq!(docs, format!("Run {0}, {0}!\n", names.cmake));
+        assert_eq!(report.replacements.len(), 1);
+        assert_eq!(report.replacements[0].count, 2);
+    }
+
+    #[test]
+    fn test_generate_scaffold_only_config() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.language = ProgrammingLanguage::Cpp17;
+        options.scaffold_only_config = true;
+        options.target = Some("esp32c3".into());
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        let mut files = crate::archive::list_files(&dir, &[]).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "CMakeLists.txt",
+                "main/CMakeLists.txt",
+                "sdkconfig.defaults"
+            ]
+            .map(PathBuf::from)
+        );
+        let cmake = fs::read_to_string(dir.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains("set(CMAKE_CXX_STANDARD 17)"));
+        assert_eq!(
+            report.manual_steps,
+            ["Add your sources to main/ and list them in main/CMakeLists.txt"]
+        );
+    }
+
+    #[test]
+    fn test_generate_scaffold_only_config_existing_sources() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        fs::create_dir_all(dir.join("main")).unwrap();
+        fs::write(dir.join("main/app.c"), "void app_main(void) {}\n").unwrap();
+        let own_cmake = "idf_component_register(SRCS \"app.c\")\n";
+        fs::write(dir.join("main/CMakeLists.txt"), own_cmake).unwrap();
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.scaffold_only_config = true;
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        // The sources and the existing build file are left alone
+        assert_eq!(
+            fs::read_to_string(dir.join("main/app.c")).unwrap(),
+            "void app_main(void) {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("main/CMakeLists.txt")).unwrap(),
+            own_cmake
+        );
+        assert_eq!(report.kept_files, [PathBuf::from("main/CMakeLists.txt")]);
+        assert!(fs::read_to_string(dir.join("CMakeLists.txt"))
+            .unwrap()
+            .contains("project(project"));
+        assert!(dir.join("sdkconfig.defaults").is_file());
+
+        // A failed generation removes nothing
+        options.template_url = Some("http://127.0.0.1:1/template.zip".into());
+        let result = generate(&options, &CancellationToken::new());
+        assert!(result.is_err());
+        assert!(dir.join("main/app.c").is_file());
+    }
+
+    #[test]
+    fn test_generate_plain_copy() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::nonstandard_template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.post_process = PostProcess::Auto;
+        options.starters = vec!["nvs".into()];
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert_eq!(
+            template_layout_issues(&dir),
+            ["no CMakeLists.txt", "no main/ directory"]
+        );
+        assert!(!dir.join("main").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("README.md")).unwrap(),
+            "# Blink for PlatformIO\n"
+        );
+        assert_eq!(
+            report.skipped_post_processing.first().map(String::as_str),
+            Some("main source (no CMakeLists.txt, no main/ directory)")
+        );
+        assert!(report
+            .skipped_post_processing
+            .iter()
+            .any(|step| step.starts_with("starter nvs (")));
+
+        // Post-processing it anyway fails
+        fs::remove_dir_all(&dir).unwrap();
+        options.post_process = PostProcess::Full;
+        assert!(generate(&options, &CancellationToken::new()).is_err());
+    }
+
+    #[test]
+    fn test_generate_plain_copy_prompt() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::nonstandard_template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.post_process = PostProcess::Auto;
+        let asked = Rc::new(RefCell::new(Vec::new()));
+
+        // Copied as it is when the user says so
+        let issues = Rc::clone(&asked);
+        let mut runner = StepRunner::default().with_plain_copy_prompt(Box::new(move |found| {
+            issues.borrow_mut().push(found.join(", "));
+            Ok(true)
+        }));
+        let mut report = GenerationReport::default();
+        generate_with_runner(
+            &options,
+            &CancellationToken::new(),
+            &mut report,
+            &mut runner,
+        )
+        .unwrap();
+        assert_eq!(*asked.borrow(), ["no CMakeLists.txt, no main/ directory"]);
+        assert!(!report.skipped_post_processing.is_empty());
+
+        // Post-processed anyway otherwise, which fails on this template
+        fs::remove_dir_all(&dir).unwrap();
+        let mut runner = StepRunner::default().with_plain_copy_prompt(Box::new(|_| Ok(false)));
+        let mut report = GenerationReport::default();
+        let result = generate_with_runner(
+            &options,
+            &CancellationToken::new(),
+            &mut report,
+            &mut runner,
+        );
+        assert!(result.is_err());
+        assert!(report.skipped_post_processing.is_empty());
+
+        // Not asked about a template with the expected layout
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.post_process = PostProcess::Auto;
+        let mut runner =
+            StepRunner::default().with_plain_copy_prompt(Box::new(|_| panic!("asked")));
+        generate_with_runner(
+            &options,
+            &CancellationToken::new(),
+            &mut GenerationReport::default(),
+            &mut runner,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_archive_layout_issues() {
+        let prefix = Path::new("esp-idf-template-master");
+        let mut zip = zip::ZipArchive::new(Cursor::new(test_fixtures::template_zip())).unwrap();
+        assert!(archive_layout_issues(&mut zip, prefix).is_empty());
+        let mut zip =
+            zip::ZipArchive::new(Cursor::new(test_fixtures::nonstandard_template_zip())).unwrap();
+        assert_eq!(
+            archive_layout_issues(&mut zip, Path::new("blink-pio")),
+            ["no CMakeLists.txt", "no main/ directory"]
+        );
+        let mut zip = zip::ZipArchive::new(Cursor::new(test_fixtures::zip_archive(&[
+            ("CMakeLists.txt", b"# nothing\n"),
+            ("main/main.c", b""),
+        ])))
+        .unwrap();
+        assert_eq!(
+            archive_layout_issues(&mut zip, Path::new("")),
+            ["CMakeLists.txt has no project(...) command"]
+        );
+    }
+
+    #[test]
+    fn test_generate_without_post_processing() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.post_process = PostProcess::None;
+
+        let report = generate(&options, &CancellationToken::new()).unwrap();
+
+        assert!(template_layout_issues(&dir).is_empty());
+        assert_eq!(
+            fs::read_to_string(dir.join("main").join("main.c")).unwrap(),
+            test_fixtures::TEMPLATE_MAIN_C
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("CMakeLists.txt")).unwrap(),
+            test_fixtures::TEMPLATE_CMAKE
+        );
+        assert!(report
+            .skipped_post_processing
+            .contains(&"README (--post-process none)".to_string()));
+    }
+
+    #[test]
+    fn test_generate_with_main_file() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let main_file = root.path().join("app.cpp");
+        fs::write(&main_file, "// my code\n").unwrap();
+        let url = test_fixtures::serve(test_fixtures::template_zip(), None);
+        let mut options = fixture_options(&dir, url);
+        options.language = ProgrammingLanguage::Cpp17;
+        options.main_file = Some(main_file);
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("main").join("main.cpp")).unwrap(),
+            "// my code\n"
+        );
+        assert!(!dir.join("main").join("main.c").exists());
+        let cmake = fs::read_to_string(dir.join("main").join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains("main.cpp"));
+    }
+
+    #[test]
+    fn test_generate_with_template_main_in_other_language() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let template = test_fixtures::zip_archive(&[
+            (test_fixtures::TEMPLATE_PREFIX, b""),
+            (
+                "esp-idf-template-master/CMakeLists.txt",
+                test_fixtures::TEMPLATE_CMAKE.as_bytes(),
+            ),
+            ("esp-idf-template-master/main/", b""),
+            (
+                "esp-idf-template-master/main/CMakeLists.txt",
+                b"idf_component_register(SRCS \"app.c\" \"led.c\"\n    INCLUDE_DIRS \".\")\n",
+            ),
+            (
+                "esp-idf-template-master/main/app.c",
+                b"void app_main(void)\n{\n}\n",
+            ),
+            (
+                "esp-idf-template-master/main/led.c",
+                b"void led_on(void) {}\n",
+            ),
+        ]);
+        let mut options = fixture_options(&dir, test_fixtures::serve(template, None));
+        options.language = ProgrammingLanguage::Cpp17;
+
+        generate(&options, &CancellationToken::new()).unwrap();
+
+        let main_dir = dir.join("main");
+        assert!(main_dir.join("app.cpp").is_file());
+        assert!(!main_dir.join("app.c").exists());
+        assert!(!main_dir.join("main.cpp").exists());
+        assert!(main_dir.join("led.c").is_file());
+        assert_eq!(
+            fs::read_to_string(main_dir.join("CMakeLists.txt")).unwrap(),
+            "idf_component_register(SRCS \"app.cpp\" \"led.c\"\n    INCLUDE_DIRS \".\")\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_cancelled_while_downloading() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        let url = test_fixtures::serve(
+            test_fixtures::template_zip(),
+            Some(Duration::from_millis(20)),
+        );
+
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                token.cancel();
+            })
+        };
+
+        let result = generate(&fixture_options(&dir, url), &token);
+        canceller.join().unwrap();
+
+        assert!(matches!(result, Err(EspCreateError::Cancelled)));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_generate_cancelled_while_extracting() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        // Enough files for the extraction to take a while
+        let content = vec![b'#'; 1024];
+        let fillers: Vec<String> = (0..2000)
+            .map(|i| format!("esp-idf-template-master/filler/{}.txt", i))
+            .collect();
+        let mut entries: Vec<(&str, &[u8])> = vec![
+            (test_fixtures::TEMPLATE_PREFIX, b""),
+            (
+                "esp-idf-template-master/CMakeLists.txt",
+                test_fixtures::TEMPLATE_CMAKE.as_bytes(),
+            ),
+            ("esp-idf-template-master/main/", b""),
+            (
+                "esp-idf-template-master/main/CMakeLists.txt",
+                test_fixtures::TEMPLATE_MAIN_CMAKE.as_bytes(),
+            ),
+            (
+                "esp-idf-template-master/main/main.c",
+                test_fixtures::TEMPLATE_MAIN_C.as_bytes(),
+            ),
+            ("esp-idf-template-master/filler/", b""),
+        ];
+        entries.extend(
+            fillers
+                .iter()
+                .map(|name| (name.as_str(), content.as_slice())),
+        );
+        let archive = root.path().join("template.zip");
+        fs::write(&archive, test_fixtures::zip_archive(&entries)).unwrap();
+        let mut options = fixture_options(&dir, String::new());
+        options.template_archive = Some(archive);
+
+        // Cancelled once the first filler file is written
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            let first_filler = dir.join("filler").join("0.txt");
+            thread::spawn(move || {
+                for _ in 0..10_000 {
+                    if first_filler.exists() {
+                        token.cancel();
+                        return true;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+                false
+            })
+        };
+
+        let result = generate(&options, &token);
+        assert!(canceller.join().unwrap());
+
+        assert!(matches!(result, Err(EspCreateError::Cancelled)));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_generate_cancelled_keeps_existing_directory_empty() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("project");
+        fs::create_dir(&dir).unwrap();
+        let url = test_fixtures::serve(
+            test_fixtures::template_zip(),
+            Some(Duration::from_millis(20)),
+        );
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = generate(&fixture_options(&dir, url), &token);
+
+        assert!(matches!(result, Err(EspCreateError::Cancelled)));
+        assert!(dir.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,126 @@
+//! Hygiene files of the project: editor, formatter, git and pre-commit
+//! settings, picked together in one list
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::options::ClangFormatStyle;
+use crate::templates;
+
+/// A hygiene file that can be added to the project
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize)]
+pub enum HygieneFile {
+    #[serde(rename = "editorconfig")]
+    EditorConfig,
+    #[serde(rename = "clang-format")]
+    ClangFormat,
+    #[serde(rename = "gitignore")]
+    Gitignore,
+    #[serde(rename = "gitattributes")]
+    Gitattributes,
+    #[serde(rename = "pre-commit")]
+    PreCommit,
+}
+
+impl HygieneFile {
+    /// Every hygiene file, in the order they are listed and written
+    pub const ALL: &'static [HygieneFile] = &[
+        HygieneFile::EditorConfig,
+        HygieneFile::ClangFormat,
+        HygieneFile::Gitignore,
+        HygieneFile::Gitattributes,
+        HygieneFile::PreCommit,
+    ];
+
+    /// Name of the file, relative to the project
+    pub fn file_name(self) -> &'static str {
+        match self {
+            HygieneFile::EditorConfig => templates::EDITORCONFIG_FILE,
+            HygieneFile::ClangFormat => templates::CLANG_FORMAT_FILE,
+            HygieneFile::Gitignore => templates::GITIGNORE_FILE,
+            HygieneFile::Gitattributes => templates::GITATTRIBUTES_FILE,
+            HygieneFile::PreCommit => templates::PRE_COMMIT_CONFIG_FILE,
+        }
+    }
+
+    /// What the file does, in one line
+    pub fn description(self) -> &'static str {
+        match self {
+            HygieneFile::EditorConfig => "indentation, charset and line endings for every editor",
+            HygieneFile::ClangFormat => "formatting of the C and C++ sources",
+            HygieneFile::Gitignore => {
+                "keeps build/, managed_components/ and sdkconfig.old out of git"
+            }
+            HygieneFile::Gitattributes => "LF line endings in the repository, binaries left alone",
+            HygieneFile::PreCommit => "whitespace and clang-format checks before each commit",
+        }
+    }
+
+    /// Renders the content of the file, nothing is written
+    ///
+    /// # Arguments
+    /// * `style` - The clang-format style, `.editorconfig` uses its indent width
+    pub fn render(self, style: ClangFormatStyle) -> String {
+        match self {
+            HygieneFile::EditorConfig => templates::EDITORCONFIG_TEMPLATE
+                .replace("{{indent_width}}", &style.indent_width.to_string()),
+            HygieneFile::ClangFormat => templates::clang_format(style),
+            HygieneFile::Gitignore => templates::GITIGNORE_TEMPLATE.into(),
+            HygieneFile::Gitattributes => templates::GITATTRIBUTES_TEMPLATE.into(),
+            HygieneFile::PreCommit => templates::PRE_COMMIT_CONFIG_TEMPLATE.into(),
+        }
+    }
+}
+
+impl FromStr for HygieneFile {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        HygieneFile::ALL
+            .iter()
+            .copied()
+            .find(|file| file.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown hygiene file \"{}\", expected editorconfig, clang-format, gitignore, gitattributes or pre-commit",
+                    name
+                )
+            })
+    }
+}
+
+impl fmt::Display for HygieneFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HygieneFile::EditorConfig => "editorconfig",
+            HygieneFile::ClangFormat => "clang-format",
+            HygieneFile::Gitignore => "gitignore",
+            HygieneFile::Gitattributes => "gitattributes",
+            HygieneFile::PreCommit => "pre-commit",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let style = ClangFormatStyle {
+            indent_width: 2,
+            ..ClangFormatStyle::default()
+        };
+        let editorconfig = HygieneFile::EditorConfig.render(style);
+        assert!(editorconfig.contains("[*.{c,h,cpp,hpp}]\nindent_style = space\nindent_size = 2\n"));
+        assert!(HygieneFile::ClangFormat
+            .render(style)
+            .contains("IndentWidth: 2\n"));
+        for file in HygieneFile::ALL {
+            assert_eq!(file.to_string().parse::<HygieneFile>(), Ok(*file));
+            assert!(file.render(style).ends_with('\n'), "{}", file);
+        }
+        assert!("prettier".parse::<HygieneFile>().is_err());
+    }
+}
@@ -0,0 +1,574 @@
+//! Translations of the prompts, picked with `--lang` or the locale of the
+//! environment (`LC_ALL`, `LC_MESSAGES`, `LANG`), English when unknown
+//!
+//! Each locale has a table of messages; a message missing from a table is
+//! shown in English.
+
+use std::sync::OnceLock;
+
+/// The variables the locale is read from, the first one set wins
+pub const LOCALE_VARIABLES: &[&str] = &["LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// A language of the prompts
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    /// Every locale, by its language code
+    pub const ALL: &'static [(&'static str, Locale)] = &[("en", Locale::En), ("es", Locale::Es)];
+
+    /// The language code of the locale, e.g. `es`
+    pub fn tag(self) -> &'static str {
+        Locale::ALL
+            .iter()
+            .find(|(_, locale)| *locale == self)
+            .map_or("en", |(code, _)| *code)
+    }
+
+    /// Parses a locale name, e.g. `es`, `es-MX` or `es_MX.UTF-8`
+    ///
+    /// # Returns
+    /// The locale of its language, `None` if it has no translation
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag.split(['_', '-', '.', '@']).next()?.to_ascii_lowercase();
+        Locale::ALL
+            .iter()
+            .find(|(code, _)| *code == language)
+            .map(|(_, locale)| *locale)
+    }
+
+    /// The locale of the prompts: `--lang` if passed, otherwise the first
+    /// locale variable set, English if it has no translation
+    ///
+    /// # Arguments
+    /// * `flag` - The value of `--lang`
+    /// * `var` - Reads an environment variable
+    pub fn detect(flag: Option<&str>, var: impl Fn(&str) -> Option<String>) -> Locale {
+        let tag = flag.map(String::from).or_else(|| {
+            LOCALE_VARIABLES
+                .iter()
+                .filter_map(|name| var(name))
+                .find(|value| !value.is_empty())
+        });
+        tag.and_then(|tag| Locale::from_tag(&tag))
+            .unwrap_or_default()
+    }
+}
+
+/// A translatable prompt
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Message {
+    Default,
+    Yes,
+    DeleteDirectory,
+    DeleteCheckoutContent,
+    CreateNestedAnyway,
+    ProgrammingLanguage,
+    AppClass,
+    Board,
+    CustomBoard,
+    Chip,
+    Template,
+    InitGit,
+    Starters,
+    AdvancedOptions,
+    HygieneFiles,
+    WriteHygieneFiles,
+    PreviewFile,
+    ChangeSelection,
+    PreviewWhichFile,
+    IndentWidth,
+    ColumnLimit,
+    TickRate,
+    MainTaskStack,
+    ComponentMetadata,
+    ComponentVersion,
+    ComponentDescription,
+    ComponentUrl,
+    ComponentMaintainers,
+    ComponentLicense,
+    PartitionTable,
+    FlashSize,
+    PartitionScheme,
+    AppSize,
+    SchemeAppSize,
+    Assertions,
+    HeapDebugging,
+    SecurityFeatures,
+    ConfirmSecurity,
+    OnboardingDefaults,
+    OnboardingUpdateCheck,
+    StepFailed,
+    RetryStep,
+    SkipStep,
+    AbortGeneration,
+    PlainCopy,
+    TickRate100,
+    TickRate250,
+    TickRate500,
+    TickRate1000,
+    MainTaskStack3584,
+    MainTaskStack4096,
+    MainTaskStack6144,
+    MainTaskStack8192,
+    SecureBootItem,
+    FlashEncryptionItem,
+    SecureBoot,
+    FlashEncryption,
+    SecureBootAndFlashEncryption,
+    AssertionsAbort,
+    AssertionsSilent,
+    AssertionsDisabled,
+    HeapDebuggingDisabled,
+    HeapDebuggingLight,
+    HeapDebuggingComprehensive,
+    HeapDebuggingTracing,
+    SchemeAppSizeItem,
+    SchemeSingleApp,
+    SchemeSingleAppLarge,
+    SchemeTwoOta,
+    SchemeFactoryTwoOta,
+    AppsResized,
+    SchemeTooLarge,
+    EditorConfigItem,
+    ClangFormatItem,
+    GitignoreItem,
+    GitattributesItem,
+    PreCommitItem,
+    CommitLockFile,
+    ApplyFix,
+    ChooseNumber,
+    ChooseNumbers,
+    YesNoDefaultYes,
+    YesNoDefaultNo,
+    YesNo,
+    InvalidAnswer,
+    MultiSelectKeys,
+    ChooseOrder,
+    SortKeys,
+    EditMainSources,
+    AddMainSources,
+    OrderMainSources,
+}
+
+/// English, the language of every message
+fn english(message: Message) -> &'static str {
+    match message {
+        Message::Default => "default",
+        Message::Yes => "Yes",
+        Message::DeleteDirectory => "Directory \"{1}\" not empty, delete?",
+        Message::DeleteCheckoutContent => {
+            "Directory \"{1}\" not empty, delete its content? (it's {2}, .git is kept)"
+        }
+        Message::CreateNestedAnyway => "Create the project there anyway?",
+        Message::ProgrammingLanguage => "Programming language?",
+        Message::AppClass => "Application class layout? (main/app.hpp and main/app.cpp)",
+        Message::Board => "Board?",
+        Message::CustomBoard => "Custom",
+        Message::Chip => "Chip?",
+        Message::Template => "Template?",
+        Message::InitGit => "Initialize git repo? (needs git)",
+        Message::Starters => "Starters?",
+        Message::AdvancedOptions => "Configure advanced options?",
+        Message::HygieneFiles => "Hygiene files?",
+        Message::WriteHygieneFiles => "Write these files?",
+        Message::PreviewFile => "Preview a file",
+        Message::ChangeSelection => "Change the selection",
+        Message::PreviewWhichFile => "Preview which file?",
+        Message::IndentWidth => "Indent width?",
+        Message::ColumnLimit => "Column limit?",
+        Message::TickRate => "FreeRTOS tick rate?",
+        Message::MainTaskStack => "Main task stack size?",
+        Message::ComponentMetadata => {
+            "Add the metadata to publish main/ to the component registry?"
+        }
+        Message::ComponentVersion => "Version?",
+        Message::ComponentDescription => "Description? (empty: derived from the project name)",
+        Message::ComponentUrl => "Homepage URL? (optional)",
+        Message::ComponentMaintainers => "Maintainers? (comma separated, optional)",
+        Message::ComponentLicense => "SPDX license? (optional)",
+        Message::PartitionTable => "Add a custom partition table?",
+        Message::FlashSize => "Flash size in MB?",
+        Message::PartitionScheme => "Partition scheme?",
+        Message::AppSize => "App partition size?",
+        Message::SchemeAppSize => "the size of the scheme",
+        Message::Assertions => "Assertions?",
+        Message::HeapDebugging => "Heap debugging?",
+        Message::SecurityFeatures => {
+            "Enable security features? (IRREVERSIBLE on real hardware, none by default)"
+        }
+        Message::ConfirmSecurity => {
+            "{1} burn eFuses on the first boot and cannot be turned off, and you must keep the keys safe. Enable anyway?"
+        }
+        Message::OnboardingDefaults => {
+            "Set the default language, board and git choice of new projects?"
+        }
+        Message::OnboardingUpdateCheck => {
+            "Check once a day for a new release? Nothing else is sent, there is no telemetry"
+        }
+        Message::StepFailed => "{1} failed, what now?",
+        Message::RetryStep => "Retry",
+        Message::SkipStep => "Skip it, the project is complete without it",
+        Message::AbortGeneration => "Abort and remove the project",
+        Message::PlainCopy => {
+            "The template is not laid out like an ESP-IDF project ({1}), copy it as it is?"
+        }
+        Message::TickRate100 => {
+            "100 Hz: IDF default, vTaskDelay(pdMS_TO_TICKS(10)) is a single tick and shorter delays don't wait at all"
+        }
+        Message::TickRate250 => "250 Hz: 4 ms resolution",
+        Message::TickRate500 => "500 Hz: 2 ms resolution",
+        Message::TickRate1000 => {
+            "1000 Hz: 1 ms resolution, delays last what pdMS_TO_TICKS() asks for, slightly more time spent in the tick"
+        }
+        Message::MainTaskStack3584 => {
+            "3584 bytes: IDF default, overflows as soon as app_main printf()s floats or keeps buffers on the stack"
+        }
+        Message::MainTaskStack4096 => "4096 bytes: room for printf-heavy code",
+        Message::MainTaskStack6144 => "6144 bytes: room for larger local buffers",
+        Message::MainTaskStack8192 => {
+            "8192 bytes: for an app_main that does the heavy work itself (TLS, JSON...)"
+        }
+        Message::SecureBootItem => {
+            "Secure boot: only apps signed with your key boot, losing the key bricks the board"
+        }
+        Message::FlashEncryptionItem => {
+            "Flash encryption (development mode): the flash is encrypted with a key burned in eFuses"
+        }
+        Message::SecureBoot => "Secure boot",
+        Message::FlashEncryption => "Flash encryption",
+        Message::SecureBootAndFlashEncryption => "Secure boot and flash encryption",
+        Message::AssertionsAbort => "Abort: print the failed assertion and abort (IDF default)",
+        Message::AssertionsSilent => "Silent: abort without printing, saves flash",
+        Message::AssertionsDisabled => "Disabled: compile assertions out, not recommended",
+        Message::HeapDebuggingDisabled => "Disabled: no checks, full speed (IDF default)",
+        Message::HeapDebuggingLight => {
+            "Light poisoning: catches buffer overruns when memory is freed, small cost"
+        }
+        Message::HeapDebuggingComprehensive => {
+            "Comprehensive poisoning: also catches use of freed or uninitialized memory, much slower allocations"
+        }
+        Message::HeapDebuggingTracing => {
+            "Comprehensive + tracing: also records allocations to find leaks, uses extra RAM"
+        }
+        Message::SchemeAppSizeItem => "The size of the scheme (1MB or 1.5MB)",
+        Message::SchemeSingleApp => "Single app (1MB factory)",
+        Message::SchemeSingleAppLarge => "Single large app (1.5MB factory)",
+        Message::SchemeTwoOta => "OTA (two 1MB slots)",
+        Message::SchemeFactoryTwoOta => "Factory app and OTA (three 1MB apps)",
+        Message::AppsResized => "{1}, apps resized to {2}KB",
+        Message::SchemeTooLarge => "{1} (too large)",
+        Message::EditorConfigItem => "indentation, charset and line endings for every editor",
+        Message::ClangFormatItem => "formatting of the C and C++ sources",
+        Message::GitignoreItem => "keeps build/, managed_components/ and sdkconfig.old out of git",
+        Message::GitattributesItem => "LF line endings in the repository, binaries left alone",
+        Message::PreCommitItem => "whitespace and clang-format checks before each commit",
+        Message::CommitLockFile => "Commit dependencies.lock to the git repo?",
+        Message::ApplyFix => "Apply the fix?",
+        Message::ChooseNumber => "Enter a number from 1 to {1}",
+        Message::ChooseNumbers => "Enter numbers from 1 to {1} separated by commas, 0 for none",
+        Message::YesNoDefaultYes => "[Y/n]",
+        Message::YesNoDefaultNo => "[y/N]",
+        Message::YesNo => "[y/n]",
+        Message::InvalidAnswer => "Invalid answer \"{1}\"",
+        Message::MultiSelectKeys => "(space to select, enter to confirm)",
+        Message::ChooseOrder => {
+            "Enter numbers from 1 to {1} in the new order, the others follow in their order"
+        }
+        Message::SortKeys => "(space to pick up or drop, arrows to move, enter to confirm)",
+        Message::EditMainSources => "Edit the sources of main/CMakeLists.txt ({1})?",
+        Message::AddMainSources => "Sources to add, relative to main/ and separated by commas",
+        Message::OrderMainSources => "Order of the sources?",
+    }
+}
+
+/// Spanish
+fn spanish(message: Message) -> Option<&'static str> {
+    Some(match message {
+        Message::Default => "por defecto",
+        Message::Yes => "Sí",
+        Message::DeleteDirectory => "El directorio \"{1}\" no está vacío, ¿borrarlo?",
+        Message::DeleteCheckoutContent => {
+            "El directorio \"{1}\" no está vacío, ¿borrar su contenido? ({2}, se conserva .git)"
+        }
+        Message::CreateNestedAnyway => "¿Crear el proyecto ahí de todos modos?",
+        Message::ProgrammingLanguage => "¿Lenguaje de programación?",
+        Message::AppClass => "¿Estructura con clase de aplicación? (main/app.hpp y main/app.cpp)",
+        Message::Board => "¿Placa de desarrollo?",
+        Message::CustomBoard => "Personalizada",
+        Message::Chip => "¿Chip?",
+        Message::Template => "¿Plantilla?",
+        Message::InitGit => "¿Inicializar un repositorio git? (requiere git)",
+        Message::Starters => "¿Starters?",
+        Message::AdvancedOptions => "¿Configurar las opciones avanzadas?",
+        Message::HygieneFiles => "¿Archivos de higiene?",
+        Message::WriteHygieneFiles => "¿Escribir estos archivos?",
+        Message::PreviewFile => "Ver un archivo",
+        Message::ChangeSelection => "Cambiar la selección",
+        Message::PreviewWhichFile => "¿Qué archivo ver?",
+        Message::IndentWidth => "¿Ancho de la sangría?",
+        Message::ColumnLimit => "¿Límite de columnas?",
+        Message::TickRate => "¿Frecuencia del tick de FreeRTOS?",
+        Message::MainTaskStack => "¿Tamaño de la pila de la tarea principal?",
+        Message::ComponentMetadata => {
+            "¿Agregar los metadatos para publicar main/ en el registro de componentes?"
+        }
+        Message::ComponentVersion => "¿Versión?",
+        Message::ComponentDescription => "¿Descripción? (vacía: derivada del nombre del proyecto)",
+        Message::ComponentUrl => "¿URL del proyecto? (opcional)",
+        Message::ComponentMaintainers => "¿Mantenedores? (separados por comas, opcional)",
+        Message::ComponentLicense => "¿Licencia SPDX? (opcional)",
+        Message::PartitionTable => "¿Agregar una tabla de particiones personalizada?",
+        Message::FlashSize => "¿Tamaño de la flash en MB?",
+        Message::PartitionScheme => "¿Esquema de particiones?",
+        Message::AppSize => "¿Tamaño de las particiones de la app?",
+        Message::SchemeAppSize => "el del esquema",
+        Message::Assertions => "¿Aserciones?",
+        Message::HeapDebugging => "¿Depuración del heap?",
+        Message::SecurityFeatures => {
+            "¿Habilitar funciones de seguridad? (IRREVERSIBLES en hardware real, ninguna por defecto)"
+        }
+        Message::ConfirmSecurity => {
+            "{1}: se queman eFuses en el primer arranque, no se puede desactivar y debe guardar las claves. ¿Habilitar de todos modos?"
+        }
+        Message::OnboardingDefaults => {
+            "¿Fijar el lenguaje, la placa y la opción de git por defecto de los proyectos nuevos?"
+        }
+        Message::OnboardingUpdateCheck => {
+            "¿Buscar una nueva versión una vez al día? No se envía nada más, no hay telemetría"
+        }
+        Message::StepFailed => "{1} falló, ¿qué hacer?",
+        Message::RetryStep => "Reintentar",
+        Message::SkipStep => "Omitirlo, el proyecto está completo sin él",
+        Message::AbortGeneration => "Abortar y borrar el proyecto",
+        Message::PlainCopy => {
+            "La plantilla no tiene la estructura de un proyecto ESP-IDF ({1}), ¿copiarla tal cual?"
+        }
+        Message::TickRate100 => {
+            "100 Hz: el valor de IDF, vTaskDelay(pdMS_TO_TICKS(10)) es un solo tick y los retardos más cortos no esperan nada"
+        }
+        Message::TickRate250 => "250 Hz: resolución de 4 ms",
+        Message::TickRate500 => "500 Hz: resolución de 2 ms",
+        Message::TickRate1000 => {
+            "1000 Hz: resolución de 1 ms, los retardos duran lo que pide pdMS_TO_TICKS(), algo más de tiempo en el tick"
+        }
+        Message::MainTaskStack3584 => {
+            "3584 bytes: el valor de IDF, se desborda en cuanto app_main hace printf() de floats o guarda búferes en la pila"
+        }
+        Message::MainTaskStack4096 => "4096 bytes: espacio para código con mucho printf",
+        Message::MainTaskStack6144 => "6144 bytes: espacio para búferes locales más grandes",
+        Message::MainTaskStack8192 => {
+            "8192 bytes: para un app_main que hace el trabajo pesado (TLS, JSON...)"
+        }
+        Message::SecureBootItem => {
+            "Arranque seguro: solo arrancan las apps firmadas con tu clave, perder la clave inutiliza la placa"
+        }
+        Message::FlashEncryptionItem => {
+            "Cifrado de la flash (modo desarrollo): la flash se cifra con una clave grabada en eFuses"
+        }
+        Message::SecureBoot => "El arranque seguro",
+        Message::FlashEncryption => "El cifrado de la flash",
+        Message::SecureBootAndFlashEncryption => "El arranque seguro y el cifrado de la flash",
+        Message::AssertionsAbort => "Abortar: imprime la aserción fallida y aborta (valor de IDF)",
+        Message::AssertionsSilent => "Silencioso: aborta sin imprimir, ahorra flash",
+        Message::AssertionsDisabled => "Desactivadas: quita las aserciones al compilar, no recomendado",
+        Message::HeapDebuggingDisabled => "Desactivado: sin comprobaciones, a toda velocidad (valor de IDF)",
+        Message::HeapDebuggingLight => {
+            "Envenenamiento ligero: detecta desbordamientos de búfer al liberar memoria, coste pequeño"
+        }
+        Message::HeapDebuggingComprehensive => {
+            "Envenenamiento completo: también detecta el uso de memoria liberada o sin inicializar, reservas mucho más lentas"
+        }
+        Message::HeapDebuggingTracing => {
+            "Completo + trazas: también registra las reservas para encontrar fugas, usa RAM extra"
+        }
+        Message::SchemeAppSizeItem => "El tamaño del esquema (1MB o 1.5MB)",
+        Message::SchemeSingleApp => "Una app (factory de 1MB)",
+        Message::SchemeSingleAppLarge => "Una app grande (factory de 1.5MB)",
+        Message::SchemeTwoOta => "OTA (dos ranuras de 1MB)",
+        Message::SchemeFactoryTwoOta => "App factory y OTA (tres apps de 1MB)",
+        Message::AppsResized => "{1}, apps redimensionadas a {2}KB",
+        Message::SchemeTooLarge => "{1} (demasiado grande)",
+        Message::EditorConfigItem => {
+            "sangría, codificación y finales de línea para cualquier editor"
+        }
+        Message::ClangFormatItem => "formato de las fuentes C y C++",
+        Message::GitignoreItem => "deja build/, managed_components/ y sdkconfig.old fuera de git",
+        Message::GitattributesItem => {
+            "finales de línea LF en el repositorio, sin tocar los binarios"
+        }
+        Message::PreCommitItem => {
+            "comprobaciones de espacios y clang-format antes de cada commit"
+        }
+        Message::CommitLockFile => "¿Hacer commit de dependencies.lock en el repositorio git?",
+        Message::ApplyFix => "¿Aplicar la corrección?",
+        Message::ChooseNumber => "Escribe un número del 1 al {1}",
+        Message::ChooseNumbers => "Escribe números del 1 al {1} separados por comas, 0 para ninguno",
+        Message::YesNoDefaultYes => "[S/n]",
+        Message::YesNoDefaultNo => "[s/N]",
+        Message::YesNo => "[s/n]",
+        Message::InvalidAnswer => "Respuesta no válida \"{1}\"",
+        Message::MultiSelectKeys => "(espacio para seleccionar, enter para confirmar)",
+        Message::ChooseOrder => {
+            "Escribe números del 1 al {1} en el nuevo orden, los demás siguen en su orden"
+        }
+        Message::SortKeys => {
+            "(espacio para tomar o soltar, flechas para mover, enter para confirmar)"
+        }
+        Message::EditMainSources => "¿Editar las fuentes de main/CMakeLists.txt ({1})?",
+        Message::AddMainSources => "Fuentes a añadir, relativas a main/ y separadas por comas",
+        Message::OrderMainSources => "¿Orden de las fuentes?",
+    })
+}
+
+/// The message in a locale, in English if it has no translation
+pub fn text(message: Message, locale: Locale) -> &'static str {
+    match locale {
+        Locale::En => None,
+        Locale::Es => spanish(message),
+    }
+    .unwrap_or_else(|| english(message))
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Sets the locale of the prompts, once at startup; English until then
+pub fn set_locale(locale: Locale) {
+    let _ = LOCALE.set(locale);
+}
+
+/// The message in the locale of the prompts
+pub fn t(message: Message) -> &'static str {
+    text(message, LOCALE.get().copied().unwrap_or_default())
+}
+
+/// The message with its numbered placeholders replaced by the arguments:
+/// `{1}` by the first one, `{2}` by the second... so a translation can
+/// change their order
+pub fn format(message: Message, args: &[&str]) -> String {
+    fill(t(message), args)
+}
+
+/// Replaces the numbered placeholders of `template`, in one pass so an
+/// argument is never read as a placeholder; unknown ones are kept as they are
+fn fill(template: &str, args: &[&str]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let arg = after.find('}').and_then(|end| {
+            let index: usize = after[..end].parse().ok()?;
+            Some((*args.get(index.checked_sub(1)?)?, end))
+        });
+        match arg {
+            Some((arg, end)) => {
+                text.push_str(arg);
+                rest = &after[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// A question with its default answer appended, e.g. `Board? (default: Custom)`
+pub fn with_default(message: Message, default: &str) -> String {
+    format!("{} ({}: {})", t(message), t(Message::Default), default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_tag() {
+        assert_eq!(Locale::from_tag("es"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("es_MX.UTF-8"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("ES-es"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("en_US.UTF-8"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("fr_FR"), None);
+        assert_eq!(Locale::from_tag("C"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(
+            Locale::detect(None, env(&[("LANG", "es_ES.UTF-8")])),
+            Locale::Es
+        );
+        assert_eq!(
+            Locale::detect(Some("en"), env(&[("LANG", "es_ES.UTF-8")])),
+            Locale::En
+        );
+        // LC_ALL wins over LANG, an empty variable is skipped
+        assert_eq!(
+            Locale::detect(
+                None,
+                env(&[("LC_ALL", ""), ("LC_MESSAGES", "es"), ("LANG", "en")])
+            ),
+            Locale::Es
+        );
+        // Unknown locales fall back to English
+        assert_eq!(Locale::detect(Some("fr"), env(&[])), Locale::En);
+        assert_eq!(Locale::detect(None, env(&[])), Locale::En);
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(text(Message::Board, Locale::Es), "¿Placa de desarrollo?");
+        assert_eq!(text(Message::Board, Locale::En), "Board?");
+        let placeholders = |text: &'static str| {
+            let mut found: Vec<&str> = text
+                .match_indices('{')
+                .filter_map(|(start, _)| text[start..].split_inclusive('}').next())
+                .collect();
+            found.sort_unstable();
+            found
+        };
+        for message in [
+            Message::DeleteDirectory,
+            Message::DeleteCheckoutContent,
+            Message::ConfirmSecurity,
+            Message::AppsResized,
+        ] {
+            assert_eq!(
+                placeholders(text(message, Locale::Es)),
+                placeholders(text(message, Locale::En))
+            );
+        }
+        assert_eq!(
+            format(Message::DeleteDirectory, &["blink"]),
+            "Directory \"blink\" not empty, delete?"
+        );
+        assert_eq!(
+            with_default(Message::Chip, "esp32"),
+            "Chip? (default: esp32)"
+        );
+    }
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(fill("{2} before {1}", &["a", "b"]), "b before a");
+        assert_eq!(fill("{1} and {1}", &["a"]), "a and a");
+        // An argument that looks like a placeholder is not replaced
+        assert_eq!(fill("{1} {2}", &["{2}", "b"]), "{2} b");
+        // Unknown placeholders and lone braces are kept
+        assert_eq!(fill("{3} {0} {x} {", &["a"]), "{3} {0} {x} {");
+    }
+}
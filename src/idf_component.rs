@@ -0,0 +1,445 @@
+//! `idf_component.yml`, the manifest of the managed components (from the
+//! component registry) a component depends on, and the metadata used to
+//! publish the component to the registry
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::boards;
+
+/// Name of the manifest, next to the component CMakeLists.txt
+pub const IDF_COMPONENT_FILE: &str = "idf_component.yml";
+
+/// Metadata of a component published to the component registry, written at
+/// the top of its manifest
+///
+/// The description and the targets are derived from the project when left
+/// empty, see [`ComponentMetadata::derive`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComponentMetadata {
+    /// Version of the component, semantic versioning (e.g. `0.1.0`)
+    pub version: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// Homepage of the component
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The people maintaining the component, e.g. `Name <email>`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub maintainers: Vec<String>,
+    /// SPDX license expression (e.g. `MIT`, `Apache-2.0`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Chips the component supports, all of them if empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+}
+
+impl Default for ComponentMetadata {
+    fn default() -> Self {
+        ComponentMetadata {
+            version: "0.1.0".into(),
+            description: String::new(),
+            url: None,
+            maintainers: Vec::new(),
+            license: None,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl ComponentMetadata {
+    /// Fills the description and the targets when they are not set
+    ///
+    /// # Arguments
+    /// * `project` - The display name of the project
+    /// * `target` - The chip of the project, if one was selected
+    pub fn derive(mut self, project: &str, target: Option<&str>) -> Self {
+        if self.description.is_empty() {
+            self.description = format!("Main component of {}", project);
+        }
+        if self.targets.is_empty() {
+            self.targets = target.map(String::from).into_iter().collect();
+        }
+        self
+    }
+
+    /// Checks the metadata against the manifest schema of the component manager
+    ///
+    /// # Errors
+    /// If the version is not a semantic version, the URL is not an HTTP(S)
+    /// URL, the license is not an SPDX expression, a target is unknown or a
+    /// value is empty or spans several lines
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !is_semver(&self.version) {
+            bail!(
+                "Component version \"{}\" is not a semantic version (e.g. 0.1.0)",
+                self.version
+            );
+        }
+        let values = std::iter::once(&self.description)
+            .chain(&self.url)
+            .chain(&self.maintainers)
+            .chain(&self.license);
+        for value in values {
+            if value.contains(|c: char| c.is_control()) {
+                bail!("Component metadata \"{}\" must fit on one line", value);
+            }
+        }
+        if let Some(url) = &self.url {
+            let host = url
+                .strip_prefix("https://")
+                .or_else(|| url.strip_prefix("http://"))
+                .unwrap_or_default();
+            if host.is_empty() || host.starts_with('/') || url.contains(char::is_whitespace) {
+                bail!("Component URL \"{}\" is not an http(s) URL", url);
+            }
+        }
+        if self.maintainers.iter().any(|name| name.trim().is_empty()) {
+            bail!("Component maintainers cannot be empty");
+        }
+        if let Some(license) = &self.license {
+            let valid = !license.trim().is_empty()
+                && license
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || " .-+()".contains(c));
+            if !valid {
+                bail!(
+                    "Component license \"{}\" is not an SPDX expression (e.g. MIT, Apache-2.0)",
+                    license
+                );
+            }
+        }
+        for (i, target) in self.targets.iter().enumerate() {
+            if !boards::TARGETS.contains(&target.as_str()) {
+                bail!(
+                    "Unknown component target \"{}\", expected one of {}",
+                    target,
+                    boards::TARGETS.join(", ")
+                );
+            }
+            if self.targets[..i].contains(target) {
+                bail!("Component target \"{}\" is listed twice", target);
+            }
+        }
+        Ok(())
+    }
+
+    /// The top-level entries of the manifest, each with its YAML
+    fn entries(&self) -> Vec<(&'static str, String)> {
+        let mut entries = vec![("version", format!("version: {}\n", quote(&self.version)))];
+        if !self.description.is_empty() {
+            entries.push((
+                "description",
+                format!("description: {}\n", quote(&self.description)),
+            ));
+        }
+        if let Some(url) = &self.url {
+            entries.push(("url", format!("url: {}\n", quote(url))));
+        }
+        if let Some(license) = &self.license {
+            entries.push(("license", format!("license: {}\n", quote(license))));
+        }
+        for (key, list) in [
+            ("maintainers", &self.maintainers),
+            ("targets", &self.targets),
+        ] {
+            if !list.is_empty() {
+                let mut yaml = format!("{}:\n", key);
+                list.iter()
+                    .for_each(|item| yaml.push_str(&format!("  - {}\n", quote(item))));
+                entries.push((key, yaml));
+            }
+        }
+        entries
+    }
+}
+
+/// Adds the metadata at the top of a manifest
+///
+/// Keys the manifest already sets are kept. As for the dependencies, the
+/// manifest is edited as text to keep its comments and layout.
+///
+/// # Arguments
+/// * `content` - The content of the manifest, empty if it doesn't exist yet
+/// * `metadata` - The metadata of the component
+///
+/// # Returns
+/// The new content of the manifest
+pub fn add_metadata(content: &str, metadata: &ComponentMetadata) -> String {
+    let mut result: String = metadata
+        .entries()
+        .into_iter()
+        .filter(|(key, _)| !has_top_level_key(content, key))
+        .map(|(_, yaml)| yaml)
+        .collect();
+    result.push_str(content);
+    result
+}
+
+/// Checks for a semantic version: `MAJOR.MINOR.PATCH`, with an optional
+/// pre-release and build metadata
+fn is_semver(version: &str) -> bool {
+    let (version, build) = match version.split_once('+') {
+        Some((version, build)) => (version, Some(build)),
+        None => (version, None),
+    };
+    let (core, pre_release) = match version.split_once('-') {
+        Some((core, pre_release)) => (core, Some(pre_release)),
+        None => (version, None),
+    };
+    let identifiers_valid = |identifiers: Option<&str>| {
+        identifiers.is_none_or(|identifiers| {
+            identifiers.split('.').all(|identifier| {
+                !identifier.is_empty()
+                    && identifier
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            })
+        })
+    };
+    let numbers: Vec<&str> = core.split('.').collect();
+    numbers.len() == 3
+        && numbers.iter().all(|number| {
+            !number.is_empty()
+                && number.chars().all(|c| c.is_ascii_digit())
+                && (number.len() == 1 || !number.starts_with('0'))
+        })
+        && identifiers_valid(pre_release)
+        && identifiers_valid(build)
+}
+
+/// Quotes a YAML scalar
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn has_top_level_key(content: &str, key: &str) -> bool {
+    content.lines().any(|line| {
+        line.strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with(':'))
+    })
+}
+
+/// Adds dependencies to a manifest, creating the `dependencies` section if needed
+///
+/// Dependencies already listed keep their version. The manifest is edited as
+/// text so the comments and layout of the existing file are kept.
+///
+/// # Arguments
+/// * `content` - The content of the manifest, empty if it doesn't exist yet
+/// * `dependencies` - The component names (e.g. `espressif/led_strip`) and version requirements
+///
+/// # Returns
+/// The new content of the manifest
+pub fn add_dependencies(content: &str, dependencies: &[(&str, &str)]) -> String {
+    let missing: Vec<String> = dependencies
+        .iter()
+        .filter(|(name, _)| !has_dependency(content, name))
+        .map(|(name, version)| format!("  {}: \"{}\"\n", name, version))
+        .collect();
+    if missing.is_empty() {
+        return content.into();
+    }
+
+    let mut result = String::with_capacity(content.len() + 64);
+    match content
+        .lines()
+        .position(|line| line.trim_end() == "dependencies:")
+    {
+        Some(index) => {
+            for (i, line) in content.lines().enumerate() {
+                result.push_str(line);
+                result.push('\n');
+                if i == index {
+                    missing.iter().for_each(|entry| result.push_str(entry));
+                }
+            }
+        }
+        None => {
+            result.push_str(content);
+            if !content.is_empty() && !content.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str("dependencies:\n");
+            missing.iter().for_each(|entry| result.push_str(entry));
+        }
+    }
+    result
+}
+
+/// Lists the dependencies of a manifest
+///
+/// Only the simple `name: "version"` form and the `version` key of the
+/// expanded form are read, other keys of a dependency are ignored.
+///
+/// # Arguments
+/// * `content` - The content of the manifest
+///
+/// # Returns
+/// The component names and version requirements, empty when the version is not set
+pub fn dependencies(content: &str) -> Vec<(String, String)> {
+    let mut dependencies: Vec<(String, String)> = Vec::new();
+    let mut in_section = false;
+    let mut entry_indent = None;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if indent == 0 {
+            in_section = line.trim_end() == "dependencies:";
+            entry_indent = None;
+            continue;
+        }
+        let (key, value) = match (in_section, trimmed.split_once(':')) {
+            (true, Some((key, value))) => (unquote(key), unquote(value)),
+            _ => continue,
+        };
+        match entry_indent {
+            Some(entry) if indent > entry => {
+                if key == "version" {
+                    if let Some(last) = dependencies.last_mut() {
+                        last.1 = value.into();
+                    }
+                }
+            }
+            _ => {
+                entry_indent = Some(indent);
+                dependencies.push((key.into(), value.into()));
+            }
+        }
+    }
+    dependencies
+}
+
+/// Strips the whitespace and quotes around a YAML scalar
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches(|c| c == '"' || c == '\'')
+}
+
+fn has_dependency(content: &str, name: &str) -> bool {
+    content.lines().any(|line| {
+        let line = line.trim_start();
+        line.strip_prefix(name)
+            .or_else(|| {
+                line.strip_prefix('"')
+                    .and_then(|line| line.strip_prefix(name))
+                    .and_then(|line| line.strip_prefix('"'))
+            })
+            .is_some_and(|rest| rest.starts_with(':'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_dependencies() {
+        assert_eq!(
+            add_dependencies("", &[("espressif/led_strip", "^2.5.0")]),
+            "dependencies:\n  espressif/led_strip: \"^2.5.0\"\n"
+        );
+
+        let existing = "## Managed dependencies\ndependencies:\n  idf: \">=5.0\"\n";
+        let merged = add_dependencies(
+            existing,
+            &[("idf", ">=4.4"), ("espressif/led_strip", "^2.5.0")],
+        );
+        assert_eq!(
+            merged,
+            "## Managed dependencies\ndependencies:\n  espressif/led_strip: \"^2.5.0\"\n  idf: \">=5.0\"\n"
+        );
+        assert_eq!(
+            add_dependencies(&merged, &[("espressif/led_strip", "^3")]),
+            merged
+        );
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let manifest = "## Managed dependencies\ndependencies:\n  idf: \">=5.0\"\n  \"espressif/led_strip\": \"^2.5.0\"\n  espressif/mdns:\n    version: \"^1.2\"\n    public: true\ndescription: app\n";
+        assert_eq!(
+            dependencies(manifest),
+            vec![
+                ("idf".to_string(), ">=5.0".to_string()),
+                ("espressif/led_strip".to_string(), "^2.5.0".to_string()),
+                ("espressif/mdns".to_string(), "^1.2".to_string()),
+            ]
+        );
+        assert!(dependencies("").is_empty());
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let metadata = ComponentMetadata {
+            url: Some("https://github.com/me/blink".into()),
+            maintainers: vec!["Jane \"JD\" Doe <jane@example.com>".into()],
+            license: Some("MIT OR Apache-2.0".into()),
+            ..ComponentMetadata::default()
+        }
+        .derive("blink", Some("esp32c3"));
+        metadata.validate().unwrap();
+
+        let manifest = add_metadata("", &metadata);
+        assert_eq!(
+            manifest,
+            "version: \"0.1.0\"\ndescription: \"Main component of blink\"\nurl: \"https://github.com/me/blink\"\nlicense: \"MIT OR Apache-2.0\"\nmaintainers:\n  - \"Jane \\\"JD\\\" Doe <jane@example.com>\"\ntargets:\n  - \"esp32c3\"\n"
+        );
+        let parsed: ComponentMetadata = serde_yaml::from_str(&manifest).unwrap();
+        assert_eq!(parsed, metadata);
+
+        // The keys already set and the dependencies are kept
+        let existing = "version: \"2.0.0\"\ndependencies:\n  idf: \">=5.0\"\n";
+        let merged = add_metadata(existing, &metadata);
+        assert!(merged.ends_with(existing));
+        assert_eq!(merged.matches("version:").count(), 1);
+        assert_eq!(dependencies(&merged), [("idf".into(), ">=5.0".into())]);
+    }
+
+    #[test]
+    fn test_validate_metadata() {
+        let valid = ComponentMetadata::default();
+        valid.validate().unwrap();
+        for version in ["1.0", "01.0.0", "1.0.0-", "1.0.0-rc.1+build.5"] {
+            let metadata = ComponentMetadata {
+                version: version.into(),
+                ..valid.clone()
+            };
+            assert_eq!(
+                metadata.validate().is_ok(),
+                version.ends_with("5"),
+                "{}",
+                version
+            );
+        }
+        let invalid = [
+            ComponentMetadata {
+                url: Some("github.com/me/blink".into()),
+                ..valid.clone()
+            },
+            ComponentMetadata {
+                license: Some("MIT; rm".into()),
+                ..valid.clone()
+            },
+            ComponentMetadata {
+                targets: vec!["esp8266".into()],
+                ..valid.clone()
+            },
+            ComponentMetadata {
+                targets: vec!["esp32".into(), "esp32".into()],
+                ..valid.clone()
+            },
+            ComponentMetadata {
+                description: "two\nlines".into(),
+                ..valid.clone()
+            },
+        ];
+        for metadata in invalid {
+            assert!(metadata.validate().is_err(), "{:?}", metadata);
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! ESP-IDF versions: the minimum version each feature needs, checked against
+//! the installed IDF (or `--idf-version`) before generating
+
+use anyhow::bail;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Suffixes of a pre-release, e.g. `v5.2-dev` or `v5.1-rc1`; other suffixes
+/// come from `git describe` and mean commits after the release
+const PRE_RELEASE_SUFFIXES: &[&str] = &["dev", "alpha", "beta", "rc"];
+
+/// A version of ESP-IDF
+///
+/// A pre-release is older than its release, so `v5.2-dev` doesn't meet a
+/// minimum of 5.2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdfVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// `dev`, `rc1`... for a pre-release
+    pub pre_release: Option<String>,
+}
+
+impl IdfVersion {
+    /// A release version
+    pub const fn new(major: u32, minor: u32) -> Self {
+        IdfVersion {
+            major,
+            minor,
+            patch: 0,
+            pre_release: None,
+        }
+    }
+
+    /// Parses a version as printed by `idf.py --version` (`ESP-IDF v5.2.1`),
+    /// by `git describe` (`v5.2-dev-1234-gabcdef`) or typed (`5.1`)
+    ///
+    /// # Returns
+    /// The version, `None` if the text isn't one
+    pub fn parse(text: &str) -> Option<IdfVersion> {
+        let text = text.trim();
+        let text = text.strip_prefix("ESP-IDF").unwrap_or(text).trim_start();
+        let text = text.strip_prefix('v').unwrap_or(text);
+        let (core, suffix) = match text.split_once('-') {
+            Some((core, suffix)) => (core, Some(suffix)),
+            None => (text, None),
+        };
+        let numbers = core
+            .split('.')
+            .map(|number| number.parse().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        let (major, minor, patch) = match numbers[..] {
+            [major] => (major, 0, 0),
+            [major, minor] => (major, minor, 0),
+            [major, minor, patch] => (major, minor, patch),
+            _ => return None,
+        };
+        let pre_release = suffix
+            .map(|suffix| suffix.split('-').next().unwrap_or_default())
+            .filter(|label| {
+                PRE_RELEASE_SUFFIXES
+                    .iter()
+                    .any(|prefix| label.starts_with(prefix))
+            })
+            .map(String::from);
+        Some(IdfVersion {
+            major,
+            minor,
+            patch,
+            pre_release,
+        })
+    }
+}
+
+impl Ord for IdfVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre_release, &other.pre_release) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => pre_release_key(a).cmp(&pre_release_key(b)),
+            })
+    }
+}
+
+/// Orders pre-releases by their kind, in the order of
+/// [`PRE_RELEASE_SUFFIXES`], then by their number, so `rc10` is after `rc9`
+///
+/// # Arguments
+/// * `label` - The pre-release, e.g. `rc1` or `beta.2`
+fn pre_release_key(label: &str) -> (usize, Option<u64>, &str) {
+    let (rank, rest) = PRE_RELEASE_SUFFIXES
+        .iter()
+        .enumerate()
+        .find_map(|(rank, prefix)| Some((rank, label.strip_prefix(prefix)?)))
+        .unwrap_or((PRE_RELEASE_SUFFIXES.len(), label));
+    let rest = rest.trim_start_matches('.');
+    (rank, rest.parse().ok(), rest)
+}
+
+impl PartialOrd for IdfVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for IdfVersion {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        IdfVersion::parse(text)
+            .ok_or_else(|| format!("\"{}\" is not an ESP-IDF version (e.g. 5.1)", text))
+    }
+}
+
+impl fmt::Display for IdfVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        if let Some(pre_release) = &self.pre_release {
+            write!(f, "-{}", pre_release)?;
+        }
+        Ok(())
+    }
+}
+
+/// A feature that needs a recent ESP-IDF, other than the chips and the
+/// starters (in [`crate::boards`] and [`crate::starters`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdfFeature {
+    /// `idf_component.yml` metadata for the component registry
+    ComponentMetadata,
+    BootloaderHooks,
+    /// A board whose console is the USB-Serial/JTAG peripheral
+    UsbSerialJtagConsole,
+}
+
+impl IdfFeature {
+    pub const ALL: &'static [IdfFeature] = &[
+        IdfFeature::ComponentMetadata,
+        IdfFeature::BootloaderHooks,
+        IdfFeature::UsbSerialJtagConsole,
+    ];
+
+    /// The oldest ESP-IDF that supports the feature
+    pub fn min_version(self) -> IdfVersion {
+        match self {
+            // The component manager, which reads idf_component.yml, ships with 4.4
+            IdfFeature::ComponentMetadata => IdfVersion::new(4, 4),
+            // bootloader_components/ is picked up by the bootloader build since 4.4
+            IdfFeature::BootloaderHooks => IdfVersion::new(4, 4),
+            // CONFIG_ESP_CONSOLE_USB_SERIAL_JTAG
+            IdfFeature::UsbSerialJtagConsole => IdfVersion::new(4, 4),
+        }
+    }
+}
+
+impl fmt::Display for IdfFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IdfFeature::ComponentMetadata => "component registry metadata",
+            IdfFeature::BootloaderHooks => "bootloader hooks",
+            IdfFeature::UsbSerialJtagConsole => "USB-Serial/JTAG console",
+        })
+    }
+}
+
+/// What an ESP-IDF older than a requirement does, `--compat-check`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompatCheck {
+    /// The generation stops before writing anything
+    #[default]
+    Error,
+    /// Every incompatibility is shown and the project is generated anyway
+    Warn,
+    /// The version isn't checked
+    Off,
+}
+
+impl CompatCheck {
+    pub const ALL: &'static [CompatCheck] =
+        &[CompatCheck::Error, CompatCheck::Warn, CompatCheck::Off];
+}
+
+impl fmt::Display for CompatCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompatCheck::Error => "error",
+            CompatCheck::Warn => "warn",
+            CompatCheck::Off => "off",
+        })
+    }
+}
+
+impl FromStr for CompatCheck {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        CompatCheck::ALL
+            .iter()
+            .copied()
+            .find(|candidate| candidate.to_string() == mode)
+            .ok_or_else(|| {
+                format!(
+                    "unknown compatibility check \"{}\", expected error, warn or off",
+                    mode
+                )
+            })
+    }
+}
+
+/// A feature of the project that needs a minimum ESP-IDF version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdfRequirement {
+    /// The feature, e.g. `esp32c6 target` or `mqtt starter`
+    pub feature: String,
+    pub version: IdfVersion,
+}
+
+/// The newest of the minimum versions, `None` if nothing needs one
+pub fn minimum(requirements: &[IdfRequirement]) -> Option<&IdfVersion> {
+    requirements
+        .iter()
+        .map(|requirement| &requirement.version)
+        .max()
+}
+
+/// The requirements an ESP-IDF version doesn't meet, each as "esp32c6
+/// target needs ESP-IDF 5.1"
+///
+/// # Arguments
+/// * `requirements` - See [`crate::options::ScaffoldOptions::idf_requirements`]
+/// * `installed` - The installed version, or the one of `--idf-version`
+pub fn unmet(requirements: &[IdfRequirement], installed: &IdfVersion) -> Vec<String> {
+    requirements
+        .iter()
+        .filter(|requirement| requirement.version > *installed)
+        .map(|requirement| {
+            format!(
+                "{} needs ESP-IDF {}",
+                requirement.feature, requirement.version
+            )
+        })
+        .collect()
+}
+
+/// Checks that the installed ESP-IDF meets every requirement
+///
+/// # Arguments
+/// * `requirements` - See [`crate::options::ScaffoldOptions::idf_requirements`]
+/// * `installed` - The installed version, or the one of `--idf-version`
+///
+/// # Errors
+/// If the version is older than a requirement, the message lists the
+/// features that need a newer one
+pub fn check(requirements: &[IdfRequirement], installed: &IdfVersion) -> anyhow::Result<()> {
+    let unmet = unmet(requirements, installed);
+    if !unmet.is_empty() {
+        bail!(
+            "ESP-IDF {} is too old for this project: {}. Update ESP-IDF, pass --idf-version if it's not the one the project will be built with, or --compat-check warn to generate it anyway",
+            installed,
+            unmet.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Lists the features of a set of requirements, e.g. for a notice
+pub fn describe(requirements: &[IdfRequirement]) -> String {
+    requirements
+        .iter()
+        .map(|requirement| format!("{} (>= {})", requirement.feature, requirement.version))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(text: &str) -> IdfVersion {
+        IdfVersion::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            version("ESP-IDF v5.2.1"),
+            IdfVersion {
+                patch: 1,
+                ..IdfVersion::new(5, 2)
+            }
+        );
+        assert_eq!(version("5.1"), IdfVersion::new(5, 1));
+        assert_eq!(version("v5.2-dev").pre_release.as_deref(), Some("dev"));
+        assert_eq!(
+            version("ESP-IDF v5.2-dev-1234-gabcdef")
+                .pre_release
+                .as_deref(),
+            Some("dev")
+        );
+        assert_eq!(version("v5.0-rc1").pre_release.as_deref(), Some("rc1"));
+        // Commits after a release are not a pre-release
+        assert_eq!(
+            version("v5.1.2-123-gabcdef"),
+            IdfVersion {
+                patch: 2,
+                ..IdfVersion::new(5, 1)
+            }
+        );
+        assert_eq!(version("v5.1.2-dirty").pre_release, None);
+        for invalid in ["", "ESP-IDF", "five", "5.x", "5.1.2.3"] {
+            assert_eq!(IdfVersion::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        assert!(version("v5.2-dev") < version("5.2"));
+        assert!(version("v5.2-dev") > version("5.1.4"));
+        assert!(version("v5.2-rc1") > version("v5.2-dev"));
+        assert!(version("v5.2-rc10") > version("v5.2-rc9"));
+        assert!(version("v5.2-beta.10") > version("v5.2-beta.2"));
+        assert!(version("v5.2-rc1") > version("v5.2-beta3"));
+        assert!(version("v5.2-alpha") > version("v5.2-dev"));
+        assert!(version("5.10") > version("5.9"));
+        assert_eq!(version("v5.1.0").cmp(&version("5.1")), Ordering::Equal);
+        assert_eq!(version("v5.2-dev").to_string(), "5.2-dev");
+    }
+
+    #[test]
+    fn test_check() {
+        let requirements = vec![
+            IdfRequirement {
+                feature: "esp32c6 target".into(),
+                version: IdfVersion::new(5, 1),
+            },
+            IdfRequirement {
+                feature: "mqtt starter".into(),
+                version: IdfVersion::new(5, 0),
+            },
+        ];
+        assert_eq!(minimum(&requirements), Some(&IdfVersion::new(5, 1)));
+        check(&requirements, &version("5.1.2")).unwrap();
+        let error = check(&requirements, &version("v5.1-dev")).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("esp32c6 target needs ESP-IDF 5.1"));
+        assert!(!error.to_string().contains("mqtt"));
+        let error = check(&requirements, &version("4.4")).unwrap_err();
+        assert!(error.to_string().contains("mqtt starter needs ESP-IDF 5.0"));
+        assert_eq!(
+            unmet(&requirements, &version("5.0")),
+            ["esp32c6 target needs ESP-IDF 5.1"]
+        );
+        assert_eq!(minimum(&[]), None);
+    }
+
+    #[test]
+    fn test_compat_check() {
+        for mode in CompatCheck::ALL {
+            assert_eq!(mode.to_string().parse::<CompatCheck>(), Ok(*mode));
+        }
+        assert!("strict".parse::<CompatCheck>().is_err());
+        assert_eq!(
+            IdfFeature::BootloaderHooks.min_version(),
+            IdfVersion::new(4, 4)
+        );
+        for feature in IdfFeature::ALL {
+            assert!(
+                feature.min_version() >= IdfVersion::new(4, 4),
+                "{}",
+                feature
+            );
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/*
+Copyright (c) 2021 Alan Ramírez Herrera
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR
+OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE
+OR OTHER DEALINGS IN THE SOFTWARE.
+ */
+
+//! Library behind the `esp-create-project` CLI, usable to embed the project
+//! generation in other tools (IDE plugins, GUIs...)
+
+pub mod archive;
+pub mod batch;
+pub mod boards;
+pub mod build_deps;
+pub mod catalog;
+pub mod ci;
+pub mod cmake;
+pub mod component;
+pub mod config;
+pub mod doctor;
+pub mod download;
+mod error;
+pub mod error_format;
+pub mod examples;
+pub mod explain;
+pub mod export_env;
+pub mod external;
+pub mod factory_test;
+pub mod file_manifest;
+pub mod flash;
+mod generator;
+pub mod http;
+pub mod hygiene;
+pub mod i18n;
+pub mod idf_component;
+pub mod idf_version;
+pub mod metadata;
+pub mod naming;
+pub mod onboarding;
+pub mod options;
+pub mod partitions;
+pub mod platform;
+pub mod project;
+pub mod project_check;
+pub mod project_model;
+pub mod prompt;
+pub mod regen;
+pub mod rename;
+pub mod replacements;
+pub mod replay;
+pub mod report;
+pub mod sdkconfig;
+pub mod self_update;
+pub mod serial;
+pub mod size_hint;
+pub mod starters;
+pub mod status;
+pub mod steps;
+pub mod symbols;
+pub mod template_archive;
+pub mod template_cache;
+pub mod template_ref;
+pub mod template_size;
+pub mod template_source;
+pub mod templates;
+pub mod templatize;
+pub mod theme;
+pub mod update_check;
+pub mod version_info;
+pub mod workspace;
+
+#[cfg(test)]
+mod test_fixtures;
+
+pub use error::{CancellationToken, EspCreateError, Result};
+pub use generator::{generate, generate_with_report, generate_with_runner, GenerationReport};
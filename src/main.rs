@@ -20,338 +20,3864 @@ OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE
 OR OTHER DEALINGS IN THE SOFTWARE.
  */
 
-mod templates;
+use anyhow::{bail, Context};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use dialoguer::theme::{ColorfulTheme, SimpleTheme, Theme as DialoguerTheme};
+
+use esp_create_project::archive;
+use esp_create_project::batch::{generate_batch, BatchEvent, BatchManifest, BatchStatus};
+use esp_create_project::boards;
+use esp_create_project::build_deps::{self, IdfEnv};
+use esp_create_project::catalog::{ManifestTemplate, TemplateManifest};
+use esp_create_project::ci::CiProvider;
+use esp_create_project::component::{self, ComponentOptions};
+use esp_create_project::config::{self, LastChoices, Profile, UserConfig};
+use esp_create_project::doctor::{self, CheckStatus};
+use esp_create_project::download;
+use esp_create_project::error_format;
+use esp_create_project::examples;
+use esp_create_project::explain::{self, ProfileLayers, Setting, SettingSource};
+use esp_create_project::export_env::{self, ExportShell};
+use esp_create_project::external;
+use esp_create_project::file_manifest::FileManifest;
+use esp_create_project::flash::{self, BoardAction};
+use esp_create_project::hygiene::HygieneFile;
+use esp_create_project::i18n::{self, t, Locale, Message};
+use esp_create_project::idf_component::ComponentMetadata;
+use esp_create_project::idf_version::{self, CompatCheck, IdfVersion};
+use esp_create_project::naming::ProjectNames;
+use esp_create_project::onboarding;
+use esp_create_project::options::{
+    AssertionLevel, ClangFormatStyle, HeapDebugging, PostProcess, ProgrammingLanguage, RtosTuning,
+    ScaffoldOptions, SecurityFeatures, COLUMN_LIMITS, DEFAULT_WORKSPACE_APPS,
+    FLASH_ENCRYPTION_DOCS_URL, INDENT_WIDTHS, MAIN_TASK_STACK_SIZES, SECURE_BOOT_DOCS_URL,
+    TICK_RATES,
+};
+use esp_create_project::partitions::{self, PartitionLayout, PartitionScheme, FLASH_SIZES};
+use esp_create_project::project::SourceLanguage;
+use esp_create_project::project_check::{self, Severity};
+use esp_create_project::project_model::ProjectModel;
+use esp_create_project::prompt::{self, PlainPrompter, Prompter, TerminalPrompter};
+use esp_create_project::regen::{self, DiffLine, RegenTarget};
+use esp_create_project::rename::{self, RenameOptions};
+use esp_create_project::replay::{self, ReplayBundle};
+use esp_create_project::report::{Environment, ReportFile, TemplateProvenance};
+use esp_create_project::say;
+use esp_create_project::size_hint;
+use esp_create_project::starters::{self, MergeMode};
+use esp_create_project::status;
+use esp_create_project::steps::{FailurePolicy, Recovery, StepRunner};
+use esp_create_project::symbols::{self, Symbol, SymbolSet};
+use esp_create_project::template_cache::{TemplateCache, TemplateTtl};
+use esp_create_project::template_size::{self, SizeReport};
+use esp_create_project::theme::Theme;
+use esp_create_project::version_info::VersionInfo;
+use esp_create_project::{
+    generate_with_runner, platform, CancellationToken, EspCreateError, GenerationReport,
+};
+use esp_create_project::{project, self_update, serial, template_ref, update_check};
+use esp_create_project::{templates, templatize};
+
+/// The theme of every prompt, built once by [`set_theme`]
+static PROMPT_THEME: OnceLock<Box<dyn DialoguerTheme + Send + Sync>> = OnceLock::new();
+
+/// Whether the prompts are plain text for screen readers, set by [`set_theme`]
+static ACCESSIBLE: OnceLock<bool> = OnceLock::new();
+
+/// Builds the theme of the prompts and sets the symbols of the status lines,
+/// once at startup before anything is printed
+///
+/// The accessible mode prints words instead of symbols, no colors, and every
+/// status line on its own line, see [`prompt::is_accessible`].
+///
+/// # Arguments
+/// * `theme` - The theme, see [`Theme::resolve`]
+/// * `force_ascii` - Whether `--ascii` was passed
+/// * `accessible` - Whether the accessible mode is on
+fn set_theme(theme: Theme, force_ascii: bool, accessible: bool) {
+    let symbol_set = if accessible {
+        SymbolSet::Words
+    } else {
+        theme.symbol_set(SymbolSet::from_env(force_ascii))
+    };
+    symbols::set_symbol_set(symbol_set);
+    let _ = PROMPT_THEME.set(prompt_theme(theme, symbol_set));
+    let _ = ACCESSIBLE.set(accessible);
+    if accessible {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+        status::set_plain();
+    }
+}
+
+/// The prompter of the mode picked at startup: numbered menus read from
+/// stdin in the accessible mode, the arrow-key menus otherwise
+fn prompter() -> Box<dyn Prompter> {
+    if ACCESSIBLE.get().copied().unwrap_or(false) {
+        Box::new(PlainPrompter::new(io::stdin().lock(), io::stderr()))
+    } else {
+        Box::new(TerminalPrompter::new(theme()))
+    }
+}
+
+/// [`Prompter::select`] with the [`prompter`] of the process
+fn select(prompt: &str, items: &[impl ToString], default: usize) -> io::Result<usize> {
+    let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+    prompter().select(prompt, &items, default)
+}
+
+/// [`Prompter::multi_select`] with the [`prompter`] of the process
+fn multi_select(
+    prompt: &str,
+    items: &[impl ToString],
+    defaults: &[bool],
+) -> io::Result<Vec<usize>> {
+    let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+    prompter().multi_select(prompt, &items, defaults)
+}
+
+/// [`Prompter::sort`] with the [`prompter`] of the process
+fn sort(prompt: &str, items: &[String]) -> io::Result<Vec<usize>> {
+    prompter().sort(prompt, items)
+}
+
+/// [`Prompter::confirm`] with the [`prompter`] of the process
+fn confirm(prompt: &str, default: Option<bool>) -> io::Result<bool> {
+    prompter().confirm(prompt, default)
+}
+
+/// The theme of the prompts, the default one if [`set_theme`] wasn't called
+fn theme() -> &'static dyn DialoguerTheme {
+    PROMPT_THEME
+        .get_or_init(|| prompt_theme(Theme::default(), symbols::symbol_set()))
+        .as_ref()
+}
+
+/// The prompt theme of a [`Theme`]: the colorful one with ASCII markers when
+/// the terminal cannot display the Unicode ones, no colors for the simple
+/// one, and bold words instead of colored marks for the high-contrast one
+///
+/// # Arguments
+/// * `theme` - The theme
+/// * `symbol_set` - The symbols of the status lines
+fn prompt_theme(theme: Theme, symbol_set: SymbolSet) -> Box<dyn DialoguerTheme + Send + Sync> {
+    let marker = |text: &str| console::style(text.to_string()).for_stderr();
+    match theme {
+        Theme::Colorful if symbol_set == SymbolSet::Emoji => Box::new(ColorfulTheme::default()),
+        Theme::Colorful => Box::new(ColorfulTheme {
+            prompt_suffix: marker(">").black().bright(),
+            success_prefix: marker("+").green(),
+            success_suffix: marker(":").black().bright(),
+            error_prefix: marker("x").red(),
+            active_item_prefix: marker(">").green(),
+            checked_item_prefix: marker("[x]").green(),
+            unchecked_item_prefix: marker("[ ]"),
+            picked_item_prefix: marker(">").green(),
+            ..ColorfulTheme::default()
+        }),
+        Theme::Simple => Box::new(SimpleTheme),
+        Theme::HighContrast => {
+            let bold = || console::Style::new().for_stderr().bold();
+            Box::new(ColorfulTheme {
+                defaults_style: bold(),
+                prompt_style: bold(),
+                prompt_prefix: marker("?").bold(),
+                prompt_suffix: marker(">"),
+                success_prefix: marker("OK").bold(),
+                success_suffix: marker(":"),
+                error_prefix: marker("FAIL").bold(),
+                error_style: bold(),
+                hint_style: console::Style::new().for_stderr(),
+                values_style: bold(),
+                active_item_style: bold().reverse(),
+                inactive_item_style: console::Style::new().for_stderr(),
+                active_item_prefix: marker(">").bold(),
+                inactive_item_prefix: marker(" "),
+                checked_item_prefix: marker("[x]").bold(),
+                unchecked_item_prefix: marker("[ ]"),
+                picked_item_prefix: marker(">").bold(),
+                unpicked_item_prefix: marker(" "),
+                ..ColorfulTheme::default()
+            })
+        }
+    }
+}
+
+/// Prompts if the selected directory should be deleted
+///
+/// A git repository or worktree is only emptied, its `.git` is kept. The
+/// prompt shows the real path, and through a symlink only the content of the
+/// directory it points to is deleted, the link is kept.
+///
+/// # Arguments
+/// * `path` - The path to the directory to be deleted
+///
+/// # Returns
+/// `true` if the directory should be deleted, `false` otherwise
+///
+/// # Errors
+/// If the path cannot be resolved or the user cancels the operation
+fn prompt_directory_delete(path: &Path) -> anyhow::Result<bool> {
+    let real = fs::canonicalize(path).context(format!(
+        "Cannot resolve the directory \"{}\"",
+        path.display()
+    ))?;
+    let is_link = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+    let checkout = project::git_checkout(&real);
+    let path = real.display().to_string();
+    let prompt = match &checkout {
+        Some(checkout) => i18n::format(
+            Message::DeleteCheckoutContent,
+            &[&path, &checkout.to_string()],
+        ),
+        None => i18n::format(Message::DeleteDirectory, &[&path]),
+    };
+    if confirm(&prompt, None).context("Failed to prompt for directory deletion")? {
+        let result = if checkout.is_some() || is_link {
+            project::clear_directory(&real)
+        } else {
+            fs::remove_dir_all(&real)
+        };
+        if let Err(e) = result {
+            eprintln!("Cannot delete directory contents, error: {}", e);
+            return Ok(false);
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Refuses to generate as root or as an elevated administrator, see
+/// [`platform::is_elevated`]
+///
+/// # Arguments
+/// * `allow_root` - Whether `--allow-root` was passed
+///
+/// # Errors
+/// If the process is elevated and `allow_root` is not set
+fn check_not_elevated(allow_root: bool) -> anyhow::Result<()> {
+    if !allow_root && platform::is_elevated() {
+        bail!(
+            "Refusing to run {}: the project files would belong to the elevated user, and \
+             building or editing them would need elevated rights too. Run it as your own user, or pass \
+             --allow-root if it's intended (e.g. in a container)",
+            platform::ELEVATED
+        );
+    }
+    Ok(())
+}
+
+/// Warns if the project would be created inside ESP-IDF or another project
+///
+/// # Arguments
+/// * `destination` - The directory of the new project
+/// * `interactive` - Whether to ask for confirmation instead of failing
+///
+/// # Returns
+/// Whether to go on, `false` if the user declined
+///
+/// # Errors
+/// If the project would be nested and `interactive` is not set, or the prompt fails
+fn confirm_not_nested(destination: &Path, interactive: bool) -> anyhow::Result<bool> {
+    let destination = env::current_dir()
+        .context("Cannot read the current directory")?
+        .join(destination);
+    let idf_path = env::var_os("IDF_PATH").map(PathBuf::from);
+    let location = match project::nested_location(&destination, idf_path.as_deref()) {
+        Some(location) => location,
+        None => return Ok(true),
+    };
+    if !interactive {
+        bail!(
+            "\"{}\" is {}, pass --allow-nested to create the project there anyway",
+            destination.display(),
+            location
+        );
+    }
+    eprintln!(
+        "{} \"{}\" is {}, the new project would be nested in it",
+        Symbol::Warning,
+        destination.display(),
+        location
+    );
+    confirm(t(Message::CreateNestedAnyway), Some(false))
+        .context("Failed to prompt for a nested project")
+}
+
+/// Command line arguments
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Print the version, the default template, the target and the versions
+    /// of the main dependencies, for bug reports
+    #[arg(long)]
+    print_version_info: bool,
+
+    /// Name of the project, also used as the destination directory
+    #[arg(default_value = "esp-new-project", conflicts_with = "options_file")]
+    name: String,
+
+    /// Read every project option from a JSON file instead of prompting
+    #[arg(long, value_name = "PATH")]
+    options_file: Option<PathBuf>,
+
+    /// Check that idf.py, cmake, ninja and the compiler are installed before generating
+    #[arg(long)]
+    verify_build_deps: bool,
+
+    /// Abort when --verify-build-deps finds a missing tool instead of only warning
+    #[arg(long, requires = "verify_build_deps")]
+    strict: bool,
+
+    /// Print every effective setting and where it comes from (default,
+    /// config file, profile, environment variable or flag) instead of
+    /// generating the project
+    #[arg(long)]
+    explain_config: bool,
+
+    /// Print --explain-config as JSON
+    #[arg(long, requires = "explain_config")]
+    json: bool,
+
+    /// List the serial ports that look like ESP boards, to pass to
+    /// `idf.py -p`, and exit
+    #[arg(long)]
+    probe_serial_ports: bool,
+
+    /// Print the size of a template (URL, zip, tar.gz or directory) by
+    /// top-level directory, largest first, and exit without generating
+    #[arg(long, value_name = "URL|PATH")]
+    analyze_template: Option<String>,
+
+    /// Development board, sets the target, flash size, PSRAM and the pins of
+    /// the starters (see `list-boards`)
+    #[arg(long, value_name = "ID", conflicts_with = "options_file")]
+    board: Option<String>,
+
+    /// Chip of a custom board (esp32, esp32s2, esp32s3, esp32c3, esp32c6, esp32h2)
+    #[arg(long, value_name = "CHIP", conflicts_with_all = ["options_file", "board"])]
+    target: Option<String>,
+
+    /// Add a sample main/Kconfig.projbuild with an option read by the main file
+    #[arg(long, conflicts_with = "options_file")]
+    kconfig_example: bool,
+
+    /// C++ only: put the application in an App class (main/app.hpp and
+    /// main/app.cpp) run by a minimal main.cpp, the starters are initialized
+    /// in App::init
+    #[arg(long, conflicts_with_all = ["options_file", "main_file"])]
+    app_class: bool,
+
+    /// Only extract the build-system files of the template (CMakeLists.txt,
+    /// Kconfig, sdkconfig, partition tables...) to add the ESP-IDF build
+    /// around existing sources; no main source or README is written
+    #[arg(long, conflicts_with_all = [
+        "options_file", "main_file", "app_class", "kconfig_example", "starters",
+        "host_test_asan", "bootloader_hooks", "workspace",
+    ])]
+    scaffold_only_config: bool,
+
+    /// Skip template files that cannot be extracted instead of aborting,
+    /// the skipped files are listed at the end
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Post-processing of the extracted template: auto copies a template
+    /// without the layout of an ESP-IDF project as it is, full always
+    /// post-processes, none only fills in the placeholders
+    #[arg(long, value_name = "MODE")]
+    post_process: Option<PostProcess>,
+
+    /// Run `idf.py reconfigure` in the generated project to check that its
+    /// CMake configuration is valid (needs ESP-IDF)
+    #[arg(long)]
+    verify_configure: bool,
+
+    /// Download the managed components of idf_component.yml to
+    /// managed_components/ and write dependencies.lock (runs `idf.py
+    /// reconfigure`, skipped with a notice without ESP-IDF); the lock file
+    /// can then be committed
+    #[arg(long, conflicts_with = "offline")]
+    fetch_components: bool,
+
+    /// Write a record of the generation (files and their hashes, template,
+    /// features, environment) to this file, as JSON if it ends with .json
+    /// and as text otherwise; it's written even if the generation fails
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Write the files of the generated project and their SHA-256 (.git
+    /// excluded) to this JSON file, for --verify-manifest
+    #[arg(long, value_name = "PATH")]
+    emit_manifest: Option<PathBuf>,
+
+    /// Check that the generated files match the manifest written by
+    /// --emit-manifest, and fail listing every missing, unexpected or changed
+    /// file otherwise
+    #[arg(long, value_name = "PATH")]
+    verify_manifest: Option<PathBuf>,
+
+    /// Record what the generation depends on (resolved options, prompt
+    /// answers, template URL, ref and SHA-256, OS and tool versions) to this
+    /// file, for --replay; secrets such as passwords and URL tokens are
+    /// redacted. It's written even if the generation fails
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Embed the template archive in the --record file, to replay it
+    /// without downloading the template again
+    #[arg(long, requires = "record")]
+    record_full: bool,
+
+    /// Generate a project again from a --record file, with its options and
+    /// the template it was generated from
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["options_file", "name"])]
+    replay: Option<PathBuf>,
+
+    /// Write the project to this archive (.zip, .tar.gz or .tgz) instead of
+    /// a directory, the archive unpacks to a directory named after the
+    /// project (no git repo is initialized without --archive-with-git)
+    #[arg(long, value_name = "PATH", conflicts_with = "verify_configure")]
+    archive: Option<PathBuf>,
+
+    /// Initialize the git repo of the project and include it in the --archive
+    #[arg(long, requires = "archive")]
+    archive_with_git: bool,
+
+    /// Write an --archive that only depends on the generated files: fixed
+    /// timestamps and permissions
+    #[arg(long, requires = "archive")]
+    reproducible: bool,
+
+    /// Add a .clang-format to the project
+    #[arg(long, conflicts_with = "options_file")]
+    clang_format: bool,
+
+    /// Hygiene files to add (editorconfig, clang-format, gitignore,
+    /// gitattributes, pre-commit), pre-selected in the prompt
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_delimiter = ',',
+        conflicts_with = "options_file"
+    )]
+    hygiene: Vec<HygieneFile>,
+
+    /// Indent width of the generated .clang-format (2 or 4)
+    #[arg(long, value_name = "WIDTH", requires = "clang_format")]
+    indent_width: Option<u32>,
+
+    /// Column limit of the generated .clang-format (80, 100 or 120)
+    #[arg(long, value_name = "COLUMNS", requires = "clang_format")]
+    column_limit: Option<u32>,
+
+    /// FreeRTOS tick rate in Hz (CONFIG_FREERTOS_HZ), 1000 if only
+    /// --main-task-stack is set
+    #[arg(long, value_name = "HZ", conflicts_with = "options_file")]
+    tick_rate: Option<u32>,
+
+    /// Stack size of the main task in bytes (CONFIG_ESP_MAIN_TASK_STACK_SIZE),
+    /// 4096 if only --tick-rate is set
+    #[arg(long, value_name = "BYTES", conflicts_with = "options_file")]
+    main_task_stack: Option<u32>,
+
+    /// Add a custom partitions.csv for a flash of this size in MB (2, 4, 8 or 16)
+    #[arg(long, value_name = "MB", conflicts_with = "options_file")]
+    flash_size: Option<u32>,
+
+    /// Partition scheme of the partitions.csv (single_app, single_app_large,
+    /// two_ota or factory_two_ota), the largest that fits the flash if not set
+    #[arg(long, value_name = "SCHEME", requires = "flash_size")]
+    partition_scheme: Option<PartitionScheme>,
+
+    /// Size of every app partition (factory and OTA slots) of the
+    /// partitions.csv, e.g. 2M or 1536K, a multiple of 64K; the one of the
+    /// scheme if not set
+    #[arg(long, value_name = "SIZE", requires = "flash_size", value_parser = partitions::parse_app_size)]
+    app_size: Option<u32>,
+
+    /// Add the metadata to publish the main component to the component
+    /// registry to main/idf_component.yml (version 0.1.0, description and
+    /// targets derived from the project)
+    #[arg(long, conflicts_with = "options_file")]
+    component_metadata: bool,
+
+    /// Homepage of the component in main/idf_component.yml
+    #[arg(long, value_name = "URL", requires = "component_metadata")]
+    component_url: Option<String>,
+
+    /// SPDX license of the component in main/idf_component.yml, e.g. MIT
+    #[arg(long, value_name = "SPDX", requires = "component_metadata")]
+    component_license: Option<String>,
+
+    /// Maintainer of the component in main/idf_component.yml, e.g.
+    /// "Name <email>"; can be repeated
+    #[arg(long, value_name = "NAME", requires = "component_metadata")]
+    maintainer: Vec<String>,
+
+    /// Add a host test in host_test/ built with AddressSanitizer, for
+    /// hardware-independent code
+    #[arg(long, conflicts_with = "options_file")]
+    host_test_asan: bool,
+
+    /// Advanced: add bootloader_components/ with a component that hooks into
+    /// the second-stage bootloader, before and after its initialization
+    #[arg(long, conflicts_with = "options_file")]
+    bootloader_hooks: bool,
+
+    /// Add flash.sh (flash.ps1 on Windows) to build, flash and monitor the
+    /// project in one command; its default port is the ESP board connected
+    /// now, if there is exactly one
+    #[arg(long, conflicts_with = "options_file")]
+    flash_script: bool,
+
+    /// Default port of the flash script instead of the detected one
+    #[arg(long, value_name = "PORT", requires = "flash_script")]
+    flash_port: Option<String>,
+
+    /// Add a pipeline that builds the project for its chip in the
+    /// espressif/idf image: github (.github/workflows/build.yml) or gitlab
+    /// (.gitlab-ci.yml)
+    #[arg(long, value_name = "PROVIDER", conflicts_with = "options_file")]
+    ci: Option<CiProvider>,
+
+    /// Leave the badges (license, minimum ESP-IDF version) out of the
+    /// generated README
+    #[arg(long, conflicts_with = "options_file")]
+    no_badges: bool,
+
+    /// After the generation, estimate the size of the app for the selected
+    /// starters and compare it with its app partition (a rough hint, not a
+    /// measurement)
+    #[arg(long)]
+    report_size: bool,
+
+    /// After the generation, print the choices (target, language...) as
+    /// `export ESP_TARGET=...` lines, or write them to FILE
+    /// (`--export-env=FILE`) to source from build scripts
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-"
+    )]
+    export_env: Option<PathBuf>,
+
+    /// Shell of the --export-env lines: sh (default) or powershell
+    #[arg(long, value_name = "SHELL", requires = "export_env")]
+    export_shell: Option<ExportShell>,
+
+    /// No .gitignore in the project, not even the template's; the git repo
+    /// is still initialized and your global ignore file applies
+    #[arg(long, conflicts_with = "options_file")]
+    no_gitignore: bool,
+
+    /// No sdkconfig.defaults, the configuration is left to idf.py
+    /// menuconfig: the board, chip and advanced configuration prompts are
+    /// skipped, and the settings picked anyway are listed as a manual step
+    #[arg(long, conflicts_with = "options_file")]
+    no_sdkconfig: bool,
+
+    /// ESP-IDF version the project will be built with (e.g. 5.1), checked
+    /// against the selected target and starters; detected from idf.py if not
+    /// set
+    #[arg(long, value_name = "VERSION")]
+    idf_version: Option<IdfVersion>,
+
+    /// What an ESP-IDF too old for the selected target and features does:
+    /// error stops before generating, warn lists the incompatibilities and
+    /// generates anyway, off skips the check
+    #[arg(long, value_name = "MODE", default_value_t = CompatCheck::Error)]
+    compat_check: CompatCheck,
+
+    /// What a failed optional step (git init) does when nobody can be asked:
+    /// skip goes on with a warning and a manual step, abort removes the
+    /// project; an interactive run asks to retry, skip or abort instead
+    #[arg(long, value_name = "POLICY", default_value_t = FailurePolicy::Skip)]
+    on_step_failure: FailurePolicy,
+
+    /// Generate a workspace: shared components/ and sdkconfig.defaults at
+    /// the root, and one ESP-IDF project per app
+    #[arg(long, conflicts_with = "options_file")]
+    workspace: bool,
+
+    /// Apps of a workspace written at the same time, after the template is
+    /// downloaded once; the number of CPUs by default
+    #[arg(long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Names of the apps of the workspace, comma separated
+    #[arg(
+        long,
+        value_name = "NAME,NAME",
+        value_delimiter = ',',
+        requires = "workspace",
+        default_values_t = DEFAULT_WORKSPACE_APPS.iter().map(|app| app.to_string())
+    )]
+    apps: Vec<String>,
+
+    /// Move the components every app gets a copy of (e.g. the bootloader
+    /// hooks) to the workspace root and link them from the apps with relative
+    /// symbolic links; copies are kept where links cannot be created
+    #[arg(long, requires = "workspace", conflicts_with = "options_file")]
+    relative_symlinks: bool,
+
+    /// Add a factory_test app to the workspace: a console with a test
+    /// command per starter (LED blink, Wi-Fi scan...), flashed to its own
+    /// partition of the custom partition table
+    #[arg(
+        long,
+        requires_all = ["workspace", "flash_size"],
+        conflicts_with = "options_file"
+    )]
+    factory_test: bool,
+
+    /// Permissions of the created directories in octal (e.g. 750), applied
+    /// regardless of the umask; Unix only, the system default if not set
+    #[arg(long, value_name = "OCTAL", value_parser = platform::parse_mode)]
+    dir_mode: Option<u32>,
+
+    /// Use this file as the main source instead of the built-in one, its
+    /// extension must match the language (.c, or .cpp/.cc/.cxx for C++)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["options_file", "kconfig_example"])]
+    main_file: Option<PathBuf>,
+
+    /// Add a starter to the main component (nvs, wifi, mqtt, led), can be repeated
+    #[arg(long = "starter", value_name = "ID", conflicts_with = "options_file")]
+    starters: Vec<String>,
+
+    /// Branch, tag or commit of the official template to use instead of master
+    #[arg(long, value_name = "REF", conflicts_with = "options_file")]
+    template_ref: Option<String>,
+
+    /// Template to use instead of the official one: the URL or path of a zip,
+    /// e.g. one written by `templatize`
+    #[arg(long, value_name = "URL|PATH", conflicts_with_all = ["options_file", "template_ref"])]
+    template: Option<String>,
+
+    /// Catalog of named templates to pick from (TOML or JSON, path or URL),
+    /// with --template-name or from a list
+    #[arg(long, value_name = "PATH|URL", conflicts_with_all = ["options_file", "template_ref", "template"])]
+    template_manifest: Option<String>,
+
+    /// Template of the --template-manifest to use, instead of picking it
+    /// from a list
+    #[arg(long, value_name = "NAME", requires = "template_manifest")]
+    template_name: Option<String>,
+
+    /// Refuse a template larger than SIZE (e.g. 500M), downloaded or
+    /// extracted; 200M if not set
+    #[arg(long, value_name = "SIZE", value_parser = download::parse_max_size)]
+    max_template_size: Option<u64>,
+
+    /// Use the cached template without downloading it again while it was
+    /// downloaded less than DURATION ago (e.g. 30m, 12h, 7d, or infinite);
+    /// 0, the default, downloads it every time
+    #[arg(long, value_name = "DURATION")]
+    template_ttl: Option<TemplateTtl>,
+
+    /// Warn if the --template-ref is older than AGE (e.g. 90d, 6m, 2y)
+    #[arg(
+        long,
+        value_name = "AGE",
+        value_parser = template_ref::parse_age,
+        requires = "template_ref"
+    )]
+    since: Option<Duration>,
+
+    /// Pre-fill the prompts with a profile of the config file, instead of
+    /// its default_profile
+    #[arg(long, value_name = "NAME", conflicts_with = "options_file")]
+    profile: Option<String>,
+
+    /// Run the first-run onboarding again (build tools found, defaults of
+    /// new projects, update check) and exit
+    #[arg(long)]
+    onboard: bool,
+
+    /// Use the default for every option not given by a flag or the profile
+    /// instead of asking
+    #[arg(long, short = 'y', conflicts_with = "options_file")]
+    yes: bool,
+
+    /// Don't use the choices of the last project (language, board or chip,
+    /// git) as the defaults of the prompts, and don't remember these
+    #[arg(long)]
+    no_remember: bool,
+
+    /// Create the project even inside ESP-IDF or another project
+    #[arg(long)]
+    allow_nested: bool,
+
+    /// Treat a directory that only holds ignorable files (.DS_Store,
+    /// Thumbs.db, desktop.ini, or `ignorable_files` in the config file) as
+    /// empty, without the prompt to delete it
+    #[arg(long)]
+    no_prompt_on_empty: bool,
+
+    /// Generate the project even when running as root or administrator,
+    /// e.g. in a container
+    #[arg(long)]
+    allow_root: bool,
+
+    /// Generate the project even in a system directory (e.g. /usr, /opt,
+    /// C:\Windows), the root of the filesystem or the home directory itself
+    #[arg(long)]
+    allow_system_dir: bool,
+
+    /// Wrap the error messages to this many columns, 0 to not wrap them;
+    /// the width of the terminal by default
+    #[arg(long, value_name = "COLUMNS", global = true)]
+    wrap_width: Option<u16>,
+
+    /// Language of the prompts (en, es), the one of LC_ALL, LC_MESSAGES or
+    /// LANG by default; English if it has no translation
+    #[arg(long, value_name = "LOCALE", global = true)]
+    lang: Option<String>,
+
+    /// Print ASCII status symbols and prompts instead of emojis, detected
+    /// from the terminal by default
+    #[arg(long, global = true)]
+    ascii: bool,
+
+    /// Theme of the prompts and status lines: colorful, simple (no colors) or
+    /// high-contrast (no colors, words like OK and FAIL instead of symbols);
+    /// the `theme` of the config file, colorful by default
+    #[arg(long, value_name = "THEME", global = true)]
+    theme: Option<Theme>,
+
+    /// Screen-reader friendly prompts: numbered menus and y/n questions
+    /// answered by typing a line, no cursor movements, colors or symbols,
+    /// and every step on its own line; on by default with TERM=dumb
+    #[arg(long, global = true)]
+    accessible: bool,
+
+    /// Never access the network, commands that need it fail right away
+    #[arg(long, global = true)]
+    offline: bool,
+}
+
+/// Commands other than generating a project
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Replace this binary with the latest release from GitHub
+    SelfUpdate {
+        /// Only report whether a newer release exists
+        #[arg(long)]
+        check: bool,
+    },
+    /// Add a starter (wifi, mqtt...) to the project that contains the current directory
+    Add {
+        /// Id of the starter
+        starter: String,
+
+        /// What to do when a starter file already exists: fail, skip or overwrite
+        #[arg(long, value_name = "MODE", default_value_t = MergeMode::Fail)]
+        on_conflict: MergeMode,
+    },
+    /// Manage the components of an existing project
+    #[command(subcommand)]
+    Component(ComponentCommand),
+    /// Print an overview of an existing project
+    Info {
+        /// A directory inside the project, the current directory by default
+        path: Option<PathBuf>,
+
+        /// Print the overview as JSON, for tools
+        #[arg(long)]
+        json: bool,
+    },
+    /// Look for known foot-guns in an existing project and offer to fix them,
+    /// fails if errors are found
+    Check {
+        /// A directory inside the project, the current directory by default
+        path: Option<PathBuf>,
+
+        /// Apply every safe fix without asking
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Re-apply the generators (CMakeLists.txt, .clang-format, .gitignore,
+    /// CMakePresets.json, hygiene files) to an existing project
+    Regen {
+        /// A directory inside the project, the current directory by default
+        path: Option<PathBuf>,
+
+        /// Only re-apply these generators (cmake, clang-format, gitignore,
+        /// presets, hygiene), all by default
+        #[arg(long, value_name = "TARGET", value_delimiter = ',')]
+        only: Vec<RegenTarget>,
+
+        /// Apply every change without asking
+        #[arg(long)]
+        yes: bool,
+
+        /// Run even if the git tree has uncommitted changes
+        #[arg(long)]
+        allow_dirty: bool,
+    },
+    /// Manage the profiles of the config file
+    #[command(subcommand)]
+    Profiles(ProfilesCommand),
+    /// Rename an existing project (project(), README title, git description)
+    Rename {
+        /// The new name of the project
+        new_name: String,
+
+        /// A directory inside the project, the current directory by default
+        path: Option<PathBuf>,
+
+        /// Also rename the project directory
+        #[arg(long = "move")]
+        move_dir: bool,
+
+        /// Rename even if the git tree has uncommitted changes
+        #[arg(long)]
+        allow_dirty: bool,
+    },
+    /// Pack an existing project into a template archive for --template
+    Templatize {
+        /// A directory inside the project, the current directory by default
+        path: Option<PathBuf>,
+
+        /// The zip archive to write
+        #[arg(long, short = 'o', value_name = "FILE")]
+        out: PathBuf,
+    },
+    /// List the starters, the chips they run on and what they need
+    Starters {
+        /// Only the starters that run on this chip (e.g. esp32c3)
+        #[arg(long, value_name = "CHIP")]
+        target: Option<String>,
+
+        /// Print the list as JSON, for tools
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the development boards that can be passed to --board
+    ListBoards,
+    /// List the example projects of ESP-IDF, grouped by category
+    ListExamples,
+    /// Build the project and flash it, setting up the ESP-IDF environment
+    /// and finding the board if needed
+    Flash {
+        /// A directory inside the project, the current directory by default
+        path: Option<PathBuf>,
+
+        /// Serial port of the board, detected if there's only one
+        #[arg(long, short = 'p')]
+        port: Option<String>,
+    },
+    /// Open the serial monitor of the board, setting up the ESP-IDF
+    /// environment and finding the board if needed
+    Monitor {
+        /// A directory inside the project, the current directory by default
+        path: Option<PathBuf>,
+
+        /// Serial port of the board, detected if there's only one
+        #[arg(long, short = 'p')]
+        port: Option<String>,
+    },
+    /// Check what the tool needs to scaffold a project: the template server,
+    /// the cache directory, git and ESP-IDF; and the serial port to flash it
+    Doctor {
+        /// Exit with an error if a check fails
+        #[arg(long)]
+        strict: bool,
+
+        /// The template to check, the official one by default
+        #[arg(long, value_name = "URL")]
+        template: Option<String>,
+    },
+    /// Generate every project of a manifest, e.g. the identical projects of
+    /// a course; the template is downloaded once, and a project that fails
+    /// doesn't stop the others
+    Batch {
+        /// The TOML manifest: a [base] profile and a [[project]] table
+        /// (name and overrides) per project
+        manifest: PathBuf,
+
+        /// Projects generated at the same time, one by default
+        #[arg(long, value_name = "N")]
+        jobs: Option<NonZeroUsize>,
+
+        /// Print the results as JSON, for scripts
+        #[arg(long)]
+        json: bool,
+
+        /// Run even as root or as an elevated administrator
+        #[arg(long)]
+        allow_root: bool,
+    },
+}
+
+/// `component` subcommands
+#[derive(Subcommand, Debug)]
+enum ComponentCommand {
+    /// Create components/<NAME>/ in the project that contains the current directory
+    New {
+        /// Name of the component, a C identifier
+        name: String,
+
+        /// Add a Kconfig with a sample option
+        #[arg(long)]
+        kconfig: bool,
+
+        /// Add a test/ directory with a Unity test case
+        #[arg(long)]
+        test: bool,
+    },
+}
+
+/// `profiles` subcommands
+#[derive(Subcommand, Debug)]
+enum ProfilesCommand {
+    /// List the profiles and the config file they come from
+    List,
+}
+
+/// Parses the command line, exits on an error like [`Cli::parse`]
+///
+/// A subcommand takes its own arguments and the global ones, a generation
+/// flag given with it (e.g. `--target esp32 component new foo`) would be
+/// ignored, so it's an error. `args_conflicts_with_subcommands` cannot be
+/// used: it reads the subcommand after a global flag (`--offline doctor`)
+/// as the project name.
+fn parse_cli() -> Cli {
+    let mut command = Cli::command();
+    let matches = command.get_matches_mut();
+    if let Some((subcommand, _)) = matches.subcommand() {
+        let ignored: Vec<String> = command
+            .get_arguments()
+            .filter(|arg| !arg.is_global_set())
+            .filter(|arg| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+            .map(|arg| match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => format!("<{}>", arg.get_id()),
+            })
+            .collect();
+        if !ignored.is_empty() {
+            command
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!(
+                        "the subcommand '{}' cannot be used with: {}",
+                        subcommand,
+                        ignored.join(", ")
+                    ),
+                )
+                .exit();
+        }
+    }
+    Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit())
+}
+
+fn main() {
+    let mut wrap_width = None;
+    let result = match run_external_subcommand() {
+        Ok(Some(code)) => process::exit(code),
+        Ok(None) => {
+            let cli = parse_cli();
+            wrap_width = cli.wrap_width;
+            run(cli)
+        }
+        Err(error) => Err(error),
+    };
+    if let Err(error) = result {
+        status::clear();
+        let width = match wrap_width {
+            Some(0) => None,
+            Some(width) => Some(usize::from(width)),
+            // Piped errors stay on one line per cause, e.g. for grep
+            None => console::Term::stderr()
+                .size_checked()
+                .map(|(_, columns)| usize::from(columns)),
+        };
+        eprint!("{}", error_format::format_error(&error, width));
+        process::exit(1);
+    }
+}
+
+/// Runs the command line, see [`main`]
+///
+/// # Errors
+/// Any error that ends the program, printed by [`main`]
+fn run(cli: Cli) -> anyhow::Result<()> {
+    i18n::set_locale(Locale::detect(cli.lang.as_deref(), |name| {
+        env::var(name).ok()
+    }));
+    // An invalid config is reported where it's used, after the theme is set
+    let config_theme = UserConfig::load().ok().and_then(|config| config.theme);
+    set_theme(
+        Theme::resolve(cli.theme, config_theme),
+        cli.ascii,
+        prompt::is_accessible(cli.accessible, env::var("TERM").ok().as_deref()),
+    );
+
+    if cli.print_version_info {
+        print!("{}", VersionInfo::current().to_text());
+        return Ok(());
+    }
+    // Stdout only gets the exported lines, to be piped or evaluated
+    if cli.export_env.as_deref() == Some(Path::new("-")) {
+        status::set_stderr();
+    }
+
+    match cli.command {
+        Some(Command::SelfUpdate { check }) => return self_update(check, cli.offline),
+        Some(Command::Component(ComponentCommand::New {
+            name,
+            kconfig,
+            test,
+        })) => {
+            let current_dir = env::current_dir().context("Cannot read the current directory")?;
+            let options = ComponentOptions {
+                kconfig,
+                unit_test: test,
+            };
+            let dir = component::new_component(&current_dir, &name, options)?;
+            println!(
+                "{} Component created in \"{}\", add it to the REQUIRES of the components using it",
+                Symbol::Done,
+                dir.display()
+            );
+            return Ok(());
+        }
+        Some(Command::Add {
+            starter,
+            on_conflict,
+        }) => return add_starter(&starter, on_conflict),
+        Some(Command::Info { path, json }) => return info(path, json),
+        Some(Command::Profiles(ProfilesCommand::List)) => return list_profiles(),
+        Some(Command::Check { path, fix }) => return check(path, fix),
+        Some(Command::Regen {
+            path,
+            only,
+            yes,
+            allow_dirty,
+        }) => return regen(path, &only, yes, allow_dirty),
+        Some(Command::Rename {
+            new_name,
+            path,
+            move_dir,
+            allow_dirty,
+        }) => {
+            let options = RenameOptions {
+                move_dir,
+                allow_dirty,
+            };
+            return rename(&new_name, path, options);
+        }
+        Some(Command::Templatize { path, out }) => return templatize(path, &out),
+        Some(Command::Starters { target, json }) => return list_starters(target.as_deref(), json),
+        Some(Command::ListBoards) => {
+            list_boards();
+            return Ok(());
+        }
+        Some(Command::ListExamples) => {
+            list_examples(cli.offline);
+            return Ok(());
+        }
+        Some(Command::Flash { path, port }) => return board(BoardAction::Flash, path, port),
+        Some(Command::Monitor { path, port }) => return board(BoardAction::Monitor, path, port),
+        Some(Command::Doctor { strict, template }) => {
+            return run_doctor(template.as_deref(), strict, cli.offline)
+        }
+        Some(Command::Batch {
+            manifest,
+            jobs,
+            json,
+            allow_root,
+        }) => return batch(&manifest, jobs, json, allow_root, cli.offline),
+        None => {}
+    }
+
+    if cli.probe_serial_ports {
+        return probe_serial_ports();
+    }
+    if let Some(template) = &cli.analyze_template {
+        return analyze_template(template, cli.max_template_size);
+    }
+    if cli.explain_config {
+        return explain_config(&cli);
+    }
+    if cli.onboard {
+        return onboard();
+    }
+
+    check_not_elevated(cli.allow_root)?;
+
+    // Only when someone can answer, and not to generate from a file
+    let interactive =
+        !cli.yes && cli.options_file.is_none() && cli.replay.is_none() && console::user_attended();
+    if interactive {
+        if let (Some(config_file), Some(state_dir)) = (config::config_path(), config::state_dir()) {
+            if onboarding::is_first_run(&config_file, &state_dir) {
+                onboard()?;
+            }
+        }
+    }
+
+    // A malformed configuration doesn't stop the run, its settings are left out
+    let config = UserConfig::load().unwrap_or_else(|error| {
+        eprintln!("{} Configuration ignored: {:#}", Symbol::Warning, error);
+        UserConfig::default()
+    });
+
+    if cli.verify_build_deps {
+        let target = match &cli.board {
+            Some(board) => Some(boards::find(board)?.target),
+            None => cli.target.as_deref(),
+        };
+        verify_build_deps(target, cli.strict)?;
+    }
+
+    let replay = cli.replay.as_deref().map(ReplayBundle::read).transpose()?;
+    let loaded = match (&replay, &cli.options_file) {
+        (Some(bundle), _) => Some(bundle.options()?),
+        (None, Some(path)) => Some(ScaffoldOptions::from_json_file(path)?),
+        (None, None) => None,
+    };
+    let ignorable: &[String] = if cli.no_prompt_on_empty {
+        &config.ignorable_files
+    } else {
+        &[]
+    };
+    let mut answers = Vec::new();
+    // The last choices with the answers of the prompts, none from an options file
+    let mut choices = None;
+    let mut options = match loaded {
+        Some(options) => {
+            // With --archive nothing is written to the directory
+            if cli.archive.is_none() {
+                if !cli.allow_system_dir {
+                    project::check_not_system_dir(Path::new(&options.project_name))?;
+                }
+                // Around an existing codebase, only the missing files are written
+                if !options.scaffold_only_config
+                    && project::directory_not_empty(Path::new(&options.project_name), ignorable)
+                {
+                    bail!(
+                        "Directory \"{}\" is not empty, refusing to overwrite it without prompting",
+                        options.project_name
+                    );
+                }
+                if !cli.allow_nested {
+                    confirm_not_nested(Path::new(&options.project_name), false)?;
+                }
+            }
+            options
+        }
+        None => {
+            ProjectNames::new(&cli.name)?;
+            let dir = Path::new(&cli.name);
+            if cli.archive.is_none() {
+                if !cli.allow_system_dir {
+                    project::check_not_system_dir(dir)?;
+                }
+                if !cli.allow_nested && !confirm_not_nested(dir, !cli.yes)? {
+                    return Ok(());
+                }
+                // Offered last, once the destination passed every check; around
+                // an existing codebase, only the missing files are written
+                if !cli.scaffold_only_config && project::directory_not_empty(dir, ignorable) {
+                    if cli.yes {
+                        bail!("Directory \"{}\" is not empty", cli.name);
+                    }
+                    if !prompt_directory_delete(dir)? {
+                        return Ok(());
+                    }
+                }
+            }
+
+            // Flags win over the profile, the prompts only ask what's left
+            let profile_name = config.profile_name(cli.profile.as_deref());
+            let (mut profile, sources) = match profile_name {
+                Some(name) => config.resolve_profile_sources(name)?,
+                None => (Profile::default(), BTreeMap::new()),
+            };
+            let mut flags = apply_flags(&cli, &mut profile);
+            let mut template_prefix = None;
+            if let Some(source) = &cli.template_manifest {
+                if cli.offline && download::local_path(source).is_none() {
+                    bail!(
+                        "The template manifest {} is downloaded from the network, it cannot be used with --offline",
+                        source
+                    );
+                }
+                let template =
+                    choose_manifest_template(source, cli.template_name.as_deref(), cli.yes)?;
+                profile.template_url = Some(template.url());
+                profile.template_ref = None;
+                template_prefix = template.strip_prefix;
+                flags.push(("template_url", "--template-manifest"));
+            }
+            let origins: Vec<String> = explain::explain_profile(&ProfileLayers {
+                profile: profile_name.map(|name| (name, &sources)),
+                last: None,
+                flags: &flags,
+                resolved: &profile,
+                yes: true,
+            })
+            .into_iter()
+            // Placeholders such as "(the board's)" are not set by the source
+            .filter(|setting| {
+                setting.source != SettingSource::Default && !setting.value.starts_with('(')
+            })
+            .map(|setting| format!("{} from {}", setting.name, setting.source))
+            .collect();
+            let remembered = if cli.no_remember {
+                LastChoices::default()
+            } else {
+                LastChoices::load()
+            };
+            let last = remembered.clone().or(&config.defaults);
+            let asked = explain::explain_profile(&ProfileLayers {
+                profile: None,
+                last: Some(&last),
+                flags: &flags,
+                resolved: &profile,
+                yes: cli.yes,
+            });
+            let app_class = match (cli.app_class, &cli.main_file) {
+                (true, _) => Some(true),
+                (false, Some(_)) => Some(false),
+                (false, None) if cli.scaffold_only_config => Some(false),
+                (false, None) => None,
+            };
+            let mut options = prompt_options(
+                cli.name.clone(),
+                profile,
+                app_class,
+                &last,
+                cli.yes,
+                cli.no_sdkconfig,
+            )?;
+            answers = replay::prompt_answers(&asked, &options);
+            let prompted: Vec<&str> = asked
+                .iter()
+                .filter(|setting| {
+                    matches!(
+                        setting.source,
+                        SettingSource::Prompt | SettingSource::LastChoices
+                    )
+                })
+                .map(|setting| setting.name.as_str())
+                .collect();
+            choices = Some(remembered.answered(&prompted, &options));
+            options.main_file = cli.main_file.clone();
+            options.scaffold_only_config = cli.scaffold_only_config;
+            options.host_test_asan = cli.host_test_asan;
+            options.bootloader_hooks = cli.bootloader_hooks;
+            options.flash_script = cli.flash_script;
+            options.flash_port = cli.flash_port.clone();
+            options.ci = cli.ci;
+            options.no_badges = cli.no_badges;
+            options.no_gitignore = cli.no_gitignore;
+            options.template_prefix = template_prefix;
+            if cli.workspace {
+                options.workspace_apps = cli.apps.clone();
+                options.relative_symlinks = cli.relative_symlinks;
+                options.factory_test = cli.factory_test;
+            }
+            options.validate().map_err(|error| {
+                if origins.is_empty() {
+                    error
+                } else {
+                    error.context(format!("Invalid settings ({})", origins.join(", ")))
+                }
+            })?;
+            if !cli.yes {
+                options.main_sources = prompt_main_sources(&options)?;
+            }
+            options
+        }
+    };
+
+    options.keep_going |= cli.keep_going;
+    if let Some(post_process) = cli.post_process {
+        options.post_process = post_process;
+    }
+    options.dir_mode = cli.dir_mode;
+    options.max_template_size = cli.max_template_size;
+    options.jobs = cli.jobs;
+    options.template_cache = TemplateCache::default_dir();
+    options.template_ttl = cli.template_ttl.or(config.template_ttl).unwrap_or_default();
+    options.offline = cli.offline;
+    check_idf_version(&mut options, cli.idf_version.clone(), cli.compat_check)?;
+
+    // Kept until the generation is done
+    let mut template_archive = None;
+    if let Some(bundle) = &replay {
+        if bundle.tool_version != self_update::CURRENT_VERSION {
+            eprintln!(
+                "{} The bundle was recorded by esp-create-project {}, the project may differ",
+                Symbol::Warning,
+                bundle.tool_version
+            );
+        }
+        options.template_sha256 = bundle.template.sha256.clone();
+        if let Some(archive) = bundle.archive()? {
+            let file = write_temp_template(&archive)?;
+            options.template_archive = Some(file.path().to_path_buf());
+            template_archive = Some(file);
+        }
+    }
+    // Before --archive moves the project to a temporary directory
+    let recorded_options = options.clone();
+
+    let project_name = options.project_name.clone();
+    // Generated in a temporary directory that only lives until it's archived
+    let staging = match &cli.archive {
+        Some(out) => {
+            archive::ArchiveFormat::from_path(out)?;
+            let names = ProjectNames::new(&options.project_name)?;
+            let staging = tempfile::tempdir().context("Cannot create a temporary directory")?;
+            let directory = staging.path().join(&names.display);
+            options.project_name = directory.to_string_lossy().into_owned();
+            options.use_git = cli.archive_with_git;
+            Some((staging, directory, names.display))
+        }
+        None => None,
+    };
+
+    if let Some(threshold) = cli.since {
+        warn_old_template_ref(&options, threshold, cli.offline);
+    }
+
+    // Runs while the template downloads, only reported if it is done by then
+    let update_check = update_check::start(cli.offline, &config);
+
+    // Installed after the prompts so Ctrl+C still aborts them right away
+    let token = CancellationToken::new();
+    {
+        let token = token.clone();
+        ctrlc::set_handler(move || token.cancel()).context("Cannot set the Ctrl+C handler")?;
+    }
+
+    // Downloaded first so the archive can be embedded in the record
+    if cli.record_full && template_archive.is_none() {
+        if cli.offline && download::local_path(&options.template_url()).is_none() {
+            return Err(download::offline_error(&options.template_url()));
+        }
+        let file = download::download_template_file(
+            &options.template_url(),
+            options.max_template_size(),
+            &token,
+        )
+        .context("Cannot download the template to record it")?;
+        options.template_archive = Some(file.path().to_path_buf());
+        template_archive = Some(file);
+    }
+
+    if options.flash_script && options.flash_port.is_none() {
+        options.flash_port = detect_flash_port();
+    }
+
+    // Read first, a wrong path fails before generating
+    let expected_manifest = cli
+        .verify_manifest
+        .as_deref()
+        .map(FileManifest::read)
+        .transpose()?;
+
+    let mut report = GenerationReport::default();
+    let mut runner = if interactive {
+        StepRunner::with_prompt(Box::new(prompt_recovery))
+            .with_plain_copy_prompt(Box::new(prompt_plain_copy))
+    } else {
+        StepRunner::new(cli.on_step_failure)
+    };
+    let result = generate_with_runner(&options, &token, &mut report, &mut runner);
+    if let Some(path) = &cli.report {
+        write_report(path, &project_name, &options, &report, &result)?;
+    }
+    if let Some(path) = &cli.record {
+        let archive = match (&template_archive, cli.record_full) {
+            (Some(file), true) => {
+                Some(fs::read(file.path()).context("Cannot read the template to record it")?)
+            }
+            _ => None,
+        };
+        let bundle = ReplayBundle::new(
+            &recorded_options,
+            answers,
+            TemplateProvenance {
+                url: recorded_options.template_url().into_owned(),
+                git_ref: recorded_options.template_ref.clone(),
+                sha256: report.template_sha256.clone(),
+            },
+            Environment::new(&build_deps::check_build_deps(
+                recorded_options.build_target().ok().as_deref(),
+            )),
+            archive.as_deref(),
+        );
+        match bundle.write(path) {
+            Err(e) if result.is_err() => eprintln!("{} {:#}", Symbol::Warning, e),
+            written => written?,
+        }
+    }
+    match result {
+        Ok(()) => {}
+        Err(EspCreateError::Cancelled) => {
+            eprintln!("\nCancelled, the generated files were removed");
+            process::exit(130);
+        }
+        Err(EspCreateError::Other(e)) => return Err(e),
+    }
+
+    if expected_manifest.is_some() || cli.emit_manifest.is_some() {
+        let names = ProjectNames::new(&options.project_name)?;
+        let manifest = FileManifest::of_project(&names.directory)?;
+        if let (Some(expected), Some(path)) = (&expected_manifest, &cli.verify_manifest) {
+            expected.verify(&manifest)?;
+            say!(
+                "{} The files match the manifest {}",
+                Symbol::Done,
+                path.display()
+            );
+        }
+        if let Some(path) = &cli.emit_manifest {
+            manifest.write(path)?;
+            say!(
+                "{} Manifest of {} files written to {}",
+                Symbol::Done,
+                manifest.files.len(),
+                path.display()
+            );
+        }
+    }
+
+    if let (false, Some(choices)) = (cli.no_remember, &choices) {
+        if let Err(e) = choices.save() {
+            eprintln!("{} {:#}", Symbol::Warning, e);
+        }
+    }
+
+    if let (Some(out), Some((_staging, directory, name))) = (&cli.archive, &staging) {
+        let files = archive::archive_directory(directory, name, out, cli.reproducible)?;
+        say!(
+            "{} {} files archived to {}",
+            Symbol::Done,
+            files,
+            out.display()
+        );
+    }
+
+    if !report.replacements.is_empty() {
+        say!("{} Replacements of the template:", Symbol::Edit);
+        for file in &report.replacements {
+            say!("  {}: {}", file.path.display(), file.count);
+        }
+    }
+    if let Some(size) = &report.template_size {
+        if let Some((first, rest)) = size.trimming_lines().split_first() {
+            eprintln!("{} {}", Symbol::Warning, first);
+            for line in rest {
+                eprintln!("{}", line);
+            }
+        }
+    }
+    if !report.skipped_post_processing.is_empty() {
+        eprintln!(
+            "{} The template was copied as it is, these steps were skipped:",
+            Symbol::Warning
+        );
+        for step in &report.skipped_post_processing {
+            eprintln!("  {}", step);
+        }
+        if options.post_process == PostProcess::Auto {
+            eprintln!("  --post-process full runs them anyway, on a template you know is fine");
+        }
+    }
+    if !report.failed_apps.is_empty() {
+        eprintln!(
+            "{} {} apps could not be written:",
+            Symbol::Failed,
+            report.failed_apps.len()
+        );
+        for app in &report.failed_apps {
+            eprintln!("  {}: {}", app.name, app.error);
+        }
+    }
+    if !report.failed_entries.is_empty() {
+        eprintln!(
+            "{} {} template files could not be extracted:",
+            Symbol::Failed,
+            report.failed_entries.len()
+        );
+        for entry in &report.failed_entries {
+            eprintln!("  {}: {}", entry.name, entry.error);
+        }
+    }
+    if !report.failed_apps.is_empty() || !report.failed_entries.is_empty() {
+        process::exit(1);
+    }
+
+    if cli.verify_configure {
+        verify_configure(&options)?;
+    }
+    if cli.fetch_components {
+        let steps = fetch_components(&options, report.git_initialized, interactive)?;
+        report.manual_steps.extend(steps);
+    }
+
+    if cli.report_size {
+        print_size_hint(&size_hint::estimate(&options));
+    }
+    if let Some(path) = &cli.export_env {
+        export_env(path, cli.export_shell.unwrap_or_default(), &options)?;
+    }
+    print_manual_steps(&report.manual_steps);
+    say!("{} Have fun!", Symbol::Fun);
+    if let Some(tag) = update_check.and_then(|check| check.newer_release()) {
+        say!(
+            "esp-create-project {} is available, run `esp-create-project self-update` to install it",
+            tag
+        );
+    }
+    Ok(())
+}
+
+/// Prints the choices of the project as shell variables, or writes them to
+/// a file to source, see [`export_env::variables`]
+///
+/// # Arguments
+/// * `path` - The file to write, `-` for stdout
+/// * `shell` - The shell the lines are written for
+/// * `options` - The options the project was generated with
+///
+/// # Errors
+/// If the project directory cannot be found or the file cannot be written
+fn export_env(path: &Path, shell: ExportShell, options: &ScaffoldOptions) -> anyhow::Result<()> {
+    let directory = env::current_dir()
+        .context("Cannot read the current directory")?
+        .join(ProjectNames::new(&options.project_name)?.directory);
+    let script = export_env::render(&export_env::variables(options, &directory)?, shell);
+    if path == Path::new("-") {
+        print!("{}", script);
+        return Ok(());
+    }
+    fs::write(path, script).context(format!("Cannot write \"{}\"", path.display()))?;
+    say!("{} Choices exported to {}", Symbol::Done, path.display());
+    Ok(())
+}
+
+/// Picks the template of a `--template-manifest`, the invalid ones are
+/// left out with a warning
+///
+/// # Arguments
+/// * `source` - Path or URL of the manifest
+/// * `name` - The `--template-name`, the template is picked from a list if
+///   not given
+/// * `yes` - Whether the prompts are skipped, then the name is required
+///
+/// # Errors
+/// If the manifest cannot be loaded, the name is not one of its valid
+/// templates, none is valid or the prompt fails
+fn choose_manifest_template(
+    source: &str,
+    name: Option<&str>,
+    yes: bool,
+) -> anyhow::Result<ManifestTemplate> {
+    let (mut templates, invalid) = TemplateManifest::load(source)?.validated();
+    for template in &invalid {
+        match name {
+            Some(name) if name == template.name => bail!(
+                "The template \"{}\" of the manifest is invalid: {:#}",
+                name,
+                template.error
+            ),
+            _ => eprintln!(
+                "{} Template \"{}\" left out: {:#}",
+                Symbol::Warning,
+                template.name,
+                template.error
+            ),
+        }
+    }
+    if templates.is_empty() {
+        bail!("The template manifest \"{}\" has no valid template", source);
+    }
+    let names: Vec<&str> = templates
+        .iter()
+        .map(|template| template.name.as_str())
+        .collect();
+    let index = match name {
+        Some(name) => names
+            .iter()
+            .position(|found| *found == name)
+            .context(format!(
+                "No template \"{}\" in the manifest, expected one of: {}",
+                name,
+                names.join(", ")
+            ))?,
+        None if yes => bail!(
+            "--template-name is needed with --yes, one of: {}",
+            names.join(", ")
+        ),
+        None => {
+            let items: Vec<String> = templates
+                .iter()
+                .map(|template| match &template.description {
+                    Some(description) => format!("{} - {}", template.name, description),
+                    None => template.name.clone(),
+                })
+                .collect();
+            select(t(Message::Template), &items, 0).context("Failed to prompt for the template")?
+        }
+    };
+    Ok(templates.swap_remove(index))
+}
+
+/// Writes a template archive to a temporary file, to generate from it
+///
+/// # Errors
+/// If the file cannot be created or written
+fn write_temp_template(archive: &[u8]) -> anyhow::Result<tempfile::NamedTempFile> {
+    let file = tempfile::NamedTempFile::new().context("Cannot create a temp file")?;
+    fs::write(file.path(), archive).context("Cannot write the template to a temp file")?;
+    Ok(file)
+}
+
+/// Writes the `--report` file of a generation
+///
+/// # Arguments
+/// * `path` - The report file
+/// * `project_name` - The project as given, not the temporary directory of `--archive`
+/// * `options` - The options of the generation
+/// * `report` - What the generation did, see [`generate_with_runner`]
+/// * `result` - The result of the generation
+///
+/// # Errors
+/// If the file cannot be written; when the generation failed too, the error
+/// is only printed so the generation error is the one reported
+fn write_report(
+    path: &Path,
+    project_name: &str,
+    options: &ScaffoldOptions,
+    report: &GenerationReport,
+    result: &Result<(), EspCreateError>,
+) -> anyhow::Result<()> {
+    let (status, error) = match result {
+        Ok(()) => ("completed", None),
+        Err(EspCreateError::Cancelled) => ("cancelled", None),
+        Err(EspCreateError::Other(e)) => ("failed", Some(format!("{:#}", e))),
+    };
+    let environment = Environment::new(&build_deps::check_build_deps(
+        options.build_target().ok().as_deref(),
+    ));
+    let mut record = ReportFile::new(options, report, status, error, environment);
+    record.project = project_name.to_string();
+    let written = record.write(path);
+    match written {
+        Err(e) if result.is_err() => {
+            eprintln!("{} {:#}", Symbol::Warning, e);
+            Ok(())
+        }
+        written => written,
+    }
+}
+
+/// Runs `esp-create-project-<name>` from the `PATH` when the first argument
+/// is not a built-in subcommand but names an external one
+///
+/// The external subcommand takes precedence over a project with the same
+/// name, `./<name>` still creates the project.
+///
+/// # Returns
+/// The exit code of the external subcommand, `None` if there is none to run
+///
+/// # Errors
+/// If the external subcommand cannot be started
+fn run_external_subcommand() -> anyhow::Result<Option<i32>> {
+    let mut args = env::args_os().skip(1);
+    let name = match args.next().and_then(|name| name.into_string().ok()) {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let cli = Cli::command();
+    let built_in = name == "help"
+        || cli.get_subcommands().any(|command| {
+            command.get_name() == name || command.get_all_aliases().any(|alias| alias == name)
+        });
+    if built_in {
+        return Ok(None);
+    }
+    match external::find(&name) {
+        Some(executable) => external::run(&executable, args).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Applies a starter to the project that contains the current directory
+///
+/// # Arguments
+/// * `id` - The id of the starter
+/// * `mode` - What to do with starter files that already exist
+///
+/// # Errors
+/// If the current directory is not inside a project, the starter doesn't run on
+/// the chip of the project, needs a starter the project doesn't have or cannot
+/// be applied
+fn add_starter(id: &str, mode: MergeMode) -> anyhow::Result<()> {
+    let starter = starters::find(id)?;
+    let current_dir = env::current_dir().context("Cannot read the current directory")?;
+    let root = project::find_project_root(&current_dir).context(
+        "The current directory is not inside an ESP-IDF project (no CMakeLists.txt including project.cmake found)",
+    )?;
+    let model = ProjectModel::load(&root)?;
+    let mut ids = model.starters.clone();
+    ids.push(id.to_string());
+    starters::resolve(&ids, model.target.as_deref())?;
+
+    let report = starters::apply_starter(&root, starter, mode, true)?;
+    for path in &report.written {
+        println!("{} {}", Symbol::Done, path.display());
+    }
+    for path in &report.skipped {
+        println!("{} {} (kept)", Symbol::Kept, path.display());
+    }
+    print_manual_steps(&report.manual_steps);
+    Ok(())
+}
+
+/// Lists the profiles of the config file
+///
+/// # Errors
+/// If the config file cannot be read
+fn list_profiles() -> anyhow::Result<()> {
+    let config = UserConfig::load()?;
+    let source = match &config.source {
+        Some(source) => source.display().to_string(),
+        None => {
+            let path = config::config_path()
+                .map(|path| format!(", add them to \"{}\"", path.display()))
+                .unwrap_or_default();
+            println!("No profile defined{}", path);
+            return Ok(());
+        }
+    };
+    if config.profile.is_empty() {
+        println!("No profile defined in \"{}\"", source);
+    }
+    for (name, profile) in &config.profile {
+        let extends = profile
+            .extends
+            .as_ref()
+            .map(|parent| format!(" (extends {})", parent))
+            .unwrap_or_default();
+        println!("{}{} - {}", name, extends, source);
+    }
+    Ok(())
+}
+
+/// Prints the development boards that can be passed to `--board`
+fn list_boards() {
+    println!(
+        "{:<20} {:<31} {:<8} {:<6} {:<6} {:<5} BUTTON",
+        "ID", "BOARD", "TARGET", "FLASH", "PSRAM", "LED"
+    );
+    let gpio = |gpio: Option<u8>| gpio.map_or("-".to_string(), |gpio| gpio.to_string());
+    for board in boards::BOARDS {
+        println!(
+            "{:<20} {:<31} {:<8} {:<6} {:<6} {:<5} {}",
+            board.id,
+            board.name,
+            board.target,
+            format!("{}MB", board.flash_size_mb),
+            if board.psram { "yes" } else { "no" },
+            gpio(board.led_gpio),
+            gpio(board.button_gpio)
+        );
+    }
+}
+
+/// Prints the starters: id, chips, oldest ESP-IDF, needed starters, managed
+/// components and description
+///
+/// # Arguments
+/// * `target` - Only the starters that run on this chip
+/// * `json` - Print the list as JSON
+///
+/// # Errors
+/// If the chip is unknown
+fn list_starters(target: Option<&str>, json: bool) -> anyhow::Result<()> {
+    if let Some(target) = target.filter(|target| !boards::TARGETS.contains(target)) {
+        bail!(
+            "Unknown chip \"{}\", expected one of: {}",
+            target,
+            boards::TARGETS.join(", ")
+        );
+    }
+    let rows = starters::compatibility(target);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    let none = |values: Vec<String>| {
+        if values.is_empty() {
+            "-".to_string()
+        } else {
+            values.join(", ")
+        }
+    };
+    println!(
+        "{:<10} {:<42} {:<8} {:<17} {:<28} DESCRIPTION",
+        "ID", "CHIPS", "ESP-IDF", "NEEDS", "COMPONENTS"
+    );
+    for row in &rows {
+        let components = row
+            .dependencies
+            .iter()
+            .map(|component| format!("{} {}", component.name, component.version))
+            .collect();
+        println!(
+            "{:<10} {:<42} {:<8} {:<17} {:<28} {}",
+            row.id,
+            row.chips(),
+            row.min_idf_version
+                .as_deref()
+                .map_or("any".to_string(), |version| format!("{}+", version)),
+            none(row.needs_text()),
+            none(components),
+            row.description
+        );
+    }
+    Ok(())
+}
+
+/// Sets the fields of the profile given by flags, flags win over the profile
+///
+/// # Arguments
+/// * `cli` - The command line
+/// * `profile` - The profile of `--profile`, empty without it
+///
+/// # Returns
+/// The fields set by flags, and the flag that set each one
+fn apply_flags(cli: &Cli, profile: &mut Profile) -> Vec<(&'static str, &'static str)> {
+    let mut flags = Vec::new();
+    if cli.board.is_some() {
+        profile.board = cli.board.clone();
+        profile.target = None;
+        flags.push(("board", "--board"));
+    }
+    if cli.target.is_some() {
+        profile.target = cli.target.clone();
+        profile.board = None;
+        flags.push(("target", "--target"));
+    }
+    if cli.kconfig_example {
+        profile.kconfig_example = Some(true);
+        flags.push(("kconfig_example", "--kconfig-example"));
+    }
+    if cli.scaffold_only_config {
+        profile.starters = Some(Vec::new());
+        flags.push(("starters", "--scaffold-only-config"));
+    }
+    if !cli.starters.is_empty() {
+        profile.starters = Some(cli.starters.clone());
+        flags.push(("starters", "--starter"));
+    }
+    if cli.template_ref.is_some() {
+        profile.template_ref = cli.template_ref.clone();
+        profile.template_url = None;
+        flags.push(("template_ref", "--template-ref"));
+    }
+    if cli.template.is_some() {
+        profile.template_url = cli.template.clone();
+        profile.template_ref = None;
+        flags.push(("template_url", "--template"));
+    }
+    if cli.clang_format {
+        let default = ClangFormatStyle::default();
+        profile.clang_format = Some(ClangFormatStyle {
+            indent_width: cli.indent_width.unwrap_or(default.indent_width),
+            column_limit: cli.column_limit.unwrap_or(default.column_limit),
+        });
+        flags.push(("clang_format", "--clang-format"));
+    }
+    if !cli.hygiene.is_empty() {
+        profile.hygiene = Some(cli.hygiene.clone());
+        flags.push(("hygiene", "--hygiene"));
+    }
+    if cli.tick_rate.is_some() || cli.main_task_stack.is_some() {
+        let default = RtosTuning::default();
+        profile.rtos = Some(RtosTuning {
+            tick_rate_hz: cli.tick_rate.unwrap_or(default.tick_rate_hz),
+            main_task_stack_size: cli.main_task_stack.unwrap_or(default.main_task_stack_size),
+        });
+        flags.push((
+            "rtos",
+            if cli.tick_rate.is_some() {
+                "--tick-rate"
+            } else {
+                "--main-task-stack"
+            },
+        ));
+    }
+    if let Some(flash_size_mb) = cli.flash_size {
+        profile.partitions = Some(PartitionLayout {
+            flash_size_mb,
+            scheme: cli.partition_scheme.unwrap_or_else(|| {
+                PartitionScheme::largest_fitting_apps(flash_size_mb, cli.app_size)
+            }),
+            app_size_kb: cli.app_size,
+        });
+        flags.push(("partitions", "--flash-size"));
+    }
+    if cli.component_metadata {
+        profile.component_metadata = Some(ComponentMetadata {
+            url: cli.component_url.clone(),
+            license: cli.component_license.clone(),
+            maintainers: cli.maintainer.clone(),
+            ..ComponentMetadata::default()
+        });
+        flags.push(("component_metadata", "--component-metadata"));
+    }
+    flags
+}
+
+/// Prints every effective setting and where it comes from, for
+/// `--explain-config`
+///
+/// # Arguments
+/// * `cli` - The command line, as it would generate the project
+///
+/// # Errors
+/// If the configuration, the profile or the options file is invalid
+fn explain_config(cli: &Cli) -> anyhow::Result<()> {
+    let config = UserConfig::load()?;
+    let mut settings = explain::explain_tool(
+        &config,
+        cli.lang.as_deref(),
+        cli.theme,
+        cli.template_ttl,
+        |name| env::var(name).ok(),
+    );
+    match &cli.options_file {
+        Some(path) => {
+            let options = ScaffoldOptions::from_json_file(path)?;
+            let options = serde_json::to_value(&options).context("Cannot read the options")?;
+            settings.extend(explain::explain_options_file(path, &options));
+        }
+        None => {
+            let profile_name = config.profile_name(cli.profile.as_deref());
+            let (mut profile, sources) = match profile_name {
+                Some(name) => {
+                    let source = match (&cli.profile, &config.source) {
+                        (None, Some(path)) => SettingSource::ConfigFile { path: path.clone() },
+                        _ => SettingSource::flag("--profile"),
+                    };
+                    settings.push(Setting::new("profile", name, source));
+                    config.resolve_profile_sources(name)?
+                }
+                None => (Profile::default(), BTreeMap::new()),
+            };
+            let flags = apply_flags(cli, &mut profile);
+            let last = if cli.no_remember {
+                LastChoices::default()
+            } else {
+                LastChoices::load()
+            }
+            .or(&config.defaults);
+            let last = (!last.is_empty()).then_some(last);
+            settings.extend(explain::explain_profile(&ProfileLayers {
+                profile: profile_name.map(|name| (name, &sources)),
+                last: last.as_ref(),
+                flags: &flags,
+                resolved: &profile,
+                yes: cli.yes,
+            }));
+            let enabled = |name: &str, set: bool, flag: &str| {
+                if set {
+                    Setting::new(name, "true", SettingSource::flag(flag))
+                } else {
+                    Setting::new(name, "false", SettingSource::Default)
+                }
+            };
+            settings.push(match &cli.main_file {
+                Some(path) => Setting::new(
+                    "main_file",
+                    path.display().to_string(),
+                    SettingSource::flag("--main-file"),
+                ),
+                None => Setting::new("main_file", "none", SettingSource::Default),
+            });
+            settings.push(enabled("app_class", cli.app_class, "--app-class"));
+            settings.push(enabled(
+                "scaffold_only_config",
+                cli.scaffold_only_config,
+                "--scaffold-only-config",
+            ));
+            settings.push(enabled(
+                "host_test_asan",
+                cli.host_test_asan,
+                "--host-test-asan",
+            ));
+            settings.push(enabled(
+                "bootloader_hooks",
+                cli.bootloader_hooks,
+                "--bootloader-hooks",
+            ));
+            settings.push(enabled("flash_script", cli.flash_script, "--flash-script"));
+            settings.push(match cli.ci {
+                Some(provider) => {
+                    Setting::new("ci", provider.to_string(), SettingSource::flag("--ci"))
+                }
+                None => Setting::new("ci", "none", SettingSource::Default),
+            });
+            settings.push(enabled("no_badges", cli.no_badges, "--no-badges"));
+            settings.push(enabled("no_gitignore", cli.no_gitignore, "--no-gitignore"));
+            settings.push(enabled("no_sdkconfig", cli.no_sdkconfig, "--no-sdkconfig"));
+            settings.push(enabled(
+                "relative_symlinks",
+                cli.relative_symlinks,
+                "--relative-symlinks",
+            ));
+            settings.push(enabled("factory_test", cli.factory_test, "--factory-test"));
+        }
+    }
+    settings.push(match &cli.idf_version {
+        Some(version) => Setting::new(
+            "idf_version",
+            version.to_string(),
+            SettingSource::flag("--idf-version"),
+        ),
+        None => match env::var("IDF_PATH") {
+            Ok(path) if !path.is_empty() => Setting::new(
+                "idf_version",
+                format!("detected from {}", path),
+                SettingSource::environment("IDF_PATH"),
+            ),
+            _ => Setting::new(
+                "idf_version",
+                "detected from idf.py",
+                SettingSource::Default,
+            ),
+        },
+    });
+    settings.push(Setting::new(
+        "compat_check",
+        cli.compat_check.to_string(),
+        if cli.compat_check == CompatCheck::default() {
+            SettingSource::Default
+        } else {
+            SettingSource::flag("--compat-check")
+        },
+    ));
+    settings.push(Setting::new(
+        "on_step_failure",
+        cli.on_step_failure.to_string(),
+        if cli.on_step_failure == FailurePolicy::default() {
+            SettingSource::Default
+        } else {
+            SettingSource::flag("--on-step-failure")
+        },
+    ));
+    settings.push(if cli.fetch_components {
+        Setting::new(
+            "fetch_components",
+            "true",
+            SettingSource::flag("--fetch-components"),
+        )
+    } else {
+        Setting::new("fetch_components", "false", SettingSource::Default)
+    });
+    settings.push(if cli.keep_going {
+        Setting::new("keep_going", "true", SettingSource::flag("--keep-going"))
+    } else {
+        Setting::new("keep_going", "false", SettingSource::Default)
+    });
+    settings.push(match cli.post_process {
+        Some(post_process) => Setting::new(
+            "post_process",
+            post_process.to_string(),
+            SettingSource::flag("--post-process"),
+        ),
+        None => Setting::new(
+            "post_process",
+            PostProcess::default().to_string(),
+            SettingSource::Default,
+        ),
+    });
+
+    if cli.json {
+        println!("{}", explain::to_json(&settings));
+    } else {
+        print!("{}", explain::to_text(&settings));
+    }
+    Ok(())
+}
+
+/// Prints the serial ports that look like ESP boards, and the other ports
+/// when there's none
+///
+/// # Errors
+/// If the ports cannot be enumerated
+fn probe_serial_ports() -> anyhow::Result<()> {
+    let ports = serial::list_ports()?;
+    let (boards, others): (Vec<_>, Vec<_>) = ports.iter().partition(|port| port.is_esp_board());
+    for board in &boards {
+        println!("{} {}  {}", Symbol::Port, board.name, board.description());
+    }
+    match boards.as_slice() {
+        [board] => println!("Flash with `idf.py -p {} flash monitor`", board.name),
+        [] => {
+            println!(
+                "{} No ESP board found, check the USB cable (some only charge) and the USB-serial driver",
+                Symbol::Warning
+            );
+            if !others.is_empty() {
+                println!("Other serial ports:");
+                for port in &others {
+                    println!("  {}  {}", port.name, port.description());
+                }
+            }
+        }
+        _ => println!("Pass one of them to `idf.py -p PORT flash monitor`"),
+    }
+    Ok(())
+}
+
+/// Prints the size of a template by top-level directory, see `--analyze-template`
+///
+/// # Arguments
+/// * `template` - The URL or path of the template, a directory is measured as it is
+/// * `max_size` - The largest template downloaded, the default if `None`
+///
+/// # Errors
+/// If the template cannot be downloaded or read
+fn analyze_template(template: &str, max_size: Option<u64>) -> anyhow::Result<()> {
+    let report = if Path::new(template).is_dir() {
+        SizeReport::of_directory(Path::new(template))?
+    } else {
+        let mut file = tempfile::tempfile().context("Cannot create a temp file")?;
+        status::start(Symbol::Download, "Downloading template");
+        let max_size = max_size.unwrap_or(download::DEFAULT_MAX_TEMPLATE_SIZE);
+        match download::download_template(template, &mut file, max_size, &CancellationToken::new())
+        {
+            Ok(_) => status::finish(Symbol::Done, "Template downloaded"),
+            Err(EspCreateError::Cancelled) => bail!("Cancelled"),
+            Err(EspCreateError::Other(e)) => {
+                status::clear();
+                return Err(e);
+            }
+        }
+        SizeReport::of_archive(&mut file)?
+    };
+    for line in report.lines() {
+        println!("{}", line);
+    }
+    if report.is_large() {
+        println!(
+            "{} Larger than {}, every project generated from it gets these files",
+            Symbol::Warning,
+            download::format_size(template_size::LARGE_TEMPLATE_SIZE)
+        );
+    }
+    Ok(())
+}
+
+/// Prints the ESP-IDF examples grouped by category
+///
+/// # Arguments
+/// * `offline` - Only use the cached or built-in listing
+fn list_examples(offline: bool) {
+    let (list, warning) = examples::list_examples(offline);
+    if let Some(warning) = warning {
+        eprintln!("{} {}", Symbol::Warning, warning);
+    }
+    for (category, examples) in list.by_category() {
+        println!("{}", category);
+        for example in examples {
+            println!("  {}", example);
+        }
+    }
+}
+
+/// Runs `flash` or `monitor` on the project
+///
+/// # Arguments
+/// * `action` - Flash or monitor
+/// * `path` - A directory inside the project, the current directory if `None`
+/// * `port` - The serial port, detected if `None`
+///
+/// # Errors
+/// If the environment, the port, the build or the action fails, see [`flash::run`]
+fn board(action: BoardAction, path: Option<PathBuf>, port: Option<String>) -> anyhow::Result<()> {
+    let start = match path {
+        Some(path) => path,
+        None => env::current_dir().context("Cannot read the current directory")?,
+    };
+    flash::run(action, &start, port)
+}
+
+/// Prints an overview of the project
+///
+/// # Arguments
+/// * `path` - A directory inside the project, the current directory if `None`
+/// * `json` - Whether to print the overview as JSON
+///
+/// # Errors
+/// If the project cannot be read, see [`ProjectModel::load`]
+fn info(path: Option<PathBuf>, json: bool) -> anyhow::Result<()> {
+    let start = match path {
+        Some(path) => path,
+        None => env::current_dir().context("Cannot read the current directory")?,
+    };
+    let model = ProjectModel::load(&start)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&model)?);
+        return Ok(());
+    }
+
+    let unset = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".into());
+    let list = |values: &[String]| {
+        if values.is_empty() {
+            "none".to_string()
+        } else {
+            values.join(", ")
+        }
+    };
+
+    println!(
+        "Project:      {} ({})",
+        unset(&model.name),
+        model.root.display()
+    );
+    println!("Version:      {}", unset(&model.version));
+    let language = match (model.language, &model.cpp_standard) {
+        (SourceLanguage::C, _) => "C".to_string(),
+        (SourceLanguage::Cpp, Some(standard)) => format!("C++{}", standard),
+        (SourceLanguage::Cpp, None) => "C++".to_string(),
+    };
+    println!("Language:     {}", language);
+    println!(
+        "Target:       {}",
+        model.target.as_deref().unwrap_or("not set (esp32)")
+    );
+    match model.flash_size_mb {
+        Some(flash_size_mb) => println!("Flash size:   {}MB", flash_size_mb),
+        None => println!("Flash size:   not set (2MB)"),
+    }
+    match &model.template {
+        Some(template) => {
+            match &template.git_ref {
+                Some(git_ref) => println!("Template:     {} ({})", template.url, git_ref),
+                None => println!("Template:     {}", template.url),
+            }
+            println!("SHA-256:      {}", unset(&template.sha256));
+        }
+        None => println!("Template:     not recorded"),
+    }
+    println!("Starters:     {}", list(&model.starters));
+    let dependencies: Vec<String> = model
+        .dependencies
+        .iter()
+        .map(|(name, version)| format!("{} {}", name, version))
+        .collect();
+    println!("Components:   {}", list(&dependencies));
+    println!("Integrations: {}", list(&model.integrations));
+    if model.partitions.is_empty() {
+        println!("Partitions:   built-in table");
+    } else {
+        println!("Partitions:");
+        for partition in &model.partitions {
+            println!(
+                "  {:<12} {:<5} {:<10} {:<10} {}",
+                partition.name, partition.kind, partition.subtype, partition.offset, partition.size
+            );
+        }
+        if let Some(flash_size_mb) = model.flash_size_mb {
+            if let Err(e) = partitions::check_fits(&model.partitions, flash_size_mb) {
+                eprintln!("{} Partition table: {:#}", Symbol::Warning, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Re-applies the generators to the project, asking before changing each file
+///
+/// # Arguments
+/// * `path` - A directory inside the project, the current directory if `None`
+/// * `only` - The generators to re-apply, all of them if empty
+/// * `yes` - Whether to apply every change without asking
+/// * `allow_dirty` - Whether to run even if the git tree has uncommitted changes
+///
+/// # Errors
+/// If the changes cannot be computed or written, see [`regen::plan_regen`]
+fn regen(
+    path: Option<PathBuf>,
+    only: &[RegenTarget],
+    yes: bool,
+    allow_dirty: bool,
+) -> anyhow::Result<()> {
+    let start = match path {
+        Some(path) => path,
+        None => env::current_dir().context("Cannot read the current directory")?,
+    };
+    let targets = if only.is_empty() {
+        RegenTarget::ALL
+    } else {
+        only
+    };
+    let plan = regen::plan_regen(&start, targets, allow_dirty)?;
+    if plan.changes.is_empty() {
+        println!("Nothing to regenerate, the project is up to date");
+        return Ok(());
+    }
+
+    for change in &plan.changes {
+        println!("{} {}", Symbol::Edit, change.path.display());
+        for line in change.diff() {
+            match line {
+                DiffLine::Removed(line) => println!("  - {}", line),
+                DiffLine::Added(line) => println!("  + {}", line),
+            }
+        }
+        let apply = yes
+            || confirm(&format!("Update {}?", change.path.display()), Some(true))
+                .context("Failed to prompt for the regenerated file")?;
+        if apply {
+            change.apply(&plan.root)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the foot-guns found in the project and offers their fixes, see
+/// [`project_check`]
+///
+/// # Arguments
+/// * `path` - A directory inside the project, the current directory if `None`
+/// * `fix` - Apply every fix without asking, otherwise they are offered when
+///   someone can answer
+///
+/// # Errors
+/// If the project cannot be checked or a fix written, and if errors remain
+/// after the fixes
+fn check(path: Option<PathBuf>, fix: bool) -> anyhow::Result<()> {
+    let start = match path {
+        Some(path) => path,
+        None => env::current_dir().context("Cannot read the current directory")?,
+    };
+    let report = project_check::check_project(&start)?;
+    if report.findings.is_empty() {
+        println!("{} No known foot-guns found", Symbol::Done);
+        return Ok(());
+    }
+
+    let interactive = console::user_attended();
+    let mut errors = 0;
+    for finding in &report.findings {
+        let symbol = match finding.severity {
+            Severity::Error => Symbol::Failed,
+            Severity::Warning => Symbol::Warning,
+        };
+        println!(
+            "{} {}[{}] {}: {}",
+            symbol,
+            finding.severity,
+            finding.id,
+            finding.path.display(),
+            finding.explanation
+        );
+        let is_error = usize::from(finding.severity == Severity::Error);
+        let change = match &finding.fix {
+            Some(change) => change,
+            None => {
+                errors += is_error;
+                continue;
+            }
+        };
+        for line in change.diff() {
+            match line {
+                DiffLine::Removed(line) => println!("  - {}", line),
+                DiffLine::Added(line) => println!("  + {}", line),
+            }
+        }
+        if !fix && !interactive {
+            errors += is_error;
+            continue;
+        }
+        if !change.is_current(&report.root) {
+            println!("  Not fixed, it builds on a fix that was skipped");
+            errors += is_error;
+            continue;
+        }
+        let apply = fix
+            || confirm(t(Message::ApplyFix), Some(true)).context("Failed to prompt for the fix")?;
+        if apply {
+            change.apply(&report.root)?;
+            println!("{} Fixed {}", Symbol::Edit, change.path.display());
+        } else {
+            errors += is_error;
+        }
+    }
+    if !fix && !interactive && report.findings.iter().any(|f| f.fix.is_some()) {
+        println!(
+            "{} Run `esp-create-project check --fix` to apply the fixes shown",
+            Symbol::Info
+        );
+    }
+    if errors > 0 {
+        bail!(
+            "{} {} found in \"{}\"",
+            errors,
+            if errors == 1 { "error" } else { "errors" },
+            report.root.display()
+        );
+    }
+    Ok(())
+}
+
+/// Renames the project and prints the changed lines
+///
+/// # Arguments
+/// * `new_name` - The new name of the project
+/// * `path` - A directory inside the project, the current directory if `None`
+/// * `options` - Whether to move the directory and to accept a dirty git tree
+///
+/// # Errors
+/// If the project cannot be renamed, see [`rename::rename_project`]
+fn rename(new_name: &str, path: Option<PathBuf>, options: RenameOptions) -> anyhow::Result<()> {
+    let start = match path {
+        Some(path) => path,
+        None => env::current_dir().context("Cannot read the current directory")?,
+    };
+    let report = rename::rename_project(&start, new_name, options)?;
+    for edit in &report.edits {
+        println!("{} {}", Symbol::Edit, edit.path.display());
+        for (before, after) in &edit.lines {
+            println!("  - {}", before);
+            println!("  + {}", after);
+        }
+    }
+    if report.edits.is_empty() {
+        println!(
+            "Nothing to rename, the project is already named \"{}\"",
+            new_name
+        );
+    } else {
+        println!(
+            "{} Project renamed in \"{}\"",
+            Symbol::Done,
+            report.root.display()
+        );
+    }
+    Ok(())
+}
+
+/// Packs the project into a template archive
+///
+/// # Arguments
+/// * `path` - A directory inside the project, the current directory if `None`
+/// * `out` - The zip archive to write
+///
+/// # Errors
+/// If the path is not inside a project or the archive cannot be written
+fn templatize(path: Option<PathBuf>, out: &Path) -> anyhow::Result<()> {
+    let start = match path {
+        Some(path) => path,
+        None => env::current_dir().context("Cannot read the current directory")?,
+    };
+    let report = templatize::templatize(&start, out)?;
+    for file in &report.placeholder_files {
+        println!(
+            "{} {}: project name replaced by {}",
+            Symbol::Edit,
+            file.display(),
+            templates::PROJECT_NAME_PLACEHOLDER
+        );
+    }
+    println!(
+        "{} {} files of \"{}\" packed in \"{}\", use it with --template {}",
+        Symbol::Done,
+        report.files,
+        report.root.display(),
+        out.display(),
+        out.display()
+    );
+    Ok(())
+}
+
+/// Prints the estimate of `--report-size`, as a warning when the app is
+/// expected to fill most of its partition
+fn print_size_hint(hint: &size_hint::SizeHint) {
+    let symbol = if hint.is_tight() {
+        Symbol::Warning
+    } else {
+        Symbol::Info
+    };
+    let lines = hint.lines();
+    say!("{} {}", symbol, lines[0]);
+    for line in &lines[1..] {
+        say!("  - {}", line);
+    }
+}
+
+/// Prints the changes left to the user
+fn print_manual_steps(steps: &[String]) {
+    if steps.is_empty() {
+        return;
+    }
+    say!("{} Left to do:", Symbol::Todo);
+    for step in steps {
+        say!("  - {}", step);
+    }
+}
+
+/// Updates the binary to the latest release, verifying its checksum
+///
+/// # Arguments
+/// * `check` - Whether to only report the latest release without installing it
+/// * `offline` - Whether network access is disabled
+///
+/// # Errors
+/// If the release cannot be fetched, verified or installed
+fn self_update(check: bool, offline: bool) -> anyhow::Result<()> {
+    if offline {
+        bail!("self-update needs network access, remove --offline");
+    }
+
+    let release = self_update::latest_release()?;
+    if !release.is_newer_than(self_update::CURRENT_VERSION) {
+        println!(
+            "{} esp-create-project {} is up to date",
+            Symbol::Done,
+            self_update::CURRENT_VERSION
+        );
+        return Ok(());
+    }
+    if check {
+        println!(
+            "{} is available (current: {}), run `esp-create-project self-update` to install it",
+            release.tag_name,
+            self_update::CURRENT_VERSION
+        );
+        return Ok(());
+    }
+
+    let asset_name = self_update::platform_asset_name()
+        .context("No release binary is published for this platform, build it from source")?;
+    status::start(
+        Symbol::Download,
+        &format!("Downloading {}", release.tag_name),
+    );
+    let result = self_update::download_verified(&release, asset_name)
+        .and_then(|binary| self_update::replace_current_exe(&binary));
+    if let Err(error) = result {
+        status::clear();
+        return Err(error);
+    }
+    status::finish(Symbol::Done, &format!("Updated to {}", release.tag_name));
+    Ok(())
+}
+
+/// Warns if the pinned template ref is older than the threshold
+///
+/// It is only a hint: nothing is checked without a pinned ref or offline, and
+/// any error (e.g. GitHub unreachable) is ignored.
+///
+/// # Arguments
+/// * `options` - The options of the project
+/// * `threshold` - The age above which the ref is considered old
+/// * `offline` - Whether network access is disabled
+fn warn_old_template_ref(options: &ScaffoldOptions, threshold: Duration, offline: bool) {
+    let git_ref = match &options.template_ref {
+        Some(git_ref) if !offline => git_ref,
+        _ => return,
+    };
+    if let Some(warning) = template_ref::commit_date(git_ref)
+        .ok()
+        .and_then(|date| template_ref::age_warning(git_ref, date, SystemTime::now(), threshold))
+    {
+        eprintln!("{} {}", Symbol::Warning, warning);
+    }
+}
+
+/// Checks the tools needed to build the project and reports the missing ones
+///
+/// # Arguments
+/// * `target` - The chip of `--board` or `--target`, see
+///   [`build_deps::check_build_deps`]
+/// * `strict` - Whether a missing tool is an error instead of a warning
+///
+/// # Errors
+/// If `strict` is set and a tool is missing
+fn verify_build_deps(target: Option<&str>, strict: bool) -> anyhow::Result<()> {
+    let checks = build_deps::check_build_deps(target);
+    print_idf_env();
+    print_build_deps(&checks);
+
+    let missing = checks.iter().filter(|check| !check.found()).count();
+    if missing > 0 {
+        if strict {
+            bail!("{} build dependencies are missing", missing);
+        }
+        say!(
+            "You can create the project, but it won't build until the missing tools are installed"
+        );
+    }
+    Ok(())
+}
+
+/// Prints whether the ESP-IDF environment is exported, see [`IdfEnv`]; a
+/// missing IDF is reported with the build tools
+fn print_idf_env() {
+    let idf_env = IdfEnv::detect();
+    let symbol = match idf_env {
+        IdfEnv::Ready { .. } => Symbol::Done,
+        IdfEnv::NeedsExport { .. } => Symbol::Info,
+        IdfEnv::NotFound => return,
+    };
+    println!("{} {}", symbol, idf_env);
+}
+
+/// Runs the checks of [`doctor`] and prints them as a checklist, with how to
+/// fix the failed ones
+///
+/// # Arguments
+/// * `template` - The template to check, the official one if `None`
+/// * `strict` - Fails if a check fails
+/// * `offline` - Skips the network check
+///
+/// # Errors
+/// With `strict`, if a check fails
+fn run_doctor(template: Option<&str>, strict: bool, offline: bool) -> anyhow::Result<()> {
+    let checks = doctor::run(template.unwrap_or(templates::TEMPLATE_FILE), offline);
+    for check in &checks {
+        let symbol = match check.status {
+            CheckStatus::Pass => Symbol::Done,
+            CheckStatus::Fail => Symbol::Failed,
+            CheckStatus::Skipped => Symbol::Info,
+        };
+        println!("{} {}: {}", symbol, check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            println!("    {}", hint);
+        }
+    }
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    if failed == 0 {
+        println!(
+            "{} Everything needed to scaffold and flash a project is there",
+            Symbol::Done
+        );
+    } else if strict {
+        bail!("{} of {} checks failed", failed, checks.len());
+    } else {
+        println!(
+            "{} {} of {} checks failed, see the hints above",
+            Symbol::Warning,
+            failed,
+            checks.len()
+        );
+    }
+    Ok(())
+}
+
+/// Generates the projects of a batch manifest, see [`BatchManifest`]
+///
+/// Every answer the manifest doesn't set has its default, the last choices
+/// are not used. Each template is downloaded once, then the projects are
+/// generated from it, `jobs` at the same time; a project that fails doesn't
+/// stop the others. One line is printed to stderr per project, and the
+/// table of the results (or the JSON) to stdout at the end.
+///
+/// # Arguments
+/// * `manifest` - The manifest file
+/// * `jobs` - Projects generated at the same time, one if `None`
+/// * `json` - Prints the results as JSON, see [`BatchReport`]
+/// * `allow_root` - Whether `--allow-root` was passed
+/// * `offline` - Whether `--offline` was passed, nothing can be generated
+///
+/// # Errors
+/// If the manifest is invalid, or a project failed
+fn batch(
+    manifest: &Path,
+    jobs: Option<NonZeroUsize>,
+    json: bool,
+    allow_root: bool,
+    offline: bool,
+) -> anyhow::Result<()> {
+    if offline {
+        bail!(
+            "The template is downloaded from GitHub, projects cannot be generated with --offline"
+        );
+    }
+    check_not_elevated(allow_root)?;
+    let config = UserConfig::load()?;
+    let projects = BatchManifest::read(manifest)?.resolve(&config)?;
+    status::set_quiet();
+
+    let token = CancellationToken::new();
+    {
+        let token = token.clone();
+        ctrlc::set_handler(move || token.cancel()).context("Cannot set the Ctrl+C handler")?;
+    }
+
+    let idf_path = env::var_os("IDF_PATH").map(PathBuf::from);
+    let planned = projects
+        .into_iter()
+        .map(|project| (project.name.clone(), project.options(idf_path.as_deref())))
+        .collect();
+    let report = generate_batch(planned, jobs, &token, |event| match event {
+        BatchEvent::Downloading(url) => eprintln!("{} Downloading {}", Symbol::Download, url),
+        BatchEvent::Done {
+            result,
+            done,
+            total,
+        } => match (&result.status, &result.error) {
+            (BatchStatus::Failed, Some(error)) => eprintln!(
+                "{} {} failed ({}/{}): {}",
+                Symbol::Failed,
+                result.name,
+                done,
+                total,
+                error
+            ),
+            (BatchStatus::Cancelled, _) => {}
+            _ => eprintln!(
+                "{} {} generated ({}/{})",
+                Symbol::Done,
+                result.name,
+                done,
+                total
+            ),
+        },
+    })?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for line in report.table() {
+            println!("{}", line);
+        }
+    }
+    if token.is_cancelled() {
+        eprintln!("\nCancelled, the projects not generated were removed");
+        process::exit(130);
+    }
+    if report.failed > 0 {
+        bail!(
+            "{} of {} projects failed",
+            report.failed,
+            report.projects.len()
+        );
+    }
+    Ok(())
+}
+
+/// Prints the build tools found and the ones missing, one per line
+fn print_build_deps(checks: &[build_deps::BuildDepCheck]) {
+    for check in checks {
+        match &check.version {
+            Some(version) => println!("{} {}: {}", Symbol::Done, check.name, version),
+            None => println!(
+                "{} {} not found, {}",
+                Symbol::Failed,
+                check.name,
+                check.hint
+            ),
+        }
+    }
+}
 
-use anyhow::Context;
-use std::env;
-use std::fs;
-use std::fs::File;
-use std::io;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+/// Runs the onboarding, see [`onboarding`]: reports the ESP-IDF and build
+/// tools found, asks for the defaults of new projects and the update check,
+/// and writes them to the config file
+///
+/// # Errors
+/// If the platform has no config directory, a prompt fails or the config
+/// cannot be read or written
+fn onboard() -> anyhow::Result<()> {
+    let (config_file, state_dir) = match (config::config_path(), config::state_dir()) {
+        (Some(config_file), Some(state_dir)) => (config_file, state_dir),
+        _ => bail!("This platform has no config directory, the answers cannot be saved"),
+    };
+    println!(
+        "{} Welcome to esp-create-project! A few questions first, the answers are saved to \"{}\"",
+        Symbol::Info,
+        config_file.display()
+    );
+    print_idf_env();
+    print_build_deps(&build_deps::check_build_deps(None));
 
-use dialoguer::theme::ColorfulTheme;
-use dialoguer::{Confirm, Select};
-use zip::ZipArchive;
+    let config = UserConfig::from_file(&config_file)?;
+    let current = onboarding::current_defaults(&config);
+    let set_defaults = confirm(t(Message::OnboardingDefaults), Some(true))
+        .context("Failed to prompt for the defaults")?;
+    let defaults = if set_defaults {
+        let language =
+            prompt_programming_language(current.language.unwrap_or(ProgrammingLanguage::C))?;
+        let (board, target) = prompt_board(current.board.as_deref(), current.target.as_deref())?;
+        let use_git = prompt_use_git(current.use_git.unwrap_or(true))?;
+        Some(onboarding::Defaults {
+            language: Some(language),
+            board,
+            target,
+            use_git: Some(use_git),
+        })
+    } else {
+        None
+    };
+    let update_check = confirm(t(Message::OnboardingUpdateCheck), Some(config.update_check))
+        .context("Failed to prompt for the update check")?;
 
-/// Prompts if the selected directory should be deleted
+    let answers = onboarding::Answers {
+        defaults,
+        update_check,
+    };
+    UserConfig::edit_file(&config_file, |document| {
+        onboarding::apply(document, &answers)
+    })?;
+    onboarding::mark_done(&state_dir)?;
+    println!(
+        "{} Saved to \"{}\", run with --onboard to change the answers",
+        Symbol::Done,
+        config_file.display()
+    );
+    Ok(())
+}
+
+/// Checks the ESP-IDF version against the features of the project
+///
+/// The version of `--idf-version` is used, or the one of `idf.py`. When
+/// neither is known the project records the version it assumes instead, see
+/// [`ScaffoldOptions::assumed_idf_version`].
 ///
 /// # Arguments
-/// * `path` - The path to the directory to be deleted
+/// * `options` - The options of the project
+/// * `version` - The version of `--idf-version`
+/// * `mode` - What an older version does, `--compat-check`
 ///
-/// # Returns
-/// `true` if the directory should be deleted, `false` otherwise
+/// # Errors
+/// If the version is older than a feature needs and `mode` is
+/// [`CompatCheck::Error`]
+fn check_idf_version(
+    options: &mut ScaffoldOptions,
+    version: Option<IdfVersion>,
+    mode: CompatCheck,
+) -> anyhow::Result<()> {
+    if mode == CompatCheck::Off {
+        return Ok(());
+    }
+    let requirements = options.idf_requirements()?;
+    let minimum = match idf_version::minimum(&requirements) {
+        Some(minimum) => minimum.clone(),
+        None => return Ok(()),
+    };
+    let installed =
+        version.or_else(|| build_deps::probe_idf().and_then(|text| IdfVersion::parse(&text)));
+    match installed {
+        Some(installed) if mode == CompatCheck::Warn => {
+            for unmet in idf_version::unmet(&requirements, &installed) {
+                status::warn(
+                    Symbol::Warning,
+                    &format!(
+                        "ESP-IDF {} is too old: {}, the project may not build",
+                        installed, unmet
+                    ),
+                );
+            }
+            Ok(())
+        }
+        Some(installed) => idf_version::check(&requirements, &installed),
+        None => {
+            say!(
+                "{} No ESP-IDF found, the project assumes ESP-IDF >= {}: {}",
+                Symbol::Info,
+                minimum,
+                idf_version::describe(&requirements)
+            );
+            options.assumed_idf_version = Some(minimum);
+            Ok(())
+        }
+    }
+}
+
+/// Runs the CMake configure step in the generated project
+///
+/// # Arguments
+/// * `options` - The options the project was generated with
 ///
 /// # Errors
-/// If the user cancels the operation
-fn prompt_directory_delete(path: &Path) -> anyhow::Result<bool> {
-    if Confirm::new()
-        .with_prompt("Directory not empty, delete?")
-        .interact()
-        .context("Failed to prompt for directory deletion")?
-    {
-        if let Err(e) = fs::remove_dir_all(&path) {
-            eprintln!("Cannot delete directory contents, error: {}", e);
-            return Ok(false);
+/// If `idf.py` is missing or the configure step fails
+fn verify_configure(options: &ScaffoldOptions) -> anyhow::Result<()> {
+    status::start(Symbol::Configure, "Configuring the project");
+    for project in project_dirs(options)? {
+        if let Err(error) = build_deps::verify_configure(&project) {
+            status::clear();
+            return Err(error.context(format!(
+                "The project was generated in \"{}\" but its CMake configuration failed",
+                project.display()
+            )));
         }
-        return Ok(true);
     }
-    Ok(false)
+    status::finish(Symbol::Done, "Project configured");
+    Ok(())
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum ProgrammingLanguage {
-    Unknown,
-    C,
-    Cpp11,
-    Cpp14,
-    Cpp17,
+/// The ESP-IDF projects generated: the project, or the apps of a workspace
+///
+/// # Errors
+/// If the project name is invalid
+fn project_dirs(options: &ScaffoldOptions) -> anyhow::Result<Vec<PathBuf>> {
+    let names = ProjectNames::new(&options.project_name)?;
+    Ok(if options.workspace_apps.is_empty() {
+        vec![names.directory]
+    } else {
+        options
+            .workspace_apps
+            .iter()
+            .map(|app| names.directory.join(app))
+            .collect()
+    })
 }
 
-impl From<usize> for ProgrammingLanguage {
-    fn from(lang: usize) -> Self {
-        match lang {
-            0 => ProgrammingLanguage::C,
-            1 => ProgrammingLanguage::Cpp11,
-            2 => ProgrammingLanguage::Cpp14,
-            3 => ProgrammingLanguage::Cpp17,
-            _ => ProgrammingLanguage::Unknown,
+/// Downloads the managed components of the generated projects and offers to
+/// commit their `dependencies.lock`, see `--fetch-components`
+///
+/// Nothing here fails the generation: without ESP-IDF, or when the
+/// component manager fails, fetching is left as a manual step.
+///
+/// # Arguments
+/// * `options` - The options of the generated project
+/// * `git_initialized` - Whether the project has a git repo to commit to
+/// * `interactive` - Whether the user can be asked to commit
+///
+/// # Returns
+/// The manual steps left to the user
+///
+/// # Errors
+/// If the project name is invalid or the prompt is cancelled
+fn fetch_components(
+    options: &ScaffoldOptions,
+    git_initialized: bool,
+    interactive: bool,
+) -> anyhow::Result<Vec<String>> {
+    const FETCH_STEP: &str = "Run `idf.py reconfigure` to fetch the managed components";
+    let idf = match build_deps::idf_py() {
+        Ok(idf) => idf,
+        Err(e) => {
+            eprintln!(
+                "{} Managed components not fetched, ESP-IDF is not available: {:#}",
+                Symbol::Warning,
+                e
+            );
+            return Ok(vec![FETCH_STEP.to_string()]);
+        }
+    };
+    let root = ProjectNames::new(&options.project_name)?.directory;
+    let mut manual_steps = Vec::new();
+    let mut lock_files = Vec::new();
+    for project in project_dirs(options)? {
+        status::start(Symbol::Download, "Fetching the managed components");
+        match build_deps::fetch_components(&idf, &project) {
+            Ok(fetched) => {
+                status::finish(
+                    Symbol::Done,
+                    &format!(
+                        "{} managed components fetched to {}",
+                        fetched.components.len(),
+                        project.join(build_deps::MANAGED_COMPONENTS_DIR).display()
+                    ),
+                );
+                if fetched.lock_file {
+                    let lock_file = project.join(build_deps::DEPENDENCIES_LOCK_FILE);
+                    lock_files.push(
+                        lock_file
+                            .strip_prefix(&root)
+                            .unwrap_or(&lock_file)
+                            .to_path_buf(),
+                    );
+                }
+            }
+            Err(e) => {
+                status::clear();
+                eprintln!(
+                    "{} Managed components of \"{}\" not fetched: {:#}",
+                    Symbol::Warning,
+                    project.display(),
+                    e
+                );
+                if !manual_steps.iter().any(|step| step == FETCH_STEP) {
+                    manual_steps.push(FETCH_STEP.to_string());
+                }
+            }
         }
     }
+
+    if lock_files.is_empty() || !git_initialized {
+        return Ok(manual_steps);
+    }
+    let commit = interactive
+        && confirm(t(Message::CommitLockFile), Some(true))
+            .context("Failed to prompt for the commit of dependencies.lock")?;
+    let committed = commit
+        && match commit_files(&root, &lock_files, "Lock the managed components") {
+            Ok(()) => {
+                say!("{} dependencies.lock committed", Symbol::Git);
+                true
+            }
+            Err(e) => {
+                eprintln!("{} {:#}", Symbol::Warning, e);
+                false
+            }
+        };
+    if !committed {
+        manual_steps.push(
+            "Commit dependencies.lock so every build resolves the same component versions".into(),
+        );
+    }
+    Ok(manual_steps)
 }
 
-fn main() -> anyhow::Result<()> {
-    // Get selected directory
-    let project_name = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "esp-new-project".into());
+/// Commits files to the git repo of a project, and only them
+///
+/// # Arguments
+/// * `root` - The root of the git repo
+/// * `files` - The files, relative to `root`
+/// * `message` - The commit message
+///
+/// # Errors
+/// If git cannot be run or fails, e.g. without a user identity
+fn commit_files(root: &Path, files: &[PathBuf], message: &str) -> anyhow::Result<()> {
+    let run = |args: &[&str]| -> anyhow::Result<()> {
+        let output = platform::command("git")
+            .arg("-C")
+            .arg(root)
+            .args(args)
+            .arg("--")
+            .args(files)
+            .output()
+            .context("git cannot be started, is it installed and in the PATH?")?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    };
+    run(&["add"])?;
+    run(&["commit", "-m", message])
+}
 
-    let dir = Path::new(&project_name);
-    if dir.exists() && dir.read_dir().unwrap().next().is_some() && !prompt_directory_delete(dir)? {
-        return Ok(());
+/// Prompts the user for every project option not set in the profile
+///
+/// # Arguments
+/// * `project_name` - The name of the project
+/// * `profile` - The answers already known, from the profile and the flags
+/// * `app_class` - Whether to use the application class layout, `None` to
+///   ask when the language is C++
+/// * `last` - The answers of the last run, the defaults of the prompts
+/// * `yes` - Whether to use the defaults instead of asking
+/// * `no_sdkconfig` - Whether `sdkconfig.defaults` is not written, the
+///   prompts that only feed it are then skipped
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_options(
+    project_name: String,
+    mut profile: Profile,
+    app_class: Option<bool>,
+    last: &LastChoices,
+    yes: bool,
+    no_sdkconfig: bool,
+) -> anyhow::Result<ScaffoldOptions> {
+    if yes {
+        let mut options = ScaffoldOptions::from_profile(project_name, profile, no_sdkconfig);
+        options.app_class = app_class.unwrap_or(false);
+        return Ok(options);
     }
 
-    let language_selection = prompt_programming_language()?;
+    let language = match profile.language {
+        Some(language) => language,
+        None => prompt_programming_language(last.language.unwrap_or(ProgrammingLanguage::C))?,
+    };
+    profile.language = Some(language);
+    let app_class = match app_class {
+        Some(app_class) => app_class,
+        None if language == ProgrammingLanguage::C => false,
+        None => prompt_app_class()?,
+    };
+    // Asked even without sdkconfig.defaults: the chip also decides the
+    // starters, the IDF target of the build and the manual steps
+    if profile.board.is_none() && profile.target.is_none() {
+        (profile.board, profile.target) =
+            prompt_board(last.board.as_deref(), last.target.as_deref())?;
+    }
+    if profile.use_git.is_none() {
+        profile.use_git = Some(prompt_use_git(last.use_git.unwrap_or(true))?);
+    }
+    if profile.starters.is_none() {
+        let target = match (&profile.board, &profile.target) {
+            (Some(board), _) => boards::find(board).ok().map(|board| board.target),
+            (None, target) => target.as_deref(),
+        };
+        profile.starters = Some(prompt_starters(target)?);
+    }
 
-    let use_git = prompt_use_git()?;
+    let mut hygiene = profile.hygiene.take().unwrap_or_default();
+    if profile.clang_format.is_some() && !hygiene.contains(&HygieneFile::ClangFormat) {
+        hygiene.push(HygieneFile::ClangFormat);
+    }
+    let hygiene = prompt_hygiene(&hygiene, profile.clang_format.unwrap_or_default())?;
+    profile.clang_format = match profile.clang_format {
+        _ if !hygiene.contains(&HygieneFile::ClangFormat) => None,
+        Some(style) => Some(style),
+        None => Some(prompt_clang_format_style()?),
+    };
+    profile.hygiene = Some(hygiene);
 
-    if !project_name.is_empty() && !Path::new(project_name.as_str()).exists() {
-        fs::create_dir_all(dir)
-            .context(format!("Failed to create directory \"{}\"", &project_name))?;
+    let advanced = ((!no_sdkconfig
+        && (profile.assertions.is_none()
+            || profile.heap_debugging.is_none()
+            || profile.rtos.is_none()
+            || profile.partitions.is_none()
+            || profile.security.is_none()))
+        || profile.component_metadata.is_none())
+        && prompt_advanced_config()?;
+    // These only go to sdkconfig.defaults
+    if advanced && !no_sdkconfig {
+        if profile.assertions.is_none() {
+            profile.assertions = Some(prompt_assertion_level()?);
+        }
+        if profile.heap_debugging.is_none() {
+            profile.heap_debugging = Some(prompt_heap_debugging()?);
+        }
+        if profile.rtos.is_none() {
+            profile.rtos = Some(prompt_rtos_tuning()?);
+        }
+        if profile.partitions.is_none() {
+            let flash_size_mb = profile
+                .board
+                .as_deref()
+                .map(boards::find)
+                .transpose()?
+                .map(|board| board.flash_size_mb);
+            profile.partitions = prompt_partitions(flash_size_mb)?;
+        }
+        if profile.security.is_none() {
+            profile.security = Some(prompt_security_features()?);
+        }
+    }
+    if advanced && profile.component_metadata.is_none() {
+        profile.component_metadata = prompt_component_metadata()?;
     }
 
-    // Create a temp file to download the template
-    let mut tmp_file = tempfile::tempfile().unwrap();
+    let mut options = ScaffoldOptions::from_profile(project_name, profile, no_sdkconfig);
+    options.app_class = app_class;
+    Ok(options)
+}
 
-    // Download the template
-    print!("🌐 Downloading template");
-    download_template(&mut tmp_file)?;
-    println!("\r✔ Template downloaded       ");
+/// Prompts the user for the programming language to use
+///
+/// # Arguments
+/// * `default` - The language selected by default
+///
+/// # Returns
+/// The programming language selected by the user
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_programming_language(
+    default: ProgrammingLanguage,
+) -> anyhow::Result<ProgrammingLanguage> {
+    const LANGUAGES: &[&str] = &["C", "C++ 11", "C++ 14", "C++ 17"];
+    let default = (0..LANGUAGES.len())
+        .find(|&i| ProgrammingLanguage::from(i) == default)
+        .unwrap_or(0);
+    let selected_language = select(
+        format!(
+            "{} {}",
+            Symbol::Language,
+            i18n::with_default(Message::ProgrammingLanguage, LANGUAGES[default])
+        )
+        .trim_start(),
+        LANGUAGES,
+        default,
+    )
+    .context("Failed to prompt for programming language")?;
 
-    // Unzip the template
-    print!("🗄 Unziping file");
-    io::stdout().flush().unwrap();
-    let mut zip = zip::ZipArchive::new(tmp_file).unwrap();
-    println!("\r✔ File unzipped");
+    Ok(ProgrammingLanguage::from(selected_language))
+}
 
-    let prefix = PathBuf::new().join("esp-idf-template-master/");
+/// Prompts the user for the development board, then for the chip if the
+/// board is custom
+///
+/// # Arguments
+/// * `default_board` - The board selected by default, custom if `None`
+/// * `default_target` - The chip selected by default, `esp32` if `None`
+///
+/// # Returns
+/// The board id, or the chip of a custom board
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_board(
+    default_board: Option<&str>,
+    default_target: Option<&str>,
+) -> anyhow::Result<(Option<String>, Option<String>)> {
+    let mut items: Vec<String> = boards::BOARDS
+        .iter()
+        .map(|board| board.name.to_string())
+        .collect();
+    items.push(t(Message::CustomBoard).into());
+    let default = default_board
+        .and_then(|id| boards::BOARDS.iter().position(|board| board.id == id))
+        .unwrap_or(boards::BOARDS.len());
+    let selected = select(
+        &i18n::with_default(Message::Board, &items[default]),
+        &items,
+        default,
+    )
+    .context("Failed to prompt for the board")?;
+    if let Some(board) = boards::BOARDS.get(selected) {
+        return Ok((Some(board.id.to_string()), None));
+    }
 
-    // Write the zip contents to the directory
-    print!("📁 Writing files");
-    extract_zip(&project_name, &mut zip, &prefix)?;
+    let default = default_target
+        .and_then(|target| boards::TARGETS.iter().position(|&t| t == target))
+        .unwrap_or(0);
+    let selected = select(
+        &i18n::with_default(Message::Chip, boards::TARGETS[default]),
+        boards::TARGETS,
+        default,
+    )
+    .context("Failed to prompt for the chip")?;
+    Ok((None, Some(boards::TARGETS[selected].to_string())))
+}
 
-    replace_main_file(&project_name, language_selection)?;
+/// Prompts the user to initialize a git repository on the new project
+///
+/// Not asked when git is not in the `PATH`, a warning is shown instead.
+///
+/// # Arguments
+/// * `default` - The answer selected by default
+///
+/// # Returns
+/// `true` if the user wants to initialize a git repository, `false` otherwise
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_use_git(default: bool) -> anyhow::Result<bool> {
+    if platform::find_executable("git").is_none() {
+        eprintln!(
+            "{} git not found in the PATH, the project won't be a git repo",
+            Symbol::Warning
+        );
+        return Ok(false);
+    }
+    confirm(t(Message::InitGit), Some(default)).context("Failed to prompt for git initialization")
+}
 
-    let project_language = match language_selection {
-        ProgrammingLanguage::C => "",
-        ProgrammingLanguage::Cpp11 => "set(CMAKE_CXX_STANDARD 11)",
-        ProgrammingLanguage::Cpp14 => "set(CMAKE_CXX_STANDARD 14)",
-        ProgrammingLanguage::Cpp17 => "set(CMAKE_CXX_STANDARD 17)",
-        _ => {
-            eprintln!("Invalid option");
-            return Ok(());
+/// Prompts the user for the starters to add, none by default
+///
+/// Only the starters that run on the chip are listed, and the prompt is shown
+/// again while a selected starter needs one that isn't selected.
+///
+/// # Arguments
+/// * `target` - The chip of the project, `None` if not known yet
+///
+/// # Returns
+/// The ids of the selected starters
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_starters(target: Option<&str>) -> anyhow::Result<Vec<String>> {
+    // The rows of `esp-create-project starters --target`
+    let available = starters::compatibility(target);
+    let items: Vec<String> = available.iter().map(|starter| starter.label()).collect();
+    let mut defaults = vec![false; items.len()];
+    loop {
+        let selected = multi_select(t(Message::Starters), &items, &defaults)
+            .context("Failed to prompt for starters")?;
+        let ids: Vec<String> = selected
+            .iter()
+            .map(|&i| available[i].id.to_string())
+            .collect();
+        match starters::resolve(&ids, target) {
+            Ok(_) => return Ok(ids),
+            Err(e) => {
+                eprintln!("{} {:#}", Symbol::Warning, e);
+                defaults = (0..items.len()).map(|i| selected.contains(&i)).collect();
+            }
         }
-    };
-    set_cmake_options(&project_name, project_language, project_name.as_str())?;
-
-    println!("\r✔ Files written  ");
-
-    if use_git {
-        print!("⚙️Initializing git repo");
-        std::io::stdout().flush().unwrap();
-        initialize_git_repo(&project_name)?;
-        println!("\r✔ Git repo initialized  ");
     }
-
-    println!("😁 Have fun!");
-    Ok(())
 }
 
-fn download_template(tmp_file: &mut File) -> anyhow::Result<()> {
-    io::stdout().flush().unwrap();
-    let mut res = ureq::get(templates::TEMPLATE_FILE)
-        .call()
-        .context("Cannot download the template")?
-        .into_reader();
-    io::copy(&mut res, tmp_file).context("Cannot copy the template to temp file")?;
-    Ok(())
+/// Finds the default port of the flash script: the ESP board connected now
+///
+/// # Returns
+/// The port, `None` with a warning if there isn't exactly one ESP board, the
+/// script then lets `idf.py` find the board
+fn detect_flash_port() -> Option<String> {
+    match serial::detect_port() {
+        Ok(port) => {
+            status::finish(
+                Symbol::Done,
+                &format!("Flash script set to use {} by default", port),
+            );
+            Some(port)
+        }
+        // Its error tells about --port, of the flash command
+        Err(_) => {
+            status::warn(
+                Symbol::Warning,
+                "The flash script has no default port, there isn't exactly one ESP board \
+                 connected (--flash-port sets one)",
+            );
+            None
+        }
+    }
 }
 
-/// Intializes the git repository in the selected directory
+/// Prompts the user for what to do about a failed optional step, its error
+/// was printed by the step
 ///
 /// # Arguments
-/// * `directory` - The directory to initialize the git repository in
-/// * `use_git` - Whether to initialize the git repository
-fn initialize_git_repo(directory: &str) -> anyhow::Result<()> {
-    Command::new("git")
-        .args(&["init", directory])
-        .output()
-        .context("Failed to init git repo")?;
-    Ok(())
+/// * `stage` - The name of the step, e.g. `git init`
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_recovery(stage: &str, _error: &anyhow::Error) -> anyhow::Result<Recovery> {
+    status::clear();
+    let items = [
+        t(Message::RetryStep),
+        t(Message::SkipStep),
+        t(Message::AbortGeneration),
+    ];
+    let selected = select(&i18n::format(Message::StepFailed, &[stage]), &items, 1)
+        .context(format!("Failed to prompt for the failed step {}", stage))?;
+    Ok(Recovery::ALL[selected])
 }
 
-/// Prompts the user for the programming language to use
+/// Prompts the user whether a template without the layout of an ESP-IDF
+/// project is copied as it is, see [`PostProcess::Auto`]
+///
+/// # Arguments
+/// * `issues` - Why the template doesn't have the expected layout
 ///
 /// # Returns
-/// The programming language selected by the user
+/// `true` to copy it as it is, `false` to post-process it anyway
 ///
 /// # Errors
 /// If the user cancels the operation
-fn prompt_programming_language() -> anyhow::Result<ProgrammingLanguage> {
-    let selected_language = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("💻 Programming language? (default: C)")
-        .item("C")
-        .item("C++ 11")
-        .item("C++ 14")
-        .item("C++ 17")
-        .default(0)
-        .interact()
-        .context("Failed to prompt for programming language")?;
-
-    Ok(ProgrammingLanguage::from(selected_language))
+fn prompt_plain_copy(issues: &[String]) -> anyhow::Result<bool> {
+    status::clear();
+    confirm(
+        &i18n::format(Message::PlainCopy, &[&issues.join(", ")]),
+        Some(true),
+    )
+    .context("Failed to prompt for the plain copy of the template")
 }
 
-/// Prompts the user to initialize a git repository on the new project
+/// Prompts the user for the application class layout of a C++ project
 ///
 /// # Returns
-/// `true` if the user wants to initialize a git repository, `false` otherwise
+/// `true` if the user wants an `App` class, `false` for a plain `app_main`
 ///
 /// # Errors
 /// If the user cancels the operation
-fn prompt_use_git() -> anyhow::Result<bool> {
-    Confirm::new()
-        .with_prompt("Initialize git repo? (needs git)?")
-        .interact()
-        .context("Failed to prompt for git initialization")
+fn prompt_app_class() -> anyhow::Result<bool> {
+    confirm(t(Message::AppClass), Some(false))
+        .context("Failed to prompt for the application class layout")
 }
 
-/// Sets the programming language in the CMakeLists.txt file
+/// Prompts the user to edit the sources listed in main/CMakeLists.txt when
+/// the project has more than one: which to add and in what order; the
+/// planned ones are always kept, the main file calls into them
 ///
 /// # Arguments
-/// * `directory` - The directory that contains the project
-/// * `language` - The programming language CMake template to use
+/// * `options` - The validated options, the sources are planned from them
+///
+/// # Returns
+/// The sources to list, `None` to keep the planned ones
 ///
 /// # Errors
-/// If the file cannot be found or the file cannot be written
-fn set_cmake_options(directory: &str, project_language: &str, project_name: &str) -> anyhow::Result<()> {
-    let cmake_file = Path::new(&directory).join("CMakeLists.txt");
-    let mut cmake_list_file = fs::read_to_string(&cmake_file)
-        .context("Cannot find CMakeLists.txt")?
-        .split('\n')
-        .map(|s| s.to_string())
-        .collect::<Vec<String>>();
-
-    cmake_list_file[4] = project_language.into();
-    cmake_list_file[5] = "set(EXTRA_COMPONENT_DIRS components)".into();
-    cmake_list_file[6] = "include($ENV{IDF_PATH}/tools/cmake/project.cmake)".into();
-    cmake_list_file.push(format!("project({})", project_name));
-
-    let new_cmake_file = cmake_list_file.join("\n");
-
-    fs::write(&cmake_file, new_cmake_file)
-        .context("Cannot write CMakeLists.txt to set programming language")?;
+/// If the user cancels the operation
+fn prompt_main_sources(options: &ScaffoldOptions) -> anyhow::Result<Option<Vec<String>>> {
+    let planned = options.planned_main_sources()?;
+    if planned.len() < 2 {
+        return Ok(None);
+    }
+    let question = i18n::format(Message::EditMainSources, &[&planned.join(", ")]);
+    if !confirm(&question, Some(false)).context("Failed to prompt for the main sources")? {
+        return Ok(None);
+    }
+    loop {
+        let mut sources = planned.clone();
+        let added = prompter()
+            .input(t(Message::AddMainSources), "")
+            .context("Failed to prompt for the main sources")?;
+        sources.extend(
+            added
+                .split(',')
+                .map(str::trim)
+                .filter(|source| !source.is_empty())
+                .map(String::from),
+        );
+        let order = sort(t(Message::OrderMainSources), &sources)
+            .context("Failed to prompt for the main sources")?;
+        sources = order.into_iter().map(|i| sources[i].clone()).collect();
+        let mut edited = options.clone();
+        edited.main_sources = Some(sources.clone());
+        match edited.validate() {
+            Ok(()) if sources == planned => return Ok(None),
+            Ok(()) => return Ok(Some(sources)),
+            Err(error) => eprintln!("{} {:#}", Symbol::Warning, error),
+        }
+    }
+}
 
-    Ok(())
+/// Prompts the user to configure the advanced options, the defaults are used otherwise
+///
+/// # Returns
+/// `true` if the user wants to configure the advanced options, `false` otherwise
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_advanced_config() -> anyhow::Result<bool> {
+    confirm(t(Message::AdvancedOptions), Some(false))
+        .context("Failed to prompt for advanced options")
 }
 
-/// Replaces the main file with the selected programming language
+/// Prompts the user for the hygiene files in one list, each with what it
+/// does, and lets them preview the content of a file before it's written
 ///
 /// # Arguments
-/// * `directory` - The directory to write the file to
-/// * `language_selection` - The programming language to use
+/// * `defaults` - The files pre-selected, from the profile or the flags
+/// * `style` - The clang-format style used for the previews
 ///
 /// # Returns
-/// `Ok(())` if the file was written successfully, `Err(anyhow::Error)` otherwise
-fn replace_main_file(
-    directory: &str,
-    language_selection: ProgrammingLanguage,
-) -> anyhow::Result<()> {
-    let mut c_file = Path::new(&directory).join("main/main.c");
-    if language_selection == ProgrammingLanguage::C {
-        fs::write(c_file, templates::C_TEMPLATE).context("Cannot write C file")?;
-    } else {
-        // Remove main C file and replace with a C++ file
-        fs::remove_file(&c_file).unwrap();
-        c_file.pop();
-        c_file.push("main.cpp");
-        fs::write(c_file, templates::CPP_TEMPLATE).context("Cannot write cpp file")?;
+/// The selected files
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_hygiene(
+    defaults: &[HygieneFile],
+    style: ClangFormatStyle,
+) -> anyhow::Result<Vec<HygieneFile>> {
+    let items: Vec<String> = HygieneFile::ALL
+        .iter()
+        .map(|file| format!("{:<24} {}", file.file_name(), hygiene_description(*file)))
+        .collect();
+    let mut checked: Vec<bool> = HygieneFile::ALL
+        .iter()
+        .map(|file| defaults.contains(file))
+        .collect();
+    loop {
+        let selected: Vec<HygieneFile> = multi_select(t(Message::HygieneFiles), &items, &checked)
+            .context("Failed to prompt for the hygiene files")?
+            .into_iter()
+            .map(|i| HygieneFile::ALL[i])
+            .collect();
+        checked = HygieneFile::ALL
+            .iter()
+            .map(|file| selected.contains(file))
+            .collect();
+        if selected.is_empty() {
+            return Ok(selected);
+        }
 
-        // Tell CMake to use the new main.cpp file
-        let cmake_file = Path::new(&directory).join("main/CMakeLists.txt");
-        let mut component_cmake = fs::read_to_string(&cmake_file)
-            .unwrap()
-            .split('\n')
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
+        let action = select(
+            t(Message::WriteHygieneFiles),
+            &[
+                t(Message::Yes),
+                t(Message::PreviewFile),
+                t(Message::ChangeSelection),
+            ],
+            0,
+        )
+        .context("Failed to prompt for the hygiene files")?;
+        match action {
+            0 => return Ok(selected),
+            1 => {
+                let names: Vec<&str> = selected.iter().map(|file| file.file_name()).collect();
+                let file = select(t(Message::PreviewWhichFile), &names, 0)
+                    .context("Failed to prompt for the previewed file")?;
+                println!("{}", console::style(names[file]).bold());
+                for line in selected[file].render(style).lines() {
+                    println!("  {}", console::style(line).dim());
+                }
+                println!();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Prompts the user for the style of the `.clang-format`
+///
+/// # Returns
+/// The selected style
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_clang_format_style() -> anyhow::Result<ClangFormatStyle> {
+    let default = ClangFormatStyle::default();
+    let indent_width = prompt_choice(Message::IndentWidth, INDENT_WIDTHS, default.indent_width)?;
+    let column_limit = prompt_choice(Message::ColumnLimit, COLUMN_LIMITS, default.column_limit)?;
+    Ok(ClangFormatStyle {
+        indent_width,
+        column_limit,
+    })
+}
 
-        component_cmake[4] = r#"set(COMPONENT_SRCS "main.cpp")"#.into();
+/// Prompts the user for the FreeRTOS tick rate and the stack of the main
+/// task, explaining what the IDF defaults break
+///
+/// # Returns
+/// The selected tuning, [`RtosTuning::default`] is pre-selected
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_rtos_tuning() -> anyhow::Result<RtosTuning> {
+    let default = RtosTuning::default();
+    let tick_rate = select(
+        &i18n::with_default(Message::TickRate, &format!("{} Hz", default.tick_rate_hz)),
+        &[
+            t(Message::TickRate100),
+            t(Message::TickRate250),
+            t(Message::TickRate500),
+            t(Message::TickRate1000),
+        ],
+        TICK_RATES
+            .iter()
+            .position(|&hz| hz == default.tick_rate_hz)
+            .unwrap_or(0),
+    )
+    .context("Failed to prompt for the tick rate")?;
+    let stack_size = select(
+        &i18n::with_default(
+            Message::MainTaskStack,
+            &format!("{} bytes", default.main_task_stack_size),
+        ),
+        &[
+            t(Message::MainTaskStack3584),
+            t(Message::MainTaskStack4096),
+            t(Message::MainTaskStack6144),
+            t(Message::MainTaskStack8192),
+        ],
+        MAIN_TASK_STACK_SIZES
+            .iter()
+            .position(|&size| size == default.main_task_stack_size)
+            .unwrap_or(0),
+    )
+    .context("Failed to prompt for the main task stack size")?;
+    Ok(RtosTuning {
+        tick_rate_hz: TICK_RATES[tick_rate],
+        main_task_stack_size: MAIN_TASK_STACK_SIZES[stack_size],
+    })
+}
 
-        let new_cmake_file = component_cmake.join("\n");
+/// Prompts the user for secure boot and flash encryption, none selected,
+/// and asks again before enabling them since they cannot be undone
+///
+/// # Returns
+/// The features to enable, [`SecurityFeatures::default`] if none or if the
+/// user backs off
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_security_features() -> anyhow::Result<SecurityFeatures> {
+    let selected = multi_select(
+        t(Message::SecurityFeatures),
+        &[t(Message::SecureBootItem), t(Message::FlashEncryptionItem)],
+        &[false, false],
+    )
+    .context("Failed to prompt for the security features")?;
+    let features = SecurityFeatures {
+        secure_boot: selected.contains(&0),
+        flash_encryption: selected.contains(&1),
+    };
+    if features.is_disabled() {
+        return Ok(features);
+    }
+    let (names, prompt_names, docs) = match (features.secure_boot, features.flash_encryption) {
+        (true, true) => (
+            "Secure boot and flash encryption",
+            Message::SecureBootAndFlashEncryption,
+            format!("{} and {}", SECURE_BOOT_DOCS_URL, FLASH_ENCRYPTION_DOCS_URL),
+        ),
+        (true, false) => (
+            "Secure boot",
+            Message::SecureBoot,
+            SECURE_BOOT_DOCS_URL.to_string(),
+        ),
+        _ => (
+            "Flash encryption",
+            Message::FlashEncryption,
+            FLASH_ENCRYPTION_DOCS_URL.to_string(),
+        ),
+    };
+    status::warn(
+        Symbol::Warning,
+        &format!(
+            "{} cannot be turned off once a board boots with them, read {} first",
+            names, docs
+        ),
+    );
+    let confirmed = confirm(
+        &i18n::format(Message::ConfirmSecurity, &[t(prompt_names)]),
+        Some(false),
+    )
+    .context("Failed to prompt for the security features")?;
+    Ok(if confirmed {
+        features
+    } else {
+        SecurityFeatures::default()
+    })
+}
 
-        fs::write(cmake_file, new_cmake_file).context("Cannot write CMakeLists.txt")?;
+/// Prompts the user for the metadata to publish the main component to the
+/// component registry
+///
+/// # Returns
+/// The metadata, `None` if the user doesn't want it
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_component_metadata() -> anyhow::Result<Option<ComponentMetadata>> {
+    let add = confirm(t(Message::ComponentMetadata), Some(false))
+        .context("Failed to prompt for the component metadata")?;
+    if !add {
+        return Ok(None);
     }
-    Ok(())
+
+    let text = |prompt: Message, default: &str| -> anyhow::Result<String> {
+        prompter()
+            .input(t(prompt), default)
+            .map(|value| value.trim().to_string())
+            .context("Failed to prompt for the component metadata")
+    };
+    let optional = |value: String| Some(value).filter(|value| !value.is_empty());
+    let default = ComponentMetadata::default();
+    let metadata = ComponentMetadata {
+        version: text(Message::ComponentVersion, &default.version)?,
+        description: text(Message::ComponentDescription, "")?,
+        url: optional(text(Message::ComponentUrl, "")?),
+        maintainers: text(Message::ComponentMaintainers, "")?
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect(),
+        license: optional(text(Message::ComponentLicense, "")?),
+        targets: Vec::new(),
+    };
+    metadata.validate()?;
+    Ok(Some(metadata))
 }
 
-/// Extracts the zip template file to the directory
+/// Prompts the user for a custom partition table: the flash size, the size
+/// of the app partitions, then a scheme, pre-selecting the largest one that fits
 ///
 /// # Arguments
-/// * `directory` - The directory to extract the template to
-/// * `zip` - The zip archive to extract
-/// * `prefix` - The zip directory prefix
+/// * `board_flash_size_mb` - The flash size of the selected board, not asked if set
 ///
 /// # Returns
-/// `Ok(())` if the extraction was successful, `Err(anyhow::Error)` otherwise
-fn extract_zip(directory: &str, zip: &mut ZipArchive<File>, prefix: &Path) -> anyhow::Result<()> {
-    for i in 1..zip.len() {
-        let mut file = zip.by_index(i).unwrap();
-
-        let outpath = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
-        };
-
-        let outpath = PathBuf::new()
-            .join(&directory)
-            .join(outpath.strip_prefix(&prefix).unwrap());
-        if file.name().ends_with('/') {
-            fs::create_dir_all(&outpath).unwrap();
-            continue;
-        }
+/// The selected layout, `None` if the user keeps the built-in table
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_partitions(board_flash_size_mb: Option<u32>) -> anyhow::Result<Option<PartitionLayout>> {
+    let add = confirm(t(Message::PartitionTable), Some(false))
+        .context("Failed to prompt for the partition table")?;
+    if !add {
+        return Ok(None);
+    }
 
-        if let Some(p) = outpath.parent() {
-            if !p.exists() {
-                fs::create_dir_all(&p).unwrap();
+    let flash_size_mb = match board_flash_size_mb {
+        Some(flash_size_mb) => flash_size_mb,
+        None => prompt_choice(Message::FlashSize, FLASH_SIZES, 4)?,
+    };
+    let app_size_kb = prompt_app_size(flash_size_mb)?;
+    let default = PartitionScheme::largest_fitting_apps(flash_size_mb, app_size_kb);
+    let items: Vec<String> = PartitionScheme::ALL
+        .iter()
+        .map(|&scheme| {
+            let layout = PartitionLayout {
+                flash_size_mb,
+                scheme,
+                app_size_kb,
+            };
+            let description = match app_size_kb {
+                Some(app_size_kb) => i18n::format(
+                    Message::AppsResized,
+                    &[scheme_description(scheme), &app_size_kb.to_string()],
+                ),
+                None => scheme_description(scheme).to_string(),
+            };
+            match partitions::check_fits(&layout.partitions(), flash_size_mb) {
+                Ok(()) => description,
+                Err(_) => i18n::format(Message::SchemeTooLarge, &[&description]),
             }
+        })
+        .collect();
+    loop {
+        let selected = select(
+            &i18n::with_default(Message::PartitionScheme, scheme_description(default)),
+            &items,
+            PartitionScheme::ALL
+                .iter()
+                .position(|&scheme| scheme == default)
+                .unwrap_or(0),
+        )
+        .context("Failed to prompt for the partition scheme")?;
+        let layout = PartitionLayout {
+            flash_size_mb,
+            scheme: PartitionScheme::ALL[selected],
+            app_size_kb,
+        };
+        match layout.validate() {
+            Ok(()) => return Ok(Some(layout)),
+            Err(e) => eprintln!("{} {:#}", Symbol::Warning, e),
         }
-
-        let mut outfile = fs::File::create(&outpath).unwrap();
-        io::copy(&mut file, &mut outfile)
-            .context(format!("Failed to unzip file \"{}\"", file.name()))?;
     }
-    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_download_and_unzip_file() {
-        let mut tmp_file = tempfile::tempfile().unwrap();
-        let download_res = download_template(&mut tmp_file);
-        assert!(download_res.is_ok());
+/// [`HygieneFile::description`] in the locale of the prompts
+fn hygiene_description(file: HygieneFile) -> &'static str {
+    t(match file {
+        HygieneFile::EditorConfig => Message::EditorConfigItem,
+        HygieneFile::ClangFormat => Message::ClangFormatItem,
+        HygieneFile::Gitignore => Message::GitignoreItem,
+        HygieneFile::Gitattributes => Message::GitattributesItem,
+        HygieneFile::PreCommit => Message::PreCommitItem,
+    })
+}
 
-        let mut zip = ZipArchive::new(tmp_file).unwrap();
-        let extract_res = extract_zip("test", &mut zip, Path::new("esp-idf-template-master/"));
-        assert!(extract_res.is_ok());
-    }
+/// [`PartitionScheme::description`] in the locale of the prompts
+fn scheme_description(scheme: PartitionScheme) -> &'static str {
+    t(match scheme {
+        PartitionScheme::SingleApp => Message::SchemeSingleApp,
+        PartitionScheme::SingleAppLarge => Message::SchemeSingleAppLarge,
+        PartitionScheme::TwoOta => Message::SchemeTwoOta,
+        PartitionScheme::FactoryTwoOta => Message::SchemeFactoryTwoOta,
+    })
+}
 
-    #[test]
-    fn test_programming_language_conversion() {
-        let c_language = 0;
-        let c_language_enum = ProgrammingLanguage::from(c_language);
+/// Prompts the user for the size of the app partitions, for firmware that
+/// doesn't fit the 1MB of the schemes
+///
+/// # Arguments
+/// * `flash_size_mb` - The flash size, only smaller sizes are offered
+///
+/// # Returns
+/// The size in KB, `None` to keep the size of the scheme
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_app_size(flash_size_mb: u32) -> anyhow::Result<Option<u32>> {
+    let sizes: Vec<u32> = partitions::APP_SIZES_KB
+        .iter()
+        .copied()
+        .filter(|&size| size < flash_size_mb * 1024)
+        .collect();
+    let mut items = vec![t(Message::SchemeAppSizeItem).to_string()];
+    items.extend(sizes.iter().map(|size| format!("{}KB", size)));
+    let selected = select(
+        &i18n::with_default(Message::AppSize, t(Message::SchemeAppSize)),
+        &items,
+        0,
+    )
+    .context("Failed to prompt for the app partition size")?;
+    Ok(selected.checked_sub(1).map(|i| sizes[i]))
+}
 
-        assert_eq!(c_language_enum, ProgrammingLanguage::C);
+/// Prompts the user to pick one of the values
+///
+/// # Arguments
+/// * `prompt` - The question, the default value is appended to it
+/// * `values` - The values to choose from
+/// * `default` - The value selected by default, one of `values`
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_choice(prompt: Message, values: &[u32], default: u32) -> anyhow::Result<u32> {
+    let selected = select(
+        &i18n::with_default(prompt, &default.to_string()),
+        values,
+        values
+            .iter()
+            .position(|&value| value == default)
+            .unwrap_or(0),
+    )
+    .context(format!(
+        "Failed to prompt \"{}\"",
+        i18n::text(prompt, Locale::En)
+    ))?;
+    Ok(values[selected])
+}
 
-        let cpp11_language = 1;
-        let cpp11_language_enum = ProgrammingLanguage::from(cpp11_language);
-        assert_eq!(cpp11_language_enum, ProgrammingLanguage::Cpp11);
+/// Prompts the user for the behavior of failed assertions
+///
+/// # Returns
+/// The assertion level selected by the user
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_assertion_level() -> anyhow::Result<AssertionLevel> {
+    let selected_level = select(
+        &i18n::with_default(Message::Assertions, "abort"),
+        &[
+            t(Message::AssertionsAbort),
+            t(Message::AssertionsSilent),
+            t(Message::AssertionsDisabled),
+        ],
+        0,
+    )
+    .context("Failed to prompt for assertion level")?;
 
-        let cpp14_language = 2;
-        let cpp14_language_enum = ProgrammingLanguage::from(cpp14_language);
-        assert_eq!(cpp14_language_enum, ProgrammingLanguage::Cpp14);
+    Ok(AssertionLevel::from(selected_level))
+}
 
-        let cpp17_language = 3;
-        let cpp17_language_enum = ProgrammingLanguage::from(cpp17_language);
-        assert_eq!(cpp17_language_enum, ProgrammingLanguage::Cpp17);
-    }
+/// Prompts the user for the heap debugging checks
+///
+/// # Returns
+/// The heap debugging level selected by the user
+///
+/// # Errors
+/// If the user cancels the operation
+fn prompt_heap_debugging() -> anyhow::Result<HeapDebugging> {
+    let selected_level = select(
+        &i18n::with_default(Message::HeapDebugging, "disabled"),
+        &[
+            t(Message::HeapDebuggingDisabled),
+            t(Message::HeapDebuggingLight),
+            t(Message::HeapDebuggingComprehensive),
+            t(Message::HeapDebuggingTracing),
+        ],
+        0,
+    )
+    .context("Failed to prompt for heap debugging")?;
 
-    #[test]
-    fn test_programming_language_conversion_unknown() {
-        let unknown_language = 4;
-        let unknown_language_enum = ProgrammingLanguage::from(unknown_language);
-        assert_eq!(unknown_language_enum, ProgrammingLanguage::Unknown);
-    }
-}
\ No newline at end of file
+    Ok(HeapDebugging::from(selected_level))
+}
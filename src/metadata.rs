@@ -0,0 +1,111 @@
+//! `.esp-create-project.toml`: what the generator chose for a project, read
+//! back by the commands that update it later, e.g. `regen`
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::hygiene::HygieneFile;
+use crate::project::LOCAL_CONFIG_FILE;
+
+/// The choices recorded for a project
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectMetadata {
+    /// The name of the project as typed, updated by `rename`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// The hygiene files that were written, refreshed by `regen`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hygiene: Vec<HygieneFile>,
+    /// The template the project was generated from, shown by `info`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<TemplateProvenance>,
+}
+
+/// Where the template of a project came from
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateProvenance {
+    /// URL of the template archive
+    pub url: String,
+    /// Branch, tag or commit of the official template, `None` for `master`
+    /// or a custom template
+    #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// SHA-256 of the template archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl ProjectMetadata {
+    /// Reads the metadata of a project
+    ///
+    /// # Arguments
+    /// * `root` - The project root
+    ///
+    /// # Returns
+    /// The metadata, empty if the project has none
+    ///
+    /// # Errors
+    /// If the file exists but cannot be read or parsed
+    pub fn load(root: &Path) -> anyhow::Result<Self> {
+        let path = root.join(LOCAL_CONFIG_FILE);
+        if !path.is_file() {
+            return Ok(ProjectMetadata::default());
+        }
+        let content =
+            fs::read_to_string(&path).context(format!("Cannot read {}", LOCAL_CONFIG_FILE))?;
+        toml::from_str(&content).context(format!("Invalid {}", LOCAL_CONFIG_FILE))
+    }
+
+    /// The content of the metadata file
+    ///
+    /// # Errors
+    /// If the metadata cannot be serialized
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(format!(
+            "# Written by esp-create-project, read by `esp-create-project regen` and `info`\n{}",
+            toml::to_string(self).context(format!("Cannot write {}", LOCAL_CONFIG_FILE))?
+        ))
+    }
+
+    /// Writes the metadata to the root of a project
+    ///
+    /// # Errors
+    /// If the file cannot be written
+    pub fn write(&self, root: &Path) -> anyhow::Result<()> {
+        fs::write(root.join(LOCAL_CONFIG_FILE), self.to_toml()?)
+            .context(format!("Cannot write {}", LOCAL_CONFIG_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            ProjectMetadata::load(dir.path()).unwrap(),
+            ProjectMetadata::default()
+        );
+
+        let metadata = ProjectMetadata {
+            project: Some("blink".into()),
+            hygiene: vec![HygieneFile::EditorConfig, HygieneFile::PreCommit],
+            template: Some(TemplateProvenance {
+                url: "https://example.com/template.zip".into(),
+                git_ref: Some("v5.1".into()),
+                sha256: Some("ab".repeat(32)),
+            }),
+        };
+        metadata.write(dir.path()).unwrap();
+        let content = fs::read_to_string(dir.path().join(LOCAL_CONFIG_FILE)).unwrap();
+        assert!(content.contains("hygiene = [\"editorconfig\", \"pre-commit\"]\n"));
+        assert!(content
+            .contains("[template]\nurl = \"https://example.com/template.zip\"\nref = \"v5.1\"\n"));
+        assert_eq!(ProjectMetadata::load(dir.path()).unwrap(), metadata);
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{anyhow, bail};
+use std::path::{Component, PathBuf};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// CMake project name used when nothing usable is left after sanitizing
+pub const FALLBACK_CMAKE_NAME: &str = "esp_project";
+
+/// The different forms of the project name
+///
+/// * The directory keeps whatever the user typed (any valid path)
+/// * The display name (README, git description) is the last path component, untouched
+/// * The CMake name is an ASCII identifier safe for `project(...)` and the binary names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectNames {
+    pub directory: PathBuf,
+    pub display: String,
+    pub cmake: String,
+}
+
+impl ProjectNames {
+    /// Derives every form of the name from the name given by the user
+    ///
+    /// # Arguments
+    /// * `name` - The project name or path given by the user
+    ///
+    /// # Errors
+    /// If the name cannot be used as a directory on this platform
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        let directory = PathBuf::from(name);
+        let display = match directory.components().next_back() {
+            Some(Component::Normal(last)) => last.to_string_lossy().into_owned(),
+            _ => bail!("\"{}\" is not a valid project directory", name),
+        };
+        validate_directory_name(&display)
+            .map_err(|e| anyhow!("\"{}\" is not a valid project directory: {}", name, e))?;
+
+        let cmake = cmake_identifier(&display);
+        Ok(ProjectNames {
+            directory,
+            display,
+            cmake,
+        })
+    }
+}
+
+/// Converts a name into an identifier accepted by CMake and the IDF build
+///
+/// Accents are stripped (`café` becomes `cafe`), every other character that is
+/// not ASCII alphanumeric, `_` or `-` becomes `_`, and runs of `_` are collapsed.
+///
+/// # Arguments
+/// * `name` - The name to convert
+pub fn cmake_identifier(name: &str) -> String {
+    let mut identifier = String::with_capacity(name.len());
+    for c in name.nfkd().filter(|c| !is_combining_mark(*c)) {
+        let c = if c.is_ascii_alphanumeric() || c == '-' {
+            c
+        } else {
+            '_'
+        };
+        if !(c == '_' && identifier.ends_with('_')) {
+            identifier.push(c);
+        }
+    }
+
+    let identifier = identifier.trim_matches(|c| c == '_' || c == '-');
+    if identifier.is_empty() {
+        FALLBACK_CMAKE_NAME.into()
+    } else {
+        identifier.into()
+    }
+}
+
+/// C keywords, which cannot be used as identifiers
+const C_KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while",
+];
+
+/// Checks that the name is a valid C identifier, e.g. for names used in
+/// generated function names
+///
+/// # Arguments
+/// * `name` - The name to check
+///
+/// # Errors
+/// If the name is empty, has characters other than ASCII letters, digits and
+/// `_`, starts with a digit or is a C keyword
+pub fn validate_c_identifier(name: &str) -> anyhow::Result<()> {
+    let mut chars = name.chars();
+    match chars.next() {
+        None => bail!("the name is empty"),
+        Some(c) if c.is_ascii_digit() => bail!("a C identifier cannot start with a digit"),
+        _ => {}
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+    {
+        bail!("'{}' is not allowed in a C identifier", c);
+    }
+    if C_KEYWORDS.contains(&name) {
+        bail!("\"{}\" is a C keyword", name);
+    }
+    Ok(())
+}
+
+/// Checks that the last component of the project path can be created
+///
+/// # Arguments
+/// * `name` - The last component of the project path
+#[cfg(windows)]
+fn validate_directory_name(name: &str) -> anyhow::Result<()> {
+    const RESERVED: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    if name.trim().is_empty() {
+        bail!("the name is empty");
+    }
+    // Windows silently drops trailing dots and spaces, so the directory would not match the name
+    if name.ends_with('.') || name.ends_with(' ') {
+        bail!("names cannot end with a dot or a space on Windows");
+    }
+    if name
+        .chars()
+        .any(|c| "<>:\"|?*".contains(c) || c.is_control())
+    {
+        bail!("names cannot contain <>:\"|?* or control characters on Windows");
+    }
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        bail!("\"{}\" is a reserved name on Windows", stem);
+    }
+    Ok(())
+}
+
+/// Checks that the last component of the project path can be created
+///
+/// # Arguments
+/// * `name` - The last component of the project path
+#[cfg(not(windows))]
+fn validate_directory_name(name: &str) -> anyhow::Result<()> {
+    if name.trim().is_empty() {
+        bail!("the name is empty");
+    }
+    if name.chars().any(|c| c == '\0') {
+        bail!("names cannot contain NUL characters");
+    }
+    Ok(())
+}
+
+/// Names that have broken the generation at some point, shared with the pipeline tests
+#[cfg(test)]
+pub const ADVERSARIAL_NAMES: &[&str] = &[
+    "café-sensor",
+    "my project",
+    "🚀 rocket",
+    "مشروع-عربي",
+    "שלום",
+    "日本語プロジェクト",
+    "name.",
+    "  padded  ",
+    "123-start-with-digits",
+    "nested/dir with spaces/proj",
+    "___",
+    "a;b$(c)`d`",
+    "Ångström Ünïcödé",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_c_identifier() {
+        assert!(validate_c_identifier("sensor").is_ok());
+        assert!(validate_c_identifier("_my_sensor2").is_ok());
+        assert!(validate_c_identifier("").is_err());
+        assert!(validate_c_identifier("2sensor").is_err());
+        assert!(validate_c_identifier("my-sensor").is_err());
+        assert!(validate_c_identifier("café").is_err());
+        assert!(validate_c_identifier("int").is_err());
+    }
+
+    #[test]
+    fn test_cmake_identifier() {
+        assert_eq!(cmake_identifier("café-sensor"), "cafe-sensor");
+        assert_eq!(cmake_identifier("my project"), "my_project");
+        assert_eq!(cmake_identifier("🚀 rocket"), "rocket");
+        assert_eq!(cmake_identifier("שלום"), FALLBACK_CMAKE_NAME);
+        assert_eq!(cmake_identifier("a;b$(c)"), "a_b_c");
+    }
+
+    #[test]
+    fn test_adversarial_names_invariants() {
+        for name in ADVERSARIAL_NAMES {
+            let names = match ProjectNames::new(name) {
+                Ok(names) => names,
+                // Only allowed to be rejected because of platform restrictions
+                Err(_) if cfg!(windows) => continue,
+                Err(e) => panic!("\"{}\" rejected: {}", name, e),
+            };
+
+            assert_eq!(names.directory, PathBuf::from(name));
+            assert!(!names.display.is_empty());
+            assert!(name.ends_with(&names.display));
+            assert!(!names.cmake.is_empty());
+            assert!(
+                names
+                    .cmake
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+                "\"{}\" is not a valid CMake name",
+                names.cmake
+            );
+            assert!(!names.cmake.starts_with('_') && !names.cmake.ends_with('_'));
+        }
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        assert!(ProjectNames::new("").is_err());
+        assert!(ProjectNames::new("..").is_err());
+        assert!(ProjectNames::new("   ").is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_invalid_names_windows() {
+        assert!(ProjectNames::new("name.").is_err());
+        assert!(ProjectNames::new("name ").is_err());
+        assert!(ProjectNames::new("CON").is_err());
+        assert!(ProjectNames::new("nul.txt").is_err());
+        assert!(ProjectNames::new("a?b").is_err());
+        assert!(ProjectNames::new("C:\\Users\\me\\Documents\\proj with spaces").is_ok());
+    }
+}
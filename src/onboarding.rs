@@ -0,0 +1,235 @@
+//! First-run onboarding: on the first interactive run (no config file and
+//! no onboarding done), the build tools found are reported, and the user can
+//! set the defaults of new projects and decide about the update check. The
+//! answers are written to the config file, see [`apply`].
+
+use anyhow::Context;
+use std::fs;
+use std::path::Path;
+
+use crate::config::UserConfig;
+use crate::options::ProgrammingLanguage;
+
+/// File of the state directory written once the onboarding is done, so it
+/// isn't run again even if the user wrote no config file
+pub const ONBOARDED_FILE: &str = "onboarded";
+
+/// Defaults of new projects, written to [`UserConfig::defaults`]: the
+/// prompts propose them, they still ask
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Defaults {
+    pub language: Option<ProgrammingLanguage>,
+    /// Board id, or `None` for a custom board with [`Defaults::target`]
+    pub board: Option<String>,
+    pub target: Option<String>,
+    pub use_git: Option<bool>,
+}
+
+/// The answers of the onboarding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answers {
+    /// The defaults of new projects, `None` to leave them unchanged
+    pub defaults: Option<Defaults>,
+    pub update_check: bool,
+}
+
+/// Whether the onboarding runs without `--onboard`: neither a config file
+/// nor [`ONBOARDED_FILE`] exists
+///
+/// # Arguments
+/// * `config_file` - See [`crate::config::config_path`]
+/// * `state_dir` - See [`crate::config::state_dir`]
+pub fn is_first_run(config_file: &Path, state_dir: &Path) -> bool {
+    !config_file.exists() && !state_dir.join(ONBOARDED_FILE).exists()
+}
+
+/// Records that the onboarding is done, see [`is_first_run`]
+///
+/// # Errors
+/// If the state directory or the file cannot be written
+pub fn mark_done(state_dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(state_dir).context("Cannot create the state directory")?;
+    let path = state_dir.join(ONBOARDED_FILE);
+    fs::write(&path, "").context(format!("Cannot write \"{}\"", path.display()))
+}
+
+/// The defaults already in the config, proposed by the prompts when the
+/// onboarding runs again
+///
+/// # Arguments
+/// * `config` - The config
+pub fn current_defaults(config: &UserConfig) -> Defaults {
+    Defaults {
+        language: config.defaults.language,
+        board: config.defaults.board.clone(),
+        target: config.defaults.target.clone(),
+        use_git: config.defaults.use_git,
+    }
+}
+
+/// Writes the answers to the config file, edited in place so everything
+/// else in it is kept, comments included; see [`UserConfig::edit_file`]
+///
+/// The defaults go to the `[defaults]` table, the fields not answered are
+/// kept.
+///
+/// # Arguments
+/// * `document` - The config file
+/// * `answers` - The answers of the onboarding
+///
+/// # Errors
+/// If `defaults` is not a table in the file
+pub fn apply(document: &mut toml_edit::DocumentMut, answers: &Answers) -> anyhow::Result<()> {
+    document["update_check"] = toml_edit::value(answers.update_check);
+    let defaults = match &answers.defaults {
+        Some(defaults) => defaults,
+        None => return Ok(()),
+    };
+    let table = document
+        .entry("defaults")
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .context("\"defaults\" of the config file is not a table")?;
+    let language = defaults
+        .language
+        .and_then(|language| serde_json::to_value(language).ok())
+        .and_then(|language| language.as_str().map(String::from));
+    if let Some(language) = language {
+        table["language"] = toml_edit::value(language);
+    }
+    if let Some(use_git) = defaults.use_git {
+        table["use_git"] = toml_edit::value(use_git);
+    }
+    // A board sets the chip, a custom board has only a chip
+    if defaults.board.is_some() || defaults.target.is_some() {
+        for (key, value) in [("board", &defaults.board), ("target", &defaults.target)] {
+            match value {
+                Some(value) => table[key] = toml_edit::value(value.as_str()),
+                None => {
+                    table.remove(key);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answers(defaults: Option<Defaults>) -> Answers {
+        Answers {
+            defaults,
+            update_check: false,
+        }
+    }
+
+    #[test]
+    fn test_is_first_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("config").join("config.toml");
+        let state_dir = dir.path().join("state");
+        assert!(is_first_run(&config_file, &state_dir));
+
+        mark_done(&state_dir).unwrap();
+        assert!(!is_first_run(&config_file, &state_dir));
+
+        fs::remove_file(state_dir.join(ONBOARDED_FILE)).unwrap();
+        fs::create_dir_all(config_file.parent().unwrap()).unwrap();
+        fs::write(&config_file, "").unwrap();
+        assert!(!is_first_run(&config_file, &state_dir));
+    }
+
+    /// Applies the answers to a config file, returns the new file
+    fn applied(content: &str, answers: &Answers) -> String {
+        let mut document: toml_edit::DocumentMut = content.parse().unwrap();
+        apply(&mut document, answers).unwrap();
+        document.to_string()
+    }
+
+    #[test]
+    fn test_apply_first_run() {
+        let content = applied(
+            "",
+            &answers(Some(Defaults {
+                language: Some(ProgrammingLanguage::Cpp17),
+                board: None,
+                target: Some("esp32c3".into()),
+                use_git: Some(true),
+            })),
+        );
+
+        let written = UserConfig::from_toml(&content).unwrap();
+        assert!(!written.update_check);
+        // Prompt defaults, not a profile that would skip the prompts
+        assert_eq!(written.default_profile, None);
+        assert!(written.profile.is_empty());
+        assert_eq!(written.defaults.language, Some(ProgrammingLanguage::Cpp17));
+        assert_eq!(written.defaults.target.as_deref(), Some("esp32c3"));
+        assert_eq!(written.defaults.use_git, Some(true));
+    }
+
+    #[test]
+    fn test_apply_partial_config() {
+        // Comments, some defaults, and a profile
+        let content = r#"# My settings
+default_profile = "team" # shared with the team
+
+[defaults]
+board = "esp32-c3-devkitm-1"
+
+[profile.team]
+language = "c"
+starters = ["nvs"]
+"#;
+        let config = UserConfig::from_toml(content).unwrap();
+        let current = current_defaults(&config);
+        assert_eq!(current.board.as_deref(), Some("esp32-c3-devkitm-1"));
+        assert_eq!(current.language, None);
+        assert!(config.update_check);
+
+        let content = applied(
+            content,
+            &answers(Some(Defaults {
+                language: Some(ProgrammingLanguage::Cpp14),
+                ..current
+            })),
+        );
+
+        assert!(content
+            .starts_with("# My settings\ndefault_profile = \"team\" # shared with the team\n"));
+        let written = UserConfig::from_toml(&content).unwrap();
+        assert_eq!(written.defaults.language, Some(ProgrammingLanguage::Cpp14));
+        assert_eq!(
+            written.defaults.board.as_deref(),
+            Some("esp32-c3-devkitm-1")
+        );
+        let team = written.resolve_profile("team").unwrap();
+        assert_eq!(team.language, Some(ProgrammingLanguage::C));
+        assert_eq!(team.starters, Some(vec!["nvs".to_string()]));
+        assert!(!written.update_check);
+
+        // A custom board replaces the board with a chip
+        let content = applied(
+            &content,
+            &answers(Some(Defaults {
+                target: Some("esp32s3".into()),
+                ..Defaults::default()
+            })),
+        );
+        let written = UserConfig::from_toml(&content).unwrap();
+        assert_eq!(written.defaults.board, None);
+        assert_eq!(written.defaults.target.as_deref(), Some("esp32s3"));
+        assert_eq!(written.defaults.language, Some(ProgrammingLanguage::Cpp14));
+    }
+
+    #[test]
+    fn test_apply_without_defaults() {
+        let content = applied("", &answers(None));
+        let config = UserConfig::from_toml(&content).unwrap();
+        assert!(config.defaults.is_empty());
+        assert!(config.profile.is_empty());
+        assert!(!config.update_check);
+    }
+}
@@ -0,0 +1,1521 @@
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+use crate::boards::{self, Console};
+use crate::ci::CiProvider;
+use crate::config::Profile;
+use crate::download;
+use crate::factory_test::{self, FACTORY_TEST_APP};
+use crate::flash;
+use crate::hygiene::HygieneFile;
+use crate::idf_component::ComponentMetadata;
+use crate::idf_version::{IdfFeature, IdfRequirement, IdfVersion};
+use crate::naming::ProjectNames;
+use crate::partitions::{self, PartitionLayout};
+use crate::platform;
+use crate::project_model::parse_partitions;
+use crate::starters;
+use crate::template_cache::TemplateTtl;
+use crate::template_ref;
+use crate::template_source::TemplateSource;
+use crate::templates;
+
+/// Programming language used by the generated project
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgrammingLanguage {
+    #[serde(skip)]
+    Unknown,
+    C,
+    Cpp11,
+    Cpp14,
+    Cpp17,
+}
+
+impl ProgrammingLanguage {
+    /// Extensions accepted for a main file in this language
+    pub fn source_extensions(self) -> &'static [&'static str] {
+        match self {
+            ProgrammingLanguage::C => &["c"],
+            ProgrammingLanguage::Cpp11
+            | ProgrammingLanguage::Cpp14
+            | ProgrammingLanguage::Cpp17 => &["cpp", "cc", "cxx"],
+            ProgrammingLanguage::Unknown => &[],
+        }
+    }
+}
+
+impl From<usize> for ProgrammingLanguage {
+    fn from(lang: usize) -> Self {
+        match lang {
+            0 => ProgrammingLanguage::C,
+            1 => ProgrammingLanguage::Cpp11,
+            2 => ProgrammingLanguage::Cpp14,
+            3 => ProgrammingLanguage::Cpp17,
+            _ => ProgrammingLanguage::Unknown,
+        }
+    }
+}
+
+/// Behavior of `assert()` in the firmware (`CONFIG_COMPILER_OPTIMIZATION_ASSERTIONS_*`)
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssertionLevel {
+    /// Failed assertions print a message and abort, the IDF default
+    #[default]
+    Abort,
+    /// Failed assertions abort without printing the file and line
+    Silent,
+    /// Assertions are compiled out
+    Disabled,
+}
+
+impl From<usize> for AssertionLevel {
+    fn from(level: usize) -> Self {
+        match level {
+            1 => AssertionLevel::Silent,
+            2 => AssertionLevel::Disabled,
+            _ => AssertionLevel::Abort,
+        }
+    }
+}
+
+impl AssertionLevel {
+    /// The `sdkconfig` key that selects this level
+    pub fn config_key(self) -> &'static str {
+        match self {
+            AssertionLevel::Abort => "CONFIG_COMPILER_OPTIMIZATION_ASSERTIONS_ENABLE",
+            AssertionLevel::Silent => "CONFIG_COMPILER_OPTIMIZATION_ASSERTIONS_SILENT",
+            AssertionLevel::Disabled => "CONFIG_COMPILER_OPTIMIZATION_ASSERTIONS_DISABLE",
+        }
+    }
+}
+
+/// Heap debugging of the firmware (`CONFIG_HEAP_POISONING_*`, `CONFIG_HEAP_TRACING_*`)
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeapDebugging {
+    /// No heap checks, the IDF default
+    #[default]
+    Disabled,
+    /// Canaries around each allocation, checked when it is freed
+    Light,
+    /// Light, plus filling allocated and freed memory to catch uses of
+    /// uninitialized or freed memory
+    Comprehensive,
+    /// Comprehensive, plus recording allocations to find leaks with `heap_trace_*()`
+    Tracing,
+}
+
+impl From<usize> for HeapDebugging {
+    fn from(level: usize) -> Self {
+        match level {
+            1 => HeapDebugging::Light,
+            2 => HeapDebugging::Comprehensive,
+            3 => HeapDebugging::Tracing,
+            _ => HeapDebugging::Disabled,
+        }
+    }
+}
+
+impl HeapDebugging {
+    /// The `sdkconfig` keys enabled for this level, none when disabled
+    pub fn config_keys(self) -> &'static [&'static str] {
+        match self {
+            HeapDebugging::Disabled => &[],
+            HeapDebugging::Light => &["CONFIG_HEAP_POISONING_LIGHT"],
+            HeapDebugging::Comprehensive => &["CONFIG_HEAP_POISONING_COMPREHENSIVE"],
+            HeapDebugging::Tracing => &[
+                "CONFIG_HEAP_POISONING_COMPREHENSIVE",
+                "CONFIG_HEAP_TRACING_STANDALONE",
+            ],
+        }
+    }
+}
+
+/// How much of the post-processing runs on the extracted template, see
+/// [`crate::generator::template_layout_issues`]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostProcess {
+    /// Everything, unless the template doesn't have the layout of an
+    /// ESP-IDF project, which is then copied as it is; see
+    /// [`crate::steps::StepRunner::with_plain_copy_prompt`] to ask first
+    #[default]
+    Auto,
+    /// Everything, whatever the layout of the template
+    Full,
+    /// Copy the template as it is, only its placeholders are filled in
+    None,
+}
+
+impl PostProcess {
+    /// Every mode, in the order of the documentation
+    pub const ALL: &'static [PostProcess] =
+        &[PostProcess::Auto, PostProcess::Full, PostProcess::None];
+}
+
+impl std::fmt::Display for PostProcess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PostProcess::Auto => "auto",
+            PostProcess::Full => "full",
+            PostProcess::None => "none",
+        })
+    }
+}
+
+impl std::str::FromStr for PostProcess {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        PostProcess::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.to_string() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown post-processing \"{}\", expected auto, full or none",
+                    name
+                )
+            })
+    }
+}
+
+/// Indent widths offered for the generated `.clang-format`
+pub const INDENT_WIDTHS: &[u32] = &[2, 4];
+
+/// Column limits offered for the generated `.clang-format`
+pub const COLUMN_LIMITS: &[u32] = &[80, 100, 120];
+
+/// Style written to the generated `.clang-format`
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClangFormatStyle {
+    /// Spaces per indentation level, one of [`INDENT_WIDTHS`]
+    pub indent_width: u32,
+    /// Maximum line length, one of [`COLUMN_LIMITS`]
+    pub column_limit: u32,
+}
+
+impl Default for ClangFormatStyle {
+    fn default() -> Self {
+        ClangFormatStyle {
+            indent_width: 4,
+            column_limit: 100,
+        }
+    }
+}
+
+impl ClangFormatStyle {
+    /// Checks that the values are among the offered ones
+    ///
+    /// # Errors
+    /// If the indent width or the column limit is not supported
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !INDENT_WIDTHS.contains(&self.indent_width) {
+            bail!(
+                "Unsupported clang-format indent width {}, expected one of {:?}",
+                self.indent_width,
+                INDENT_WIDTHS
+            );
+        }
+        if !COLUMN_LIMITS.contains(&self.column_limit) {
+            bail!(
+                "Unsupported clang-format column limit {}, expected one of {:?}",
+                self.column_limit,
+                COLUMN_LIMITS
+            );
+        }
+        Ok(())
+    }
+}
+
+/// FreeRTOS tick rates offered, in Hz
+pub const TICK_RATES: &[u32] = &[100, 250, 500, 1000];
+
+/// Stack sizes of the main task offered, in bytes
+pub const MAIN_TASK_STACK_SIZES: &[u32] = &[3584, 4096, 6144, 8192];
+
+/// Smallest stack of the main task accepted, in bytes
+const MIN_MAIN_TASK_STACK_SIZE: u32 = 2048;
+
+/// FreeRTOS tick rate and stack of the main task (`CONFIG_FREERTOS_HZ`,
+/// `CONFIG_ESP_MAIN_TASK_STACK_SIZE`)
+///
+/// A value left out, e.g. in the `rtos` object of an options file, is the
+/// default of the tool: 1000 Hz and 4096 bytes, not the IDF defaults of
+/// 100 Hz and 3584 bytes.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RtosTuning {
+    /// Ticks per second; at the IDF default of 100 Hz, `pdMS_TO_TICKS(10)`
+    /// is a single tick and shorter delays round down to none
+    pub tick_rate_hz: u32,
+    /// Stack of the task that runs `app_main`; the IDF default of 3584 bytes
+    /// overflows as soon as `printf` formats floats
+    pub main_task_stack_size: u32,
+}
+
+impl Default for RtosTuning {
+    fn default() -> Self {
+        RtosTuning {
+            tick_rate_hz: 1000,
+            main_task_stack_size: 4096,
+        }
+    }
+}
+
+impl RtosTuning {
+    /// Checks that the values can be set in `sdkconfig`
+    ///
+    /// # Errors
+    /// If the tick rate is not between 1 and 1000 Hz or the stack is too small
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(1..=1000).contains(&self.tick_rate_hz) {
+            bail!(
+                "Unsupported FreeRTOS tick rate {} Hz, expected 1 to 1000",
+                self.tick_rate_hz
+            );
+        }
+        if self.main_task_stack_size < MIN_MAIN_TASK_STACK_SIZE {
+            bail!(
+                "A main task stack of {} bytes is too small, expected at least {}",
+                self.main_task_stack_size,
+                MIN_MAIN_TASK_STACK_SIZE
+            );
+        }
+        Ok(())
+    }
+
+    /// The `sdkconfig.defaults` entries of the tuning
+    pub fn sdkconfig(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                "CONFIG_FREERTOS_HZ".to_string(),
+                self.tick_rate_hz.to_string(),
+            ),
+            (
+                "CONFIG_ESP_MAIN_TASK_STACK_SIZE".to_string(),
+                self.main_task_stack_size.to_string(),
+            ),
+        ]
+    }
+}
+
+/// Where the risks of [`SecurityFeatures::secure_boot`] are documented
+pub const SECURE_BOOT_DOCS_URL: &str =
+    "https://docs.espressif.com/projects/esp-idf/en/latest/esp32/security/secure-boot-v2.html";
+/// Where the risks of [`SecurityFeatures::flash_encryption`] are documented
+pub const FLASH_ENCRYPTION_DOCS_URL: &str =
+    "https://docs.espressif.com/projects/esp-idf/en/latest/esp32/security/flash-encryption.html";
+
+/// Secure boot and flash encryption, enabled in `sdkconfig.defaults` as
+/// stubs to finish with `idf.py menuconfig`; both burn eFuses on the first
+/// boot, which cannot be undone on real hardware
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityFeatures {
+    /// Whether the bootloader only runs signed apps (`CONFIG_SECURE_BOOT`)
+    pub secure_boot: bool,
+    /// Whether the flash is encrypted (`CONFIG_SECURE_FLASH_ENC_ENABLED`),
+    /// in development mode so the board can still be reflashed
+    pub flash_encryption: bool,
+}
+
+impl SecurityFeatures {
+    /// Whether no feature is enabled, the default
+    pub fn is_disabled(&self) -> bool {
+        !self.secure_boot && !self.flash_encryption
+    }
+
+    /// The `sdkconfig.defaults` entries of the enabled features, with the
+    /// warning written above each of them
+    pub fn sdkconfig(&self) -> Vec<(String, String, String)> {
+        let mut entries = Vec::new();
+        if self.secure_boot {
+            entries.push((
+                "CONFIG_SECURE_BOOT".to_string(),
+                "y".to_string(),
+                format!(
+                    "WARNING: secure boot is IRREVERSIBLE on real hardware, the first boot\n\
+                     burns eFuses and the chip then only runs apps signed with your key.\n\
+                     Losing the signing key bricks every board that uses it: generate it\n\
+                     with espsecure.py, keep it out of git and back it up.\n\
+                     See {}",
+                    SECURE_BOOT_DOCS_URL
+                ),
+            ));
+        }
+        if self.flash_encryption {
+            entries.push((
+                "CONFIG_SECURE_FLASH_ENC_ENABLED".to_string(),
+                "y".to_string(),
+                format!(
+                    "WARNING: flash encryption is IRREVERSIBLE on real hardware, the first\n\
+                     boot burns the key in eFuses and encrypts the flash in place.\n\
+                     Development mode still allows a limited number of reflashes; release\n\
+                     mode disables plaintext flashing for good. Plan the key management\n\
+                     before the boards leave your desk.\n\
+                     See {}",
+                    FLASH_ENCRYPTION_DOCS_URL
+                ),
+            ));
+            entries.push((
+                "CONFIG_SECURE_FLASH_ENCRYPTION_MODE_DEVELOPMENT".to_string(),
+                "y".to_string(),
+                String::new(),
+            ));
+        }
+        entries
+    }
+}
+
+/// Every choice needed to scaffold a project, either collected from the
+/// prompts or read from an options file
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScaffoldOptions {
+    /// Name of the project, also used as the destination directory
+    pub project_name: String,
+    /// Programming language of the main file
+    pub language: ProgrammingLanguage,
+    /// Whether to initialize a git repo in the project
+    #[serde(default)]
+    pub use_git: bool,
+    /// Id of the development board, see [`crate::boards`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub board: Option<String>,
+    /// Chip of a custom board, the ESP-IDF default (esp32) if not set; the
+    /// target of the board when one is picked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Behavior of failed assertions
+    #[serde(default)]
+    pub assertions: AssertionLevel,
+    /// Heap poisoning and tracing, disabled if not set
+    #[serde(default)]
+    pub heap_debugging: HeapDebugging,
+    /// Whether to add a sample `main/Kconfig.projbuild` and read its option in the main file
+    #[serde(default)]
+    pub kconfig_example: bool,
+    /// Whether template entries that cannot be extracted are skipped instead
+    /// of aborting the generation
+    #[serde(default)]
+    pub keep_going: bool,
+    /// Whether the extracted template is post-processed or copied as it is
+    #[serde(default)]
+    pub post_process: PostProcess,
+    /// Permissions of the created directories (Unix only), the system
+    /// default if `None`; only set from the command line
+    #[serde(skip)]
+    pub dir_mode: Option<u32>,
+    /// Largest template accepted in bytes, downloaded and extracted,
+    /// [`crate::download::DEFAULT_MAX_TEMPLATE_SIZE`] if `None`; only set from
+    /// the command line
+    #[serde(skip)]
+    pub max_template_size: Option<u64>,
+    /// Apps of a workspace written at the same time, the number of CPUs if
+    /// `None`; only set from the command line
+    #[serde(skip)]
+    pub jobs: Option<NonZeroUsize>,
+    /// Directory of the [`crate::template_cache`], where downloaded templates
+    /// are stored and read back when the download fails; no cache if `None`,
+    /// only set from the command line
+    #[serde(skip)]
+    pub template_cache: Option<PathBuf>,
+    /// How long a cached template is used without downloading it again;
+    /// only set from the command line or the configuration file
+    #[serde(skip)]
+    pub template_ttl: TemplateTtl,
+    /// Whether network access is disabled (`--offline`): a template that has
+    /// to be downloaded is only read from the cache, even a stale copy; only
+    /// set from the command line
+    #[serde(skip)]
+    pub offline: bool,
+    /// Minimum ESP-IDF version the project assumes, written to the README and
+    /// `main/idf_component.yml` when the installed version is unknown
+    #[serde(skip)]
+    pub assumed_idf_version: Option<IdfVersion>,
+    /// Local template zip read instead of downloading the template; only
+    /// set by `--replay` and `--record-full`
+    #[serde(skip)]
+    pub template_archive: Option<PathBuf>,
+    /// SHA-256 the template must have, e.g. the one of a replayed
+    /// generation; only set by `--replay`
+    #[serde(skip)]
+    pub template_sha256: Option<String>,
+    /// Local file copied as the main file instead of the built-in one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_file: Option<PathBuf>,
+    /// Whether a C++ project gets an `App` class in `main/app.hpp` and
+    /// `main/app.cpp`, initialized and run by a minimal `main.cpp`
+    #[serde(default)]
+    pub app_class: bool,
+    /// The sources of `main/CMakeLists.txt`, in order, replacing the ones of
+    /// [`ScaffoldOptions::planned_main_sources`]; the missing ones are
+    /// written empty, and the other sources the template lists are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_sources: Option<Vec<String>>,
+    /// Whether only the build-system files of the template are extracted
+    /// (see [`crate::archive::is_build_file`]), to add the ESP-IDF build
+    /// around existing sources; the main source and the README are not written
+    #[serde(default)]
+    pub scaffold_only_config: bool,
+    /// Style of the `.clang-format` added to the project, none if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clang_format: Option<ClangFormatStyle>,
+    /// Hygiene files to add, `.clang-format` is also added when
+    /// [`ScaffoldOptions::clang_format`] is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hygiene: Vec<HygieneFile>,
+    /// FreeRTOS tick rate and main task stack, the IDF defaults if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtos: Option<RtosTuning>,
+    /// Flash size and scheme of the custom `partitions.csv`, the built-in
+    /// table of ESP-IDF if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitions: Option<PartitionLayout>,
+    /// Secure boot and flash encryption stubs, disabled by default
+    #[serde(default, skip_serializing_if = "SecurityFeatures::is_disabled")]
+    pub security: SecurityFeatures,
+    /// Registry metadata written to `main/idf_component.yml`, the file is
+    /// only written for the starters' dependencies if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component_metadata: Option<ComponentMetadata>,
+    /// Whether to add a host test built with AddressSanitizer in `host_test/`
+    #[serde(default)]
+    pub host_test_asan: bool,
+    /// Whether to add a second-stage bootloader hooks component in
+    /// `bootloader_components/`, for advanced users
+    #[serde(default)]
+    pub bootloader_hooks: bool,
+    /// Whether to add a script that flashes the board and opens the monitor,
+    /// `flash.sh` or `flash.ps1` on Windows, see [`crate::flash::script`]
+    #[serde(default)]
+    pub flash_script: bool,
+    /// Serial port the flash script uses when it's run without one, `idf.py`
+    /// finds the port if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flash_port: Option<String>,
+    /// CI service a build pipeline is written for at the root of the
+    /// project, none if not set, see [`crate::ci`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ci: Option<CiProvider>,
+    /// Whether to leave the badges (license, ESP-IDF version) out of the README
+    #[serde(default)]
+    pub no_badges: bool,
+    /// Whether the project has no `.gitignore`, neither the hygiene file nor
+    /// the one of the template; a git repo is still initialized with
+    /// [`ScaffoldOptions::use_git`], and git applies the global ignore file
+    /// of the user (`core.excludesFile`) instead
+    #[serde(default)]
+    pub no_gitignore: bool,
+    /// Whether no `sdkconfig.defaults` is written, the configuration is left
+    /// to `idf.py menuconfig`; the settings that would have been written
+    /// (board, target, advanced options, starters) become a manual step
+    #[serde(default)]
+    pub no_sdkconfig: bool,
+    /// Names of the sub-projects of a workspace (shared `components/` and
+    /// `sdkconfig.defaults` at the root), a single project if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workspace_apps: Vec<String>,
+    /// Whether the components identical in every app of a workspace are
+    /// moved to the root and linked from the apps with relative symbolic
+    /// links, see [`crate::workspace::share_components`]
+    #[serde(default)]
+    pub relative_symlinks: bool,
+    /// Whether a workspace gets a factory test app, see
+    /// [`crate::factory_test`]; it needs a custom partition table, which
+    /// gets the partition of the app
+    #[serde(default)]
+    pub factory_test: bool,
+    /// Ids of the starters added to the main component, see [`crate::starters`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub starters: Vec<String>,
+    /// Branch, tag or commit of the official template, `master` when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_ref: Option<String>,
+    /// URL of the template zip, the official template when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_url: Option<String>,
+    /// Directory of the template zip its files are in, detected when not
+    /// set (see [`crate::archive::detect_prefix`]), e.g. the `strip_prefix`
+    /// of a [`crate::catalog::ManifestTemplate`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_prefix: Option<String>,
+}
+
+impl ScaffoldOptions {
+    /// The options of a project from the answers of a profile, the ones it
+    /// doesn't set get the defaults of `--yes`
+    ///
+    /// # Arguments
+    /// * `project_name` - The name of the project
+    /// * `profile` - The answers, from the profile, the flags and the prompts
+    /// * `no_sdkconfig` - Whether `sdkconfig.defaults` is not written
+    pub fn from_profile(project_name: String, profile: Profile, no_sdkconfig: bool) -> Self {
+        let mut hygiene = profile.hygiene.unwrap_or_default();
+        if profile.clang_format.is_some() && !hygiene.contains(&HygieneFile::ClangFormat) {
+            hygiene.push(HygieneFile::ClangFormat);
+        }
+        let style = profile.clang_format.unwrap_or_default();
+        let clang_format = hygiene.contains(&HygieneFile::ClangFormat).then_some(style);
+        let rtos = match profile.rtos {
+            Some(rtos) => Some(rtos),
+            None if no_sdkconfig => None,
+            // The IDF defaults are what trips up new projects
+            None => Some(RtosTuning::default()),
+        };
+        ScaffoldOptions {
+            project_name,
+            language: profile.language.unwrap_or(ProgrammingLanguage::C),
+            use_git: profile.use_git.unwrap_or(false),
+            board: profile.board,
+            target: profile.target,
+            assertions: profile.assertions.unwrap_or_default(),
+            heap_debugging: profile.heap_debugging.unwrap_or_default(),
+            kconfig_example: profile.kconfig_example.unwrap_or(false),
+            keep_going: false,
+            post_process: PostProcess::Auto,
+            dir_mode: None,
+            max_template_size: None,
+            jobs: None,
+            template_cache: None,
+            template_ttl: TemplateTtl::default(),
+            offline: false,
+            assumed_idf_version: None,
+            template_archive: None,
+            template_sha256: None,
+            main_file: None,
+            app_class: false,
+            main_sources: None,
+            scaffold_only_config: false,
+            clang_format,
+            hygiene,
+            rtos,
+            partitions: profile.partitions,
+            security: profile.security.unwrap_or_default(),
+            component_metadata: profile.component_metadata,
+            host_test_asan: false,
+            bootloader_hooks: false,
+            flash_script: false,
+            flash_port: None,
+            ci: None,
+            no_badges: false,
+            no_gitignore: false,
+            no_sdkconfig,
+            workspace_apps: vec![],
+            relative_symlinks: false,
+            factory_test: false,
+            starters: profile.starters.unwrap_or_default(),
+            template_ref: profile.template_ref,
+            template_url: profile.template_url,
+            template_prefix: None,
+        }
+    }
+
+    /// The hygiene files to write, in the order of [`HygieneFile::ALL`]
+    pub fn hygiene_files(&self) -> Vec<HygieneFile> {
+        let mut files = self.hygiene.clone();
+        if self.clang_format.is_some() {
+            files.push(HygieneFile::ClangFormat);
+        }
+        // no_gitignore wins over a gitignore picked in a profile
+        if self.no_gitignore {
+            files.retain(|file| *file != HygieneFile::Gitignore);
+        }
+        files.sort_unstable();
+        files.dedup();
+        files
+    }
+
+    /// The template of the project, before it's downloaded
+    pub fn template_source(&self) -> TemplateSource<'_> {
+        TemplateSource {
+            url: self.template_url.as_deref(),
+            git_ref: self.template_ref.as_deref(),
+        }
+    }
+
+    /// URL of the template to download, see [`TemplateSource::url`]
+    pub fn template_url(&self) -> Cow<'_, str> {
+        self.template_source().url()
+    }
+
+    /// Largest template accepted in bytes, see [`ScaffoldOptions::max_template_size`]
+    pub fn max_template_size(&self) -> u64 {
+        self.max_template_size
+            .unwrap_or(download::DEFAULT_MAX_TEMPLATE_SIZE)
+    }
+
+    /// The selected development board, `None` for a custom board
+    ///
+    /// # Errors
+    /// If the board id is unknown
+    pub fn board(&self) -> anyhow::Result<Option<&'static boards::Board>> {
+        self.board.as_deref().map(boards::find).transpose()
+    }
+
+    /// The chip the project is built for: the one of the board, the target,
+    /// or [`crate::export_env::DEFAULT_TARGET`]
+    ///
+    /// # Errors
+    /// If the board is unknown
+    pub fn build_target(&self) -> anyhow::Result<String> {
+        Ok(match (self.board()?, &self.target) {
+            (Some(board), _) => board.target.to_string(),
+            (None, Some(target)) => target.clone(),
+            (None, None) => crate::export_env::DEFAULT_TARGET.to_string(),
+        })
+    }
+
+    /// The features of the project that need a minimum ESP-IDF version, see
+    /// [`crate::idf_version`]
+    ///
+    /// # Errors
+    /// If the board or a starter is unknown
+    pub fn idf_requirements(&self) -> anyhow::Result<Vec<IdfRequirement>> {
+        let mut requirements = Vec::new();
+        let mut require = |feature: String, version: Option<IdfVersion>| {
+            if let Some(version) = version {
+                requirements.push(IdfRequirement { feature, version });
+            }
+        };
+        let board = self.board()?;
+        let target = match board {
+            Some(board) => Some(board.target),
+            None => self.target.as_deref(),
+        };
+        if let Some(target) = target {
+            require(
+                format!("{} target", target),
+                boards::target_min_idf_version(target),
+            );
+        }
+        let starters = self.resolved_starters()?;
+        for starter in &starters {
+            require(
+                format!("{} starter", starter.id),
+                starter.min_idf_version.clone(),
+            );
+        }
+        let features = [
+            (
+                IdfFeature::ComponentMetadata,
+                self.component_metadata.is_some(),
+            ),
+            (IdfFeature::BootloaderHooks, self.bootloader_hooks),
+            (
+                IdfFeature::UsbSerialJtagConsole,
+                board.is_some_and(|board| board.console == Console::UsbSerialJtag),
+            ),
+        ];
+        for (feature, selected) in features {
+            if selected {
+                require(feature.to_string(), Some(feature.min_version()));
+            }
+        }
+        Ok(requirements)
+    }
+
+    /// The selected starters, in the order they are applied, see [`starters::resolve`]
+    ///
+    /// # Errors
+    /// If a starter is unknown, doesn't run on the chip of the board or target,
+    /// or needs a starter that isn't selected
+    pub fn resolved_starters(&self) -> anyhow::Result<Vec<&'static starters::Starter>> {
+        let target = match self.board()? {
+            Some(board) => Some(board.target),
+            None => self.target.as_deref(),
+        };
+        starters::resolve(&self.starters, target)
+    }
+
+    /// The starters of the factory test app, the ones of the project and the
+    /// console, see [`ScaffoldOptions::resolved_starters`]
+    ///
+    /// # Errors
+    /// If a starter is unknown or doesn't run on the target
+    pub fn factory_test_starters(&self) -> anyhow::Result<Vec<&'static starters::Starter>> {
+        let options = ScaffoldOptions {
+            starters: factory_test::starter_ids(&self.starters),
+            ..self.clone()
+        };
+        options.resolved_starters()
+    }
+
+    /// The sources the generation lists in `main/CMakeLists.txt`, in order:
+    /// the main file, the source of the application class layout, then the
+    /// sources of the starters
+    ///
+    /// Empty for a workspace or [`ScaffoldOptions::scaffold_only_config`],
+    /// whose main component is not written.
+    ///
+    /// # Errors
+    /// If a starter is unknown or doesn't run on the target
+    pub fn planned_main_sources(&self) -> anyhow::Result<Vec<String>> {
+        if !self.workspace_apps.is_empty() || self.scaffold_only_config {
+            return Ok(Vec::new());
+        }
+        let extension = match self.language {
+            ProgrammingLanguage::C => "c",
+            _ => "cpp",
+        };
+        let mut sources = vec![format!("main.{}", extension)];
+        if self.app_class {
+            sources.push(templates::APP_CLASS_SOURCE_FILE.to_string());
+        }
+        for starter in self.resolved_starters()? {
+            sources.extend(starter.sources().into_iter().map(String::from));
+        }
+        Ok(sources)
+    }
+
+    /// Parses the options from a JSON string
+    ///
+    /// # Arguments
+    /// * `json` - The JSON document to parse
+    ///
+    /// # Errors
+    /// If the JSON is malformed, a required field is missing or a field has an invalid value
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let options: ScaffoldOptions =
+            serde_json::from_str(json).context("Invalid project options")?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Reads the options from a JSON file
+    ///
+    /// # Arguments
+    /// * `path` - The path to the JSON file
+    ///
+    /// # Errors
+    /// If the file cannot be read or its contents are not valid options
+    pub fn from_json_file(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read_to_string(path)
+            .context(format!("Cannot read options file \"{}\"", path.display()))?;
+        Self::from_json(&json).context(format!("Cannot load options file \"{}\"", path.display()))
+    }
+
+    /// Checks the values that serde cannot validate by itself
+    ///
+    /// # Errors
+    /// If the project name is empty or cannot be used as a directory, or the
+    /// main file doesn't exist or its extension doesn't match the language
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.project_name.trim().is_empty() {
+            bail!("\"project_name\" cannot be empty");
+        }
+        ProjectNames::new(&self.project_name)?;
+        if let Some(target) = &self.target {
+            if !boards::TARGETS.contains(&target.as_str()) {
+                bail!(
+                    "Unknown target \"{}\", expected one of {}",
+                    target,
+                    boards::TARGETS.join(", ")
+                );
+            }
+        }
+        if let Some(board) = self.board()? {
+            if self
+                .target
+                .as_deref()
+                .is_some_and(|target| target != board.target)
+            {
+                bail!(
+                    "The {} is an {} board, it cannot target another chip",
+                    board.name,
+                    board.target
+                );
+            }
+            if let Some(layout) = &self.partitions {
+                if layout.flash_size_mb != board.flash_size_mb {
+                    bail!(
+                        "The {} has {}MB of flash, not {}MB",
+                        board.name,
+                        board.flash_size_mb,
+                        layout.flash_size_mb
+                    );
+                }
+            }
+        }
+        if let Some(git_ref) = &self.template_ref {
+            if self.template_url.is_some() {
+                bail!("\"template_ref\" only applies to the official template, it cannot be used with \"template_url\"");
+            }
+            template_ref::validate_ref(git_ref)?;
+        }
+        if let Some(prefix) = &self.template_prefix {
+            if platform::archive_entry_path(prefix).is_none() {
+                bail!(
+                    "Invalid template prefix \"{}\", expected a relative path inside the archive",
+                    prefix
+                );
+            }
+        }
+        if let Some(main_file) = &self.main_file {
+            self.validate_main_file(main_file)?;
+        }
+        if self.app_class {
+            if self.language == ProgrammingLanguage::C {
+                bail!("The application class layout needs C++");
+            }
+            if self.main_file.is_some() {
+                bail!("The application class layout needs the built-in main file");
+            }
+        }
+        if let Some(sources) = &self.main_sources {
+            self.validate_main_sources(sources)?;
+        }
+        if let Some(port) = &self.flash_port {
+            flash::validate_port(port)?;
+            if !self.flash_script {
+                bail!("\"flash_port\" is only used by the flash script, set \"flash_script\"");
+            }
+        }
+        if self.scaffold_only_config {
+            let sources = [
+                ("a main file", self.main_file.is_some()),
+                ("the application class layout", self.app_class),
+                ("the Kconfig example", self.kconfig_example),
+                ("starters", !self.starters.is_empty()),
+                ("a host test", self.host_test_asan),
+                ("bootloader hooks", self.bootloader_hooks),
+                ("a workspace", !self.workspace_apps.is_empty()),
+            ];
+            if let Some((feature, _)) = sources.iter().find(|(_, selected)| *selected) {
+                bail!(
+                    "\"scaffold_only_config\" only writes build-system files, it cannot be combined with {}",
+                    feature
+                );
+            }
+        }
+        if let Some(style) = &self.clang_format {
+            style.validate()?;
+        }
+        if let Some(rtos) = &self.rtos {
+            rtos.validate()?;
+        }
+        if let Some(layout) = &self.partitions {
+            layout.validate()?;
+        }
+        if let Some(metadata) = &self.component_metadata {
+            metadata.validate()?;
+        }
+        self.resolved_starters()?;
+        if !self.workspace_apps.is_empty() {
+            validate_workspace_apps(&self.workspace_apps)?;
+        } else if self.relative_symlinks {
+            bail!("Relative symbolic links are only used in a workspace");
+        }
+        if self.factory_test {
+            self.validate_factory_test()?;
+        }
+        Ok(())
+    }
+
+    fn validate_factory_test(&self) -> anyhow::Result<()> {
+        if self.workspace_apps.is_empty() {
+            bail!("The factory test app is only generated in a workspace");
+        }
+        if self
+            .workspace_apps
+            .iter()
+            .any(|app| app == FACTORY_TEST_APP)
+        {
+            bail!(
+                "The workspace app \"{}\" clashes with the factory test app",
+                FACTORY_TEST_APP
+            );
+        }
+        let layout = match &self.partitions {
+            Some(layout) => layout,
+            None => {
+                bail!("The factory test app needs a custom partition table to get its partition")
+            }
+        };
+        let partitions = parse_partitions(&partitions::with_test_partition(&layout.csv()));
+        partitions::check_fits(&partitions, layout.flash_size_mb)
+            .context("The partition of the factory test app doesn't fit")?;
+        self.factory_test_starters()?;
+        Ok(())
+    }
+
+    fn validate_main_sources(&self, sources: &[String]) -> anyhow::Result<()> {
+        if !self.workspace_apps.is_empty() || self.scaffold_only_config {
+            bail!("\"main_sources\" cannot be used with a workspace or \"scaffold_only_config\", the main component is not written");
+        }
+        if sources.is_empty() {
+            bail!("\"main_sources\" needs at least one source");
+        }
+        for (i, source) in sources.iter().enumerate() {
+            // Written to a CMake list, unquoted by CMake
+            let listable = !source.contains(|c: char| {
+                c.is_whitespace() || matches!(c, '"' | '(' | ')' | ';' | '$' | '\\')
+            });
+            if !listable || platform::archive_entry_path(source).is_none() {
+                bail!(
+                    "Invalid source \"{}\", expected a path inside main/ without spaces or quotes",
+                    source
+                );
+            }
+            if sources[..i].contains(source) {
+                bail!("The source \"{}\" is listed twice", source);
+            }
+        }
+        // The main file calls into the generated sources
+        if let Some(missing) = self
+            .planned_main_sources()?
+            .into_iter()
+            .find(|source| !sources.contains(source))
+        {
+            bail!(
+                "\"main_sources\" must list the generated source \"{}\"",
+                missing
+            );
+        }
+        Ok(())
+    }
+
+    fn validate_main_file(&self, main_file: &Path) -> anyhow::Result<()> {
+        if !main_file.is_file() {
+            bail!("Main file \"{}\" doesn't exist", main_file.display());
+        }
+        if self.kconfig_example {
+            bail!("The Kconfig example needs the built-in main file");
+        }
+        let extensions = self.language.source_extensions();
+        let extension = main_file
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension {
+            Some(extension) if extensions.contains(&extension.as_str()) => Ok(()),
+            _ => bail!(
+                "Main file \"{}\" doesn't match the language, expected a .{} file",
+                main_file.display(),
+                extensions.join(" or .")
+            ),
+        }
+    }
+}
+
+/// Sub-projects of a workspace when their names are not given
+pub const DEFAULT_WORKSPACE_APPS: &[&str] = &["app", "factory"];
+
+/// Directory of the components shared by the sub-projects of a workspace
+pub const WORKSPACE_COMPONENTS_DIR: &str = "components";
+
+/// Checks the names of the sub-projects of a workspace
+///
+/// # Arguments
+/// * `apps` - The names, each one is a directory at the workspace root
+///
+/// # Errors
+/// If there are less than two names, a name is not a plain directory name,
+/// is used twice or clashes with the shared components directory
+pub fn validate_workspace_apps(apps: &[String]) -> anyhow::Result<()> {
+    if apps.len() < 2 {
+        bail!("A workspace needs at least two apps, got {}", apps.len());
+    }
+    for (i, app) in apps.iter().enumerate() {
+        let names = ProjectNames::new(app)?;
+        if names.display != *app {
+            bail!(
+                "Workspace app \"{}\" must be a directory name, not a path",
+                app
+            );
+        }
+        if app == WORKSPACE_COMPONENTS_DIR {
+            bail!(
+                "Workspace app \"{}\" clashes with the shared components directory",
+                app
+            );
+        }
+        if apps[..i].contains(app) {
+            bail!("Workspace app \"{}\" is listed twice", app);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partitions::PartitionScheme;
+
+    #[test]
+    fn test_options_from_json() {
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "cpp17", "use_git": true}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            options,
+            ScaffoldOptions {
+                project_name: "blink".into(),
+                language: ProgrammingLanguage::Cpp17,
+                use_git: true,
+                board: None,
+                target: None,
+                assertions: AssertionLevel::Abort,
+                heap_debugging: HeapDebugging::Disabled,
+                kconfig_example: false,
+                keep_going: false,
+                post_process: PostProcess::Auto,
+                dir_mode: None,
+                max_template_size: None,
+                jobs: None,
+                template_cache: None,
+                template_ttl: TemplateTtl::default(),
+                offline: false,
+                assumed_idf_version: None,
+                template_archive: None,
+                template_sha256: None,
+                main_file: None,
+                app_class: false,
+                main_sources: None,
+                scaffold_only_config: false,
+                clang_format: None,
+                hygiene: vec![],
+                rtos: None,
+                security: SecurityFeatures::default(),
+                partitions: None,
+                component_metadata: None,
+                host_test_asan: false,
+                bootloader_hooks: false,
+                flash_script: false,
+                flash_port: None,
+                ci: None,
+                no_badges: false,
+                no_gitignore: false,
+                no_sdkconfig: false,
+                workspace_apps: vec![],
+                relative_symlinks: false,
+                factory_test: false,
+                starters: vec![],
+                template_ref: None,
+                template_url: None,
+                template_prefix: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_options_from_json_defaults_git() {
+        let options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        assert!(!options.use_git);
+        assert_eq!(options.assertions, AssertionLevel::Abort);
+        assert_eq!(options.heap_debugging, HeapDebugging::Disabled);
+    }
+
+    #[test]
+    fn test_heap_debugging() {
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "heap_debugging": "tracing"}"#,
+        )
+        .unwrap();
+        assert_eq!(options.heap_debugging, HeapDebugging::Tracing);
+        assert!(HeapDebugging::Disabled.config_keys().is_empty());
+        assert_eq!(
+            HeapDebugging::from(2).config_keys(),
+            ["CONFIG_HEAP_POISONING_COMPREHENSIVE"]
+        );
+    }
+
+    #[test]
+    fn test_options_from_json_invalid() {
+        // Missing required field
+        assert!(ScaffoldOptions::from_json(r#"{"project_name": "blink"}"#).is_err());
+        // Unknown language
+        assert!(
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "unknown"}"#)
+                .is_err()
+        );
+        // Empty name
+        assert!(ScaffoldOptions::from_json(r#"{"project_name": " ", "language": "c"}"#).is_err());
+        // Unknown starter
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "starters": ["zigbee"]}"#
+        )
+        .is_err());
+        // Starter that doesn't run on the chip of the board
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "board": "esp32-c3-devkitm-1", "starters": ["ethernet"]}"#
+        )
+        .is_err());
+        // Symbolic links outside a workspace
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "relative_symlinks": true}"#
+        )
+        .is_err());
+        // A factory test app outside a workspace, without a partition table,
+        // where an app is or whose partition doesn't fit
+        for factory_test in [
+            r#""partitions": {"flash_size_mb": 4, "scheme": "two_ota"}"#,
+            r#""workspace_apps": ["app", "factory"]"#,
+            r#""workspace_apps": ["app", "factory_test"], "partitions": {"flash_size_mb": 4, "scheme": "two_ota"}"#,
+            r#""workspace_apps": ["app", "factory"], "partitions": {"flash_size_mb": 4, "scheme": "factory_two_ota"}"#,
+        ] {
+            let json = format!(
+                r#"{{"project_name": "blink", "language": "c", "factory_test": true, {}}}"#,
+                factory_test
+            );
+            assert!(ScaffoldOptions::from_json(&json).is_err(), "{}", json);
+        }
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "factory_test": true, "workspace_apps": ["app", "factory"], "partitions": {"flash_size_mb": 4, "scheme": "two_ota"}, "starters": ["led"]}"#,
+        )
+        .unwrap();
+        let starters: Vec<&str> = options
+            .factory_test_starters()
+            .unwrap()
+            .iter()
+            .map(|starter| starter.id)
+            .collect();
+        assert_eq!(starters, ["led", "console"]);
+        // Malformed JSON
+        assert!(ScaffoldOptions::from_json(r#"{"project_name": "blink","#).is_err());
+    }
+
+    #[test]
+    fn test_options_clang_format() {
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "clang_format": {"indent_width": 2}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            options.clang_format,
+            Some(ClangFormatStyle {
+                indent_width: 2,
+                column_limit: 100,
+            })
+        );
+
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "clang_format": {"column_limit": 90}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_options_rtos() {
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "rtos": {"main_task_stack_size": 8192}}"#,
+        )
+        .unwrap();
+        let rtos = options.rtos.unwrap();
+        assert_eq!(rtos.tick_rate_hz, 1000);
+        assert_eq!(
+            rtos.sdkconfig(),
+            vec![
+                ("CONFIG_FREERTOS_HZ".to_string(), "1000".to_string()),
+                (
+                    "CONFIG_ESP_MAIN_TASK_STACK_SIZE".to_string(),
+                    "8192".to_string()
+                ),
+            ]
+        );
+
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "rtos": {"tick_rate_hz": 2000}}"#
+        )
+        .is_err());
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "rtos": {"main_task_stack_size": 1024}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_options_security() {
+        let options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        assert!(options.security.is_disabled());
+        assert!(options.security.sdkconfig().is_empty());
+        assert!(serde_json::to_value(&options)
+            .unwrap()
+            .get("security")
+            .is_none());
+
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "security": {"flash_encryption": true}}"#,
+        )
+        .unwrap();
+        let entries = options.security.sdkconfig();
+        let keys: Vec<&str> = entries.iter().map(|(key, _, _)| key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "CONFIG_SECURE_FLASH_ENC_ENABLED",
+                "CONFIG_SECURE_FLASH_ENCRYPTION_MODE_DEVELOPMENT"
+            ]
+        );
+        assert!(entries[0].2.contains("IRREVERSIBLE"));
+        assert!(entries[0].2.contains(FLASH_ENCRYPTION_DOCS_URL));
+    }
+
+    #[test]
+    fn test_options_component_metadata() {
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "component_metadata": {"license": "MIT"}}"#,
+        )
+        .unwrap();
+        let metadata = options.component_metadata.unwrap();
+        assert_eq!(metadata.version, "0.1.0");
+        assert_eq!(metadata.license.as_deref(), Some("MIT"));
+
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "component_metadata": {"version": "1"}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_options_partitions() {
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "partitions": {"flash_size_mb": 4, "scheme": "two_ota"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            options.partitions,
+            Some(PartitionLayout {
+                flash_size_mb: 4,
+                scheme: PartitionScheme::TwoOta,
+                app_size_kb: None,
+            })
+        );
+
+        // Three 1MB apps don't fit in 2MB
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "partitions": {"flash_size_mb": 2, "scheme": "factory_two_ota"}}"#
+        )
+        .is_err());
+        // Nor two 2MB OTA slots in 4MB
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "partitions": {"flash_size_mb": 4, "scheme": "two_ota", "app_size_kb": 2048}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_options_board() {
+        let board = |json: &str| {
+            ScaffoldOptions::from_json(&format!(
+                r#"{{"project_name": "blink", "language": "c", {}}}"#,
+                json
+            ))
+        };
+        assert!(board(r#""board": "esp32-c3-devkitm-1", "target": "esp32c3""#).is_ok());
+        assert!(board(r#""target": "esp32c6""#).is_ok());
+        assert!(board(r#""board": "arduino-uno""#).is_err());
+        assert!(board(r#""target": "esp8266""#).is_err());
+        assert!(board(r#""board": "esp32-c3-devkitm-1", "target": "esp32s3""#).is_err());
+        assert!(board(
+            r#""board": "esp32-devkitc", "partitions": {"flash_size_mb": 8, "scheme": "two_ota"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_idf_requirements() {
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "target": "esp32c6", "starters": ["wifi", "mqtt"], "bootloader_hooks": true}"#,
+        )
+        .unwrap();
+        let requirements = options.idf_requirements().unwrap();
+        let features: Vec<_> = requirements
+            .iter()
+            .map(|requirement| {
+                (
+                    requirement.feature.as_str(),
+                    requirement.version.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            features,
+            [
+                ("esp32c6 target", "5.1".to_string()),
+                ("mqtt starter", "5.0".to_string()),
+                ("bootloader hooks", "4.4".to_string()),
+            ]
+        );
+        assert_eq!(
+            crate::idf_version::minimum(&requirements),
+            Some(&IdfVersion::new(5, 1))
+        );
+
+        let options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        assert!(options.idf_requirements().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_workspace_apps() {
+        let apps = |names: &[&str]| -> Vec<String> { names.iter().map(|&n| n.into()).collect() };
+        assert!(validate_workspace_apps(&apps(&["app", "factory"])).is_ok());
+        assert!(validate_workspace_apps(&apps(&["app"])).is_err());
+        assert!(validate_workspace_apps(&apps(&["app", "app"])).is_err());
+        assert!(validate_workspace_apps(&apps(&["app", "tools/app"])).is_err());
+        assert!(validate_workspace_apps(&apps(&["app", "components"])).is_err());
+        assert!(validate_workspace_apps(&apps(&["app", ".."])).is_err());
+    }
+
+    #[test]
+    fn test_validate_main_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let c_file = dir.path().join("app.c");
+        fs::write(&c_file, "").unwrap();
+        let mut options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+
+        options.main_file = Some(c_file.clone());
+        assert!(options.validate().is_ok());
+
+        options.language = ProgrammingLanguage::Cpp17;
+        assert!(options.validate().is_err());
+
+        options.main_file = Some(dir.path().join("missing.cpp"));
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn test_post_process() {
+        for mode in PostProcess::ALL {
+            assert_eq!(mode.to_string().parse::<PostProcess>(), Ok(*mode));
+        }
+        assert!("partial".parse::<PostProcess>().is_err());
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "post_process": "none"}"#,
+        )
+        .unwrap();
+        assert_eq!(options.post_process, PostProcess::None);
+    }
+
+    #[test]
+    fn test_validate_scaffold_only_config() {
+        let mut options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        options.scaffold_only_config = true;
+        assert!(options.validate().is_ok());
+
+        options.starters = vec!["nvs".into()];
+        let error = options.validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "\"scaffold_only_config\" only writes build-system files, it cannot be combined with starters"
+        );
+    }
+
+    #[test]
+    fn test_validate_app_class() {
+        let mut options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        assert!(!options.app_class);
+        options.app_class = true;
+        assert!(options.validate().is_err());
+
+        options.language = ProgrammingLanguage::Cpp17;
+        assert!(options.validate().is_ok());
+
+        options.main_file = Some(PathBuf::from("app.cpp"));
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_main_sources() {
+        let mut options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "main_sources": ["main.c", "drivers/uart.c"]}"#,
+        )
+        .unwrap();
+        assert_eq!(options.planned_main_sources().unwrap(), ["main.c"]);
+
+        for invalid in [
+            vec![],
+            vec!["main.c", "main.c"],
+            vec!["../main.c"],
+            vec!["my file.c"],
+            vec!["\"main.c\""],
+            // The generated main file
+            vec!["drivers/uart.c"],
+        ] {
+            options.main_sources = Some(invalid.iter().map(|s| s.to_string()).collect());
+            assert!(options.validate().is_err(), "{:?}", invalid);
+        }
+
+        options.starters = vec!["nvs".into()];
+        options.main_sources = Some(vec!["main.c".into()]);
+        assert!(options.validate().is_err());
+        options.main_sources = Some(vec!["starter_nvs.c".into(), "main.c".into()]);
+        options.validate().unwrap();
+
+        options.starters.clear();
+        options.main_sources = Some(vec!["main.c".into()]);
+        options.scaffold_only_config = true;
+        assert!(options.validate().is_err());
+        assert!(options.planned_main_sources().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_flash_port() {
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "flash_port": "/dev/ttyUSB0"}"#,
+        )
+        .is_err());
+
+        let mut options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "flash_script": true, "flash_port": "/dev/ttyUSB0"}"#,
+        )
+        .unwrap();
+        assert!(options.flash_script);
+
+        options.flash_port = Some("COM3'; rm -rf ~".into());
+        assert!(options.validate().is_err());
+
+        let options = ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "ci": "gitlab"}"#,
+        )
+        .unwrap();
+        assert_eq!(options.ci, Some(CiProvider::GitLab));
+        assert!(ScaffoldOptions::from_json(
+            r#"{"project_name": "blink", "language": "c", "ci": "jenkins"}"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_programming_language_conversion() {
+        let c_language = 0;
+        let c_language_enum = ProgrammingLanguage::from(c_language);
+
+        assert_eq!(c_language_enum, ProgrammingLanguage::C);
+
+        let cpp11_language = 1;
+        let cpp11_language_enum = ProgrammingLanguage::from(cpp11_language);
+        assert_eq!(cpp11_language_enum, ProgrammingLanguage::Cpp11);
+
+        let cpp14_language = 2;
+        let cpp14_language_enum = ProgrammingLanguage::from(cpp14_language);
+        assert_eq!(cpp14_language_enum, ProgrammingLanguage::Cpp14);
+
+        let cpp17_language = 3;
+        let cpp17_language_enum = ProgrammingLanguage::from(cpp17_language);
+        assert_eq!(cpp17_language_enum, ProgrammingLanguage::Cpp17);
+    }
+
+    #[test]
+    fn test_programming_language_conversion_unknown() {
+        let unknown_language = 4;
+        let unknown_language_enum = ProgrammingLanguage::from(unknown_language);
+        assert_eq!(unknown_language_enum, ProgrammingLanguage::Unknown);
+    }
+}
@@ -0,0 +1,541 @@
+//! Custom partition tables: built-in schemes for the generated `partitions.csv`
+//! and the check that a table fits in the flash
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::project_model::{parse_partitions, Partition};
+use crate::sdkconfig::SdkConfigDefaults;
+
+/// Name of the generated partition table, relative to the project
+pub const PARTITIONS_FILE: &str = "partitions.csv";
+
+/// Flash sizes that can be selected, in MB
+pub const FLASH_SIZES: &[u32] = &[2, 4, 8, 16];
+
+/// App partition sizes offered in the prompt, in KB
+pub const APP_SIZES_KB: &[u32] = &[1536, 2048, 3072, 4096, 6144, 8192];
+
+/// Offset of the first partition, after the bootloader and the partition
+/// table (`CONFIG_PARTITION_TABLE_OFFSET` is 0x8000 by default)
+const FIRST_PARTITION_OFFSET: u64 = 0x9000;
+
+/// Alignment of the app partitions
+const APP_ALIGNMENT: u64 = 0x10000;
+
+/// Alignment of the data partitions
+const DATA_ALIGNMENT: u64 = 0x1000;
+
+/// Layout of the partition table, from the smallest to the most capable
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionScheme {
+    /// A 1MB factory app
+    SingleApp,
+    /// A 1.5MB factory app
+    SingleAppLarge,
+    /// Two 1MB OTA slots, no factory app
+    TwoOta,
+    /// A 1MB factory app and two 1MB OTA slots
+    FactoryTwoOta,
+}
+
+impl PartitionScheme {
+    /// Every scheme, from the smallest to the most capable
+    pub const ALL: &'static [PartitionScheme] = &[
+        PartitionScheme::SingleApp,
+        PartitionScheme::SingleAppLarge,
+        PartitionScheme::TwoOta,
+        PartitionScheme::FactoryTwoOta,
+    ];
+
+    /// Short description shown in the prompts
+    pub fn description(self) -> &'static str {
+        match self {
+            PartitionScheme::SingleApp => "Single app (1MB factory)",
+            PartitionScheme::SingleAppLarge => "Single large app (1.5MB factory)",
+            PartitionScheme::TwoOta => "OTA (two 1MB slots)",
+            PartitionScheme::FactoryTwoOta => "Factory app and OTA (three 1MB apps)",
+        }
+    }
+
+    /// The `partitions.csv` of the scheme
+    pub fn csv(self) -> &'static str {
+        match self {
+            PartitionScheme::SingleApp => SINGLE_APP_CSV,
+            PartitionScheme::SingleAppLarge => SINGLE_APP_LARGE_CSV,
+            PartitionScheme::TwoOta => TWO_OTA_CSV,
+            PartitionScheme::FactoryTwoOta => FACTORY_TWO_OTA_CSV,
+        }
+    }
+
+    /// The partitions of the scheme
+    pub fn partitions(self) -> Vec<Partition> {
+        parse_partitions(self.csv())
+    }
+
+    /// The most capable scheme that fits in the flash, the smallest one if none fits
+    ///
+    /// # Arguments
+    /// * `flash_size_mb` - The flash size in MB
+    pub fn largest_fitting(flash_size_mb: u32) -> PartitionScheme {
+        PartitionScheme::largest_fitting_apps(flash_size_mb, None)
+    }
+
+    /// The most capable scheme that fits in the flash with apps of this
+    /// size, the smallest one if none fits
+    ///
+    /// # Arguments
+    /// * `flash_size_mb` - The flash size in MB
+    /// * `app_size_kb` - The size of each app partition, the one of the scheme if `None`
+    pub fn largest_fitting_apps(flash_size_mb: u32, app_size_kb: Option<u32>) -> PartitionScheme {
+        PartitionScheme::ALL
+            .iter()
+            .rev()
+            .copied()
+            .find(|&scheme| {
+                let layout = PartitionLayout {
+                    flash_size_mb,
+                    scheme,
+                    app_size_kb,
+                };
+                check_fits(&layout.partitions(), flash_size_mb).is_ok()
+            })
+            .unwrap_or(PartitionScheme::SingleApp)
+    }
+}
+
+impl fmt::Display for PartitionScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PartitionScheme::SingleApp => "single_app",
+            PartitionScheme::SingleAppLarge => "single_app_large",
+            PartitionScheme::TwoOta => "two_ota",
+            PartitionScheme::FactoryTwoOta => "factory_two_ota",
+        })
+    }
+}
+
+impl std::str::FromStr for PartitionScheme {
+    type Err = String;
+
+    fn from_str(scheme: &str) -> Result<Self, Self::Err> {
+        PartitionScheme::ALL
+            .iter()
+            .copied()
+            .find(|candidate| candidate.to_string() == scheme)
+            .ok_or_else(|| {
+                let names: Vec<String> =
+                    PartitionScheme::ALL.iter().map(|s| s.to_string()).collect();
+                format!(
+                    "unknown partition scheme \"{}\", expected one of {}",
+                    scheme,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+const SINGLE_APP_CSV: &str = "# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+";
+
+const SINGLE_APP_LARGE_CSV: &str = "# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x6000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1536K,
+";
+
+const TWO_OTA_CSV: &str = "# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x4000,
+otadata,  data, ota,     ,        0x2000,
+phy_init, data, phy,     ,        0x1000,
+ota_0,    app,  ota_0,   ,        1M,
+ota_1,    app,  ota_1,   ,        1M,
+";
+
+const FACTORY_TWO_OTA_CSV: &str = "# Name,   Type, SubType, Offset,  Size, Flags
+nvs,      data, nvs,     ,        0x4000,
+otadata,  data, ota,     ,        0x2000,
+phy_init, data, phy,     ,        0x1000,
+factory,  app,  factory, ,        1M,
+ota_0,    app,  ota_0,   ,        1M,
+ota_1,    app,  ota_1,   ,        1M,
+";
+
+/// Custom partition table added to the project
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartitionLayout {
+    /// Flash size in MB, one of [`FLASH_SIZES`]
+    pub flash_size_mb: u32,
+    pub scheme: PartitionScheme,
+    /// Size in KB of every app partition (factory and OTA slots), a multiple
+    /// of 64; the size of the scheme if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_size_kb: Option<u32>,
+}
+
+impl PartitionLayout {
+    /// Checks the flash size, the app size and that the partitions fit in the flash
+    ///
+    /// # Errors
+    /// If the flash size is not supported, the app size is not a multiple of
+    /// 64KB or the partitions exceed the flash
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !FLASH_SIZES.contains(&self.flash_size_mb) {
+            bail!(
+                "Unsupported flash size {}MB, expected one of {:?}",
+                self.flash_size_mb,
+                FLASH_SIZES
+            );
+        }
+        match self.app_size_kb {
+            Some(app_size_kb) => {
+                check_app_size(u64::from(app_size_kb) * 1024)?;
+                check_fits(&self.partitions(), self.flash_size_mb).context(format!(
+                    "The \"{}\" partition scheme with {}KB apps doesn't fit",
+                    self.scheme, app_size_kb
+                ))
+            }
+            None => check_fits(&self.partitions(), self.flash_size_mb).context(format!(
+                "The \"{}\" partition scheme doesn't fit",
+                self.scheme
+            )),
+        }
+    }
+
+    /// The `partitions.csv` of the scheme, with the app partitions resized
+    /// to [`PartitionLayout::app_size_kb`]
+    pub fn csv(&self) -> String {
+        let app_size_kb = match self.app_size_kb {
+            Some(app_size_kb) => app_size_kb,
+            None => return self.scheme.csv().to_string(),
+        };
+        let mut csv = String::new();
+        for line in self.scheme.csv().lines() {
+            let mut fields: Vec<String> = line.split(',').map(str::to_string).collect();
+            if !line.starts_with('#') && fields.len() > 4 && fields[1].trim() == "app" {
+                // Keeps the column alignment of the original size
+                let indent = fields[4].len() - fields[4].trim_start().len();
+                fields[4] = format!("{}{}K", &fields[4][..indent], app_size_kb);
+            }
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// The partitions of the table, see [`PartitionLayout::csv`]
+    pub fn partitions(&self) -> Vec<Partition> {
+        parse_partitions(&self.csv())
+    }
+
+    /// The `sdkconfig.defaults` entries that select the table and the flash size
+    pub fn sdkconfig(&self) -> Vec<(String, String)> {
+        vec![
+            (
+                format!("CONFIG_ESPTOOLPY_FLASHSIZE_{}MB", self.flash_size_mb),
+                "y".into(),
+            ),
+            ("CONFIG_PARTITION_TABLE_CUSTOM".into(), "y".into()),
+            (
+                "CONFIG_PARTITION_TABLE_CUSTOM_FILENAME".into(),
+                format!("\"{}\"", PARTITIONS_FILE),
+            ),
+        ]
+    }
+}
+
+/// Name and subtype of the partition of the factory test app, the bootloader
+/// starts it instead of the main app when `CONFIG_BOOTLOADER_APP_TEST` is set
+/// and its GPIO is held at reset
+pub const TEST_PARTITION: &str = "test";
+
+/// Appends the partition of the factory test app to a partition table, as
+/// large as its first app partition
+///
+/// # Arguments
+/// * `csv` - The partition table, e.g. [`PartitionLayout::csv`]
+pub fn with_test_partition(csv: &str) -> String {
+    let size = parse_partitions(csv)
+        .into_iter()
+        .find(|partition| partition.kind == "app")
+        .map_or_else(|| "1M".to_string(), |partition| partition.size);
+    let mut csv = csv.to_string();
+    if !csv.is_empty() && !csv.ends_with('\n') {
+        csv.push('\n');
+    }
+    csv.push_str(&format!(
+        "{:<9} app,  {:<8} ,        {},\n",
+        format!("{},", TEST_PARTITION),
+        format!("{},", TEST_PARTITION),
+        size
+    ));
+    csv
+}
+
+/// Reads the flash size selected in a configuration, either the
+/// `CONFIG_ESPTOOLPY_FLASHSIZE_<n>MB=y` choice or `CONFIG_ESPTOOLPY_FLASHSIZE="<n>MB"`
+///
+/// # Arguments
+/// * `sdkconfig` - The entries of `sdkconfig.defaults` or `sdkconfig`
+///
+/// # Returns
+/// The flash size in MB, `None` if it is not set
+pub fn flash_size_mb(sdkconfig: &SdkConfigDefaults) -> Option<u32> {
+    let megabytes = |value: &str| value.strip_suffix("MB")?.parse().ok();
+    sdkconfig
+        .get("CONFIG_ESPTOOLPY_FLASHSIZE")
+        .and_then(|value| megabytes(value.trim_matches('"')))
+        .or_else(|| {
+            sdkconfig.entries().iter().find_map(|(key, value)| {
+                let size = key.strip_prefix("CONFIG_ESPTOOLPY_FLASHSIZE_")?;
+                if value != "y" {
+                    return None;
+                }
+                megabytes(size)
+            })
+        })
+}
+
+/// Parses a partition size or offset: hexadecimal (`0x6000`), decimal, or
+/// decimal with a `K` or `M` suffix (`24K`, `1M`)
+///
+/// # Arguments
+/// * `size` - The field of the partition table
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    if let Some(hex) = size.strip_prefix("0x").or_else(|| size.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).ok();
+    }
+    let (number, multiplier) = match size.char_indices().last()? {
+        (i, 'K') | (i, 'k') => (&size[..i], 1024),
+        (i, 'M') | (i, 'm') => (&size[..i], 1024 * 1024),
+        _ => (size, 1),
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses the size of the app partitions given on the command line, e.g.
+/// `2M`, `1536K` or `0x200000`
+///
+/// # Returns
+/// The size in KB
+///
+/// # Errors
+/// If the size cannot be parsed or it's not a multiple of 64KB
+pub fn parse_app_size(size: &str) -> Result<u32, String> {
+    let bytes = parse_size(size).ok_or_else(|| {
+        format!(
+            "invalid size \"{}\", expected e.g. 2M, 1536K or 0x200000",
+            size
+        )
+    })?;
+    check_app_size(bytes).map_err(|e| e.to_string())?;
+    u32::try_from(bytes / 1024).map_err(|_| format!("size \"{}\" is too large", size))
+}
+
+/// Checks that an app partition size can be used, in bytes
+fn check_app_size(bytes: u64) -> anyhow::Result<()> {
+    if bytes == 0 || !bytes.is_multiple_of(APP_ALIGNMENT) {
+        bail!(
+            "the app partition size must be a non-zero multiple of 64KB, not {}KB",
+            bytes as f64 / 1024.0
+        );
+    }
+    Ok(())
+}
+
+/// Computes where the last partition ends, placing the partitions without
+/// an offset after the previous one like the IDF build does
+///
+/// # Arguments
+/// * `partitions` - The rows of the partition table
+///
+/// # Errors
+/// If a size or offset cannot be parsed, or a partition ends past the
+/// largest address
+pub fn layout_end(partitions: &[Partition]) -> anyhow::Result<u64> {
+    let mut next = FIRST_PARTITION_OFFSET;
+    let mut end = next;
+    for partition in partitions {
+        let size = parse_size(&partition.size).context(format!(
+            "Invalid size \"{}\" of partition \"{}\"",
+            partition.size, partition.name
+        ))?;
+        let offset = if partition.offset.is_empty() {
+            let alignment = if partition.kind == "app" {
+                APP_ALIGNMENT
+            } else {
+                DATA_ALIGNMENT
+            };
+            next.div_ceil(alignment)
+                .checked_mul(alignment)
+                .context(format!("Partition \"{}\" is out of range", partition.name))?
+        } else {
+            parse_size(&partition.offset).context(format!(
+                "Invalid offset \"{}\" of partition \"{}\"",
+                partition.offset, partition.name
+            ))?
+        };
+        next = match offset.checked_add(size) {
+            Some(next) => next,
+            None => bail!(
+                "Partition \"{}\" ends past the largest address: offset {:#x}, size {:#x}",
+                partition.name,
+                offset,
+                size
+            ),
+        };
+        end = end.max(next);
+    }
+    Ok(end)
+}
+
+/// Checks that the partitions fit in the flash
+///
+/// # Arguments
+/// * `partitions` - The rows of the partition table
+/// * `flash_size_mb` - The flash size in MB
+///
+/// # Errors
+/// If the partitions end past the flash size, or a size cannot be parsed
+pub fn check_fits(partitions: &[Partition], flash_size_mb: u32) -> anyhow::Result<()> {
+    let flash_size = u64::from(flash_size_mb) * 1024 * 1024;
+    let end = layout_end(partitions)?;
+    if end > flash_size {
+        bail!(
+            "the partitions end at {:#x}, {}KB past the end of the {}MB flash",
+            end,
+            (end - flash_size).div_ceil(1024),
+            flash_size_mb
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0x6000"), Some(0x6000));
+        assert_eq!(parse_size("24K"), Some(24 * 1024));
+        assert_eq!(parse_size("1M"), Some(1024 * 1024));
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("1G"), None);
+    }
+
+    #[test]
+    fn test_with_test_partition() {
+        let layout = PartitionLayout {
+            flash_size_mb: 8,
+            scheme: PartitionScheme::TwoOta,
+            app_size_kb: Some(2048),
+        };
+        let csv = with_test_partition(&layout.csv());
+        assert!(csv.starts_with(&layout.csv()));
+        assert!(csv.ends_with(
+            "ota_1,    app,  ota_1,   ,        2048K,\ntest,     app,  test,    ,        2048K,\n"
+        ));
+        let test = parse_partitions(&csv).pop().unwrap();
+        assert_eq!((test.kind.as_str(), test.subtype.as_str()), ("app", "test"));
+        assert!(check_fits(&parse_partitions(&csv), 8).is_ok());
+        assert!(check_fits(&parse_partitions(&csv), 4).is_err());
+    }
+
+    #[test]
+    fn test_check_fits() {
+        // 0x9000 + nvs, otadata, phy_init, then two 1MB apps from 0x10000
+        let two_ota = PartitionScheme::TwoOta.partitions();
+        assert_eq!(layout_end(&two_ota).unwrap(), 0x210000);
+        assert!(check_fits(&two_ota, 4).is_ok());
+        let error = check_fits(&two_ota, 2).unwrap_err().to_string();
+        assert!(error.contains("64KB past the end of the 2MB flash"));
+
+        // Explicit offsets are kept
+        let partitions = parse_partitions("storage, data, spiffs, 0x3f0000, 64K\n");
+        assert_eq!(layout_end(&partitions).unwrap(), 0x400000);
+        assert!(check_fits(&partitions, 4).is_ok());
+
+        // An end that overflows is an error, not a panic
+        let partitions = parse_partitions("storage, data, spiffs, 0xffffffffffff0000, 0x20000\n");
+        let error = layout_end(&partitions).unwrap_err().to_string();
+        assert!(error.contains("ends past the largest address"), "{}", error);
+        assert!(check_fits(&partitions, 4).is_err());
+    }
+
+    #[test]
+    fn test_largest_fitting() {
+        assert_eq!(
+            PartitionScheme::largest_fitting(2),
+            PartitionScheme::SingleAppLarge
+        );
+        assert_eq!(
+            PartitionScheme::largest_fitting(4),
+            PartitionScheme::FactoryTwoOta
+        );
+        assert!(PartitionLayout {
+            flash_size_mb: 2,
+            scheme: PartitionScheme::FactoryTwoOta,
+            app_size_kb: None,
+        }
+        .validate()
+        .is_err());
+        assert!(PartitionLayout {
+            flash_size_mb: 3,
+            scheme: PartitionScheme::SingleApp,
+            app_size_kb: None,
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_app_size() {
+        assert_eq!(parse_app_size("2M"), Ok(2048));
+        assert_eq!(parse_app_size("0x180000"), Ok(1536));
+        assert!(parse_app_size("1000K")
+            .unwrap_err()
+            .contains("multiple of 64KB"));
+        assert!(parse_app_size("big").is_err());
+
+        let layout = PartitionLayout {
+            flash_size_mb: 8,
+            scheme: PartitionScheme::TwoOta,
+            app_size_kb: Some(3072),
+        };
+        assert!(layout
+            .csv()
+            .contains("ota_0,    app,  ota_0,   ,        3072K,\n"));
+        assert!(layout
+            .csv()
+            .contains("otadata,  data, ota,     ,        0x2000,\n"));
+        // Two 3MB slots from 0x10000
+        assert_eq!(layout_end(&layout.partitions()).unwrap(), 0x610000);
+        assert!(layout.validate().is_ok());
+
+        let too_large = PartitionLayout {
+            flash_size_mb: 4,
+            ..layout
+        };
+        let error = format!("{:#}", too_large.validate().unwrap_err());
+        assert!(error.contains("with 3072KB apps doesn't fit"), "{}", error);
+        // The single app schemes only differ by their app size, both fit
+        assert_eq!(
+            PartitionScheme::largest_fitting_apps(4, Some(3072)),
+            PartitionScheme::SingleAppLarge
+        );
+        assert_eq!(
+            PartitionScheme::largest_fitting_apps(8, Some(2048)),
+            PartitionScheme::FactoryTwoOta
+        );
+    }
+}
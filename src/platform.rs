@@ -0,0 +1,548 @@
+//! Platform differences (process spawning, archive paths and file
+//! permissions) kept in one place so the rest of the code doesn't need `cfg`s
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Finds an executable on the `PATH`
+///
+/// On Windows every extension listed in `PATHEXT` is tried, so tools installed
+/// as `.cmd` or `.bat` shims (e.g. some git distributions) are found too.
+///
+/// # Arguments
+/// * `program` - The name of the program, without extension
+///
+/// # Returns
+/// The full path to the executable, or `None` if it isn't on the `PATH`
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    find_executable_in(program, &path)
+}
+
+/// Finds an executable in the given `PATH`-like list of directories
+///
+/// On Unix a file without an executable bit is skipped, like the shell does.
+///
+/// # Arguments
+/// * `program` - The name of the program, without extension
+/// * `path` - The list of directories, separated by the platform separator
+pub fn find_executable_in(program: &str, path: &OsString) -> Option<PathBuf> {
+    env::split_paths(path).find_map(|dir| {
+        executable_names(program)
+            .into_iter()
+            .map(|name| dir.join(name))
+            .find(|candidate| is_executable(candidate))
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(windows)]
+fn executable_names(program: &str) -> Vec<String> {
+    let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+    extensions
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| format!("{}{}", program, ext.to_lowercase()))
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn executable_names(program: &str) -> Vec<String> {
+    vec![program.to_string()]
+}
+
+/// Creates a `Command` for an external tool, resolving it through the `PATH`
+///
+/// Falls back to the bare program name when it cannot be resolved, so the
+/// spawn error still mentions the missing tool.
+///
+/// # Arguments
+/// * `program` - The name of the program, without extension
+pub fn command(program: &str) -> Command {
+    match find_executable(program) {
+        Some(path) => Command::new(path),
+        None => Command::new(program),
+    }
+}
+
+/// Converts the name of an archive entry to a relative native path
+///
+/// Archive entries always use `/` as separator, but archives created on Windows
+/// sometimes contain `\` too. Both are accepted and the result uses the native
+/// separator.
+///
+/// # Arguments
+/// * `name` - The entry name as stored in the archive
+///
+/// # Returns
+/// The relative path, or `None` if the entry is absolute, has a drive prefix or
+/// escapes the extraction directory with `..`
+pub fn archive_entry_path(name: &str) -> Option<PathBuf> {
+    if name.starts_with('/') || name.starts_with('\\') {
+        return None;
+    }
+
+    let mut path = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return None,
+            // Drive prefixes such as `C:`
+            part if part.contains(':') => return None,
+            part => path.push(part),
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// Applies the permissions stored in an archive to an extracted file
+///
+/// Only the executable bit matters (e.g. scripts), and only Unix stores
+/// meaningful modes, elsewhere this does nothing.
+///
+/// # Arguments
+/// * `file` - The extracted file
+/// * `mode` - The Unix mode stored in the archive, if any
+#[cfg(unix)]
+pub fn set_file_mode(file: &File, mode: Option<u32>) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match mode {
+        // Files are created with 0o666 & umask, skip the syscall when nothing would change
+        Some(mode) if mode & 0o111 != 0 => {
+            file.set_permissions(std::fs::Permissions::from_mode(mode & 0o777))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Applies the permissions stored in an archive to an extracted file
+///
+/// Only the executable bit matters (e.g. scripts), and only Unix stores
+/// meaningful modes, elsewhere this does nothing.
+///
+/// # Arguments
+/// * `file` - The extracted file
+/// * `mode` - The Unix mode stored in the archive, if any
+#[cfg(not(unix))]
+pub fn set_file_mode(_file: &File, _mode: Option<u32>) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Parses a permission mode written in octal, e.g. `750`, `0750` or `0o750`
+///
+/// # Errors
+/// If the mode is not an octal number up to `7777`
+pub fn parse_mode(mode: &str) -> Result<u32, String> {
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if !digits.is_empty() && mode <= 0o7777 => Ok(mode),
+        _ => Err(format!(
+            "\"{}\" is not an octal permission mode (e.g. 750)",
+            mode
+        )),
+    }
+}
+
+/// Creates a directory and its missing parents, applying the mode to every
+/// directory created
+///
+/// The mode is applied after creation, so it is not affected by the umask.
+/// Directories that already exist are left untouched. Only Unix has modes,
+/// elsewhere the mode is ignored.
+///
+/// # Arguments
+/// * `dir` - The directory to create
+/// * `mode` - The permissions of the created directories, the system default if `None`
+pub fn create_dir_all_with_mode(dir: &Path, mode: Option<u32>) -> std::io::Result<()> {
+    let mode = match mode {
+        Some(mode) if cfg!(unix) => mode,
+        _ => return std::fs::create_dir_all(dir),
+    };
+    let missing: Vec<&Path> = dir.ancestors().take_while(|d| !d.exists()).collect();
+    for dir in missing.into_iter().rev() {
+        match std::fs::create_dir(dir) {
+            Ok(()) => set_dir_mode(dir, mode)?,
+            // Created concurrently
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_dir_mode(dir: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_dir_mode(_dir: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Creates a symbolic link to a directory
+///
+/// Windows only allows it with developer mode or as administrator, the
+/// error is returned otherwise so the caller can copy the directory instead.
+///
+/// # Arguments
+/// * `target` - The directory the link points to, relative to the link's directory
+/// * `link` - The link to create
+#[cfg(unix)]
+pub fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn symlink_dir(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn symlink_dir(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    ))
+}
+
+/// How the process runs with elevated rights, for messages
+#[cfg(windows)]
+pub const ELEVATED: &str = "as administrator";
+/// How the process runs with elevated rights, for messages
+#[cfg(not(windows))]
+pub const ELEVATED: &str = "as root (e.g. with sudo)";
+
+/// Whether the process runs as root (effective user id 0) on Unix, or
+/// elevated as administrator on Windows
+#[cfg(unix)]
+pub fn is_elevated() -> bool {
+    // `id -u` prints the effective user id
+    command("id")
+        .arg("-u")
+        .stderr(std::process::Stdio::null())
+        .output()
+        .is_ok_and(|output| output.status.success() && output.stdout.trim_ascii() == b"0")
+}
+
+/// Whether the process runs as root (effective user id 0) on Unix, or
+/// elevated as administrator on Windows
+#[cfg(windows)]
+pub fn is_elevated() -> bool {
+    // `net session` is only allowed with an elevated token
+    command("net")
+        .arg("session")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Whether the process runs as root (effective user id 0) on Unix, or
+/// elevated as administrator on Windows
+#[cfg(not(any(unix, windows)))]
+pub fn is_elevated() -> bool {
+    false
+}
+
+/// Directories of the system, nothing is generated in or under them
+#[cfg(windows)]
+const SYSTEM_DIRS: &[&str] = &[
+    r"C:\Windows",
+    r"C:\Program Files",
+    r"C:\Program Files (x86)",
+    r"C:\ProgramData",
+];
+/// Directories of the system, nothing is generated in or under them
+#[cfg(not(windows))]
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/opt", "/proc", "/sbin", "/sys",
+    "/usr", "/Library", "/System",
+];
+
+/// Why a directory should not hold a project, see [`classify_system_dir`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SystemDir {
+    /// `/` or the root of a drive
+    FilesystemRoot,
+    /// In a directory of the system, e.g. `/usr` or `C:\Windows`
+    System,
+    /// The home directory itself, not a directory in it
+    HomeRoot,
+}
+
+impl SystemDir {
+    /// Why the directory is refused, for the error message
+    pub fn reason(self) -> &'static str {
+        match self {
+            SystemDir::FilesystemRoot => "it's the root of the filesystem",
+            SystemDir::System => {
+                "it's a system directory, only writable with elevated rights, so every later \
+                 build and edit would need them too"
+            }
+            SystemDir::HomeRoot => {
+                "it's your home directory itself, the project files would be mixed with yours"
+            }
+        }
+    }
+}
+
+/// Tells whether a directory is an obviously wrong place for a project
+///
+/// # Arguments
+/// * `dir` - The project directory, absolute and normalized, see [`absolute_path`]
+/// * `home` - The home directory of the user, if known
+///
+/// # Returns
+/// Why the directory is wrong, `None` if it's fine
+pub fn classify_system_dir(dir: &Path, home: Option<&Path>) -> Option<SystemDir> {
+    if dir.parent().is_none() {
+        return Some(SystemDir::FilesystemRoot);
+    }
+    let dir = case_folded(dir);
+    if home.is_some_and(|home| case_folded(home) == dir) {
+        return Some(SystemDir::HomeRoot);
+    }
+    if SYSTEM_DIRS
+        .iter()
+        .any(|system| dir.starts_with(case_folded(Path::new(system))))
+    {
+        return Some(SystemDir::System);
+    }
+    None
+}
+
+/// The path in the case it's compared in, Windows paths are case insensitive
+#[cfg(windows)]
+fn case_folded(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().to_lowercase())
+}
+
+/// The path in the case it's compared in, Windows paths are case insensitive
+#[cfg(not(windows))]
+fn case_folded(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Makes a path absolute and removes its `.` and `..` components, without
+/// resolving links (the path doesn't need to exist)
+///
+/// # Errors
+/// If the path is relative and the current directory cannot be read
+pub fn absolute_path(path: &Path) -> std::io::Result<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_elevated() {
+        use std::os::unix::fs::MetadataExt;
+
+        // A new file belongs to the effective user
+        let uid = tempfile::tempfile().unwrap().metadata().unwrap().uid();
+        assert_eq!(is_elevated(), uid == 0);
+    }
+
+    #[test]
+    fn test_archive_entry_path() {
+        assert_eq!(
+            archive_entry_path("esp-idf-template-master/main/main.c"),
+            Some(
+                Path::new("esp-idf-template-master")
+                    .join("main")
+                    .join("main.c")
+            )
+        );
+        assert_eq!(
+            archive_entry_path("esp-idf-template-master\\main\\main.c"),
+            Some(
+                Path::new("esp-idf-template-master")
+                    .join("main")
+                    .join("main.c")
+            )
+        );
+        assert_eq!(
+            archive_entry_path("./dir with spaces//file"),
+            Some(Path::new("dir with spaces").join("file"))
+        );
+    }
+
+    #[test]
+    fn test_archive_entry_path_rejects_escapes() {
+        assert_eq!(archive_entry_path("../evil"), None);
+        assert_eq!(archive_entry_path("dir/../../evil"), None);
+        assert_eq!(archive_entry_path("/etc/passwd"), None);
+        assert_eq!(archive_entry_path("\\Windows\\evil"), None);
+        assert_eq!(archive_entry_path("C:\\Windows\\evil"), None);
+        assert_eq!(archive_entry_path("C:/Windows/evil"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_executable_in() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin_dir = dir.path().join("dir with spaces");
+        fs::create_dir(&bin_dir).unwrap();
+        let tool = bin_dir.join("tool");
+        fs::write(&tool, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        // Not executable, the next one on the PATH is found
+        let shadowed = dir.path().join("tool");
+        fs::write(&shadowed, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&shadowed, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let path = env::join_paths([dir.path(), bin_dir.as_path()]).unwrap();
+        assert_eq!(find_executable_in("tool", &path), Some(tool));
+        assert_eq!(find_executable_in("missing", &path), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_find_executable_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin_dir = dir.path().join("dir with spaces");
+        fs::create_dir(&bin_dir).unwrap();
+        let tool = bin_dir.join("tool.cmd");
+        fs::write(&tool, "@echo off\r\n").unwrap();
+
+        let path = env::join_paths([dir.path(), bin_dir.as_path()]).unwrap();
+        assert_eq!(find_executable_in("tool", &path), Some(tool));
+        assert_eq!(find_executable_in("missing", &path), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_archive_entry_path_windows_separators() {
+        let path = archive_entry_path("template/main/main.c").unwrap();
+        assert_eq!(path.to_str().unwrap(), "template\\main\\main.c");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+
+        set_file_mode(&File::open(&script).unwrap(), Some(0o100755)).unwrap();
+        let mode = fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("750"), Ok(0o750));
+        assert_eq!(parse_mode("0750"), Ok(0o750));
+        assert_eq!(parse_mode("0o2775"), Ok(0o2775));
+        assert!(parse_mode("").is_err());
+        assert!(parse_mode("789").is_err());
+        assert!(parse_mode("17777").is_err());
+        assert!(parse_mode("rwx").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_dir_all_with_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("a").join("b");
+        let mode = |dir: &Path| fs::metadata(dir).unwrap().permissions().mode() & 0o7777;
+        let root_mode = mode(root.path());
+        create_dir_all_with_mode(&dir, Some(0o710)).unwrap();
+
+        assert_eq!(mode(&root.path().join("a")), 0o710);
+        assert_eq!(mode(&dir), 0o710);
+        // The existing root is left untouched
+        assert_eq!(mode(root.path()), root_mode);
+    }
+
+    #[test]
+    fn test_absolute_path() {
+        let current = env::current_dir().unwrap();
+        assert_eq!(
+            absolute_path(Path::new("./a/../b")).unwrap(),
+            current.join("b")
+        );
+        assert!(absolute_path(Path::new("..")).unwrap().is_absolute());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_classify_system_dir() {
+        let home = Path::new("/home/student");
+        let classify = |dir: &str| classify_system_dir(Path::new(dir), Some(home));
+        assert_eq!(classify("/"), Some(SystemDir::FilesystemRoot));
+        assert_eq!(classify("/usr"), Some(SystemDir::System));
+        assert_eq!(classify("/etc/blink"), Some(SystemDir::System));
+        assert_eq!(classify("/opt/esp/blink"), Some(SystemDir::System));
+        assert_eq!(classify("/home/student"), Some(SystemDir::HomeRoot));
+        assert_eq!(classify("/home/student/blink"), None);
+        assert_eq!(classify("/tmp/blink"), None);
+        // Only the directory itself, not a name that starts the same
+        assert_eq!(classify("/usrdata/blink"), None);
+        assert_eq!(classify_system_dir(home, None), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_classify_system_dir() {
+        let home = Path::new(r"C:\Users\student");
+        let classify = |dir: &str| classify_system_dir(Path::new(dir), Some(home));
+        assert_eq!(classify(r"C:\"), Some(SystemDir::FilesystemRoot));
+        assert_eq!(classify(r"D:\"), Some(SystemDir::FilesystemRoot));
+        assert_eq!(classify(r"C:\Windows"), Some(SystemDir::System));
+        assert_eq!(classify(r"c:\windows\blink"), Some(SystemDir::System));
+        assert_eq!(classify(r"C:\Program Files\blink"), Some(SystemDir::System));
+        assert_eq!(classify(r"C:\Users\Student"), Some(SystemDir::HomeRoot));
+        assert_eq!(classify(r"C:\Users\student\blink"), None);
+        assert_eq!(classify(r"D:\projects\blink"), None);
+    }
+}
@@ -0,0 +1,427 @@
+//! Inspection of existing ESP-IDF projects, used by the commands that work on
+//! a project after it was generated
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::platform;
+
+/// Line that every ESP-IDF top-level CMakeLists.txt includes
+const PROJECT_CMAKE_MARKER: &str = "project.cmake";
+
+/// File that marks a directory set up by esp-create-project, e.g. a
+/// workspace, and records the choices of the generator, see [`crate::metadata`]
+pub const LOCAL_CONFIG_FILE: &str = ".esp-create-project.toml";
+
+/// Entry with the git metadata: a directory in a repository, a file in a
+/// worktree or a submodule
+pub const GIT_ENTRY: &str = ".git";
+
+/// Language of the sources of an existing project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceLanguage {
+    C,
+    Cpp,
+}
+
+impl SourceLanguage {
+    /// Extension of the source files written for this language
+    pub fn extension(self) -> &'static str {
+        match self {
+            SourceLanguage::C => "c",
+            SourceLanguage::Cpp => "cpp",
+        }
+    }
+}
+
+/// Checks if the directory is the root of an ESP-IDF project, i.e. it has a
+/// CMakeLists.txt that includes `project.cmake`
+///
+/// # Arguments
+/// * `dir` - The directory to check
+pub fn is_project_root(dir: &Path) -> bool {
+    fs::read_to_string(dir.join("CMakeLists.txt"))
+        .is_ok_and(|cmake| cmake.contains(PROJECT_CMAKE_MARKER))
+}
+
+/// Finds the root of the project that contains the directory
+///
+/// Component CMakeLists.txt files don't include `project.cmake`, so walking
+/// up from inside a component finds the project and not the component.
+///
+/// # Arguments
+/// * `start` - The directory to start from, usually the current directory
+///
+/// # Returns
+/// The project root, or `None` if no parent directory is a project
+pub fn find_project_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| is_project_root(dir))
+        .map(Path::to_path_buf)
+}
+
+/// Detects the language of the project from the extension of its main files
+///
+/// # Arguments
+/// * `root` - The root of the project
+///
+/// # Returns
+/// C++ if `main/` has a C++ source, C otherwise
+pub fn detect_language(root: &Path) -> SourceLanguage {
+    let has_cpp = fs::read_dir(root.join("main")).is_ok_and(|entries| {
+        entries.filter_map(|entry| entry.ok()).any(|entry| {
+            matches!(
+                entry.path().extension().and_then(|ext| ext.to_str()),
+                Some("cpp" | "cc" | "cxx")
+            )
+        })
+    });
+    if has_cpp {
+        SourceLanguage::Cpp
+    } else {
+        SourceLanguage::C
+    }
+}
+
+/// Where a new project would end up nested, see [`nested_location`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NestedLocation {
+    /// Inside the ESP-IDF installation, the path is `IDF_PATH`
+    Idf(PathBuf),
+    /// Inside an existing project (or a directory with [`LOCAL_CONFIG_FILE`]),
+    /// the path is its root
+    Project(PathBuf),
+}
+
+impl fmt::Display for NestedLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NestedLocation::Idf(path) => {
+                write!(f, "inside the ESP-IDF installation \"{}\"", path.display())
+            }
+            NestedLocation::Project(path) => {
+                write!(f, "inside the existing project \"{}\"", path.display())
+            }
+        }
+    }
+}
+
+/// Checks if a new project would be created inside ESP-IDF or another project,
+/// which gives confusing nested projects
+///
+/// # Arguments
+/// * `destination` - The directory of the new project, it may not exist yet
+/// * `idf_path` - The ESP-IDF installation, usually `IDF_PATH`
+///
+/// # Returns
+/// Where the project would be nested, `None` if it's not
+pub fn nested_location(destination: &Path, idf_path: Option<&Path>) -> Option<NestedLocation> {
+    // The destination and some parents may not exist yet, start from the first that does
+    let existing = destination
+        .ancestors()
+        .find(|dir| !dir.as_os_str().is_empty() && dir.exists())
+        .unwrap_or_else(|| Path::new("."));
+    let existing = fs::canonicalize(existing).ok()?;
+
+    if let Some(idf_path) = idf_path.and_then(|path| fs::canonicalize(path).ok()) {
+        if existing.starts_with(&idf_path) {
+            return Some(NestedLocation::Idf(idf_path));
+        }
+    }
+    existing
+        .ancestors()
+        .find(|dir| is_project_root(dir) || dir.join(LOCAL_CONFIG_FILE).is_file())
+        .map(|dir| NestedLocation::Project(dir.to_path_buf()))
+}
+
+/// How a directory is checked out by git, see [`git_checkout`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitCheckout {
+    /// `.git` is a directory, the directory is the root of a repository
+    Repository,
+    /// `.git` is a file, the directory is a worktree (or a submodule) whose
+    /// metadata is in `git_dir`, inside another repository
+    Linked { git_dir: PathBuf },
+}
+
+impl fmt::Display for GitCheckout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitCheckout::Repository => f.write_str("a git repository"),
+            GitCheckout::Linked { git_dir } => {
+                write!(f, "a git worktree (metadata in \"{}\")", git_dir.display())
+            }
+        }
+    }
+}
+
+/// Reads how the directory is checked out by git from its `.git` entry,
+/// without running git
+///
+/// # Arguments
+/// * `dir` - The directory, e.g. the root of a project
+///
+/// # Returns
+/// `None` if the directory has no `.git` entry, it may still be inside a repository
+pub fn git_checkout(dir: &Path) -> Option<GitCheckout> {
+    let entry = dir.join(GIT_ENTRY);
+    if entry.is_dir() {
+        return Some(GitCheckout::Repository);
+    }
+    if !entry.is_file() {
+        return None;
+    }
+    // "gitdir: <path>", the path is relative to the directory when not absolute
+    let content = fs::read_to_string(&entry).unwrap_or_default();
+    let git_dir = content
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))
+        .map(|path| dir.join(path.trim()))
+        .unwrap_or(entry);
+    Some(GitCheckout::Linked { git_dir })
+}
+
+/// Checks if the directory exists and has any content, other than the `.git`
+/// of an empty repository or worktree and the ignorable files
+///
+/// # Arguments
+/// * `dir` - The directory to check
+/// * `ignorable` - Names of the files that don't count, e.g. `.DS_Store`,
+///   see [`crate::config::UserConfig::ignorable_files`]
+pub fn directory_not_empty(dir: &Path, ignorable: &[String]) -> bool {
+    dir.exists()
+        && dir.read_dir().is_ok_and(|mut entries| {
+            entries.any(|entry| {
+                entry.map_or(true, |entry| {
+                    let name = entry.file_name();
+                    name != GIT_ENTRY
+                        && !(entry.path().is_file()
+                            && ignorable.iter().any(|file| name == file.as_str()))
+                })
+            })
+        })
+}
+
+/// Removes the content of a directory but its `.git` entry, so a repository
+/// or a worktree stays usable
+///
+/// # Arguments
+/// * `dir` - The directory to clear
+///
+/// # Errors
+/// If an entry cannot be removed
+pub fn clear_directory(dir: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name() == GIT_ENTRY {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Refuses a project directory that is obviously wrong, see
+/// [`platform::classify_system_dir`]
+///
+/// Checked before anything is written, and before the prompt to delete a
+/// non-empty directory.
+///
+/// # Arguments
+/// * `destination` - The directory of the new project
+///
+/// # Errors
+/// If the directory is a system directory, the root of the filesystem or
+/// the home directory
+pub fn check_not_system_dir(destination: &Path) -> anyhow::Result<()> {
+    let absolute =
+        platform::absolute_path(destination).context("Cannot read the current directory")?;
+    if let Some(kind) = platform::classify_system_dir(&absolute, dirs::home_dir().as_deref()) {
+        bail!(
+            "Refusing to generate the project in \"{}\": {}. Pick a directory of your own \
+             (e.g. in your home directory), or pass --allow-system-dir",
+            absolute.display(),
+            kind.reason()
+        );
+    }
+    Ok(())
+}
+
+/// Checks if the project is in a git tree with uncommitted changes
+///
+/// A project outside a git tree has none.
+///
+/// # Arguments
+/// * `root` - The root of the project
+///
+/// # Errors
+/// If the project is in a git tree but git cannot tell its changes: git is
+/// not installed, or the repository is broken (e.g. the repository of a
+/// worktree is gone)
+pub fn has_uncommitted_changes(root: &Path) -> anyhow::Result<bool> {
+    if !root.ancestors().any(|dir| git_checkout(dir).is_some()) {
+        return Ok(false);
+    }
+    let output = platform::command("git")
+        .arg("-C")
+        .arg(root)
+        .args(["status", "--porcelain"])
+        .output()
+        .context("Cannot run git")?;
+    if !output.status.success() {
+        bail!(
+            "git status failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(!output.stdout.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_find_project_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("project");
+        test_fixtures::write_template(&root);
+        let component = root.join("components").join("sensor");
+        fs::create_dir_all(&component).unwrap();
+        fs::write(
+            component.join("CMakeLists.txt"),
+            "idf_component_register(SRCS \"sensor.c\")\n",
+        )
+        .unwrap();
+
+        assert_eq!(find_project_root(&root), Some(root.clone()));
+        assert_eq!(find_project_root(&component), Some(root.clone()));
+        assert_eq!(find_project_root(dir.path()), None);
+    }
+
+    #[test]
+    fn test_git_worktree() {
+        let git = |dir: &Path, args: &[&str]| {
+            let status = platform::command("git")
+                .arg("-C")
+                .arg(dir)
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .map(|output| output.status.success());
+            assert!(status.unwrap_or(false), "git {:?}", args);
+        };
+        if platform::find_executable("git").is_none() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let worktree = dir.path().join("worktree");
+        fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        git(&repo, &["worktree", "add", "-q", "--detach", "../worktree"]);
+
+        assert_eq!(git_checkout(&repo), Some(GitCheckout::Repository));
+        let git_dir = match git_checkout(&worktree) {
+            Some(GitCheckout::Linked { git_dir }) => git_dir,
+            checkout => panic!("{:?}", checkout),
+        };
+        assert!(git_dir.is_dir());
+        assert_eq!(git_checkout(dir.path()), None);
+
+        assert!(!has_uncommitted_changes(&worktree).unwrap());
+        fs::write(worktree.join("main.c"), "").unwrap();
+        assert!(has_uncommitted_changes(&worktree).unwrap());
+
+        clear_directory(&worktree).unwrap();
+        assert!(worktree.join(GIT_ENTRY).is_file());
+        assert!(!worktree.join("main.c").exists());
+        assert!(!has_uncommitted_changes(&worktree).unwrap());
+
+        // Outside a git tree
+        let outside = tempfile::tempdir().unwrap();
+        assert!(!has_uncommitted_changes(outside.path()).unwrap());
+
+        // The repository of the worktree is gone, nothing can be checked
+        fs::remove_dir_all(&repo).unwrap();
+        assert!(has_uncommitted_changes(&worktree).is_err());
+    }
+
+    #[test]
+    fn test_directory_not_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let ignorable = vec![".DS_Store".to_string(), "Thumbs.db".to_string()];
+        assert!(!directory_not_empty(
+            &dir.path().join("missing"),
+            &ignorable
+        ));
+        assert!(!directory_not_empty(dir.path(), &[]));
+
+        fs::write(dir.path().join(".DS_Store"), "").unwrap();
+        assert!(directory_not_empty(dir.path(), &[]));
+        assert!(!directory_not_empty(dir.path(), &ignorable));
+
+        // Only files are ignored
+        fs::create_dir(dir.path().join("Thumbs.db")).unwrap();
+        assert!(directory_not_empty(dir.path(), &ignorable));
+        fs::remove_dir(dir.path().join("Thumbs.db")).unwrap();
+        fs::write(dir.path().join("main.c"), "").unwrap();
+        assert!(directory_not_empty(dir.path(), &ignorable));
+    }
+
+    #[test]
+    fn test_nested_location() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let idf = root.join("esp-idf");
+        fs::create_dir_all(idf.join("examples")).unwrap();
+        let project = root.join("project");
+        test_fixtures::write_template(&project);
+        let workspace = root.join("workspace");
+        fs::create_dir_all(&workspace).unwrap();
+        fs::write(workspace.join(LOCAL_CONFIG_FILE), "").unwrap();
+
+        assert_eq!(
+            nested_location(&idf.join("examples").join("blink"), Some(&idf)),
+            Some(NestedLocation::Idf(idf.clone()))
+        );
+        assert_eq!(
+            nested_location(&project.join("components").join("new"), Some(&idf)),
+            Some(NestedLocation::Project(project.clone()))
+        );
+        assert_eq!(
+            nested_location(&workspace.join("app"), None),
+            Some(NestedLocation::Project(workspace))
+        );
+        assert_eq!(
+            nested_location(&root.join("new").join("blink"), Some(&idf)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        assert_eq!(detect_language(dir.path()), SourceLanguage::C);
+
+        fs::rename(
+            dir.path().join("main").join("main.c"),
+            dir.path().join("main").join("main.cpp"),
+        )
+        .unwrap();
+        assert_eq!(detect_language(dir.path()), SourceLanguage::Cpp);
+    }
+}
@@ -0,0 +1,395 @@
+//! `check`: looks for the foot-guns of an existing project that the
+//! generator avoids, and fixes the safe ones with the rewriters of
+//! [`crate::regen`]
+
+use anyhow::Context;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cmake;
+use crate::partitions;
+use crate::platform;
+use crate::project_model::ProjectModel;
+use crate::regen::RegenChange;
+use crate::sdkconfig::SdkConfigDefaults;
+use crate::templates::GITIGNORE_FILE;
+
+/// The configuration generated by the build from `sdkconfig.defaults`
+const SDKCONFIG_FILE: &str = "sdkconfig";
+
+/// Flash size of ESP-IDF when the configuration doesn't select one
+const DEFAULT_FLASH_SIZE_MB: u32 = 2;
+
+/// Lines appended to the gitignore by the fix of `gitignore-managed-components`
+const MANAGED_COMPONENTS_IGNORE: &str =
+    "# Downloaded by the component manager, dependencies.lock pins them\nmanaged_components/\n";
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The build fails or the firmware doesn't boot
+    Error,
+    /// The project works but is likely to break or surprise later
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// A foot-gun found in the project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Stable name of the check, e.g. `legacy-component-srcs`
+    pub id: &'static str,
+    pub severity: Severity,
+    /// The file concerned, relative to the project root
+    pub path: PathBuf,
+    /// What is wrong and why it matters
+    pub explanation: String,
+    /// The change that fixes it, `None` if it cannot be fixed safely
+    ///
+    /// The fixes of a file build on each other in the order of the checks:
+    /// once one is skipped, the next ones are no longer
+    /// [current](RegenChange::is_current).
+    pub fix: Option<RegenChange>,
+}
+
+/// The findings of [`check_project`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+    /// The project root
+    pub root: PathBuf,
+    /// Errors first, then in the order of the checks
+    pub findings: Vec<Finding>,
+}
+
+impl CheckReport {
+    /// The number of findings with [`Severity::Error`]
+    pub fn errors(&self) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .count()
+    }
+}
+
+/// Checks the project that contains `start`, nothing is written
+///
+/// # Arguments
+/// * `start` - A directory inside the project, usually the current directory
+///
+/// # Errors
+/// If `start` is not inside a project or one of its files cannot be read
+pub fn check_project(start: &Path) -> anyhow::Result<CheckReport> {
+    let model = ProjectModel::load(start)?;
+    let root = model.root.clone();
+    let mut findings = Vec::new();
+
+    let top_level = PathBuf::from("CMakeLists.txt");
+    if let Some(content) = read(&root, &top_level)? {
+        let deduplicated = cmake::remove_duplicate_commands(&content);
+        if deduplicated != content {
+            findings.push(Finding {
+                id: "duplicate-cmake-commands",
+                severity: Severity::Error,
+                path: top_level.clone(),
+                explanation: "project.cmake is included or project(...) is called more than once, \
+                              a leftover of the line-numbered edits of old versions of \
+                              esp-create-project on a template with another layout"
+                    .to_string(),
+                fix: Some(change(&top_level, &content, deduplicated.clone())),
+            });
+        }
+        // Builds on the previous fix, see [`Finding::fix`]
+        let required = cmake::require_cxx_standard(&deduplicated);
+        if required != deduplicated {
+            findings.push(Finding {
+                id: "cxx-standard-required",
+                severity: Severity::Warning,
+                path: top_level.clone(),
+                explanation: "CMAKE_CXX_STANDARD is set without CMAKE_CXX_STANDARD_REQUIRED, \
+                              a compiler without that standard silently builds with an older one"
+                    .to_string(),
+                fix: Some(change(&top_level, &deduplicated, required)),
+            });
+        }
+    }
+
+    for path in component_cmake_files(&root)? {
+        let content = match read(&root, &path)? {
+            Some(content) => content,
+            None => continue,
+        };
+        if !content
+            .lines()
+            .any(|line| line.trim() == "register_component()")
+        {
+            continue;
+        }
+        // A set(COMPONENT_*) over several lines is reported without a fix
+        let fix = cmake::modernize_component_cmake(&content)
+            .ok()
+            .map(|after| change(&path, &content, after));
+        findings.push(Finding {
+            id: "legacy-component-srcs",
+            severity: Severity::Warning,
+            explanation: "set(COMPONENT_SRCS ...) and register_component() are the legacy \
+                          component syntax, deprecated in favor of idf_component_register(...)"
+                .to_string(),
+            path,
+            fix,
+        });
+    }
+
+    if !model.partitions.is_empty() {
+        let (flash_size_mb, source) = match sdkconfig_flash_size(&root)? {
+            Some(size) => (size, SDKCONFIG_FILE),
+            None => match model.flash_size_mb {
+                Some(size) => (size, crate::sdkconfig::SDKCONFIG_DEFAULTS_FILE),
+                None => (DEFAULT_FLASH_SIZE_MB, "the ESP-IDF default"),
+            },
+        };
+        if let Err(error) = partitions::check_fits(&model.partitions, flash_size_mb) {
+            findings.push(Finding {
+                id: "partition-table-flash-size",
+                severity: Severity::Error,
+                path: PathBuf::from(partitions_file(&model)),
+                explanation: format!(
+                    "{:#} (flash size from {}), select a larger flash with idf.py menuconfig \
+                     or shrink the partitions",
+                    error, source
+                ),
+                fix: None,
+            });
+        }
+    }
+
+    if is_tracked(&root, SDKCONFIG_FILE) {
+        findings.push(Finding {
+            id: "sdkconfig-committed",
+            severity: Severity::Warning,
+            path: PathBuf::from(SDKCONFIG_FILE),
+            explanation: "sdkconfig is generated by the build and committed to git, changes to \
+                          sdkconfig.defaults are ignored while it exists; keep the settings in \
+                          sdkconfig.defaults, run `git rm --cached sdkconfig` and add it to \
+                          .gitignore"
+                .to_string(),
+            fix: None,
+        });
+    }
+
+    let gitignore = PathBuf::from(GITIGNORE_FILE);
+    let ignored = read(&root, &gitignore)?;
+    if ignored.is_some() || root.join(".git").exists() {
+        let content = ignored.unwrap_or_default();
+        if !ignores_managed_components(&content) {
+            let mut after = content.clone();
+            if !after.is_empty() && !after.ends_with('\n') {
+                after.push('\n');
+            }
+            after.push_str(MANAGED_COMPONENTS_IGNORE);
+            findings.push(Finding {
+                id: "gitignore-managed-components",
+                severity: Severity::Warning,
+                explanation: "managed_components/ is not ignored by git, the components \
+                              downloaded by the component manager would be committed"
+                    .to_string(),
+                fix: Some(change(&gitignore, &content, after)),
+                path: gitignore,
+            });
+        }
+    }
+
+    // Stable, so the checks keep their order within a severity
+    findings.sort_by_key(|finding| finding.severity);
+    Ok(CheckReport { root, findings })
+}
+
+fn change(path: &Path, before: &str, after: String) -> RegenChange {
+    RegenChange {
+        path: path.to_path_buf(),
+        before: before.to_string(),
+        after,
+    }
+}
+
+/// Reads a file of the project, `None` if it doesn't exist
+fn read(root: &Path, path: &Path) -> anyhow::Result<Option<String>> {
+    let full_path = root.join(path);
+    if !full_path.is_file() {
+        return Ok(None);
+    }
+    fs::read_to_string(&full_path)
+        .map(Some)
+        .context(format!("Cannot read \"{}\"", full_path.display()))
+}
+
+/// The CMakeLists.txt of `main/` and of every component in `components/`,
+/// relative to the project root
+fn component_cmake_files(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = vec![Path::new("main").join("CMakeLists.txt")];
+    let components = root.join("components");
+    if components.is_dir() {
+        let entries = fs::read_dir(&components)
+            .context(format!("Cannot read \"{}\"", components.display()))?;
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry.context(format!("Cannot read \"{}\"", components.display()))?;
+            names.push(entry.file_name());
+        }
+        names.sort();
+        files.extend(
+            names
+                .into_iter()
+                .map(|name| Path::new("components").join(name).join("CMakeLists.txt")),
+        );
+    }
+    Ok(files)
+}
+
+/// The flash size selected in the `sdkconfig` of the last build, which wins
+/// over `sdkconfig.defaults`
+fn sdkconfig_flash_size(root: &Path) -> anyhow::Result<Option<u32>> {
+    Ok(read(root, Path::new(SDKCONFIG_FILE))?
+        .and_then(|content| partitions::flash_size_mb(&SdkConfigDefaults::parse(&content))))
+}
+
+/// The partition table named in `sdkconfig.defaults`, `partitions.csv` by default
+fn partitions_file(model: &ProjectModel) -> &str {
+    model
+        .sdkconfig
+        .iter()
+        .find(|(key, _)| key == "CONFIG_PARTITION_TABLE_CUSTOM_FILENAME")
+        .map(|(_, file)| file.trim_matches('"'))
+        .unwrap_or(partitions::PARTITIONS_FILE)
+}
+
+/// Whether git tracks the file, `false` outside a git tree or without git
+fn is_tracked(root: &Path, path: &str) -> bool {
+    platform::command("git")
+        .arg("-C")
+        .arg(root)
+        .args(["ls-files", "--", path])
+        .output()
+        .is_ok_and(|output| output.status.success() && !output.stdout.is_empty())
+}
+
+/// Whether a gitignore has a pattern for `managed_components/`
+fn ignores_managed_components(content: &str) -> bool {
+    content.lines().any(|line| {
+        let pattern = line.trim().trim_start_matches('/').trim_end_matches('/');
+        pattern == "managed_components" || pattern == "**/managed_components"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+
+    fn ids(report: &CheckReport) -> Vec<&'static str> {
+        report.findings.iter().map(|finding| finding.id).collect()
+    }
+
+    #[test]
+    fn test_check_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        test_fixtures::write_template(root);
+        fs::write(
+            root.join("CMakeLists.txt"),
+            "set(CMAKE_CXX_STANDARD 17)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\n\
+             project(app-template)\ninclude($ENV{IDF_PATH}/tools/cmake/project.cmake)\nproject(blink)\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("components").join("sensor")).unwrap();
+        fs::write(
+            root.join("components")
+                .join("sensor")
+                .join("CMakeLists.txt"),
+            "idf_component_register(SRCS \"sensor.c\")\n",
+        )
+        .unwrap();
+        // Two 1MB apps and more don't fit the 2MB default flash
+        fs::write(
+            root.join("partitions.csv"),
+            partitions::PartitionScheme::TwoOta.csv(),
+        )
+        .unwrap();
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+
+        let report = check_project(&root.join("main")).unwrap();
+        assert_eq!(
+            ids(&report),
+            [
+                "duplicate-cmake-commands",
+                "partition-table-flash-size",
+                "cxx-standard-required",
+                "legacy-component-srcs",
+                "gitignore-managed-components",
+            ]
+        );
+        assert_eq!(report.errors(), 2);
+        assert_eq!(
+            report.findings[3].path,
+            Path::new("main").join("CMakeLists.txt")
+        );
+        assert!(report.findings[1].fix.is_none());
+        assert!(
+            report.findings[1]
+                .explanation
+                .contains("the ESP-IDF default"),
+            "{}",
+            report.findings[1].explanation
+        );
+
+        // The fixes of a file build on each other, in the order of the checks
+        let mut fixes: Vec<&RegenChange> = Vec::new();
+        for id in ["duplicate-cmake-commands", "cxx-standard-required"] {
+            let finding = report.findings.iter().find(|f| f.id == id).unwrap();
+            fixes.push(finding.fix.as_ref().unwrap());
+        }
+        assert!(!fixes[1].is_current(root));
+        for finding in &report.findings {
+            if let Some(fix) = &finding.fix {
+                assert!(fix.is_current(root), "{}", finding.id);
+                fix.apply(root).unwrap();
+            }
+        }
+        fs::write(
+            root.join(crate::sdkconfig::SDKCONFIG_DEFAULTS_FILE),
+            "CONFIG_ESPTOOLPY_FLASHSIZE_4MB=y\n",
+        )
+        .unwrap();
+        let report = check_project(root).unwrap();
+        assert!(report.findings.is_empty(), "{:?}", ids(&report));
+
+        // The sdkconfig of the last build wins over the defaults
+        fs::write(
+            root.join("sdkconfig"),
+            "CONFIG_ESPTOOLPY_FLASHSIZE=\"2MB\"\n",
+        )
+        .unwrap();
+        let report = check_project(root).unwrap();
+        assert_eq!(report.errors(), 1);
+        assert!(report.findings[0].explanation.contains("from sdkconfig"));
+    }
+
+    #[test]
+    fn test_ignores_managed_components() {
+        assert!(ignores_managed_components(
+            crate::templates::GITIGNORE_TEMPLATE
+        ));
+        assert!(ignores_managed_components("/managed_components\n"));
+        assert!(!ignores_managed_components(
+            "build/\n# managed_components/\n"
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+//! Everything the files of an existing project say about it, read by the
+//! commands that inspect or update a project after it was generated
+
+use anyhow::Context;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cmake;
+use crate::idf_component::{self, IDF_COMPONENT_FILE};
+use crate::metadata::{ProjectMetadata, TemplateProvenance};
+use crate::partitions;
+use crate::project::{self, SourceLanguage};
+use crate::sdkconfig::{SdkConfigDefaults, SDKCONFIG_DEFAULTS_FILE};
+use crate::starters::STARTERS;
+use crate::templates;
+
+/// Partition table read when `sdkconfig.defaults` doesn't name another one
+const DEFAULT_PARTITIONS_FILE: &str = "partitions.csv";
+
+/// Files or directories whose presence means an IDE or CI integration is set up
+const INTEGRATIONS: &[(&str, &str)] = &[
+    (".git", "git"),
+    (".vscode", "VS Code"),
+    (".devcontainer", "Dev Container"),
+    (".clang-format", "clang-format"),
+    (templates::CMAKE_PRESETS_FILE, "CMake presets"),
+    (".github/workflows", "GitHub Actions"),
+    (".gitlab-ci.yml", "GitLab CI"),
+];
+
+/// A row of a custom partition table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Partition {
+    pub name: String,
+    /// `app` or `data`
+    pub kind: String,
+    pub subtype: String,
+    /// Empty when the offset is computed by the build
+    pub offset: String,
+    pub size: String,
+}
+
+/// Overview of an existing project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProjectModel {
+    /// The project root
+    pub root: PathBuf,
+    /// Name in `project(...)` of the top-level CMakeLists.txt
+    pub name: Option<String>,
+    /// `PROJECT_VER` set in the top-level CMakeLists.txt
+    pub version: Option<String>,
+    /// Language of the main component
+    pub language: SourceLanguage,
+    /// `CMAKE_CXX_STANDARD` set in the top-level CMakeLists.txt
+    pub cpp_standard: Option<String>,
+    /// `CONFIG_IDF_TARGET` set in `sdkconfig.defaults`
+    pub target: Option<String>,
+    /// Flash size in MB selected in `sdkconfig.defaults`
+    pub flash_size_mb: Option<u32>,
+    /// Every entry of `sdkconfig.defaults`
+    pub sdkconfig: Vec<(String, String)>,
+    /// Managed components of the main component (`main/idf_component.yml`)
+    pub dependencies: Vec<(String, String)>,
+    /// Ids of the starters whose files are in `main/`
+    pub starters: Vec<String>,
+    /// Custom partition table, empty when the project uses a built-in one
+    pub partitions: Vec<Partition>,
+    /// IDE and CI integrations found in the project
+    pub integrations: Vec<String>,
+    /// The template the project was generated from, recorded in its
+    /// metadata (see [`crate::metadata`])
+    pub template: Option<TemplateProvenance>,
+}
+
+impl ProjectModel {
+    /// Reads the project that contains `start`
+    ///
+    /// Missing optional files (`sdkconfig.defaults`, `idf_component.yml`,
+    /// partition table) leave their fields empty.
+    ///
+    /// # Arguments
+    /// * `start` - A directory inside the project, usually the current directory
+    ///
+    /// # Errors
+    /// If `start` is not inside a project or one of its files cannot be read
+    pub fn load(start: &Path) -> anyhow::Result<Self> {
+        let root = project::find_project_root(start).context(format!(
+            "\"{}\" is not inside an ESP-IDF project (no CMakeLists.txt including project.cmake found)",
+            start.display()
+        ))?;
+
+        let cmake = read(&root.join("CMakeLists.txt"))?.unwrap_or_default();
+        let sdkconfig = SdkConfigDefaults::parse(
+            &read(&root.join(SDKCONFIG_DEFAULTS_FILE))?.unwrap_or_default(),
+        );
+        let manifest = read(&root.join("main").join(IDF_COMPONENT_FILE))?.unwrap_or_default();
+        let partitions_file = sdkconfig
+            .get("CONFIG_PARTITION_TABLE_CUSTOM_FILENAME")
+            .map(|file| file.trim_matches('"'))
+            .unwrap_or(DEFAULT_PARTITIONS_FILE);
+        let partitions = read(&root.join(partitions_file))?
+            .map(|csv| parse_partitions(&csv))
+            .unwrap_or_default();
+        let template = ProjectMetadata::load(&root)?.template;
+
+        Ok(ProjectModel {
+            name: cmake::project_name(&cmake).map(String::from),
+            version: cmake::variable(&cmake, "PROJECT_VER").map(String::from),
+            language: project::detect_language(&root),
+            cpp_standard: cmake::variable(&cmake, "CMAKE_CXX_STANDARD").map(String::from),
+            target: sdkconfig
+                .get("CONFIG_IDF_TARGET")
+                .map(|target| target.trim_matches('"').to_string()),
+            flash_size_mb: partitions::flash_size_mb(&sdkconfig),
+            sdkconfig: sdkconfig.entries().to_vec(),
+            dependencies: idf_component::dependencies(&manifest),
+            starters: STARTERS
+                .iter()
+                .filter(|starter| starter.is_in(&root.join("main")))
+                .map(|starter| starter.id.to_string())
+                .collect(),
+            partitions,
+            integrations: INTEGRATIONS
+                .iter()
+                .filter(|(path, _)| root.join(path).exists())
+                .map(|(_, name)| name.to_string())
+                .collect(),
+            template,
+            root,
+        })
+    }
+}
+
+/// Reads a file of the project
+///
+/// # Returns
+/// The content, or `None` if the file doesn't exist
+fn read(path: &Path) -> anyhow::Result<Option<String>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .context(format!("Cannot read \"{}\"", path.display()))
+}
+
+/// Parses the rows of a partition table CSV, comments and short rows are skipped
+///
+/// # Arguments
+/// * `csv` - The content of the partition table
+pub fn parse_partitions(csv: &str) -> Vec<Partition> {
+    csv.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            match fields.as_slice() {
+                [name, kind, subtype, offset, size, ..] => Some(Partition {
+                    name: name.to_string(),
+                    kind: kind.to_string(),
+                    subtype: subtype.to_string(),
+                    offset: offset.to_string(),
+                    size: size.to_string(),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starters::{self, MergeMode};
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_parse_partitions() {
+        let partitions = parse_partitions(
+            "# Name,   Type, SubType, Offset,  Size, Flags\nnvs,      data, nvs,     0x9000,  0x6000,\nfactory,  app,  factory, ,        1M,\nbroken\n",
+        );
+        assert_eq!(partitions.len(), 2);
+        assert_eq!(partitions[0].name, "nvs");
+        assert_eq!(partitions[1].kind, "app");
+        assert_eq!(partitions[1].offset, "");
+        assert_eq!(partitions[1].size, "1M");
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        test_fixtures::write_template(root);
+        let cmake = cmake::rewrite_project_cmake(
+            test_fixtures::TEMPLATE_CMAKE,
+            "set(CMAKE_CXX_STANDARD 17)",
+            "blink",
+        )
+        .unwrap();
+        fs::write(root.join("CMakeLists.txt"), cmake).unwrap();
+        fs::write(
+            root.join(SDKCONFIG_DEFAULTS_FILE),
+            "CONFIG_IDF_TARGET=\"esp32c3\"\nCONFIG_ESPTOOLPY_FLASHSIZE_8MB=y\nCONFIG_PARTITION_TABLE_CUSTOM_FILENAME=\"table.csv\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("table.csv"), "factory, app, factory, , 1M\n").unwrap();
+        starters::apply_starter(root, starters::find("led").unwrap(), MergeMode::Fail, true)
+            .unwrap();
+
+        let model = ProjectModel::load(&root.join("main")).unwrap();
+
+        assert_eq!(model.root, root);
+        assert_eq!(model.name.as_deref(), Some("blink"));
+        assert_eq!(model.version, None);
+        assert_eq!(model.cpp_standard.as_deref(), Some("17"));
+        assert_eq!(model.target.as_deref(), Some("esp32c3"));
+        assert_eq!(model.flash_size_mb, Some(8));
+        assert_eq!(model.starters, vec!["led"]);
+        assert_eq!(model.dependencies[0].0, "espressif/led_strip");
+        assert_eq!(model.partitions[0].name, "factory");
+        assert!(model.integrations.is_empty());
+        assert_eq!(model.template, None);
+
+        let template = TemplateProvenance {
+            url: "https://example.com/template.zip".into(),
+            git_ref: None,
+            sha256: Some("ab".repeat(32)),
+        };
+        ProjectMetadata {
+            template: Some(template.clone()),
+            ..ProjectMetadata::default()
+        }
+        .write(root)
+        .unwrap();
+        assert_eq!(ProjectModel::load(root).unwrap().template, Some(template));
+    }
+}
@@ -0,0 +1,482 @@
+//! The prompts, behind [`Prompter`] so they can be answered without the
+//! interactive menus
+//!
+//! [`TerminalPrompter`] shows the arrow-key menus of dialoguer, which redraw
+//! the lines under the cursor. A screen reader cannot follow them, so the
+//! accessible mode (`--accessible`, on by default with `TERM=dumb`) uses
+//! [`PlainPrompter`] instead: numbered menus and y/n questions answered by
+//! typing a line, each question printed once and never redrawn.
+
+use dialoguer::theme::Theme as DialoguerTheme;
+use dialoguer::{Confirm, Input, MultiSelect, Select, Sort};
+use std::io::{self, BufRead, Write};
+
+use crate::i18n::{self, t, Message};
+
+/// Asks the user
+pub trait Prompter {
+    /// Asks to pick one of the items
+    ///
+    /// # Arguments
+    /// * `prompt` - The question
+    /// * `items` - The answers, at least one
+    /// * `default` - The index of the answer picked by default
+    ///
+    /// # Returns
+    /// The index of the picked item
+    ///
+    /// # Errors
+    /// If the terminal cannot be read or written, or the input ended
+    fn select(&mut self, prompt: &str, items: &[String], default: usize) -> io::Result<usize>;
+
+    /// Asks to pick any number of the items
+    ///
+    /// # Arguments
+    /// * `prompt` - The question
+    /// * `items` - The answers
+    /// * `defaults` - Whether each item is picked by default
+    ///
+    /// # Returns
+    /// The indices of the picked items, in order
+    ///
+    /// # Errors
+    /// If the terminal cannot be read or written, or the input ended
+    fn multi_select(
+        &mut self,
+        prompt: &str,
+        items: &[String],
+        defaults: &[bool],
+    ) -> io::Result<Vec<usize>>;
+
+    /// Asks to put the items in order
+    ///
+    /// # Arguments
+    /// * `prompt` - The question
+    /// * `items` - The items, in their current order
+    ///
+    /// # Returns
+    /// The indices of every item, in the new order
+    ///
+    /// # Errors
+    /// If the terminal cannot be read or written, or the input ended
+    fn sort(&mut self, prompt: &str, items: &[String]) -> io::Result<Vec<usize>>;
+
+    /// Asks a yes or no question
+    ///
+    /// # Arguments
+    /// * `prompt` - The question
+    /// * `default` - The answer when none is typed, `None` to require one
+    ///
+    /// # Errors
+    /// If the terminal cannot be read or written, or the input ended
+    fn confirm(&mut self, prompt: &str, default: Option<bool>) -> io::Result<bool>;
+
+    /// Asks for a line of text, possibly empty
+    ///
+    /// # Errors
+    /// If the terminal cannot be read or written, or the input ended
+    fn input(&mut self, prompt: &str, default: &str) -> io::Result<String>;
+}
+
+/// Whether the prompts and status lines are plain text for screen readers:
+/// with `--accessible`, or on a `dumb` terminal that cannot move the cursor
+///
+/// # Arguments
+/// * `flag` - Whether `--accessible` was passed
+/// * `term` - The `TERM` variable
+pub fn is_accessible(flag: bool, term: Option<&str>) -> bool {
+    flag || term == Some("dumb")
+}
+
+/// The arrow-key menus of dialoguer
+pub struct TerminalPrompter<'a> {
+    theme: &'a dyn DialoguerTheme,
+}
+
+impl<'a> TerminalPrompter<'a> {
+    /// Creates the prompter
+    ///
+    /// # Arguments
+    /// * `theme` - The theme of every prompt
+    pub fn new(theme: &'a dyn DialoguerTheme) -> Self {
+        TerminalPrompter { theme }
+    }
+}
+
+impl Prompter for TerminalPrompter<'_> {
+    fn select(&mut self, prompt: &str, items: &[String], default: usize) -> io::Result<usize> {
+        Select::with_theme(self.theme)
+            .with_prompt(prompt)
+            .items(items)
+            .default(default)
+            .interact()
+    }
+
+    fn multi_select(
+        &mut self,
+        prompt: &str,
+        items: &[String],
+        defaults: &[bool],
+    ) -> io::Result<Vec<usize>> {
+        // The keys only apply to these menus
+        MultiSelect::with_theme(self.theme)
+            .with_prompt(format!("{} {}", prompt, t(Message::MultiSelectKeys)))
+            .items(items)
+            .defaults(defaults)
+            .interact()
+    }
+
+    fn sort(&mut self, prompt: &str, items: &[String]) -> io::Result<Vec<usize>> {
+        Sort::with_theme(self.theme)
+            .with_prompt(format!("{} {}", prompt, t(Message::SortKeys)))
+            .items(items)
+            .interact()
+    }
+
+    fn confirm(&mut self, prompt: &str, default: Option<bool>) -> io::Result<bool> {
+        let mut confirm = Confirm::with_theme(self.theme);
+        confirm.with_prompt(prompt);
+        if let Some(default) = default {
+            confirm.default(default);
+        }
+        confirm.interact()
+    }
+
+    fn input(&mut self, prompt: &str, default: &str) -> io::Result<String> {
+        Input::<String>::with_theme(self.theme)
+            .with_prompt(prompt)
+            .default(default.to_string())
+            .allow_empty(true)
+            .interact_text()
+    }
+}
+
+/// Numbered menus and questions answered a line at a time, for screen
+/// readers; an invalid answer is reported and the question asked again
+#[derive(Debug)]
+pub struct PlainPrompter<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> PlainPrompter<R, W> {
+    /// Creates the prompter
+    ///
+    /// # Arguments
+    /// * `input` - Where the answers are read, stdin outside tests
+    /// * `output` - Where the questions are printed, stderr outside tests
+    pub fn new(input: R, output: W) -> Self {
+        PlainPrompter { input, output }
+    }
+
+    /// Prints the question and reads the answer, trimmed
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        write!(self.output, "{}: ", question)?;
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            writeln!(self.output)?;
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the input ended before the answer",
+            ));
+        }
+        Ok(line.trim().to_string())
+    }
+
+    fn invalid(&mut self, answer: &str) -> io::Result<()> {
+        writeln!(
+            self.output,
+            "{}",
+            i18n::format(Message::InvalidAnswer, &[answer])
+        )
+    }
+
+    fn menu(&mut self, prompt: &str, items: &[String], checked: Option<&[bool]>) -> io::Result<()> {
+        writeln!(self.output, "{}", prompt)?;
+        for (i, item) in items.iter().enumerate() {
+            match checked {
+                Some(checked) if checked.get(i).copied().unwrap_or(false) => {
+                    writeln!(self.output, "  {}. [x] {}", i + 1, item)?
+                }
+                Some(_) => writeln!(self.output, "  {}. [ ] {}", i + 1, item)?,
+                None => writeln!(self.output, "  {}. {}", i + 1, item)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a number of a menu of `count` items, from 1
+fn parse_number(answer: &str, count: usize) -> Option<usize> {
+    answer
+        .parse::<usize>()
+        .ok()
+        .filter(|&number| (1..=count).contains(&number))
+        .map(|number| number - 1)
+}
+
+impl<R: BufRead, W: Write> Prompter for PlainPrompter<R, W> {
+    fn select(&mut self, prompt: &str, items: &[String], default: usize) -> io::Result<usize> {
+        self.menu(prompt, items, None)?;
+        let question = format!(
+            "{} [{}]",
+            i18n::format(Message::ChooseNumber, &[&items.len().to_string()]),
+            default + 1
+        );
+        loop {
+            let answer = self.ask(&question)?;
+            if answer.is_empty() {
+                return Ok(default);
+            }
+            match parse_number(&answer, items.len()) {
+                Some(selected) => return Ok(selected),
+                None => self.invalid(&answer)?,
+            }
+        }
+    }
+
+    fn multi_select(
+        &mut self,
+        prompt: &str,
+        items: &[String],
+        defaults: &[bool],
+    ) -> io::Result<Vec<usize>> {
+        self.menu(prompt, items, Some(defaults))?;
+        let picked: Vec<usize> = (0..items.len())
+            .filter(|&i| defaults.get(i).copied().unwrap_or(false))
+            .collect();
+        let shown: Vec<String> = picked.iter().map(|i| (i + 1).to_string()).collect();
+        let question = format!(
+            "{} [{}]",
+            i18n::format(Message::ChooseNumbers, &[&items.len().to_string()]),
+            if shown.is_empty() {
+                "0".to_string()
+            } else {
+                shown.join(",")
+            }
+        );
+        loop {
+            let answer = self.ask(&question)?;
+            if answer.is_empty() {
+                return Ok(picked);
+            }
+            if answer == "0" {
+                return Ok(Vec::new());
+            }
+            let numbers: Option<Vec<usize>> = answer
+                .split([',', ' '])
+                .filter(|number| !number.is_empty())
+                .map(|number| parse_number(number, items.len()))
+                .collect();
+            match numbers {
+                Some(mut selected) => {
+                    selected.sort_unstable();
+                    selected.dedup();
+                    return Ok(selected);
+                }
+                None => self.invalid(&answer)?,
+            }
+        }
+    }
+
+    fn sort(&mut self, prompt: &str, items: &[String]) -> io::Result<Vec<usize>> {
+        self.menu(prompt, items, None)?;
+        let current: Vec<String> = (1..=items.len()).map(|i| i.to_string()).collect();
+        let question = format!(
+            "{} [{}]",
+            i18n::format(Message::ChooseOrder, &[&items.len().to_string()]),
+            current.join(",")
+        );
+        loop {
+            let answer = self.ask(&question)?;
+            let numbers: Option<Vec<usize>> = answer
+                .split([',', ' '])
+                .filter(|number| !number.is_empty())
+                .map(|number| parse_number(number, items.len()))
+                .collect();
+            match numbers {
+                Some(mut order)
+                    if !order
+                        .iter()
+                        .enumerate()
+                        .any(|(i, n)| order[..i].contains(n)) =>
+                {
+                    order.extend(
+                        (0..items.len())
+                            .filter(|i| !order.contains(i))
+                            .collect::<Vec<_>>(),
+                    );
+                    return Ok(order);
+                }
+                _ => self.invalid(&answer)?,
+            }
+        }
+    }
+
+    fn confirm(&mut self, prompt: &str, default: Option<bool>) -> io::Result<bool> {
+        let question = format!(
+            "{} {}",
+            prompt,
+            t(match default {
+                Some(true) => Message::YesNoDefaultYes,
+                Some(false) => Message::YesNoDefaultNo,
+                None => Message::YesNo,
+            })
+        );
+        loop {
+            let answer = self.ask(&question)?;
+            // Both languages are understood, whatever the locale
+            match (answer.to_lowercase().as_str(), default) {
+                ("", Some(default)) => return Ok(default),
+                ("y" | "yes" | "s" | "si" | "sí", _) => return Ok(true),
+                ("n" | "no", _) => return Ok(false),
+                _ => self.invalid(&answer)?,
+            }
+        }
+    }
+
+    fn input(&mut self, prompt: &str, default: &str) -> io::Result<String> {
+        let question = if default.is_empty() {
+            prompt.to_string()
+        } else {
+            format!("{} [{}]", prompt, default)
+        };
+        let answer = self.ask(&question)?;
+        Ok(if answer.is_empty() {
+            default.to_string()
+        } else {
+            answer
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Answers the questions with the lines of `script`, and returns what
+    /// was printed
+    fn scripted<T>(
+        script: &str,
+        ask: impl FnOnce(&mut PlainPrompter<&[u8], &mut Vec<u8>>) -> T,
+    ) -> (T, String) {
+        let mut output = Vec::new();
+        let mut prompter = PlainPrompter::new(script.as_bytes(), &mut output);
+        let answer = ask(&mut prompter);
+        (answer, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_select() {
+        let languages = items(&["C", "C++ 11", "C++ 17"]);
+        let (selected, output) = scripted("\n", |prompter| {
+            prompter.select("Programming language?", &languages, 1)
+        });
+        assert_eq!(selected.unwrap(), 1);
+        assert_eq!(
+            output,
+            "Programming language?\n  1. C\n  2. C++ 11\n  3. C++ 17\nEnter a number from 1 to 3 [2]: "
+        );
+
+        // Asked again until the answer is a number of the menu
+        let (selected, output) = scripted("c\n4\n 3 \n", |prompter| {
+            prompter.select("Programming language?", &languages, 0)
+        });
+        assert_eq!(selected.unwrap(), 2);
+        assert_eq!(output.matches("Invalid answer").count(), 2);
+        // No cursor movement, every line is printed once
+        assert!(!output.contains('\r') && !output.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_multi_select() {
+        let starters = items(&["nvs", "wifi", "mqtt"]);
+        let (selected, output) = scripted("\n", |prompter| {
+            prompter.multi_select("Starters?", &starters, &[false, true, true])
+        });
+        assert_eq!(selected.unwrap(), [1, 2]);
+        assert!(
+            output.contains("  1. [ ] nvs\n  2. [x] wifi\n"),
+            "{}",
+            output
+        );
+        assert!(output.ends_with("[2,3]: "), "{}", output);
+
+        let (selected, _) = scripted("3, 1 3\n", |prompter| {
+            prompter.multi_select("Starters?", &starters, &[false; 3])
+        });
+        assert_eq!(selected.unwrap(), [0, 2]);
+
+        let (selected, output) = scripted("1,9\n0\n", |prompter| {
+            prompter.multi_select("Starters?", &starters, &[true; 3])
+        });
+        assert!(selected.unwrap().is_empty());
+        assert!(output.contains("Invalid answer \"1,9\""), "{}", output);
+    }
+
+    #[test]
+    fn test_sort() {
+        let sources = items(&["main.c", "wifi.c", "mqtt.c"]);
+        let (order, output) = scripted("\n", |prompter| prompter.sort("Order?", &sources));
+        assert_eq!(order.unwrap(), [0, 1, 2]);
+        assert!(output.ends_with("[1,2,3]: "), "{}", output);
+
+        // The items not listed follow in their order
+        let (order, _) = scripted("3\n", |prompter| prompter.sort("Order?", &sources));
+        assert_eq!(order.unwrap(), [2, 0, 1]);
+
+        let (order, output) =
+            scripted("1 1\n2,3,1\n", |prompter| prompter.sort("Order?", &sources));
+        assert_eq!(order.unwrap(), [1, 2, 0]);
+        assert!(output.contains("Invalid answer \"1 1\""), "{}", output);
+    }
+
+    #[test]
+    fn test_confirm_and_input() {
+        let (answers, output) = scripted("\nno\nmaybe\nS\n\nn\n", |prompter| {
+            (
+                prompter.confirm("Init git?", Some(true)).unwrap(),
+                prompter.confirm("Init git?", Some(true)).unwrap(),
+                prompter.confirm("Advanced?", Some(false)).unwrap(),
+                prompter.confirm("Delete?", None).unwrap(),
+            )
+        });
+        assert_eq!(answers, (true, false, true, false));
+        assert!(output.starts_with("Init git? [Y/n]: "), "{}", output);
+        assert!(output.contains("Advanced? [y/N]: "), "{}", output);
+        // Without a default, an empty answer is not one
+        assert!(
+            output.ends_with("Delete? [y/n]: Invalid answer \"\"\nDelete? [y/n]: "),
+            "{}",
+            output
+        );
+
+        let (answers, output) = scripted("\n  Jane Doe \n", |prompter| {
+            (
+                prompter.input("Version?", "0.1.0").unwrap(),
+                prompter.input("Maintainers?", "").unwrap(),
+            )
+        });
+        assert_eq!(answers, ("0.1.0".to_string(), "Jane Doe".to_string()));
+        assert_eq!(output, "Version? [0.1.0]: Maintainers?: ");
+    }
+
+    #[test]
+    fn test_end_of_input() {
+        let (answer, _) = scripted("", |prompter| prompter.confirm("Delete?", Some(true)));
+        assert_eq!(answer.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_is_accessible() {
+        assert!(is_accessible(true, Some("xterm-256color")));
+        assert!(is_accessible(false, Some("dumb")));
+        assert!(!is_accessible(false, Some("xterm")));
+        assert!(!is_accessible(false, None));
+    }
+}
@@ -0,0 +1,417 @@
+//! `regen`: re-applies the generators to an existing project, so it benefits
+//! from the improvements made after it was generated
+
+use anyhow::{bail, Context};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cmake;
+use crate::export_env::DEFAULT_TARGET;
+use crate::hygiene::HygieneFile;
+use crate::metadata::ProjectMetadata;
+use crate::options::ClangFormatStyle;
+use crate::project;
+use crate::project_model::ProjectModel;
+use crate::templates;
+
+/// A generator that can be re-applied
+///
+/// Only build system and tooling files are regenerated, never user sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegenTarget {
+    /// The top-level and `main/` CMakeLists.txt
+    Cmake,
+    /// `.clang-format`, keeping its indent width and column limit
+    ClangFormat,
+    /// `.gitignore`, with the rules of [`templates::GITIGNORE_TEMPLATE`]
+    Gitignore,
+    /// `CMakePresets.json`, for the name and the target of the project
+    Presets,
+    /// The hygiene files recorded in the project metadata when it was
+    /// generated, see [`crate::metadata`]
+    Hygiene,
+}
+
+impl RegenTarget {
+    /// Every target, in the order they are applied
+    pub const ALL: &'static [RegenTarget] = &[
+        RegenTarget::Cmake,
+        RegenTarget::ClangFormat,
+        RegenTarget::Gitignore,
+        RegenTarget::Presets,
+        RegenTarget::Hygiene,
+    ];
+}
+
+impl FromStr for RegenTarget {
+    type Err = String;
+
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        match target {
+            "cmake" => Ok(RegenTarget::Cmake),
+            "clang-format" => Ok(RegenTarget::ClangFormat),
+            "gitignore" => Ok(RegenTarget::Gitignore),
+            "presets" => Ok(RegenTarget::Presets),
+            "hygiene" => Ok(RegenTarget::Hygiene),
+            _ => Err(format!(
+                "unknown target \"{}\", expected cmake, clang-format, gitignore, presets or hygiene",
+                target
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RegenTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RegenTarget::Cmake => "cmake",
+            RegenTarget::ClangFormat => "clang-format",
+            RegenTarget::Gitignore => "gitignore",
+            RegenTarget::Presets => "presets",
+            RegenTarget::Hygiene => "hygiene",
+        })
+    }
+}
+
+/// A file whose regenerated content differs from the current one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegenChange {
+    /// The file, relative to the project root
+    pub path: PathBuf,
+    /// The current content
+    pub before: String,
+    /// The regenerated content
+    pub after: String,
+}
+
+impl RegenChange {
+    /// The lines removed (`-`) and added (`+`) by the change, in file order
+    pub fn diff(&self) -> Vec<DiffLine> {
+        diff_lines(&self.before, &self.after)
+    }
+
+    /// Whether the file still has the content the change was computed from,
+    /// a missing file has none
+    ///
+    /// # Arguments
+    /// * `root` - The project root
+    pub fn is_current(&self, root: &Path) -> bool {
+        fs::read_to_string(root.join(&self.path)).unwrap_or_default() == self.before
+    }
+
+    /// Writes the regenerated content
+    ///
+    /// # Arguments
+    /// * `root` - The project root
+    ///
+    /// # Errors
+    /// If the file cannot be written
+    pub fn apply(&self, root: &Path) -> anyhow::Result<()> {
+        let path = root.join(&self.path);
+        fs::write(&path, &self.after).context(format!("Cannot write \"{}\"", path.display()))
+    }
+}
+
+/// A line of a [`RegenChange::diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Removed(String),
+    Added(String),
+}
+
+/// The changes regenerating a project would make, nothing is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegenPlan {
+    /// The project root
+    pub root: PathBuf,
+    /// The files that would change
+    pub changes: Vec<RegenChange>,
+}
+
+/// Computes what re-applying the generators to the project that contains
+/// `start` would change
+///
+/// # Arguments
+/// * `start` - A directory inside the project, usually the current directory
+/// * `targets` - The generators to re-apply
+/// * `allow_dirty` - Whether to run even if the git tree has uncommitted changes
+///
+/// # Errors
+/// If `start` is not inside a project, the git tree has uncommitted changes
+/// and `allow_dirty` is not set, or a file cannot be read or regenerated
+pub fn plan_regen(
+    start: &Path,
+    targets: &[RegenTarget],
+    allow_dirty: bool,
+) -> anyhow::Result<RegenPlan> {
+    let root = project::find_project_root(start).context(format!(
+        "\"{}\" is not inside an ESP-IDF project (no CMakeLists.txt including project.cmake found)",
+        start.display()
+    ))?;
+    if !allow_dirty
+        && project::has_uncommitted_changes(&root).context(format!(
+            "Cannot tell if \"{}\" has uncommitted changes, pass --allow-dirty to skip the check",
+            root.display()
+        ))?
+    {
+        bail!(
+            "\"{}\" has uncommitted changes, commit them or pass --allow-dirty",
+            root.display()
+        );
+    }
+
+    let mut changes = Vec::new();
+    let mut regenerate =
+        |path: PathBuf, rewrite: &dyn Fn(&str) -> anyhow::Result<String>| -> anyhow::Result<()> {
+            let full_path = root.join(&path);
+            if !full_path.is_file() {
+                return Ok(());
+            }
+            let before = fs::read_to_string(&full_path)
+                .context(format!("Cannot read \"{}\"", full_path.display()))?;
+            let after =
+                rewrite(&before).context(format!("Cannot regenerate \"{}\"", path.display()))?;
+            if after != before {
+                changes.push(RegenChange {
+                    path,
+                    before,
+                    after,
+                });
+            }
+            Ok(())
+        };
+
+    for target in targets {
+        match target {
+            RegenTarget::Cmake => {
+                regenerate(
+                    PathBuf::from("CMakeLists.txt"),
+                    &cmake::modernize_project_cmake,
+                )?;
+                regenerate(
+                    Path::new("main").join("CMakeLists.txt"),
+                    &cmake::modernize_component_cmake,
+                )?;
+            }
+            RegenTarget::ClangFormat => regenerate(
+                PathBuf::from(templates::CLANG_FORMAT_FILE),
+                &|content: &str| Ok(templates::clang_format(clang_format_style(content))),
+            )?,
+            RegenTarget::Gitignore => {
+                regenerate(PathBuf::from(templates::GITIGNORE_FILE), &|_: &str| {
+                    Ok(templates::GITIGNORE_TEMPLATE.to_string())
+                })?
+            }
+            RegenTarget::Presets => {
+                let model = ProjectModel::load(&root)?;
+                let name = model
+                    .name
+                    .context("The top-level CMakeLists.txt has no project(...) command")?;
+                let target = model.target.as_deref().unwrap_or(DEFAULT_TARGET);
+                regenerate(PathBuf::from(templates::CMAKE_PRESETS_FILE), &|_: &str| {
+                    Ok(templates::cmake_presets(&name, target))
+                })?
+            }
+            RegenTarget::Hygiene => {
+                let style = clang_format_style(
+                    &fs::read_to_string(root.join(templates::CLANG_FORMAT_FILE))
+                        .unwrap_or_default(),
+                );
+                for file in ProjectMetadata::load(&root)?.hygiene {
+                    // Already regenerated by its own target
+                    let own_target = match file {
+                        HygieneFile::ClangFormat => Some(RegenTarget::ClangFormat),
+                        HygieneFile::Gitignore => Some(RegenTarget::Gitignore),
+                        _ => None,
+                    };
+                    if own_target.is_some_and(|target| targets.contains(&target)) {
+                        continue;
+                    }
+                    regenerate(PathBuf::from(file.file_name()), &|_: &str| {
+                        Ok(file.render(style))
+                    })?;
+                }
+            }
+        }
+    }
+    Ok(RegenPlan { root, changes })
+}
+
+/// Reads the indent width and column limit of an existing `.clang-format`,
+/// the defaults are used for the missing ones
+fn clang_format_style(content: &str) -> ClangFormatStyle {
+    let value = |key: &str| {
+        content.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if name.trim() != key {
+                return None;
+            }
+            value.trim().parse().ok()
+        })
+    };
+    let default = ClangFormatStyle::default();
+    ClangFormatStyle {
+        indent_width: value("IndentWidth").unwrap_or(default.indent_width),
+        column_limit: value("ColumnLimit").unwrap_or(default.column_limit),
+    }
+}
+
+/// Diffs two texts line by line (longest common subsequence)
+fn diff_lines(before: &str, after: &str) -> Vec<DiffLine> {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+
+    // common[i][j]: length of the common subsequence of before[i..] and after[j..]
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = if before[i] == after[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            i += 1;
+            j += 1;
+        } else if j < after.len() && (i == before.len() || common[i][j + 1] > common[i + 1][j]) {
+            diff.push(DiffLine::Added(after[j].to_string()));
+            j += 1;
+        } else {
+            diff.push(DiffLine::Removed(before[i].to_string()));
+            i += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdkconfig::SDKCONFIG_DEFAULTS_FILE;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nx\nc\nd\n"),
+            vec![
+                DiffLine::Removed("b".into()),
+                DiffLine::Added("x".into()),
+                DiffLine::Added("d".into()),
+            ]
+        );
+        assert!(diff_lines("a\n", "a\n").is_empty());
+    }
+
+    #[test]
+    fn test_plan_regen() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        test_fixtures::write_template(root);
+        fs::write(root.join(".clang-format"), "IndentWidth: 2\n").unwrap();
+        let main_c = root.join("main").join("main.c");
+
+        let plan = plan_regen(root, RegenTarget::ALL, true).unwrap();
+
+        let paths: Vec<&Path> = plan.changes.iter().map(|c| c.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("CMakeLists.txt"),
+                &Path::new("main").join("CMakeLists.txt"),
+                Path::new(".clang-format"),
+            ]
+        );
+        assert!(plan.changes[1].after.contains("idf_component_register"));
+        assert!(plan.changes[2].after.contains("IndentWidth: 2\n"));
+        assert!(plan.changes[2].after.contains("ColumnLimit: 100\n"));
+
+        // Nothing is written until the changes are applied
+        assert_eq!(
+            fs::read_to_string(root.join("main").join("CMakeLists.txt")).unwrap(),
+            test_fixtures::TEMPLATE_MAIN_CMAKE
+        );
+        for change in &plan.changes {
+            change.apply(root).unwrap();
+        }
+        assert!(plan_regen(root, RegenTarget::ALL, true)
+            .unwrap()
+            .changes
+            .is_empty());
+
+        // Only the hygiene files recorded in the metadata are refreshed
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::write(root.join(".editorconfig"), "root = true\n").unwrap();
+        ProjectMetadata {
+            hygiene: vec![HygieneFile::EditorConfig, HygieneFile::ClangFormat],
+            ..ProjectMetadata::default()
+        }
+        .write(root)
+        .unwrap();
+        let plan = plan_regen(root, &[RegenTarget::Hygiene], true).unwrap();
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].path, Path::new(".editorconfig"));
+        assert!(plan.changes[0].after.contains("indent_size = 2\n"));
+        assert_eq!(
+            fs::read_to_string(main_c).unwrap(),
+            test_fixtures::TEMPLATE_MAIN_C
+        );
+    }
+
+    #[test]
+    fn test_plan_regen_gitignore_presets() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        test_fixtures::write_template(root);
+        let targets = [RegenTarget::Gitignore, RegenTarget::Presets];
+        // Only the files the project has are rewritten
+        assert!(plan_regen(root, &targets, true).unwrap().changes.is_empty());
+
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::write(root.join("CMakePresets.json"), "{}\n").unwrap();
+        fs::write(
+            root.join(SDKCONFIG_DEFAULTS_FILE),
+            "CONFIG_IDF_TARGET=\"esp32c3\"\n",
+        )
+        .unwrap();
+        // The hygiene target leaves .gitignore to its own target
+        ProjectMetadata {
+            hygiene: vec![HygieneFile::Gitignore],
+            ..ProjectMetadata::default()
+        }
+        .write(root)
+        .unwrap();
+        let plan = plan_regen(
+            root,
+            &[
+                RegenTarget::Gitignore,
+                RegenTarget::Presets,
+                RegenTarget::Hygiene,
+            ],
+            true,
+        )
+        .unwrap();
+
+        let paths: Vec<&Path> = plan.changes.iter().map(|c| c.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [Path::new(".gitignore"), Path::new("CMakePresets.json")]
+        );
+        assert_eq!(plan.changes[0].after, templates::GITIGNORE_TEMPLATE);
+        let presets = &plan.changes[1].after;
+        assert!(presets.contains("toolchain-esp32c3.cmake"));
+        assert!(presets.contains("\"IDF_TARGET\": \"esp32c3\""));
+        assert!(presets.contains("\"displayName\": \"app-template (esp32c3)\""));
+
+        assert_eq!("presets".parse(), Ok(RegenTarget::Presets));
+        assert_eq!(RegenTarget::Gitignore.to_string(), "gitignore");
+    }
+}
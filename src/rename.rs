@@ -0,0 +1,342 @@
+//! `rename`: renames an existing project everywhere the generator wrote its name
+
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cmake;
+use crate::idf_component::IDF_COMPONENT_FILE;
+use crate::metadata::ProjectMetadata;
+use crate::naming::ProjectNames;
+use crate::project::{self, LOCAL_CONFIG_FILE};
+use crate::templates;
+
+/// Optional behaviors of [`rename_project`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenameOptions {
+    /// Whether to also rename the project directory
+    pub move_dir: bool,
+    /// Whether to run even if the git tree has uncommitted changes
+    pub allow_dirty: bool,
+}
+
+/// The changed lines of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEdit {
+    /// The file, relative to the project root
+    pub path: PathBuf,
+    /// Every changed line, before and after the edit
+    pub lines: Vec<(String, String)>,
+}
+
+/// What [`rename_project`] changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameReport {
+    /// The project root, after the move if the directory was renamed
+    pub root: PathBuf,
+    /// The edited files
+    pub edits: Vec<FileEdit>,
+}
+
+/// Renames the project that contains `start`
+///
+/// Updates the name in `project(...)` of the top-level CMakeLists.txt, the
+/// README title, the git repo description, the description of
+/// `main/idf_component.yml`, the presets of `CMakePresets.json` and the
+/// project metadata file. Everything but `project(...)` is only changed
+/// where it still holds the old name.
+///
+/// # Arguments
+/// * `start` - A directory inside the project, usually the current directory
+/// * `new_name` - The new name, as it would be typed when creating the project
+/// * `options` - Whether to move the directory and to accept a dirty git tree
+///
+/// # Returns
+/// The new root and the changed lines of every edited file
+///
+/// # Errors
+/// If `start` is not inside a project, the name is not a valid directory name,
+/// the git tree has uncommitted changes and `allow_dirty` is not set, the
+/// destination directory already exists or the files cannot be written
+pub fn rename_project(
+    start: &Path,
+    new_name: &str,
+    options: RenameOptions,
+) -> anyhow::Result<RenameReport> {
+    let names = ProjectNames::new(new_name)?;
+    if names.directory.components().count() != 1 {
+        bail!("\"{}\" must be a name, not a path", new_name);
+    }
+    let root = project::find_project_root(start).context(format!(
+        "\"{}\" is not inside an ESP-IDF project (no CMakeLists.txt including project.cmake found)",
+        start.display()
+    ))?;
+    if !options.allow_dirty
+        && project::has_uncommitted_changes(&root).context(format!(
+            "Cannot tell if \"{}\" has uncommitted changes, pass --allow-dirty to skip the check",
+            root.display()
+        ))?
+    {
+        bail!(
+            "\"{}\" has uncommitted changes, commit them or pass --allow-dirty",
+            root.display()
+        );
+    }
+
+    let old_display = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let cmake_path = Path::new("CMakeLists.txt");
+    let cmake = read(&root, cmake_path)?;
+    let old_cmake = cmake::project_name(&cmake)
+        .context("The CMakeLists.txt of the project has no project(...) command")?
+        .to_string();
+
+    // Every file is rewritten in memory first, so nothing is written if one fails
+    let mut files = vec![(
+        cmake_path.to_path_buf(),
+        cmake::rename_project(&cmake, &names.cmake)?,
+        cmake,
+    )];
+    let readme_path = Path::new("README.md");
+    if root.join(readme_path).is_file() {
+        let readme = read(&root, readme_path)?;
+        let title = readme.lines().next().unwrap_or_default();
+        if title == format!("# {}", old_display) || title == format!("# {}", old_cmake) {
+            let renamed = format!("# {}{}", names.display, &readme[title.len()..]);
+            files.push((readme_path.to_path_buf(), renamed, readme));
+        }
+    }
+    let manifest_path = Path::new("main").join(IDF_COMPONENT_FILE);
+    if root.join(&manifest_path).is_file() {
+        let manifest = read(&root, &manifest_path)?;
+        let renamed = [&old_display, &old_cmake]
+            .iter()
+            .fold(manifest.clone(), |manifest, old| {
+                manifest.replace(
+                    &format!("Main component of {}\"", old),
+                    &format!("Main component of {}\"", names.display),
+                )
+            });
+        files.push((manifest_path, renamed, manifest));
+    }
+    let presets_path = Path::new(templates::CMAKE_PRESETS_FILE);
+    if root.join(presets_path).is_file() {
+        let presets = read(&root, presets_path)?;
+        let renamed = replace_word(&presets, &old_cmake, &names.cmake);
+        let renamed = replace_word(&renamed, &old_display, &names.display);
+        files.push((presets_path.to_path_buf(), renamed, presets));
+    }
+    if root.join(LOCAL_CONFIG_FILE).is_file() {
+        let content = read(&root, Path::new(LOCAL_CONFIG_FILE))?;
+        let mut metadata = ProjectMetadata::load(&root)?;
+        if metadata.project.as_ref() == Some(&old_display) {
+            metadata.project = Some(names.display.clone());
+            files.push((LOCAL_CONFIG_FILE.into(), metadata.to_toml()?, content));
+        }
+    }
+    let description_path = Path::new(".git").join("description");
+    if root.join(&description_path).is_file() {
+        let description = read(&root, &description_path)?;
+        if description.trim_end() == old_display {
+            files.push((
+                description_path,
+                format!("{}\n", names.display),
+                description,
+            ));
+        }
+    }
+
+    let destination = root.with_file_name(&names.directory);
+    if options.move_dir && destination != root && destination.exists() {
+        bail!("\"{}\" already exists", destination.display());
+    }
+
+    let mut edits = Vec::new();
+    for (path, renamed, original) in files {
+        if renamed == original {
+            continue;
+        }
+        fs::write(root.join(&path), &renamed)
+            .context(format!("Cannot write \"{}\"", root.join(&path).display()))?;
+        edits.push(FileEdit {
+            lines: changed_lines(&original, &renamed),
+            path,
+        });
+    }
+
+    if options.move_dir && destination != root {
+        fs::rename(&root, &destination).context(format!(
+            "Cannot move \"{}\" to \"{}\"",
+            root.display(),
+            destination.display()
+        ))?;
+        return Ok(RenameReport {
+            root: destination,
+            edits,
+        });
+    }
+    Ok(RenameReport { root, edits })
+}
+
+/// Reads a file of the project
+fn read(root: &Path, path: &Path) -> anyhow::Result<String> {
+    let path = root.join(path);
+    fs::read_to_string(&path).context(format!("Cannot read \"{}\"", path.display()))
+}
+
+/// Replaces the whole-word occurrences of `old`, not the ones inside a
+/// longer name (`blink` in `blink_test` or `my-blink`)
+fn replace_word(content: &str, old: &str, new: &str) -> String {
+    if old.is_empty() {
+        return content.to_string();
+    }
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut replaced = String::with_capacity(content.len());
+    let mut copied = 0;
+    for (start, _) in content.match_indices(old) {
+        let end = start + old.len();
+        if content[..start].chars().next_back().is_some_and(is_name)
+            || content[end..].chars().next().is_some_and(is_name)
+        {
+            continue;
+        }
+        replaced.push_str(&content[copied..start]);
+        replaced.push_str(new);
+        copied = end;
+    }
+    replaced.push_str(&content[copied..]);
+    replaced
+}
+
+/// Pairs the lines that differ, the rewrites never add or remove lines
+fn changed_lines(before: &str, after: &str) -> Vec<(String, String)> {
+    before
+        .lines()
+        .zip(after.lines())
+        .filter(|(before, after)| before != after)
+        .map(|(before, after)| (before.to_string(), after.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+
+    /// Writes a project named `blink` like the generator does
+    fn blink_project(parent: &Path) -> PathBuf {
+        let root = parent.join("blink");
+        test_fixtures::write_template(&root);
+        let cmake = fs::read_to_string(root.join("CMakeLists.txt")).unwrap();
+        let cmake = cmake::rewrite_project_cmake(&cmake, "", "blink").unwrap();
+        fs::write(root.join("CMakeLists.txt"), cmake).unwrap();
+        fs::write(root.join("README.md"), "# blink\n\nBlinks a LED.\n").unwrap();
+        fs::write(
+            root.join("main").join(IDF_COMPONENT_FILE),
+            "version: \"0.1.0\"\ndescription: \"Main component of blink\"\n",
+        )
+        .unwrap();
+        ProjectMetadata {
+            project: Some("blink".into()),
+            ..ProjectMetadata::default()
+        }
+        .write(&root)
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn test_rename_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = blink_project(dir.path());
+        let options = RenameOptions {
+            allow_dirty: true,
+            ..RenameOptions::default()
+        };
+
+        let report = rename_project(&root.join("main"), "led strip", options).unwrap();
+
+        assert_eq!(report.root, root);
+        let cmake = fs::read_to_string(root.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.ends_with("project(led_strip)"));
+        assert_eq!(
+            fs::read_to_string(root.join("README.md")).unwrap(),
+            "# led strip\n\nBlinks a LED.\n"
+        );
+        assert_eq!(
+            report.edits[0].lines,
+            vec![(
+                "project(blink)".to_string(),
+                "project(led_strip)".to_string()
+            )]
+        );
+        assert_eq!(report.edits[1].path, Path::new("README.md"));
+        assert_eq!(
+            fs::read_to_string(root.join("main").join(IDF_COMPONENT_FILE)).unwrap(),
+            "version: \"0.1.0\"\ndescription: \"Main component of led strip\"\n"
+        );
+        assert_eq!(
+            ProjectMetadata::load(&root).unwrap().project.as_deref(),
+            Some("led strip")
+        );
+    }
+
+    #[test]
+    fn test_rename_project_presets() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = blink_project(dir.path());
+        fs::write(
+            root.join(templates::CMAKE_PRESETS_FILE),
+            "{\n  \"displayName\": \"blink debug\",\n  \"binaryDir\": \"build/blink_test\"\n}\n",
+        )
+        .unwrap();
+        let options = RenameOptions {
+            allow_dirty: true,
+            ..RenameOptions::default()
+        };
+
+        let report = rename_project(&root, "sensor", options).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join(templates::CMAKE_PRESETS_FILE)).unwrap(),
+            "{\n  \"displayName\": \"sensor debug\",\n  \"binaryDir\": \"build/blink_test\"\n}\n"
+        );
+        assert!(report
+            .edits
+            .iter()
+            .any(|edit| edit.path == Path::new(templates::CMAKE_PRESETS_FILE)));
+    }
+
+    #[test]
+    fn test_replace_word() {
+        assert_eq!(
+            replace_word("blink blink_test my-blink (blink)", "blink", "led"),
+            "led blink_test my-blink (led)"
+        );
+        assert_eq!(replace_word("blink", "", "led"), "blink");
+    }
+
+    #[test]
+    fn test_rename_project_move() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = blink_project(dir.path());
+        let options = RenameOptions {
+            move_dir: true,
+            allow_dirty: true,
+        };
+
+        fs::create_dir(dir.path().join("taken")).unwrap();
+        assert!(rename_project(&root, "taken", options).is_err());
+        // Nothing is written when the destination exists
+        assert!(fs::read_to_string(root.join("CMakeLists.txt"))
+            .unwrap()
+            .ends_with("project(blink)"));
+
+        let report = rename_project(&root, "sensor", options).unwrap();
+        assert_eq!(report.root, dir.path().join("sensor"));
+        assert!(!root.exists());
+        assert!(report.root.join("CMakeLists.txt").exists());
+    }
+}
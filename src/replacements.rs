@@ -0,0 +1,316 @@
+//! Content replacements declared by a template in the `replacements` of its
+//! `template.toml`, e.g. to rename the example the template was made from in
+//! every file, not only in the ones [`crate::templatize`] handles
+//!
+//! ```toml
+//! [[replacements]]
+//! literal = "blink_example"
+//! with = "{{project_name}}"
+//!
+//! [[replacements]]
+//! regex = "BLINK_EXAMPLE_([A-Z]+)"
+//! with = "{{project_name}}_$1"
+//! ```
+//!
+//! The regexes are matched in linear time (the `regex` crate doesn't
+//! backtrack), and a pattern too long or compiling to a too large program is
+//! rejected, so a template cannot hang the generation.
+
+use anyhow::{bail, Context};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::naming::ProjectNames;
+
+/// Longest pattern accepted, in bytes
+pub const MAX_PATTERN_LENGTH: usize = 256;
+
+/// Largest compiled regex accepted, in bytes
+const REGEX_SIZE_LIMIT: usize = 256 * 1024;
+
+/// Largest file the replacements are applied to, larger files are left as
+/// they are
+pub const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Bytes read at the start of a file to tell text from binary
+const BINARY_CHECK_LENGTH: usize = 8 * 1024;
+
+/// A replacement of the `template.toml` of a template
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replacement {
+    /// Text replaced as it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub literal: Option<String>,
+    /// Pattern replaced, its groups can be used in `with` as `$1`, `$name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// The replacement; `{{project_name}}` is replaced by the CMake name of
+    /// the project and `{{display_name}}` by its name as typed
+    pub with: String,
+}
+
+/// A [`Replacement`] ready to be applied, see [`compile`]
+#[derive(Debug)]
+pub struct CompiledReplacement {
+    pattern: Pattern,
+    with: String,
+}
+
+#[derive(Debug)]
+enum Pattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+/// How many replacements were made in a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileReplacements {
+    /// The file, relative to the project
+    pub path: PathBuf,
+    pub count: usize,
+}
+
+/// Compiles the replacements once for every file, with the variables of
+/// `with` filled in
+///
+/// # Arguments
+/// * `replacements` - The replacements of the template
+/// * `names` - The names of the new project
+///
+/// # Errors
+/// If a replacement has no pattern or both kinds, an empty pattern, a
+/// pattern longer than [`MAX_PATTERN_LENGTH`], or an invalid or too complex
+/// regex
+pub fn compile(
+    replacements: &[Replacement],
+    names: &ProjectNames,
+) -> anyhow::Result<Vec<CompiledReplacement>> {
+    replacements
+        .iter()
+        .map(|replacement| {
+            let source = match (&replacement.literal, &replacement.regex) {
+                (Some(source), None) | (None, Some(source)) => source,
+                _ => bail!("A replacement needs either \"literal\" or \"regex\""),
+            };
+            if source.is_empty() {
+                bail!("A replacement cannot have an empty pattern");
+            }
+            if source.len() > MAX_PATTERN_LENGTH {
+                bail!(
+                    "The replacement pattern \"{}...\" is longer than {} bytes",
+                    source.chars().take(20).collect::<String>(),
+                    MAX_PATTERN_LENGTH
+                );
+            }
+            let pattern = match replacement.literal {
+                Some(_) => Pattern::Literal(source.clone()),
+                None => Pattern::Regex(
+                    RegexBuilder::new(source)
+                        .size_limit(REGEX_SIZE_LIMIT)
+                        .dfa_size_limit(REGEX_SIZE_LIMIT)
+                        .build()
+                        .context(format!("Invalid replacement regex \"{}\"", source))?,
+                ),
+            };
+            let with = replacement
+                .with
+                .replace("{{project_name}}", &names.cmake)
+                .replace("{{display_name}}", &names.display);
+            Ok(CompiledReplacement { pattern, with })
+        })
+        .collect()
+}
+
+/// Applies the replacements to a text, in order
+///
+/// # Returns
+/// The new text and the number of replacements made
+pub fn apply(text: &str, replacements: &[CompiledReplacement]) -> (String, usize) {
+    let mut text = text.to_string();
+    let mut count = 0;
+    for replacement in replacements {
+        match &replacement.pattern {
+            Pattern::Literal(literal) => {
+                let found = text.matches(literal.as_str()).count();
+                if found > 0 {
+                    text = text.replace(literal.as_str(), &replacement.with);
+                    count += found;
+                }
+            }
+            Pattern::Regex(regex) => {
+                let found = regex.find_iter(&text).count();
+                if found > 0 {
+                    text = regex
+                        .replace_all(&text, replacement.with.as_str())
+                        .into_owned();
+                    count += found;
+                }
+            }
+        }
+    }
+    (text, count)
+}
+
+/// Whether the content looks binary: a NUL byte near its start, or not UTF-8
+fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_LENGTH)].contains(&0)
+        || std::str::from_utf8(content).is_err()
+}
+
+/// Applies the replacements to every text file of the project up to
+/// [`MAX_FILE_SIZE`]; binary files (images, archives...) are left as they are
+///
+/// # Arguments
+/// * `directory` - The directory of the extracted project
+/// * `replacements` - The compiled replacements
+///
+/// # Returns
+/// The files changed and how many replacements were made in each
+///
+/// # Errors
+/// If a file cannot be read or written
+pub fn apply_to_tree(
+    directory: &Path,
+    replacements: &[CompiledReplacement],
+) -> anyhow::Result<Vec<FileReplacements>> {
+    let mut changed = Vec::new();
+    if replacements.is_empty() {
+        return Ok(changed);
+    }
+    for relative in archive::list_files(directory, &[])? {
+        let path = directory.join(&relative);
+        let metadata =
+            fs::symlink_metadata(&path).context(format!("Cannot read \"{}\"", path.display()))?;
+        if !metadata.is_file() || metadata.len() > MAX_FILE_SIZE {
+            continue;
+        }
+        let content = fs::read(&path).context(format!("Cannot read \"{}\"", path.display()))?;
+        if is_binary(&content) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&content);
+        let (replaced, count) = apply(&text, replacements);
+        if count > 0 {
+            fs::write(&path, replaced).context(format!("Cannot write \"{}\"", path.display()))?;
+            changed.push(FileReplacements {
+                path: relative,
+                count,
+            });
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(literal: &str, with: &str) -> Replacement {
+        Replacement {
+            literal: Some(literal.into()),
+            regex: None,
+            with: with.into(),
+        }
+    }
+
+    fn regex(regex: &str, with: &str) -> Replacement {
+        Replacement {
+            literal: None,
+            regex: Some(regex.into()),
+            with: with.into(),
+        }
+    }
+
+    #[test]
+    fn test_compile_and_apply() {
+        let names = ProjectNames::new("My Sensor").unwrap();
+        let replacements = compile(
+            &[
+                literal("blink_example", "{{project_name}}"),
+                regex(r"BLINK_EXAMPLE_([A-Z]+)", "SENSOR_$1"),
+                literal("$1", "{{display_name}}"),
+            ],
+            &names,
+        )
+        .unwrap();
+
+        let (text, count) = apply(
+            "blink_example(); BLINK_EXAMPLE_PIN BLINK_EXAMPLE_LEVEL $1",
+            &replacements,
+        );
+        assert_eq!(
+            text,
+            format!(
+                "{}(); SENSOR_PIN SENSOR_LEVEL {}",
+                names.cmake, names.display
+            )
+        );
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_compile_rejects_invalid_patterns() {
+        let names = ProjectNames::new("blink").unwrap();
+        let invalid = [
+            Replacement {
+                literal: None,
+                regex: None,
+                with: String::new(),
+            },
+            Replacement {
+                literal: Some("a".into()),
+                regex: Some("a".into()),
+                with: String::new(),
+            },
+            literal("", "x"),
+            regex("(unclosed", "x"),
+            literal(&"a".repeat(MAX_PATTERN_LENGTH + 1), "x"),
+            // Compiles to a program far over the size limit
+            regex(r"\w{1000}\w{1000}", "x"),
+        ];
+        for replacement in &invalid {
+            assert!(
+                compile(std::slice::from_ref(replacement), &names).is_err(),
+                "{:?}",
+                replacement
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_to_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("main")).unwrap();
+        fs::write(dir.path().join("main").join("blink.c"), "blink blink\n").unwrap();
+        fs::write(dir.path().join("README.md"), "# Nothing\n").unwrap();
+        let image = [0x89, b'P', b'N', b'G', 0, b'b', b'l', b'i', b'n', b'k'];
+        fs::write(dir.path().join("logo.png"), image).unwrap();
+        let large = "blink\n".repeat(MAX_FILE_SIZE as usize / 6 + 1);
+        fs::write(dir.path().join("large.txt"), &large).unwrap();
+
+        let names = ProjectNames::new("sensor").unwrap();
+        let replacements = compile(&[literal("blink", "{{project_name}}")], &names).unwrap();
+        let changed = apply_to_tree(dir.path(), &replacements).unwrap();
+
+        assert_eq!(
+            changed,
+            [FileReplacements {
+                path: Path::new("main").join("blink.c"),
+                count: 2
+            }]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("main").join("blink.c")).unwrap(),
+            "sensor sensor\n"
+        );
+        assert_eq!(fs::read(dir.path().join("logo.png")).unwrap(), image);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("large.txt")).unwrap(),
+            large
+        );
+    }
+}
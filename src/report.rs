@@ -0,0 +1,337 @@
+//! `--report`: a record of what the generator did, for teams that need to
+//! audit how a project was created
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::build_deps::BuildDepCheck;
+use crate::generator::GenerationReport;
+use crate::options::{ProgrammingLanguage, ScaffoldOptions};
+use crate::self_update::{sha256_reader, CURRENT_VERSION};
+
+/// Entries of the project that are not hashed, they are not generated files
+const UNHASHED_ENTRIES: &[&str] = &[".git"];
+
+/// A file of the project and the SHA-256 of its content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileHash {
+    /// Path relative to the project directory, with `/` separators
+    pub path: String,
+    pub sha256: String,
+}
+
+/// Hashes every file under `root`, sorted by path
+///
+/// # Arguments
+/// * `root` - The project directory
+///
+/// # Errors
+/// If a directory or a file cannot be read
+pub fn hash_files(root: &Path) -> anyhow::Result<Vec<FileHash>> {
+    let mut hashes = Vec::new();
+    hash_dir(root, "", &mut hashes)?;
+    hashes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(hashes)
+}
+
+fn hash_dir(root: &Path, relative: &str, hashes: &mut Vec<FileHash>) -> anyhow::Result<()> {
+    let dir = root.join(relative);
+    let entries = fs::read_dir(&dir).context(format!("Cannot read \"{}\"", dir.display()))?;
+    for entry in entries {
+        let entry = entry.context(format!("Cannot read \"{}\"", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if relative.is_empty() && UNHASHED_ENTRIES.contains(&name.as_str()) {
+            continue;
+        }
+        let path = if relative.is_empty() {
+            name
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        if entry.path().is_dir() {
+            hash_dir(root, &path, hashes)?;
+        } else {
+            let sha256 = fs::File::open(entry.path())
+                .and_then(|mut file| sha256_reader(&mut file))
+                .context(format!("Cannot read \"{}\"", path))?;
+            hashes.push(FileHash { path, sha256 });
+        }
+    }
+    Ok(())
+}
+
+/// Where the template came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateProvenance {
+    /// The URL or path the template was read from
+    pub url: String,
+    /// The git ref of the official template, if one was selected
+    pub git_ref: Option<String>,
+    /// SHA-256 of the template archive, `None` if it wasn't downloaded
+    pub sha256: Option<String>,
+}
+
+/// A tool of the build environment and its version, `None` if missing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolVersion {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+/// The machine the project was generated on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Environment {
+    pub os: String,
+    pub arch: String,
+    /// `IDF_PATH` when the project was generated
+    pub idf_path: Option<String>,
+    /// The build tools, as in `--check-build-deps`
+    pub tools: Vec<ToolVersion>,
+}
+
+impl Environment {
+    /// Builds the summary from the result of the build dependency checks
+    ///
+    /// # Arguments
+    /// * `checks` - See [`crate::build_deps::check_build_deps`]
+    pub fn new(checks: &[BuildDepCheck]) -> Self {
+        Environment {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            idf_path: std::env::var("IDF_PATH").ok(),
+            tools: checks
+                .iter()
+                .map(|check| ToolVersion {
+                    name: check.name.to_string(),
+                    version: check.version.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The features selected for the project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Features {
+    pub language: ProgrammingLanguage,
+    pub board: Option<String>,
+    pub target: Option<String>,
+    pub starters: Vec<String>,
+    pub workspace_apps: Vec<String>,
+    pub git: bool,
+}
+
+/// The record written by `--report`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportFile<'a> {
+    pub tool_version: &'static str,
+    pub project: String,
+    /// `completed`, `failed` or `cancelled`
+    pub status: &'static str,
+    /// Why the generation failed, with its causes
+    pub error: Option<String>,
+    pub template: TemplateProvenance,
+    pub features: Features,
+    pub environment: Environment,
+    /// What the generator did, up to the failure if it failed
+    pub generation: &'a GenerationReport,
+}
+
+impl<'a> ReportFile<'a> {
+    /// Builds the record of a generation
+    ///
+    /// # Arguments
+    /// * `options` - The options of the generation
+    /// * `report` - What the generator did, complete or not
+    /// * `status` - `completed`, `failed` or `cancelled`
+    /// * `error` - The error if the generation failed
+    /// * `environment` - The machine, see [`Environment::new`]
+    pub fn new(
+        options: &ScaffoldOptions,
+        report: &'a GenerationReport,
+        status: &'static str,
+        error: Option<String>,
+        environment: Environment,
+    ) -> Self {
+        ReportFile {
+            tool_version: CURRENT_VERSION,
+            project: options.project_name.clone(),
+            status,
+            error,
+            template: TemplateProvenance {
+                url: options.template_url().into_owned(),
+                git_ref: options.template_ref.clone(),
+                sha256: report.template_sha256.clone(),
+            },
+            features: Features {
+                language: options.language,
+                board: options.board.clone(),
+                target: options.target.clone(),
+                starters: options.starters.clone(),
+                workspace_apps: options.workspace_apps.clone(),
+                git: options.use_git,
+            },
+            environment,
+            generation: report,
+        }
+    }
+
+    /// Formats the record as JSON
+    pub fn to_json(&self) -> String {
+        // Only strings, lists and structs, it cannot fail
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Formats the record as text, one section per part
+    pub fn to_text(&self) -> String {
+        let none = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        let mut text = format!(
+            "esp-create-project {}\nProject:  {}\nStatus:   {}\n",
+            self.tool_version, self.project, self.status
+        );
+        if let Some(error) = &self.error {
+            text.push_str(&format!("Error:    {}\n", error));
+        }
+
+        text.push_str(&format!(
+            "\nTemplate\n  URL:    {}\n  Ref:    {}\n  SHA256: {}\n",
+            self.template.url,
+            none(&self.template.git_ref),
+            none(&self.template.sha256)
+        ));
+
+        let features = &self.features;
+        text.push_str(&format!(
+            "\nFeatures\n  Language:  {:?}\n  Board:     {}\n  Target:    {}\n  Starters:  {}\n  Git:       {}\n",
+            features.language,
+            none(&features.board),
+            none(&features.target),
+            features.starters.join(", "),
+            match (features.git, self.generation.git_initialized) {
+                (true, true) => "yes",
+                (true, false) => "yes (not initialized)",
+                (false, _) => "no",
+            }
+        ));
+        if !features.workspace_apps.is_empty() {
+            text.push_str(&format!(
+                "  Apps:      {}\n",
+                features.workspace_apps.join(", ")
+            ));
+        }
+
+        let environment = &self.environment;
+        text.push_str(&format!(
+            "\nEnvironment\n  OS:       {} ({})\n  IDF_PATH: {}\n",
+            environment.os,
+            environment.arch,
+            none(&environment.idf_path)
+        ));
+        for tool in &environment.tools {
+            text.push_str(&format!(
+                "  {}: {}\n",
+                tool.name,
+                tool.version.as_deref().unwrap_or("not found")
+            ));
+        }
+
+        text.push_str("\nSteps\n");
+        for step in &self.generation.steps {
+            text.push_str(&format!("  {}\n", step));
+        }
+        if !self.generation.stages.is_empty() {
+            text.push_str("\nStages\n");
+        }
+        for stage in &self.generation.stages {
+            text.push_str(&format!("  {}: {}", stage.name, stage.status));
+            if stage.attempts > 1 {
+                text.push_str(&format!(" after {} attempts", stage.attempts));
+            }
+            if let Some(error) = &stage.error {
+                text.push_str(&format!(" ({})", error));
+            }
+            text.push('\n');
+        }
+        text.push_str("\nFiles (SHA256)\n");
+        for file in &self.generation.files {
+            text.push_str(&format!("  {}  {}\n", file.sha256, file.path));
+        }
+        for entry in &self.generation.failed_entries {
+            text.push_str(&format!(
+                "  not extracted: {} ({})\n",
+                entry.name, entry.error
+            ));
+        }
+        for file in &self.generation.replacements {
+            text.push_str(&format!(
+                "  replaced: {} ({} times)\n",
+                file.path.display(),
+                file.count
+            ));
+        }
+        for step in &self.generation.skipped_post_processing {
+            text.push_str(&format!("  skipped: {}\n", step));
+        }
+        for app in &self.generation.failed_apps {
+            text.push_str(&format!(
+                "  not written: app {} ({})\n",
+                app.name, app.error
+            ));
+        }
+        for step in &self.generation.manual_steps {
+            text.push_str(&format!("  manual step: {}\n", step));
+        }
+        text
+    }
+
+    /// Writes the record, as JSON if the path ends with `.json` and as text
+    /// otherwise
+    ///
+    /// # Errors
+    /// If the file cannot be written
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let content = if is_json {
+            self.to_json()
+        } else {
+            self.to_text()
+        };
+        fs::write(path, content).context(format!("Cannot write the report \"{}\"", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_update::sha256_hex;
+
+    #[test]
+    fn test_hash_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("main")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join("main").join("main.c"), "abc").unwrap();
+        fs::write(dir.path().join("CMakeLists.txt"), "").unwrap();
+        fs::write(dir.path().join(".git").join("HEAD"), "ref").unwrap();
+
+        let hashes = hash_files(dir.path()).unwrap();
+
+        assert_eq!(
+            hashes,
+            [
+                FileHash {
+                    path: "CMakeLists.txt".into(),
+                    sha256: sha256_hex(b""),
+                },
+                FileHash {
+                    path: "main/main.c".into(),
+                    sha256: sha256_hex(b"abc"),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use anyhow::Context;
+use std::fs;
+use std::path::Path;
+
+/// Name of the file ESP-IDF reads the default configuration from
+pub const SDKCONFIG_DEFAULTS_FILE: &str = "sdkconfig.defaults";
+
+/// `CONFIG_*` entries written to `sdkconfig.defaults`, kept in insertion order
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SdkConfigDefaults {
+    entries: Vec<(String, String)>,
+    /// Comments written above the entries they belong to
+    comments: Vec<(String, String)>,
+}
+
+impl SdkConfigDefaults {
+    /// Creates an empty set of defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the `KEY=value` lines of an existing file, comments are skipped
+    ///
+    /// # Arguments
+    /// * `content` - The content of a `sdkconfig.defaults` or `sdkconfig`
+    pub fn parse(content: &str) -> Self {
+        let mut defaults = Self::new();
+        for line in content.lines().map(str::trim) {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                defaults.set(key.trim(), value.trim());
+            }
+        }
+        defaults
+    }
+
+    /// Reads the raw value of a key
+    ///
+    /// # Arguments
+    /// * `key` - The config key, including the `CONFIG_` prefix
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The entries, in the order they were set
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    /// Sets a config value, replacing the previous value of the key
+    ///
+    /// # Arguments
+    /// * `key` - The config key, including the `CONFIG_` prefix
+    /// * `value` - The raw value, e.g. `y` or `"text"`
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value.into(),
+            None => self.entries.push((key.into(), value.into())),
+        }
+    }
+
+    /// Enables a boolean config key
+    ///
+    /// # Arguments
+    /// * `key` - The config key, including the `CONFIG_` prefix
+    pub fn enable(&mut self, key: &str) {
+        self.set(key, "y");
+    }
+
+    /// Sets the comment written above a key, e.g. a warning about its
+    /// risks; a comment set again replaces the previous one
+    ///
+    /// # Arguments
+    /// * `key` - The config key, including the `CONFIG_` prefix
+    /// * `text` - The comment, one `# ` line per line of text
+    pub fn comment(&mut self, key: &str, text: &str) {
+        match self.comments.iter_mut().find(|(k, _)| k == key) {
+            Some(comment) => comment.1 = text.into(),
+            None => self.comments.push((key.into(), text.into())),
+        }
+    }
+
+    /// An entry in the `KEY=value` format, preceded by its comment
+    fn render_entry(&self, key: &str, value: &str) -> String {
+        let mut rendered = String::new();
+        if let Some((_, text)) = self.comments.iter().find(|(k, _)| k == key) {
+            for line in text.lines() {
+                rendered.push_str(format!("# {}", line).trim_end());
+                rendered.push('\n');
+            }
+        }
+        rendered.push_str(&format!("{}={}\n", key, value));
+        rendered
+    }
+
+    /// Renders the entries in the `KEY=value` format used by ESP-IDF, each
+    /// after its comment
+    pub fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| self.render_entry(key, value))
+            .collect()
+    }
+
+    /// The entries on one line, `KEY=value, KEY=value`, for the messages
+    pub fn summary(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Appends the entries whose key is not in the existing file yet
+    ///
+    /// Keys already set keep their value, so user settings are never overridden.
+    ///
+    /// # Arguments
+    /// * `content` - The content of the existing `sdkconfig.defaults`
+    ///
+    /// # Returns
+    /// The new content of the file
+    pub fn append_missing(&self, content: &str) -> String {
+        let existing: Vec<&str> = content
+            .lines()
+            .filter_map(|line| line.split_once('=').map(|(key, _)| key.trim()))
+            .collect();
+        let mut result = content.to_string();
+        for (key, value) in &self.entries {
+            if existing.contains(&key.as_str()) {
+                continue;
+            }
+            if !result.is_empty() && !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str(&self.render_entry(key, value));
+        }
+        result
+    }
+
+    /// Merges the entries into an existing file, these values win
+    ///
+    /// Keys already set (or disabled with `# CONFIG_X is not set`) get the new
+    /// value in place, the other lines and comments are kept, and the keys not
+    /// in the file yet are appended; the comments of the entries are written
+    /// above them either way. The opposite of [`SdkConfigDefaults::append_missing`].
+    ///
+    /// # Arguments
+    /// * `content` - The content of the existing `sdkconfig.defaults`
+    ///
+    /// # Returns
+    /// The new content of the file
+    pub fn merge_over(&self, content: &str) -> String {
+        let mut written = Vec::new();
+        let mut result = String::with_capacity(content.len() + 64);
+        for line in content.lines() {
+            let trimmed = line.trim();
+            let key = match trimmed.split_once('=') {
+                Some((key, _)) if !trimmed.starts_with('#') => Some(key.trim()),
+                _ => trimmed
+                    .strip_prefix("# ")
+                    .and_then(|rest| rest.strip_suffix(" is not set")),
+            };
+            match key.and_then(|key| self.get(key).map(|value| (key, value))) {
+                Some((key, value)) => {
+                    result.push_str(&self.render_entry(key, value));
+                    written.push(key.to_string());
+                }
+                None => {
+                    result.push_str(line);
+                    result.push('\n');
+                }
+            }
+        }
+        for (key, value) in &self.entries {
+            if !written.contains(key) {
+                result.push_str(&self.render_entry(key, value));
+            }
+        }
+        result
+    }
+
+    /// Writes the entries to the `sdkconfig.defaults` file of the project
+    ///
+    /// A file shipped by the template is merged, see [`SdkConfigDefaults::merge_over`].
+    ///
+    /// # Arguments
+    /// * `directory` - The directory that contains the project
+    ///
+    /// # Errors
+    /// If the existing file cannot be read or the file cannot be written
+    pub fn write(&self, directory: &str) -> anyhow::Result<()> {
+        let path = Path::new(directory).join(SDKCONFIG_DEFAULTS_FILE);
+        let existing = if path.is_file() {
+            fs::read_to_string(&path).context("Cannot read sdkconfig.defaults")?
+        } else {
+            String::new()
+        };
+        fs::write(&path, self.merge_over(&existing)).context("Cannot write sdkconfig.defaults")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_keeps_order_and_replaces() {
+        let mut defaults = SdkConfigDefaults::new();
+        defaults.enable("CONFIG_B");
+        defaults.set("CONFIG_A", "1");
+        defaults.set("CONFIG_B", "n");
+
+        assert_eq!(defaults.render(), "CONFIG_B=n\nCONFIG_A=1\n");
+    }
+
+    #[test]
+    fn test_render_comments() {
+        let mut defaults = SdkConfigDefaults::new();
+        defaults.enable("CONFIG_A");
+        defaults.enable("CONFIG_B");
+        defaults.comment("CONFIG_B", "Irreversible\n\nSee the docs");
+
+        let rendered = "CONFIG_A=y\n# Irreversible\n#\n# See the docs\nCONFIG_B=y\n";
+        assert_eq!(defaults.render(), rendered);
+        assert_eq!(defaults.merge_over(""), rendered);
+        assert_eq!(
+            defaults.merge_over("CONFIG_B=n\n"),
+            "# Irreversible\n#\n# See the docs\nCONFIG_B=y\nCONFIG_A=y\n"
+        );
+        assert_eq!(
+            defaults.append_missing("CONFIG_A=n\n"),
+            "CONFIG_A=n\n# Irreversible\n#\n# See the docs\nCONFIG_B=y\n"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let defaults = SdkConfigDefaults::parse(
+            "# Comment\nCONFIG_IDF_TARGET=\"esp32s3\"\n\n# CONFIG_A is not set\nCONFIG_B = y\n",
+        );
+        assert_eq!(defaults.get("CONFIG_IDF_TARGET"), Some("\"esp32s3\""));
+        assert_eq!(defaults.get("CONFIG_B"), Some("y"));
+        assert_eq!(defaults.get("CONFIG_A"), None);
+        assert_eq!(defaults.entries().len(), 2);
+    }
+
+    #[test]
+    fn test_merge_over_prefers_new_values() {
+        let mut defaults = SdkConfigDefaults::new();
+        defaults.set("CONFIG_IDF_TARGET", "\"esp32c3\"");
+        defaults.enable("CONFIG_A");
+        defaults.enable("CONFIG_NEW");
+
+        let template = "# From the template\nCONFIG_IDF_TARGET=\"esp32\"\nCONFIG_KEEP=42\n# CONFIG_A is not set\n";
+        assert_eq!(
+            defaults.merge_over(template),
+            "# From the template\nCONFIG_IDF_TARGET=\"esp32c3\"\nCONFIG_KEEP=42\nCONFIG_A=y\nCONFIG_NEW=y\n"
+        );
+        assert_eq!(defaults.merge_over(""), defaults.render());
+    }
+
+    #[test]
+    fn test_write_merges_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SDKCONFIG_DEFAULTS_FILE);
+        fs::write(&path, "CONFIG_FREERTOS_HZ=1000\nCONFIG_A=n\n").unwrap();
+        let mut defaults = SdkConfigDefaults::new();
+        defaults.enable("CONFIG_A");
+
+        defaults.write(dir.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "CONFIG_FREERTOS_HZ=1000\nCONFIG_A=y\n"
+        );
+    }
+
+    #[test]
+    fn test_append_missing_keeps_existing_values() {
+        let mut defaults = SdkConfigDefaults::new();
+        defaults.enable("CONFIG_A");
+        defaults.enable("CONFIG_B");
+
+        assert_eq!(
+            defaults.append_missing("# Comment\nCONFIG_A=n"),
+            "# Comment\nCONFIG_A=n\nCONFIG_B=y\n"
+        );
+        assert_eq!(defaults.append_missing(""), "CONFIG_A=y\nCONFIG_B=y\n");
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+
+use crate::http;
+
+/// GitHub API endpoint of the latest release of this tool
+pub const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/Alan5142/esp-create-project/releases/latest";
+
+/// Version of the running binary
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Extension of the checksum file published next to every release binary
+pub const CHECKSUM_EXTENSION: &str = ".sha256";
+
+/// A GitHub release, only the fields used by the update
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<ReleaseAsset>,
+}
+
+/// A file attached to a GitHub release
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// Finds an asset by name
+    pub fn asset(&self, name: &str) -> Option<&ReleaseAsset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+
+    /// Checks if the release is newer than the given version
+    pub fn is_newer_than(&self, version: &str) -> bool {
+        match (parse_version(&self.tag_name), parse_version(version)) {
+            (Some(latest), Some(current)) => latest > current,
+            _ => false,
+        }
+    }
+}
+
+/// Parses a `vMAJOR.MINOR.PATCH` version, ignoring pre-release and build suffixes
+///
+/// # Arguments
+/// * `version` - The version, with or without the `v` prefix
+///
+/// # Returns
+/// The version numbers, or `None` if the version is malformed
+pub fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    let mut numbers = version.split('.').map(|n| n.parse::<u64>().ok());
+    let major = numbers.next()??;
+    let minor = numbers.next().unwrap_or(Some(0))?;
+    let patch = numbers.next().unwrap_or(Some(0))?;
+    if numbers.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/// Name of the release asset built for the platform of the running binary
+///
+/// # Returns
+/// The asset name, or `None` if no binary is released for this platform
+pub fn platform_asset_name() -> Option<&'static str> {
+    if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+        Some("esp-create-project-linux-x86_64")
+    } else if cfg!(all(target_os = "linux", target_arch = "aarch64")) {
+        Some("esp-create-project-linux-aarch64")
+    } else if cfg!(all(target_os = "windows", target_arch = "x86_64")) {
+        Some("esp-create-project-windows-x86_64.exe")
+    } else {
+        None
+    }
+}
+
+/// Extracts the hash of a file from a checksum file
+///
+/// Both a bare hash and the `sha256sum` format (`<hash>  <file name>`, one
+/// file per line) are accepted.
+///
+/// # Arguments
+/// * `content` - The content of the checksum file
+/// * `file_name` - The name of the checksummed file
+///
+/// # Returns
+/// The lowercase hex hash, or `None` if the file has no hash for `file_name`
+pub fn parse_checksum(content: &str, file_name: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let hash = parts.next()?;
+        let is_hash = hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit());
+        match parts.next().map(|name| name.trim_start_matches('*')) {
+            Some(name) if name == file_name && is_hash => Some(hash.to_lowercase()),
+            None if is_hash => Some(hash.to_lowercase()),
+            _ => None,
+        }
+    })
+}
+
+/// Size of the chunks hashed by [`sha256_reader`]
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Computes the SHA-256 of the data as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+/// Computes the SHA-256 of everything the reader returns as lowercase hex,
+/// in chunks so a large file is never read in memory at once
+///
+/// # Errors
+/// If the reader fails
+pub fn sha256_reader(reader: &mut impl Read) -> std::io::Result<String> {
+    let mut reader = HashingReader::new(reader);
+    let mut buffer = [0; HASH_BUFFER_SIZE];
+    while reader.read(&mut buffer)? != 0 {}
+    Ok(reader.finish())
+}
+
+/// A reader that computes the SHA-256 of what is read through it, for a
+/// copy that hashes what it copies
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// The SHA-256 of the bytes read so far, as lowercase hex
+    pub fn finish(self) -> String {
+        hex(&self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Formats bytes, e.g. a digest, as lowercase hex
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Fetches the latest release from GitHub
+///
+/// # Errors
+/// If the API cannot be reached or its response is not a release
+pub fn latest_release() -> anyhow::Result<Release> {
+    fetch_latest_release(&http::agent(LATEST_RELEASE_URL)?)
+}
+
+/// Fetches the latest release from GitHub with the given agent
+///
+/// # Arguments
+/// * `agent` - The agent used for the request, see [`http`]
+///
+/// # Errors
+/// If the API cannot be reached or its response is not a release
+pub fn fetch_latest_release(agent: &ureq::Agent) -> anyhow::Result<Release> {
+    let response = agent
+        .get(LATEST_RELEASE_URL)
+        .call()
+        .context("Cannot check the latest release")?
+        .into_string()
+        .context("Cannot check the latest release")?;
+    serde_json::from_str(&response).context("Invalid release information")
+}
+
+/// Downloads a release asset and verifies it against its checksum asset
+///
+/// # Arguments
+/// * `release` - The release that contains the asset
+/// * `asset_name` - The name of the asset to download
+///
+/// # Returns
+/// The verified content of the asset
+///
+/// # Errors
+/// If an asset is missing, cannot be downloaded or the checksum doesn't match
+pub fn download_verified(release: &Release, asset_name: &str) -> anyhow::Result<Vec<u8>> {
+    let asset = release
+        .asset(asset_name)
+        .ok_or_else(|| anyhow!("Release {} has no {} binary", release.tag_name, asset_name))?;
+    let checksum_name = format!("{}{}", asset_name, CHECKSUM_EXTENSION);
+    let checksum_asset = release.asset(&checksum_name).ok_or_else(|| {
+        anyhow!(
+            "Release {} has no checksum for {}, refusing to install it",
+            release.tag_name,
+            asset_name
+        )
+    })?;
+
+    let checksum_file = http::agent(&checksum_asset.browser_download_url)?
+        .get(&checksum_asset.browser_download_url)
+        .call()
+        .context("Cannot download the checksum file")?
+        .into_string()
+        .context("Invalid checksum file")?;
+    let expected = parse_checksum(&checksum_file, asset_name)
+        .ok_or_else(|| anyhow!("The checksum file has no hash for {}", asset_name))?;
+
+    let mut binary = Vec::new();
+    http::agent(&asset.browser_download_url)?
+        .get(&asset.browser_download_url)
+        .call()
+        .context("Cannot download the new version")?
+        .into_reader()
+        .read_to_end(&mut binary)
+        .context("Cannot download the new version")?;
+
+    let actual = sha256_hex(&binary);
+    if actual != expected {
+        bail!(
+            "Checksum mismatch for {} (expected {}, got {}), the download may be corrupted",
+            asset_name,
+            expected,
+            actual
+        );
+    }
+    Ok(binary)
+}
+
+/// Replaces the running executable with the new binary
+///
+/// The binary is written to a temp file that is then renamed over the current
+/// executable, so an interrupted update never leaves a half-written binary. On
+/// Windows the running executable cannot be overwritten, `self_replace` moves
+/// it away first and schedules its deletion.
+///
+/// # Arguments
+/// * `binary` - The new executable
+///
+/// # Errors
+/// If the new executable cannot be written or moved in place
+pub fn replace_current_exe(binary: &[u8]) -> anyhow::Result<()> {
+    let tmp_dir = tempfile::tempdir().context("Cannot create a temp directory")?;
+    let new_exe = tmp_dir.path().join("esp-create-project-update");
+    fs::write(&new_exe, binary).context("Cannot write the new version")?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755))
+            .context("Cannot make the new version executable")?;
+    }
+
+    self_replace::self_replace(&new_exe).context("Cannot replace the current executable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("v0.2.2"), Some((0, 2, 2)));
+        assert_eq!(parse_version("1.10.0"), Some((1, 10, 0)));
+        assert_eq!(parse_version("v2"), Some((2, 0, 0)));
+        assert_eq!(parse_version("v1.2.3-beta.1"), Some((1, 2, 3)));
+        assert_eq!(parse_version("latest"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn test_is_newer_than() {
+        let release = Release {
+            tag_name: "v0.10.0".into(),
+            assets: vec![],
+        };
+        assert!(release.is_newer_than("0.2.2"));
+        assert!(!release.is_newer_than("0.10.0"));
+        assert!(!release.is_newer_than("1.0.0"));
+    }
+
+    #[test]
+    fn test_parse_release() {
+        let release: Release = serde_json::from_str(
+            r#"{
+                "tag_name": "v0.3.0",
+                "name": "ignored",
+                "assets": [
+                    {"name": "esp-create-project-linux-x86_64", "browser_download_url": "https://example.com/bin", "size": 1}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(release.tag_name, "v0.3.0");
+        assert!(release.asset("esp-create-project-linux-x86_64").is_some());
+        assert!(release.asset("missing").is_none());
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let hash = "a".repeat(64);
+        assert_eq!(parse_checksum(&hash, "bin"), Some(hash.clone()));
+        assert_eq!(
+            parse_checksum(
+                &format!("{}  other\n{}  *bin\n", "b".repeat(64), hash),
+                "bin"
+            ),
+            Some(hash.clone())
+        );
+        assert_eq!(parse_checksum("not a hash  bin", "bin"), None);
+        assert_eq!(parse_checksum(&format!("{}  other", hash), "bin"), None);
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_hashing_reader() {
+        let content = vec![7; HASH_BUFFER_SIZE * 2 + 5];
+        assert_eq!(
+            sha256_reader(&mut content.as_slice()).unwrap(),
+            sha256_hex(&content)
+        );
+
+        // The bytes are passed through as read
+        let mut reader = HashingReader::new(&b"abc"[..]);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"abc");
+        assert_eq!(reader.finish(), sha256_hex(b"abc"));
+    }
+
+    fn fixture_release(binary: &[u8], checksum: String) -> Release {
+        let base = test_fixtures::serve_routes(vec![
+            ("/bin", binary.to_vec()),
+            ("/bin.sha256", checksum.into_bytes()),
+        ]);
+        Release {
+            tag_name: "v9.0.0".into(),
+            assets: vec![
+                ReleaseAsset {
+                    name: "bin".into(),
+                    browser_download_url: format!("{}/bin", base),
+                },
+                ReleaseAsset {
+                    name: "bin.sha256".into(),
+                    browser_download_url: format!("{}/bin.sha256", base),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_download_verified() {
+        let binary = b"new version".to_vec();
+        let release = fixture_release(&binary, format!("{}  bin\n", sha256_hex(&binary)));
+        assert_eq!(download_verified(&release, "bin").unwrap(), binary);
+    }
+
+    #[test]
+    fn test_download_verified_rejects_mismatch() {
+        let release = fixture_release(b"tampered", format!("{}  bin\n", sha256_hex(b"original")));
+        let error = download_verified(&release, "bin").unwrap_err();
+        assert!(error.to_string().contains("Checksum mismatch"));
+    }
+}
@@ -0,0 +1,292 @@
+//! Serial port detection, shared by the commands that talk to a board
+
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::Path;
+
+use crate::platform;
+
+/// Directory Linux lists the USB devices in, one directory per device with
+/// its `idVendor` and `idProduct`
+pub const SYSFS_USB_DEVICES: &str = "/sys/bus/usb/devices";
+
+/// Registry key Windows lists the COM ports in, one value per port
+pub const SERIALCOMM_KEY: &str = r"HKLM\HARDWARE\DEVICEMAP\SERIALCOMM";
+
+/// USB-serial chips found on ESP development boards, by USB vendor id and
+/// product id (`None` matches every product of the vendor)
+const KNOWN_CHIPS: &[(u16, Option<u16>, &str)] = &[
+    (0x303a, None, "Espressif USB-Serial/JTAG"),
+    (0x10c4, Some(0xea60), "Silicon Labs CP210x"),
+    (0x1a86, Some(0x7523), "WCH CH340"),
+    (0x1a86, Some(0x55d4), "WCH CH9102"),
+    (0x0403, None, "FTDI"),
+];
+
+/// A serial port of the machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialDevice {
+    /// The name passed to `idf.py -p`, e.g. `/dev/ttyUSB0` or `COM3`
+    pub name: String,
+    /// USB vendor and product id, `None` for non-USB ports
+    pub usb_id: Option<(u16, u16)>,
+    /// Product name reported by the USB device
+    pub product: Option<String>,
+    /// The USB-serial chip, `None` if it's not one used by ESP boards
+    pub chip: Option<&'static str>,
+}
+
+impl SerialDevice {
+    /// Checks if the port is likely an ESP board
+    pub fn is_esp_board(&self) -> bool {
+        self.chip.is_some()
+    }
+
+    /// Describes the device, e.g. `Silicon Labs CP210x (10c4:ea60), CP2102N
+    /// USB to UART Bridge`
+    pub fn description(&self) -> String {
+        let mut description = match (self.chip, self.usb_id) {
+            (Some(chip), Some((vid, pid))) => format!("{} ({:04x}:{:04x})", chip, vid, pid),
+            (None, Some((vid, pid))) => format!("USB device {:04x}:{:04x}", vid, pid),
+            (_, None) => "not a USB device".to_string(),
+        };
+        if let Some(product) = &self.product {
+            description.push_str(&format!(", {}", product));
+        }
+        description
+    }
+}
+
+/// Finds the USB-serial chip of a USB device
+///
+/// # Arguments
+/// * `vid` - The USB vendor id
+/// * `pid` - The USB product id
+///
+/// # Returns
+/// The name of the chip, `None` if it's not one used by ESP boards
+pub fn identify(vid: u16, pid: u16) -> Option<&'static str> {
+    KNOWN_CHIPS
+        .iter()
+        .find(|(known_vid, known_pid, _)| *known_vid == vid && known_pid.is_none_or(|p| p == pid))
+        .map(|(_, _, name)| *name)
+}
+
+/// Lists the serial ports of the machine
+///
+/// # Errors
+/// If the ports cannot be enumerated
+pub fn list_ports() -> anyhow::Result<Vec<SerialDevice>> {
+    let ports = serialport::available_ports().context("Cannot list the serial ports")?;
+    Ok(ports
+        .into_iter()
+        .map(|port| match port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => SerialDevice {
+                name: port.port_name,
+                usb_id: Some((usb.vid, usb.pid)),
+                product: usb.product,
+                chip: identify(usb.vid, usb.pid),
+            },
+            _ => SerialDevice {
+                name: port.port_name,
+                usb_id: None,
+                product: None,
+                chip: None,
+            },
+        })
+        .collect())
+}
+
+/// Lists the USB devices plugged in, serial or not, from sysfs: a device
+/// missing from [`list_ports`] has no driver; Linux only
+///
+/// # Arguments
+/// * `devices_dir` - The sysfs directory, [`SYSFS_USB_DEVICES`] outside tests
+///
+/// # Returns
+/// The USB vendor and product ids, empty if the directory cannot be read
+pub fn usb_devices(devices_dir: &Path) -> Vec<(u16, u16)> {
+    let entries = match fs::read_dir(devices_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let read_id = |dir: &Path, file: &str| {
+        fs::read_to_string(dir.join(file))
+            .ok()
+            .and_then(|id| u16::from_str_radix(id.trim(), 16).ok())
+    };
+    let mut devices: Vec<(u16, u16)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let dir = entry.path();
+            Some((read_id(&dir, "idVendor")?, read_id(&dir, "idProduct")?))
+        })
+        .collect();
+    devices.sort_unstable();
+    devices.dedup();
+    devices
+}
+
+/// Lists the COM ports of the registry with the device behind each, e.g.
+/// `("COM3", "\Device\Silabser0")`; Windows only
+///
+/// # Errors
+/// If `reg` cannot be run
+pub fn registry_ports() -> anyhow::Result<Vec<(String, String)>> {
+    let output = platform::command("reg")
+        .args(["query", SERIALCOMM_KEY])
+        .output()
+        .context("Cannot run reg query")?;
+    // The key only exists once a serial port was seen
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    Ok(parse_serialcomm(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the output of `reg query` on [`SERIALCOMM_KEY`], see
+/// [`registry_ports`]
+///
+/// # Arguments
+/// * `output` - The output, a `DEVICE REG_SZ PORT` line per port
+pub fn parse_serialcomm(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            if fields.next()? != "REG_SZ" {
+                return None;
+            }
+            Some((fields.next()?.to_string(), device.to_string()))
+        })
+        .collect()
+}
+
+/// Finds the port of the connected ESP board
+///
+/// # Errors
+/// If the ports cannot be enumerated, or there isn't exactly one ESP board
+pub fn detect_port() -> anyhow::Result<String> {
+    pick_port(&list_ports()?)
+}
+
+/// Picks the only ESP board among the ports, see [`detect_port`]
+///
+/// # Arguments
+/// * `ports` - The serial ports of the machine
+///
+/// # Errors
+/// If no port or more than one port is an ESP board
+pub fn pick_port(ports: &[SerialDevice]) -> anyhow::Result<String> {
+    let boards: Vec<&SerialDevice> = ports.iter().filter(|port| port.is_esp_board()).collect();
+    match boards.as_slice() {
+        [board] => Ok(board.name.clone()),
+        [] => bail!(
+            "no ESP board found on the serial ports, check the USB cable (some only charge) \
+             and the USB-serial driver, or pass the port with --port"
+        ),
+        _ => {
+            let names: Vec<&str> = boards.iter().map(|board| board.name.as_str()).collect();
+            bail!(
+                "several ESP boards found ({}), pick one with --port",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str, usb_id: Option<(u16, u16)>) -> SerialDevice {
+        SerialDevice {
+            name: name.into(),
+            usb_id,
+            product: None,
+            chip: usb_id.and_then(|(vid, pid)| identify(vid, pid)),
+        }
+    }
+
+    #[test]
+    fn test_identify() {
+        assert_eq!(identify(0x10c4, 0xea60), Some("Silicon Labs CP210x"));
+        assert_eq!(identify(0x303a, 0x1001), Some("Espressif USB-Serial/JTAG"));
+        assert_eq!(identify(0x1a86, 0x0001), None);
+        assert_eq!(identify(0x046d, 0xc52b), None);
+    }
+
+    #[test]
+    fn test_description() {
+        let mut cp210x = device("/dev/ttyUSB0", Some((0x10c4, 0xea60)));
+        assert_eq!(cp210x.description(), "Silicon Labs CP210x (10c4:ea60)");
+        cp210x.product = Some("CP2102N USB to UART Bridge".into());
+        assert_eq!(
+            cp210x.description(),
+            "Silicon Labs CP210x (10c4:ea60), CP2102N USB to UART Bridge"
+        );
+        assert_eq!(
+            device("/dev/ttyACM0", Some((0x046d, 0xc52b))).description(),
+            "USB device 046d:c52b"
+        );
+        assert_eq!(device("/dev/ttyS0", None).description(), "not a USB device");
+    }
+
+    #[test]
+    fn test_usb_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, ids) in [
+            ("1-1", Some(("10c4", "ea60\n"))),
+            ("1-2", Some(("1a86", "7523\n"))),
+            // An interface of the first device, no ids
+            ("1-1:1.0", None),
+            ("usb1", Some(("1d6b", "0002\n"))),
+        ] {
+            let device = dir.path().join(name);
+            fs::create_dir(&device).unwrap();
+            if let Some((vid, pid)) = ids {
+                fs::write(device.join("idVendor"), vid).unwrap();
+                fs::write(device.join("idProduct"), pid).unwrap();
+            }
+        }
+        assert_eq!(
+            usb_devices(dir.path()),
+            [(0x10c4, 0xea60), (0x1a86, 0x7523), (0x1d6b, 0x0002)]
+        );
+        assert!(usb_devices(&dir.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn test_parse_serialcomm() {
+        let output = "\r\nHKEY_LOCAL_MACHINE\\HARDWARE\\DEVICEMAP\\SERIALCOMM\r\n    \\Device\\Silabser0    REG_SZ    COM3\r\n    \\Device\\Serial0    REG_SZ    COM1\r\n\r\n";
+        assert_eq!(
+            parse_serialcomm(output),
+            [
+                ("COM3".to_string(), "\\Device\\Silabser0".to_string()),
+                ("COM1".to_string(), "\\Device\\Serial0".to_string()),
+            ]
+        );
+        assert!(parse_serialcomm("").is_empty());
+    }
+
+    #[test]
+    fn test_pick_port() {
+        let serial = device("/dev/ttyS0", None);
+        let cp210x = device("/dev/ttyUSB0", Some((0x10c4, 0xea60)));
+        let ch340 = device("/dev/ttyUSB1", Some((0x1a86, 0x7523)));
+
+        assert_eq!(
+            pick_port(&[serial.clone(), cp210x.clone()]).unwrap(),
+            "/dev/ttyUSB0"
+        );
+        assert!(pick_port(&[serial])
+            .unwrap_err()
+            .to_string()
+            .contains("no ESP board"));
+        assert!(pick_port(&[cp210x, ch340])
+            .unwrap_err()
+            .to_string()
+            .contains("/dev/ttyUSB0, /dev/ttyUSB1"));
+    }
+}
@@ -0,0 +1,147 @@
+//! `--report-size`: a rough estimate of the size of the built app for the
+//! selected starters, compared with its app partition, so beginners pick a
+//! flash size and partition table it fits in. It's guidance from a static
+//! table, not a measurement of a build.
+
+use crate::options::ScaffoldOptions;
+use crate::partitions;
+
+/// Size of an app with nothing but `app_main`, the bootloader not included
+pub const BASE_APP_SIZE_KB: u32 = 200;
+
+/// App partition of the built-in partition table of ESP-IDF
+pub const DEFAULT_APP_PARTITION_KB: u32 = 1024;
+
+/// Share of the app partition above which a larger partition is advised,
+/// in percent
+const TIGHT_PERCENT: u32 = 75;
+
+/// What each starter typically adds to the app: id, size in KB, and what
+/// takes the space
+pub const STARTER_SIZES_KB: &[(&str, u32, &str)] = &[
+    ("nvs", 20, "the NVS library"),
+    ("wifi", 550, "the Wi-Fi driver and the TCP/IP stack"),
+    ("ethernet", 250, "the Ethernet driver and the TCP/IP stack"),
+    ("mqtt", 60, "the MQTT client and TLS"),
+    ("led", 15, "the RMT driver and led_strip"),
+    ("scheduler", 2, "its callbacks, esp_timer is in every app"),
+    ("events", 5, "the esp_event loop and its task"),
+    ("console", 40, "esp_console, linenoise and argtable3"),
+];
+
+/// The estimate of [`estimate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeHint {
+    /// Estimated size of the app in KB
+    pub app_size_kb: u32,
+    /// Size of the app partition in KB, the smallest one of a custom table
+    pub partition_kb: u32,
+    /// What each starter adds: id, size in KB and what takes the space
+    pub starters: Vec<(String, u32, &'static str)>,
+}
+
+/// Estimates the size of the app of a project
+///
+/// # Arguments
+/// * `options` - The options of the project, its starters and partitions
+pub fn estimate(options: &ScaffoldOptions) -> SizeHint {
+    let starters: Vec<(String, u32, &'static str)> = options
+        .starters
+        .iter()
+        .filter_map(|id| {
+            STARTER_SIZES_KB
+                .iter()
+                .find(|(starter, _, _)| starter == id)
+                .map(|(_, size_kb, reason)| (id.clone(), *size_kb, *reason))
+        })
+        .collect();
+    let partition_kb = options
+        .partitions
+        .and_then(|layout| {
+            layout
+                .partitions()
+                .iter()
+                .filter(|partition| partition.kind == "app")
+                .filter_map(|partition| partitions::parse_size(&partition.size))
+                .min()
+                .map(|size| (size / 1024) as u32)
+        })
+        .unwrap_or(DEFAULT_APP_PARTITION_KB);
+    SizeHint {
+        app_size_kb: BASE_APP_SIZE_KB + starters.iter().map(|(_, size, _)| size).sum::<u32>(),
+        partition_kb,
+        starters,
+    }
+}
+
+impl SizeHint {
+    /// Whether the app is expected to take more than three quarters of its
+    /// partition, leaving little room for the application code
+    pub fn is_tight(&self) -> bool {
+        self.app_size_kb * 100 > self.partition_kb * TIGHT_PERCENT
+    }
+
+    /// The lines printed after the generation
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "The app should take about {}KB of its {}KB partition (a rough estimate, not a measurement)",
+            self.app_size_kb, self.partition_kb
+        )];
+        lines.push(format!("~{}KB for a minimal app", BASE_APP_SIZE_KB));
+        for (id, size_kb, reason) in &self.starters {
+            lines.push(format!("~{}KB for {}: {}", size_kb, id, reason));
+        }
+        if self.is_tight() {
+            lines.push(
+                "Little room is left for your code: pick a larger app partition (--app-size, --partition-scheme single_app_large) or flash size"
+                    .to_string(),
+            );
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partitions::{PartitionLayout, PartitionScheme};
+
+    #[test]
+    fn test_estimate() {
+        let mut options =
+            ScaffoldOptions::from_json(r#"{"project_name": "blink", "language": "c"}"#).unwrap();
+        let hint = estimate(&options);
+        assert_eq!(hint.app_size_kb, BASE_APP_SIZE_KB);
+        assert_eq!(hint.partition_kb, DEFAULT_APP_PARTITION_KB);
+        assert!(!hint.is_tight());
+
+        options.starters = vec!["nvs".into(), "wifi".into(), "mqtt".into()];
+        let hint = estimate(&options);
+        assert_eq!(hint.app_size_kb, 830);
+        assert!(hint.is_tight());
+        assert!(hint
+            .lines()
+            .iter()
+            .any(|line| line.contains("~550KB for wifi")));
+
+        options.partitions = Some(PartitionLayout {
+            flash_size_mb: 4,
+            scheme: PartitionScheme::SingleAppLarge,
+            app_size_kb: None,
+        });
+        let hint = estimate(&options);
+        assert_eq!(hint.partition_kb, 1536);
+        assert!(!hint.is_tight());
+    }
+
+    #[test]
+    fn test_every_starter_has_a_size() {
+        for starter in crate::starters::STARTERS {
+            assert!(
+                STARTER_SIZES_KB.iter().any(|(id, _, _)| *id == starter.id),
+                "{} has no size",
+                starter.id
+            );
+        }
+    }
+}
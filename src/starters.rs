@@ -0,0 +1,2081 @@
+//! Starters: ready-made pieces of code (Wi-Fi, MQTT, NVS...) added to the main
+//! component, either when the project is generated or later with `add`
+//!
+//! A starter is only data (files, Kconfig, requirements, supported chips,
+//! other starters it needs...), applying it to a project is the same whether
+//! the project was just generated or not, and the prompts, `add` and the
+//! README table are all driven by [`STARTERS`].
+
+use anyhow::{anyhow, bail, Context};
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cmake;
+use crate::idf_component::{self, IDF_COMPONENT_FILE};
+use crate::idf_version::IdfVersion;
+use crate::sdkconfig::{SdkConfigDefaults, SDKCONFIG_DEFAULTS_FILE};
+use crate::templates;
+
+/// Line of the built-in main files before which the starter calls are inserted
+pub const APP_MAIN_MARKER: &str = "// TODO Insert code";
+
+/// How the code of the project calls the starters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integration {
+    /// Free functions called from `app_main` in `main.c` or `main.cpp`
+    AppMain,
+    /// Calls in `App::init` of `app.cpp`, the application class layout of
+    /// C++ projects
+    AppClass,
+}
+
+impl Integration {
+    /// The integration of a project, [`Integration::AppClass`] if its main
+    /// component has the `App` class
+    ///
+    /// # Arguments
+    /// * `main_dir` - The `main/` directory of the project
+    pub fn detect(main_dir: &Path) -> Self {
+        if main_dir.join(templates::APP_CLASS_SOURCE_FILE).exists() {
+            Integration::AppClass
+        } else {
+            Integration::AppMain
+        }
+    }
+
+    /// The files of `main/` the calls are inserted in, the first one found
+    /// is used
+    pub fn source_files(self) -> &'static [&'static str] {
+        match self {
+            Integration::AppMain => &["main.c", "main.cpp"],
+            Integration::AppClass => &[templates::APP_CLASS_SOURCE_FILE],
+        }
+    }
+
+    /// The function the init functions of the starters are called from
+    pub fn caller(self) -> &'static str {
+        match self {
+            Integration::AppMain => "app_main",
+            Integration::AppClass => "App::init",
+        }
+    }
+}
+
+/// A starter added to the main component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Starter {
+    /// Identifier used on the command line
+    pub id: &'static str,
+    /// One-line description shown in the prompts
+    pub description: &'static str,
+    /// Chips the starter runs on, every chip if empty
+    pub targets: &'static [&'static str],
+    /// Other starters it needs, each entry is satisfied by any one of its ids
+    /// (e.g. a network: `wifi` or `ethernet`)
+    pub needs: &'static [&'static [&'static str]],
+    /// Files written to `main/`, name and content
+    pub files: &'static [(&'static str, &'static str)],
+    /// Menu appended to `main/Kconfig.projbuild`, empty for none
+    pub kconfig: &'static str,
+    /// IDF components added to the requirements of the main component
+    pub requires: &'static [&'static str],
+    /// Entries added to `sdkconfig.defaults`
+    pub sdkconfig: &'static [(&'static str, &'static str)],
+    /// Managed components added to `main/idf_component.yml`, name and version
+    pub dependencies: &'static [(&'static str, &'static str)],
+    /// Header that declares the init function
+    pub header: &'static str,
+    /// Function to call from `app_main` or `App::init`, without arguments
+    pub init_function: &'static str,
+    /// Oldest ESP-IDF with the APIs the starter uses, any version if `None`
+    pub min_idf_version: Option<IdfVersion>,
+    /// Snippets added to its files when another starter is in the project
+    pub hooks: &'static [StarterHook],
+    /// Command of the factory test app that checks the hardware of the
+    /// starter, see [`crate::factory_test`]
+    pub factory_test: Option<FactoryTest>,
+}
+
+/// A snippet added to a file of a starter when another starter is in the
+/// project, e.g. the Wi-Fi starter posting its state on the loop of the
+/// `events` starter instead of only setting its event group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StarterHook {
+    /// Id of the starter that must be in the project
+    pub with: &'static str,
+    /// The file of [`Starter::files`] the snippet is added to
+    pub file: &'static str,
+    /// The line the snippet is inserted after, without its indentation; it
+    /// must be in the file exactly once
+    pub after: &'static str,
+    /// The lines to insert, indented like the `after` line
+    pub snippet: &'static str,
+}
+
+/// A console command of the factory test app, e.g. blinking the LED of the
+/// `led` starter on the production line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FactoryTest {
+    /// Name of the console command
+    pub command: &'static str,
+    /// Help of the command, shown by `help`
+    pub help: &'static str,
+    /// Headers the body needs besides the one of the starter
+    pub includes: &'static [&'static str],
+    /// Body of the C function of the command, unindented; it returns 0 if
+    /// the test passed
+    pub body: &'static str,
+}
+
+/// What to do when a file of the starter already exists
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Abort before writing anything
+    #[default]
+    Fail,
+    /// Keep the existing file
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+}
+
+impl FromStr for MergeMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "fail" => Ok(MergeMode::Fail),
+            "skip" => Ok(MergeMode::Skip),
+            "overwrite" => Ok(MergeMode::Overwrite),
+            _ => Err(format!(
+                "unknown mode \"{}\", expected fail, skip or overwrite",
+                mode
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MergeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MergeMode::Fail => "fail",
+            MergeMode::Skip => "skip",
+            MergeMode::Overwrite => "overwrite",
+        })
+    }
+}
+
+/// Outcome of applying a starter
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StarterReport {
+    /// Files written to `main/`
+    pub written: Vec<PathBuf>,
+    /// Existing files kept because of [`MergeMode::Skip`]
+    pub skipped: Vec<PathBuf>,
+    /// Changes that could not be made safely and must be done by hand
+    pub manual_steps: Vec<String>,
+}
+
+/// Every built-in starter
+pub const STARTERS: &[Starter] = &[NVS, WIFI, ETHERNET, MQTT, LED, SCHEDULER, EVENTS, CONSOLE];
+
+/// Finds a built-in starter by id
+///
+/// # Errors
+/// If no starter has this id, the message lists the available ones
+pub fn find(id: &str) -> anyhow::Result<&'static Starter> {
+    STARTERS
+        .iter()
+        .find(|starter| starter.id == id)
+        .ok_or_else(|| {
+            let ids: Vec<&str> = STARTERS.iter().map(|starter| starter.id).collect();
+            anyhow!(
+                "Unknown starter \"{}\", available starters: {}",
+                id,
+                ids.join(", ")
+            )
+        })
+}
+
+impl Starter {
+    /// Checks if the starter runs on the chip
+    ///
+    /// # Arguments
+    /// * `target` - The chip, as in `idf.py set-target`
+    pub fn supports(&self, target: &str) -> bool {
+        self.targets.is_empty() || self.targets.contains(&target)
+    }
+
+    /// Checks if the starter was applied to a project, all its files are there
+    ///
+    /// # Arguments
+    /// * `main_dir` - The `main/` directory of the project
+    pub fn is_in(&self, main_dir: &Path) -> bool {
+        self.files
+            .iter()
+            .all(|(name, _)| main_dir.join(name).exists())
+    }
+
+    /// The files of the starter with the snippets of its hooks
+    ///
+    /// # Arguments
+    /// * `present` - Ids of the other starters in the project
+    pub fn files_with(&self, present: &[&str]) -> Vec<(&'static str, String)> {
+        self.files
+            .iter()
+            .map(|(name, content)| {
+                let hooks = self
+                    .hooks
+                    .iter()
+                    .filter(|hook| hook.file == *name && present.contains(&hook.with));
+                let content = hooks.fold(content.to_string(), |content, hook| {
+                    insert_snippet(&content, hook.after, hook.snippet)
+                });
+                (*name, content)
+            })
+            .collect()
+    }
+
+    /// The files the starter adds to the sources of `main/CMakeLists.txt`
+    pub fn sources(&self) -> Vec<&'static str> {
+        self.files
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| name.ends_with(".c") || name.ends_with(".cpp"))
+            .collect()
+    }
+}
+
+/// What a starter runs on and needs, a row of `esp-create-project
+/// starters`; the starters prompt lists the same rows, so the table and the
+/// prompt always agree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StarterRequirements {
+    pub id: &'static str,
+    pub description: &'static str,
+    /// Chips the starter runs on, every chip if empty
+    pub targets: Vec<&'static str>,
+    /// Oldest ESP-IDF, e.g. `5.0`, any version if `None`
+    pub min_idf_version: Option<String>,
+    /// Other starters it needs, each entry is satisfied by any one of its ids
+    pub needs: Vec<Vec<&'static str>>,
+    /// Managed components added to the project, name and version
+    pub dependencies: Vec<ManagedComponent>,
+}
+
+/// A managed component a starter adds, see [`Starter::dependencies`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ManagedComponent {
+    pub name: &'static str,
+    pub version: &'static str,
+}
+
+impl StarterRequirements {
+    /// The requirements of a starter
+    pub fn new(starter: &'static Starter) -> Self {
+        StarterRequirements {
+            id: starter.id,
+            description: starter.description,
+            targets: starter.targets.to_vec(),
+            min_idf_version: starter
+                .min_idf_version
+                .as_ref()
+                .map(|version| version.to_string()),
+            needs: starter.needs.iter().map(|ids| ids.to_vec()).collect(),
+            dependencies: starter
+                .dependencies
+                .iter()
+                .map(|(name, version)| ManagedComponent { name, version })
+                .collect(),
+        }
+    }
+
+    /// The chips, `all` if the starter runs on every chip
+    pub fn chips(&self) -> String {
+        if self.targets.is_empty() {
+            "all".to_string()
+        } else {
+            self.targets.join(", ")
+        }
+    }
+
+    /// The needed starters, e.g. `wifi or ethernet`; empty for none
+    pub fn needs_text(&self) -> Vec<String> {
+        self.needs.iter().map(|ids| ids.join(" or ")).collect()
+    }
+
+    /// Line of the starters prompt: the description, what it needs and the
+    /// oldest ESP-IDF
+    pub fn label(&self) -> String {
+        let mut notes = Vec::new();
+        if !self.needs.is_empty() {
+            notes.push(format!("needs {}", self.needs_text().join(", ")));
+        }
+        if let Some(version) = &self.min_idf_version {
+            notes.push(format!("ESP-IDF {}+", version));
+        }
+        if notes.is_empty() {
+            format!("{}: {}", self.id, self.description)
+        } else {
+            format!("{}: {} ({})", self.id, self.description, notes.join(", "))
+        }
+    }
+}
+
+/// The starters available on a chip, with their requirements: the ones that
+/// run on it, and for each of their needs one of the alternatives does
+///
+/// # Arguments
+/// * `target` - The chip, every starter if `None`
+pub fn compatibility(target: Option<&str>) -> Vec<StarterRequirements> {
+    let mut available: Vec<&Starter> = STARTERS
+        .iter()
+        .filter(|starter| target.is_none_or(|target| starter.supports(target)))
+        .collect();
+    // Dropping a starter can leave another without its needs
+    loop {
+        let before = available.len();
+        let ids: Vec<&str> = available.iter().map(|starter| starter.id).collect();
+        available.retain(|starter| {
+            starter
+                .needs
+                .iter()
+                .all(|alternatives| alternatives.iter().any(|id| ids.contains(id)))
+        });
+        if available.len() == before {
+            break;
+        }
+    }
+    available
+        .into_iter()
+        .map(StarterRequirements::new)
+        .collect()
+}
+
+/// Checks that the starters can be added together and orders them so each one
+/// comes after the starters it needs, which is the order of the init calls
+///
+/// # Arguments
+/// * `ids` - The ids of the starters, duplicates are ignored
+/// * `target` - The chip of the project, `None` if unknown (no chip is checked)
+///
+/// # Returns
+/// The starters, in the order they must be applied
+///
+/// # Errors
+/// If a starter is unknown, doesn't run on the chip, or needs a starter that
+/// is not in `ids`
+pub fn resolve(ids: &[String], target: Option<&str>) -> anyhow::Result<Vec<&'static Starter>> {
+    let mut selected: Vec<&'static Starter> = Vec::new();
+    for id in ids {
+        let starter = find(id)?;
+        if let Some(target) = target.filter(|target| !starter.supports(target)) {
+            bail!(
+                "Starter \"{}\" doesn't run on {}, only on {}",
+                starter.id,
+                target,
+                starter.targets.join(", ")
+            );
+        }
+        if !selected.contains(&starter) {
+            selected.push(starter);
+        }
+    }
+    for starter in &selected {
+        for alternatives in starter.needs {
+            if !selected
+                .iter()
+                .any(|other| alternatives.contains(&other.id))
+            {
+                bail!(
+                    "Starter \"{}\" needs {}, add it too",
+                    starter.id,
+                    alternatives.join(" or ")
+                );
+            }
+        }
+    }
+
+    // The needs are one level deep, so moving each starter after the ones it
+    // needs until nothing moves is enough
+    let mut ordered = selected;
+    let mut moved = true;
+    while moved {
+        moved = false;
+        for i in 0..ordered.len() {
+            // The starters it hooks into are applied first, so it sees them
+            let needed = ordered[i + 1..].iter().position(|other| {
+                ordered[i]
+                    .needs
+                    .iter()
+                    .any(|alternatives| alternatives.contains(&other.id))
+                    || ordered[i].hooks.iter().any(|hook| hook.with == other.id)
+            });
+            if let Some(offset) = needed {
+                let starter = ordered.remove(i);
+                ordered.insert(i + offset + 1, starter);
+                moved = true;
+                break;
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+/// Builds the Markdown table of the starters shown in the README
+pub fn markdown_table() -> String {
+    let mut table = String::from(
+        "| Starter | Description | Chips | Needs |\n| ------- | ----------- | ----- | ----- |\n",
+    );
+    for starter in compatibility(None) {
+        let needs: Vec<String> = starter
+            .needs
+            .iter()
+            .map(|alternatives| {
+                let ids: Vec<String> = alternatives.iter().map(|id| format!("`{}`", id)).collect();
+                ids.join(" or ")
+            })
+            .collect();
+        table.push_str(&format!(
+            "| `{}` | {} | {} | {} |\n",
+            starter.id,
+            starter.description,
+            starter.chips(),
+            if needs.is_empty() {
+                "-".to_string()
+            } else {
+                needs.join(", ")
+            }
+        ));
+    }
+    table
+}
+
+/// Applies a starter to the main component of a project
+///
+/// The files are written to `main/`, then the sources, requirements, Kconfig
+/// menu, `sdkconfig.defaults` entries and managed components are merged into
+/// the existing files, keeping what is already there. The init function call is
+/// inserted in the main file (`app.cpp` with the application class layout, see
+/// [`Integration`]) only if it still has the [`APP_MAIN_MARKER`] line of the
+/// built-in templates, otherwise it is reported as a manual step.
+///
+/// # Arguments
+/// * `root` - The root of the project
+/// * `starter` - The starter to apply
+/// * `mode` - What to do with starter files that already exist
+/// * `sdkconfig` - Whether the `sdkconfig.defaults` entries are written, they
+///   are reported as a manual step otherwise
+///
+/// # Errors
+/// If a file exists and `mode` is [`MergeMode::Fail`], the main CMakeLists.txt
+/// cannot be edited, or a file cannot be read or written
+pub fn apply_starter(
+    root: &Path,
+    starter: &Starter,
+    mode: MergeMode,
+    sdkconfig: bool,
+) -> anyhow::Result<StarterReport> {
+    let main_dir = root.join("main");
+    let mut report = StarterReport::default();
+
+    let present: Vec<&str> = STARTERS
+        .iter()
+        .filter(|other| other.id != starter.id && other.is_in(&main_dir))
+        .map(|other| other.id)
+        .collect();
+
+    // Checked before writing anything so a conflict leaves the project untouched
+    let existing: Vec<PathBuf> = starter
+        .files
+        .iter()
+        .map(|(name, _)| main_dir.join(name))
+        .filter(|path| path.exists())
+        .collect();
+    if mode == MergeMode::Fail && !existing.is_empty() {
+        let names: Vec<String> = existing.iter().map(|p| p.display().to_string()).collect();
+        bail!(
+            "Starter \"{}\" would overwrite {}, use --on-conflict skip or overwrite to continue",
+            starter.id,
+            names.join(", ")
+        );
+    }
+
+    for (name, content) in starter.files_with(&present) {
+        let path = main_dir.join(name);
+        if mode == MergeMode::Skip && existing.contains(&path) {
+            report.skipped.push(path);
+            continue;
+        }
+        fs::write(&path, content).context(format!("Cannot write \"{}\"", path.display()))?;
+        report.written.push(path);
+    }
+
+    let cmake_file = main_dir.join("CMakeLists.txt");
+    let sources = starter.sources();
+    update_file(&cmake_file, |cmake| {
+        let cmake = cmake::add_component_sources(cmake, &sources)?;
+        cmake::add_component_requires(&cmake, starter.requires)
+    })
+    .context(format!(
+        "Cannot add starter \"{}\" to the build",
+        starter.id
+    ))?;
+
+    if !starter.kconfig.is_empty() {
+        update_file(&main_dir.join("Kconfig.projbuild"), |kconfig| {
+            Ok(append_kconfig(kconfig, starter.kconfig))
+        })?;
+    }
+
+    if !starter.sdkconfig.is_empty() {
+        let mut defaults = SdkConfigDefaults::new();
+        for (key, value) in starter.sdkconfig {
+            defaults.set(key, value);
+        }
+        if sdkconfig {
+            update_file(&root.join(SDKCONFIG_DEFAULTS_FILE), |content| {
+                Ok(defaults.append_missing(content))
+            })?;
+        } else {
+            report.manual_steps.push(format!(
+                "Set {} with idf.py menuconfig, starter \"{}\" needs it",
+                defaults.summary(),
+                starter.id
+            ));
+        }
+    }
+
+    if !starter.dependencies.is_empty() {
+        update_file(&main_dir.join(IDF_COMPONENT_FILE), |content| {
+            Ok(idf_component::add_dependencies(
+                content,
+                starter.dependencies,
+            ))
+        })?;
+    }
+
+    // Written before this starter was there, without its snippets
+    for other in STARTERS.iter().filter(|other| present.contains(&other.id)) {
+        let unhooked = other
+            .hooks
+            .iter()
+            .filter(|hook| hook.with == starter.id)
+            .any(|hook| {
+                let first_line = hook.snippet.lines().next().unwrap_or_default();
+                fs::read_to_string(main_dir.join(hook.file))
+                    .is_ok_and(|content| !content.contains(first_line))
+            });
+        if unhooked {
+            report.manual_steps.push(format!(
+                "Run `esp-create-project add {} --on-conflict overwrite` to use starter \"{}\" in it (your changes to its files are lost)",
+                other.id, starter.id
+            ));
+        }
+    }
+
+    let integration = Integration::detect(&main_dir);
+    if !insert_init_call(
+        &main_dir,
+        integration,
+        starter.header,
+        starter.init_function,
+    )? {
+        report.manual_steps.push(format!(
+            "Call {}() from {}, after #include \"{}\"",
+            starter.init_function,
+            integration.caller(),
+            starter.header
+        ));
+    }
+    Ok(report)
+}
+
+/// Reads a file (empty if it doesn't exist), transforms it and writes it back if it changed
+fn update_file<F>(path: &Path, update: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&str) -> anyhow::Result<String>,
+{
+    let content = if path.exists() {
+        fs::read_to_string(path).context(format!("Cannot read \"{}\"", path.display()))?
+    } else {
+        String::new()
+    };
+    let updated = update(&content)?;
+    if updated != content {
+        fs::write(path, updated).context(format!("Cannot write \"{}\"", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Appends a Kconfig menu unless its first option is already defined
+fn append_kconfig(content: &str, menu: &str) -> String {
+    let first_config = menu
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("config "));
+    if first_config.is_some_and(|config| content.lines().any(|line| line.trim() == config)) {
+        return content.into();
+    }
+
+    let mut result = content.to_string();
+    if !result.is_empty() {
+        if !result.ends_with('\n') {
+            result.push('\n');
+        }
+        result.push('\n');
+    }
+    result.push_str(menu);
+    result
+}
+
+/// Inserts the include and the init call in the main file, or in `app.cpp`
+/// with the application class layout
+///
+/// # Arguments
+/// * `main_dir` - The `main/` directory of the project
+/// * `integration` - How the code of the project calls the init functions
+/// * `header` - Header that declares the init function
+/// * `init_function` - Function to call, without arguments
+///
+/// # Returns
+/// `true` if the call is in the file, `false` if it must be added by hand
+pub(crate) fn insert_init_call(
+    main_dir: &Path,
+    integration: Integration,
+    header: &str,
+    init_function: &str,
+) -> anyhow::Result<bool> {
+    let main_file = integration
+        .source_files()
+        .iter()
+        .map(|name| main_dir.join(name))
+        .find(|path| path.exists());
+    let main_file = match main_file {
+        Some(main_file) => main_file,
+        None => return Ok(false),
+    };
+    let source = fs::read_to_string(&main_file)
+        .context(format!("Cannot read \"{}\"", main_file.display()))?;
+    let call = format!("{}();", init_function);
+    if source.contains(&call) {
+        return Ok(true);
+    }
+    match insert_init_call_in_source(&source, header, &call) {
+        Some(source) => {
+            fs::write(&main_file, source)
+                .context(format!("Cannot write \"{}\"", main_file.display()))?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Inserts the lines of a snippet after a line, with its indentation
+fn insert_snippet(content: &str, after: &str, snippet: &str) -> String {
+    let mut result = String::with_capacity(content.len() + snippet.len());
+    for line in content.split_inclusive('\n') {
+        result.push_str(line);
+        if line.trim() == after {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            for snippet_line in snippet.lines() {
+                if !snippet_line.is_empty() {
+                    result.push_str(indent);
+                }
+                result.push_str(snippet_line);
+                result.push('\n');
+            }
+        }
+    }
+    result
+}
+
+/// Adds `#include "header"` after the last include and the call before the
+/// [`APP_MAIN_MARKER`] line, `None` if the source has no marker
+fn insert_init_call_in_source(source: &str, header: &str, call: &str) -> Option<String> {
+    let lines: Vec<&str> = source.split('\n').collect();
+    let marker = lines
+        .iter()
+        .position(|line| line.trim() == APP_MAIN_MARKER)?;
+    let last_include = lines.iter().rposition(|line| line.starts_with("#include"));
+    let include = format!("#include \"{}\"", header);
+    let indent = &lines[marker][..lines[marker].len() - lines[marker].trim_start().len()];
+
+    let mut result = Vec::with_capacity(lines.len() + 2);
+    if last_include.is_none() {
+        result.push(include.clone());
+    }
+    for (i, line) in lines.iter().enumerate() {
+        if i == marker {
+            result.push(format!("{}{}", indent, call));
+        }
+        result.push(line.to_string());
+        if Some(i) == last_include && !source.contains(&include) {
+            result.push(include.clone());
+        }
+    }
+    Some(result.join("\n"))
+}
+
+/// NVS initialization, with the erase-and-retry dance for outdated partitions
+pub const NVS: Starter = Starter {
+    id: "nvs",
+    description: "Initialize the NVS flash partition (key-value storage)",
+    targets: &[],
+    needs: &[],
+    files: &[("starter_nvs.h", NVS_HEADER), ("starter_nvs.c", NVS_SOURCE)],
+    kconfig: "",
+    requires: &["nvs_flash"],
+    sdkconfig: &[],
+    dependencies: &[],
+    header: "starter_nvs.h",
+    init_function: "starter_nvs_init",
+    min_idf_version: None,
+    hooks: &[],
+    factory_test: Some(FactoryTest {
+        command: "nvs_check",
+        help: "Write a key to NVS and read it back",
+        includes: &["nvs.h"],
+        body: NVS_FACTORY_TEST,
+    }),
+};
+
+/// Wi-Fi station connected to the network configured in menuconfig
+pub const WIFI: Starter = Starter {
+    id: "wifi",
+    description: "Connect to a Wi-Fi network set in menuconfig",
+    targets: &["esp32", "esp32s2", "esp32s3", "esp32c3", "esp32c6"],
+    needs: &[],
+    files: &[
+        ("starter_wifi.h", WIFI_HEADER),
+        ("starter_wifi.c", WIFI_SOURCE),
+    ],
+    kconfig: WIFI_KCONFIG,
+    requires: &["esp_wifi", "esp_netif", "esp_event", "nvs_flash"],
+    sdkconfig: &[],
+    dependencies: &[],
+    header: "starter_wifi.h",
+    init_function: "starter_wifi_init",
+    min_idf_version: None,
+    hooks: &[
+        StarterHook {
+            with: "events",
+            file: "starter_wifi.c",
+            after: "#include \"starter_wifi.h\"",
+            snippet: "#include \"starter_events.h\"",
+        },
+        StarterHook {
+            with: "events",
+            file: "starter_wifi.c",
+            after: "esp_wifi_connect();",
+            snippet: "if (event_id == WIFI_EVENT_STA_DISCONNECTED) {\n    starter_events_post(STARTER_EVENT_NETWORK_DISCONNECTED);\n}",
+        },
+        StarterHook {
+            with: "events",
+            file: "starter_wifi.c",
+            after: "xEventGroupSetBits(wifi_events, CONNECTED_BIT);",
+            snippet: "starter_events_post(STARTER_EVENT_NETWORK_CONNECTED);",
+        },
+    ],
+    factory_test: Some(FactoryTest {
+        command: "wifi_scan",
+        help: "Scan the Wi-Fi networks around, fails if none is found",
+        includes: &["esp_wifi.h", "freertos/FreeRTOS.h", "freertos/task.h"],
+        body: WIFI_FACTORY_TEST,
+    }),
+};
+
+/// Ethernet through the internal EMAC and a LAN87xx PHY, as on the WT32-ETH01
+pub const ETHERNET: Starter = Starter {
+    id: "ethernet",
+    description: "Connect to Ethernet with the internal MAC and a LAN8720 PHY (pins in menuconfig)",
+    targets: &["esp32"],
+    needs: &[],
+    files: &[
+        ("starter_ethernet.h", ETHERNET_HEADER),
+        ("starter_ethernet.c", ETHERNET_SOURCE),
+    ],
+    kconfig: ETHERNET_KCONFIG,
+    requires: &["esp_eth", "esp_netif", "esp_event"],
+    sdkconfig: &[],
+    dependencies: &[],
+    header: "starter_ethernet.h",
+    init_function: "starter_ethernet_init",
+    // `smi_gpio` in the EMAC configuration
+    min_idf_version: Some(IdfVersion::new(5, 2)),
+    hooks: &[
+        StarterHook {
+            with: "events",
+            file: "starter_ethernet.c",
+            after: "#include \"starter_ethernet.h\"",
+            snippet: "#include \"starter_events.h\"",
+        },
+        StarterHook {
+            with: "events",
+            file: "starter_ethernet.c",
+            after: "ESP_LOGW(TAG, \"Link down\");",
+            snippet: "starter_events_post(STARTER_EVENT_NETWORK_DISCONNECTED);",
+        },
+        StarterHook {
+            with: "events",
+            file: "starter_ethernet.c",
+            after: "xEventGroupSetBits(ethernet_events, CONNECTED_BIT);",
+            snippet: "starter_events_post(STARTER_EVENT_NETWORK_CONNECTED);",
+        },
+    ],
+    factory_test: None,
+};
+
+/// MQTT client connected to the broker configured in menuconfig
+pub const MQTT: Starter = Starter {
+    id: "mqtt",
+    description: "Connect to an MQTT broker set in menuconfig",
+    targets: &[],
+    needs: &[&["wifi", "ethernet"]],
+    files: &[
+        ("starter_mqtt.h", MQTT_HEADER),
+        ("starter_mqtt.c", MQTT_SOURCE),
+    ],
+    kconfig: MQTT_KCONFIG,
+    requires: &["mqtt"],
+    sdkconfig: &[],
+    dependencies: &[],
+    header: "starter_mqtt.h",
+    init_function: "starter_mqtt_init",
+    // `broker.address.uri` in the client configuration
+    min_idf_version: Some(IdfVersion::new(5, 0)),
+    hooks: &[
+        StarterHook {
+            with: "events",
+            file: "starter_mqtt.c",
+            after: "#include \"starter_mqtt.h\"",
+            snippet: "#include \"starter_events.h\"",
+        },
+        StarterHook {
+            with: "events",
+            file: "starter_mqtt.c",
+            after: "ESP_LOGI(TAG, \"Connected to %s\", CONFIG_STARTER_MQTT_BROKER_URL);",
+            snippet: "starter_events_post(STARTER_EVENT_MQTT_CONNECTED);",
+        },
+        StarterHook {
+            with: "events",
+            file: "starter_mqtt.c",
+            after: "ESP_LOGW(TAG, \"Disconnected, reconnecting\");",
+            snippet: "starter_events_post(STARTER_EVENT_MQTT_DISCONNECTED);",
+        },
+    ],
+    factory_test: None,
+};
+
+/// Addressable LED (WS2812) through the `led_strip` managed component
+pub const LED: Starter = Starter {
+    id: "led",
+    description: "Drive an addressable RGB LED (WS2812) with the led_strip component",
+    targets: &[],
+    needs: &[],
+    files: &[("starter_led.h", LED_HEADER), ("starter_led.c", LED_SOURCE)],
+    kconfig: LED_KCONFIG,
+    requires: &[],
+    sdkconfig: &[],
+    dependencies: &[("espressif/led_strip", "^2.5.0")],
+    header: "starter_led.h",
+    init_function: "starter_led_init",
+    // led_strip 2.x, from the component manager
+    min_idf_version: Some(IdfVersion::new(4, 4)),
+    hooks: &[],
+    factory_test: Some(FactoryTest {
+        command: "led_blink",
+        help: "Light the LED red, green then blue",
+        includes: &["freertos/FreeRTOS.h", "freertos/task.h"],
+        body: LED_FACTORY_TEST,
+    }),
+};
+
+/// Periodic work and one-shot actions with `esp_timer` instead of
+/// `vTaskDelay` loops
+pub const SCHEDULER: Starter = Starter {
+    id: "scheduler",
+    description: "Run periodic work and delayed one-shot actions with esp_timer",
+    targets: &[],
+    needs: &[],
+    files: &[
+        ("starter_scheduler.h", SCHEDULER_HEADER),
+        ("starter_scheduler.c", SCHEDULER_SOURCE),
+    ],
+    kconfig: SCHEDULER_KCONFIG,
+    requires: &["esp_timer"],
+    sdkconfig: &[],
+    dependencies: &[],
+    header: "starter_scheduler.h",
+    init_function: "starter_scheduler_init",
+    min_idf_version: None,
+    hooks: &[],
+    factory_test: None,
+};
+
+/// Application events on a user event loop, the other starters post their
+/// state on it through their [`StarterHook`]s
+pub const EVENTS: Starter = Starter {
+    id: "events",
+    description: "Post and handle typed application events on a user esp_event loop",
+    targets: &[],
+    needs: &[],
+    files: &[
+        ("starter_events.h", EVENTS_HEADER),
+        ("starter_events.c", EVENTS_SOURCE),
+    ],
+    kconfig: EVENTS_KCONFIG,
+    requires: &["esp_event"],
+    sdkconfig: &[],
+    dependencies: &[],
+    header: "starter_events.h",
+    init_function: "starter_events_init",
+    min_idf_version: None,
+    hooks: &[],
+    factory_test: None,
+};
+
+/// Interactive console (REPL) on the UART or USB console of the chip, the
+/// factory test app registers its commands on it
+pub const CONSOLE: Starter = Starter {
+    id: "console",
+    description: "Run an interactive command console on the serial port",
+    targets: &[],
+    needs: &[],
+    files: &[
+        ("starter_console.h", CONSOLE_HEADER),
+        ("starter_console.c", CONSOLE_SOURCE),
+    ],
+    kconfig: CONSOLE_KCONFIG,
+    requires: &["console"],
+    sdkconfig: &[],
+    dependencies: &[],
+    header: "starter_console.h",
+    init_function: "starter_console_init",
+    // The REPL on the USB Serial/JTAG console
+    min_idf_version: Some(IdfVersion::new(4, 4)),
+    hooks: &[],
+    factory_test: None,
+};
+
+const NVS_FACTORY_TEST: &str = r#"nvs_handle_t handle;
+esp_err_t err = nvs_open("factory_test", NVS_READWRITE, &handle);
+if (err != ESP_OK) {
+    printf("Cannot open NVS: %s\n", esp_err_to_name(err));
+    return 1;
+}
+uint32_t value = 0;
+err = nvs_set_u32(handle, "check", 0xa5a5a5a5);
+if (err == ESP_OK) {
+    err = nvs_get_u32(handle, "check", &value);
+}
+nvs_erase_key(handle, "check");
+nvs_commit(handle);
+nvs_close(handle);
+if (err != ESP_OK || value != 0xa5a5a5a5) {
+    printf("NVS read back failed: %s\n", esp_err_to_name(err));
+    return 1;
+}
+return 0;
+"#;
+
+const NVS_HEADER: &str = r#"#pragma once
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Initializes the default NVS partition, erasing it if its layout is outdated
+void starter_nvs_init(void);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const NVS_SOURCE: &str = r#"#include "starter_nvs.h"
+
+#include "esp_log.h"
+#include "nvs_flash.h"
+
+static const char *TAG = "starter_nvs";
+
+void starter_nvs_init(void)
+{
+    esp_err_t err = nvs_flash_init();
+    if (err == ESP_ERR_NVS_NO_FREE_PAGES || err == ESP_ERR_NVS_NEW_VERSION_FOUND) {
+        // The partition was truncated or written by another IDF version
+        ESP_LOGW(TAG, "Erasing the NVS partition");
+        ESP_ERROR_CHECK(nvs_flash_erase());
+        err = nvs_flash_init();
+    }
+    ESP_ERROR_CHECK(err);
+}
+"#;
+
+const WIFI_KCONFIG: &str = r#"menu "Wi-Fi starter"
+
+    config STARTER_WIFI_SSID
+        string "WiFi SSID"
+        default "myssid"
+        help
+            SSID (network name) to connect to.
+
+    config STARTER_WIFI_PASSWORD
+        string "WiFi password"
+        default "mypassword"
+        help
+            Password of the network, empty for an open network.
+
+    config STARTER_WIFI_TIMEOUT_MS
+        int "Connection timeout (ms)"
+        default 10000
+        help
+            How long starter_wifi_init() waits for an IP address.
+
+endmenu
+"#;
+
+const WIFI_FACTORY_TEST: &str = r#"wifi_ap_record_t records[10];
+uint16_t count = sizeof(records) / sizeof(records[0]);
+esp_err_t err = ESP_ERR_WIFI_STATE;
+// The starter may be busy connecting to the network set in menuconfig
+for (int attempt = 0; attempt < 10 && err == ESP_ERR_WIFI_STATE; attempt++) {
+    err = esp_wifi_scan_start(NULL, true);
+    if (err == ESP_ERR_WIFI_STATE) {
+        vTaskDelay(pdMS_TO_TICKS(500));
+    }
+}
+if (err == ESP_OK) {
+    err = esp_wifi_scan_get_ap_records(&count, records);
+}
+if (err != ESP_OK) {
+    printf("Scan failed: %s\n", esp_err_to_name(err));
+    return 1;
+}
+for (int i = 0; i < count; i++) {
+    printf("%-32s %4d dBm\n", (const char *)records[i].ssid, records[i].rssi);
+}
+printf("%u networks found\n", count);
+return count > 0 ? 0 : 1;
+"#;
+
+const WIFI_HEADER: &str = r#"#pragma once
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Connects to the network set in menuconfig ("Wi-Fi starter")
+///
+/// Reconnects automatically when the connection is lost.
+///
+/// @return true if an IP address was obtained before the timeout
+bool starter_wifi_init(void);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const WIFI_SOURCE: &str = r#"#include "starter_wifi.h"
+
+#include <string.h>
+#include "esp_event.h"
+#include "esp_log.h"
+#include "esp_netif.h"
+#include "esp_wifi.h"
+#include "freertos/FreeRTOS.h"
+#include "freertos/event_groups.h"
+#include "nvs_flash.h"
+#include "sdkconfig.h"
+
+#define CONNECTED_BIT BIT0
+
+static const char *TAG = "starter_wifi";
+
+static EventGroupHandle_t wifi_events;
+
+static void event_handler(void *arg, esp_event_base_t event_base, int32_t event_id, void *event_data)
+{
+    if (event_base == WIFI_EVENT &&
+        (event_id == WIFI_EVENT_STA_START || event_id == WIFI_EVENT_STA_DISCONNECTED)) {
+        xEventGroupClearBits(wifi_events, CONNECTED_BIT);
+        ESP_LOGI(TAG, "Connecting to %s", CONFIG_STARTER_WIFI_SSID);
+        esp_wifi_connect();
+    } else if (event_base == IP_EVENT && event_id == IP_EVENT_STA_GOT_IP) {
+        ip_event_got_ip_t *event = (ip_event_got_ip_t *)event_data;
+        ESP_LOGI(TAG, "Got IP " IPSTR, IP2STR(&event->ip_info.ip));
+        xEventGroupSetBits(wifi_events, CONNECTED_BIT);
+    }
+}
+
+bool starter_wifi_init(void)
+{
+    // Wi-Fi keeps its calibration data in NVS
+    esp_err_t err = nvs_flash_init();
+    if (err == ESP_ERR_NVS_NO_FREE_PAGES || err == ESP_ERR_NVS_NEW_VERSION_FOUND) {
+        ESP_ERROR_CHECK(nvs_flash_erase());
+        err = nvs_flash_init();
+    }
+    ESP_ERROR_CHECK(err);
+
+    wifi_events = xEventGroupCreate();
+    ESP_ERROR_CHECK(esp_netif_init());
+    // Another part of the application may have created it already
+    err = esp_event_loop_create_default();
+    if (err != ESP_ERR_INVALID_STATE) {
+        ESP_ERROR_CHECK(err);
+    }
+    esp_netif_create_default_wifi_sta();
+
+    wifi_init_config_t init_config = WIFI_INIT_CONFIG_DEFAULT();
+    ESP_ERROR_CHECK(esp_wifi_init(&init_config));
+    ESP_ERROR_CHECK(esp_event_handler_register(WIFI_EVENT, ESP_EVENT_ANY_ID, event_handler, NULL));
+    ESP_ERROR_CHECK(esp_event_handler_register(IP_EVENT, IP_EVENT_STA_GOT_IP, event_handler, NULL));
+
+    wifi_config_t wifi_config = {0};
+    strncpy((char *)wifi_config.sta.ssid, CONFIG_STARTER_WIFI_SSID, sizeof(wifi_config.sta.ssid));
+    strncpy((char *)wifi_config.sta.password, CONFIG_STARTER_WIFI_PASSWORD,
+            sizeof(wifi_config.sta.password));
+    ESP_ERROR_CHECK(esp_wifi_set_mode(WIFI_MODE_STA));
+    ESP_ERROR_CHECK(esp_wifi_set_config(WIFI_IF_STA, &wifi_config));
+    ESP_ERROR_CHECK(esp_wifi_start());
+
+    EventBits_t bits = xEventGroupWaitBits(wifi_events, CONNECTED_BIT, pdFALSE, pdTRUE,
+                                           pdMS_TO_TICKS(CONFIG_STARTER_WIFI_TIMEOUT_MS));
+    if (!(bits & CONNECTED_BIT)) {
+        ESP_LOGW(TAG, "Not connected yet, still retrying in the background");
+    }
+    return (bits & CONNECTED_BIT) != 0;
+}
+"#;
+
+const ETHERNET_KCONFIG: &str = r#"menu "Ethernet starter"
+
+    config STARTER_ETHERNET_MDC_GPIO
+        int "SMI MDC GPIO"
+        default 23
+        help
+            GPIO of the MDC line of the PHY management interface.
+
+    config STARTER_ETHERNET_MDIO_GPIO
+        int "SMI MDIO GPIO"
+        default 18
+        help
+            GPIO of the MDIO line of the PHY management interface.
+
+    config STARTER_ETHERNET_PHY_ADDR
+        int "PHY address"
+        default 1
+        help
+            Address of the PHY on the management bus, 1 on the WT32-ETH01.
+
+    config STARTER_ETHERNET_PHY_RESET_GPIO
+        int "PHY reset GPIO"
+        default 16
+        help
+            GPIO that resets (or powers) the PHY, -1 if none. 16 on the WT32-ETH01.
+
+    config STARTER_ETHERNET_TIMEOUT_MS
+        int "Connection timeout (ms)"
+        default 10000
+        help
+            How long starter_ethernet_init() waits for an IP address.
+
+endmenu
+"#;
+
+const ETHERNET_HEADER: &str = r#"#pragma once
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Starts Ethernet with the pins set in menuconfig ("Ethernet starter")
+///
+/// The IP address is renewed automatically when the cable is plugged back.
+///
+/// @return true if an IP address was obtained before the timeout
+bool starter_ethernet_init(void);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const ETHERNET_SOURCE: &str = r#"#include "starter_ethernet.h"
+
+#include "esp_eth.h"
+#include "esp_event.h"
+#include "esp_log.h"
+#include "esp_netif.h"
+#include "freertos/FreeRTOS.h"
+#include "freertos/event_groups.h"
+#include "sdkconfig.h"
+
+#define CONNECTED_BIT BIT0
+
+static const char *TAG = "starter_ethernet";
+
+static EventGroupHandle_t ethernet_events;
+
+static void event_handler(void *arg, esp_event_base_t event_base, int32_t event_id, void *event_data)
+{
+    if (event_base == ETH_EVENT && event_id == ETHERNET_EVENT_DISCONNECTED) {
+        xEventGroupClearBits(ethernet_events, CONNECTED_BIT);
+        ESP_LOGW(TAG, "Link down");
+    } else if (event_base == IP_EVENT && event_id == IP_EVENT_ETH_GOT_IP) {
+        ip_event_got_ip_t *event = (ip_event_got_ip_t *)event_data;
+        ESP_LOGI(TAG, "Got IP " IPSTR, IP2STR(&event->ip_info.ip));
+        xEventGroupSetBits(ethernet_events, CONNECTED_BIT);
+    }
+}
+
+bool starter_ethernet_init(void)
+{
+    ethernet_events = xEventGroupCreate();
+    ESP_ERROR_CHECK(esp_netif_init());
+    // Another part of the application may have created it already
+    esp_err_t err = esp_event_loop_create_default();
+    if (err != ESP_ERR_INVALID_STATE) {
+        ESP_ERROR_CHECK(err);
+    }
+    esp_netif_config_t netif_config = ESP_NETIF_DEFAULT_ETH();
+    esp_netif_t *netif = esp_netif_new(&netif_config);
+
+    eth_mac_config_t mac_config = ETH_MAC_DEFAULT_CONFIG();
+    eth_esp32_emac_config_t emac_config = ETH_ESP32_EMAC_DEFAULT_CONFIG();
+    emac_config.smi_gpio.mdc_num = CONFIG_STARTER_ETHERNET_MDC_GPIO;
+    emac_config.smi_gpio.mdio_num = CONFIG_STARTER_ETHERNET_MDIO_GPIO;
+    esp_eth_mac_t *mac = esp_eth_mac_new_esp32(&emac_config, &mac_config);
+
+    eth_phy_config_t phy_config = ETH_PHY_DEFAULT_CONFIG();
+    phy_config.phy_addr = CONFIG_STARTER_ETHERNET_PHY_ADDR;
+    phy_config.reset_gpio_num = CONFIG_STARTER_ETHERNET_PHY_RESET_GPIO;
+    esp_eth_phy_t *phy = esp_eth_phy_new_lan87xx(&phy_config);
+
+    esp_eth_config_t config = ETH_DEFAULT_CONFIG(mac, phy);
+    esp_eth_handle_t handle = NULL;
+    ESP_ERROR_CHECK(esp_eth_driver_install(&config, &handle));
+    ESP_ERROR_CHECK(esp_netif_attach(netif, esp_eth_new_netif_glue(handle)));
+    ESP_ERROR_CHECK(esp_event_handler_register(ETH_EVENT, ESP_EVENT_ANY_ID, event_handler, NULL));
+    ESP_ERROR_CHECK(esp_event_handler_register(IP_EVENT, IP_EVENT_ETH_GOT_IP, event_handler, NULL));
+    ESP_ERROR_CHECK(esp_eth_start(handle));
+
+    EventBits_t bits = xEventGroupWaitBits(ethernet_events, CONNECTED_BIT, pdFALSE, pdTRUE,
+                                           pdMS_TO_TICKS(CONFIG_STARTER_ETHERNET_TIMEOUT_MS));
+    if (!(bits & CONNECTED_BIT)) {
+        ESP_LOGW(TAG, "No IP address yet, check the cable");
+    }
+    return (bits & CONNECTED_BIT) != 0;
+}
+"#;
+
+const MQTT_KCONFIG: &str = r#"menu "MQTT starter"
+
+    config STARTER_MQTT_BROKER_URL
+        string "Broker URL"
+        default "mqtt://mqtt.eclipseprojects.io"
+        help
+            URL of the MQTT broker, mqtt:// or mqtts://.
+
+endmenu
+"#;
+
+const MQTT_HEADER: &str = r#"#pragma once
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Connects to the broker set in menuconfig ("MQTT starter"), the network must be up
+void starter_mqtt_init(void);
+
+/// Publishes a message with QoS 1
+///
+/// @return the message id, or -1 on error
+int starter_mqtt_publish(const char *topic, const char *data);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const MQTT_SOURCE: &str = r#"#include "starter_mqtt.h"
+
+#include "esp_log.h"
+#include "mqtt_client.h"
+#include "sdkconfig.h"
+
+static const char *TAG = "starter_mqtt";
+
+static esp_mqtt_client_handle_t client;
+
+static void event_handler(void *arg, esp_event_base_t event_base, int32_t event_id, void *event_data)
+{
+    switch ((esp_mqtt_event_id_t)event_id) {
+    case MQTT_EVENT_CONNECTED:
+        ESP_LOGI(TAG, "Connected to %s", CONFIG_STARTER_MQTT_BROKER_URL);
+        break;
+    case MQTT_EVENT_DISCONNECTED:
+        ESP_LOGW(TAG, "Disconnected, reconnecting");
+        break;
+    default:
+        break;
+    }
+}
+
+void starter_mqtt_init(void)
+{
+    esp_mqtt_client_config_t config = {
+        .broker.address.uri = CONFIG_STARTER_MQTT_BROKER_URL,
+    };
+    client = esp_mqtt_client_init(&config);
+    ESP_ERROR_CHECK(esp_mqtt_client_register_event(client, ESP_EVENT_ANY_ID, event_handler, NULL));
+    ESP_ERROR_CHECK(esp_mqtt_client_start(client));
+}
+
+int starter_mqtt_publish(const char *topic, const char *data)
+{
+    return esp_mqtt_client_publish(client, topic, data, 0, 1, 0);
+}
+"#;
+
+const LED_KCONFIG: &str = r#"menu "LED starter"
+
+    config STARTER_LED_GPIO
+        int "LED GPIO"
+        default 8
+        help
+            GPIO of the data line of the addressable LED.
+
+endmenu
+"#;
+
+const LED_FACTORY_TEST: &str = r#"const uint8_t colors[][3] = {{255, 0, 0}, {0, 255, 0}, {0, 0, 255}};
+for (int i = 0; i < 3; i++) {
+    starter_led_set(colors[i][0], colors[i][1], colors[i][2]);
+    vTaskDelay(pdMS_TO_TICKS(500));
+}
+starter_led_set(0, 0, 0);
+printf("Check that the LED was red, green then blue\n");
+return 0;
+"#;
+
+const LED_HEADER: &str = r#"#pragma once
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Initializes the LED on the GPIO set in menuconfig ("LED starter") and turns it off
+void starter_led_init(void);
+
+/// Sets the color of the LED
+void starter_led_set(uint8_t red, uint8_t green, uint8_t blue);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const LED_SOURCE: &str = r#"#include "starter_led.h"
+
+#include "esp_err.h"
+#include "led_strip.h"
+#include "sdkconfig.h"
+
+static led_strip_handle_t strip;
+
+void starter_led_init(void)
+{
+    led_strip_config_t strip_config = {
+        .strip_gpio_num = CONFIG_STARTER_LED_GPIO,
+        .max_leds = 1,
+    };
+    led_strip_rmt_config_t rmt_config = {
+        .resolution_hz = 10 * 1000 * 1000,
+    };
+    ESP_ERROR_CHECK(led_strip_new_rmt_device(&strip_config, &rmt_config, &strip));
+    ESP_ERROR_CHECK(led_strip_clear(strip));
+}
+
+void starter_led_set(uint8_t red, uint8_t green, uint8_t blue)
+{
+    ESP_ERROR_CHECK(led_strip_set_pixel(strip, 0, red, green, blue));
+    ESP_ERROR_CHECK(led_strip_refresh(strip));
+}
+"#;
+
+const SCHEDULER_KCONFIG: &str = r#"menu "Scheduler starter"
+
+    config STARTER_SCHEDULER_PERIOD_MS
+        int "Period of the periodic work (ms)"
+        default 1000
+        range 1 3600000
+        help
+            How often the periodic callback runs.
+
+    config STARTER_SCHEDULER_DELAY_MS
+        int "Delay of the one-shot action (ms)"
+        default 5000
+        range 1 3600000
+        help
+            How long after the start the one-shot callback runs.
+
+endmenu
+"#;
+
+const SCHEDULER_HEADER: &str = r#"#pragma once
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Starts the periodic work every CONFIG_STARTER_SCHEDULER_PERIOD_MS and the
+/// one-shot action after CONFIG_STARTER_SCHEDULER_DELAY_MS ("Scheduler starter")
+///
+/// The callbacks are C functions of starter_scheduler.c run by the esp_timer
+/// task: keep them short and never block in them, hand longer work to a task.
+void starter_scheduler_init(void);
+
+/// Runs the one-shot action once after a delay, replacing a pending one
+void starter_scheduler_run_once(uint32_t delay_ms);
+
+/// Stops both timers and deletes them, starter_scheduler_init() can run again
+void starter_scheduler_deinit(void);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const SCHEDULER_SOURCE: &str = r#"#include "starter_scheduler.h"
+
+#include "esp_err.h"
+#include "esp_log.h"
+#include "esp_timer.h"
+#include "sdkconfig.h"
+
+static const char *TAG = "starter_scheduler";
+
+static esp_timer_handle_t periodic_timer;
+static esp_timer_handle_t one_shot_timer;
+
+static void periodic_callback(void *arg)
+{
+    static unsigned runs;
+    // TODO Replace with the periodic work
+    ESP_LOGI(TAG, "Periodic work, run %u", ++runs);
+}
+
+static void one_shot_callback(void *arg)
+{
+    // TODO Replace with the delayed action
+    ESP_LOGI(TAG, "One-shot action, %lld ms after boot", (long long)(esp_timer_get_time() / 1000));
+}
+
+void starter_scheduler_init(void)
+{
+    const esp_timer_create_args_t periodic_args = {
+        .callback = periodic_callback,
+        .name = "periodic",
+    };
+    ESP_ERROR_CHECK(esp_timer_create(&periodic_args, &periodic_timer));
+    const esp_timer_create_args_t one_shot_args = {
+        .callback = one_shot_callback,
+        .name = "one_shot",
+    };
+    ESP_ERROR_CHECK(esp_timer_create(&one_shot_args, &one_shot_timer));
+
+    // The periods of esp_timer are in microseconds
+    ESP_ERROR_CHECK(esp_timer_start_periodic(periodic_timer, CONFIG_STARTER_SCHEDULER_PERIOD_MS * 1000ULL));
+    starter_scheduler_run_once(CONFIG_STARTER_SCHEDULER_DELAY_MS);
+}
+
+void starter_scheduler_run_once(uint32_t delay_ms)
+{
+    // Fails if the action is not pending, which is fine
+    esp_timer_stop(one_shot_timer);
+    ESP_ERROR_CHECK(esp_timer_start_once(one_shot_timer, delay_ms * 1000ULL));
+}
+
+void starter_scheduler_deinit(void)
+{
+    // A running timer cannot be deleted
+    esp_timer_stop(periodic_timer);
+    esp_timer_stop(one_shot_timer);
+    ESP_ERROR_CHECK(esp_timer_delete(periodic_timer));
+    ESP_ERROR_CHECK(esp_timer_delete(one_shot_timer));
+    periodic_timer = NULL;
+    one_shot_timer = NULL;
+}
+"#;
+
+const EVENTS_KCONFIG: &str = r#"menu "Events starter"
+
+    config STARTER_EVENTS_QUEUE_SIZE
+        int "Queue size"
+        default 16
+        help
+            Events the loop holds before posting waits.
+
+    config STARTER_EVENTS_TASK_STACK_SIZE
+        int "Task stack size"
+        default 3072
+        help
+            Stack of the task that runs the handlers.
+
+endmenu
+"#;
+
+const EVENTS_HEADER: &str = r#"#pragma once
+
+/// @file
+/// @brief Application events, posted and handled on a user event loop
+///
+/// Parts of the application post events instead of sharing event groups, and
+/// the ones interested subscribe to them. The Wi-Fi, Ethernet and MQTT
+/// starters post their state changes here.
+
+#include "esp_err.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// @brief The events of the application
+typedef enum {
+    /// Every event, to subscribe to all of them
+    STARTER_EVENT_ANY = -1,
+    /// An IP address was obtained, no data
+    STARTER_EVENT_NETWORK_CONNECTED,
+    /// The network was lost, no data
+    STARTER_EVENT_NETWORK_DISCONNECTED,
+    /// Connected to the MQTT broker, no data
+    STARTER_EVENT_MQTT_CONNECTED,
+    /// Disconnected from the MQTT broker, no data
+    STARTER_EVENT_MQTT_DISCONNECTED,
+    /// A sensor was read, the data is a ::starter_events_reading_t
+    STARTER_EVENT_READING,
+} starter_event_t;
+
+/// @brief Data of ::STARTER_EVENT_READING, an example of an event with data
+typedef struct {
+    /// Which sensor was read
+    int sensor;
+    /// The value read
+    float value;
+} starter_events_reading_t;
+
+/// @brief Handler of an application event, run by the task of the loop
+///
+/// @param event The event
+/// @param data The data of the event, valid until the handler returns
+/// @param arg The argument given to starter_events_subscribe()
+typedef void (*starter_events_handler_t)(starter_event_t event, const void *data, void *arg);
+
+/// @brief Creates the loop and its task ("Events starter" in menuconfig),
+/// and wires the example consumer and producer
+void starter_events_init(void);
+
+/// @brief Subscribes a handler to an event
+///
+/// @param event The event, ::STARTER_EVENT_ANY for all of them
+/// @param handler The handler
+/// @param arg Passed to the handler
+/// @return ESP_OK, or the error of esp_event
+esp_err_t starter_events_subscribe(starter_event_t event, starter_events_handler_t handler, void *arg);
+
+/// @brief Posts an event without data
+///
+/// @param event The event
+/// @return ESP_OK, or ESP_ERR_TIMEOUT if the queue stayed full
+esp_err_t starter_events_post(starter_event_t event);
+
+/// @brief Posts ::STARTER_EVENT_READING
+///
+/// @param reading The reading, copied
+/// @return ESP_OK, or ESP_ERR_TIMEOUT if the queue stayed full
+esp_err_t starter_events_post_reading(const starter_events_reading_t *reading);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const EVENTS_SOURCE: &str = r#"#include "starter_events.h"
+
+#include <stdlib.h>
+#include "esp_event.h"
+#include "esp_log.h"
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+#include "sdkconfig.h"
+
+// Posting waits at most this long for room in the queue
+#define POST_TIMEOUT pdMS_TO_TICKS(100)
+
+ESP_EVENT_DEFINE_BASE(STARTER_EVENTS);
+
+static const char *TAG = "starter_events";
+
+static esp_event_loop_handle_t loop;
+
+typedef struct {
+    starter_events_handler_t handler;
+    void *arg;
+} subscription_t;
+
+static void dispatch(void *arg, esp_event_base_t event_base, int32_t event_id, void *event_data)
+{
+    subscription_t *subscription = (subscription_t *)arg;
+    subscription->handler((starter_event_t)event_id, event_data, subscription->arg);
+}
+
+// Example consumer
+static void log_event(starter_event_t event, const void *data, void *arg)
+{
+    if (event == STARTER_EVENT_READING) {
+        const starter_events_reading_t *reading = (const starter_events_reading_t *)data;
+        ESP_LOGI(TAG, "Sensor %d read %.2f", reading->sensor, reading->value);
+    } else {
+        ESP_LOGI(TAG, "Event %d", (int)event);
+    }
+}
+
+void starter_events_init(void)
+{
+    esp_event_loop_args_t loop_args = {
+        .queue_size = CONFIG_STARTER_EVENTS_QUEUE_SIZE,
+        .task_name = "app_events",
+        .task_priority = uxTaskPriorityGet(NULL),
+        .task_stack_size = CONFIG_STARTER_EVENTS_TASK_STACK_SIZE,
+        .task_core_id = tskNO_AFFINITY,
+    };
+    ESP_ERROR_CHECK(esp_event_loop_create(&loop_args, &loop));
+    ESP_ERROR_CHECK(starter_events_subscribe(STARTER_EVENT_ANY, log_event, NULL));
+
+    // Example producer, TODO post the readings of your sensors instead
+    starter_events_reading_t reading = {.sensor = 0, .value = 21.5f};
+    ESP_ERROR_CHECK(starter_events_post_reading(&reading));
+}
+
+esp_err_t starter_events_subscribe(starter_event_t event, starter_events_handler_t handler, void *arg)
+{
+    // Lives as long as the subscription, which is the whole program
+    subscription_t *subscription = malloc(sizeof(subscription_t));
+    if (subscription == NULL) {
+        return ESP_ERR_NO_MEM;
+    }
+    subscription->handler = handler;
+    subscription->arg = arg;
+    esp_err_t err = esp_event_handler_instance_register_with(loop, STARTER_EVENTS, event, dispatch,
+                                                             subscription, NULL);
+    if (err != ESP_OK) {
+        free(subscription);
+    }
+    return err;
+}
+
+esp_err_t starter_events_post(starter_event_t event)
+{
+    return esp_event_post_to(loop, STARTER_EVENTS, event, NULL, 0, POST_TIMEOUT);
+}
+
+esp_err_t starter_events_post_reading(const starter_events_reading_t *reading)
+{
+    return esp_event_post_to(loop, STARTER_EVENTS, STARTER_EVENT_READING, reading,
+                             sizeof(*reading), POST_TIMEOUT);
+}
+"#;
+
+const CONSOLE_KCONFIG: &str = r#"menu "Console starter"
+
+    config STARTER_CONSOLE_PROMPT
+        string "Prompt"
+        default "esp"
+        help
+            Shown before each command, followed by ">".
+
+endmenu
+"#;
+
+const CONSOLE_HEADER: &str = r#"#pragma once
+
+#include "esp_console.h"
+#include "esp_err.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Starts the console on the UART or USB console set in menuconfig, with the
+/// prompt set in menuconfig ("Console starter"); `help` lists the commands
+void starter_console_init(void);
+
+/// Adds a command to the console, call it after starter_console_init()
+///
+/// @return ESP_OK, or the error of esp_console_cmd_register()
+esp_err_t starter_console_register(const char *command, const char *help, esp_console_cmd_func_t func);
+
+#ifdef __cplusplus
+}
+#endif
+"#;
+
+const CONSOLE_SOURCE: &str = r#"#include "starter_console.h"
+
+#include "sdkconfig.h"
+
+void starter_console_init(void)
+{
+    esp_console_repl_t *repl = NULL;
+    esp_console_repl_config_t repl_config = ESP_CONSOLE_REPL_CONFIG_DEFAULT();
+    repl_config.prompt = CONFIG_STARTER_CONSOLE_PROMPT ">";
+#if defined(CONFIG_ESP_CONSOLE_USB_CDC)
+    esp_console_dev_usb_cdc_config_t cdc_config = ESP_CONSOLE_DEV_CDC_CONFIG_DEFAULT();
+    ESP_ERROR_CHECK(esp_console_new_repl_usb_cdc(&cdc_config, &repl_config, &repl));
+#elif defined(CONFIG_ESP_CONSOLE_USB_SERIAL_JTAG)
+    esp_console_dev_usb_serial_jtag_config_t jtag_config =
+        ESP_CONSOLE_DEV_USB_SERIAL_JTAG_CONFIG_DEFAULT();
+    ESP_ERROR_CHECK(esp_console_new_repl_usb_serial_jtag(&jtag_config, &repl_config, &repl));
+#else
+    esp_console_dev_uart_config_t uart_config = ESP_CONSOLE_DEV_UART_CONFIG_DEFAULT();
+    ESP_ERROR_CHECK(esp_console_new_repl_uart(&uart_config, &repl_config, &repl));
+#endif
+    ESP_ERROR_CHECK(esp_console_start_repl(repl));
+}
+
+esp_err_t starter_console_register(const char *command, const char *help, esp_console_cmd_func_t func)
+{
+    const esp_console_cmd_t cmd = {
+        .command = command,
+        .help = help,
+        .func = func,
+    };
+    return esp_console_cmd_register(&cmd);
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("wifi").unwrap().id, "wifi");
+        let error = find("zigbee").unwrap_err().to_string();
+        assert!(error.contains("nvs, wifi, ethernet, mqtt, led"));
+        // The calls are inserted before this line of the built-in main files
+        assert!(templates::C_TEMPLATE.contains(APP_MAIN_MARKER));
+        assert!(templates::CPP_TEMPLATE.contains(APP_MAIN_MARKER));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let order = |starters: Vec<&Starter>| starters.iter().map(|s| s.id).collect::<Vec<_>>();
+
+        // The network is initialized before MQTT, whatever the order given
+        let resolved = resolve(&ids(&["mqtt", "led", "wifi", "mqtt"]), Some("esp32c3")).unwrap();
+        assert_eq!(order(resolved), ["led", "wifi", "mqtt"]);
+        let resolved = resolve(&ids(&["mqtt", "ethernet"]), None).unwrap();
+        assert_eq!(order(resolved), ["ethernet", "mqtt"]);
+        // Events first, so the others hook into it
+        let resolved = resolve(&ids(&["wifi", "mqtt", "events"]), None).unwrap();
+        assert_eq!(order(resolved), ["events", "wifi", "mqtt"]);
+
+        let error = resolve(&ids(&["mqtt", "nvs"]), None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Starter \"mqtt\" needs wifi or ethernet, add it too"
+        );
+        let error = resolve(&ids(&["ethernet"]), Some("esp32c3")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Starter \"ethernet\" doesn't run on esp32c3, only on esp32"
+        );
+        assert!(resolve(&ids(&["wifi"]), Some("esp32h2")).is_err());
+    }
+
+    #[test]
+    fn test_compatibility() {
+        let ids = |target| -> Vec<&str> {
+            compatibility(target)
+                .iter()
+                .map(|starter| starter.id)
+                .collect()
+        };
+        assert_eq!(ids(None).len(), STARTERS.len());
+        assert_eq!(
+            ids(Some("esp32h2")),
+            ["nvs", "led", "scheduler", "events", "console"]
+        );
+        // The same check as resolve(): each listed starter can be picked with
+        // the starters it needs
+        for target in crate::boards::TARGETS {
+            let listed = ids(Some(target));
+            for starter in STARTERS {
+                let needs_met = starter.needs.iter().all(|alternatives| {
+                    alternatives
+                        .iter()
+                        .any(|id| find(id).unwrap().supports(target))
+                });
+                assert_eq!(
+                    listed.contains(&starter.id),
+                    starter.supports(target) && needs_met
+                );
+            }
+            for id in &listed {
+                let mut picked = vec![id.to_string()];
+                if let Some(alternatives) = find(id).unwrap().needs.first() {
+                    let need = alternatives.iter().find(|need| listed.contains(need));
+                    picked.push(need.unwrap().to_string());
+                }
+                assert!(
+                    resolve(&picked, Some(target)).is_ok(),
+                    "{} on {}",
+                    id,
+                    target
+                );
+            }
+        }
+
+        let mqtt = StarterRequirements::new(find("mqtt").unwrap());
+        assert_eq!(
+            mqtt.label(),
+            "mqtt: Connect to an MQTT broker set in menuconfig (needs wifi or ethernet, ESP-IDF 5.0+)"
+        );
+        let led = StarterRequirements::new(find("led").unwrap());
+        assert_eq!(led.dependencies[0].name, "espressif/led_strip");
+        assert_eq!(led.chips(), "all");
+    }
+
+    #[test]
+    fn test_starter_sources() {
+        for starter in STARTERS {
+            // Every option read by the sources is in the menu of the starter
+            for (name, content) in starter.files {
+                for option in content.split("CONFIG_STARTER_").skip(1) {
+                    let option: String = option
+                        .chars()
+                        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                        .collect();
+                    assert!(
+                        starter
+                            .kconfig
+                            .contains(&format!("config STARTER_{}\n", option)),
+                        "{} reads CONFIG_STARTER_{} but the menu doesn't define it",
+                        name,
+                        option
+                    );
+                }
+            }
+            // C++ projects call the init function of the C sources
+            let header = starter
+                .files
+                .iter()
+                .find(|(name, _)| *name == starter.header)
+                .unwrap()
+                .1;
+            let init = header
+                .find(&format!(" {}(void);", starter.init_function))
+                .unwrap();
+            assert!(header.find("extern \"C\" {").unwrap() < init);
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        for starter in STARTERS {
+            for hook in starter.hooks {
+                let content = starter
+                    .files
+                    .iter()
+                    .find(|(name, _)| *name == hook.file)
+                    .unwrap()
+                    .1;
+                let anchors = content
+                    .lines()
+                    .filter(|line| line.trim() == hook.after)
+                    .count();
+                assert_eq!(anchors, 1, "{}: {}", hook.file, hook.after);
+                assert!(find(hook.with).is_ok());
+            }
+        }
+
+        let files = WIFI.files_with(&["events"]);
+        assert!(files[1].1.starts_with(
+            "#include \"starter_wifi.h\"\n#include \"starter_events.h\"\n\n#include <string.h>\n"
+        ));
+        assert!(files[1].1.contains(
+            "        esp_wifi_connect();\n        if (event_id == WIFI_EVENT_STA_DISCONNECTED) {\n            starter_events_post(STARTER_EVENT_NETWORK_DISCONNECTED);\n        }\n"
+        ));
+        assert_eq!(files[0].1, WIFI_HEADER);
+        let files = WIFI.files_with(&["led"]);
+        assert_eq!(files[1].1, WIFI_SOURCE);
+    }
+
+    #[test]
+    fn test_apply_starter_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        let main_dir = dir.path().join("main");
+
+        apply_starter(dir.path(), &EVENTS, MergeMode::Fail, true).unwrap();
+        apply_starter(dir.path(), &MQTT, MergeMode::Fail, true).unwrap();
+        let mqtt = fs::read_to_string(main_dir.join("starter_mqtt.c")).unwrap();
+        assert!(mqtt.contains("starter_events_post(STARTER_EVENT_MQTT_CONNECTED);"));
+
+        // Added after Wi-Fi, which must be applied again to post its state
+        fs::remove_file(main_dir.join("starter_events.c")).unwrap();
+        apply_starter(dir.path(), &WIFI, MergeMode::Fail, true).unwrap();
+        let wifi = fs::read_to_string(main_dir.join("starter_wifi.c")).unwrap();
+        assert_eq!(wifi, WIFI_SOURCE);
+        let report = apply_starter(dir.path(), &EVENTS, MergeMode::Overwrite, true).unwrap();
+        // MQTT has the snippets already
+        assert_eq!(
+            report.manual_steps[0],
+            "Run `esp-create-project add wifi --on-conflict overwrite` to use starter \"events\" in it (your changes to its files are lost)"
+        );
+        assert!(!report.manual_steps[1..]
+            .iter()
+            .any(|step| step.contains("add mqtt")));
+    }
+
+    #[test]
+    fn test_readme_table_is_up_to_date() {
+        assert!(
+            include_str!("../README.md").contains(&markdown_table()),
+            "Update the starters table of the README with starters::markdown_table()"
+        );
+    }
+
+    #[test]
+    fn test_apply_starter() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        fs::write(
+            dir.path().join("main").join("main.c"),
+            templates::C_TEMPLATE,
+        )
+        .unwrap();
+
+        let report = apply_starter(dir.path(), &WIFI, MergeMode::Fail, true).unwrap();
+        assert_eq!(report.written.len(), 2);
+        assert!(report.manual_steps.is_empty());
+
+        let main_dir = dir.path().join("main");
+        let cmake = fs::read_to_string(main_dir.join("CMakeLists.txt")).unwrap();
+        assert!(cmake.contains(r#"set(COMPONENT_SRCS "main.c" "starter_wifi.c")"#));
+        assert!(cmake.contains("esp_wifi"));
+        let kconfig = fs::read_to_string(main_dir.join("Kconfig.projbuild")).unwrap();
+        assert!(kconfig.contains("config STARTER_WIFI_SSID"));
+        let main = fs::read_to_string(main_dir.join("main.c")).unwrap();
+        assert!(main.contains("#include \"starter_wifi.h\"\n"));
+        assert!(main.contains("    starter_wifi_init();\n    // TODO Insert code"));
+
+        // Applying it again conflicts unless asked to skip, which changes nothing
+        assert!(apply_starter(dir.path(), &WIFI, MergeMode::Fail, true).is_err());
+        let report = apply_starter(dir.path(), &WIFI, MergeMode::Skip, true).unwrap();
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(fs::read_to_string(main_dir.join("main.c")).unwrap(), main);
+        assert_eq!(
+            fs::read_to_string(main_dir.join("Kconfig.projbuild")).unwrap(),
+            kconfig
+        );
+    }
+
+    #[test]
+    fn test_apply_starter_app_class() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        let main_dir = dir.path().join("main");
+        assert_eq!(Integration::detect(&main_dir), Integration::AppMain);
+        fs::write(
+            main_dir.join("main.cpp"),
+            templates::APP_CLASS_MAIN_TEMPLATE,
+        )
+        .unwrap();
+        fs::write(
+            main_dir.join(templates::APP_CLASS_SOURCE_FILE),
+            templates::APP_CLASS_SOURCE_TEMPLATE,
+        )
+        .unwrap();
+        assert_eq!(Integration::detect(&main_dir), Integration::AppClass);
+
+        let report = apply_starter(dir.path(), &NVS, MergeMode::Fail, true).unwrap();
+        assert!(report.manual_steps.is_empty());
+        let app = fs::read_to_string(main_dir.join(templates::APP_CLASS_SOURCE_FILE)).unwrap();
+        assert!(app.contains("#include \"starter_nvs.h\"\n"));
+        assert!(app.contains("    starter_nvs_init();\n    // TODO Insert code"));
+        assert_eq!(
+            fs::read_to_string(main_dir.join("main.cpp")).unwrap(),
+            templates::APP_CLASS_MAIN_TEMPLATE
+        );
+
+        // Without the marker, the step names App::init
+        fs::write(main_dir.join(templates::APP_CLASS_SOURCE_FILE), "").unwrap();
+        let report = apply_starter(dir.path(), &LED, MergeMode::Fail, true).unwrap();
+        assert_eq!(
+            report.manual_steps,
+            ["Call starter_led_init() from App::init, after #include \"starter_led.h\""]
+        );
+    }
+
+    #[test]
+    fn test_apply_starter_merges_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        fs::write(dir.path().join("sdkconfig.defaults"), "CONFIG_A=n\n").unwrap();
+        let starter = Starter {
+            sdkconfig: &[("CONFIG_A", "y"), ("CONFIG_B", "y")],
+            ..LED
+        };
+
+        let report = apply_starter(dir.path(), &starter, MergeMode::Fail, true).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("sdkconfig.defaults")).unwrap(),
+            "CONFIG_A=n\nCONFIG_B=y\n"
+        );
+        let manifest =
+            fs::read_to_string(dir.path().join("main").join(IDF_COMPONENT_FILE)).unwrap();
+        assert!(manifest.contains("espressif/led_strip"));
+        // The fixture main file is not the built-in one
+        assert_eq!(report.manual_steps.len(), 1);
+    }
+
+    #[test]
+    fn test_apply_starter_without_sdkconfig() {
+        let dir = tempfile::tempdir().unwrap();
+        test_fixtures::write_template(dir.path());
+        let starter = Starter {
+            sdkconfig: &[("CONFIG_A", "y"), ("CONFIG_B", "3")],
+            ..LED
+        };
+
+        let report = apply_starter(dir.path(), &starter, MergeMode::Fail, false).unwrap();
+
+        assert!(!dir.path().join("sdkconfig.defaults").exists());
+        assert_eq!(
+            report.manual_steps[0],
+            "Set CONFIG_A=y, CONFIG_B=3 with idf.py menuconfig, starter \"led\" needs it"
+        );
+    }
+}
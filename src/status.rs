@@ -0,0 +1,274 @@
+//! Status lines of the generation steps
+//!
+//! On a terminal the running step ("🌐 Downloading template") is replaced in
+//! place by its outcome ("✔ Template downloaded"). When stdout is piped or
+//! redirected, every step is printed on its own line instead, without
+//! carriage returns. Everything printed while a step runs goes through the
+//! status line, so warnings don't end up in the middle of it. The accessible
+//! mode prints every step on its own line on a terminal too, see
+//! [`set_plain`]. When stdout carries the output asked for, e.g. the
+//! `--export-env` lines, the steps and the messages go to stderr, see
+//! [`set_stderr`].
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use crate::symbols::Symbol;
+
+/// Columns assumed when the terminal doesn't report its size
+const DEFAULT_WIDTH: usize = 80;
+
+/// Prints the status of the steps, see the module documentation
+#[derive(Debug)]
+pub struct StatusLine<W: Write> {
+    out: W,
+    /// Whether `out` is a terminal, where the running step is replaced in place
+    interactive: bool,
+    /// Columns of the terminal, the running step is cut to fit in one line
+    /// so it can be erased
+    width: usize,
+    /// The running step as displayed, `None` once it finished
+    running: Option<String>,
+}
+
+impl<W: Write> StatusLine<W> {
+    /// Creates the status line
+    ///
+    /// # Arguments
+    /// * `out` - Where the steps are printed
+    /// * `interactive` - Whether `out` is a terminal
+    /// * `width` - Columns of the terminal
+    pub fn new(out: W, interactive: bool, width: usize) -> Self {
+        StatusLine {
+            out,
+            interactive,
+            width,
+            running: None,
+        }
+    }
+
+    /// Shows a step that is running, replaced by the next [`StatusLine::finish`]
+    ///
+    /// # Arguments
+    /// * `symbol` - The symbol, see [`crate::symbols`]
+    /// * `message` - What the step does
+    ///
+    /// # Errors
+    /// If the output cannot be written
+    pub fn start(&mut self, symbol: &str, message: &str) -> io::Result<()> {
+        self.erase()?;
+        let line = format!("{} {}", symbol, message);
+        if !self.interactive {
+            self.running = None;
+            return writeln!(self.out, "{}", line);
+        }
+        // A wrapped line cannot be erased with a carriage return
+        let line = console::truncate_str(&line, self.width.saturating_sub(1), "…").into_owned();
+        write!(self.out, "{}", line)?;
+        self.running = Some(line);
+        self.out.flush()
+    }
+
+    /// Replaces the running step, if any, by its outcome
+    ///
+    /// # Arguments
+    /// * `symbol` - The symbol, see [`crate::symbols`]
+    /// * `message` - The outcome of the step
+    ///
+    /// # Errors
+    /// If the output cannot be written
+    pub fn finish(&mut self, symbol: &str, message: &str) -> io::Result<()> {
+        self.erase()?;
+        self.running = None;
+        writeln!(self.out, "{} {}", symbol, message)?;
+        self.out.flush()
+    }
+
+    /// Prints a warning to `err` on its own line, then shows the running
+    /// step again
+    ///
+    /// # Arguments
+    /// * `err` - Where warnings are printed, stderr outside tests
+    /// * `symbol` - The symbol, see [`crate::symbols`]
+    /// * `message` - The warning
+    ///
+    /// # Errors
+    /// If the output cannot be written
+    pub fn warn(&mut self, err: &mut impl Write, symbol: &str, message: &str) -> io::Result<()> {
+        self.erase()?;
+        writeln!(err, "{} {}", symbol, message)?;
+        err.flush()?;
+        if let Some(line) = &self.running {
+            write!(self.out, "{}", line)?;
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Erases the running step and forgets it, e.g. before an error is printed
+    ///
+    /// # Errors
+    /// If the output cannot be written
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.erase()?;
+        self.running = None;
+        self.out.flush()
+    }
+
+    /// Erases the running step from the terminal, it stays the running step
+    fn erase(&mut self) -> io::Result<()> {
+        if let Some(line) = &self.running {
+            let width = console::measure_text_width(line);
+            write!(self.out, "\r{}\r", " ".repeat(width))?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the steps are printed one per line even on a terminal
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Prints every step on its own line, the running step is never erased; for
+/// screen readers, once at startup before any step
+pub fn set_plain() {
+    PLAIN.store(true, Ordering::Relaxed);
+}
+
+/// Whether the steps are not printed, only the warnings
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Stops printing the steps, e.g. while `batch` generates many projects
+/// and prints one line per project instead; the warnings are still printed
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+/// Whether the steps and the messages are printed to stderr
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Prints the steps and the messages to stderr, stdout is left to the
+/// output asked for (e.g. `--export-env` without a file); once at startup
+/// before any step
+pub fn set_stderr() {
+    TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Where the steps and the messages are printed, see [`set_stderr`]
+fn output() -> Box<dyn Write + Send> {
+    if TO_STDERR.load(Ordering::Relaxed) {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    }
+}
+
+/// The status line, shared by every step of the process
+fn stdout_line() -> MutexGuard<'static, StatusLine<Box<dyn Write + Send>>> {
+    static LINE: OnceLock<Mutex<StatusLine<Box<dyn Write + Send>>>> = OnceLock::new();
+    LINE.get_or_init(|| {
+        let term = if TO_STDERR.load(Ordering::Relaxed) {
+            console::Term::stderr()
+        } else {
+            console::Term::stdout()
+        };
+        let width = term
+            .size_checked()
+            .map_or(DEFAULT_WIDTH, |(_, columns)| usize::from(columns));
+        let interactive = term.is_term() && !PLAIN.load(Ordering::Relaxed);
+        Mutex::new(StatusLine::new(output(), interactive, width))
+    })
+    .lock()
+    // A panic while printing leaves nothing inconsistent
+    .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Printing the status is best effort, e.g. stdout may be a closed pipe
+
+/// [`StatusLine::start`] on stdout
+pub fn start(symbol: Symbol, message: &str) {
+    if QUIET.load(Ordering::Relaxed) {
+        return;
+    }
+    let _ = stdout_line().start(&symbol.to_string(), message);
+}
+
+/// [`StatusLine::finish`] on stdout
+pub fn finish(symbol: Symbol, message: &str) {
+    if QUIET.load(Ordering::Relaxed) {
+        return;
+    }
+    let _ = stdout_line().finish(&symbol.to_string(), message);
+}
+
+/// [`StatusLine::warn`] on stdout, the warning is printed to stderr
+pub fn warn(symbol: Symbol, message: &str) {
+    if QUIET.load(Ordering::Relaxed) {
+        eprintln!("{} {}", symbol, message);
+        return;
+    }
+    let _ = stdout_line().warn(&mut io::stderr(), &symbol.to_string(), message);
+}
+
+/// [`StatusLine::clear`] on stdout
+pub fn clear() {
+    let _ = stdout_line().clear();
+}
+
+/// Prints a message of the generation on its own line, to stdout or to
+/// stderr after [`set_stderr`]; see the [`crate::say`] macro
+pub fn say(message: fmt::Arguments) {
+    let _ = writeln!(output(), "{}", message);
+}
+
+/// `println!` for the messages of the generation, see [`fn@crate::status::say`]
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::status::say(format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piped_output_is_one_line_per_step() {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let mut status = StatusLine::new(&mut out, false, 10);
+        status.start("*", "Downloading template").unwrap();
+        status.finish("+", "Template downloaded").unwrap();
+        status.start("*", "Writing files").unwrap();
+        status.warn(&mut err, "!", "Entry skipped").unwrap();
+        status.finish("+", "Files written").unwrap();
+        status.clear().unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "* Downloading template\n+ Template downloaded\n* Writing files\n+ Files written\n"
+        );
+        assert_eq!(String::from_utf8(err).unwrap(), "! Entry skipped\n");
+    }
+
+    #[test]
+    fn test_terminal_replaces_the_running_step() {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let mut status = StatusLine::new(&mut out, true, 12);
+        status.start("*", "Downloading template").unwrap();
+        status.warn(&mut err, "!", "Slow").unwrap();
+        status.finish("+", "Done").unwrap();
+
+        // Cut to 11 columns so it never wraps, then erased with as many spaces
+        let running = "* Download…";
+        let erase = format!("\r{}\r", " ".repeat(11));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{0}{1}{0}{1}+ Done\n", running, erase)
+        );
+        assert_eq!(String::from_utf8(err).unwrap(), "! Slow\n");
+    }
+}
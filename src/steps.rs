@@ -0,0 +1,394 @@
+//! The stages of the generation and what happens when one fails
+//!
+//! An essential stage (extraction, post-processing...) that fails stops the
+//! generation, which then removes what it wrote. An optional one (git init)
+//! can be retried, skipped or abort the generation: the user is asked when
+//! a prompt is set, otherwise the [`FailurePolicy`] decides. Every stage ends
+//! up in a [`StageRecord`] of the generation report.
+
+use serde::Serialize;
+use std::fmt;
+
+use crate::error::{EspCreateError, Result};
+
+/// What the user picks when an optional stage fails
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Recovery {
+    /// Run the stage again, e.g. after fixing the environment
+    Retry,
+    /// Go on without the stage
+    Skip,
+    /// Stop the generation
+    Abort,
+}
+
+impl Recovery {
+    /// Every answer, in the order they are offered
+    pub const ALL: &'static [Recovery] = &[Recovery::Retry, Recovery::Skip, Recovery::Abort];
+}
+
+/// What a failed optional stage does when nobody can be asked
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum FailurePolicy {
+    /// Go on without the stage, with a warning and a manual step
+    #[default]
+    Skip,
+    /// Stop the generation
+    Abort,
+}
+
+impl FailurePolicy {
+    /// Every policy, in the order of the documentation
+    pub const ALL: &'static [FailurePolicy] = &[FailurePolicy::Skip, FailurePolicy::Abort];
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailurePolicy::Skip => "skip",
+            FailurePolicy::Abort => "abort",
+        })
+    }
+}
+
+impl std::str::FromStr for FailurePolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        FailurePolicy::ALL
+            .iter()
+            .copied()
+            .find(|policy| policy.to_string() == name)
+            .ok_or_else(|| format!("unknown policy \"{}\", expected skip or abort", name))
+    }
+}
+
+/// How a stage ended
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageStatus {
+    Done,
+    /// An optional stage that failed and was skipped
+    Skipped,
+    /// An essential stage that failed, or an optional one that was aborted
+    Failed,
+}
+
+impl fmt::Display for StageStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StageStatus::Done => "done",
+            StageStatus::Skipped => "skipped",
+            StageStatus::Failed => "failed",
+        })
+    }
+}
+
+/// A stage of the generation and how it ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StageRecord {
+    pub name: String,
+    /// Whether a failure always stops the generation
+    pub essential: bool,
+    pub status: StageStatus,
+    /// Number of runs, more than one if it was retried
+    pub attempts: u32,
+    /// The error of the last failed run
+    pub error: Option<String>,
+}
+
+/// Asks what to do about a failed optional stage, given its name and error
+pub type RecoveryPrompt = Box<dyn FnMut(&str, &anyhow::Error) -> anyhow::Result<Recovery>>;
+
+/// Asks whether a template without the layout of an ESP-IDF project is
+/// copied as it is, given why; `false` post-processes it anyway
+pub type PlainCopyPrompt = Box<dyn FnMut(&[String]) -> anyhow::Result<bool>>;
+
+/// Runs the stages of a generation and records how each one ended
+pub struct StepRunner {
+    policy: FailurePolicy,
+    prompt: Option<RecoveryPrompt>,
+    plain_copy_prompt: Option<PlainCopyPrompt>,
+    stages: Vec<StageRecord>,
+}
+
+impl Default for StepRunner {
+    fn default() -> Self {
+        StepRunner::new(FailurePolicy::default())
+    }
+}
+
+impl StepRunner {
+    /// A runner that never asks, failed optional stages follow the policy
+    ///
+    /// # Arguments
+    /// * `policy` - What a failed optional stage does
+    pub fn new(policy: FailurePolicy) -> Self {
+        StepRunner {
+            policy,
+            prompt: None,
+            plain_copy_prompt: None,
+            stages: Vec::new(),
+        }
+    }
+
+    /// A runner that asks what to do when an optional stage fails
+    ///
+    /// # Arguments
+    /// * `prompt` - Asks the user, an error of the prompt aborts the stage
+    pub fn with_prompt(prompt: RecoveryPrompt) -> Self {
+        StepRunner {
+            prompt: Some(prompt),
+            ..StepRunner::default()
+        }
+    }
+
+    /// Asks before copying a template without the layout of an ESP-IDF
+    /// project as it is, see [`crate::options::PostProcess::Auto`]
+    ///
+    /// # Arguments
+    /// * `prompt` - Asks the user, an error of the prompt stops the generation
+    pub fn with_plain_copy_prompt(self, prompt: PlainCopyPrompt) -> Self {
+        StepRunner {
+            plain_copy_prompt: Some(prompt),
+            ..self
+        }
+    }
+
+    /// Whether a template without the expected layout is copied as it is:
+    /// the answer of the prompt if set, otherwise it is
+    ///
+    /// # Arguments
+    /// * `issues` - Why the template doesn't have the expected layout
+    ///
+    /// # Errors
+    /// If the prompt failed
+    pub fn plain_copy(&mut self, issues: &[String]) -> anyhow::Result<bool> {
+        match &mut self.plain_copy_prompt {
+            Some(prompt) => prompt(issues),
+            None => Ok(true),
+        }
+    }
+
+    /// The stages run so far, in order
+    pub fn stages(&self) -> &[StageRecord] {
+        &self.stages
+    }
+
+    /// Runs an essential stage, its error is returned as is
+    ///
+    /// # Arguments
+    /// * `name` - The name of the stage, in the report
+    /// * `stage` - The stage
+    ///
+    /// # Errors
+    /// The error of the stage
+    pub fn essential<T, F>(&mut self, name: &str, stage: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let result = stage();
+        self.record(
+            name,
+            true,
+            1,
+            match &result {
+                Ok(_) => Ok(()),
+                Err(EspCreateError::Cancelled) => Err("cancelled".to_string()),
+                Err(EspCreateError::Other(error)) => Err(format!("{:#}", error)),
+            },
+        );
+        result
+    }
+
+    /// Runs an optional stage until it succeeds, is skipped or aborted
+    ///
+    /// # Arguments
+    /// * `name` - The name of the stage, in the report and the prompt
+    /// * `stage` - The stage, called again on each retry
+    ///
+    /// # Returns
+    /// `true` if the stage succeeded, `false` if it was skipped
+    ///
+    /// # Errors
+    /// If the stage failed and was aborted, or the prompt failed
+    pub fn optional<F>(&mut self, name: &str, mut stage: F) -> anyhow::Result<bool>
+    where
+        F: FnMut() -> anyhow::Result<()>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match stage() {
+                Ok(()) => {
+                    self.record(name, false, attempts, Ok(()));
+                    return Ok(true);
+                }
+                Err(error) => error,
+            };
+            let recovery = match &mut self.prompt {
+                Some(prompt) => prompt(name, &error),
+                None => Ok(match self.policy {
+                    FailurePolicy::Skip => Recovery::Skip,
+                    FailurePolicy::Abort => Recovery::Abort,
+                }),
+            };
+            match recovery {
+                Ok(Recovery::Retry) => continue,
+                Ok(Recovery::Skip) => {
+                    self.record(name, false, attempts, Err(format!("{:#}", error)));
+                    self.stages.last_mut().unwrap().status = StageStatus::Skipped;
+                    return Ok(false);
+                }
+                Ok(Recovery::Abort) => {
+                    self.record(name, false, attempts, Err(format!("{:#}", error)));
+                    return Err(error.context(format!("{} failed", name)));
+                }
+                Err(prompt_error) => {
+                    self.record(name, false, attempts, Err(format!("{:#}", error)));
+                    return Err(prompt_error);
+                }
+            }
+        }
+    }
+
+    fn record(
+        &mut self,
+        name: &str,
+        essential: bool,
+        attempts: u32,
+        result: std::result::Result<(), String>,
+    ) {
+        self.stages.push(StageRecord {
+            name: name.to_string(),
+            essential,
+            status: if result.is_ok() {
+                StageStatus::Done
+            } else {
+                StageStatus::Failed
+            },
+            attempts,
+            error: result.err(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A prompt giving the answers in order, and counting the questions
+    fn scripted(answers: &[Recovery]) -> (RecoveryPrompt, Rc<Cell<usize>>) {
+        let asked = Rc::new(Cell::new(0));
+        let answers = answers.to_vec();
+        let counter = Rc::clone(&asked);
+        let prompt = Box::new(move |_: &str, _: &anyhow::Error| {
+            let answer = answers[counter.get()];
+            counter.set(counter.get() + 1);
+            Ok(answer)
+        });
+        (prompt, asked)
+    }
+
+    /// A stage failing the given number of times before succeeding
+    fn failing(failures: u32) -> impl FnMut() -> anyhow::Result<()> {
+        let mut runs = 0;
+        move || {
+            runs += 1;
+            if runs <= failures {
+                Err(anyhow!("run {} failed", runs))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_plain_copy() {
+        let issues = ["no main/ directory".to_string()];
+        assert!(StepRunner::default().plain_copy(&issues).unwrap());
+        let mut runner = StepRunner::new(FailurePolicy::Abort)
+            .with_plain_copy_prompt(Box::new(|issues| Ok(issues.is_empty())));
+        assert!(!runner.plain_copy(&issues).unwrap());
+        assert!(runner.plain_copy(&[]).unwrap());
+    }
+
+    #[test]
+    fn test_retry_until_success() {
+        let (prompt, asked) = scripted(&[Recovery::Retry, Recovery::Retry]);
+        let mut runner = StepRunner::with_prompt(prompt);
+
+        assert!(runner.optional("git init", failing(2)).unwrap());
+
+        assert_eq!(asked.get(), 2);
+        let stage = &runner.stages()[0];
+        assert_eq!(stage.status, StageStatus::Done);
+        assert_eq!(stage.attempts, 3);
+        assert_eq!(stage.error, None);
+    }
+
+    #[test]
+    fn test_retry_then_skip() {
+        let (prompt, asked) = scripted(&[Recovery::Retry, Recovery::Skip]);
+        let mut runner = StepRunner::with_prompt(prompt);
+
+        assert!(!runner.optional("git init", failing(5)).unwrap());
+
+        assert_eq!(asked.get(), 2);
+        let stage = &runner.stages()[0];
+        assert_eq!(stage.status, StageStatus::Skipped);
+        assert_eq!(stage.attempts, 2);
+        assert_eq!(stage.error.as_deref(), Some("run 2 failed"));
+    }
+
+    #[test]
+    fn test_abort() {
+        let (prompt, _) = scripted(&[Recovery::Abort]);
+        let mut runner = StepRunner::with_prompt(prompt);
+
+        let error = runner.optional("git init", failing(1)).unwrap_err();
+
+        assert_eq!(format!("{:#}", error), "git init failed: run 1 failed");
+        assert_eq!(runner.stages()[0].status, StageStatus::Failed);
+    }
+
+    #[test]
+    fn test_policy_without_prompt() {
+        let mut runner = StepRunner::new(FailurePolicy::Skip);
+        assert!(!runner.optional("git init", failing(1)).unwrap());
+        assert_eq!(runner.stages()[0].status, StageStatus::Skipped);
+
+        let mut runner = StepRunner::new(FailurePolicy::Abort);
+        assert!(runner.optional("git init", failing(1)).is_err());
+        assert_eq!(runner.stages()[0].status, StageStatus::Failed);
+
+        for policy in FailurePolicy::ALL {
+            assert_eq!(policy.to_string().parse::<FailurePolicy>(), Ok(*policy));
+        }
+        assert!("retry".parse::<FailurePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_essential_is_never_asked() {
+        let (prompt, asked) = scripted(&[]);
+        let mut runner = StepRunner::with_prompt(prompt);
+
+        let result: Result<()> = runner.essential("extraction", || Err(anyhow!("bad zip").into()));
+
+        assert!(result.is_err());
+        assert_eq!(asked.get(), 0);
+        assert_eq!(
+            runner.stages(),
+            [StageRecord {
+                name: "extraction".into(),
+                essential: true,
+                status: StageStatus::Failed,
+                attempts: 1,
+                error: Some("bad zip".into()),
+            }]
+        );
+    }
+}
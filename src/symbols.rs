@@ -0,0 +1,176 @@
+//! The symbols in front of the status lines, emojis or plain ASCII
+//!
+//! Legacy Windows consoles, non UTF-8 locales and minimal terminals show
+//! emojis as garbage, so the set is picked once at startup from what the
+//! terminal can display, see [`SymbolSet::detect`]. Every symbol is listed in
+//! [`SYMBOLS`], nothing else prints emojis.
+
+use std::env;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The symbols a terminal can display
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SymbolSet {
+    Emoji,
+    Ascii,
+    /// ASCII with words for the outcomes (`[OK]`, `[FAIL]`), so none is told
+    /// apart by its color or shape alone, see [`crate::theme::Theme`]
+    Words,
+}
+
+/// `TERM` values of terminals that only display ASCII
+const ASCII_TERMS: &[&str] = &["dumb", "linux", "vt100", "vt220"];
+
+impl SymbolSet {
+    /// Picks the set of the terminal
+    ///
+    /// # Arguments
+    /// * `force_ascii` - Whether `--ascii` was passed
+    /// * `term` - The `TERM` variable
+    /// * `console_emoji` - Whether the console reports it can display emojis:
+    ///   stdout is a terminal, with a UTF-8 locale on Unix or a modern console
+    ///   on Windows
+    pub fn detect(force_ascii: bool, term: Option<&str>, console_emoji: bool) -> SymbolSet {
+        let ascii_term = term.is_some_and(|term| ASCII_TERMS.contains(&term));
+        if force_ascii || ascii_term || !console_emoji {
+            SymbolSet::Ascii
+        } else {
+            SymbolSet::Emoji
+        }
+    }
+
+    /// Picks the set of the terminal of this process, see [`SymbolSet::detect`]
+    ///
+    /// # Arguments
+    /// * `force_ascii` - Whether `--ascii` was passed
+    pub fn from_env(force_ascii: bool) -> SymbolSet {
+        SymbolSet::detect(
+            force_ascii,
+            env::var("TERM").ok().as_deref(),
+            console::Term::stdout().features().wants_emoji(),
+        )
+    }
+}
+
+/// A symbol of the status lines
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Symbol {
+    Download,
+    Unzip,
+    Write,
+    Git,
+    Configure,
+    Todo,
+    Port,
+    Done,
+    Failed,
+    Kept,
+    Edit,
+    Warning,
+    Info,
+    Fun,
+    Language,
+    Cause,
+}
+
+/// The outcomes as words, the other symbols are the ASCII ones in
+/// [`SymbolSet::Words`]
+pub const WORDS: &[(Symbol, &str)] = &[
+    (Symbol::Done, "[OK]"),
+    (Symbol::Failed, "[FAIL]"),
+    (Symbol::Kept, "[KEPT]"),
+    (Symbol::Edit, "[EDIT]"),
+    (Symbol::Warning, "[WARN]"),
+    (Symbol::Info, "[INFO]"),
+];
+
+/// Every symbol, as an emoji and in ASCII
+pub const SYMBOLS: &[(Symbol, &str, &str)] = &[
+    (Symbol::Download, "🌐", "[*]"),
+    (Symbol::Unzip, "🗄", "[*]"),
+    (Symbol::Write, "📁", "[*]"),
+    (Symbol::Git, "⚙️", "[*]"),
+    (Symbol::Configure, "🔧", "[*]"),
+    (Symbol::Todo, "📝", "[*]"),
+    (Symbol::Port, "🔌", "[+]"),
+    (Symbol::Done, "✔", "[ok]"),
+    (Symbol::Failed, "✘", "[x]"),
+    (Symbol::Kept, "-", "[-]"),
+    (Symbol::Edit, "✎", "[~]"),
+    (Symbol::Warning, "⚠", "[!]"),
+    (Symbol::Info, "ℹ", "[i]"),
+    (Symbol::Fun, "😁", ":)"),
+    // The prompt has its own marker
+    (Symbol::Language, "💻", ""),
+    // The causes of an error
+    (Symbol::Cause, "•", "-"),
+];
+
+impl Symbol {
+    /// The symbol in a set
+    pub fn text(self, set: SymbolSet) -> &'static str {
+        if set == SymbolSet::Words {
+            if let Some((_, word)) = WORDS.iter().find(|(symbol, _)| *symbol == self) {
+                return word;
+            }
+        }
+        SYMBOLS
+            .iter()
+            .find(|(symbol, _, _)| *symbol == self)
+            .map_or("*", |(_, emoji, ascii)| match set {
+                SymbolSet::Emoji => emoji,
+                SymbolSet::Ascii | SymbolSet::Words => ascii,
+            })
+    }
+}
+
+/// Displays the symbol in the set of the terminal, see [`symbol_set`]
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.text(symbol_set()))
+    }
+}
+
+static SYMBOL_SET: OnceLock<SymbolSet> = OnceLock::new();
+
+/// Sets the symbols of the process, once at startup before anything is
+/// printed; later calls are ignored
+pub fn set_symbol_set(set: SymbolSet) {
+    let _ = SYMBOL_SET.set(set);
+}
+
+/// The symbols of the process, detected from the terminal if
+/// [`set_symbol_set`] wasn't called
+pub fn symbol_set() -> SymbolSet {
+    *SYMBOL_SET.get_or_init(|| SymbolSet::from_env(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let term = Some("xterm-256color");
+        assert_eq!(SymbolSet::detect(false, term, true), SymbolSet::Emoji);
+        assert_eq!(SymbolSet::detect(true, term, true), SymbolSet::Ascii);
+        assert_eq!(SymbolSet::detect(false, term, false), SymbolSet::Ascii);
+        assert_eq!(
+            SymbolSet::detect(false, Some("dumb"), true),
+            SymbolSet::Ascii
+        );
+        assert_eq!(SymbolSet::detect(false, None, true), SymbolSet::Emoji);
+    }
+
+    #[test]
+    fn test_every_symbol_has_both_forms() {
+        for (symbol, emoji, ascii) in SYMBOLS {
+            assert_eq!(symbol.text(SymbolSet::Emoji), *emoji);
+            assert!(ascii.is_ascii(), "{:?}", symbol);
+        }
+        assert_eq!(Symbol::Done.text(SymbolSet::Ascii), "[ok]");
+        assert_eq!(Symbol::Failed.text(SymbolSet::Words), "[FAIL]");
+        assert_eq!(Symbol::Download.text(SymbolSet::Words), "[*]");
+    }
+}
@@ -0,0 +1,342 @@
+//! The files of a template without extracting it, as the generation would
+//! write them: for tools that show the file tree of a template before a
+//! project is generated from it, see also [`crate::template_source`]
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+pub use crate::archive::EntryKind;
+use crate::archive::{self, ExtractOptions, StoredEntry};
+use crate::template_cache::TemplateCache;
+
+/// A template on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateArchive {
+    Zip(PathBuf),
+    TarGz(PathBuf),
+    /// A template directory, e.g. a checkout of the template repository
+    Directory(PathBuf),
+}
+
+/// An entry of a template, see [`TemplateArchive::list_entries`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TemplateEntry {
+    /// The path in the project, the prefix of the template stripped
+    pub path: PathBuf,
+    /// The size of its content, uncompressed; 0 for a directory
+    pub size: u64,
+    pub kind: EntryKind,
+}
+
+impl From<StoredEntry> for TemplateEntry {
+    fn from(entry: StoredEntry) -> Self {
+        TemplateEntry {
+            path: entry.path,
+            size: entry.size,
+            kind: entry.kind,
+        }
+    }
+}
+
+impl TemplateArchive {
+    /// Opens a template: a directory, or an archive told apart by its first
+    /// bytes
+    ///
+    /// # Errors
+    /// If the path cannot be read, or is neither a directory, a zip nor a tar.gz
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if path.is_dir() {
+            return Ok(TemplateArchive::Directory(path.to_path_buf()));
+        }
+        let mut magic = [0; 4];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .context(format!("Cannot read the template \"{}\"", path.display()))?;
+        match magic {
+            [0x1f, 0x8b, ..] => Ok(TemplateArchive::TarGz(path.to_path_buf())),
+            [b'P', b'K', ..] => Ok(TemplateArchive::Zip(path.to_path_buf())),
+            _ => bail!(
+                "The template \"{}\" is neither a zip nor a tar.gz",
+                path.display()
+            ),
+        }
+    }
+
+    /// The cached archive of a URL, see [`TemplateCache::lookup`]
+    ///
+    /// # Returns
+    /// `None` if the URL is not cached
+    ///
+    /// # Errors
+    /// If the cached archive cannot be read
+    pub fn cached(cache: &TemplateCache, url: &str) -> anyhow::Result<Option<Self>> {
+        cache
+            .lookup(url)
+            .map(|(_, path)| Self::open(&path))
+            .transpose()
+    }
+
+    /// The path of the archive or directory
+    pub fn path(&self) -> &Path {
+        match self {
+            TemplateArchive::Zip(path)
+            | TemplateArchive::TarGz(path)
+            | TemplateArchive::Directory(path) => path,
+        }
+    }
+
+    /// The entries the generation would extract, in the order of the
+    /// archive (sorted by name for a directory): without the prefix, the
+    /// skipped entries and, with [`ExtractOptions::build_files_only`], the
+    /// files that are not build files and every directory
+    ///
+    /// # Arguments
+    /// * `prefix` - The directory the files are taken from, see
+    ///   [`crate::options::ScaffoldOptions::template_prefix`]; detected for an
+    ///   archive when `None`, as [`archive::detect_prefix`] does
+    /// * `options` - The entries left out, only
+    ///   [`ExtractOptions::skipped_entries`] and
+    ///   [`ExtractOptions::build_files_only`] are used
+    ///
+    /// # Errors
+    /// If the template cannot be read, or has no `prefix` directory
+    pub fn list_entries(
+        &self,
+        prefix: Option<&Path>,
+        options: &ExtractOptions,
+    ) -> anyhow::Result<Vec<TemplateEntry>> {
+        let entries: Vec<TemplateEntry> = match self {
+            TemplateArchive::Zip(path) => zip_entries(path)?,
+            TemplateArchive::TarGz(path) => tar_gz_entries(path)?,
+            TemplateArchive::Directory(path) => directory_entries(path)?,
+        }
+        .into_iter()
+        .filter(|entry| !archive::is_skipped(&entry.path, options.skipped_entries))
+        .collect();
+
+        let prefix = match prefix {
+            Some(prefix) => {
+                if !entries.iter().any(|entry| entry.path.starts_with(prefix)) {
+                    bail!(
+                        "The template has no directory \"{}\" to take its files from",
+                        prefix.display()
+                    );
+                }
+                prefix.to_path_buf()
+            }
+            None if matches!(self, TemplateArchive::Directory(_)) => PathBuf::new(),
+            None => common_root(&entries),
+        };
+
+        let mut listed: Vec<TemplateEntry> = Vec::new();
+        for entry in entries {
+            let path = match entry.path.strip_prefix(&prefix) {
+                Ok(path) if !path.as_os_str().is_empty() => path.to_path_buf(),
+                _ => continue,
+            };
+            if options.build_files_only
+                && (entry.kind == EntryKind::Directory || !archive::is_build_file(&path))
+            {
+                continue;
+            }
+            // Only the first entry with a path is extracted
+            if listed.iter().any(|listed| listed.path == path) {
+                continue;
+            }
+            listed.push(TemplateEntry { path, ..entry });
+        }
+        Ok(listed)
+    }
+}
+
+/// The directory every entry is in, empty if a file is at the top level
+/// or the entries are in different directories
+fn common_root(entries: &[TemplateEntry]) -> PathBuf {
+    let mut root: Option<PathBuf> = None;
+    for entry in entries {
+        let mut components = entry.path.components();
+        let first = match components.next() {
+            Some(first) => PathBuf::from(first.as_os_str()),
+            None => continue,
+        };
+        if components.next().is_none() && entry.kind != EntryKind::Directory {
+            return PathBuf::new();
+        }
+        match &root {
+            Some(root) if *root != first => return PathBuf::new(),
+            Some(_) => {}
+            None => root = Some(first),
+        }
+    }
+    root.unwrap_or_default()
+}
+
+fn zip_entries(path: &Path) -> anyhow::Result<Vec<TemplateEntry>> {
+    let file =
+        File::open(path).context(format!("Cannot read the template \"{}\"", path.display()))?;
+    let mut zip = zip::ZipArchive::new(file).context("The template is not a valid zip")?;
+    Ok(archive::zip_entries(&mut zip)?
+        .into_iter()
+        .map(TemplateEntry::from)
+        .collect())
+}
+
+fn tar_gz_entries(path: &Path) -> anyhow::Result<Vec<TemplateEntry>> {
+    let file =
+        File::open(path).context(format!("Cannot read the template \"{}\"", path.display()))?;
+    Ok(archive::tar_gz_entries(io::BufReader::new(file))?
+        .into_iter()
+        .filter_map(|entry| {
+            let kind = match entry.kind {
+                _ if entry.is_file() => EntryKind::File,
+                tar::EntryType::Directory => EntryKind::Directory,
+                tar::EntryType::Symlink => EntryKind::Symlink,
+                // Hard links, devices and pax headers are not extracted
+                _ => return None,
+            };
+            Some(TemplateEntry {
+                path: entry.path,
+                size: entry.size,
+                kind,
+            })
+        })
+        .collect())
+}
+
+/// The entries of a template directory, `.git` excluded
+fn directory_entries(root: &Path) -> anyhow::Result<Vec<TemplateEntry>> {
+    Ok(archive::directory_entries(root)?
+        .into_iter()
+        .map(TemplateEntry::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+    use std::fs;
+
+    fn paths(entries: &[TemplateEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| archive::entry_name(&entry.path))
+            .collect()
+    }
+
+    #[test]
+    fn test_list_zip_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("template.zip");
+        fs::write(&path, test_fixtures::template_zip()).unwrap();
+        let template = TemplateArchive::open(&path).unwrap();
+        assert_eq!(template, TemplateArchive::Zip(path.clone()));
+
+        let entries = template
+            .list_entries(None, &ExtractOptions::default())
+            .unwrap();
+        assert_eq!(
+            paths(&entries),
+            [
+                "CMakeLists.txt",
+                "README.md",
+                "main",
+                "main/CMakeLists.txt",
+                "main/main.c",
+                "padding.txt"
+            ]
+        );
+        assert_eq!(entries[2].kind, EntryKind::Directory);
+        assert_eq!(entries[5].size, 64 * 1024);
+
+        let build_files = ExtractOptions {
+            build_files_only: true,
+            ..ExtractOptions::default()
+        };
+        let entries = template.list_entries(None, &build_files).unwrap();
+        assert_eq!(paths(&entries), ["CMakeLists.txt", "main/CMakeLists.txt"]);
+
+        // A prefix given picks a subdirectory
+        let entries = template
+            .list_entries(
+                Some(Path::new("esp-idf-template-master/main")),
+                &ExtractOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(paths(&entries), ["CMakeLists.txt", "main.c"]);
+        assert!(template
+            .list_entries(Some(Path::new("missing")), &ExtractOptions::default())
+            .is_err());
+
+        let cache = TemplateCache::new(dir.path().join("cache"));
+        let url = "https://example.com/template.zip";
+        assert_eq!(TemplateArchive::cached(&cache, url).unwrap(), None);
+        let content = fs::read(&path).unwrap();
+        let sha256 = crate::self_update::sha256_hex(&content);
+        cache
+            .store(url, &sha256, &mut File::open(&path).unwrap())
+            .unwrap();
+        let cached = TemplateArchive::cached(&cache, url).unwrap().unwrap();
+        assert_eq!(
+            cached
+                .list_entries(None, &ExtractOptions::default())
+                .unwrap()
+                .len(),
+            6
+        );
+
+        fs::write(dir.path().join("invalid"), "not a template").unwrap();
+        assert!(TemplateArchive::open(&dir.path().join("invalid")).is_err());
+    }
+
+    #[test]
+    fn test_list_tar_gz_and_directory_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("template");
+        test_fixtures::write_template(&template);
+        fs::write(template.join(".DS_Store"), "").unwrap();
+
+        // The archive has the files only, under a root directory
+        let out = dir.path().join("template.tar.gz");
+        archive::archive_directory(&template, "template-main", &out, true).unwrap();
+        let tar = TemplateArchive::open(&out).unwrap();
+        assert_eq!(tar, TemplateArchive::TarGz(out.clone()));
+        let entries = tar.list_entries(None, &ExtractOptions::default()).unwrap();
+        assert_eq!(
+            paths(&entries),
+            [
+                "CMakeLists.txt",
+                "README.md",
+                "main/CMakeLists.txt",
+                "main/main.c"
+            ]
+        );
+        assert!(entries.iter().all(|entry| entry.kind == EntryKind::File));
+
+        fs::create_dir_all(template.join(".git")).unwrap();
+        let entries = TemplateArchive::open(&template)
+            .unwrap()
+            .list_entries(None, &ExtractOptions::default())
+            .unwrap();
+        assert_eq!(
+            paths(&entries),
+            [
+                "CMakeLists.txt",
+                "README.md",
+                "main",
+                "main/CMakeLists.txt",
+                "main/main.c"
+            ]
+        );
+        assert_eq!(entries[2].kind, EntryKind::Directory);
+        assert_eq!(
+            entries[4].size,
+            fs::metadata(template.join("main").join("main.c"))
+                .unwrap()
+                .len()
+        );
+    }
+}
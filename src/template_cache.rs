@@ -0,0 +1,393 @@
+//! Cache of the downloaded templates, used when the download fails, or
+//! instead of downloading while the cached archive is fresh (see
+//! [`TemplateTtl`])
+//!
+//! Archives are stored once, named after their SHA-256, and an index maps
+//! each template URL to the hash of its last download. Refs whose archives
+//! are identical share the same file.
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::self_update::sha256_hex;
+
+/// Name of the directory, in the state directory, with the cached templates
+pub const CACHE_DIR: &str = "templates";
+
+/// Name of the index file in the cache directory
+pub const INDEX_FILE: &str = "index.json";
+
+/// Extension of the archives in the cache directory
+const ARCHIVE_EXTENSION: &str = "zip";
+
+/// Units of a [`TemplateTtl`], from the largest, and their length in seconds
+const TTL_UNITS: &[(&str, u64)] = &[
+    ("w", 7 * 24 * 3600),
+    ("d", 24 * 3600),
+    ("h", 3600),
+    ("m", 60),
+    ("s", 1),
+];
+
+/// How long a cached template is used without downloading it again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TemplateTtl {
+    /// Downloaded again once the cached archive is older; `0`, the default,
+    /// downloads every time and only falls back to the cache when the
+    /// download fails
+    Expires(Duration),
+    /// Never downloaded again once cached
+    Infinite,
+}
+
+impl Default for TemplateTtl {
+    fn default() -> Self {
+        TemplateTtl::Expires(Duration::ZERO)
+    }
+}
+
+impl TemplateTtl {
+    /// Whether an archive cached this long ago is still used
+    pub fn is_fresh(self, age: Duration) -> bool {
+        match self {
+            TemplateTtl::Expires(ttl) => age < ttl,
+            TemplateTtl::Infinite => true,
+        }
+    }
+}
+
+impl FromStr for TemplateTtl {
+    type Err = anyhow::Error;
+
+    /// Parses `infinite`, `0` or a number followed by `s`, `m` (minutes), `h`,
+    /// `d` or `w`, e.g. `30m` or `7d`
+    fn from_str(ttl: &str) -> Result<Self, Self::Err> {
+        let ttl = ttl.trim();
+        if ttl == "infinite" {
+            return Ok(TemplateTtl::Infinite);
+        }
+        if ttl == "0" {
+            return Ok(TemplateTtl::default());
+        }
+        let split = ttl
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| anyhow!("\"{}\" has no unit, use s, m, h, d or w (e.g. 7d)", ttl))?;
+        let (number, unit) = ttl.split_at(split);
+        let number: u64 = number
+            .parse()
+            .context(format!("\"{}\" is not a valid duration", ttl))?;
+        let seconds = match TTL_UNITS.iter().find(|(name, _)| *name == unit) {
+            Some((_, seconds)) => number.checked_mul(*seconds),
+            None => bail!(
+                "Unknown unit \"{}\", use s, m, h, d or w (e.g. 7d), or infinite",
+                unit
+            ),
+        };
+        let seconds = seconds.ok_or_else(|| anyhow!("\"{}\" is too long", ttl))?;
+        Ok(TemplateTtl::Expires(Duration::from_secs(seconds)))
+    }
+}
+
+impl fmt::Display for TemplateTtl {
+    /// In the largest unit up to days that divides it, as parsed by
+    /// [`TemplateTtl::from_str`]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = match self {
+            TemplateTtl::Infinite => return f.write_str("infinite"),
+            TemplateTtl::Expires(ttl) => ttl.as_secs(),
+        };
+        match TTL_UNITS
+            .iter()
+            .skip_while(|(name, _)| *name == "w")
+            .find(|(_, unit)| seconds > 0 && seconds % unit == 0)
+        {
+            Some((name, unit)) => write!(f, "{}{}", seconds / unit, name),
+            None => f.write_str("0"),
+        }
+    }
+}
+
+impl TryFrom<String> for TemplateTtl {
+    type Error = anyhow::Error;
+
+    fn try_from(ttl: String) -> Result<Self, Self::Error> {
+        ttl.parse()
+    }
+}
+
+impl From<TemplateTtl> for String {
+    fn from(ttl: TemplateTtl) -> Self {
+        ttl.to_string()
+    }
+}
+
+/// The cached templates: the SHA-256 of the archive of each URL
+///
+/// The URL of a ref of the official template names the ref, so each ref has
+/// its own entry.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheIndex {
+    pub templates: BTreeMap<String, String>,
+    /// When each URL was last downloaded, in seconds since the Unix epoch;
+    /// missing for the entries of older versions, which are never fresh
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub downloaded: BTreeMap<String, u64>,
+}
+
+/// A directory of templates stored by content, see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateCache {
+    dir: PathBuf,
+}
+
+impl TemplateCache {
+    /// Uses this directory as the cache, created on the first store
+    pub fn new(dir: PathBuf) -> Self {
+        TemplateCache { dir }
+    }
+
+    /// The cache directory of the user, `None` if the platform has none
+    pub fn default_dir() -> Option<PathBuf> {
+        crate::config::state_dir().map(|dir| dir.join(CACHE_DIR))
+    }
+
+    /// Path of the archive with this SHA-256
+    pub fn archive_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(sha256).with_extension(ARCHIVE_EXTENSION)
+    }
+
+    /// Reads the index, empty if there's no cache or the index is unreadable
+    pub fn index(&self) -> CacheIndex {
+        fs::read_to_string(self.dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Finds the cached archive of a URL
+    ///
+    /// The archive is hashed again, so a corrupted file is never used.
+    ///
+    /// # Arguments
+    /// * `url` - The URL or path of the template
+    ///
+    /// # Returns
+    /// The SHA-256 and the path of the archive, `None` if the URL isn't cached
+    pub fn lookup(&self, url: &str) -> Option<(String, PathBuf)> {
+        let sha256 = self.index().templates.remove(url)?;
+        let path = self.archive_path(&sha256);
+        let content = fs::read(&path).ok()?;
+        if sha256_hex(&content) != sha256 {
+            return None;
+        }
+        Some((sha256, path))
+    }
+
+    /// Finds the cached archive of a URL if it was downloaded recently enough
+    /// to be used without downloading it again, see [`TemplateCache::lookup`]
+    ///
+    /// # Arguments
+    /// * `url` - The URL or path of the template
+    /// * `ttl` - How long a cached archive is used
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    /// The SHA-256 and the path of the archive, `None` if the URL isn't
+    /// cached or its archive expired
+    pub fn fresh(&self, url: &str, ttl: TemplateTtl, now: SystemTime) -> Option<(String, PathBuf)> {
+        let downloaded = *self.index().downloaded.get(url)?;
+        let downloaded = UNIX_EPOCH + Duration::from_secs(downloaded);
+        // A download in the future (the clock was changed) is not trusted
+        let age = now.duration_since(downloaded).ok()?;
+        if !ttl.is_fresh(age) {
+            return None;
+        }
+        self.lookup(url)
+    }
+
+    /// Stores the archive of a URL, unless an archive with the same hash is
+    /// already cached, and points the URL to it
+    ///
+    /// The archive and the index are written to temp files renamed in place,
+    /// so another run never reads them half written. The archive the URL
+    /// pointed to before is removed when no other URL uses it.
+    ///
+    /// # Arguments
+    /// * `url` - The URL or path of the template
+    /// * `sha256` - The SHA-256 of the archive, see [`crate::download::download_template`]
+    /// * `archive` - The downloaded archive, read from its start
+    ///
+    /// # Returns
+    /// `true` if the archive was written, `false` if it was already cached
+    ///
+    /// # Errors
+    /// If the cache directory, the archive or the index cannot be written
+    pub fn store(&self, url: &str, sha256: &str, archive: &mut File) -> anyhow::Result<bool> {
+        fs::create_dir_all(&self.dir).context(format!(
+            "Cannot create the template cache \"{}\"",
+            self.dir.display()
+        ))?;
+
+        let path = self.archive_path(sha256);
+        let written = !path.is_file();
+        if written {
+            archive
+                .seek(SeekFrom::Start(0))
+                .context("Cannot read the downloaded template")?;
+            self.write_atomically(&path, |file| io::copy(archive, file).map(|_| ()))?;
+        }
+
+        let mut index = self.index();
+        let previous = index.templates.insert(url.to_string(), sha256.to_string());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        index.downloaded.insert(url.to_string(), now);
+        let json = serde_json::to_string_pretty(&index).context("Cannot write the cache index")?;
+        self.write_atomically(&self.dir.join(INDEX_FILE), |file| {
+            file.write_all(json.as_bytes())
+        })?;
+
+        if let Some(previous) = previous.filter(|previous| {
+            previous != sha256 && !index.templates.values().any(|sha| sha == previous)
+        }) {
+            // Only wastes space if it stays
+            let _ = fs::remove_file(self.archive_path(&previous));
+        }
+        Ok(written)
+    }
+
+    /// Writes a file of the cache through a temp file renamed over it
+    fn write_atomically(
+        &self,
+        path: &Path,
+        write: impl FnOnce(&mut File) -> io::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut temp = tempfile::NamedTempFile::new_in(&self.dir)
+            .context("Cannot create a temp file in the template cache")?;
+        write(temp.as_file_mut())
+            .and_then(|_| temp.as_file().sync_all())
+            .context(format!("Cannot write \"{}\"", path.display()))?;
+        temp.persist(path)
+            .context(format!("Cannot write \"{}\"", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(content: &[u8]) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(content).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_store_deduplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TemplateCache::new(dir.path().join(CACHE_DIR));
+        let (v1, v2) = (b"v1 archive", b"v2 archive");
+        let (sha1, sha2) = (sha256_hex(v1), sha256_hex(v2));
+        assert_eq!(cache.lookup("ref-a"), None);
+
+        assert!(cache.store("ref-a", &sha1, &mut archive(v1)).unwrap());
+        // Same archive from another ref
+        assert!(!cache.store("ref-b", &sha1, &mut archive(v1)).unwrap());
+        assert_eq!(fs::read_dir(&cache.dir).unwrap().count(), 2);
+        assert_eq!(
+            cache.lookup("ref-b"),
+            Some((sha1.clone(), cache.archive_path(&sha1)))
+        );
+
+        // The old archive is kept while ref-b uses it
+        cache.store("ref-a", &sha2, &mut archive(v2)).unwrap();
+        assert!(cache.archive_path(&sha1).is_file());
+        cache.store("ref-b", &sha2, &mut archive(v2)).unwrap();
+        assert!(!cache.archive_path(&sha1).exists());
+        assert_eq!(
+            cache.index().templates.values().collect::<Vec<_>>(),
+            [&sha2, &sha2]
+        );
+
+        // A corrupted archive is not used
+        fs::write(cache.archive_path(&sha2), "corrupted").unwrap();
+        assert_eq!(cache.lookup("ref-a"), None);
+    }
+
+    #[test]
+    fn test_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TemplateCache::new(dir.path().join(CACHE_DIR));
+        let sha = sha256_hex(b"v1 archive");
+        cache
+            .store("ref-a", &sha, &mut archive(b"v1 archive"))
+            .unwrap();
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+        let ttl = |ttl: &str| ttl.parse::<TemplateTtl>().unwrap();
+
+        assert!(cache.fresh("ref-a", ttl("1h"), now).is_some());
+        assert_eq!(cache.fresh("ref-a", ttl("1h"), now + 2 * hour), None);
+        assert_eq!(cache.fresh("ref-a", ttl("0"), now), None);
+        assert!(cache
+            .fresh("ref-a", ttl("infinite"), now + 1000 * hour)
+            .is_some());
+        assert_eq!(cache.fresh("ref-b", ttl("infinite"), now), None);
+        assert_eq!(cache.fresh("ref-a", ttl("1h"), now - 2 * hour), None);
+
+        // Entries written before the download times were recorded
+        let mut index = cache.index();
+        index.downloaded.clear();
+        fs::write(
+            dir.path().join(CACHE_DIR).join(INDEX_FILE),
+            serde_json::to_string(&index).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(cache.fresh("ref-a", ttl("infinite"), now), None);
+        assert!(cache.lookup("ref-a").is_some());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let ttl = |ttl: &str| ttl.parse::<TemplateTtl>();
+        assert_eq!(
+            ttl("30m").unwrap(),
+            TemplateTtl::Expires(Duration::from_secs(1800))
+        );
+        assert_eq!(
+            ttl("7d").unwrap(),
+            TemplateTtl::Expires(Duration::from_secs(7 * 86400))
+        );
+        assert_eq!(ttl("0").unwrap(), TemplateTtl::default());
+        assert_eq!(ttl("infinite").unwrap(), TemplateTtl::Infinite);
+        for invalid in ["", "7", "d", "7y", "-1h", "99999999999999999999w"] {
+            assert!(ttl(invalid).is_err(), "{}", invalid);
+        }
+
+        for (text, display) in [("90m", "90m"), ("120m", "2h"), ("2w", "14d"), ("0s", "0")] {
+            assert_eq!(ttl(text).unwrap().to_string(), display);
+        }
+        assert!(!TemplateTtl::default().is_fresh(Duration::ZERO));
+        assert_eq!(
+            serde_json::to_string(&TemplateTtl::Infinite).unwrap(),
+            "\"infinite\""
+        );
+        assert_eq!(
+            serde_json::from_str::<TemplateTtl>("\"12h\"").unwrap(),
+            TemplateTtl::Expires(Duration::from_secs(12 * 3600))
+        );
+        assert!(serde_json::from_str::<TemplateTtl>("\"soon\"").is_err());
+    }
+}
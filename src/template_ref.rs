@@ -0,0 +1,285 @@
+//! Pinning the official template to a git ref and checking how old the ref is
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::http;
+
+/// Archive of a ref of the official template, `{}` is replaced by the ref
+const TEMPLATE_REF_URL: &str = "https://github.com/espressif/esp-idf-template/archive/{}.zip";
+
+/// GitHub API endpoint of a commit of the official template, `{}` is replaced by the ref
+const COMMIT_API_URL: &str = "https://api.github.com/repos/espressif/esp-idf-template/commits/{}";
+
+/// Timeout of the age check, it is only a hint and must not hold up the generation
+pub const AGE_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// URL of the archive of the official template at the ref
+///
+/// # Arguments
+/// * `git_ref` - A branch, tag or commit of the template repository
+pub fn template_ref_url(git_ref: &str) -> String {
+    TEMPLATE_REF_URL.replace("{}", git_ref)
+}
+
+/// Checks that the ref can be used in a URL
+///
+/// # Arguments
+/// * `git_ref` - A branch, tag or commit of the template repository
+///
+/// # Errors
+/// If the ref is empty or has characters not allowed in git refs
+pub fn validate_ref(git_ref: &str) -> anyhow::Result<()> {
+    if git_ref.is_empty() {
+        bail!("the template ref is empty");
+    }
+    if git_ref.starts_with('/') || git_ref.ends_with('/') || git_ref.contains("..") {
+        bail!("\"{}\" is not a valid git ref", git_ref);
+    }
+    if let Some(c) = git_ref
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/')))
+    {
+        bail!("'{}' is not allowed in a template ref", c);
+    }
+    Ok(())
+}
+
+/// Parses an age such as `30d`, `6m` (months of 30 days) or `2y` (years of 365 days)
+///
+/// # Arguments
+/// * `age` - The age, a number followed by `d`, `w`, `m` or `y`
+///
+/// # Errors
+/// If the number or the unit is invalid, or the age is too large
+pub fn parse_age(age: &str) -> anyhow::Result<Duration> {
+    let age = age.trim();
+    let split = age
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("\"{}\" has no unit, use d, w, m or y (e.g. 2y)", age))?;
+    let (number, unit) = age.split_at(split);
+    let number: u64 = number
+        .parse()
+        .context(format!("\"{}\" is not a valid age", age))?;
+    let days = match unit {
+        "d" => 1,
+        "w" => 7,
+        "m" => 30,
+        "y" => 365,
+        _ => bail!("Unknown unit \"{}\", use d, w, m or y (e.g. 2y)", unit),
+    };
+    number
+        .checked_mul(days * DAY)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow!("\"{}\" is too large an age", age))
+}
+
+/// Formats an age in the largest whole unit, e.g. `2 years`
+pub fn format_age(age: Duration) -> String {
+    let days = age.as_secs() / DAY;
+    let (count, unit) = if days >= 365 {
+        (days / 365, "year")
+    } else if days >= 30 {
+        (days / 30, "month")
+    } else {
+        (days, "day")
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Parses an ISO 8601 UTC date as returned by the GitHub API (`2021-08-30T12:00:00Z`)
+///
+/// # Errors
+/// If the date doesn't have that exact format
+pub fn parse_github_date(date: &str) -> anyhow::Result<SystemTime> {
+    let invalid = || anyhow!("Invalid date \"{}\"", date);
+    let (day, time) = date
+        .strip_suffix('Z')
+        .and_then(|date| date.split_once('T'))
+        .ok_or_else(invalid)?;
+    let day: Vec<i64> = day
+        .split('-')
+        .map(|n| n.parse().map_err(|_| invalid()))
+        .collect::<anyhow::Result<_>>()?;
+    let time: Vec<u64> = time
+        .split(':')
+        .map(|n| n.parse().map_err(|_| invalid()))
+        .collect::<anyhow::Result<_>>()?;
+    let (year, month, day, hour, minute, second) = match (day.as_slice(), time.as_slice()) {
+        ([year, month, day], [hour, minute, second]) => {
+            (*year, *month, *day, *hour, *minute, *second)
+        }
+        _ => return Err(invalid()),
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return Err(invalid());
+    }
+
+    let days = days_from_civil(year, month, day);
+    let days = u64::try_from(days).map_err(|_| invalid())?;
+    Ok(UNIX_EPOCH + Duration::from_secs(days * DAY + hour * 3600 + minute * 60 + second))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[derive(Deserialize)]
+struct CommitResponse {
+    sha: String,
+    commit: CommitDetails,
+}
+
+#[derive(Deserialize)]
+struct CommitDetails {
+    committer: CommitSignature,
+}
+
+#[derive(Deserialize)]
+struct CommitSignature {
+    date: String,
+}
+
+/// Fetches the commit date of a ref of the official template from GitHub
+///
+/// # Arguments
+/// * `git_ref` - A branch, tag or commit of the template repository
+///
+/// # Errors
+/// If GitHub cannot be reached or doesn't know the ref
+pub fn commit_date(git_ref: &str) -> anyhow::Result<SystemTime> {
+    parse_github_date(&fetch_commit(git_ref)?.commit.committer.date)
+}
+
+/// Fetches the commit a ref of the official template points to from GitHub
+///
+/// # Arguments
+/// * `git_ref` - A branch, tag or commit of the template repository
+///
+/// # Returns
+/// The full SHA-1 of the commit
+///
+/// # Errors
+/// If GitHub cannot be reached or doesn't know the ref
+pub fn commit_sha(git_ref: &str) -> anyhow::Result<String> {
+    Ok(fetch_commit(git_ref)?.sha)
+}
+
+fn fetch_commit(git_ref: &str) -> anyhow::Result<CommitResponse> {
+    let url = COMMIT_API_URL.replace("{}", git_ref);
+    let response = http::agent_with_timeout(&url, AGE_CHECK_TIMEOUT)?
+        .get(&url)
+        .call()
+        .context("Cannot get the template ref from GitHub")?
+        .into_string()
+        .context("Cannot get the template ref from GitHub")?;
+    serde_json::from_str(&response).context("Invalid commit information")
+}
+
+/// Builds the warning shown when the commit is older than the threshold
+///
+/// # Arguments
+/// * `git_ref` - The ref, for the message
+/// * `date` - The commit date of the ref
+/// * `now` - The current time
+/// * `threshold` - The age above which the ref is considered old
+///
+/// # Returns
+/// The warning, or `None` if the ref is recent enough
+pub fn age_warning(
+    git_ref: &str,
+    date: SystemTime,
+    now: SystemTime,
+    threshold: Duration,
+) -> Option<String> {
+    let age = now.duration_since(date).ok()?;
+    if age < threshold {
+        return None;
+    }
+    Some(format!(
+        "The template ref \"{}\" is {} old, consider a newer one",
+        git_ref,
+        format_age(age)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_github_date() {
+        assert_eq!(
+            parse_github_date("1970-01-01T00:00:00Z").unwrap(),
+            UNIX_EPOCH
+        );
+        assert_eq!(
+            parse_github_date("2021-08-30T12:34:56Z").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_630_326_896)
+        );
+        assert_eq!(
+            parse_github_date("2024-02-29T00:00:00Z").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_709_164_800)
+        );
+        assert!(parse_github_date("2021-08-30").is_err());
+        assert!(parse_github_date("2021-13-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d").unwrap(), Duration::from_secs(30 * DAY));
+        assert_eq!(parse_age("2y").unwrap(), Duration::from_secs(730 * DAY));
+        assert!(parse_age("2").is_err());
+        assert!(parse_age("y").is_err());
+        assert!(parse_age("2x").is_err());
+        assert!(parse_age("99999999999999999y")
+            .unwrap_err()
+            .to_string()
+            .contains("too large"));
+    }
+
+    #[test]
+    fn test_age_warning() {
+        let date = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let threshold = parse_age("1y").unwrap();
+        assert_eq!(
+            age_warning(
+                "v1.0",
+                date,
+                date + Duration::from_secs(800 * DAY),
+                threshold
+            ),
+            Some("The template ref \"v1.0\" is 2 years old, consider a newer one".into())
+        );
+        assert_eq!(
+            age_warning(
+                "v1.0",
+                date,
+                date + Duration::from_secs(10 * DAY),
+                threshold
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validate_ref() {
+        assert!(validate_ref("master").is_ok());
+        assert!(validate_ref("release/v4.4").is_ok());
+        assert!(validate_ref("").is_err());
+        assert!(validate_ref("../evil").is_err());
+        assert!(validate_ref("a b").is_err());
+        assert!(validate_ref("a?b").is_err());
+    }
+}
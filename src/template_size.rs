@@ -0,0 +1,249 @@
+//! The size of a template by top-level directory, to spot the docs and
+//! images a template would copy into every project: reported after the
+//! extraction when the template is large, and by `--analyze-template`
+//! without generating anything
+
+use anyhow::Context;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::archive::{self, EntrySize};
+use crate::download::format_size;
+
+/// Size of the extracted template above which its largest directories are
+/// reported
+pub const LARGE_TEMPLATE_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Number of directories reported after the extraction
+const LARGEST_REPORTED: usize = 3;
+
+/// Name the files at the root of the template are grouped under
+pub const TOP_LEVEL_FILES: &str = "(top-level files)";
+
+/// A top-level directory of a template and what it holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DirectorySize {
+    /// The name of the directory, or [`TOP_LEVEL_FILES`]
+    pub name: String,
+    /// The size of its files, in bytes
+    pub size: u64,
+    pub files: usize,
+}
+
+/// The sizes of a template
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SizeReport {
+    /// The size of every file, in bytes
+    pub total: u64,
+    pub files: usize,
+    /// Largest first, by name when the sizes are the same
+    pub directories: Vec<DirectorySize>,
+}
+
+impl SizeReport {
+    /// Adds up the files by top-level directory
+    ///
+    /// # Arguments
+    /// * `entries` - The files of the template, from [`archive::zip_entry_sizes`],
+    ///   [`archive::tar_gz_entry_sizes`] or [`archive::directory_entry_sizes`]
+    pub fn new(entries: &[EntrySize]) -> Self {
+        let mut directories: Vec<DirectorySize> = Vec::new();
+        for entry in entries {
+            let mut components = entry.path.components();
+            let name = match (components.next(), components.next()) {
+                (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+                _ => TOP_LEVEL_FILES.to_string(),
+            };
+            match directories
+                .iter_mut()
+                .find(|directory| directory.name == name)
+            {
+                Some(directory) => {
+                    directory.size += entry.size;
+                    directory.files += 1;
+                }
+                None => directories.push(DirectorySize {
+                    name,
+                    size: entry.size,
+                    files: 1,
+                }),
+            }
+        }
+        directories.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        SizeReport {
+            total: entries.iter().map(|entry| entry.size).sum(),
+            files: entries.len(),
+            directories,
+        }
+    }
+
+    /// The sizes of an extracted template or a template directory
+    ///
+    /// # Errors
+    /// If a directory or a file cannot be read
+    pub fn of_directory(root: &Path) -> anyhow::Result<Self> {
+        Ok(SizeReport::new(&archive::directory_entry_sizes(root)?))
+    }
+
+    /// The sizes of a template archive, a zip or a tar.gz told apart by
+    /// their first bytes
+    ///
+    /// # Errors
+    /// If the archive cannot be read or is neither a zip nor a tar.gz
+    pub fn of_archive(file: &mut File) -> anyhow::Result<Self> {
+        let mut magic = [0; 2];
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.read_exact(&mut magic))
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .context("Cannot read the template")?;
+        let entries = if magic == [0x1f, 0x8b] {
+            archive::tar_gz_entry_sizes(file)?
+        } else {
+            let mut zip =
+                zip::ZipArchive::new(file).context("The template is neither a zip nor a tar.gz")?;
+            archive::zip_entry_sizes(&mut zip)?
+        };
+        Ok(SizeReport::new(&entries))
+    }
+
+    /// Whether the template is larger than [`LARGE_TEMPLATE_SIZE`]
+    pub fn is_large(&self) -> bool {
+        self.total > LARGE_TEMPLATE_SIZE
+    }
+
+    /// Every directory, largest first, as printed by `--analyze-template`
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} in {} files",
+            format_size(self.total),
+            self.files
+        )];
+        lines.extend(self.directories.iter().map(directory_line));
+        lines
+    }
+
+    /// The three largest directories and how to leave them out, empty if
+    /// the template is not large
+    pub fn trimming_lines(&self) -> Vec<String> {
+        if !self.is_large() {
+            return Vec::new();
+        }
+        let mut lines = vec![format!(
+            "The template wrote {}, most of it in:",
+            format_size(self.total)
+        )];
+        lines.extend(
+            self.directories
+                .iter()
+                .take(LARGEST_REPORTED)
+                .map(directory_line),
+        );
+        lines.push(
+            "Remove what the projects don't need from the template (see --analyze-template), \
+             or pass --scaffold-only-config to extract only its build files"
+                .to_string(),
+        );
+        lines
+    }
+}
+
+fn directory_line(directory: &DirectorySize) -> String {
+    let name = if directory.name == TOP_LEVEL_FILES {
+        directory.name.clone()
+    } else {
+        format!("{}/", directory.name)
+    };
+    format!(
+        "  {}  {} ({} files)",
+        name,
+        format_size(directory.size),
+        directory.files
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, size: u64) -> EntrySize {
+        EntrySize {
+            path: PathBuf::from(path),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let report = SizeReport::new(&[
+            entry("CMakeLists.txt", 200),
+            entry("main/main.c", 300),
+            entry("docs/manual.pdf", 4 * 1024 * 1024),
+            entry("docs/images/board.png", 2 * 1024 * 1024),
+            entry("assets/logo.png", 100 * 1024),
+            entry("README.md", 500),
+        ]);
+
+        assert_eq!(report.files, 6);
+        assert!(report.is_large());
+        let names: Vec<&str> = report
+            .directories
+            .iter()
+            .map(|directory| directory.name.as_str())
+            .collect();
+        assert_eq!(names, ["docs", "assets", TOP_LEVEL_FILES, "main"]);
+        assert_eq!(report.directories[2].size, 700);
+
+        let lines = report.trimming_lines();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], "  docs/  6MB (2 files)");
+        assert!(!lines.iter().any(|line| line.contains("main/")));
+
+        let small = SizeReport::new(&[entry("main/main.c", 300)]);
+        assert!(small.trimming_lines().is_empty());
+        assert_eq!(small.lines()[1], "  main/  0.3KB (1 files)");
+    }
+
+    #[test]
+    fn test_of_archive_and_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("template");
+        fs::create_dir_all(project.join("main")).unwrap();
+        fs::create_dir_all(project.join("docs")).unwrap();
+        fs::write(project.join("CMakeLists.txt"), "project(blink)\n").unwrap();
+        fs::write(
+            project.join("main").join("main.c"),
+            "void app_main(void) {}\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("docs").join("guide.md"),
+            "# Guide\n".repeat(100),
+        )
+        .unwrap();
+        let expected = SizeReport::of_directory(&project).unwrap();
+        assert_eq!(expected.files, 3);
+        assert_eq!(expected.directories[0].name, "docs");
+
+        // The same files in every kind of archive, under a root directory
+        for format in ["template.zip", "template.tar.gz"] {
+            let out = dir.path().join(format);
+            archive::archive_directory(&project, "template-main", &out, true).unwrap();
+            let mut file = File::open(&out).unwrap();
+            assert_eq!(
+                SizeReport::of_archive(&mut file).unwrap(),
+                expected,
+                "{}",
+                format
+            );
+        }
+
+        let mut invalid = tempfile::tempfile().unwrap();
+        invalid.write_all(b"not an archive").unwrap();
+        assert!(SizeReport::of_archive(&mut invalid).is_err());
+    }
+}
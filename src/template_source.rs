@@ -0,0 +1,227 @@
+//! Where the template of a generation comes from, resolved without
+//! downloading it: for tools that show a template before a project is
+//! generated from it, see also [`crate::template_archive`]
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::doctor;
+use crate::download;
+use crate::http;
+use crate::template_cache::TemplateCache;
+use crate::template_ref;
+use crate::templates;
+
+/// Time given to the template server to answer
+pub const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The template as given: a URL or path, or a ref of the official template
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TemplateSource<'a> {
+    /// URL or path of the template, see [`crate::options::ScaffoldOptions::template_url`]
+    pub url: Option<&'a str>,
+    /// Branch, tag or commit of the official template, used when `url` is not set
+    pub git_ref: Option<&'a str>,
+}
+
+/// What a template is stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Downloaded over HTTP(S)
+    Remote,
+    /// A zip or tar.gz on disk
+    Archive,
+    /// A template directory
+    Directory,
+}
+
+/// A template once resolved, see [`TemplateSource::resolve`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolvedTemplate {
+    /// The URL or path, the ref expanded
+    pub url: String,
+    pub kind: SourceKind,
+    /// The URL the archive is downloaded from after the redirects, `None`
+    /// for a local template or offline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    /// The size of the archive in bytes, as announced by the server; `None`
+    /// if it didn't, offline, or for a directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// The commit the ref points to, from the GitHub API; `None` without a
+    /// ref, offline, or if the API cannot be reached (e.g. rate limited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The cached archive of the URL, see [`crate::template_archive::TemplateArchive::cached`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached: Option<PathBuf>,
+}
+
+impl<'a> TemplateSource<'a> {
+    /// A template given by URL or path
+    pub fn from_url(url: &'a str) -> Self {
+        TemplateSource {
+            url: Some(url),
+            git_ref: None,
+        }
+    }
+
+    /// The URL or path of the template: `url`, the archive of the ref of the
+    /// official template, or the official template
+    pub fn url(&self) -> Cow<'a, str> {
+        match (self.url, self.git_ref) {
+            (Some(url), _) => Cow::Borrowed(url),
+            (None, Some(git_ref)) => Cow::Owned(template_ref::template_ref_url(git_ref)),
+            (None, None) => Cow::Borrowed(templates::TEMPLATE_FILE),
+        }
+    }
+
+    /// Finds where the template is without downloading it: the metadata of
+    /// a local file or directory, a HEAD request for a URL (see
+    /// [`doctor::probe_template`]), and the commit
+    /// of the ref from the GitHub API
+    ///
+    /// # Arguments
+    /// * `cache` - The template cache, looked up for the URL
+    /// * `offline` - Sends no request, only the cache and the local
+    ///   templates are looked at
+    ///
+    /// # Errors
+    /// If a local template doesn't exist, or the server cannot be reached or
+    /// answers with an error status
+    pub fn resolve(
+        &self,
+        cache: Option<&TemplateCache>,
+        offline: bool,
+    ) -> anyhow::Result<ResolvedTemplate> {
+        let url = self.url().into_owned();
+        let cached = cache
+            .and_then(|cache| cache.lookup(&url))
+            .map(|(_, path)| path);
+        let mut resolved = ResolvedTemplate {
+            url,
+            kind: SourceKind::Remote,
+            final_url: None,
+            size: None,
+            commit: None,
+            cached,
+        };
+        if let Some(path) = download::local_path(&resolved.url) {
+            let metadata = fs::metadata(Path::new(path))
+                .context(format!("Cannot read the template \"{}\"", path))?;
+            if metadata.is_dir() {
+                resolved.kind = SourceKind::Directory;
+            } else {
+                resolved.kind = SourceKind::Archive;
+                resolved.size = Some(metadata.len());
+            }
+            return Ok(resolved);
+        }
+        if offline {
+            return Ok(resolved);
+        }
+
+        let agent = http::agent_with_timeout(&resolved.url, RESOLVE_TIMEOUT)?;
+        let response = match doctor::probe_template(&agent, &resolved.url).map_err(|error| *error) {
+            Ok(response) => response,
+            Err(ureq::Error::Status(code, _)) => {
+                bail!("{} answered with HTTP {}", resolved.url, code)
+            }
+            Err(error) => {
+                return Err(anyhow::Error::new(error)
+                    .context(format!("Cannot reach the template {}", resolved.url)))
+            }
+        };
+        resolved.final_url = Some(response.get_url().to_string());
+        // The total after the slash for the first byte of a server that
+        // refuses HEAD
+        resolved.size = match response.header("Content-Range") {
+            Some(range) => range.rsplit('/').next(),
+            None => response.header("Content-Length"),
+        }
+        .and_then(|length| length.parse().ok());
+        if let (None, Some(git_ref)) = (self.url, self.git_ref) {
+            resolved.commit = template_ref::commit_sha(git_ref).ok();
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+
+    #[test]
+    fn test_url() {
+        assert_eq!(TemplateSource::default().url(), templates::TEMPLATE_FILE);
+        let source = TemplateSource {
+            url: None,
+            git_ref: Some("v1.0"),
+        };
+        assert_eq!(source.url(), template_ref::template_ref_url("v1.0"));
+        let source = TemplateSource {
+            url: Some("https://example.com/t.zip"),
+            git_ref: Some("v1.0"),
+        };
+        assert_eq!(source.url(), "https://example.com/t.zip");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let body = test_fixtures::template_zip();
+        let url = test_fixtures::serve(body.clone(), None);
+        let resolved = TemplateSource::from_url(&url).resolve(None, false).unwrap();
+        assert_eq!(resolved.kind, SourceKind::Remote);
+        assert_eq!(resolved.final_url.as_deref(), Some(url.as_str()));
+        assert_eq!(resolved.size, Some(body.len() as u64));
+        assert_eq!(resolved.commit, None);
+
+        // Nothing is sent offline, the cache is still looked up
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TemplateCache::new(dir.path().join("cache"));
+        let mut archive = tempfile::tempfile().unwrap();
+        std::io::Write::write_all(&mut archive, &body).unwrap();
+        cache
+            .store(&url, &crate::self_update::sha256_hex(&body), &mut archive)
+            .unwrap();
+        let offline = TemplateSource::from_url("http://127.0.0.1:1/template.zip")
+            .resolve(Some(&cache), true)
+            .unwrap();
+        assert_eq!((offline.final_url, offline.cached), (None, None));
+        let cached = TemplateSource::from_url(&url)
+            .resolve(Some(&cache), true)
+            .unwrap();
+        assert!(cached.cached.unwrap().is_file());
+
+        let missing = test_fixtures::serve_routes(vec![]);
+        let error = TemplateSource::from_url(&format!("{}/missing.zip", missing))
+            .resolve(None, false)
+            .unwrap_err();
+        assert!(error.to_string().contains("HTTP 404"), "{}", error);
+
+        let path = dir.path().join("template.zip");
+        fs::write(&path, &body).unwrap();
+        let path = path.to_string_lossy();
+        let local = TemplateSource::from_url(&path)
+            .resolve(None, false)
+            .unwrap();
+        assert_eq!(
+            (local.kind, local.size),
+            (SourceKind::Archive, Some(body.len() as u64))
+        );
+        let local = TemplateSource::from_url(&dir.path().to_string_lossy())
+            .resolve(None, false)
+            .unwrap();
+        assert_eq!(local.kind, SourceKind::Directory);
+        assert!(TemplateSource::from_url("/missing/template.zip")
+            .resolve(None, true)
+            .is_err());
+    }
+}
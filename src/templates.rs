@@ -1,8 +1,15 @@
+use crate::ci::CiProvider;
+use crate::factory_test::FACTORY_TEST_APP;
+use crate::idf_version::IdfVersion;
+use crate::options::{ClangFormatStyle, ProgrammingLanguage};
 
 /// URL to download the template from
 pub const TEMPLATE_FILE: &str =
     "https://github.com/espressif/esp-idf-template/archive/refs/heads/master.zip";
 
+/// Branch of the official template in [`TEMPLATE_FILE`]
+pub const DEFAULT_TEMPLATE_REF: &str = "master";
+
 /// IDF C template
 pub const C_TEMPLATE: &str = r#"#include <stdio.h>
 #include "freertos/FreeRTOS.h"
@@ -25,4 +32,776 @@ extern "C" void app_main(void)
 {
     // TODO Insert code
 }
-"#;
\ No newline at end of file
+"#;
+
+/// Path of the Kconfig example, relative to the project directory
+pub const KCONFIG_FILE: &str = "main/Kconfig.projbuild";
+
+/// Sample `Kconfig.projbuild`, its options show up in `idf.py menuconfig`
+pub const KCONFIG_TEMPLATE: &str = r#"# Options of this project, shown in `idf.py menuconfig` under "Example Configuration".
+# Every option is available in the code as a CONFIG_<name> macro from "sdkconfig.h".
+menu "Example Configuration"
+
+    config EXAMPLE_WIFI_SSID
+        string "WiFi SSID"
+        default "myssid"
+        help
+            SSID (network name) the example connects to.
+            Read in the code as CONFIG_EXAMPLE_WIFI_SSID.
+
+endmenu
+"#;
+
+/// IDF C template that reads the option of [`KCONFIG_TEMPLATE`]
+pub const C_KCONFIG_TEMPLATE: &str = r#"#include <stdio.h>
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+#include "sdkconfig.h"
+
+
+void app_main(void)
+{
+    // Set in main/Kconfig.projbuild, change it with `idf.py menuconfig`
+    printf("WiFi SSID: %s\n", CONFIG_EXAMPLE_WIFI_SSID);
+    // TODO Insert code
+}
+"#;
+
+/// IDF C++ template that reads the option of [`KCONFIG_TEMPLATE`]
+pub const CPP_KCONFIG_TEMPLATE: &str = r#"#include <stdio.h>
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+#include "sdkconfig.h"
+
+
+extern "C" void app_main(void)
+{
+    // Set in main/Kconfig.projbuild, change it with `idf.py menuconfig`
+    printf("WiFi SSID: %s\n", CONFIG_EXAMPLE_WIFI_SSID);
+    // TODO Insert code
+}
+"#;
+
+/// Header of the application class, in `main/`
+pub const APP_CLASS_HEADER_FILE: &str = "app.hpp";
+
+/// Source of the application class, in `main/`; the starters are initialized
+/// in its `App::init`
+pub const APP_CLASS_SOURCE_FILE: &str = "app.cpp";
+
+/// Main file of the application class layout, it only runs the application
+pub const APP_CLASS_MAIN_TEMPLATE: &str = r#"#include "app.hpp"
+
+
+extern "C" void app_main(void)
+{
+    // Static, so the members of the application are not on the stack of the
+    // main task and live as long as the firmware runs
+    static App app;
+    app.init();
+    app.run();
+}
+"#;
+
+/// Declaration of the application class, see [`APP_CLASS_SOURCE_TEMPLATE`]
+pub const APP_CLASS_HEADER_TEMPLATE: &str = r#"#pragma once
+
+
+/// The application, created and run once by app_main
+class App {
+public:
+    App() = default;
+    App(const App &) = delete;
+    App &operator=(const App &) = delete;
+
+    /// Initializes the drivers and services of the application
+    void init();
+
+    /// Runs the application once init() is done, app_main returns when it does
+    void run();
+};
+"#;
+
+/// Definition of the application class
+pub const APP_CLASS_SOURCE_TEMPLATE: &str = r#"#include "app.hpp"
+
+#include <stdio.h>
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+
+
+void App::init()
+{
+    // TODO Insert code
+}
+
+void App::run()
+{
+    // Main loop of the application
+}
+"#;
+
+/// Application class source that reads the option of [`KCONFIG_TEMPLATE`]
+pub const APP_CLASS_KCONFIG_SOURCE_TEMPLATE: &str = r#"#include "app.hpp"
+
+#include <stdio.h>
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+#include "sdkconfig.h"
+
+
+void App::init()
+{
+    // Set in main/Kconfig.projbuild, change it with `idf.py menuconfig`
+    printf("WiFi SSID: %s\n", CONFIG_EXAMPLE_WIFI_SSID);
+    // TODO Insert code
+}
+
+void App::run()
+{
+    // Main loop of the application
+}
+"#;
+
+/// Picks the built-in source of the application class
+///
+/// # Arguments
+/// * `kconfig_example` - Whether it reads the option of [`KCONFIG_TEMPLATE`]
+pub fn app_class_source(kconfig_example: bool) -> &'static str {
+    if kconfig_example {
+        APP_CLASS_KCONFIG_SOURCE_TEMPLATE
+    } else {
+        APP_CLASS_SOURCE_TEMPLATE
+    }
+}
+
+/// Picks the built-in main file for the language
+///
+/// # Arguments
+/// * `language` - The programming language of the project
+/// * `kconfig_example` - Whether the main file reads the option of [`KCONFIG_TEMPLATE`]
+/// * `app_class` - Whether the main file only runs the application class,
+///   see [`APP_CLASS_MAIN_TEMPLATE`]
+pub fn main_template(
+    language: ProgrammingLanguage,
+    kconfig_example: bool,
+    app_class: bool,
+) -> &'static str {
+    if app_class && language != ProgrammingLanguage::C {
+        return APP_CLASS_MAIN_TEMPLATE;
+    }
+    match (language, kconfig_example) {
+        (ProgrammingLanguage::C, false) => C_TEMPLATE,
+        (ProgrammingLanguage::C, true) => C_KCONFIG_TEMPLATE,
+        (_, false) => CPP_TEMPLATE,
+        (_, true) => CPP_KCONFIG_TEMPLATE,
+    }
+}
+
+/// Path of the clang-format configuration, relative to the project
+pub const CLANG_FORMAT_FILE: &str = ".clang-format";
+
+/// clang-format configuration, the `{{indent_width}}` and `{{column_limit}}`
+/// placeholders are replaced by the selected style
+pub const CLANG_FORMAT_TEMPLATE: &str = r#"# Generated by esp-create-project, see https://clang.llvm.org/docs/ClangFormatStyleOptions.html
+BasedOnStyle: LLVM
+IndentWidth: {{indent_width}}
+ColumnLimit: {{column_limit}}
+UseTab: Never
+BreakBeforeBraces: Linux
+IndentCaseLabels: true
+AllowShortFunctionsOnASingleLine: Empty
+AllowShortIfStatementsOnASingleLine: Never
+PointerAlignment: Right
+SortIncludes: false
+"#;
+
+/// Renders [`CLANG_FORMAT_TEMPLATE`] with the selected style
+///
+/// # Arguments
+/// * `style` - The indent width and column limit
+pub fn clang_format(style: ClangFormatStyle) -> String {
+    CLANG_FORMAT_TEMPLATE
+        .replace("{{indent_width}}", &style.indent_width.to_string())
+        .replace("{{column_limit}}", &style.column_limit.to_string())
+}
+
+/// Path of the EditorConfig file, relative to the project
+pub const EDITORCONFIG_FILE: &str = ".editorconfig";
+
+/// EditorConfig settings, `{{indent_width}}` is replaced by the indent width
+/// of the clang-format style so both agree
+pub const EDITORCONFIG_TEMPLATE: &str = r#"# Generated by esp-create-project, see https://editorconfig.org
+root = true
+
+[*]
+charset = utf-8
+end_of_line = lf
+insert_final_newline = true
+trim_trailing_whitespace = true
+
+[*.{c,h,cpp,hpp}]
+indent_style = space
+indent_size = {{indent_width}}
+
+[{CMakeLists.txt,*.cmake,Kconfig*}]
+indent_style = space
+indent_size = 4
+
+[*.{yml,yaml}]
+indent_style = space
+indent_size = 2
+
+[Makefile]
+indent_style = tab
+
+[*.md]
+trim_trailing_whitespace = false
+"#;
+
+/// Path of the gitignore, relative to the project
+pub const GITIGNORE_FILE: &str = ".gitignore";
+
+/// Build output and files regenerated by ESP-IDF
+pub const GITIGNORE_TEMPLATE: &str = r#"# Generated by esp-create-project
+# Build output
+build/
+build-*/
+
+# Downloaded by the component manager, dependencies.lock pins them
+managed_components/
+
+# Previous configuration, kept by menuconfig
+sdkconfig.old
+"#;
+
+/// Path of the CMake presets, relative to the project
+pub const CMAKE_PRESETS_FILE: &str = "CMakePresets.json";
+
+/// CMake presets configuring the project with the ESP-IDF toolchain of its
+/// chip, for IDEs that build through CMake; `{{name}}` and `{{target}}` are
+/// replaced
+pub const CMAKE_PRESETS_TEMPLATE: &str = r#"{
+  "version": 3,
+  "configurePresets": [
+    {
+      "name": "default",
+      "displayName": "{{name}} ({{target}})",
+      "generator": "Ninja",
+      "binaryDir": "${sourceDir}/build",
+      "toolchainFile": "$env{IDF_PATH}/tools/cmake/toolchain-{{target}}.cmake",
+      "cacheVariables": {
+        "IDF_TARGET": "{{target}}"
+      }
+    }
+  ],
+  "buildPresets": [
+    {
+      "name": "default",
+      "configurePreset": "default"
+    }
+  ]
+}
+"#;
+
+/// Renders [`CMAKE_PRESETS_TEMPLATE`]
+///
+/// # Arguments
+/// * `name` - The name of the project
+/// * `target` - The chip the project is built for
+pub fn cmake_presets(name: &str, target: &str) -> String {
+    CMAKE_PRESETS_TEMPLATE
+        .replace("{{name}}", name)
+        .replace("{{target}}", target)
+}
+
+/// Path of the git attributes, relative to the project
+pub const GITATTRIBUTES_FILE: &str = ".gitattributes";
+
+/// Line endings normalized to LF, binary files never diffed or converted
+pub const GITATTRIBUTES_TEMPLATE: &str = r#"# Generated by esp-create-project
+* text=auto eol=lf
+
+*.bat text eol=crlf
+*.bin binary
+*.png binary
+*.jpg binary
+*.der binary
+"#;
+
+/// Path of the pre-commit configuration, relative to the project
+pub const PRE_COMMIT_CONFIG_FILE: &str = ".pre-commit-config.yaml";
+
+/// pre-commit hooks: whitespace fixes and clang-format on the C and C++ sources
+pub const PRE_COMMIT_CONFIG_TEMPLATE: &str = r#"# Generated by esp-create-project, run `pre-commit install` to enable the
+# hooks, see https://pre-commit.com
+repos:
+  - repo: https://github.com/pre-commit/pre-commit-hooks
+    rev: v4.6.0
+    hooks:
+      - id: trailing-whitespace
+      - id: end-of-file-fixer
+      - id: check-yaml
+      - id: check-added-large-files
+  - repo: https://github.com/pre-commit/mirrors-clang-format
+    rev: v18.1.8
+    hooks:
+      - id: clang-format
+        types_or: [c, c++]
+"#;
+
+/// Path of the GitHub Actions workflow, relative to the project
+pub const GITHUB_CI_FILE: &str = ".github/workflows/build.yml";
+
+/// GitHub Actions workflow building the project in the ESP-IDF image, the
+/// `{{target}}`, `{{apps}}` and `{{idf_tag}}` placeholders are replaced by
+/// the chip, the directories to build (`.` for a single project) and the tag
+/// of the image
+pub const GITHUB_CI_TEMPLATE: &str = r#"# Generated by esp-create-project, builds the project on each push and pull
+# request in the ESP-IDF image; pin the image tag to build with a fixed version
+name: Build
+
+on: [push, pull_request]
+
+jobs:
+  build:
+    runs-on: ubuntu-latest
+    container: espressif/idf:{{idf_tag}}
+    env:
+      IDF_TARGET: {{target}}
+      APPS: "{{apps}}"
+    steps:
+      - uses: actions/checkout@v4
+        with:
+          submodules: recursive
+      - name: Build
+        shell: bash
+        run: |
+          . "$IDF_PATH/export.sh"
+          for app in $APPS; do
+            idf.py -C "$app" set-target "$IDF_TARGET" build
+          done
+"#;
+
+/// Path of the GitLab CI configuration, relative to the project
+pub const GITLAB_CI_FILE: &str = ".gitlab-ci.yml";
+
+/// GitLab CI pipeline building the project in the ESP-IDF image, same
+/// placeholders as [`GITHUB_CI_TEMPLATE`]
+pub const GITLAB_CI_TEMPLATE: &str = r#"# Generated by esp-create-project, builds the project on each push in the
+# ESP-IDF image; pin the image tag to build with a fixed version
+build:
+  image: espressif/idf:{{idf_tag}}
+  variables:
+    GIT_SUBMODULE_STRATEGY: recursive
+    IDF_TARGET: {{target}}
+    APPS: "{{apps}}"
+  before_script:
+    - . "$IDF_PATH/export.sh"
+  script:
+    - for app in $APPS; do idf.py -C "$app" set-target "$IDF_TARGET" build || exit 1; done
+"#;
+
+/// Directory of the host test, relative to the project
+pub const HOST_TEST_DIR: &str = "host_test";
+
+/// CMakeLists.txt of the host test, a plain CMake project built with the
+/// compiler of the host instead of the IDF toolchain
+const HOST_TEST_CMAKE_TEMPLATE: &str = r#"# Host test: builds hardware-independent code with the compiler of your
+# machine, under AddressSanitizer. See README.md in this directory.
+cmake_minimum_required(VERSION 3.16)
+project(host_test {{cmake_language}})
+{{language_standard}}
+option(HOST_TEST_ASAN "Build the host test with AddressSanitizer" ON)
+
+add_executable(host_test {{source}})
+# Headers of the main component, for the code under test
+target_include_directories(host_test PRIVATE ../main)
+
+if(HOST_TEST_ASAN)
+    target_compile_options(host_test PRIVATE -fsanitize=address -fno-omit-frame-pointer -g)
+    target_link_options(host_test PRIVATE -fsanitize=address)
+endif()
+
+enable_testing()
+add_test(NAME host_test COMMAND host_test)
+"#;
+
+/// Sample host test, checks a hardware-independent function with plain asserts
+const HOST_TEST_SOURCE_TEMPLATE: &str = r#"// Host test: no ESP-IDF headers here, only code that runs on any machine.
+// Move the logic to test into its own files in main/ and include them here.
+#include <assert.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+
+// Sample logic under test: copies at most `size - 1` characters and always
+// terminates `destination`. Writing `size` characters instead is caught by
+// AddressSanitizer as a stack-buffer-overflow.
+static size_t copy_string(char *destination, size_t size, const char *source)
+{
+    size_t length = strlen(source);
+    if (length >= size) {
+        length = size - 1;
+    }
+    memcpy(destination, source, length);
+    destination[length] = '\0';
+    return length;
+}
+
+int main(void)
+{
+    char buffer[8];
+    // The calls stay out of assert(), which NDEBUG compiles away
+    size_t copied = copy_string(buffer, sizeof(buffer), "esp");
+    assert(copied == 3);
+    assert(strcmp(buffer, "esp") == 0);
+    copied = copy_string(buffer, sizeof(buffer), "truncated string");
+    assert(copied == 7);
+    assert(strcmp(buffer, "truncat") == 0);
+    (void)copied;
+
+    printf("All host tests passed\n");
+    return 0;
+}
+"#;
+
+/// Notes on running the host test
+pub const HOST_TEST_README: &str = r#"# Host test
+
+Tests for hardware-independent code (parsers, protocol logic, data
+structures), built with the compiler of your machine and AddressSanitizer
+(`-fsanitize=address`), which reports out-of-bounds accesses, use after free
+and leaks as soon as they happen.
+
+```
+cmake -S host_test -B build-host
+cmake --build build-host
+ctest --test-dir build-host --output-on-failure
+```
+
+Run the commands from the project root, without sourcing the ESP-IDF export
+script so the host compiler is used. AddressSanitizer needs GCC or Clang
+(Linux, macOS, or MinGW/WSL on Windows); pass `-DHOST_TEST_ASAN=OFF` to build
+without it.
+"#;
+
+/// Renders the host test files for the language of the project
+///
+/// # Arguments
+/// * `language` - The programming language of the project
+///
+/// # Returns
+/// The file names, relative to [`HOST_TEST_DIR`], and their contents
+pub fn host_test_files(language: ProgrammingLanguage) -> Vec<(String, String)> {
+    let (cmake_language, standard, source) = match language {
+        ProgrammingLanguage::Cpp11 => ("CXX", "set(CMAKE_CXX_STANDARD 11)\n", "test_main.cpp"),
+        ProgrammingLanguage::Cpp14 => ("CXX", "set(CMAKE_CXX_STANDARD 14)\n", "test_main.cpp"),
+        ProgrammingLanguage::Cpp17 => ("CXX", "set(CMAKE_CXX_STANDARD 17)\n", "test_main.cpp"),
+        ProgrammingLanguage::C | ProgrammingLanguage::Unknown => ("C", "", "test_main.c"),
+    };
+    let cmake = HOST_TEST_CMAKE_TEMPLATE
+        .replace("{{cmake_language}}", cmake_language)
+        .replace("{{language_standard}}", standard)
+        .replace("{{source}}", source);
+    vec![
+        ("CMakeLists.txt".into(), cmake),
+        (source.into(), HOST_TEST_SOURCE_TEMPLATE.into()),
+        ("README.md".into(), HOST_TEST_README.into()),
+    ]
+}
+
+/// Directory of the bootloader components, relative to the project; the
+/// bootloader build of ESP-IDF picks it up without any setting
+pub const BOOTLOADER_COMPONENTS_DIR: &str = "bootloader_components";
+
+/// Name of the component with the bootloader hooks
+pub const BOOTLOADER_HOOKS_COMPONENT: &str = "bootloader_hooks";
+
+/// CMakeLists.txt of the bootloader hooks component
+const BOOTLOADER_HOOKS_CMAKE: &str = r#"# Built into the second-stage bootloader, not into the app
+idf_component_register(SRCS "hooks.c")
+
+# The hooks replace weak functions of the bootloader, the linker would drop
+# them as nothing calls into this library: make it keep the whole file
+target_link_libraries(${COMPONENT_LIB} INTERFACE "-u bootloader_hooks_include")
+"#;
+
+/// Source of the bootloader hooks component, empty hooks that only log
+const BOOTLOADER_HOOKS_SOURCE: &str = r#"// Second-stage bootloader hooks. This runs before the app, with most of
+// ESP-IDF unavailable: see README.md in bootloader_components/.
+#include "esp_log.h"
+
+static const char *TAG = "boot_hooks";
+
+// Referenced by the CMakeLists.txt so the linker keeps this file
+void bootloader_hooks_include(void)
+{
+}
+
+// Called before the bootloader initializes the hardware: BSS, the flash and
+// the clocks are not set up yet, keep this to register accesses
+void bootloader_before_init(void)
+{
+    ESP_LOGI(TAG, "Before the bootloader initialization");
+}
+
+// Called once the bootloader is initialized, before it loads the app
+void bootloader_after_init(void)
+{
+    ESP_LOGI(TAG, "After the bootloader initialization");
+}
+"#;
+
+/// Notes on the bootloader components
+pub const BOOTLOADER_COMPONENTS_README: &str = r#"# Bootloader components
+
+Advanced: components built into the second-stage bootloader instead of the
+app. ESP-IDF adds this directory to the bootloader build on its own.
+
+`bootloader_hooks/` implements the hooks the bootloader calls before and
+after its initialization. The bootloader runs from a small region of IRAM
+before the app, so only a few ESP-IDF components are available (`log`,
+`esp_rom`, `hal`, `soc`, `bootloader_support`...) and there is no heap, no
+FreeRTOS and no C library beyond what the ROM provides.
+
+A crash here stops the device before the app starts and is only recovered by
+flashing over serial: keep the hooks short, and rebuild the bootloader with
+`idf.py bootloader` then flash it with `idf.py bootloader-flash` (or
+`idf.py flash`). To replace the whole bootloader instead, copy the
+`bootloader` component of ESP-IDF here, see the `custom_bootloader` examples
+of ESP-IDF.
+"#;
+
+/// The bootloader hooks component
+///
+/// # Returns
+/// The file names, relative to [`BOOTLOADER_COMPONENTS_DIR`], and their contents
+pub fn bootloader_hooks_files() -> Vec<(String, String)> {
+    vec![
+        ("README.md".into(), BOOTLOADER_COMPONENTS_README.into()),
+        (
+            format!("{}/CMakeLists.txt", BOOTLOADER_HOOKS_COMPONENT),
+            BOOTLOADER_HOOKS_CMAKE.into(),
+        ),
+        (
+            format!("{}/hooks.c", BOOTLOADER_HOOKS_COMPONENT),
+            BOOTLOADER_HOOKS_SOURCE.into(),
+        ),
+    ]
+}
+
+/// Placeholder replaced by the display name of the project
+pub const PROJECT_NAME_PLACEHOLDER: &str = "{{project_name}}";
+
+/// Placeholder replaced by the badges of the project, see [`readme_badges`]
+pub const BADGES_PLACEHOLDER: &str = "{{badges}}";
+
+/// README of the generated project
+pub const README_TEMPLATE: &str = r#"# {{project_name}}
+
+{{badges}}ESP-IDF project created with [esp-create-project](https://github.com/Alan5142/esp-create-project).
+
+## Build and flash
+
+```
+idf.py build
+idf.py -p PORT flash monitor
+```
+"#;
+
+/// README of the components directory shared by the sub-projects of a workspace
+pub const WORKSPACE_COMPONENTS_README: &str = r#"# Shared components
+
+Components used by every app of the workspace, one directory per component.
+Create one with `idf.py create-component -C ../components NAME` from an app
+directory, or copy an existing component here.
+"#;
+
+/// `sdkconfig.defaults` of a sub-project of a workspace
+pub const WORKSPACE_APP_SDKCONFIG_DEFAULTS: &str =
+    "# Settings of this app, applied over the shared ../sdkconfig.defaults\n";
+
+/// README of a workspace
+pub const WORKSPACE_README_TEMPLATE: &str = r#"# {{project_name}}
+
+{{badges}}ESP-IDF workspace created with [esp-create-project](https://github.com/Alan5142/esp-create-project).
+
+## Layout
+
+```
+{{layout}}```
+
+Each app is a regular ESP-IDF project. Its `CMakeLists.txt` adds
+`../components` to `EXTRA_COMPONENT_DIRS`, and reads `../sdkconfig.defaults`
+before its own `sdkconfig.defaults`, so the settings of the app win.
+
+## Build and flash
+
+Run `idf.py` from the directory of an app:
+
+```
+cd {{first_app}}
+idf.py build
+idf.py -p PORT flash monitor
+```
+{{factory_test}}"#;
+
+/// The factory test section of [`WORKSPACE_README_TEMPLATE`], see
+/// [`crate::factory_test`]
+pub const WORKSPACE_FACTORY_TEST_TEMPLATE: &str = r#"
+## Factory test
+
+`{{app}}/` is a console for the production line, the console starter with
+a command per test:
+
+{{commands}}
+Every app has the `test` partition of the factory test app in its
+`partitions.csv`. Flash the main app, then write the factory test app to its
+partition:
+
+```
+cd {{first_app}}
+idf.py -p PORT flash
+cd ../{{app}}
+idf.py build
+python $IDF_PATH/components/partition_table/parttool.py -p PORT write_partition --partition-name=test --input build/{{app}}.bin
+```
+
+The bootloader starts the factory test app instead of the main app while the
+GPIO of `CONFIG_BOOTLOADER_NUM_PIN_APP_TEST` is held at reset, the option is
+enabled in `sdkconfig.defaults`; pick the pin and its level in menuconfig.
+"#;
+
+/// The badges section of the README, only with the badges of the features
+/// that are set
+///
+/// # Arguments
+/// * `ci` - The CI service that builds the project
+/// * `license` - The SPDX license of the project
+/// * `idf_version` - The minimum ESP-IDF version of the project
+///
+/// # Returns
+/// One badge per line followed by a blank line, empty without badges
+pub fn readme_badges(
+    ci: Option<CiProvider>,
+    license: Option<&str>,
+    idf_version: Option<&IdfVersion>,
+) -> String {
+    let mut badges = String::new();
+    if let Some(provider) = ci {
+        badges.push_str(&provider.badge());
+        badges.push('\n');
+    }
+    if let Some(license) = license {
+        // A single license has its own page, an expression links to the list
+        let page = if license.contains(' ') {
+            String::new()
+        } else {
+            format!("{}.html", license)
+        };
+        badges.push_str(&format!(
+            "[![License: {}](https://img.shields.io/badge/license-{}-blue.svg)](https://spdx.org/licenses/{})\n",
+            license,
+            shields_escape(license),
+            page
+        ));
+    }
+    if let Some(version) = idf_version {
+        badges.push_str(&format!(
+            "[![ESP-IDF >= {}](https://img.shields.io/badge/ESP--IDF-{}-red.svg)](https://github.com/espressif/esp-idf)\n",
+            version,
+            shields_escape(&format!(">={}", version))
+        ));
+    }
+    if !badges.is_empty() {
+        badges.push('\n');
+    }
+    badges
+}
+
+/// Escapes the text of a shields.io static badge: dashes and underscores are
+/// doubled, everything else but letters, digits and dots is percent-encoded
+fn shields_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '-' => escaped.push_str("--"),
+            '_' => escaped.push_str("__"),
+            c if c.is_ascii_alphanumeric() || c == '.' => escaped.push(c),
+            c => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    escaped.push_str(&format!("%{:02X}", byte));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// Renders [`WORKSPACE_README_TEMPLATE`]
+///
+/// # Arguments
+/// * `display_name` - The name of the workspace shown in the title
+/// * `apps` - The names of the sub-projects
+/// * `factory_test` - The commands and their help of the factory test app,
+///   `None` without one
+/// * `badges` - The badges section, see [`readme_badges`]
+pub fn workspace_readme(
+    display_name: &str,
+    apps: &[String],
+    factory_test: Option<&[(&str, &str)]>,
+    badges: &str,
+) -> String {
+    let first_app = apps.first().map_or("APP", String::as_str);
+    let mut entries = vec![
+        ("components/".to_string(), "Components shared by every app"),
+        (
+            "sdkconfig.defaults".to_string(),
+            "Configuration shared by every app",
+        ),
+    ];
+    entries.extend(
+        apps.iter()
+            .map(|app| (format!("{}/", app), "ESP-IDF project of an app")),
+    );
+    let factory_test = match factory_test {
+        Some(commands) => {
+            entries.push((
+                format!("{}/", FACTORY_TEST_APP),
+                "Factory test app, flashed to the test partition",
+            ));
+            let commands: String = if commands.is_empty() {
+                "None of the starters of the workspace has a test.\n".to_string()
+            } else {
+                commands
+                    .iter()
+                    .map(|(command, help)| format!("- `{}`: {}\n", command, help))
+                    .collect()
+            };
+            WORKSPACE_FACTORY_TEST_TEMPLATE
+                .replace("{{commands}}", &commands)
+                .replace("{{app}}", FACTORY_TEST_APP)
+                .replace("{{first_app}}", first_app)
+        }
+        None => String::new(),
+    };
+    let width = entries
+        .iter()
+        .map(|(path, _)| path.len())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let layout: String = entries
+        .iter()
+        .map(|(path, description)| format!("{:<width$}{}\n", path, description, width = width))
+        .collect();
+
+    WORKSPACE_README_TEMPLATE
+        .replace(PROJECT_NAME_PLACEHOLDER, display_name)
+        .replace(BADGES_PLACEHOLDER, badges)
+        .replace("{{layout}}", &layout)
+        .replace("{{first_app}}", first_app)
+        .replace("{{factory_test}}", &factory_test)
+}
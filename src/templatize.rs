@@ -0,0 +1,316 @@
+//! `templatize`: packs an existing project into a template archive, with the
+//! project name replaced by a placeholder filled in when generating from it
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::archive;
+use crate::cmake;
+use crate::naming::ProjectNames;
+use crate::project::{self, SourceLanguage};
+use crate::replacements::{self, FileReplacements, Replacement};
+use crate::templates::PROJECT_NAME_PLACEHOLDER;
+
+/// Manifest written at the root of the template
+pub const TEMPLATE_MANIFEST_FILE: &str = "template.toml";
+
+/// Top-level entries that are generated by the build or by git, left out of the template
+const EXCLUDED_ENTRIES: &[&str] = &[
+    ".git",
+    "build",
+    "managed_components",
+    "sdkconfig",
+    "sdkconfig.old",
+    "dependencies.lock",
+];
+
+/// Extensions of the files of `main/` where the project name is replaced
+const PLACEHOLDER_EXTENSIONS: &[&str] = &["c", "cpp", "h", "hpp", "txt", "yml", "projbuild"];
+
+/// What the template was made from, stored in [`TEMPLATE_MANIFEST_FILE`]
+///
+/// Only the [`TemplateManifest::replacements`] are needed in a manifest
+/// written by hand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateManifest {
+    /// Name of the project the template was made from
+    #[serde(default)]
+    pub name: String,
+    /// Language of the sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<SourceLanguage>,
+    /// The placeholders used in the files, filled in when generating
+    #[serde(default)]
+    pub placeholders: Vec<String>,
+    /// Replacements applied to every text file when generating, see
+    /// [`crate::replacements`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replacements: Vec<Replacement>,
+}
+
+/// What [`templatize`] packed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplatizeReport {
+    /// The root of the project
+    pub root: PathBuf,
+    /// Number of files in the archive, the manifest included
+    pub files: usize,
+    /// The files where the project name was replaced by the placeholder,
+    /// relative to the project root
+    pub placeholder_files: Vec<PathBuf>,
+}
+
+/// Packs the project that contains `start` into a template archive usable
+/// with `--template`
+///
+/// Build output, `sdkconfig`, managed components and git metadata are left
+/// out. The project name is replaced by `{{project_name}}` in the top-level
+/// CMakeLists.txt, the README, `sdkconfig.defaults` and the sources and
+/// build files of `main/`.
+///
+/// # Arguments
+/// * `start` - A directory inside the project, usually the current directory
+/// * `out` - The zip archive to write
+///
+/// # Returns
+/// The packed files
+///
+/// # Errors
+/// If `start` is not inside a project, the CMakeLists.txt wasn't generated
+/// from the template, the archive is inside the project or a file cannot be
+/// read or written
+pub fn templatize(start: &Path, out: &Path) -> anyhow::Result<TemplatizeReport> {
+    let root = project::find_project_root(start).context(format!(
+        "\"{}\" is not inside an ESP-IDF project (no CMakeLists.txt including project.cmake found)",
+        start.display()
+    ))?;
+    let out_dir = out
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    if fs::canonicalize(out_dir)
+        .is_ok_and(|dir| dir.starts_with(fs::canonicalize(&root).unwrap_or_default()))
+    {
+        bail!(
+            "The template cannot be written inside the project \"{}\"",
+            root.display()
+        );
+    }
+
+    let cmake_path = root.join("CMakeLists.txt");
+    let cmake = fs::read_to_string(&cmake_path).context("Cannot read CMakeLists.txt")?;
+    let cmake_name = cmake::project_name(&cmake)
+        .context("The CMakeLists.txt of the project has no project(...) command")?
+        .to_string();
+    let display_name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| cmake_name.clone());
+
+    let files = archive::list_files(&root, EXCLUDED_ENTRIES)?;
+
+    let archive = File::create(out).context(format!("Cannot create \"{}\"", out.display()))?;
+    let mut zip = ZipWriter::new(archive);
+    let mut placeholder_files = Vec::new();
+    for relative in &files {
+        let path = root.join(relative);
+        let content = if relative == Path::new("CMakeLists.txt") {
+            placeholder_files.push(relative.clone());
+            cmake::to_template_layout(&cmake, PROJECT_NAME_PLACEHOLDER)?.into_bytes()
+        } else {
+            let content = fs::read(&path).context(format!("Cannot read \"{}\"", path.display()))?;
+            match String::from_utf8(content) {
+                Ok(text) if is_placeholder_file(relative) => {
+                    let mut replaced = replace_identifier(&text, &cmake_name);
+                    if display_name != cmake_name {
+                        replaced = replaced.replace(&display_name, PROJECT_NAME_PLACEHOLDER);
+                    }
+                    if replaced != text {
+                        placeholder_files.push(relative.clone());
+                    }
+                    replaced.into_bytes()
+                }
+                Ok(text) => text.into_bytes(),
+                Err(error) => error.into_bytes(),
+            }
+        };
+        zip.start_file(archive::entry_name(relative), archive::entry_options(&path))
+            .and_then(|_| zip.write_all(&content).map_err(Into::into))
+            .context(format!(
+                "Cannot add \"{}\" to the template",
+                relative.display()
+            ))?;
+    }
+
+    let manifest = TemplateManifest {
+        name: cmake_name,
+        language: Some(project::detect_language(&root)),
+        placeholders: vec![PROJECT_NAME_PLACEHOLDER.to_string()],
+        replacements: Vec::new(),
+    };
+    let manifest = format!(
+        "# Written by esp-create-project templatize\n{}",
+        toml::to_string(&manifest).context("Cannot write the template manifest")?
+    );
+    zip.start_file(TEMPLATE_MANIFEST_FILE, FileOptions::default())
+        .and_then(|_| zip.write_all(manifest.as_bytes()).map_err(Into::into))
+        .and_then(|_| zip.finish().map(|_| ()))
+        .context(format!("Cannot write \"{}\"", out.display()))?;
+
+    Ok(TemplatizeReport {
+        root,
+        files: files.len() + 1,
+        placeholder_files,
+    })
+}
+
+/// Fills in the placeholders of a project extracted from a template made by
+/// [`templatize`], applies the replacements of its manifest and removes it
+///
+/// # Arguments
+/// * `directory` - The directory of the extracted project
+/// * `names` - The names of the new project
+///
+/// # Returns
+/// The files changed by the replacements of the manifest, and how many
+/// replacements were made in each
+///
+/// # Errors
+/// If the manifest or its replacements are invalid, or a file cannot be read
+/// or written
+pub fn fill_placeholders(
+    directory: &Path,
+    names: &ProjectNames,
+) -> anyhow::Result<Vec<FileReplacements>> {
+    let manifest = directory.join(TEMPLATE_MANIFEST_FILE);
+    if !manifest.is_file() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&manifest).context("Cannot read the template manifest")?;
+    let parsed: TemplateManifest = toml::from_str(&content).context(format!(
+        "Invalid template manifest {}",
+        TEMPLATE_MANIFEST_FILE
+    ))?;
+    let replacements = replacements::compile(&parsed.replacements, names).context(format!(
+        "Invalid replacements in {}",
+        TEMPLATE_MANIFEST_FILE
+    ))?;
+    fs::remove_file(&manifest).context("Cannot remove the template manifest")?;
+
+    let files = archive::list_files(directory, &[])?;
+    for relative in files.iter().filter(|relative| {
+        *relative == Path::new("CMakeLists.txt") || is_placeholder_file(relative)
+    }) {
+        let path = directory.join(relative);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            // Not text, there's nothing to fill in
+            Err(_) => continue,
+        };
+        if content.contains(PROJECT_NAME_PLACEHOLDER) {
+            fs::write(
+                &path,
+                content.replace(PROJECT_NAME_PLACEHOLDER, &names.cmake),
+            )
+            .context(format!("Cannot write \"{}\"", path.display()))?;
+        }
+    }
+    replacements::apply_to_tree(directory, &replacements)
+}
+
+/// Replaces the name by the placeholder where it's not part of a longer
+/// identifier, so `blink` is replaced in `blink.h` but not in `blink_led()`
+///
+/// # Arguments
+/// * `content` - The content of the file
+/// * `name` - The CMake name of the project
+pub fn replace_identifier(content: &str, name: &str) -> String {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (i, _) in content.match_indices(name) {
+        if i < last {
+            continue;
+        }
+        let before = content[..i].chars().next_back();
+        let after = content[i + name.len()..].chars().next();
+        if before.is_some_and(is_word) || after.is_some_and(is_word) {
+            continue;
+        }
+        result.push_str(&content[last..i]);
+        result.push_str(PROJECT_NAME_PLACEHOLDER);
+        last = i + name.len();
+    }
+    result.push_str(&content[last..]);
+    result
+}
+
+/// Checks if the project name is replaced in this file, see [`templatize`]
+fn is_placeholder_file(relative: &Path) -> bool {
+    if relative == Path::new("README.md") || relative == Path::new("sdkconfig.defaults") {
+        return true;
+    }
+    relative.parent() == Some(Path::new("main"))
+        && relative
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| PLACEHOLDER_EXTENSIONS.contains(&ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_identifier() {
+        assert_eq!(
+            replace_identifier("#include \"blink.h\"\nblink_led(); // blink\n", "blink"),
+            "#include \"{{project_name}}.h\"\nblink_led(); // {{project_name}}\n"
+        );
+        assert_eq!(
+            replace_identifier("void app_main()", "app"),
+            "void app_main()"
+        );
+    }
+
+    #[test]
+    fn test_templatize_excludes_build_output() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("blink");
+        crate::test_fixtures::write_template(&dir);
+        let cmake = cmake::rewrite_project_cmake(crate::test_fixtures::TEMPLATE_CMAKE, "", "blink")
+            .unwrap();
+        fs::write(dir.join("CMakeLists.txt"), cmake).unwrap();
+        for generated in ["build", "managed_components", ".git"] {
+            fs::create_dir_all(dir.join(generated)).unwrap();
+            fs::write(dir.join(generated).join("file"), "").unwrap();
+        }
+        fs::write(dir.join("sdkconfig"), "CONFIG_A=y\n").unwrap();
+        let out = root.path().join("blink.zip");
+
+        let report = templatize(&dir.join("main"), &out).unwrap();
+
+        let zip = zip::ZipArchive::new(File::open(&out).unwrap()).unwrap();
+        let mut names: Vec<&str> = zip.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "CMakeLists.txt",
+                "README.md",
+                "main/CMakeLists.txt",
+                "main/main.c",
+                "template.toml"
+            ]
+        );
+        assert_eq!(report.files, 5);
+        assert_eq!(report.placeholder_files, [PathBuf::from("CMakeLists.txt")]);
+        assert!(templatize(&dir, &dir.join("blink.zip")).is_err());
+    }
+}
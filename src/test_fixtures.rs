@@ -0,0 +1,226 @@
+//! Fixtures shared by the tests: a minimal copy of the upstream template
+//! layout, in-memory archives and a local HTTP server
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Top-level CMakeLists.txt of the upstream template
+pub const TEMPLATE_CMAKE: &str = "# The following lines of boilerplate have to be in your project's
+# CMakeLists in this exact order for cmake to work correctly
+cmake_minimum_required(VERSION 3.5)
+
+include($ENV{IDF_PATH}/tools/cmake/project.cmake)
+project(app-template)
+";
+
+/// main/CMakeLists.txt of the upstream template
+pub const TEMPLATE_MAIN_CMAKE: &str =
+    "# Edit following two lines to set component requirements (see docs)
+set(COMPONENT_REQUIRES )
+set(COMPONENT_PRIV_REQUIRES )
+
+set(COMPONENT_SRCS \"main.c\")
+set(COMPONENT_ADD_INCLUDEDIRS \"\")
+
+register_component()
+";
+
+/// main/main.c of the upstream template
+pub const TEMPLATE_MAIN_C: &str = "void app_main(void)\n{\n}\n";
+
+/// Writes the template files to the directory, as if it was extracted
+///
+/// # Arguments
+/// * `dir` - The directory to write the template to
+pub fn write_template(dir: &Path) {
+    fs::create_dir_all(dir.join("main")).unwrap();
+    fs::write(dir.join("CMakeLists.txt"), TEMPLATE_CMAKE).unwrap();
+    fs::write(dir.join("main").join("CMakeLists.txt"), TEMPLATE_MAIN_CMAKE).unwrap();
+    fs::write(dir.join("main").join("main.c"), TEMPLATE_MAIN_C).unwrap();
+    fs::write(dir.join("README.md"), "# Hello world template\n").unwrap();
+}
+
+/// Root directory of the upstream template archive
+pub const TEMPLATE_PREFIX: &str = "esp-idf-template-master/";
+
+/// Builds a zip archive in memory
+///
+/// Entries ending with `/` are stored as directories.
+///
+/// # Arguments
+/// * `entries` - The entry names and contents, in archive order
+pub fn zip_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, content) in entries {
+        if name.ends_with('/') {
+            writer.add_directory(*name, options).unwrap();
+        } else {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Builds an archive with the same layout as the upstream template
+///
+/// It includes a 64 KiB padding file so slow downloads take a while.
+pub fn template_zip() -> Vec<u8> {
+    let padding = vec![b'#'; 64 * 1024];
+    zip_archive(&[
+        (TEMPLATE_PREFIX, b""),
+        (
+            "esp-idf-template-master/CMakeLists.txt",
+            TEMPLATE_CMAKE.as_bytes(),
+        ),
+        (
+            "esp-idf-template-master/README.md",
+            b"# Hello world template\n",
+        ),
+        ("esp-idf-template-master/main/", b""),
+        (
+            "esp-idf-template-master/main/CMakeLists.txt",
+            TEMPLATE_MAIN_CMAKE.as_bytes(),
+        ),
+        (
+            "esp-idf-template-master/main/main.c",
+            TEMPLATE_MAIN_C.as_bytes(),
+        ),
+        ("esp-idf-template-master/padding.txt", &padding),
+    ])
+}
+
+/// Builds a template without the layout of an ESP-IDF project: a
+/// PlatformIO project, its sources in `src/` and no CMakeLists.txt
+pub fn nonstandard_template_zip() -> Vec<u8> {
+    zip_archive(&[
+        ("blink-pio/", b""),
+        (
+            "blink-pio/platformio.ini",
+            b"[env:esp32dev]\nframework = espidf\n",
+        ),
+        ("blink-pio/README.md", b"# Blink for PlatformIO\n"),
+        ("blink-pio/src/", b""),
+        ("blink-pio/src/blink.c", TEMPLATE_MAIN_C.as_bytes()),
+    ])
+}
+
+/// Serves the body over HTTP on a local port until the test process exits
+///
+/// # Arguments
+/// * `body` - The response body of every request
+/// * `chunk_delay` - Slow mode: delay between each 1 KiB chunk of the body
+///
+/// # Returns
+/// The URL of the server
+pub fn serve(body: Vec<u8>, chunk_delay: Option<Duration>) -> String {
+    let body = Arc::new(body);
+    let base = listen(move |_| Some(Arc::clone(&body)), chunk_delay);
+    format!("{}/template.zip", base)
+}
+
+/// Serves the body like [`serve`] and counts the requests
+///
+/// # Returns
+/// The URL of the server, and the number of requests it answered so far
+pub fn serve_counted(body: Vec<u8>) -> (String, Arc<AtomicUsize>) {
+    let body = Arc::new(body);
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    let base = listen(
+        move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Some(Arc::clone(&body))
+        },
+        None,
+    );
+    (format!("{}/template.zip", base), requests)
+}
+
+/// Serves a different body per path, unknown paths get a 404
+///
+/// # Arguments
+/// * `routes` - The paths, starting with `/`, and their response bodies
+///
+/// # Returns
+/// The base URL of the server, without trailing `/`
+pub fn serve_routes(routes: Vec<(&str, Vec<u8>)>) -> String {
+    let routes: HashMap<String, Arc<Vec<u8>>> = routes
+        .into_iter()
+        .map(|(path, body)| (path.to_string(), Arc::new(body)))
+        .collect();
+    listen(move |path| routes.get(path).cloned(), None)
+}
+
+fn listen<F>(route: F, chunk_delay: Option<Duration>) -> String
+where
+    F: Fn(&str) -> Option<Arc<Vec<u8>>> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let body = match read_request_path(&mut stream) {
+                Some(path) => route(&path),
+                None => continue,
+            };
+            thread::spawn(move || respond(stream, body.as_deref(), chunk_delay));
+        }
+    });
+    base
+}
+
+/// Reads the request headers and returns the requested path
+fn read_request_path(stream: &mut TcpStream) -> Option<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    request.split_whitespace().nth(1).map(String::from)
+}
+
+fn respond(mut stream: TcpStream, body: Option<&Vec<u8>>, chunk_delay: Option<Duration>) {
+    let body = match body {
+        Some(body) => body,
+        None => {
+            let _ = stream.write_all(
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            );
+            return;
+        }
+    };
+
+    let headers = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/zip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    if stream.write_all(headers.as_bytes()).is_err() {
+        return;
+    }
+    for chunk in body.chunks(1024) {
+        if let Some(delay) = chunk_delay {
+            thread::sleep(delay);
+        }
+        // The client may hang up, e.g. when the download is cancelled
+        if stream.write_all(chunk).is_err() {
+            return;
+        }
+    }
+}
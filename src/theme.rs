@@ -0,0 +1,108 @@
+//! Themes of the prompts and status lines, picked with `--theme` or the
+//! `theme` key of the config file
+//!
+//! The colorful theme tells success from failure by green and red, which
+//! some color-blind users cannot distinguish. The simple theme has no colors,
+//! and the high-contrast one has no colors either but bold text and words
+//! (`OK`, `FAIL`) instead of symbols, so nothing is told by color alone.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::symbols::SymbolSet;
+
+/// A theme of the prompts and status lines
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Green and red cues, the default
+    #[default]
+    Colorful,
+    /// No colors
+    Simple,
+    /// No colors, bold text and words instead of symbols
+    HighContrast,
+}
+
+impl Theme {
+    /// Every theme, in the order of the documentation
+    pub const ALL: &'static [Theme] = &[Theme::Colorful, Theme::Simple, Theme::HighContrast];
+
+    /// The theme of the process: `--theme` if passed, otherwise the one of
+    /// the config file, the default if neither is set
+    ///
+    /// # Arguments
+    /// * `flag` - The value of `--theme`
+    /// * `config` - The `theme` of the config file
+    pub fn resolve(flag: Option<Theme>, config: Option<Theme>) -> Theme {
+        flag.or(config).unwrap_or_default()
+    }
+
+    /// The symbols of the status lines with this theme
+    ///
+    /// # Arguments
+    /// * `detected` - The symbols the terminal can display, see
+    ///   [`SymbolSet::from_env`]
+    pub fn symbol_set(self, detected: SymbolSet) -> SymbolSet {
+        match self {
+            Theme::Colorful | Theme::Simple => detected,
+            Theme::HighContrast => SymbolSet::Words,
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Theme::Colorful => "colorful",
+            Theme::Simple => "simple",
+            Theme::HighContrast => "high-contrast",
+        })
+    }
+}
+
+impl std::str::FromStr for Theme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Theme::ALL
+            .iter()
+            .copied()
+            .find(|theme| theme.to_string() == name)
+            .ok_or_else(|| {
+                let names: Vec<String> = Theme::ALL.iter().map(|theme| theme.to_string()).collect();
+                format!(
+                    "unknown theme \"{}\", expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_resolve() {
+        for theme in Theme::ALL {
+            assert_eq!(theme.to_string().parse::<Theme>(), Ok(*theme));
+            let json = serde_json::to_string(theme).unwrap();
+            assert_eq!(json, format!("\"{}\"", theme));
+        }
+        assert!("contrast".parse::<Theme>().is_err());
+
+        assert_eq!(Theme::resolve(None, None), Theme::Colorful);
+        assert_eq!(Theme::resolve(None, Some(Theme::Simple)), Theme::Simple);
+        assert_eq!(
+            Theme::resolve(Some(Theme::HighContrast), Some(Theme::Simple)),
+            Theme::HighContrast
+        );
+        assert_eq!(
+            Theme::HighContrast.symbol_set(SymbolSet::Emoji),
+            SymbolSet::Words
+        );
+        assert_eq!(Theme::Simple.symbol_set(SymbolSet::Emoji), SymbolSet::Emoji);
+    }
+}
@@ -0,0 +1,288 @@
+//! Background check for a new release, throttled to once a day
+//!
+//! The check runs on its own thread while the project is generated and its
+//! result is only used if it is ready by then, so it never delays or fails a run.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::UserConfig;
+use crate::{http, self_update};
+
+/// Environment variable that disables the check when set
+pub const DISABLE_VARIABLE: &str = "ESP_CREATE_NO_UPDATE_CHECK";
+
+/// Minimum time between two checks
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Timeout of the request to GitHub
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Name of the file, in the state directory, with the time of the last check
+pub const STATE_FILE: &str = "last-update-check";
+
+/// Source of the current time, replaced in tests
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Checks if the update check is allowed by the flags, the config and the environment
+///
+/// # Arguments
+/// * `offline` - Whether network access is disabled
+/// * `config` - The user configuration
+pub fn is_enabled(offline: bool, config: &UserConfig) -> bool {
+    is_enabled_with(offline, config, |name| env::var_os(name))
+}
+
+/// Same as [`is_enabled`], reading the environment with `var`
+fn is_enabled_with(
+    offline: bool,
+    config: &UserConfig,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> bool {
+    !offline && config.update_check && var(DISABLE_VARIABLE).is_none_or(|value| value.is_empty())
+}
+
+/// Remembers when the last check happened, so it runs at most once per [`CHECK_INTERVAL`]
+#[derive(Debug, Clone)]
+pub struct UpdateThrottle<C: Clock> {
+    state_file: PathBuf,
+    clock: C,
+}
+
+impl<C: Clock> UpdateThrottle<C> {
+    /// Creates the throttle
+    ///
+    /// # Arguments
+    /// * `state_file` - The file that stores the time of the last check
+    /// * `clock` - The source of the current time
+    pub fn new(state_file: PathBuf, clock: C) -> Self {
+        Self { state_file, clock }
+    }
+
+    /// Time of the last check, `None` if there was none or the state file is unreadable
+    pub fn last_check(&self) -> Option<SystemTime> {
+        let seconds = fs::read_to_string(&self.state_file).ok()?;
+        let seconds = seconds.trim().parse().ok()?;
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+    }
+
+    /// Checks if the last check is older than [`CHECK_INTERVAL`]
+    ///
+    /// A last check in the future (the clock was moved back) doesn't block the check.
+    pub fn is_due(&self) -> bool {
+        match self.last_check() {
+            Some(last) => self
+                .clock
+                .now()
+                .duration_since(last)
+                .map_or(true, |elapsed| elapsed >= CHECK_INTERVAL),
+            None => true,
+        }
+    }
+
+    /// Stores the current time as the time of the last check
+    ///
+    /// # Errors
+    /// If the state file cannot be written
+    pub fn record(&self) -> io::Result<()> {
+        write_check_time(&self.state_file, self.clock.now())
+    }
+
+    /// Starts the check in the background if it is due
+    ///
+    /// The time of the start is recorded once the result of the check is
+    /// read, see [`PendingUpdateCheck::newer_release`]: a check that didn't
+    /// finish in time runs again next time, a failing one isn't retried
+    /// before the next interval.
+    ///
+    /// # Returns
+    /// The pending check, or `None` if it isn't due
+    pub fn start(&self) -> Option<PendingUpdateCheck> {
+        if !self.is_due() {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let newer = http::agent_with_timeout(self_update::LATEST_RELEASE_URL, CHECK_TIMEOUT)
+                .and_then(|agent| self_update::fetch_latest_release(&agent))
+                .ok()
+                .filter(|release| release.is_newer_than(self_update::CURRENT_VERSION))
+                .map(|release| release.tag_name);
+            let _ = sender.send(newer);
+        });
+        Some(PendingUpdateCheck {
+            receiver,
+            state_file: self.state_file.clone(),
+            started: self.clock.now(),
+        })
+    }
+}
+
+/// Writes the time of a check to the state file
+///
+/// # Arguments
+/// * `state_file` - The file that stores the time of the last check
+/// * `time` - The time of the check
+///
+/// # Errors
+/// If the state file cannot be written
+fn write_check_time(state_file: &Path, time: SystemTime) -> io::Result<()> {
+    if let Some(parent) = state_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    fs::write(state_file, seconds.to_string())
+}
+
+/// An update check running in the background
+#[derive(Debug)]
+pub struct PendingUpdateCheck {
+    receiver: Receiver<Option<String>>,
+    /// Where the time of the check is recorded once its result is read
+    state_file: PathBuf,
+    started: SystemTime,
+}
+
+impl PendingUpdateCheck {
+    /// Tag of the newer release, if the check found one
+    ///
+    /// It never waits: `None` is also returned if the check hasn't finished,
+    /// the check is then not recorded and runs again next time.
+    pub fn newer_release(&self) -> Option<String> {
+        let newer = self.receiver.try_recv().ok()?;
+        // Not being able to store the time only means checking again next run
+        let _ = write_check_time(&self.state_file, self.started);
+        newer
+    }
+}
+
+/// Starts the background check when it is enabled and due
+///
+/// # Arguments
+/// * `offline` - Whether network access is disabled
+/// * `config` - The user configuration
+///
+/// # Returns
+/// The pending check, or `None` if no check was started
+pub fn start(offline: bool, config: &UserConfig) -> Option<PendingUpdateCheck> {
+    if !is_enabled(offline, config) {
+        return None;
+    }
+    let state_dir = crate::config::state_dir()?;
+    UpdateThrottle::new(state_dir.join(STATE_FILE), SystemClock).start()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clock stopped at a fixed time
+    struct FixedClock(SystemTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> SystemTime {
+            self.0
+        }
+    }
+
+    fn throttle_at(dir: &tempfile::TempDir, now: SystemTime) -> UpdateThrottle<FixedClock> {
+        UpdateThrottle::new(dir.path().join("state").join(STATE_FILE), FixedClock(now))
+    }
+
+    #[test]
+    fn test_throttle_first_run_is_due() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert!(throttle_at(&dir, now).is_due());
+    }
+
+    #[test]
+    fn test_throttle_once_per_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        throttle_at(&dir, now).record().unwrap();
+
+        assert_eq!(throttle_at(&dir, now).last_check(), Some(now));
+        assert!(!throttle_at(&dir, now).is_due());
+        assert!(!throttle_at(&dir, now + CHECK_INTERVAL - Duration::from_secs(1)).is_due());
+        assert!(throttle_at(&dir, now + CHECK_INTERVAL).is_due());
+        // The clock was moved back
+        assert!(throttle_at(&dir, now - Duration::from_secs(60)).is_due());
+    }
+
+    #[test]
+    fn test_throttle_corrupted_state_is_due() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let throttle = throttle_at(&dir, now);
+        throttle.record().unwrap();
+        fs::write(&throttle.state_file, "garbage").unwrap();
+        assert!(throttle.is_due());
+    }
+
+    #[test]
+    fn test_is_enabled() {
+        let config = UserConfig::default();
+        assert!(!is_enabled(true, &config));
+        assert!(!is_enabled(
+            false,
+            &UserConfig {
+                update_check: false,
+                ..UserConfig::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_is_enabled_variable() {
+        let config = UserConfig::default();
+        assert!(is_enabled_with(false, &config, |_| None));
+        let disabled = |name: &str| (name == DISABLE_VARIABLE).then(|| OsString::from("1"));
+        assert!(!is_enabled_with(false, &config, disabled));
+        // Set but empty, as `ESP_CREATE_NO_UPDATE_CHECK=` does
+        assert!(is_enabled_with(false, &config, |_| Some(OsString::new())));
+    }
+
+    #[test]
+    fn test_recorded_once_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let throttle = throttle_at(&dir, now);
+        let (sender, receiver) = mpsc::channel();
+        let pending = PendingUpdateCheck {
+            receiver,
+            state_file: throttle.state_file.clone(),
+            started: now,
+        };
+
+        // Not finished, its result is dropped and it runs again next time
+        assert_eq!(pending.newer_release(), None);
+        assert!(throttle.is_due());
+
+        sender.send(Some("v9.0.0".to_string())).unwrap();
+        assert_eq!(pending.newer_release(), Some("v9.0.0".to_string()));
+        assert_eq!(throttle.last_check(), Some(now));
+        assert!(!throttle.is_due());
+    }
+}
@@ -0,0 +1,70 @@
+//! `--print-version-info`: the version of the tool and how it was built, to
+//! paste in bug reports
+
+use crate::self_update::CURRENT_VERSION;
+use crate::templates::{DEFAULT_TEMPLATE_REF, TEMPLATE_FILE};
+
+/// The version of the tool, its defaults and its build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    /// The template used without `--template` or `--template-ref`
+    pub template_url: &'static str,
+    pub template_ref: &'static str,
+    /// The target triple the binary was built for
+    pub target: &'static str,
+    /// `release` or `debug`
+    pub profile: &'static str,
+    /// The versions of the download and archive dependencies, by crate name
+    pub dependencies: Vec<(&'static str, &'static str)>,
+}
+
+impl VersionInfo {
+    /// The information of the running binary, collected by `build.rs`
+    pub fn current() -> Self {
+        VersionInfo {
+            version: CURRENT_VERSION,
+            template_url: TEMPLATE_FILE,
+            template_ref: DEFAULT_TEMPLATE_REF,
+            target: env!("ESP_CREATE_BUILD_TARGET"),
+            profile: env!("ESP_CREATE_BUILD_PROFILE"),
+            dependencies: vec![
+                ("zip", env!("ESP_CREATE_DEP_ZIP_VERSION")),
+                ("ureq", env!("ESP_CREATE_DEP_UREQ_VERSION")),
+            ],
+        }
+    }
+
+    /// Formats the information, one `key: value` line each
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "esp-create-project {}\ntarget: {} ({})\ntemplate: {} (ref {})\n",
+            self.version, self.target, self.profile, self.template_url, self.template_ref
+        );
+        for (name, version) in &self.dependencies {
+            text.push_str(&format!("{}: {}\n", name, version));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current() {
+        let info = VersionInfo::current();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info.target.contains(std::env::consts::ARCH));
+        // The tests are built from the repository, with its Cargo.lock
+        assert!(info
+            .dependencies
+            .iter()
+            .all(|(_, version)| version.split('.').all(|part| part.parse::<u32>().is_ok())));
+
+        let text = info.to_text();
+        assert!(text.starts_with(&format!("esp-create-project {}\n", info.version)));
+        assert!(text.contains("\nzip: "));
+    }
+}
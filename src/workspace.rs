@@ -0,0 +1,267 @@
+//! `--relative-symlinks`: the components every app of a workspace gets a
+//! copy of (e.g. the bootloader hooks) are moved once to the workspace root,
+//! and each app links to them with a relative symlink
+
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::options::WORKSPACE_COMPONENTS_DIR;
+use crate::platform;
+use crate::report;
+use crate::status;
+use crate::symbols::Symbol;
+use crate::templates::BOOTLOADER_COMPONENTS_DIR;
+
+/// Directories of an app whose components can be shared
+pub const SHARED_DIRS: &[&str] = &[WORKSPACE_COMPONENTS_DIR, BOOTLOADER_COMPONENTS_DIR];
+
+/// Shares the components that are identical in every app of a workspace
+///
+/// Each shared component is moved to the same directory at the workspace
+/// root (e.g. `bootloader_components/bootloader_hooks`), and replaced in the
+/// apps by a link such as `../../bootloader_components/bootloader_hooks`.
+/// Components that differ between apps, or whose name is taken at the root,
+/// are left as copies. When the links cannot be created (Windows without
+/// developer mode) a warning is shown and the copies are kept.
+///
+/// # Arguments
+/// * `root` - The workspace root
+/// * `apps` - The names of the apps, sub-directories of the root
+/// * `dir_mode` - Permissions of the created directories, see [`ScaffoldOptions::dir_mode`](crate::options::ScaffoldOptions::dir_mode)
+///
+/// # Returns
+/// The shared components, relative to the root with `/` separators
+///
+/// # Errors
+/// If a directory cannot be read, moved or removed, or a link resolves
+/// outside the workspace
+pub fn share_components(
+    root: &Path,
+    apps: &[String],
+    dir_mode: Option<u32>,
+) -> anyhow::Result<Vec<String>> {
+    let first = match apps.first() {
+        Some(first) => root.join(first),
+        None => return Ok(Vec::new()),
+    };
+    let mut shared = Vec::new();
+    for dir in SHARED_DIRS {
+        let entries = match fs::read_dir(first.join(dir)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort_unstable();
+        for name in names {
+            let relative = Path::new(dir).join(&name);
+            if share_component(root, apps, &relative, dir_mode)? {
+                shared.push(format!("{}/{}", dir, name));
+            }
+        }
+    }
+    Ok(shared)
+}
+
+/// Shares one component, see [`share_components`]
+///
+/// # Returns
+/// Whether the component is shared
+fn share_component(
+    root: &Path,
+    apps: &[String],
+    relative: &Path,
+    dir_mode: Option<u32>,
+) -> anyhow::Result<bool> {
+    let copies: Vec<PathBuf> = apps
+        .iter()
+        .map(|app| root.join(app).join(relative))
+        .collect();
+    let first = report::hash_files(&copies[0])?;
+    for copy in &copies[1..] {
+        if !copy.is_dir() || report::hash_files(copy)? != first {
+            return Ok(false);
+        }
+    }
+    let shared = root.join(relative);
+    if shared.exists() {
+        status::warn(
+            Symbol::Warning,
+            &format!(
+                "\"{}\" already exists at the workspace root, each app keeps its copy",
+                relative.display()
+            ),
+        );
+        return Ok(false);
+    }
+
+    if let Some(parent) = shared.parent() {
+        platform::create_dir_all_with_mode(parent, dir_mode).context(format!(
+            "Failed to create directory \"{}\"",
+            parent.display()
+        ))?;
+    }
+    fs::rename(&copies[0], &shared).context(format!(
+        "Cannot move \"{}\" to the workspace root",
+        copies[0].display()
+    ))?;
+    // From <root>/<app>/<dir>/<name> back up to <root>
+    let target = Path::new("..").join("..").join(relative);
+    for (i, copy) in copies.iter().enumerate() {
+        if i > 0 {
+            fs::remove_dir_all(copy).context(format!("Cannot remove \"{}\"", copy.display()))?;
+        }
+        if let Err(error) = platform::symlink_dir(&target, copy) {
+            status::warn(
+                Symbol::Warning,
+                &format!(
+                    "Cannot create the symbolic link \"{}\" ({}), each app keeps a copy of \"{}\"",
+                    copy.display(),
+                    error,
+                    relative.display()
+                ),
+            );
+            unshare(&shared, &copies)?;
+            return Ok(false);
+        }
+        check_inside(root, copy)?;
+    }
+    Ok(true)
+}
+
+/// Puts back a copy of a shared component in every app, after a link
+/// failed, and removes it from the root
+///
+/// # Arguments
+/// * `shared` - The component at the workspace root
+/// * `copies` - Where each app had its copy, a link or nothing now
+fn unshare(shared: &Path, copies: &[PathBuf]) -> anyhow::Result<()> {
+    for copy in copies {
+        if fs::symlink_metadata(copy).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            // A link to a directory is a directory on Windows
+            fs::remove_file(copy)
+                .or_else(|_| fs::remove_dir(copy))
+                .context(format!("Cannot remove the link \"{}\"", copy.display()))?;
+        }
+        copy_dir(shared, copy)?;
+    }
+    fs::remove_dir_all(shared).context(format!("Cannot remove \"{}\"", shared.display()))
+}
+
+/// Copies a directory and its files
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    for file in archive::list_files(from, &[])? {
+        let destination = to.join(&file);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).context(format!(
+                "Failed to create directory \"{}\"",
+                parent.display()
+            ))?;
+        }
+        fs::copy(from.join(&file), &destination)
+            .context(format!("Cannot copy to \"{}\"", destination.display()))?;
+    }
+    Ok(())
+}
+
+/// Checks that a link resolves inside the workspace
+///
+/// # Arguments
+/// * `root` - The workspace root
+/// * `link` - The link
+///
+/// # Errors
+/// If the link cannot be resolved or it points outside the root
+pub fn check_inside(root: &Path, link: &Path) -> anyhow::Result<()> {
+    let root = fs::canonicalize(root).context(format!(
+        "Cannot resolve the directory \"{}\"",
+        root.display()
+    ))?;
+    let target = fs::canonicalize(link)
+        .context(format!("Cannot resolve the link \"{}\"", link.display()))?;
+    if !target.starts_with(&root) {
+        bail!(
+            "\"{}\" points to \"{}\", outside the workspace \"{}\"",
+            link.display(),
+            target.display(),
+            root.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_component(app: &Path, relative: &str, content: &str) {
+        let dir = app.join(relative);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("CMakeLists.txt"), content).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_share_components() {
+        let root = tempfile::tempdir().unwrap();
+        let apps = vec!["app".to_string(), "factory".to_string()];
+        for app in &apps {
+            let app = root.path().join(app);
+            write_component(&app, "bootloader_components/hooks", "hooks");
+            write_component(&app, "components/sensor", "sensor");
+        }
+        write_component(&root.path().join("app"), "components/display", "app");
+        write_component(
+            &root.path().join("factory"),
+            "components/display",
+            "factory",
+        );
+
+        let shared = share_components(root.path(), &apps, None).unwrap();
+
+        assert_eq!(shared, ["components/sensor", "bootloader_components/hooks"]);
+        let hooks = root.path().join("bootloader_components").join("hooks");
+        assert!(hooks.join("CMakeLists.txt").is_file());
+        for app in &apps {
+            let link = root
+                .path()
+                .join(app)
+                .join("bootloader_components")
+                .join("hooks");
+            assert_eq!(
+                fs::read_link(&link).unwrap(),
+                Path::new("../../bootloader_components/hooks")
+            );
+            assert_eq!(
+                fs::read_to_string(link.join("CMakeLists.txt")).unwrap(),
+                "hooks"
+            );
+            // Different in each app, not shared
+            let display = root.path().join(app).join("components").join("display");
+            assert!(!fs::symlink_metadata(&display)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_inside() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let workspace = root.path().join("workspace");
+        fs::create_dir_all(workspace.join("shared")).unwrap();
+        fs::create_dir_all(workspace.join("app")).unwrap();
+        platform::symlink_dir(Path::new("../shared"), &workspace.join("app").join("in")).unwrap();
+        platform::symlink_dir(outside.path(), &workspace.join("app").join("out")).unwrap();
+
+        assert!(check_inside(&workspace, &workspace.join("app").join("in")).is_ok());
+        assert!(check_inside(&workspace, &workspace.join("app").join("out")).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! `--export-env` without a file: stdout only gets the exported lines, the
+//! steps of the generation go to stderr
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::process::Command;
+
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+/// A template with the layout of the upstream one
+fn template_zip() -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default();
+    let entries: &[(&str, &str)] = &[
+        (
+            "CMakeLists.txt",
+            "# The following lines of boilerplate have to be in your project's
+# CMakeLists in this exact order for cmake to work correctly
+cmake_minimum_required(VERSION 3.5)
+
+include($ENV{IDF_PATH}/tools/cmake/project.cmake)
+project(app-template)
+",
+        ),
+        (
+            "main/CMakeLists.txt",
+            "idf_component_register(SRCS \"main.c\" INCLUDE_DIRS \"\")\n",
+        ),
+        ("main/main.c", "void app_main(void)\n{\n}\n"),
+    ];
+    for (name, content) in entries {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_export_env_to_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    let template = dir.path().join("template.zip");
+    fs::write(&template, template_zip()).unwrap();
+    let home = dir.path().join("home");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_esp-create-project"))
+        .current_dir(dir.path())
+        // Nothing of the user's configuration, and no network
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("ESP_CREATE_NO_UPDATE_CHECK", "1")
+        .arg("--template")
+        .arg(&template)
+        .args([
+            "--yes",
+            "--no-remember",
+            "--allow-root",
+            "--export-env",
+            "blink",
+        ])
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stdout.contains("export ESP_PROJECT_NAME='blink'\n"),
+        "{}",
+        stdout
+    );
+    for line in stdout.lines() {
+        assert!(line.starts_with("export ESP_"), "{:?} on stdout", line);
+    }
+    assert!(stderr.contains("Have fun!"), "{}", stderr);
+}